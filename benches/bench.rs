@@ -1,10 +1,46 @@
-use alloy_trie::nodes::encode_path_leaf;
+use alloy_trie::{nodes::encode_path_leaf, HashBuilder};
 use criterion::{
     criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, Criterion,
 };
 use nybbles::Nibbles;
 use proptest::{prelude::*, strategy::ValueTree};
-use std::{hint::black_box, time::Duration};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Counts the allocations made by the benchmarks, to report the allocations per root.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Returns the result of the function and the number of allocations it made.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
 
 /// Benchmarks the nibble path encoding.
 pub fn nibbles_path_encoding(c: &mut Criterion) {
@@ -21,6 +57,121 @@ pub fn nibbles_path_encoding(c: &mut Criterion) {
     }
 }
 
+/// Benchmarks nibble unpacking and packing against the scalar implementation.
+pub fn nibbles_packing(c: &mut Criterion) {
+    let lengths = [32u64, 256, 2048];
+
+    let mut g = group(c, "unpack");
+    for len in lengths {
+        g.throughput(criterion::Throughput::Bytes(len));
+        let data = get_nibbles(len as usize * 2).pack();
+        g.bench_function(criterion::BenchmarkId::new("scalar", len), |b| {
+            b.iter(|| black_box(Nibbles::unpack(black_box(&data))))
+        });
+        g.bench_function(criterion::BenchmarkId::new("simd", len), |b| {
+            b.iter(|| black_box(alloy_trie::nibbles::unpack(black_box(&data))))
+        });
+    }
+    g.finish();
+
+    let mut g = group(c, "pack");
+    for len in lengths {
+        g.throughput(criterion::Throughput::Bytes(len));
+        let nibbles = get_nibbles(len as usize * 2);
+        g.bench_function(criterion::BenchmarkId::new("scalar", len), |b| {
+            b.iter(|| black_box(black_box(&nibbles).pack()))
+        });
+        g.bench_function(criterion::BenchmarkId::new("simd", len), |b| {
+            b.iter(|| black_box(alloy_trie::nibbles::pack(black_box(&nibbles))))
+        });
+    }
+    g.finish();
+}
+
+/// Benchmarks adding leaves with owned and borrowed values, with receipt-sized values keyed as in
+/// a receipts trie.
+pub fn hash_builder_leaves(c: &mut Criterion) {
+    let counts = [100usize, 1000];
+
+    let mut g = group(c, "receipts_root");
+    for count in counts {
+        let mut leaves = (0..count)
+            .map(|i| (alloy_trie::root::ordered_trie_key(i), vec![i as u8; 512]))
+            .collect::<Vec<_>>();
+        leaves.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        g.throughput(criterion::Throughput::Bytes((count * 512) as u64));
+        g.bench_function(criterion::BenchmarkId::new("owned", count), |b| {
+            b.iter(|| {
+                let mut hb = HashBuilder::default();
+                for (key, value) in &leaves {
+                    hb.add_leaf(key.clone(), value);
+                }
+                black_box(hb.root())
+            })
+        });
+        g.bench_function(criterion::BenchmarkId::new("borrowed", count), |b| {
+            b.iter(|| {
+                let mut hb = HashBuilder::default();
+                let mut borrowed = hb.borrowed_leaves();
+                for (key, value) in &leaves {
+                    borrowed.add_leaf_borrowed(key.clone(), value);
+                }
+                drop(borrowed);
+                black_box(hb.root())
+            })
+        });
+    }
+    g.finish();
+}
+
+/// Benchmarks computing roots of unsorted leaves with a new builder for each root and with a
+/// builder that is reset and reused. The allocations per root are reported to stderr when
+/// `BENCH_ALLOCATIONS` is set.
+pub fn hash_builder_reuse(c: &mut Criterion) {
+    let counts = [100usize, 1000];
+    let report_allocations = std::env::var_os("BENCH_ALLOCATIONS").is_some();
+
+    let mut g = group(c, "unsorted_root");
+    for count in counts {
+        let leaves = (0..count)
+            .map(|i| (Nibbles::unpack(alloy_primitives::keccak256(i.to_be_bytes())), [i as u8; 70]))
+            .collect::<Vec<_>>();
+        let root = |hb: &mut HashBuilder| {
+            for (key, value) in &leaves {
+                hb.add_unsorted_leaf(key.clone(), value);
+            }
+            hb.finalize()
+        };
+
+        if report_allocations {
+            let (_, allocations) = count_allocations(|| root(&mut HashBuilder::default()));
+            eprintln!("unsorted_root/new/{count}: {allocations} allocations per root");
+        }
+        g.bench_function(criterion::BenchmarkId::new("new", count), |b| {
+            b.iter(|| black_box(root(&mut HashBuilder::default())))
+        });
+
+        let mut hb = HashBuilder::with_capacity(count);
+        root(&mut hb);
+        hb.reset();
+        if report_allocations {
+            let (_, allocations) = count_allocations(|| {
+                root(&mut hb);
+                hb.reset();
+            });
+            eprintln!("unsorted_root/reused/{count}: {allocations} allocations per root");
+        }
+        g.bench_function(criterion::BenchmarkId::new("reused", count), |b| {
+            b.iter(|| {
+                let root = root(&mut hb);
+                hb.reset();
+                black_box(root)
+            })
+        });
+    }
+    g.finish();
+}
+
 fn group<'c>(c: &'c mut Criterion, name: &str) -> BenchmarkGroup<'c, WallTime> {
     let mut g = c.benchmark_group(name);
     g.warm_up_time(Duration::from_secs(1));
@@ -35,5 +186,11 @@ fn get_nibbles(len: usize) -> Nibbles {
         .current()
 }
 
-criterion_group!(benches, nibbles_path_encoding);
+criterion_group!(
+    benches,
+    nibbles_path_encoding,
+    nibbles_packing,
+    hash_builder_leaves,
+    hash_builder_reuse
+);
 criterion_main!(benches);
@@ -0,0 +1,226 @@
+use crate::{
+    root::{storage_root_unhashed, GenesisAccount},
+    EMPTY_ROOT_HASH, KECCAK_EMPTY,
+};
+use alloy_primitives::{keccak256, B256, U256};
+use alloy_rlp::{RlpDecodable, RlpEncodable};
+
+/// An Ethereum account as it is stored in the leaves of the state trie.
+///
+/// Accounts can be built from the default, empty account:
+///
+/// ```
+/// # use alloy_primitives::{B256, U256};
+/// # use alloy_trie::TrieAccount;
+/// let account = TrieAccount::default()
+///     .with_nonce(1)
+///     .with_balance(U256::from(100))
+///     .with_code(&[0x60, 0x00])
+///     .with_storage([(B256::with_last_byte(1), U256::from(42))]);
+/// assert!(!account.is_empty());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Debug, RlpEncodable, RlpDecodable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+#[cfg_attr(feature = "arbitrary", derive(derive_arbitrary::Arbitrary, proptest_derive::Arbitrary))]
+pub struct TrieAccount {
+    /// Account nonce.
+    pub nonce: u64,
+    /// Account balance.
+    pub balance: U256,
+    /// Root of the account storage trie.
+    pub storage_root: B256,
+    /// Hash of the account code.
+    pub code_hash: B256,
+}
+
+impl Default for TrieAccount {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl From<&GenesisAccount> for TrieAccount {
+    /// Converts the genesis account, computing its storage root from its storage slots.
+    fn from(account: &GenesisAccount) -> Self {
+        let storage = account
+            .storage
+            .iter()
+            .flatten()
+            .map(|(slot, value)| (*slot, U256::from_be_bytes(value.0)));
+        account.trie_account(storage_root_unhashed(storage))
+    }
+}
+
+impl TrieAccount {
+    /// The code hash of accounts without code, which is the keccak256 hash of empty input.
+    pub const EMPTY_CODE_HASH: B256 = KECCAK_EMPTY;
+
+    /// The storage root of accounts without storage, which is the root of an empty trie.
+    pub const EMPTY_STORAGE_ROOT: B256 = EMPTY_ROOT_HASH;
+
+    /// The empty account, without nonce, balance, code and storage.
+    pub const EMPTY: Self = Self {
+        nonce: 0,
+        balance: U256::ZERO,
+        storage_root: Self::EMPTY_STORAGE_ROOT,
+        code_hash: Self::EMPTY_CODE_HASH,
+    };
+
+    /// The RLP encoding of the [empty account](Self::EMPTY), which is the value of its leaf in the
+    /// state trie.
+    pub const EMPTY_RLP: [u8; 70] = {
+        let mut rlp = [0u8; 70];
+        // The list header, the nonce and the balance, followed by the headers of the hashes.
+        rlp[0] = 0xf8;
+        rlp[1] = 0x44;
+        rlp[2] = alloy_rlp::EMPTY_STRING_CODE;
+        rlp[3] = alloy_rlp::EMPTY_STRING_CODE;
+        rlp[4] = 0xa0;
+        rlp[37] = 0xa0;
+        let mut i = 0;
+        while i < 32 {
+            rlp[5 + i] = EMPTY_ROOT_HASH.0[i];
+            rlp[38 + i] = KECCAK_EMPTY.0[i];
+            i += 1;
+        }
+        rlp
+    };
+
+    /// Sets the nonce of the account.
+    pub const fn with_nonce(self, nonce: u64) -> Self {
+        Self { nonce, ..self }
+    }
+
+    /// Sets the balance of the account.
+    pub const fn with_balance(self, balance: U256) -> Self {
+        Self { balance, ..self }
+    }
+
+    /// Sets the storage root of the account.
+    pub const fn with_storage_root(self, storage_root: B256) -> Self {
+        Self { storage_root, ..self }
+    }
+
+    /// Sets the code hash of the account.
+    pub const fn with_code_hash(self, code_hash: B256) -> Self {
+        Self { code_hash, ..self }
+    }
+
+    /// Sets the code hash of the account to the hash of the given code.
+    pub fn with_code(self, code: &[u8]) -> Self {
+        self.with_code_hash(keccak256(code))
+    }
+
+    /// Sets the storage root of the account to the root computed from the given storage slots,
+    /// keyed by slot. See [`storage_root_unhashed`].
+    pub fn with_storage<S>(self, storage: S) -> Self
+    where
+        S: IntoIterator<Item = (B256, U256)>,
+    {
+        self.with_storage_root(storage_root_unhashed(storage))
+    }
+
+    /// Returns `true` if the account has code.
+    pub fn has_code(&self) -> bool {
+        self.code_hash != Self::EMPTY_CODE_HASH
+    }
+
+    /// Returns `true` if the account has storage.
+    pub fn has_storage(&self) -> bool {
+        self.storage_root != Self::EMPTY_STORAGE_ROOT
+    }
+
+    /// Returns `true` if the account is empty as defined in [EIP-161], i.e. it has no code, a
+    /// zero nonce and a zero balance.
+    ///
+    /// Note that empty accounts may still have storage.
+    ///
+    /// [EIP-161]: https://eips.ethereum.org/EIPS/eip-161
+    pub fn is_empty(&self) -> bool {
+        self.nonce == 0 && self.balance.is_zero() && !self.has_code()
+    }
+
+    /// Returns `true` if creating a contract at the address of the account fails because of an
+    /// address collision, i.e. the account has a nonzero nonce, code or storage, as defined in
+    /// [EIP-684] and [EIP-7610].
+    ///
+    /// [EIP-684]: https://eips.ethereum.org/EIPS/eip-684
+    /// [EIP-7610]: https://eips.ethereum.org/EIPS/eip-7610
+    pub fn is_creation_collision(&self) -> bool {
+        self.nonce != 0 || self.has_code() || self.has_storage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::Bytes;
+
+    #[test]
+    fn builder() {
+        let account = TrieAccount::default();
+        assert!(account.is_empty());
+        assert!(!account.is_creation_collision());
+
+        let code = [0x60, 0x00];
+        let storage = [(B256::with_last_byte(1), U256::from(42))];
+        let built = account
+            .with_nonce(1)
+            .with_balance(U256::from(100))
+            .with_code(&code)
+            .with_storage(storage);
+        assert_eq!(
+            built,
+            TrieAccount {
+                nonce: 1,
+                balance: U256::from(100),
+                storage_root: storage_root_unhashed(storage),
+                code_hash: keccak256(code),
+            }
+        );
+        assert!(built.has_code());
+        assert!(built.has_storage());
+
+        // Zero values are not stored.
+        assert!(!account.with_storage([(B256::with_last_byte(1), U256::ZERO)]).has_storage());
+    }
+
+    #[test]
+    fn empty_accounts() {
+        let account = TrieAccount::default();
+        assert!(!account.with_balance(U256::from(1)).is_empty());
+        assert!(!account.with_balance(U256::from(1)).is_creation_collision());
+        assert!(!account.with_nonce(1).is_empty());
+        assert!(account.with_nonce(1).is_creation_collision());
+        assert!(!account.with_code(&[0x00]).is_empty());
+        assert!(account.with_code(&[0x00]).is_creation_collision());
+
+        // Accounts with storage only are empty, but still collide with contract creation.
+        let with_storage = account.with_storage([(B256::with_last_byte(1), U256::from(1))]);
+        assert!(with_storage.is_empty());
+        assert!(with_storage.is_creation_collision());
+    }
+
+    #[test]
+    fn from_genesis_account() {
+        let genesis = GenesisAccount {
+            nonce: None,
+            balance: U256::from(7),
+            code: Some(Bytes::from_static(&[0x60, 0x00])),
+            storage: Some(BTreeMap::from([
+                (B256::with_last_byte(1), B256::with_last_byte(0xaa)),
+                (B256::with_last_byte(2), B256::ZERO),
+            ])),
+        };
+        assert_eq!(
+            TrieAccount::from(&genesis),
+            TrieAccount::default()
+                .with_balance(U256::from(7))
+                .with_code(&[0x60, 0x00])
+                .with_storage([(B256::with_last_byte(1), U256::from(0xaa))])
+        );
+        assert_eq!(TrieAccount::from(&GenesisAccount::default()), TrieAccount::default());
+    }
+}
@@ -0,0 +1,240 @@
+use super::{path_bit, BinaryBranch, BinaryLeaf, BinaryNode};
+use crate::{KeccakHasher, Nibbles, StateCommitment, TrieHasher};
+use alloc::collections::BTreeMap;
+use alloy_primitives::{keccak256, Bytes, B256};
+use core::marker::PhantomData;
+use tracing::trace;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Computes the root of a binary trie from its leaves and retains the proofs of target keys.
+///
+/// Unlike the [`HashBuilder`](crate::HashBuilder), leaves can be added in any order. Adding a leaf
+/// with the key of an existing leaf replaces it.
+#[derive(Clone, Debug)]
+pub struct BinaryTrieBuilder<H = KeccakHasher> {
+    leaves: BTreeMap<Nibbles, Bytes>,
+    targets: Vec<Nibbles>,
+    proofs: BTreeMap<B256, Vec<(usize, Bytes)>>,
+    _hasher: PhantomData<H>,
+}
+
+impl<H> Default for BinaryTrieBuilder<H> {
+    fn default() -> Self {
+        Self {
+            leaves: BTreeMap::new(),
+            targets: Vec::new(),
+            proofs: BTreeMap::new(),
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H: TrieHasher> BinaryTrieBuilder<H> {
+    /// Creates a new, empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retains the proofs of the given keys while computing the root.
+    pub fn with_proof_targets(mut self, targets: impl IntoIterator<Item = B256>) -> Self {
+        self.targets = targets.into_iter().map(Nibbles::unpack).collect();
+        self
+    }
+
+    /// Adds a leaf to the trie.
+    pub fn add_leaf(&mut self, key: B256, value: impl Into<Bytes>) {
+        trace!(target: "trie::binary_trie", ?key, "adding leaf");
+        self.leaves.insert(Nibbles::unpack(key), value.into());
+    }
+
+    /// Computes the root of the trie. The root of an empty trie is zero.
+    pub fn root(&mut self) -> B256 {
+        let leaves = self.leaves.iter().collect::<Vec<_>>();
+        let mut targets = self.targets.clone();
+        targets.sort_unstable();
+        targets.dedup();
+
+        self.proofs.clear();
+        let root = Self::build(&leaves, &targets, 0, &mut self.proofs);
+        trace!(target: "trie::binary_trie", ?root, "computed root");
+        root
+    }
+
+    /// Takes the proofs of the target keys, retained during the last [`Self::root`] call.
+    ///
+    /// Each proof consists of the encoded nodes from the root down to the leaf at the path of the
+    /// target, or down to the last branch node if the path ends at an empty subtrie.
+    pub fn take_proofs(&mut self) -> BTreeMap<B256, Vec<Bytes>> {
+        core::mem::take(&mut self.proofs)
+            .into_iter()
+            .map(|(key, mut nodes)| {
+                nodes.sort_unstable_by_key(|(depth, _)| *depth);
+                (key, nodes.into_iter().map(|(_, node)| node).collect())
+            })
+            .collect()
+    }
+
+    /// Computes the hash of the subtrie with the given leaves at the given depth.
+    fn build(
+        leaves: &[(&Nibbles, &Bytes)],
+        targets: &[Nibbles],
+        depth: usize,
+        proofs: &mut BTreeMap<B256, Vec<(usize, Bytes)>>,
+    ) -> B256 {
+        let node = match leaves {
+            [] => return B256::ZERO,
+            [(path, value)] => BinaryNode::Leaf(BinaryLeaf {
+                key: B256::from_slice(&crate::nibbles::pack(path)),
+                value: (*value).clone(),
+            }),
+            _ => {
+                // The keys are distinct, so they differ before the maximum depth.
+                let split = leaves.partition_point(|(path, _)| !path_bit(path, depth));
+                let target_split = targets.partition_point(|path| !path_bit(path, depth));
+                let left =
+                    Self::build(&leaves[..split], &targets[..target_split], depth + 1, proofs);
+                let right =
+                    Self::build(&leaves[split..], &targets[target_split..], depth + 1, proofs);
+                BinaryNode::Branch(BinaryBranch { left, right })
+            }
+        };
+
+        let encoded = node.encoded();
+        for target in targets {
+            let key = B256::from_slice(&crate::nibbles::pack(target));
+            proofs.entry(key).or_default().push((depth, encoded.clone()));
+        }
+        H::hash(&encoded)
+    }
+}
+
+/// The [`StateCommitment`] of a binary trie whose nodes are hashed with the [`TrieHasher`] `H`.
+///
+/// Keys are hashed with keccak256 to obtain the key of their leaf, and the commitment is the root
+/// computed by the [`BinaryTrieBuilder`].
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct BinaryTrie<H = KeccakHasher>(PhantomData<H>);
+
+impl<H: TrieHasher> StateCommitment for BinaryTrie<H> {
+    type Commitment = B256;
+
+    #[inline]
+    fn empty_commitment() -> B256 {
+        B256::ZERO
+    }
+
+    fn commit<I, K, V>(entries: I) -> B256
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut builder = BinaryTrieBuilder::<H>::new();
+        for (key, value) in entries {
+            builder.add_leaf(keccak256(key), Bytes::copy_from_slice(value.as_ref()));
+        }
+        builder.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary_trie::verify_binary_proof;
+
+    fn leaves(n: u64) -> Vec<(B256, Bytes)> {
+        (0..n)
+            .map(|i| (keccak256(i.to_be_bytes()), Bytes::from(i.to_be_bytes().to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(BinaryTrieBuilder::<KeccakHasher>::new().root(), B256::ZERO);
+
+        let leaves = leaves(100);
+        let (key, value) = leaves[0].clone();
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new();
+        builder.add_leaf(key, value.clone());
+        let leaf = BinaryNode::Leaf(BinaryLeaf { key, value });
+        assert_eq!(builder.root(), leaf.hash::<KeccakHasher>());
+
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new();
+        let mut reversed = BinaryTrieBuilder::<KeccakHasher>::new();
+        for (key, value) in &leaves {
+            builder.add_leaf(*key, value.clone());
+        }
+        for (key, value) in leaves.iter().rev() {
+            reversed.add_leaf(*key, value.clone());
+        }
+        let root = builder.root();
+        assert_eq!(reversed.root(), root);
+
+        // Replacing a value changes the root.
+        builder.add_leaf(leaves[3].0, Bytes::from_static(b"replaced"));
+        assert_ne!(builder.root(), root);
+    }
+
+    #[test]
+    fn two_leaves() {
+        // The keys share the first two bits, so the leaves are placed at depth 3.
+        let a = B256::with_last_byte(1);
+        let mut b = a;
+        b[0] = 0b0010_0000;
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new();
+        builder.add_leaf(a, Bytes::from_static(b"a"));
+        builder.add_leaf(b, Bytes::from_static(b"b"));
+
+        let leaf = |key, value: &'static [u8]| {
+            BinaryNode::Leaf(BinaryLeaf { key, value: Bytes::from_static(value) })
+                .hash::<KeccakHasher>()
+        };
+        let branch =
+            |left, right| BinaryNode::Branch(BinaryBranch { left, right }).hash::<KeccakHasher>();
+        let fork = branch(leaf(a, b"a"), leaf(b, b"b"));
+        let expected = branch(branch(fork, B256::ZERO), B256::ZERO);
+        assert_eq!(builder.root(), expected);
+    }
+
+    #[test]
+    fn proofs() {
+        let leaves = leaves(50);
+        let absent = B256::repeat_byte(0x11);
+        let targets = leaves.iter().step_by(5).map(|(key, _)| *key).chain([absent]);
+
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new().with_proof_targets(targets);
+        for (key, value) in &leaves {
+            builder.add_leaf(*key, value.clone());
+        }
+        let root = builder.root();
+        let proofs = builder.take_proofs();
+        assert_eq!(proofs.len(), 11);
+
+        for (key, value) in leaves.iter().step_by(5) {
+            let proof = &proofs[key];
+            assert_eq!(
+                verify_binary_proof::<KeccakHasher, _>(root, *key, Some(value), proof),
+                Ok(())
+            );
+            assert!(verify_binary_proof::<KeccakHasher, _>(root, *key, None, proof).is_err());
+        }
+        let proof = &proofs[&absent];
+        assert_eq!(verify_binary_proof::<KeccakHasher, _>(root, absent, None, proof), Ok(()));
+    }
+
+    #[test]
+    fn state_commitment() {
+        let entries = (0u64..20).map(|i| (i.to_be_bytes(), [i as u8; 3])).collect::<Vec<_>>();
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new();
+        for (key, value) in &entries {
+            builder.add_leaf(keccak256(key), value.to_vec());
+        }
+        assert_eq!(BinaryTrie::<KeccakHasher>::commit(entries), builder.root());
+        assert_eq!(
+            BinaryTrie::<KeccakHasher>::commit(core::iter::empty::<([u8; 0], [u8; 0])>()),
+            BinaryTrie::<KeccakHasher>::empty_commitment()
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use alloy_primitives::{Bytes, B256};
+use core::fmt;
+
+/// Error during binary trie node decoding or proof verification.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BinaryTrieError {
+    /// A node has an unknown type.
+    UnknownNodeType(u8),
+    /// A node encoding is shorter than its type requires.
+    UnexpectedEnd,
+    /// A node encoding continues after the end of the node.
+    TrailingBytes,
+    /// The hash of a proof node does not match the hash referenced by its parent, or the root.
+    NodeHashMismatch {
+        /// Depth of the node in the trie.
+        depth: usize,
+        /// Hash referenced by the parent.
+        expected: B256,
+        /// Hash of the proof node.
+        got: B256,
+    },
+    /// The proof ends at a branch node.
+    IncompleteProof {
+        /// Depth at which the proof ends.
+        depth: usize,
+    },
+    /// The value at the key does not match the expected value.
+    ValueMismatch {
+        /// Value in the trie, [`None`] if the key is absent.
+        got: Option<Bytes>,
+        /// Expected value, [`None`] if the key is expected to be absent.
+        expected: Option<Bytes>,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for BinaryTrieError {}
+
+impl fmt::Display for BinaryTrieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownNodeType(node_type) => {
+                write!(f, "unknown binary trie node type {node_type}")
+            }
+            Self::UnexpectedEnd => f.write_str("unexpected end of binary trie node"),
+            Self::TrailingBytes => f.write_str("unexpected trailing bytes after binary trie node"),
+            Self::NodeHashMismatch { depth, expected, got } => {
+                write!(f, "node hash mismatch at depth {depth}. got: {got}. expected: {expected}")
+            }
+            Self::IncompleteProof { depth } => {
+                write!(f, "proof ends at a branch node at depth {depth}")
+            }
+            Self::ValueMismatch { got, expected } => {
+                write!(f, "value mismatch. got: {got:?}. expected: {expected:?}")
+            }
+        }
+    }
+}
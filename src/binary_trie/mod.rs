@@ -0,0 +1,63 @@
+//! Experimental binary trie, in the style of EIP-3102.
+//!
+//! The binary trie is a sparse binary Merkle trie keyed by 32-byte key hashes. Leaves are placed
+//! at the shortest path that distinguishes their key from all other keys, where the path is taken
+//! from the bits of the key starting at the most significant bit of its first nibble, so the
+//! leaves of a binary trie are ordered like the leaves of a Merkle Patricia trie. Empty subtries
+//! have the zero hash and are not stored. Nodes are hashed with a [`TrieHasher`](crate::TrieHasher)
+//! over their encoding, see [`BinaryNode::encode`].
+//!
+//! The module mirrors the MPT API, so that witness sizes of both layouts can be compared:
+//! [`BinaryTrieBuilder`] computes the root of a set of leaves and retains proofs for target keys,
+//! [`verify_binary_proof`] verifies them, and [`BinaryTrie`] is the
+//! [`StateCommitment`](crate::StateCommitment) of the layout.
+
+mod error;
+pub use error::BinaryTrieError;
+
+mod node;
+pub use node::{BinaryBranch, BinaryLeaf, BinaryNode};
+
+mod builder;
+pub use builder::{BinaryTrie, BinaryTrieBuilder};
+
+mod proof;
+pub use proof::verify_binary_proof;
+
+use crate::Nibbles;
+
+/// Maximum depth of the trie, the number of bits of a key.
+pub const MAX_DEPTH: usize = 256;
+
+/// Returns the bit of the path at the given depth, `true` for the right child.
+///
+/// Each nibble of the path contributes four bits, starting at its most significant bit.
+///
+/// # Panics
+///
+/// Panics if the depth is not smaller than four times the length of the path.
+#[inline]
+pub fn path_bit(path: &Nibbles, depth: usize) -> bool {
+    (path[depth / 4] >> (3 - depth % 4)) & 1 == 1
+}
+
+/// Returns an iterator over the bits of the path, see [`path_bit`].
+pub fn bit_path(path: &Nibbles) -> impl Iterator<Item = bool> + '_ {
+    (0..path.len() * 4).map(|depth| path_bit(path, depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn bits() {
+        let path = Nibbles::from_nibbles([0x5, 0xc]);
+        let bits = bit_path(&path).collect::<Vec<_>>();
+        assert_eq!(bits, [false, true, false, true, true, true, false, false]);
+        assert!(path_bit(&path, 4));
+        assert!(!path_bit(&path, 7));
+        assert_eq!(bit_path(&Nibbles::default()).count(), 0);
+    }
+}
@@ -0,0 +1,133 @@
+use super::BinaryTrieError;
+use crate::TrieHasher;
+use alloy_primitives::{Bytes, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Type of a leaf node.
+const NODE_TYPE_LEAF: u8 = 0;
+/// Type of a branch node.
+const NODE_TYPE_BRANCH: u8 = 1;
+
+/// A node of the binary trie. Empty subtries are not stored, as their hash is zero.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BinaryNode {
+    /// A leaf node.
+    Leaf(BinaryLeaf),
+    /// A branch node.
+    Branch(BinaryBranch),
+}
+
+impl BinaryNode {
+    /// Computes the hash of the node, which is the hash of its encoding.
+    pub fn hash<H: TrieHasher>(&self) -> B256 {
+        H::hash(&self.encoded())
+    }
+
+    /// Encodes the node into the given buffer.
+    ///
+    /// Leaf nodes are encoded as their type, key and value, and branch nodes as their type
+    /// followed by the hashes of their children.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Leaf(leaf) => {
+                out.push(NODE_TYPE_LEAF);
+                out.extend_from_slice(leaf.key.as_slice());
+                out.extend_from_slice(&leaf.value);
+            }
+            Self::Branch(branch) => {
+                out.push(NODE_TYPE_BRANCH);
+                out.extend_from_slice(branch.left.as_slice());
+                out.extend_from_slice(branch.right.as_slice());
+            }
+        }
+    }
+
+    /// Returns the encoding of the node.
+    pub fn encoded(&self) -> Bytes {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out.into()
+    }
+
+    /// Decodes a node from its encoding. See [`Self::encode`].
+    pub fn decode(buf: &[u8]) -> Result<Self, BinaryTrieError> {
+        let (&node_type, buf) = buf.split_first().ok_or(BinaryTrieError::UnexpectedEnd)?;
+        match node_type {
+            NODE_TYPE_LEAF => {
+                if buf.len() < 32 {
+                    return Err(BinaryTrieError::UnexpectedEnd);
+                }
+                let (key, value) = buf.split_at(32);
+                Ok(Self::Leaf(BinaryLeaf {
+                    key: B256::from_slice(key),
+                    value: Bytes::copy_from_slice(value),
+                }))
+            }
+            NODE_TYPE_BRANCH => {
+                if buf.len() < 64 {
+                    return Err(BinaryTrieError::UnexpectedEnd);
+                }
+                if buf.len() > 64 {
+                    return Err(BinaryTrieError::TrailingBytes);
+                }
+                Ok(Self::Branch(BinaryBranch {
+                    left: B256::from_slice(&buf[..32]),
+                    right: B256::from_slice(&buf[32..]),
+                }))
+            }
+            _ => Err(BinaryTrieError::UnknownNodeType(node_type)),
+        }
+    }
+}
+
+/// A branch node of the binary trie.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BinaryBranch {
+    /// Hash of the left child, zero if it is empty.
+    pub left: B256,
+    /// Hash of the right child, zero if it is empty.
+    pub right: B256,
+}
+
+/// A leaf node of the binary trie.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BinaryLeaf {
+    /// The key, which determines the path of the leaf.
+    pub key: B256,
+    /// The value.
+    pub value: Bytes,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeccakHasher;
+
+    #[test]
+    fn encoding_roundtrip() {
+        let leaf = BinaryNode::Leaf(BinaryLeaf {
+            key: B256::repeat_byte(0x12),
+            value: Bytes::from_static(b"value"),
+        });
+        let branch = BinaryNode::Branch(BinaryBranch {
+            left: leaf.hash::<KeccakHasher>(),
+            right: B256::ZERO,
+        });
+        for node in [leaf, branch] {
+            let encoded = node.encoded();
+            assert_eq!(BinaryNode::decode(&encoded), Ok(node.clone()));
+            assert_eq!(node.hash::<KeccakHasher>(), alloy_primitives::keccak256(&encoded));
+        }
+    }
+
+    #[test]
+    fn invalid_encodings() {
+        assert_eq!(BinaryNode::decode(&[]), Err(BinaryTrieError::UnexpectedEnd));
+        assert_eq!(BinaryNode::decode(&[2]), Err(BinaryTrieError::UnknownNodeType(2)));
+        assert_eq!(BinaryNode::decode(&[0; 32]), Err(BinaryTrieError::UnexpectedEnd));
+        assert_eq!(BinaryNode::decode(&[1; 64]), Err(BinaryTrieError::UnexpectedEnd));
+        assert_eq!(BinaryNode::decode(&[1; 66]), Err(BinaryTrieError::TrailingBytes));
+    }
+}
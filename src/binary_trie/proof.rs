@@ -0,0 +1,107 @@
+use super::{path_bit, BinaryNode, BinaryTrieError};
+use crate::{Nibbles, TrieHasher};
+use alloy_primitives::{Bytes, B256};
+
+/// Verifies the proof of the key against the binary trie root.
+///
+/// If `expected` is [`None`], the proof must show that the key is absent from the trie, either by
+/// ending at an empty subtrie or by ending at a leaf with a different key. Otherwise, the proof
+/// must end at a leaf with the key and the expected value. The proof nodes are ordered from the
+/// root.
+pub fn verify_binary_proof<'a, H, I>(
+    root: B256,
+    key: B256,
+    expected: Option<&Bytes>,
+    proof: I,
+) -> Result<(), BinaryTrieError>
+where
+    H: TrieHasher,
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    let path = Nibbles::unpack(key);
+    let mut expected_hash = root;
+    let mut depth = 0;
+    let mut got = None;
+    let mut terminated = false;
+    for encoded in proof {
+        let hash = H::hash(encoded);
+        if hash != expected_hash {
+            return Err(BinaryTrieError::NodeHashMismatch {
+                depth,
+                expected: expected_hash,
+                got: hash,
+            });
+        }
+        match BinaryNode::decode(encoded)? {
+            BinaryNode::Branch(branch) => {
+                expected_hash = if path_bit(&path, depth) { branch.right } else { branch.left };
+                depth += 1;
+            }
+            BinaryNode::Leaf(leaf) => {
+                got = (leaf.key == key).then_some(leaf.value);
+                terminated = true;
+                break;
+            }
+        }
+    }
+
+    // Empty subtries are not included in the proof, as their hash is zero.
+    if !terminated && !expected_hash.is_zero() {
+        return Err(BinaryTrieError::IncompleteProof { depth });
+    }
+    if got.as_ref() != expected {
+        return Err(BinaryTrieError::ValueMismatch { got, expected: expected.cloned() });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{binary_trie::BinaryTrieBuilder, KeccakHasher};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn empty_trie() {
+        let key = B256::with_last_byte(1);
+        assert_eq!(verify_binary_proof::<KeccakHasher, _>(B256::ZERO, key, None, []), Ok(()));
+        let value = Bytes::from_static(b"value");
+        assert_eq!(
+            verify_binary_proof::<KeccakHasher, _>(B256::ZERO, key, Some(&value), []),
+            Err(BinaryTrieError::ValueMismatch { got: None, expected: Some(value) })
+        );
+    }
+
+    #[test]
+    fn invalid_proofs() {
+        let keys = (0..8u8).map(|i| alloy_primitives::keccak256([i])).collect::<Vec<_>>();
+        let target = keys[0];
+        let value = Bytes::from_static(b"value");
+        let mut builder = BinaryTrieBuilder::<KeccakHasher>::new().with_proof_targets([target]);
+        for key in &keys {
+            builder.add_leaf(*key, value.clone());
+        }
+        let root = builder.root();
+        let proof = builder.take_proofs().remove(&target).unwrap();
+        let verify = |proof: &[Bytes]| {
+            verify_binary_proof::<KeccakHasher, _>(root, target, Some(&value), proof)
+        };
+        assert_eq!(verify(&proof), Ok(()));
+
+        // Truncated before the leaf.
+        assert!(matches!(
+            verify(&proof[..proof.len() - 1]),
+            Err(BinaryTrieError::IncompleteProof { .. })
+        ));
+        // Leaf with a different value.
+        let mut tampered = proof.clone();
+        let leaf = crate::binary_trie::BinaryLeaf { key: target, value: Bytes::from_static(b"x") };
+        *tampered.last_mut().unwrap() = BinaryNode::Leaf(leaf).encoded();
+        assert!(matches!(verify(&tampered), Err(BinaryTrieError::NodeHashMismatch { .. })));
+        // Wrong root.
+        assert!(matches!(
+            verify_binary_proof::<KeccakHasher, _>(B256::ZERO, target, Some(&value), &proof),
+            Err(BinaryTrieError::NodeHashMismatch { depth: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! Commitments to key/value sets, independent of the trie that computes them.
+//!
+//! [`StateCommitment`] maps a set of key/value pairs to the commitment of the set, such as the
+//! root of a trie. Clients that are generic over it can switch to a different commitment scheme,
+//! such as a Verkle trie (EIP-6800) or a binary trie, without depending on the Merkle Patricia
+//! trie specifics of the [`HashBuilder`]. The Merkle Patricia trie is provided by
+//! [`MerklePatriciaTrie`], and [`EthereumStateCommitment`] is the one used by Ethereum.
+
+use crate::{HashBuilder, KeccakHasher, KeccakKeyHasher, KeyHasher, TrieHasher};
+use alloy_primitives::B256;
+use core::{fmt::Debug, marker::PhantomData};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A scheme committing to a set of key/value pairs. See the [module documentation](self).
+pub trait StateCommitment: Clone + Copy + Debug + Default + Send + Sync + 'static {
+    /// The commitment to a set of key/value pairs.
+    type Commitment: Clone + Copy + PartialEq + Eq + Debug + Send + Sync + 'static;
+
+    /// Returns the commitment to the empty set.
+    fn empty_commitment() -> Self::Commitment;
+
+    /// Computes the commitment to the given key/value pairs.
+    ///
+    /// The entries don't need to be sorted, but their keys must be distinct.
+    fn commit<I, K, V>(entries: I) -> Self::Commitment
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>;
+}
+
+/// The [`StateCommitment`] of a Merkle Patricia trie, whose keys are mapped to paths with the
+/// [`KeyHasher`] `K` and whose nodes are hashed with the [`TrieHasher`] `H`.
+///
+/// The commitment is the root hash computed by the [`HashBuilder`].
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct MerklePatriciaTrie<K = KeccakKeyHasher, H = KeccakHasher>(PhantomData<(K, H)>);
+
+/// The [`StateCommitment`] of the Ethereum state and storage tries, which are keccak256 secure
+/// Merkle Patricia tries.
+pub type EthereumStateCommitment = MerklePatriciaTrie<KeccakKeyHasher, KeccakHasher>;
+
+impl<K: KeyHasher, H: TrieHasher> StateCommitment for MerklePatriciaTrie<K, H> {
+    type Commitment = B256;
+
+    #[inline]
+    fn empty_commitment() -> B256 {
+        H::empty_root()
+    }
+
+    fn commit<I, Key, V>(entries: I) -> B256
+    where
+        I: IntoIterator<Item = (Key, V)>,
+        Key: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut leaves =
+            entries.into_iter().map(|(key, value)| (K::hash_key(key), value)).collect::<Vec<_>>();
+        leaves.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut hash_builder = HashBuilder::<H>::new();
+        for (path, value) in leaves {
+            hash_builder.add_leaf(path, value.as_ref());
+        }
+        hash_builder.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{root::trie_root, IdentityKeyHasher, InlineThreshold, EMPTY_ROOT_HASH};
+
+    fn entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0u8..64).map(|i| (vec![i; (i as usize % 5) + 1], vec![0xab; i as usize + 1])).collect()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(EthereumStateCommitment::empty_commitment(), EMPTY_ROOT_HASH);
+        assert_eq!(
+            EthereumStateCommitment::commit(core::iter::empty::<(&[u8], &[u8])>()),
+            EMPTY_ROOT_HASH
+        );
+    }
+
+    #[test]
+    fn matches_trie_root() {
+        let entries = entries();
+        assert_eq!(
+            EthereumStateCommitment::commit(entries.clone()),
+            trie_root::<KeccakKeyHasher, _, _, _>(entries.clone())
+        );
+        assert_eq!(
+            MerklePatriciaTrie::<IdentityKeyHasher>::commit(entries.clone()),
+            trie_root::<IdentityKeyHasher, _, _, _>(entries)
+        );
+    }
+
+    #[test]
+    fn generic_hasher() {
+        // Short keys and values produce leaves that are embedded in their parent by default.
+        let entries = (0u8..16).map(|i| ([i], [i])).collect::<Vec<_>>();
+        let root = MerklePatriciaTrie::<IdentityKeyHasher>::commit(entries.clone());
+        let hashed = MerklePatriciaTrie::<IdentityKeyHasher, InlineThreshold<0>>::commit(entries);
+        assert_ne!(hashed, root);
+        assert_eq!(
+            MerklePatriciaTrie::<KeccakKeyHasher, InlineThreshold<0>>::empty_commitment(),
+            EMPTY_ROOT_HASH
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use super::{
+    HashedCursor, HashedStorageCursor, InMemoryHashedCursor, InMemoryTrieCursor, TrieCursor,
+};
+use crate::{BranchNodeCompact, Nibbles};
+use alloy_primitives::B256;
+use core::future::{ready, Future};
+
+/// The asynchronous version of [`TrieCursor`], for backends that fetch nodes over the network.
+///
+/// The returned futures are not required to be [`Send`], so that the traits can also be
+/// implemented for single-threaded runtimes.
+pub trait AsyncTrieCursor {
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Moves the cursor to the node at exactly the given path.
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>>;
+
+    /// Moves the cursor to the first node with a path greater than or equal to the given one.
+    fn seek(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>>;
+
+    /// Moves the cursor to the next node.
+    fn next(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>>;
+
+    /// Returns the path of the node at the cursor.
+    fn current(&mut self) -> impl Future<Output = Result<Option<Nibbles>, Self::Error>>;
+}
+
+/// The asynchronous version of [`HashedCursor`].
+pub trait AsyncHashedCursor {
+    /// The value of the entries.
+    type Value;
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Moves the cursor to the first entry with a key greater than or equal to the given one.
+    fn seek(
+        &mut self,
+        key: B256,
+    ) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>>;
+
+    /// Moves the cursor to the next entry.
+    fn next(&mut self) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>>;
+}
+
+/// The asynchronous version of [`HashedStorageCursor`].
+pub trait AsyncHashedStorageCursor: AsyncHashedCursor {
+    /// Returns `true` if the account has no storage slots.
+    fn is_storage_empty(&mut self) -> impl Future<Output = Result<bool, Self::Error>>;
+}
+
+impl<C: AsyncTrieCursor + ?Sized> AsyncTrieCursor for &mut C {
+    type Error = C::Error;
+
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        (**self).seek_exact(key)
+    }
+
+    fn seek(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        (**self).seek(key)
+    }
+
+    fn next(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        (**self).next()
+    }
+
+    fn current(&mut self) -> impl Future<Output = Result<Option<Nibbles>, Self::Error>> {
+        (**self).current()
+    }
+}
+
+impl<C: AsyncHashedCursor + ?Sized> AsyncHashedCursor for &mut C {
+    type Value = C::Value;
+    type Error = C::Error;
+
+    fn seek(
+        &mut self,
+        key: B256,
+    ) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>> {
+        (**self).seek(key)
+    }
+
+    fn next(&mut self) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>> {
+        (**self).next()
+    }
+}
+
+impl<C: AsyncHashedStorageCursor + ?Sized> AsyncHashedStorageCursor for &mut C {
+    fn is_storage_empty(&mut self) -> impl Future<Output = Result<bool, Self::Error>> {
+        (**self).is_storage_empty()
+    }
+}
+
+impl AsyncTrieCursor for InMemoryTrieCursor<'_> {
+    type Error = <Self as TrieCursor>::Error;
+
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        ready(TrieCursor::seek_exact(self, key))
+    }
+
+    fn seek(
+        &mut self,
+        key: Nibbles,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        ready(TrieCursor::seek(self, key))
+    }
+
+    fn next(
+        &mut self,
+    ) -> impl Future<Output = Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>> {
+        ready(TrieCursor::next(self))
+    }
+
+    fn current(&mut self) -> impl Future<Output = Result<Option<Nibbles>, Self::Error>> {
+        ready(TrieCursor::current(self))
+    }
+}
+
+impl<V: Clone> AsyncHashedCursor for InMemoryHashedCursor<'_, V> {
+    type Value = V;
+    type Error = <Self as HashedCursor>::Error;
+
+    fn seek(
+        &mut self,
+        key: B256,
+    ) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>> {
+        ready(HashedCursor::seek(self, key))
+    }
+
+    fn next(&mut self) -> impl Future<Output = Result<Option<(B256, Self::Value)>, Self::Error>> {
+        ready(HashedCursor::next(self))
+    }
+}
+
+impl<V: Clone> AsyncHashedStorageCursor for InMemoryHashedCursor<'_, V> {
+    fn is_storage_empty(&mut self) -> impl Future<Output = Result<bool, Self::Error>> {
+        ready(HashedStorageCursor::is_storage_empty(self))
+    }
+}
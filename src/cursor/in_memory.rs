@@ -0,0 +1,228 @@
+use super::{
+    HashedCursor, HashedCursorFactory, HashedStorageCursor, TrieCursor, TrieCursorFactory,
+};
+use crate::{BranchNodeCompact, HashMap, Nibbles, TrieAccount};
+use alloc::collections::BTreeMap;
+use alloy_primitives::{B256, U256};
+use core::{convert::Infallible, ops::Bound};
+
+/// The nodes of a storage trie without stored nodes.
+static EMPTY_TRIE: BTreeMap<Nibbles, BranchNodeCompact> = BTreeMap::new();
+
+/// The slots of an account without storage.
+static EMPTY_STORAGE: BTreeMap<B256, U256> = BTreeMap::new();
+
+/// A [`TrieCursor`] over branch nodes stored in memory.
+#[derive(Clone, Debug)]
+pub struct InMemoryTrieCursor<'a> {
+    nodes: &'a BTreeMap<Nibbles, BranchNodeCompact>,
+    current: Option<Nibbles>,
+}
+
+impl<'a> InMemoryTrieCursor<'a> {
+    /// Creates a new cursor over the given nodes.
+    pub const fn new(nodes: &'a BTreeMap<Nibbles, BranchNodeCompact>) -> Self {
+        Self { nodes, current: None }
+    }
+
+    fn set_current(
+        &mut self,
+        entry: Option<(&Nibbles, &BranchNodeCompact)>,
+    ) -> Option<(Nibbles, BranchNodeCompact)> {
+        let entry = entry.map(|(key, node)| (key.clone(), node.clone()));
+        self.current = entry.as_ref().map(|(key, _)| key.clone());
+        entry
+    }
+}
+
+impl TrieCursor for InMemoryTrieCursor<'_> {
+    type Error = Infallible;
+
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        let entry = self.nodes.get_key_value(&key);
+        Ok(self.set_current(entry))
+    }
+
+    fn seek(&mut self, key: Nibbles) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        let entry = self.nodes.range::<Nibbles, _>(key..).next();
+        Ok(self.set_current(entry))
+    }
+
+    fn next(&mut self) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        let entry = match &self.current {
+            Some(current) => {
+                self.nodes.range::<Nibbles, _>((Bound::Excluded(current), Bound::Unbounded)).next()
+            }
+            None => self.nodes.iter().next(),
+        };
+        Ok(self.set_current(entry))
+    }
+
+    fn current(&mut self) -> Result<Option<Nibbles>, Self::Error> {
+        Ok(self.current.clone())
+    }
+}
+
+/// A [`HashedCursor`] over hashed entries stored in memory.
+#[derive(Clone, Debug)]
+pub struct InMemoryHashedCursor<'a, V> {
+    entries: &'a BTreeMap<B256, V>,
+    current: Option<B256>,
+}
+
+impl<'a, V> InMemoryHashedCursor<'a, V> {
+    /// Creates a new cursor over the given entries.
+    pub const fn new(entries: &'a BTreeMap<B256, V>) -> Self {
+        Self { entries, current: None }
+    }
+}
+
+impl<V: Clone> HashedCursor for InMemoryHashedCursor<'_, V> {
+    type Value = V;
+    type Error = Infallible;
+
+    fn seek(&mut self, key: B256) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        let entry = self.entries.range(key..).next().map(|(key, value)| (*key, value.clone()));
+        self.current = entry.as_ref().map(|(key, _)| *key);
+        Ok(entry)
+    }
+
+    fn next(&mut self) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        let mut range = match self.current {
+            Some(current) => self.entries.range((Bound::Excluded(current), Bound::Unbounded)),
+            None => self.entries.range::<B256, _>(..),
+        };
+        let entry = range.next().map(|(key, value)| (*key, value.clone()));
+        self.current = entry.as_ref().map(|(key, _)| *key);
+        Ok(entry)
+    }
+}
+
+impl<V: Clone> HashedStorageCursor for InMemoryHashedCursor<'_, V> {
+    fn is_storage_empty(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.entries.is_empty())
+    }
+}
+
+/// A [`TrieCursorFactory`] over the branch nodes of the account trie and the storage tries
+/// stored in memory.
+#[derive(Clone, Copy, Debug)]
+pub struct InMemoryTrieCursorFactory<'a> {
+    account_nodes: &'a BTreeMap<Nibbles, BranchNodeCompact>,
+    storage_nodes: &'a HashMap<B256, BTreeMap<Nibbles, BranchNodeCompact>>,
+}
+
+impl<'a> InMemoryTrieCursorFactory<'a> {
+    /// Creates a new factory over the given account trie nodes and storage trie nodes, keyed by
+    /// hashed address.
+    pub const fn new(
+        account_nodes: &'a BTreeMap<Nibbles, BranchNodeCompact>,
+        storage_nodes: &'a HashMap<B256, BTreeMap<Nibbles, BranchNodeCompact>>,
+    ) -> Self {
+        Self { account_nodes, storage_nodes }
+    }
+}
+
+impl<'a> TrieCursorFactory for InMemoryTrieCursorFactory<'a> {
+    type AccountTrieCursor = InMemoryTrieCursor<'a>;
+    type StorageTrieCursor = InMemoryTrieCursor<'a>;
+    type Error = Infallible;
+
+    fn account_trie_cursor(&self) -> Result<Self::AccountTrieCursor, Self::Error> {
+        Ok(InMemoryTrieCursor::new(self.account_nodes))
+    }
+
+    fn storage_trie_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageTrieCursor, Self::Error> {
+        let nodes = self.storage_nodes.get(&hashed_address).unwrap_or(&EMPTY_TRIE);
+        Ok(InMemoryTrieCursor::new(nodes))
+    }
+}
+
+/// A [`HashedCursorFactory`] over hashed accounts and storage slots stored in memory.
+#[derive(Clone, Copy, Debug)]
+pub struct InMemoryHashedCursorFactory<'a> {
+    accounts: &'a BTreeMap<B256, TrieAccount>,
+    storages: &'a HashMap<B256, BTreeMap<B256, U256>>,
+}
+
+impl<'a> InMemoryHashedCursorFactory<'a> {
+    /// Creates a new factory over the given accounts and storage slots, keyed by hashed address.
+    pub const fn new(
+        accounts: &'a BTreeMap<B256, TrieAccount>,
+        storages: &'a HashMap<B256, BTreeMap<B256, U256>>,
+    ) -> Self {
+        Self { accounts, storages }
+    }
+}
+
+impl<'a> HashedCursorFactory for InMemoryHashedCursorFactory<'a> {
+    type AccountCursor = InMemoryHashedCursor<'a, TrieAccount>;
+    type StorageCursor = InMemoryHashedCursor<'a, U256>;
+    type Error = Infallible;
+
+    fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, Self::Error> {
+        Ok(InMemoryHashedCursor::new(self.accounts))
+    }
+
+    fn hashed_storage_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageCursor, Self::Error> {
+        let storage = self.storages.get(&hashed_address).unwrap_or(&EMPTY_STORAGE);
+        Ok(InMemoryHashedCursor::new(storage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trie_cursor() {
+        let nodes = [vec![0x1], vec![0x1, 0x2], vec![0x3]]
+            .into_iter()
+            .map(|path| (Nibbles::from_nibbles(path), BranchNodeCompact::default()))
+            .collect::<BTreeMap<_, _>>();
+        let mut cursor = InMemoryTrieCursor::new(&nodes);
+        let key = |entry: Option<(Nibbles, _)>| entry.map(|(key, _)| key.to_vec());
+
+        assert_eq!(cursor.current(), Ok(None));
+        assert_eq!(cursor.next().map(key), Ok(Some(vec![0x1])));
+        assert_eq!(
+            cursor.seek(Nibbles::from_nibbles([0x1, 0x0])).map(key),
+            Ok(Some(vec![0x1, 0x2]))
+        );
+        assert_eq!(cursor.next().map(key), Ok(Some(vec![0x3])));
+        assert_eq!(cursor.next().map(key), Ok(None));
+        assert_eq!(cursor.seek_exact(Nibbles::from_nibbles([0x2])).map(key), Ok(None));
+        assert_eq!(cursor.seek_exact(Nibbles::from_nibbles([0x3])).map(key), Ok(Some(vec![0x3])));
+        assert_eq!(cursor.current(), Ok(Some(Nibbles::from_nibbles([0x3]))));
+    }
+
+    #[test]
+    fn hashed_cursor() {
+        let entries = (1..4u8)
+            .map(|i| (B256::with_last_byte(i * 2), U256::from(i)))
+            .collect::<BTreeMap<_, _>>();
+        let mut cursor = InMemoryHashedCursor::new(&entries);
+
+        assert_eq!(cursor.is_storage_empty(), Ok(false));
+        assert_eq!(cursor.next(), Ok(Some((B256::with_last_byte(2), U256::from(1)))));
+        assert_eq!(
+            cursor.seek(B256::with_last_byte(3)),
+            Ok(Some((B256::with_last_byte(4), U256::from(2))))
+        );
+        assert_eq!(cursor.next(), Ok(Some((B256::with_last_byte(6), U256::from(3)))));
+        assert_eq!(cursor.next(), Ok(None));
+        assert_eq!(cursor.seek(B256::with_last_byte(7)), Ok(None));
+
+        let empty = BTreeMap::<B256, U256>::new();
+        assert_eq!(InMemoryHashedCursor::new(&empty).is_storage_empty(), Ok(true));
+    }
+}
@@ -0,0 +1,188 @@
+//! Cursor abstractions over the storage of trie nodes and hashed entries.
+//!
+//! The algorithms that walk the trie against a database are generic over these traits, so that
+//! they can be used with any storage backend. In-memory implementations are provided for testing,
+//! and overlay implementations apply pending [`TrieUpdates`](crate::TrieUpdates) and
+//! [`HashedPostState`](crate::HashedPostState) changes on top of another backend, so that roots of
+//! unpersisted blocks can be computed without writing to it.
+//!
+//! With the `async` feature, asynchronous versions of the traits are provided for backends that
+//! fetch nodes remotely, e.g. over RPC. The [`TrieWalker`](crate::walker::TrieWalker) awaits them
+//! in [`new_async`](crate::walker::TrieWalker::new_async) and
+//! [`advance_async`](crate::walker::TrieWalker::advance_async).
+
+use crate::{BranchNodeCompact, Nibbles, TrieAccount};
+use alloy_primitives::{B256, U256};
+
+mod in_memory;
+pub use in_memory::{
+    InMemoryHashedCursor, InMemoryHashedCursorFactory, InMemoryTrieCursor,
+    InMemoryTrieCursorFactory,
+};
+
+mod overlay;
+pub use overlay::{
+    OverlayHashedCursor, OverlayHashedCursorFactory, OverlayTrieCursor, OverlayTrieCursorFactory,
+};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncHashedCursor, AsyncHashedStorageCursor, AsyncTrieCursor};
+
+/// A cursor over the stored branch nodes of a trie, keyed by their path and ordered by key.
+pub trait TrieCursor {
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Moves the cursor to the node at exactly the given path.
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>;
+
+    /// Moves the cursor to the first node with a path greater than or equal to the given one.
+    fn seek(&mut self, key: Nibbles) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>;
+
+    /// Moves the cursor to the next node.
+    fn next(&mut self) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error>;
+
+    /// Returns the path of the node at the cursor.
+    fn current(&mut self) -> Result<Option<Nibbles>, Self::Error>;
+}
+
+/// A cursor over hashed entries, such as accounts or storage slots keyed by their hashed address
+/// or hashed slot, ordered by key.
+pub trait HashedCursor {
+    /// The value of the entries.
+    type Value;
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Moves the cursor to the first entry with a key greater than or equal to the given one.
+    fn seek(&mut self, key: B256) -> Result<Option<(B256, Self::Value)>, Self::Error>;
+
+    /// Moves the cursor to the next entry.
+    fn next(&mut self) -> Result<Option<(B256, Self::Value)>, Self::Error>;
+}
+
+/// A [`HashedCursor`] over the storage slots of a single account.
+pub trait HashedStorageCursor: HashedCursor {
+    /// Returns `true` if the account has no storage slots.
+    fn is_storage_empty(&mut self) -> Result<bool, Self::Error>;
+}
+
+/// A factory of [`TrieCursor`]s over the account trie and the storage tries.
+pub trait TrieCursorFactory {
+    /// The cursor over the account trie.
+    type AccountTrieCursor: TrieCursor<Error = Self::Error>;
+    /// The cursor over a storage trie.
+    type StorageTrieCursor: TrieCursor<Error = Self::Error>;
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Returns a cursor over the account trie.
+    fn account_trie_cursor(&self) -> Result<Self::AccountTrieCursor, Self::Error>;
+
+    /// Returns a cursor over the storage trie of the account with the given hashed address.
+    fn storage_trie_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageTrieCursor, Self::Error>;
+}
+
+/// A factory of [`HashedCursor`]s over the hashed accounts and the hashed storage slots.
+pub trait HashedCursorFactory {
+    /// The cursor over the accounts, keyed by hashed address. The storage roots of the accounts
+    /// are ignored.
+    type AccountCursor: HashedCursor<Value = TrieAccount, Error = Self::Error>;
+    /// The cursor over the non-zero storage slots of an account, keyed by hashed slot.
+    type StorageCursor: HashedStorageCursor<Value = U256, Error = Self::Error>;
+    /// The error returned by the storage backend.
+    type Error;
+
+    /// Returns a cursor over the hashed accounts.
+    fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, Self::Error>;
+
+    /// Returns a cursor over the storage slots of the account with the given hashed address.
+    fn hashed_storage_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageCursor, Self::Error>;
+}
+
+impl<F: TrieCursorFactory + ?Sized> TrieCursorFactory for &F {
+    type AccountTrieCursor = F::AccountTrieCursor;
+    type StorageTrieCursor = F::StorageTrieCursor;
+    type Error = F::Error;
+
+    fn account_trie_cursor(&self) -> Result<Self::AccountTrieCursor, Self::Error> {
+        (**self).account_trie_cursor()
+    }
+
+    fn storage_trie_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageTrieCursor, Self::Error> {
+        (**self).storage_trie_cursor(hashed_address)
+    }
+}
+
+impl<F: HashedCursorFactory + ?Sized> HashedCursorFactory for &F {
+    type AccountCursor = F::AccountCursor;
+    type StorageCursor = F::StorageCursor;
+    type Error = F::Error;
+
+    fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, Self::Error> {
+        (**self).hashed_account_cursor()
+    }
+
+    fn hashed_storage_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageCursor, Self::Error> {
+        (**self).hashed_storage_cursor(hashed_address)
+    }
+}
+
+impl<C: TrieCursor + ?Sized> TrieCursor for &mut C {
+    type Error = C::Error;
+
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        (**self).seek_exact(key)
+    }
+
+    fn seek(&mut self, key: Nibbles) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        (**self).seek(key)
+    }
+
+    fn next(&mut self) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        (**self).next()
+    }
+
+    fn current(&mut self) -> Result<Option<Nibbles>, Self::Error> {
+        (**self).current()
+    }
+}
+
+impl<C: HashedCursor + ?Sized> HashedCursor for &mut C {
+    type Value = C::Value;
+    type Error = C::Error;
+
+    fn seek(&mut self, key: B256) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        (**self).seek(key)
+    }
+
+    fn next(&mut self) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        (**self).next()
+    }
+}
+
+impl<C: HashedStorageCursor + ?Sized> HashedStorageCursor for &mut C {
+    fn is_storage_empty(&mut self) -> Result<bool, Self::Error> {
+        (**self).is_storage_empty()
+    }
+}
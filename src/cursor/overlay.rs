@@ -0,0 +1,429 @@
+use super::{
+    HashedCursor, HashedCursorFactory, HashedStorageCursor, TrieCursor, TrieCursorFactory,
+};
+use crate::{
+    hashed_state::HashedPostStateSorted, updates::TrieUpdates, BranchNodeCompact, Nibbles,
+    TrieAccount,
+};
+use alloc::vec::Vec;
+use alloy_primitives::{B256, U256};
+
+/// Sorts changed entries by key. A [`None`] value marks a removed entry.
+fn sorted<K: Ord, V>(entries: impl IntoIterator<Item = (K, Option<V>)>) -> Vec<(K, Option<V>)> {
+    let mut entries = entries.into_iter().collect::<Vec<_>>();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    entries
+}
+
+/// The overlay entries starting at the first key greater than or equal to, or greater than, the
+/// given one.
+fn overlay_from<'a, K: Ord, V>(
+    entries: &'a [(K, Option<V>)],
+    key: &K,
+    inclusive: bool,
+) -> impl Iterator<Item = (&'a K, &'a V)> {
+    let start = entries.partition_point(|(k, _)| if inclusive { k < key } else { k <= key });
+    entries[start..].iter().filter_map(|(key, value)| value.as_ref().map(|value| (key, value)))
+}
+
+/// A [`TrieCursor`] over the stored branch nodes of a base cursor, with pending updates applied
+/// on top.
+#[derive(Debug)]
+pub struct OverlayTrieCursor<C> {
+    base: C,
+    /// The updated nodes, and the removed ones as [`None`], sorted by path.
+    overlay: Vec<(Nibbles, Option<BranchNodeCompact>)>,
+    /// Whether all nodes of the base cursor were removed.
+    cleared: bool,
+    current: Option<Nibbles>,
+}
+
+impl<C> OverlayTrieCursor<C> {
+    /// Creates a new cursor over the base cursor with the given updated and removed nodes. If
+    /// `cleared` is set, the nodes of the base cursor are ignored.
+    pub fn new(
+        base: C,
+        updated: impl IntoIterator<Item = (Nibbles, BranchNodeCompact)>,
+        removed: impl IntoIterator<Item = Nibbles>,
+        cleared: bool,
+    ) -> Self {
+        let entries = removed
+            .into_iter()
+            .map(|path| (path, None))
+            .chain(updated.into_iter().map(|(path, node)| (path, Some(node))));
+        Self { base, overlay: sorted(entries), cleared, current: None }
+    }
+
+    fn is_overridden(&self, key: &Nibbles) -> bool {
+        self.overlay.binary_search_by(|(path, _)| path.cmp(key)).is_ok()
+    }
+}
+
+impl<C: TrieCursor> OverlayTrieCursor<C> {
+    /// Returns the first node of the base cursor with a path greater than or equal to, or greater
+    /// than, the given one, which is not overridden by the overlay.
+    fn base_from(
+        &mut self,
+        key: Nibbles,
+        inclusive: bool,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, C::Error> {
+        if self.cleared {
+            return Ok(None);
+        }
+        let mut entry = self.base.seek(key.clone())?;
+        if !inclusive && entry.as_ref().is_some_and(|(path, _)| *path == key) {
+            entry = self.base.next()?;
+        }
+        while entry.as_ref().is_some_and(|(path, _)| self.is_overridden(path)) {
+            entry = self.base.next()?;
+        }
+        Ok(entry)
+    }
+
+    fn seek_from(
+        &mut self,
+        key: Nibbles,
+        inclusive: bool,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, C::Error> {
+        let overlay = overlay_from(&self.overlay, &key, inclusive).next();
+        let overlay = overlay.map(|(path, node)| (path.clone(), node.clone()));
+        let entry = match (self.base_from(key, inclusive)?, overlay) {
+            (Some(base), Some(overlay)) => Some(if base.0 < overlay.0 { base } else { overlay }),
+            (base, overlay) => base.or(overlay),
+        };
+        self.current = entry.as_ref().map(|(path, _)| path.clone());
+        Ok(entry)
+    }
+}
+
+impl<C: TrieCursor> TrieCursor for OverlayTrieCursor<C> {
+    type Error = C::Error;
+
+    fn seek_exact(
+        &mut self,
+        key: Nibbles,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        let entry = match self.overlay.binary_search_by(|(path, _)| path.cmp(&key)) {
+            Ok(index) => self.overlay[index].1.clone().map(|node| (key, node)),
+            Err(_) if self.cleared => None,
+            Err(_) => self.base.seek_exact(key)?,
+        };
+        self.current = entry.as_ref().map(|(path, _)| path.clone());
+        Ok(entry)
+    }
+
+    fn seek(&mut self, key: Nibbles) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        self.seek_from(key, true)
+    }
+
+    fn next(&mut self) -> Result<Option<(Nibbles, BranchNodeCompact)>, Self::Error> {
+        match self.current.clone() {
+            Some(current) => self.seek_from(current, false),
+            None => self.seek_from(Nibbles::default(), true),
+        }
+    }
+
+    fn current(&mut self) -> Result<Option<Nibbles>, Self::Error> {
+        Ok(self.current.clone())
+    }
+}
+
+/// A [`HashedCursor`] over the entries of a base cursor, with pending changes applied on top.
+#[derive(Debug)]
+pub struct OverlayHashedCursor<C, V> {
+    base: C,
+    /// The changed entries, and the removed ones as [`None`], sorted by key.
+    overlay: Vec<(B256, Option<V>)>,
+    /// Whether all entries of the base cursor were removed.
+    cleared: bool,
+    current: Option<B256>,
+}
+
+impl<C, V> OverlayHashedCursor<C, V> {
+    /// Creates a new cursor over the base cursor with the given changed entries, where [`None`]
+    /// removes an entry. If `cleared` is set, the entries of the base cursor are ignored.
+    pub fn new(
+        base: C,
+        changes: impl IntoIterator<Item = (B256, Option<V>)>,
+        cleared: bool,
+    ) -> Self {
+        Self { base, overlay: sorted(changes), cleared, current: None }
+    }
+}
+
+impl<C: HashedCursor<Value = V>, V: Clone> OverlayHashedCursor<C, V> {
+    fn base_from(&mut self, key: B256, inclusive: bool) -> Result<Option<(B256, V)>, C::Error> {
+        if self.cleared {
+            return Ok(None);
+        }
+        let mut entry = self.base.seek(key)?;
+        if !inclusive && entry.as_ref().is_some_and(|(k, _)| *k == key) {
+            entry = self.base.next()?;
+        }
+        while entry.as_ref().is_some_and(|(k, _)| {
+            self.overlay.binary_search_by(|(overridden, _)| overridden.cmp(k)).is_ok()
+        }) {
+            entry = self.base.next()?;
+        }
+        Ok(entry)
+    }
+
+    fn seek_from(&mut self, key: B256, inclusive: bool) -> Result<Option<(B256, V)>, C::Error> {
+        let overlay = overlay_from(&self.overlay, &key, inclusive).next();
+        let overlay = overlay.map(|(key, value)| (*key, value.clone()));
+        let entry = match (self.base_from(key, inclusive)?, overlay) {
+            (Some(base), Some(overlay)) => Some(if base.0 < overlay.0 { base } else { overlay }),
+            (base, overlay) => base.or(overlay),
+        };
+        self.current = entry.as_ref().map(|(key, _)| *key);
+        Ok(entry)
+    }
+}
+
+impl<C: HashedCursor<Value = V>, V: Clone> HashedCursor for OverlayHashedCursor<C, V> {
+    type Value = V;
+    type Error = C::Error;
+
+    fn seek(&mut self, key: B256) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        self.seek_from(key, true)
+    }
+
+    fn next(&mut self) -> Result<Option<(B256, Self::Value)>, Self::Error> {
+        match self.current {
+            Some(current) => self.seek_from(current, false),
+            None => self.seek_from(B256::ZERO, true),
+        }
+    }
+}
+
+impl<C: HashedStorageCursor<Value = V>, V: Clone> HashedStorageCursor
+    for OverlayHashedCursor<C, V>
+{
+    /// Returns `true` if no slots remain after applying the changes. This moves the cursor to the
+    /// first slot.
+    fn is_storage_empty(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.seek(B256::ZERO)?.is_none())
+    }
+}
+
+/// A [`TrieCursorFactory`] applying pending [`TrieUpdates`] on top of the stored branch nodes of
+/// a base factory.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayTrieCursorFactory<'a, F> {
+    base: F,
+    updates: &'a TrieUpdates,
+}
+
+impl<'a, F> OverlayTrieCursorFactory<'a, F> {
+    /// Creates a new factory applying the given updates on top of the base factory.
+    pub const fn new(base: F, updates: &'a TrieUpdates) -> Self {
+        Self { base, updates }
+    }
+}
+
+impl<F: TrieCursorFactory> TrieCursorFactory for OverlayTrieCursorFactory<'_, F> {
+    type AccountTrieCursor = OverlayTrieCursor<F::AccountTrieCursor>;
+    type StorageTrieCursor = OverlayTrieCursor<F::StorageTrieCursor>;
+    type Error = F::Error;
+
+    fn account_trie_cursor(&self) -> Result<Self::AccountTrieCursor, Self::Error> {
+        let updated = self.updates.account_nodes.iter().map(|(k, v)| (k.clone(), v.clone()));
+        let removed = self.updates.removed_nodes.iter().cloned();
+        Ok(OverlayTrieCursor::new(self.base.account_trie_cursor()?, updated, removed, false))
+    }
+
+    fn storage_trie_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageTrieCursor, Self::Error> {
+        let base = self.base.storage_trie_cursor(hashed_address)?;
+        Ok(match self.updates.storage_tries.get(&hashed_address) {
+            Some(updates) => {
+                let updated = updates.storage_nodes.iter().map(|(k, v)| (k.clone(), v.clone()));
+                let removed = updates.removed_nodes.iter().cloned();
+                OverlayTrieCursor::new(base, updated, removed, updates.is_deleted)
+            }
+            None => OverlayTrieCursor::new(base, [], [], false),
+        })
+    }
+}
+
+/// A [`HashedCursorFactory`] applying pending changes of a sorted
+/// [`HashedPostState`](crate::HashedPostState) on top of the hashed state of a base factory.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayHashedCursorFactory<'a, F> {
+    base: F,
+    state: &'a HashedPostStateSorted,
+}
+
+impl<'a, F> OverlayHashedCursorFactory<'a, F> {
+    /// Creates a new factory applying the given state changes on top of the base factory.
+    pub const fn new(base: F, state: &'a HashedPostStateSorted) -> Self {
+        Self { base, state }
+    }
+}
+
+impl<F: HashedCursorFactory> HashedCursorFactory for OverlayHashedCursorFactory<'_, F> {
+    type AccountCursor = OverlayHashedCursor<F::AccountCursor, TrieAccount>;
+    type StorageCursor = OverlayHashedCursor<F::StorageCursor, U256>;
+    type Error = F::Error;
+
+    fn hashed_account_cursor(&self) -> Result<Self::AccountCursor, Self::Error> {
+        let changes = self.state.accounts.iter().map(|(k, v)| (*k, *v));
+        Ok(OverlayHashedCursor::new(self.base.hashed_account_cursor()?, changes, false))
+    }
+
+    fn hashed_storage_cursor(
+        &self,
+        hashed_address: B256,
+    ) -> Result<Self::StorageCursor, Self::Error> {
+        let base = self.base.hashed_storage_cursor(hashed_address)?;
+        Ok(match self.state.storages.get(&hashed_address) {
+            Some(storage) => {
+                let changes = storage
+                    .storage
+                    .iter()
+                    .map(|(slot, value)| (*slot, (!value.is_zero()).then_some(*value)));
+                OverlayHashedCursor::new(base, changes, storage.wiped)
+            }
+            None => OverlayHashedCursor::new(base, [], false),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursor::{InMemoryHashedCursorFactory, InMemoryTrieCursorFactory},
+        hashed_state::{HashedPostState, HashedStorage},
+        root::StateRoot,
+        HashMap,
+    };
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::keccak256;
+
+    type Storages = HashMap<B256, BTreeMap<B256, U256>>;
+
+    fn apply(
+        accounts: &mut BTreeMap<B256, TrieAccount>,
+        storages: &mut Storages,
+        state: &HashedPostState,
+    ) {
+        for (hashed_address, account) in &state.accounts {
+            match account {
+                Some(account) => _ = accounts.insert(*hashed_address, *account),
+                None => _ = accounts.remove(hashed_address),
+            }
+        }
+        for (hashed_address, changes) in &state.storages {
+            let storage = storages.entry(*hashed_address).or_default();
+            if changes.wiped {
+                storage.clear();
+            }
+            for (slot, value) in &changes.storage {
+                if value.is_zero() {
+                    storage.remove(slot);
+                } else {
+                    storage.insert(*slot, *value);
+                }
+            }
+        }
+    }
+
+    fn full_root(accounts: &BTreeMap<B256, TrieAccount>, storages: &Storages) -> B256 {
+        let (account_nodes, storage_nodes) = (BTreeMap::new(), HashMap::default());
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(accounts, storages);
+        StateRoot::new(trie, hashed).root().unwrap()
+    }
+
+    #[test]
+    fn overlay_cursors() {
+        let hashed_address = |i: u64| keccak256(i.to_be_bytes());
+        let hashed_slot = |i: u64| keccak256((i + 1000).to_be_bytes());
+        let account = |nonce: u64| TrieAccount { nonce, ..Default::default() };
+
+        // The persisted state and its branch nodes.
+        let accounts =
+            (0..100).map(|i| (hashed_address(i), account(i))).collect::<BTreeMap<_, _>>();
+        let storages = (0..10)
+            .map(|i| {
+                let storage = (1..=i * 4).map(|j| (hashed_slot(j), U256::from(j))).collect();
+                (hashed_address(i), storage)
+            })
+            .collect::<Storages>();
+        let (account_nodes, storage_nodes) = (BTreeMap::new(), HashMap::default());
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+        let (_, updates) = StateRoot::new(trie, hashed).root_with_updates().unwrap();
+        let mut account_nodes = account_nodes;
+        account_nodes.extend(updates.account_nodes);
+        let storage_nodes = updates
+            .storage_tries
+            .into_iter()
+            .map(|(hashed_address, updates)| {
+                (hashed_address, updates.storage_nodes.into_iter().collect())
+            })
+            .collect::<HashMap<_, BTreeMap<_, _>>>();
+        let base_trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let base_hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+
+        // The first unpersisted block changes and removes accounts and slots, and wipes a storage.
+        let mut first = HashedPostState::default();
+        first.accounts.insert(hashed_address(5), Some(account(500)));
+        first.accounts.insert(hashed_address(50), None);
+        first.accounts.insert(hashed_address(200), Some(account(200)));
+        let mut storage = HashedStorage::default();
+        storage.storage.insert(hashed_slot(1), U256::ZERO);
+        storage.storage.insert(hashed_slot(100), U256::from(100));
+        first.storages.insert(hashed_address(9), storage);
+        first.storages.insert(hashed_address(8), HashedStorage::wiped());
+
+        let (mut expected_accounts, mut expected_storages) = (accounts.clone(), storages.clone());
+        apply(&mut expected_accounts, &mut expected_storages, &first);
+        let sorted = first.clone().into_sorted();
+        let hashed = OverlayHashedCursorFactory::new(base_hashed, &sorted);
+        let (root, first_updates) = StateRoot::new(base_trie, hashed)
+            .with_prefix_sets(first.construct_prefix_sets())
+            .root_with_updates()
+            .unwrap();
+        assert_eq!(root, full_root(&expected_accounts, &expected_storages));
+
+        // The second block is computed on top of the first one, without persisting it.
+        let mut second = HashedPostState::default();
+        second.accounts.insert(hashed_address(60), Some(account(600)));
+        let mut storage = HashedStorage::default();
+        storage.storage.insert(hashed_slot(2), U256::from(2000));
+        second.storages.insert(hashed_address(7), storage);
+        second.storages.insert(hashed_address(8), HashedStorage::default());
+        second
+            .storages
+            .get_mut(&hashed_address(8))
+            .unwrap()
+            .storage
+            .insert(hashed_slot(3), U256::from(3));
+
+        apply(&mut expected_accounts, &mut expected_storages, &second);
+        let sorted = first.merge(second.clone()).into_sorted();
+        let trie = OverlayTrieCursorFactory::new(base_trie, &first_updates);
+        let hashed = OverlayHashedCursorFactory::new(base_hashed, &sorted);
+        let root = StateRoot::new(trie, hashed)
+            .with_prefix_sets(second.construct_prefix_sets())
+            .root()
+            .unwrap();
+        assert_eq!(root, full_root(&expected_accounts, &expected_storages));
+
+        // The overlay cursors iterate over the merged entries in order.
+        let mut cursor = hashed.hashed_storage_cursor(hashed_address(8)).unwrap();
+        assert!(!cursor.is_storage_empty().unwrap());
+        assert_eq!(cursor.seek(B256::ZERO), Ok(Some((hashed_slot(3), U256::from(3)))));
+        assert_eq!(cursor.next(), Ok(None));
+        let mut cursor = hashed.hashed_account_cursor().unwrap();
+        let mut merged = Vec::new();
+        while let Some(entry) = cursor.next().unwrap() {
+            merged.push(entry);
+        }
+        assert_eq!(merged, expected_accounts.into_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,428 @@
+//! Differences between two tries.
+//!
+//! [`diff_tries`] walks two tries with given roots through their [`NodeProvider`]s, such as the
+//! node databases of two snapshots or the witnesses of two executions of a block, and returns the
+//! leaves that were added, removed or changed, along with the paths of the nodes whose subtries
+//! differ. Subtries that are referenced by the same hash in both tries are skipped without
+//! fetching their nodes, so only the nodes on the paths to the differences are visited.
+
+use crate::{
+    nodes::{BranchNode, RlpNode, TrieNode, CHILD_INDEX_RANGE},
+    resolver::{resolve, NodeProvider, ResolveError},
+    Nibbles, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::B256;
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A leaf that differs between two tries.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LeafChange {
+    /// The leaf is only in the new trie.
+    Added {
+        /// The full path of the leaf.
+        path: Nibbles,
+        /// The value of the leaf.
+        value: Vec<u8>,
+    },
+    /// The leaf is only in the old trie.
+    Removed {
+        /// The full path of the leaf.
+        path: Nibbles,
+        /// The value of the leaf.
+        value: Vec<u8>,
+    },
+    /// The leaf is in both tries, with different values.
+    Changed {
+        /// The full path of the leaf.
+        path: Nibbles,
+        /// The value of the leaf in the old trie.
+        old: Vec<u8>,
+        /// The value of the leaf in the new trie.
+        new: Vec<u8>,
+    },
+}
+
+impl LeafChange {
+    /// Returns the full path of the leaf.
+    pub const fn path(&self) -> &Nibbles {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+/// The differences between two tries, returned by [`diff_tries`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TrieDiff {
+    /// The leaves that differ, ordered by path.
+    pub leaves: Vec<LeafChange>,
+    /// The paths of the branch and extension nodes of either trie whose subtries differ, ordered
+    /// by path. These are the nodes on the paths from the root to the differing leaves.
+    pub divergent_paths: Vec<Nibbles>,
+}
+
+impl TrieDiff {
+    /// Returns `true` if no leaves differ.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+}
+
+/// Error while comparing two tries.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiffError {
+    /// A node of the old trie could not be resolved.
+    Old(ResolveError),
+    /// A node of the new trie could not be resolved.
+    New(ResolveError),
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for DiffError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Old(error) | Self::New(error) => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Old(error) => write!(f, "old trie: {error}"),
+            Self::New(error) => write!(f, "new trie: {error}"),
+        }
+    }
+}
+
+/// Compares the trie with the old root, whose nodes are fetched from the old provider, with the
+/// trie with the new root, whose nodes are fetched from the new provider. See the
+/// [module documentation](self).
+///
+/// Returns an error if a node that has to be visited is missing from its provider.
+pub fn diff_tries<A: NodeProvider, B: NodeProvider>(
+    old_root: B256,
+    old: A,
+    new_root: B256,
+    new: B,
+) -> Result<TrieDiff, DiffError> {
+    let mut differ = Differ { old, new, diff: TrieDiff::default() };
+    differ.compare(Nibbles::default(), Subtrie::root(old_root), Subtrie::root(new_root))?;
+    Ok(differ.diff)
+}
+
+/// The subtrie of one of the tries at a path.
+#[derive(Clone, PartialEq, Eq)]
+enum Subtrie {
+    Empty,
+    /// A node referenced by its parent, which is yet to be resolved.
+    Ref(RlpNode),
+    Branch(BranchNode),
+    /// The rest of the key of an extension node, along with its child.
+    Extension(Nibbles, RlpNode),
+    /// The rest of the key of a leaf node, along with its value.
+    Leaf(Nibbles, Vec<u8>),
+}
+
+impl Subtrie {
+    fn root(root: B256) -> Self {
+        if root == EMPTY_ROOT_HASH {
+            Self::Empty
+        } else {
+            Self::Ref(RlpNode::word_rlp(&root))
+        }
+    }
+
+    /// Resolves a node reference, returning the subtrie and whether it's a branch or extension
+    /// node of the trie.
+    fn resolve<P: NodeProvider>(
+        self,
+        provider: &P,
+        path: &Nibbles,
+    ) -> Result<(Self, bool), ResolveError> {
+        let Self::Ref(child) = self else { return Ok((self, false)) };
+        Ok(match resolve(provider, path, &child, |_| {})? {
+            TrieNode::Branch(branch) => (Self::Branch(branch), true),
+            TrieNode::Extension(extension) => {
+                (Self::Extension(extension.key, extension.child), true)
+            }
+            TrieNode::Leaf(leaf) => (Self::Leaf(leaf.key, leaf.value), false),
+            TrieNode::EmptyRoot => (Self::Empty, false),
+        })
+    }
+
+    /// Returns the subtries one nibble below a resolved branch or extension.
+    fn children(self) -> [Self; 16] {
+        let mut children = core::array::from_fn(|_| Self::Empty);
+        match self {
+            Self::Branch(branch) => {
+                for (nibble, child) in branch.children() {
+                    children[nibble as usize] = Self::Ref(child.clone());
+                }
+            }
+            Self::Extension(key, child) => {
+                children[key[0] as usize] = if key.len() == 1 {
+                    Self::Ref(child)
+                } else {
+                    Self::Extension(key.slice(1..), child)
+                };
+            }
+            Self::Empty | Self::Ref(_) | Self::Leaf(..) => {}
+        }
+        children
+    }
+
+    /// Collects the leaves of the subtrie at the path.
+    fn leaves<P: NodeProvider>(
+        self,
+        provider: &P,
+        path: Nibbles,
+        leaves: &mut Vec<(Nibbles, Vec<u8>)>,
+    ) -> Result<(), ResolveError> {
+        match self.resolve(provider, &path)?.0 {
+            Self::Branch(branch) => {
+                for (nibble, child) in branch.children() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    Self::Ref(child.clone()).leaves(provider, child_path, leaves)?;
+                }
+            }
+            Self::Extension(key, child) => {
+                Self::Ref(child).leaves(provider, path.join(&key), leaves)?;
+            }
+            Self::Leaf(key, value) => leaves.push((path.join(&key), value)),
+            Self::Empty | Self::Ref(_) => {}
+        }
+        Ok(())
+    }
+}
+
+struct Differ<A, B> {
+    old: A,
+    new: B,
+    diff: TrieDiff,
+}
+
+impl<A: NodeProvider, B: NodeProvider> Differ<A, B> {
+    /// Compares the subtries of both tries at the path, descending in lockstep while both are
+    /// branch or extension nodes.
+    fn compare(&mut self, path: Nibbles, old: Subtrie, new: Subtrie) -> Result<(), DiffError> {
+        if old == new {
+            return Ok(());
+        }
+        let (old, old_is_node) = old.resolve(&self.old, &path).map_err(DiffError::Old)?;
+        let (new, new_is_node) = new.resolve(&self.new, &path).map_err(DiffError::New)?;
+        // The rest of an extension can be equal to an extension node of the other trie.
+        if old == new {
+            return Ok(());
+        }
+        if old_is_node || new_is_node {
+            self.diff.divergent_paths.push(path.clone());
+        }
+
+        match (old, new) {
+            (Subtrie::Extension(old_key, old_child), Subtrie::Extension(new_key, new_child))
+                if old_key == new_key =>
+            {
+                let path = path.join(&old_key);
+                self.compare(path, Subtrie::Ref(old_child), Subtrie::Ref(new_child))
+            }
+            (
+                old @ (Subtrie::Branch(_) | Subtrie::Extension(..)),
+                new @ (Subtrie::Branch(_) | Subtrie::Extension(..)),
+            ) => {
+                for (nibble, (old, new)) in
+                    CHILD_INDEX_RANGE.zip(old.children().into_iter().zip(new.children()))
+                {
+                    if old == Subtrie::Empty && new == Subtrie::Empty {
+                        continue;
+                    }
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    self.compare(child_path, old, new)?;
+                }
+                Ok(())
+            }
+            // At least one side is a leaf or empty, so the leaves are compared one by one.
+            (old, new) => {
+                let mut old_leaves = Vec::new();
+                old.leaves(&self.old, path.clone(), &mut old_leaves).map_err(DiffError::Old)?;
+                let mut new_leaves = Vec::new();
+                new.leaves(&self.new, path, &mut new_leaves).map_err(DiffError::New)?;
+                self.merge(old_leaves, new_leaves);
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the differences between two lists of leaves ordered by path.
+    fn merge(&mut self, old: Vec<(Nibbles, Vec<u8>)>, new: Vec<(Nibbles, Vec<u8>)>) {
+        let mut old = old.into_iter().peekable();
+        let mut new = new.into_iter().peekable();
+        loop {
+            let change = match (old.peek(), new.peek()) {
+                (Some((old_path, _)), Some((new_path, _))) if old_path == new_path => {
+                    let (path, old) = old.next().unwrap();
+                    let (_, new) = new.next().unwrap();
+                    if old == new {
+                        continue;
+                    }
+                    LeafChange::Changed { path, old, new }
+                }
+                (Some((old_path, _)), Some((new_path, _))) if new_path < old_path => {
+                    let (path, value) = new.next().unwrap();
+                    LeafChange::Added { path, value }
+                }
+                (Some(_), _) => {
+                    let (path, value) = old.next().unwrap();
+                    LeafChange::Removed { path, value }
+                }
+                (None, Some(_)) => {
+                    let (path, value) = new.next().unwrap();
+                    LeafChange::Added { path, value }
+                }
+                (None, None) => return,
+            };
+            self.diff.leaves.push(change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofNodesByHash, HashBuilder};
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{keccak256, U256};
+
+    /// Builds a trie with the given leaves, returning its root and all of its nodes by hash.
+    fn trie(leaves: &BTreeMap<Nibbles, Vec<u8>>) -> (B256, ProofNodesByHash) {
+        let retainer = crate::proof::ProofRetainer::new(leaves.keys().cloned().collect());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (path, value) in leaves {
+            hb.add_leaf(path.clone(), value);
+        }
+        let root = hb.root();
+        (root, hb.take_proof_nodes().into())
+    }
+
+    /// Computes the expected leaf changes from the leaves of both tries.
+    fn expected(
+        old: &BTreeMap<Nibbles, Vec<u8>>,
+        new: &BTreeMap<Nibbles, Vec<u8>>,
+    ) -> Vec<LeafChange> {
+        let mut paths = old.keys().chain(new.keys()).cloned().collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+            .into_iter()
+            .filter_map(|path| match (old.get(&path).cloned(), new.get(&path).cloned()) {
+                (Some(old), Some(new)) if old == new => None,
+                (Some(old), Some(new)) => Some(LeafChange::Changed { path, old, new }),
+                (Some(value), None) => Some(LeafChange::Removed { path, value }),
+                (None, Some(value)) => Some(LeafChange::Added { path, value }),
+                (None, None) => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn assert_diff(old: &BTreeMap<Nibbles, Vec<u8>>, new: &BTreeMap<Nibbles, Vec<u8>>) {
+        let (old_root, old_nodes) = trie(old);
+        let (new_root, new_nodes) = trie(new);
+        let diff = diff_tries(old_root, &old_nodes, new_root, &new_nodes).unwrap();
+        assert_eq!(diff.leaves, expected(old, new));
+        assert!(diff.divergent_paths.windows(2).all(|paths| paths[0] < paths[1]));
+        for path in &diff.divergent_paths {
+            assert!(diff.leaves.iter().any(|change| change.path().starts_with(path)));
+        }
+    }
+
+    #[test]
+    fn hashed_keys() {
+        let old = (0..200u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let mut new = old.clone();
+        for (i, value) in new.values_mut().enumerate().step_by(31) {
+            *value = alloy_rlp::encode(U256::from(i + 1000));
+        }
+        let removed = old.keys().skip(5).step_by(47).cloned().collect::<Vec<_>>();
+        for key in removed {
+            new.remove(&key);
+        }
+        new.extend((200..210u64).map(|i| {
+            (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+        }));
+
+        assert_diff(&old, &new);
+        assert_diff(&new, &old);
+        assert_diff(&old, &BTreeMap::new());
+        assert_diff(&BTreeMap::new(), &new);
+    }
+
+    #[test]
+    fn short_keys() {
+        // Short keys and values produce extension nodes and nodes embedded in their parent.
+        let tries = (0..40u8)
+            .map(|seed| {
+                keccak256([seed])
+                    .iter()
+                    .take(seed as usize % 12 + 1)
+                    .map(|&byte| (Nibbles::from_nibbles([1, 2, byte >> 6, byte & 3]), vec![byte]))
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        for old in &tries {
+            for new in &tries {
+                assert_diff(old, new);
+            }
+        }
+    }
+
+    #[test]
+    fn skips_identical_subtries() {
+        let old = (0..100u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let (old_root, old_nodes) = trie(&old);
+        let diff = diff_tries(
+            old_root,
+            ProofNodesByHash::default(),
+            old_root,
+            ProofNodesByHash::default(),
+        );
+        assert_eq!(diff, Ok(TrieDiff::default()));
+
+        // Only the nodes on the path to the changed leaf are fetched.
+        let path = old.keys().nth(42).unwrap().clone();
+        let mut new = old.clone();
+        new.insert(path.clone(), vec![0xff; 40]);
+        let (new_root, new_nodes) = trie(&new);
+        let old_proof = crate::resolver::TrieResolver::new(&old_nodes, old_root).proof(&path);
+        let old_proof = old_proof.unwrap().into_iter().collect::<ProofNodesByHash>();
+        let diff = diff_tries(old_root, &old_proof, new_root, &new_nodes).unwrap();
+        assert_eq!(diff.leaves, expected(&old, &new));
+        assert!(diff.divergent_paths.iter().all(|prefix| path.starts_with(prefix)));
+        assert_eq!(diff.divergent_paths[0], Nibbles::default());
+
+        let diff = diff_tries(old_root, &old_proof, new_root, ProofNodesByHash::default());
+        assert_eq!(
+            diff,
+            Err(DiffError::New(ResolveError::MissingNode {
+                path: Nibbles::default(),
+                hash: new_root
+            }))
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! A differential fuzz harness, checking the production paths of the crate against the
+//! [`ReferenceTrie`].
+//!
+//! A fuzz case is a sequence of [`Operation`]s, which implements both [`arbitrary::Arbitrary`],
+//! for `cargo-fuzz`, and [`proptest::arbitrary::Arbitrary`], for shrinking failing cases to a
+//! minimal sequence. Each operation is applied to the reference trie and to a [`SparseTrie`] that
+//! is updated incrementally, and the roots and proofs computed by the
+//! [`HashBuilder`] and the [`SparseTrie`] are asserted to match the reference.
+//! Any mismatch panics.
+//!
+//! A `cargo-fuzz` target is a single call to [`run`]:
+//!
+//! ```ignore
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|operations: Vec<alloy_trie::fuzz::Operation>| {
+//!     alloy_trie::fuzz::run(&operations);
+//! });
+//! ```
+
+use crate::{
+    proof::{verify_proof, ProofRetainer},
+    reference::ReferenceTrie,
+    sparse::SparseTrie,
+    HashBuilder, Nibbles,
+};
+use alloy_primitives::{keccak256, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The key of a leaf in a fuzz case, mapped to a path of 64 nibbles.
+///
+/// Keys are drawn from small spaces, so that operations hit existing leaves.
+#[derive(
+    Clone, Copy, PartialEq, Eq, Debug, derive_arbitrary::Arbitrary, proptest_derive::Arbitrary,
+)]
+pub enum Key {
+    /// The keccak256 hash of the byte, as in tries keyed by hashes.
+    Hashed(u8),
+    /// A key whose first and last bytes are those of the number, with zeros in between, so that
+    /// keys share long prefixes and form extension nodes.
+    Clustered(u16),
+}
+
+impl Key {
+    /// Returns the path of the leaf.
+    pub fn path(self) -> Nibbles {
+        match self {
+            Self::Hashed(byte) => Nibbles::unpack(keccak256([byte])),
+            Self::Clustered(number) => {
+                let mut key = B256::ZERO;
+                let [first, last] = number.to_be_bytes();
+                key[0] = first;
+                key[31] = last;
+                Nibbles::unpack(key)
+            }
+        }
+    }
+}
+
+/// An operation of a fuzz case. See the [module documentation](self).
+#[derive(Clone, PartialEq, Eq, Debug, derive_arbitrary::Arbitrary, proptest_derive::Arbitrary)]
+pub enum Operation {
+    /// Inserts or updates the leaf at the key. Empty values are replaced with `[0]`, as leaves
+    /// can't be empty.
+    Insert(Key, Vec<u8>),
+    /// Updates the value of an existing leaf, selected by its index modulo the number of leaves.
+    /// Does nothing if the trie is empty.
+    Update(u16, Vec<u8>),
+    /// Removes the leaf at the key, if any.
+    Delete(Key),
+    /// Checks the roots computed by the hash builder and the sparse trie.
+    Root,
+    /// Checks the proof of the key retained by the hash builder.
+    Proof(Key),
+    /// Checks that the proof of the key verifies against the root with the value of the key, and
+    /// fails with a different value.
+    Verify(Key),
+}
+
+/// The state of a fuzz case, to which the [`Operation`]s are applied.
+#[derive(Clone, Default, Debug)]
+pub struct Harness {
+    reference: ReferenceTrie,
+    sparse: SparseTrie,
+}
+
+impl Harness {
+    /// Creates the harness of an empty trie.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the reference trie holding the current leaves.
+    pub const fn reference(&self) -> &ReferenceTrie {
+        &self.reference
+    }
+
+    /// Applies the operation, panicking if a production path disagrees with the reference.
+    pub fn apply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Insert(key, value) => self.insert(key.path(), value),
+            Operation::Update(index, value) => {
+                let len = self.reference.leaves().len();
+                if len > 0 {
+                    let key = self.reference.leaves().keys().nth(*index as usize % len).unwrap();
+                    self.insert(key.clone(), value);
+                }
+            }
+            Operation::Delete(key) => {
+                let key = key.path();
+                let removed = self.sparse.remove_leaf(&key).expect("sparse trie is revealed");
+                assert_eq!(removed, self.reference.remove(&key), "removed value of {key:?}");
+            }
+            Operation::Root => {
+                let root = self.reference.root();
+                assert_eq!(self.sparse.root(), root, "sparse trie root");
+                assert_eq!(self.hash_builder(ProofRetainer::default()).0, root, "root");
+            }
+            Operation::Proof(key) => {
+                let key = key.path();
+                let retainer = ProofRetainer::from_iter([key.clone()]);
+                let (_, proof_nodes) = self.hash_builder(retainer);
+                assert_eq!(proof_nodes, self.reference.proof_nodes(&[key]), "proof nodes");
+            }
+            Operation::Verify(key) => {
+                let key = key.path();
+                let root = self.reference.root();
+                let proof = self.reference.proof(&key);
+                let value = self.reference.get(&key).map(<[u8]>::to_vec);
+                assert_eq!(verify_proof(root, key.clone(), value.clone(), &proof), Ok(()));
+
+                let mut wrong = value.unwrap_or_default();
+                wrong.push(0);
+                assert!(verify_proof(root, key, Some(wrong), &proof).is_err(), "wrong value");
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Nibbles, value: &[u8]) {
+        let value = if value.is_empty() { vec![0] } else { value.to_vec() };
+        self.sparse.update_leaf(key.clone(), value.clone()).expect("sparse trie is revealed");
+        self.reference.insert(key, value);
+    }
+
+    /// Computes the root of the current leaves with the hash builder, along with the nodes
+    /// retained by the retainer.
+    fn hash_builder(&self, retainer: ProofRetainer) -> (B256, crate::proof::ProofNodes) {
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in self.reference.leaves() {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_proof_nodes())
+    }
+}
+
+/// Applies the operations to a new [`Harness`], checking the root after the last one.
+pub fn run(operations: &[Operation]) {
+    let mut harness = Harness::new();
+    for operation in operations {
+        harness.apply(operation);
+    }
+    harness.apply(&Operation::Root);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn operations() {
+        run(&[
+            Operation::Root,
+            Operation::Update(3, vec![1]),
+            Operation::Insert(Key::Clustered(0x1201), vec![]),
+            Operation::Insert(Key::Clustered(0x1202), vec![2; 40]),
+            Operation::Insert(Key::Hashed(1), vec![3]),
+            Operation::Proof(Key::Clustered(0x1202)),
+            Operation::Verify(Key::Clustered(0x1203)),
+            Operation::Update(4, vec![4; 33]),
+            Operation::Delete(Key::Clustered(0x1201)),
+            Operation::Delete(Key::Hashed(2)),
+            Operation::Verify(Key::Hashed(1)),
+            Operation::Root,
+        ]);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let data = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+        let mut unstructured = arbitrary::Unstructured::new(&data);
+        let operations: Vec<Operation> =
+            arbitrary::Arbitrary::arbitrary(&mut unstructured).unwrap();
+        run(&operations);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no proptest")]
+    fn arbitrary_operations() {
+        proptest!(ProptestConfig::with_cases(64), |(operations: Vec<Operation>)| {
+            run(&operations);
+        });
+    }
+}
@@ -0,0 +1,309 @@
+use super::HashBuilder;
+use crate::{nodes::RlpNode, TrieHasher};
+use alloc::{boxed::Box, vec::Vec};
+use alloy_primitives::B256;
+use core::{fmt, ops::Range};
+
+/// A function hashing a batch of node encodings into the output at the same indices.
+type HashBatchFn = Box<dyn FnMut(&[&[u8]], &mut [B256]) + Send + Sync>;
+
+/// A node whose hash is deferred, and the placeholder of which is on the stack.
+#[derive(Clone, Debug)]
+struct DeferredNode {
+    /// The index of the placeholder on the stack.
+    index: usize,
+    /// The range of the encoding of the node in the preimage buffer.
+    preimage: Range<usize>,
+    /// Whether the hash is inserted into the hash cache once computed.
+    cached: bool,
+}
+
+/// Hashes the nodes of a [`HashBuilder`] in batches with a user-supplied function, e.g. a SIMD,
+/// GPU or hardware-accelerated keccak256, instead of one at a time.
+///
+/// The hashes of nodes that are referenced by hash are deferred: their encodings are collected and
+/// placeholders are pushed onto the stack. Deferred hashes are resolved once their parent is
+/// built, so all deferred children of a branch node are hashed in a single batch, along with the
+/// branch node itself if it is the root. Batches hold at most 16 nodes.
+///
+/// The function is passed the encodings of a batch and must write the hash of each encoding to
+/// the output at the same index, as computed by the [`TrieHasher`] of the builder. Nodes that are
+/// embedded in their parent are never hashed.
+pub struct BatchHasher {
+    hash_batch: HashBatchFn,
+    deferred: Vec<DeferredNode>,
+    preimages: Vec<u8>,
+    hashes: Vec<B256>,
+    batches: u64,
+    nodes_hashed: u64,
+}
+
+impl fmt::Debug for BatchHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchHasher")
+            .field("deferred", &self.deferred.len())
+            .field("batches", &self.batches)
+            .field("nodes_hashed", &self.nodes_hashed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BatchHasher {
+    /// Creates a batch hasher calling the function with each batch of node encodings.
+    pub fn new(hash_batch: impl FnMut(&[&[u8]], &mut [B256]) + Send + Sync + 'static) -> Self {
+        Self {
+            hash_batch: Box::new(hash_batch),
+            deferred: Vec::new(),
+            preimages: Vec::new(),
+            hashes: Vec::new(),
+            batches: 0,
+            nodes_hashed: 0,
+        }
+    }
+
+    /// Returns the number of batches hashed so far.
+    pub const fn batches(&self) -> u64 {
+        self.batches
+    }
+
+    /// Returns the number of nodes hashed so far.
+    pub const fn nodes_hashed(&self) -> u64 {
+        self.nodes_hashed
+    }
+
+    /// Returns the number of nodes whose hashes are deferred.
+    pub fn deferred(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Drops the deferred nodes, e.g. when the builder is reset.
+    pub(super) fn clear(&mut self) {
+        self.deferred.clear();
+        self.preimages.clear();
+    }
+
+    /// Defers the hash of the node with the given encoding, whose placeholder is at the given
+    /// index of the stack.
+    fn defer(&mut self, index: usize, encoding: &[u8], cached: bool) {
+        debug_assert!(self.deferred.last().map_or(true, |node| node.index < index));
+        let start = self.preimages.len();
+        self.preimages.extend_from_slice(encoding);
+        self.deferred.push(DeferredNode { index, preimage: start..self.preimages.len(), cached });
+    }
+
+    /// Hashes the deferred nodes whose placeholders are at or above the given index of the stack
+    /// in a single batch, and passes each of them to `resolve` along with its encoding and hash.
+    fn flush(&mut self, from: usize, mut resolve: impl FnMut(&DeferredNode, &[u8], B256)) {
+        // Placeholders are pushed in stack order, so the deferred nodes are sorted by index.
+        let split = self.deferred.partition_point(|node| node.index < from);
+        let Some(first) = self.deferred.get(split) else { return };
+        let start = first.preimage.start;
+
+        let preimages = self.deferred[split..]
+            .iter()
+            .map(|node| &self.preimages[node.preimage.clone()])
+            .collect::<Vec<_>>();
+        self.hashes.clear();
+        self.hashes.resize(preimages.len(), B256::ZERO);
+        (self.hash_batch)(&preimages, &mut self.hashes);
+        self.batches += 1;
+        self.nodes_hashed += preimages.len() as u64;
+
+        for ((node, preimage), hash) in
+            self.deferred[split..].iter().zip(preimages).zip(&self.hashes)
+        {
+            resolve(node, preimage, *hash);
+        }
+        self.deferred.truncate(split);
+        self.preimages.truncate(start);
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Sets the batch hasher with which nodes are hashed. See [`BatchHasher`].
+    pub fn with_batch_hasher(mut self, batch_hasher: BatchHasher) -> Self {
+        self.batch_hasher = Some(batch_hasher);
+        self
+    }
+
+    /// Takes the batch hasher set with [`HashBuilder::with_batch_hasher`], e.g. to reuse it in the
+    /// next root computation. The root must have been computed, so that no hashes are deferred.
+    pub fn take_batch_hasher(&mut self) -> Option<BatchHasher> {
+        self.batch_hasher.take()
+    }
+
+    /// Pushes the reference to the node RLP-encoded in `rlp_buf` onto the stack.
+    ///
+    /// With a batch hasher, the hash of a node that is not embedded is deferred, unless it's a
+    /// branch node found in the hash cache.
+    pub(super) fn push_node_from_buf(&mut self, is_branch: bool) {
+        let rlp = match self.batch_hasher.as_mut() {
+            Some(batch_hasher) if self.rlp_buf.len() >= H::INLINE_THRESHOLD => {
+                let cache = self.hash_cache.as_mut().filter(|_| is_branch);
+                let cached = cache.is_some();
+                match cache.and_then(|cache| cache.get(&self.rlp_buf)) {
+                    Some(hash) => RlpNode::word_rlp(&hash),
+                    None => {
+                        batch_hasher.defer(self.stack.len(), &self.rlp_buf, cached);
+                        RlpNode::default()
+                    }
+                }
+            }
+            _ if is_branch => self.branch_node_rlp_from_buf(),
+            _ => RlpNode::from_rlp_with_hasher::<H>(&self.rlp_buf),
+        };
+        self.stack.push(rlp);
+    }
+
+    /// Resolves the deferred hashes of the nodes at or above the given index of the stack.
+    pub(super) fn resolve_stack(&mut self, from: usize) {
+        let Some(batch_hasher) = self.batch_hasher.as_mut() else { return };
+        let (stack, hash_cache) = (&mut self.stack, &mut self.hash_cache);
+        batch_hasher.flush(from, |node, preimage, hash| {
+            stack[node.index] = RlpNode::word_rlp(&hash);
+            if let Some(cache) = hash_cache.as_mut().filter(|_| node.cached) {
+                cache.insert(preimage.to_vec(), hash);
+            }
+        });
+    }
+
+    /// Returns the stack with the deferred hashes resolved with the [`TrieHasher`].
+    pub(super) fn resolved_stack(&self) -> Vec<RlpNode> {
+        let mut stack = self.stack.clone();
+        if let Some(batch_hasher) = &self.batch_hasher {
+            for node in &batch_hasher.deferred {
+                let preimage = &batch_hasher.preimages[node.preimage.clone()];
+                stack[node.index] = RlpNode::word_rlp(&H::hash(preimage));
+            }
+        }
+        stack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hash_builder::NodeHashCache, proof::ProofRetainer, HashBuilder, InlineThreshold,
+        KeccakHasher, Nibbles,
+    };
+    use alloc::sync::Arc;
+    use alloy_primitives::{keccak256, U256};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns a batch hasher computing keccak256, along with the size of the largest batch.
+    fn keccak_batch_hasher() -> (BatchHasher, Arc<AtomicUsize>) {
+        let largest = Arc::new(AtomicUsize::new(0));
+        let largest_batch = largest.clone();
+        let batch_hasher = BatchHasher::new(move |preimages, hashes| {
+            largest_batch.fetch_max(preimages.len(), Ordering::Relaxed);
+            for (preimage, hash) in preimages.iter().zip(hashes) {
+                *hash = keccak256(preimage);
+            }
+        });
+        (batch_hasher, largest)
+    }
+
+    fn leaves(n: u64) -> Vec<(Nibbles, Vec<u8>)> {
+        let mut leaves = (0..n)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<Vec<_>>();
+        leaves.sort_unstable();
+        leaves
+    }
+
+    fn build<H: TrieHasher>(
+        mut hb: HashBuilder<H>,
+        leaves: &[(Nibbles, Vec<u8>)],
+    ) -> HashBuilder<H> {
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root();
+        hb
+    }
+
+    #[test]
+    fn batched_root() {
+        let leaves = leaves(1000);
+        let target = leaves[500].0.clone();
+        let unbatched = || {
+            HashBuilder::default()
+                .with_updates(true)
+                .with_proof_retainer(ProofRetainer::from_iter([target.clone()]))
+        };
+        let mut expected = build(unbatched(), &leaves);
+        let root = expected.root();
+
+        let (batch_hasher, largest) = keccak_batch_hasher();
+        let mut batched = build(unbatched().with_batch_hasher(batch_hasher), &leaves);
+        assert_eq!(batched.root(), expected.root());
+        assert_eq!(batched.take_proof_nodes(), expected.take_proof_nodes());
+        let batch_hasher = batched.take_batch_hasher().unwrap();
+        assert_eq!(batched.split().1, expected.split().1);
+
+        assert_eq!(batch_hasher.deferred(), 0);
+        // All leaves and branch nodes are hashed, mostly in batches of siblings.
+        assert!(batch_hasher.nodes_hashed() > 1000);
+        assert!(batch_hasher.batches() * 3 < batch_hasher.nodes_hashed());
+        assert_eq!(largest.load(Ordering::Relaxed), 16);
+
+        // Hashes of branch nodes found in the cache are not deferred.
+        let cache = NodeHashCache::new(4096);
+        let (batch_hasher, _) = keccak_batch_hasher();
+        let mut hb = build(
+            HashBuilder::default().with_hash_cache(cache).with_batch_hasher(batch_hasher),
+            &leaves,
+        );
+        assert_eq!(hb.root(), root);
+        let (cache, batch_hasher) =
+            (hb.take_hash_cache().unwrap(), hb.take_batch_hasher().unwrap());
+        let (misses, nodes_hashed) = (cache.misses(), batch_hasher.nodes_hashed());
+        assert!(misses > 0);
+
+        let mut hb = build(
+            HashBuilder::default().with_hash_cache(cache).with_batch_hasher(batch_hasher),
+            &leaves,
+        );
+        assert_eq!(hb.root(), root);
+        assert_eq!(hb.take_hash_cache().unwrap().misses(), misses);
+        // Only the leaves and the few extension nodes are hashed again.
+        let rehashed = hb.take_batch_hasher().unwrap().nodes_hashed() - nodes_hashed;
+        assert!((1000..1100).contains(&rehashed));
+    }
+
+    #[test]
+    fn batched_checkpoint() {
+        let leaves = leaves(300);
+        let expected = build(HashBuilder::default(), &leaves).root();
+
+        let (batch_hasher, _) = keccak_batch_hasher();
+        let mut hb = HashBuilder::default().with_batch_hasher(batch_hasher);
+        for (key, value) in &leaves[..150] {
+            hb.add_leaf(key.clone(), value);
+        }
+        assert!(hb.batch_hasher.as_ref().unwrap().deferred() > 0);
+        let mut restored = HashBuilder::<KeccakHasher>::restore(hb.checkpoint());
+        for (key, value) in &leaves[150..] {
+            hb.add_leaf(key.clone(), value);
+            restored.add_leaf(key.clone(), value);
+        }
+        assert_eq!(hb.root(), expected);
+        assert_eq!(restored.root(), expected);
+
+        // Every node is hashed if none are embedded.
+        let leaves = (0..16u8)
+            .flat_map(|i| (0..3u8).map(move |j| (Nibbles::from_nibbles([i, j, 0, 1]), vec![i, j])))
+            .collect::<Vec<_>>();
+        let expected = build(HashBuilder::<InlineThreshold<0>>::new(), &leaves).root();
+        let (batch_hasher, _) = keccak_batch_hasher();
+        let mut hb = build(
+            HashBuilder::<InlineThreshold<0>>::new().with_batch_hasher(batch_hasher),
+            &leaves,
+        );
+        assert_eq!(hb.root(), expected);
+        assert_eq!(hb.take_batch_hasher().unwrap().nodes_hashed(), 48 + 16 + 1);
+    }
+}
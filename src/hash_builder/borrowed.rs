@@ -0,0 +1,203 @@
+use super::{HashBuilder, HashBuilderError, HashBuilderValueRef, KeyOrderPolicy};
+use crate::{KeccakHasher, Nibbles, TrieHasher};
+
+/// Adds leaves to a [`HashBuilder`] without copying their values, see
+/// [`HashBuilder::borrowed_leaves`].
+///
+/// The value of a leaf is only needed until the key of the next leaf is known, at which point the
+/// leaf is hashed. The values are therefore borrowed for `'a` and read straight out of the
+/// caller's buffers, instead of being copied into the builder as done by
+/// [`HashBuilder::add_leaf`]. Only the value of the last leaf is copied into the builder when this
+/// is dropped, as it is needed by the next call to the builder.
+#[derive(Debug)]
+pub struct BorrowedLeaves<'b, 'a, H: TrieHasher = KeccakHasher> {
+    builder: &'b mut HashBuilder<H>,
+    /// The last added leaf, not yet hashed.
+    pending: Option<(Nibbles, &'a [u8])>,
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Returns a handle for adding leaves whose values are borrowed for `'a`, avoiding copying
+    /// each value into the builder.
+    ///
+    /// This is useful when the encoded values are already held in stable buffers, for example
+    /// when computing the root of encoded receipts.
+    pub fn borrowed_leaves<'a>(&mut self) -> BorrowedLeaves<'_, 'a, H> {
+        BorrowedLeaves { builder: self, pending: None }
+    }
+}
+
+impl<'a, H: TrieHasher> BorrowedLeaves<'_, 'a, H> {
+    /// Adds a new leaf element and its value to the trie hash builder. Equivalent to
+    /// [`HashBuilder::add_leaf`], including the handling of invalid keys according to the
+    /// [`KeyOrderPolicy`]. With the [`KeyOrderPolicy::LastWriteWins`] policy, the value is copied
+    /// and buffered.
+    ///
+    /// # Panics
+    ///
+    /// If the key or the value is invalid, with the [`KeyOrderPolicy::Panic`] policy. With the
+    /// [`KeyOrderPolicy::LastWriteWins`] policy, if the value is too long.
+    pub fn add_leaf_borrowed(&mut self, key: Nibbles, value: &'a [u8]) {
+        if let Err(error) = self.builder.check_value(&key, value) {
+            self.builder.reject(error);
+            return;
+        }
+        if self.builder.key_order == KeyOrderPolicy::LastWriteWins {
+            self.builder.add_unsorted_leaf(key, value);
+            return;
+        }
+        if let Err(error) = self.check_key(&key) {
+            self.builder.reject(error);
+            return;
+        }
+        self.add_checked_leaf(key, value);
+    }
+
+    /// Adds a new leaf element and its value to the trie hash builder, unless the computation was
+    /// cancelled or the leaf is invalid. Equivalent to [`HashBuilder::try_add_leaf`].
+    pub fn try_add_leaf_borrowed(
+        &mut self,
+        key: Nibbles,
+        value: &'a [u8],
+    ) -> Result<(), HashBuilderError> {
+        if self.builder.is_cancelled() {
+            return Err(HashBuilderError::Cancelled);
+        }
+        self.builder.check_value(&key, value)?;
+        if self.builder.key_order == KeyOrderPolicy::LastWriteWins {
+            if key.is_empty() {
+                return Err(HashBuilderError::EmptyKey);
+            }
+            self.builder.add_unsorted_leaf(key, value);
+            return Ok(());
+        }
+        self.check_key(&key)?;
+        self.add_checked_leaf(key, value);
+        Ok(())
+    }
+
+    /// Checks that the key can follow the pending leaf, or the last element of the builder.
+    fn check_key(&self, key: &Nibbles) -> Result<(), HashBuilderError> {
+        match &self.pending {
+            Some((current, _)) => self.builder.check_key_after(current, key, false),
+            None => self.builder.check_key(key, false),
+        }
+    }
+
+    fn add_checked_leaf(&mut self, key: Nibbles, value: &'a [u8]) {
+        match self.pending.take() {
+            Some((current, current_value)) => {
+                self.builder.update_with(current, HashBuilderValueRef::Bytes(current_value), &key);
+            }
+            None => {
+                if !self.builder.key.is_empty() {
+                    self.builder.update(&key);
+                }
+            }
+        }
+        self.builder.touch_leaf(&key);
+        self.builder.report_progress(&key);
+        self.pending = Some((key, value));
+        self.builder.record_leaf();
+    }
+}
+
+impl<H: TrieHasher> Drop for BorrowedLeaves<'_, '_, H> {
+    fn drop(&mut self) {
+        if let Some((key, value)) = self.pending.take() {
+            self.builder.set_key_value(key, HashBuilderValueRef::Bytes(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::ProofRetainer;
+    use alloy_primitives::keccak256;
+
+    #[allow(unused_imports)]
+    use alloc::vec::Vec;
+
+    #[test]
+    fn matches_owned_leaves() {
+        let leaves = (0..100u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), vec![i as u8; i as usize % 40]))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let leaves = leaves.into_iter().collect::<Vec<_>>();
+        let targets = leaves.iter().step_by(7).map(|(key, _)| key.clone()).collect::<Vec<_>>();
+
+        let mut expected = HashBuilder::default()
+            .with_updates(true)
+            .with_proof_retainer(ProofRetainer::from_iter(targets.clone()));
+        for (key, value) in &leaves {
+            expected.add_leaf(key.clone(), value);
+        }
+
+        // Leaves are added in batches, mixed with owned leaves.
+        let mut hb = HashBuilder::default()
+            .with_updates(true)
+            .with_proof_retainer(ProofRetainer::from_iter(targets));
+        let (first, rest) = leaves.split_first().unwrap();
+        hb.add_leaf(first.0.clone(), &first.1);
+        for chunk in rest.chunks(30) {
+            let mut borrowed = hb.borrowed_leaves();
+            for (key, value) in chunk {
+                borrowed.add_leaf_borrowed(key.clone(), value);
+            }
+        }
+
+        assert_eq!(hb.root(), expected.root());
+        assert_eq!(hb.take_proof_nodes(), expected.take_proof_nodes());
+        assert_eq!(hb.split().1, expected.split().1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsorted_keys() {
+        let mut hb = HashBuilder::default();
+        let mut borrowed = hb.borrowed_leaves();
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([2]), &[1]);
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([1]), &[1]);
+    }
+
+    #[test]
+    fn error_policy() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::Error);
+        let mut borrowed = hb.borrowed_leaves();
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([1]), &[1]);
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([3]), &[3]);
+        assert_eq!(
+            borrowed.try_add_leaf_borrowed(Nibbles::from_nibbles([2]), &[2]),
+            Err(HashBuilderError::NonMonotonicKey {
+                key: Nibbles::from_nibbles([2]),
+                previous: Nibbles::from_nibbles([3])
+            })
+        );
+        // The invalid leaf is skipped and the error is kept.
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([2]), &[2]);
+        drop(borrowed);
+        assert_eq!(
+            hb.try_root(),
+            Err(HashBuilderError::NonMonotonicKey {
+                key: Nibbles::from_nibbles([2]),
+                previous: Nibbles::from_nibbles([3])
+            })
+        );
+    }
+
+    #[test]
+    fn last_write_wins_policy() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::LastWriteWins);
+        let mut borrowed = hb.borrowed_leaves();
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([2]), &[2]);
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([1]), &[1]);
+        borrowed.add_leaf_borrowed(Nibbles::from_nibbles([2]), &[3]);
+        drop(borrowed);
+
+        let mut expected = HashBuilder::default();
+        expected.add_leaf(Nibbles::from_nibbles([1]), &[1]);
+        expected.add_leaf(Nibbles::from_nibbles([2]), &[3]);
+        assert_eq!(hb.root(), expected.root());
+    }
+}
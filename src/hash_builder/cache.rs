@@ -0,0 +1,215 @@
+use super::HashBuilder;
+use crate::{nodes::RlpNode, HashMap, TrieHasher};
+use alloc::vec::Vec;
+use alloy_primitives::B256;
+use core::mem;
+
+/// A bounded cache of branch node hashes, keyed by the RLP encoding of the branch node.
+///
+/// The encoding of a branch node consists of the references to its children, so identical
+/// subtrees produce identical encodings regardless of where they are in the trie. Roots of similar
+/// states, e.g. of the iterations of a block being built, share most of their branch nodes, and a
+/// [`HashBuilder`] set up with [`HashBuilder::with_hash_cache`] looks up their hashes instead of
+/// re-hashing them. The cache can be moved to the next builder with
+/// [`HashBuilder::take_hash_cache`].
+///
+/// At most `capacity` entries are kept. The least recently used entries are evicted in two
+/// generations: once the current generation holds half of the capacity, it replaces the previous
+/// one, which is dropped. Hits in the previous generation are moved back to the current one.
+///
+/// Nodes that are embedded in their parent are never hashed, and are not cached.
+#[derive(Clone, Debug, Default)]
+pub struct NodeHashCache {
+    capacity: usize,
+    current: HashMap<Vec<u8>, B256>,
+    previous: HashMap<Vec<u8>, B256>,
+    hits: u64,
+    misses: u64,
+}
+
+impl NodeHashCache {
+    /// Creates a cache holding the hashes of at most `capacity` branch nodes.
+    ///
+    /// As the capacity is split between two generations, a cache with a capacity below two stores
+    /// nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, ..Default::default() }
+    }
+
+    /// Returns the maximum number of cached hashes.
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of cached hashes.
+    pub fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+
+    /// Returns `true` if no hashes are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of lookups that found a cached hash.
+    pub const fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the number of lookups that had to compute the hash.
+    pub const fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Removes all cached hashes and resets the hit and miss counters.
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Returns the cached hash of the given encoding, or computes it with `hash` and caches it.
+    pub fn get_or_insert_with(
+        &mut self,
+        encoding: &[u8],
+        hash: impl FnOnce(&[u8]) -> B256,
+    ) -> B256 {
+        if let Some(hash) = self.get(encoding) {
+            return hash;
+        }
+        let hash = hash(encoding);
+        self.insert(encoding.to_vec(), hash);
+        hash
+    }
+
+    /// Returns the cached hash of the given encoding, counting a hit or a miss. On a miss, the
+    /// hash is expected to be computed and inserted with [`NodeHashCache::insert`].
+    pub(super) fn get(&mut self, encoding: &[u8]) -> Option<B256> {
+        if let Some(hash) = self.current.get(encoding) {
+            self.hits += 1;
+            return Some(*hash);
+        }
+        if let Some((encoding, hash)) = self.previous.remove_entry(encoding) {
+            self.hits += 1;
+            self.insert(encoding, hash);
+            return Some(hash);
+        }
+        self.misses += 1;
+        None
+    }
+
+    pub(super) fn insert(&mut self, encoding: Vec<u8>, hash: B256) {
+        let generation = self.capacity / 2;
+        if generation == 0 {
+            return;
+        }
+        if self.current.len() >= generation {
+            self.previous = mem::take(&mut self.current);
+        }
+        self.current.insert(encoding, hash);
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Sets the cache with which the hashes of branch nodes are looked up before hashing them.
+    /// See [`NodeHashCache`].
+    pub fn with_hash_cache(mut self, cache: NodeHashCache) -> Self {
+        self.hash_cache = Some(cache);
+        self
+    }
+
+    /// Takes the cache set with [`HashBuilder::with_hash_cache`], e.g. to reuse it in the next
+    /// root computation.
+    pub fn take_hash_cache(&mut self) -> Option<NodeHashCache> {
+        self.hash_cache.take()
+    }
+
+    /// Returns the reference to the branch node RLP-encoded in `rlp_buf`, looking up its hash in
+    /// the cache, if any.
+    #[inline]
+    pub(super) fn branch_node_rlp_from_buf(&mut self) -> RlpNode {
+        match self.hash_cache.as_mut() {
+            Some(cache) if self.rlp_buf.len() >= H::INLINE_THRESHOLD => {
+                RlpNode::word_rlp(&cache.get_or_insert_with(&self.rlp_buf, H::hash))
+            }
+            _ => RlpNode::from_rlp_with_hasher::<H>(&self.rlp_buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeccakHasher, Nibbles};
+
+    fn root_with_cache<H: TrieHasher>(
+        leaves: &[(Nibbles, Vec<u8>)],
+        cache: NodeHashCache,
+    ) -> (B256, NodeHashCache) {
+        let mut hb = HashBuilder::<H>::new().with_hash_cache(cache);
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_hash_cache().unwrap())
+    }
+
+    fn leaves(modified: Option<u8>) -> Vec<(Nibbles, Vec<u8>)> {
+        (0..=255u8)
+            .map(|i| {
+                let value = if Some(i) == modified { vec![0xff; 32] } else { vec![i; 32] };
+                (Nibbles::unpack(B256::repeat_byte(i)), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reuses_branch_hashes() {
+        let original = leaves(None);
+        let expected = {
+            let mut hb = HashBuilder::default();
+            original.iter().for_each(|(key, value)| hb.add_leaf(key.clone(), value));
+            hb.root()
+        };
+
+        let (root, cache) = root_with_cache::<KeccakHasher>(&original, NodeHashCache::new(64));
+        assert_eq!(root, expected);
+        assert_eq!(cache.hits(), 0);
+        let misses = cache.misses();
+        assert!(misses > 0);
+
+        // Only the branch nodes on the path to the modified leaf are hashed again.
+        let (root, cache) = root_with_cache::<KeccakHasher>(&leaves(Some(7)), cache);
+        assert_ne!(root, expected);
+        assert_eq!(cache.hits(), misses - 2);
+        assert_eq!(cache.misses(), misses + 2);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut cache = NodeHashCache::new(4);
+        for i in 0..10u8 {
+            cache.get_or_insert_with(&[i], |_| B256::with_last_byte(i));
+            assert!(cache.len() <= cache.capacity());
+        }
+        // The most recent entries are kept.
+        assert_eq!(cache.get_or_insert_with(&[9], |_| B256::ZERO), B256::with_last_byte(9));
+        assert_eq!(cache.get_or_insert_with(&[8], |_| B256::ZERO), B256::with_last_byte(8));
+        assert_eq!(cache.get_or_insert_with(&[0], |_| B256::ZERO), B256::ZERO);
+
+        let mut disabled = NodeHashCache::new(0);
+        disabled.get_or_insert_with(&[0], |_| B256::ZERO);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn embedded_nodes_are_not_cached() {
+        let leaves = (0..16u8).map(|i| (Nibbles::from_nibbles([i]), vec![i])).collect::<Vec<_>>();
+        let (root, cache) = root_with_cache::<KeccakHasher>(&leaves, NodeHashCache::new(16));
+        let mut hb = HashBuilder::default();
+        leaves.iter().for_each(|(key, value)| hb.add_leaf(key.clone(), value));
+        assert_eq!(root, hb.root());
+        // Only the root branch node is hashed, as the leaves are embedded in it.
+        assert_eq!(cache.misses(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+use super::{HashBuilder, HashBuilderError, HashBuilderValue, KeyOrderPolicy};
+use crate::{
+    nodes::RlpNode, proof::ProofRetainer, BranchNodeCompact, HashMap, Nibbles, TrieHasher, TrieMask,
+};
+use alloy_primitives::map::HashSet;
+use core::marker::PhantomData;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The intermediate state of a [`HashBuilder`], from which the computation can be resumed.
+///
+/// A checkpoint holds everything that determines the result of the builder: the last added key
+/// and value, the stack of nodes, the masks, the retained updates and proofs, the buffered
+/// unsorted leaves, the touched leaves, the [`KeyOrderPolicy`], the maximum value length and the
+/// kept error. With the `serde` feature, checkpoints can be persisted to survive process
+/// restarts, either as JSON or in a compact binary format such as bincode. Restoring a checkpoint
+/// and adding the remaining leaves produces the same root, updates and proofs as an uninterrupted
+/// computation.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(missing_docs)]
+pub struct HashBuilderCheckpoint {
+    pub key: Nibbles,
+    pub value: HashBuilderValue,
+    pub stack: Vec<RlpNode>,
+
+    pub groups: Vec<TrieMask>,
+    pub tree_masks: Vec<TrieMask>,
+    pub hash_masks: Vec<TrieMask>,
+
+    pub stored_in_database: bool,
+
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::option_map_as_seq"))]
+    pub updated_branch_nodes: Option<HashMap<Nibbles, BranchNodeCompact>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub removed_branch_nodes: Option<HashSet<Nibbles>>,
+    pub proof_retainer: Option<ProofRetainer>,
+
+    pub unsorted_leaves: Vec<(Nibbles, Vec<u8>)>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub touched_leaves: Option<Vec<Nibbles>>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_order: KeyOrderPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_value_len: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error: Option<HashBuilderError>,
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Returns a checkpoint of the current state of the builder.
+    ///
+    /// Hashes deferred by the [`BatchHasher`](super::BatchHasher) are computed with the
+    /// [`TrieHasher`], as the batch hasher is not part of the checkpoint.
+    pub fn checkpoint(&self) -> HashBuilderCheckpoint {
+        HashBuilderCheckpoint {
+            key: self.key.clone(),
+            value: self.value.clone(),
+            stack: self.resolved_stack(),
+            groups: self.groups.clone(),
+            tree_masks: self.tree_masks.clone(),
+            hash_masks: self.hash_masks.clone(),
+            stored_in_database: self.stored_in_database,
+            updated_branch_nodes: self.updated_branch_nodes.clone(),
+            removed_branch_nodes: self.removed_branch_nodes.clone(),
+            proof_retainer: self.proof_retainer.clone(),
+            unsorted_leaves: self.unsorted_leaves.clone(),
+            touched_leaves: self.touched_leaves.clone(),
+            key_order: self.key_order,
+            max_value_len: self.max_value_len,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl<H> HashBuilder<H> {
+    /// Restores a builder from the checkpoint. The builder must hash nodes with the same
+    /// [`TrieHasher`] as the builder the checkpoint was taken from.
+    pub fn restore(checkpoint: HashBuilderCheckpoint) -> Self {
+        let HashBuilderCheckpoint {
+            key,
+            value,
+            stack,
+            groups,
+            tree_masks,
+            hash_masks,
+            stored_in_database,
+            updated_branch_nodes,
+            removed_branch_nodes,
+            proof_retainer,
+            unsorted_leaves,
+            touched_leaves,
+            key_order,
+            max_value_len,
+            error,
+        } = checkpoint;
+        Self {
+            key,
+            value,
+            stack,
+            groups,
+            tree_masks,
+            hash_masks,
+            stored_in_database,
+            updated_branch_nodes,
+            removed_branch_nodes,
+            proof_retainer,
+            unsorted_leaves,
+            value_pool: Default::default(),
+            touched_leaves,
+            rlp_buf: Vec::new(),
+            progress: None,
+            cancellation: None,
+            hash_cache: None,
+            batch_hasher: None,
+            key_order,
+            max_value_len,
+            error,
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H> From<HashBuilderCheckpoint> for HashBuilder<H> {
+    fn from(checkpoint: HashBuilderCheckpoint) -> Self {
+        Self::restore(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{keccak256, U256};
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let leaves = (0..200u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(13).cloned().collect::<Vec<_>>();
+        let new_builder = || {
+            HashBuilder::default()
+                .with_updates(true)
+                .with_proof_retainer(ProofRetainer::new(targets.clone()))
+        };
+
+        let mut expected = new_builder();
+        for (key, value) in &leaves {
+            expected.add_leaf(key.clone(), value);
+        }
+        let expected_root = expected.root();
+
+        let mut hash_builder = new_builder();
+        let mut leaves = leaves.iter();
+        for (key, value) in leaves.by_ref().take(100) {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let checkpoint = hash_builder.checkpoint();
+        drop(hash_builder);
+
+        #[cfg(feature = "serde")]
+        let checkpoint = serde_json::from_str::<HashBuilderCheckpoint>(
+            &serde_json::to_string(&checkpoint).unwrap(),
+        )
+        .unwrap();
+
+        let mut hash_builder: HashBuilder = HashBuilder::restore(checkpoint);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        assert_eq!(hash_builder.root(), expected_root);
+        assert_eq!(hash_builder.take_proof_nodes(), expected.take_proof_nodes());
+        let (_, updates, removals) = hash_builder.split_with_removals();
+        let (_, expected_updates, expected_removals) = expected.split_with_removals();
+        assert_eq!(updates, expected_updates);
+        assert_eq!(removals, expected_removals);
+    }
+
+    #[test]
+    fn checkpoint_keeps_key_order() {
+        let mut hash_builder: HashBuilder =
+            HashBuilder::default().with_key_order(KeyOrderPolicy::Error);
+        hash_builder.add_leaf(Nibbles::from_nibbles([2]), &[2]);
+        hash_builder.add_leaf(Nibbles::from_nibbles([1]), &[1]);
+        let error = hash_builder.error.clone();
+        assert!(error.is_some());
+
+        let mut hash_builder: HashBuilder = HashBuilder::restore(hash_builder.checkpoint());
+        assert_eq!(hash_builder.key_order, KeyOrderPolicy::Error);
+        // Invalid leaves are still skipped, and the kept error is returned.
+        hash_builder.add_leaf(Nibbles::from_nibbles([0]), &[0]);
+        assert_eq!(hash_builder.try_root().err(), error);
+    }
+}
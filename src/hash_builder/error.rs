@@ -0,0 +1,72 @@
+use crate::Nibbles;
+use core::fmt;
+
+/// Error returned by the fallible methods of [`HashBuilder`](super::HashBuilder), such as
+/// [`HashBuilder::try_add_leaf`](super::HashBuilder::try_add_leaf) and
+/// [`HashBuilder::try_root`](super::HashBuilder::try_root).
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashBuilderError {
+    /// The computation was cancelled with a [`CancellationToken`](super::CancellationToken).
+    Cancelled,
+    /// The key of a leaf is empty.
+    EmptyKey,
+    /// The key does not strictly follow the key of the previous element.
+    NonMonotonicKey {
+        /// The key of the rejected element.
+        key: Nibbles,
+        /// The key of the previous element.
+        previous: Nibbles,
+    },
+    /// The key extends the key of the previous element, which would be a node inside the
+    /// previous leaf or below the previous branch.
+    PrefixKey {
+        /// The key of the rejected element.
+        key: Nibbles,
+        /// The key of the previous element.
+        previous: Nibbles,
+    },
+    /// A sorted leaf or a branch was added after leaves buffered with
+    /// [`HashBuilder::add_unsorted_leaf`](super::HashBuilder::add_unsorted_leaf), which are only
+    /// added when the root is computed.
+    AfterUnsortedLeaves {
+        /// The key of the rejected element.
+        key: Nibbles,
+    },
+    /// The value of a leaf is longer than the limit set with
+    /// [`HashBuilder::with_max_value_len`](super::HashBuilder::with_max_value_len).
+    ValueTooLong {
+        /// The key of the rejected leaf.
+        key: Nibbles,
+        /// The length of the value.
+        len: usize,
+        /// The maximum length of a value.
+        max: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for HashBuilderError {}
+
+impl fmt::Display for HashBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("root computation cancelled"),
+            Self::EmptyKey => f.write_str("leaf key is empty"),
+            Self::NonMonotonicKey { key, previous } => {
+                write!(f, "key {key:?} does not follow the previous key {previous:?}")
+            }
+            Self::PrefixKey { key, previous } => {
+                write!(f, "key {key:?} extends the previous key {previous:?}")
+            }
+            Self::AfterUnsortedLeaves { key } => {
+                write!(f, "key {key:?} added after buffered unsorted leaves")
+            }
+            Self::ValueTooLong { key, len, max } => {
+                write!(f, "value of key {key:?} is {len} bytes long, more than {max}")
+            }
+        }
+    }
+}
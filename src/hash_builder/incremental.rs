@@ -0,0 +1,309 @@
+use super::HashBuilder;
+use crate::{nodes::TrieNode, proof::ProofNodes, BranchNodeCompact, Nibbles};
+use alloc::{collections::BTreeMap, vec::Vec};
+use alloy_primitives::{keccak256, B256};
+use alloy_rlp::Decodable;
+use tracing::trace;
+
+/// Hash of a branch node located at a known path, cached between root computations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CachedBranch {
+    /// The hash of the branch node.
+    hash: B256,
+    /// Whether the branch node is stored in the database.
+    stored_in_database: bool,
+}
+
+/// A hash builder that recomputes the root of a previously built trie after a batch of changes
+/// without re-hashing the subtrees that were not affected by them.
+///
+/// The builder keeps the leaves of the trie along with the hashes of its branch nodes, keyed by
+/// their path. Whenever a key is inserted or removed, the hashes of all branch nodes on the path
+/// to that key are invalidated. On the next call to [`IncrementalHashBuilder::root`], every
+/// subtree that still has a valid cached hash is fed into the [`HashBuilder`] as a single
+/// pre-hashed branch (see [`HashBuilder::add_branch`]), so only the changed paths are re-hashed.
+///
+/// The cache can be seeded with the branch nodes stored by a previous [`HashBuilder`] run (see
+/// [`HashBuilder::with_updates`]) or with retained proof nodes. It is kept up to date on every
+/// root computation.
+#[derive(Clone, Debug, Default)]
+pub struct IncrementalHashBuilder {
+    /// The leaves of the trie.
+    leaves: BTreeMap<Nibbles, Vec<u8>>,
+    /// The cached hashes of the branch nodes keyed by their path.
+    branches: BTreeMap<Nibbles, CachedBranch>,
+    /// The root computed by the last call to [`IncrementalHashBuilder::root`], if no changes
+    /// were applied since.
+    root: Option<B256>,
+}
+
+impl FromIterator<(Nibbles, Vec<u8>)> for IncrementalHashBuilder {
+    fn from_iter<T: IntoIterator<Item = (Nibbles, Vec<u8>)>>(iter: T) -> Self {
+        Self { leaves: BTreeMap::from_iter(iter), ..Default::default() }
+    }
+}
+
+impl IncrementalHashBuilder {
+    /// Seeds the cache with branch nodes produced by a previous [`HashBuilder`] run over the same
+    /// leaves.
+    ///
+    /// The hashes of the children marked in the hash mask, as well as the root hash of the root
+    /// node, are cached.
+    pub fn with_branch_nodes<I>(mut self, branch_nodes: I) -> Self
+    where
+        I: IntoIterator<Item = (Nibbles, BranchNodeCompact)>,
+    {
+        for (path, node) in branch_nodes {
+            self.cache_branch_node(&path, &node);
+        }
+        self
+    }
+
+    /// Seeds the cache with the hashes of the branch nodes found among the proof nodes retained
+    /// by a previous [`HashBuilder`] run over the same leaves.
+    ///
+    /// Nodes other than hashed branch nodes are ignored, since leaf and extension nodes can be
+    /// merged with their parents when the trie is modified.
+    pub fn with_proof_nodes(mut self, proof_nodes: &ProofNodes) -> Self {
+        for (path, node) in proof_nodes.iter() {
+            if node.len() < B256::len_bytes() {
+                continue;
+            }
+            if let Ok(TrieNode::Branch(_)) = TrieNode::decode(&mut &node[..]) {
+                let branch = CachedBranch { hash: keccak256(node), stored_in_database: false };
+                self.branches.insert(path.clone(), branch);
+            }
+        }
+        self
+    }
+
+    /// Returns the number of leaves in the trie.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Returns `true` if the trie has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Returns the value of the leaf at the given key.
+    pub fn get(&self, key: &Nibbles) -> Option<&[u8]> {
+        self.leaves.get(key).map(Vec::as_slice)
+    }
+
+    /// Inserts or updates a leaf, invalidating the cached hashes on its path.
+    pub fn insert(&mut self, key: Nibbles, value: Vec<u8>) {
+        self.invalidate(&key);
+        self.leaves.insert(key, value);
+    }
+
+    /// Removes a leaf, invalidating the cached hashes on its path.
+    ///
+    /// Returns the value of the removed leaf, if it was present.
+    pub fn remove(&mut self, key: &Nibbles) -> Option<Vec<u8>> {
+        let value = self.leaves.remove(key)?;
+        self.invalidate(key);
+        Some(value)
+    }
+
+    /// Removes all leaves along with the cached hashes, e.g. to wipe the storage trie of an
+    /// account that was destroyed before its new slots are applied.
+    pub fn clear(&mut self) {
+        self.leaves.clear();
+        self.branches.clear();
+        self.root = None;
+    }
+
+    /// Applies a batch of changes. A [`None`] value removes the leaf at the given key.
+    pub fn apply<I>(&mut self, changes: I)
+    where
+        I: IntoIterator<Item = (Nibbles, Option<Vec<u8>>)>,
+    {
+        for (key, value) in changes {
+            match value {
+                Some(value) => self.insert(key, value),
+                None => {
+                    self.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Computes the root of the trie, re-hashing only the subtrees that were changed since the
+    /// cache was last updated.
+    pub fn root(&mut self) -> B256 {
+        if let Some(root) = self.root {
+            return root;
+        }
+        if let Some(root) = self.branches.get(&Nibbles::default()) {
+            self.root = Some(root.hash);
+            return root.hash;
+        }
+
+        let mut hash_builder = HashBuilder::default().with_updates(true);
+        let mut leaves = self.leaves.iter().peekable();
+        while let Some((key, value)) = leaves.next() {
+            let Some((path, branch)) = self.cached_branch_for(key) else {
+                hash_builder.add_leaf(key.clone(), value);
+                continue;
+            };
+
+            trace!(target: "trie::incremental", ?path, ?branch, "reusing cached branch node");
+            hash_builder.add_branch(path.clone(), branch.hash, branch.stored_in_database);
+            while leaves.next_if(|(next, _)| next.starts_with(path)).is_some() {}
+        }
+
+        let root = hash_builder.root();
+        let (_, updates) = hash_builder.split();
+        for (path, node) in updates {
+            self.cache_branch_node(&path, &node);
+        }
+        self.root = Some(root);
+        root
+    }
+
+    /// Returns the topmost cached branch node on the path to the given key.
+    fn cached_branch_for(&self, key: &Nibbles) -> Option<(&Nibbles, &CachedBranch)> {
+        (1..key.len()).find_map(|len| self.branches.get_key_value(&key[..len]))
+    }
+
+    /// Removes the cached hashes of all branch nodes on the path to the given key.
+    fn invalidate(&mut self, key: &Nibbles) {
+        self.root = None;
+        for len in 0..=key.len() {
+            self.branches.remove(&key[..len]);
+        }
+    }
+
+    /// Caches the hashes of the hashed children of the branch node at the given path.
+    fn cache_branch_node(&mut self, path: &Nibbles, node: &BranchNodeCompact) {
+        let mut hashes = node.hashes.iter();
+        for nibble in 0..16 {
+            if !node.hash_mask.is_bit_set(nibble) {
+                continue;
+            }
+
+            let Some(&hash) = hashes.next() else { break };
+            let mut child = path.clone();
+            child.push(nibble);
+            let stored_in_database = node.tree_mask.is_bit_set(nibble);
+            self.branches.insert(child, CachedBranch { hash, stored_in_database });
+        }
+
+        if let Some(hash) = node.root_hash {
+            self.branches.insert(path.clone(), CachedBranch { hash, stored_in_database: true });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, EMPTY_ROOT_HASH};
+    use alloy_primitives::U256;
+
+    fn leaves(range: core::ops::Range<u64>) -> BTreeMap<Nibbles, Vec<u8>> {
+        range
+            .map(|i| {
+                let key = keccak256(i.to_be_bytes());
+                (Nibbles::unpack(key), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect()
+    }
+
+    fn full_root(leaves: &BTreeMap<Nibbles, Vec<u8>>) -> B256 {
+        let mut hb = HashBuilder::default();
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(IncrementalHashBuilder::default().root(), EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn matches_full_rebuild() {
+        let mut expected = leaves(0..256);
+        let mut incremental = IncrementalHashBuilder::from_iter(expected.clone());
+        assert_eq!(incremental.root(), full_root(&expected));
+
+        // Update existing leaves, insert new ones and remove some.
+        let mut changes = Vec::new();
+        for (i, (key, _)) in expected.iter().enumerate().step_by(17) {
+            let value = if i % 2 == 0 { None } else { Some(alloy_rlp::encode(U256::from(i))) };
+            changes.push((key.clone(), value));
+        }
+        changes.extend(leaves(1000..1010).into_iter().map(|(key, value)| (key, Some(value))));
+        for (key, value) in &changes {
+            match value {
+                Some(value) => expected.insert(key.clone(), value.clone()),
+                None => expected.remove(key),
+            };
+        }
+
+        incremental.apply(changes);
+        assert_eq!(incremental.len(), expected.len());
+        assert_eq!(incremental.root(), full_root(&expected));
+
+        // Remove everything.
+        incremental.apply(expected.keys().cloned().map(|key| (key, None)));
+        assert!(incremental.is_empty());
+        assert_eq!(incremental.root(), EMPTY_ROOT_HASH);
+
+        // Wipe the trie, then write new leaves.
+        let mut incremental = IncrementalHashBuilder::from_iter(leaves(0..256));
+        incremental.root();
+        incremental.clear();
+        incremental.apply(leaves(300..310).into_iter().map(|(key, value)| (key, Some(value))));
+        assert_eq!(incremental.root(), full_root(&leaves(300..310)));
+    }
+
+    #[test]
+    fn seeded_from_branch_nodes() {
+        let mut expected = leaves(0..512);
+        let mut hb = HashBuilder::default().with_updates(true);
+        for (key, value) in &expected {
+            hb.add_leaf(key.clone(), value);
+        }
+        let root = hb.root();
+        let (_, updates) = hb.split();
+
+        let mut incremental =
+            IncrementalHashBuilder::from_iter(expected.clone()).with_branch_nodes(updates);
+        assert_eq!(incremental.root(), root);
+
+        let (key, _) = expected.pop_first().unwrap();
+        incremental.remove(&key);
+        let value = alloy_rlp::encode(U256::MAX);
+        let key = Nibbles::unpack(B256::repeat_byte(0x11));
+        expected.insert(key.clone(), value.clone());
+        incremental.insert(key, value);
+        assert_eq!(incremental.root(), full_root(&expected));
+    }
+
+    #[test]
+    fn seeded_from_proof_nodes() {
+        let mut expected = leaves(0..128);
+        let retainer = ProofRetainer::from_iter(expected.keys().cloned());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &expected {
+            hb.add_leaf(key.clone(), value);
+        }
+        let root = hb.root();
+        let proof_nodes = hb.take_proof_nodes();
+
+        let mut incremental =
+            IncrementalHashBuilder::from_iter(expected.clone()).with_proof_nodes(&proof_nodes);
+        assert_eq!(incremental.root(), root);
+
+        let key = expected.keys().nth(42).unwrap().clone();
+        let value = alloy_rlp::encode(U256::from(42_000));
+        expected.insert(key.clone(), value.clone());
+        incremental.insert(key, value);
+        assert_eq!(incremental.root(), full_root(&expected));
+    }
+}
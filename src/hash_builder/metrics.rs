@@ -0,0 +1,99 @@
+use super::HashBuilder;
+use crate::TrieHasher;
+
+/// Counters of the work done by a [`HashBuilder`], kept in [`HashBuilder::metrics`] when the
+/// `metrics` feature is enabled.
+///
+/// The counters are cumulative over the lifetime of the builder and are not part of its
+/// [checkpoints](HashBuilder::checkpoint). They can be reported to any metrics system after the
+/// root is computed, for example to track the cost of the state root computation of each block.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HashBuilderMetrics {
+    /// The number of leaves added.
+    pub leaves_added: u64,
+    /// The number of branch nodes that were hashed, as opposed to embedded in their parent.
+    pub branch_nodes_hashed: u64,
+    /// The number of bytes of encoded nodes that were hashed, excluding the root node.
+    pub bytes_hashed: u64,
+    /// The number of proof nodes retained.
+    pub proof_nodes_retained: u64,
+    /// The largest number of nodes on the stack.
+    pub stack_high_water_mark: usize,
+}
+
+#[cfg(feature = "metrics")]
+impl<H: TrieHasher> HashBuilder<H> {
+    #[inline]
+    pub(super) fn record_leaf(&mut self) {
+        self.metrics.leaves_added += 1;
+    }
+
+    /// Records the node that was just encoded into `rlp_buf` and pushed onto the stack.
+    #[inline]
+    pub(super) fn record_node(&mut self, is_branch: bool) {
+        if self.rlp_buf.len() >= H::INLINE_THRESHOLD {
+            self.metrics.bytes_hashed += self.rlp_buf.len() as u64;
+            self.metrics.branch_nodes_hashed += is_branch as u64;
+        }
+        self.record_stack();
+    }
+
+    #[inline]
+    pub(super) fn record_stack(&mut self) {
+        self.metrics.stack_high_water_mark =
+            self.metrics.stack_high_water_mark.max(self.stack.len());
+    }
+
+    #[inline]
+    pub(super) fn record_retained_proof_node(&mut self) {
+        self.metrics.proof_nodes_retained += 1;
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl<H: TrieHasher> HashBuilder<H> {
+    #[inline(always)]
+    pub(super) fn record_leaf(&mut self) {}
+
+    #[inline(always)]
+    pub(super) fn record_node(&mut self, _is_branch: bool) {}
+
+    #[inline(always)]
+    pub(super) fn record_stack(&mut self) {}
+
+    #[inline(always)]
+    pub(super) fn record_retained_proof_node(&mut self) {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, Nibbles};
+    use alloy_primitives::keccak256;
+
+    #[test]
+    fn counters() {
+        let mut hb = HashBuilder::default();
+        assert_eq!(hb.root(), crate::EMPTY_ROOT_HASH);
+        assert_eq!(hb.metrics, HashBuilderMetrics::default());
+
+        let leaves = (0..100u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), [i as u8; 40]))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let target = leaves.keys().next().unwrap().clone();
+        let mut hb = HashBuilder::default().with_proof_retainer(ProofRetainer::from_iter([target]));
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root();
+
+        let metrics = hb.metrics;
+        assert_eq!(metrics.leaves_added, 100);
+        // The root and the first level of branch nodes at least.
+        assert!(metrics.branch_nodes_hashed > 16);
+        assert!(metrics.bytes_hashed > 100 * 40);
+        assert_eq!(metrics.proof_nodes_retained, hb.take_proof_nodes().len() as u64);
+        assert!(metrics.stack_high_water_mark > 1);
+    }
+}
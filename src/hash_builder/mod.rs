@@ -3,18 +3,58 @@
 use super::{
     nodes::{BranchNodeRef, ExtensionNodeRef, LeafNodeRef},
     proof::ProofRetainer,
-    BranchNodeCompact, Nibbles, TrieMask, EMPTY_ROOT_HASH,
+    BranchNodeCompact, Nibbles, TrieMask,
 };
-use crate::{nodes::RlpNode, proof::ProofNodes, HashMap};
+use crate::{nodes::RlpNode, proof::ProofNodes, HashMap, KeccakHasher, TrieHasher};
 use alloc::vec::Vec;
-use alloy_primitives::{keccak256, B256};
-use alloy_rlp::EMPTY_STRING_CODE;
-use core::cmp;
+use alloy_primitives::{map::HashSet, B256};
+use alloy_rlp::{Encodable, EMPTY_STRING_CODE};
+use core::{cmp, marker::PhantomData};
 use tracing::trace;
 
 mod value;
 pub use value::{HashBuilderValue, HashBuilderValueRef};
 
+mod incremental;
+pub use incremental::IncrementalHashBuilder;
+
+mod checkpoint;
+pub use checkpoint::HashBuilderCheckpoint;
+
+mod borrowed;
+pub use borrowed::BorrowedLeaves;
+
+mod output;
+pub use output::HashBuilderOutput;
+
+mod progress;
+pub use progress::{CancellationToken, HashBuilderProgress, ProgressTracker};
+
+mod cache;
+pub use cache::NodeHashCache;
+
+mod pool;
+pub use pool::ValuePool;
+
+mod batch;
+pub use batch::BatchHasher;
+
+mod error;
+pub use error::HashBuilderError;
+
+mod order;
+pub use order::KeyOrderPolicy;
+
+mod stack;
+pub use stack::{HashBuilderStack, PendingBranch};
+
+mod subtrie;
+pub use subtrie::SubtrieOutput;
+
+mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::HashBuilderMetrics;
+
 /// A component used to construct the root hash of the trie.
 ///
 /// The primary purpose of a Hash Builder is to build the Merkle proof that is essential for
@@ -39,9 +79,15 @@ pub use value::{HashBuilderValue, HashBuilderValueRef};
 /// up, combining the hashes of child nodes and ultimately generating the root hash. The root hash
 /// can then be used to verify the integrity and authenticity of the trie's data by constructing and
 /// verifying Merkle proofs.
-#[derive(Debug, Default)]
+///
+/// Nodes are hashed with keccak256 by default. A different hash function can be used by
+/// specifying the [`TrieHasher`] type parameter and creating the builder with
+/// [`HashBuilder::new`]. The hasher also determines which nodes are embedded in their parents, see
+/// [`InlineThreshold`](crate::InlineThreshold). Nodes can also be hashed in batches by an external
+/// function, see [`BatchHasher`].
+#[derive(Debug)]
 #[allow(missing_docs)]
-pub struct HashBuilder {
+pub struct HashBuilder<H = KeccakHasher> {
     pub key: Nibbles,
     pub value: HashBuilderValue,
     pub stack: Vec<RlpNode>,
@@ -53,12 +99,68 @@ pub struct HashBuilder {
     pub stored_in_database: bool,
 
     pub updated_branch_nodes: Option<HashMap<Nibbles, BranchNodeCompact>>,
+    pub removed_branch_nodes: Option<HashSet<Nibbles>>,
     pub proof_retainer: Option<ProofRetainer>,
 
+    pub unsorted_leaves: Vec<(Nibbles, Vec<u8>)>,
+    pub value_pool: ValuePool,
+
+    pub touched_leaves: Option<Vec<Nibbles>>,
+
     pub rlp_buf: Vec<u8>,
+
+    pub progress: Option<ProgressTracker>,
+    pub cancellation: Option<CancellationToken>,
+
+    pub hash_cache: Option<NodeHashCache>,
+    pub batch_hasher: Option<BatchHasher>,
+    pub key_order: KeyOrderPolicy,
+    pub max_value_len: Option<usize>,
+    pub error: Option<HashBuilderError>,
+
+    #[cfg(feature = "metrics")]
+    pub metrics: HashBuilderMetrics,
+
+    _hasher: PhantomData<H>,
 }
 
-impl HashBuilder {
+impl Default for HashBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Creates a new hash builder that hashes nodes with the given [`TrieHasher`].
+    pub fn new() -> Self {
+        Self {
+            key: Nibbles::default(),
+            value: HashBuilderValue::default(),
+            stack: Vec::new(),
+            groups: Vec::new(),
+            tree_masks: Vec::new(),
+            hash_masks: Vec::new(),
+            stored_in_database: false,
+            updated_branch_nodes: None,
+            removed_branch_nodes: None,
+            proof_retainer: None,
+            unsorted_leaves: Vec::new(),
+            value_pool: ValuePool::new(),
+            touched_leaves: None,
+            rlp_buf: Vec::new(),
+            progress: None,
+            cancellation: None,
+            hash_cache: None,
+            batch_hasher: None,
+            key_order: KeyOrderPolicy::default(),
+            max_value_len: None,
+            error: None,
+            #[cfg(feature = "metrics")]
+            metrics: HashBuilderMetrics::default(),
+            _hasher: PhantomData,
+        }
+    }
+
     /// Enables the Hash Builder to store updated branch nodes.
     ///
     /// Call [HashBuilder::split] to get the updates to branch nodes.
@@ -79,7 +181,18 @@ impl HashBuilder {
     pub fn set_updates(&mut self, retain_updates: bool) {
         if retain_updates {
             self.updated_branch_nodes = Some(HashMap::default());
+            self.removed_branch_nodes = Some(HashSet::default());
+        }
+    }
+
+    /// Enables the Hash Builder to record the keys of all added leaves.
+    ///
+    /// Call [HashBuilder::root_with_proofs] to get the recorded keys.
+    pub fn with_touched_leaves(mut self, record: bool) -> Self {
+        if record {
+            self.touched_leaves = Some(Vec::new());
         }
+        self
     }
 
     /// Splits the [HashBuilder] into a [HashBuilder] and hash builder updates.
@@ -88,6 +201,32 @@ impl HashBuilder {
         (self, updates.unwrap_or_default())
     }
 
+    /// Splits the [HashBuilder] into a [HashBuilder], hash builder updates and the paths of branch
+    /// nodes that must be removed from the database.
+    ///
+    /// The builder does not know which branch nodes were stored before, so the paths of the stored
+    /// nodes that were invalidated must be added with [HashBuilder::add_removed_branch_nodes],
+    /// e.g. from [`TrieWalker::split`](crate::walker::TrieWalker::split). Of those, the paths
+    /// where the builder stored an updated branch node are not reported as removed, so only the
+    /// nodes that are no longer stored, because the trie has no branch node at their path anymore
+    /// or none of its children are stored or hashed, are removed.
+    pub fn split_with_removals(
+        mut self,
+    ) -> (Self, HashMap<Nibbles, BranchNodeCompact>, HashSet<Nibbles>) {
+        let updates = self.updated_branch_nodes.take().unwrap_or_default();
+        let mut removals = self.removed_branch_nodes.take().unwrap_or_default();
+        removals.retain(|path| !updates.contains_key(path));
+        (self, updates, removals)
+    }
+
+    /// Marks the branch nodes at the given paths as removed, if updates are enabled. See
+    /// [HashBuilder::split_with_removals].
+    pub fn add_removed_branch_nodes(&mut self, paths: impl IntoIterator<Item = Nibbles>) {
+        if let Some(removed_branch_nodes) = self.removed_branch_nodes.as_mut() {
+            removed_branch_nodes.extend(paths);
+        }
+    }
+
     /// Take and return retained proof nodes.
     pub fn take_proof_nodes(&mut self) -> ProofNodes {
         self.proof_retainer.take().map(ProofRetainer::into_proof_nodes).unwrap_or_default()
@@ -110,33 +249,192 @@ impl HashBuilder {
     }
 
     /// Adds a new leaf element and its value to the trie hash builder.
+    ///
+    /// Keys must be added in strictly increasing order, unless the leaves are buffered with the
+    /// [`KeyOrderPolicy::LastWriteWins`] policy. A key must not be empty or extend the previous
+    /// key. With the [`KeyOrderPolicy::Error`] policy, an invalid leaf is skipped and the error is
+    /// returned by [`HashBuilder::try_root`].
+    ///
+    /// The [`CancellationToken`] set with [`HashBuilder::with_cancellation`] is ignored, see
+    /// [`HashBuilder::try_add_leaf`].
+    ///
+    /// # Panics
+    ///
+    /// If the key or the value is invalid, with the [`KeyOrderPolicy::Panic`] policy. With the
+    /// [`KeyOrderPolicy::LastWriteWins`] policy, if the value is too long.
     pub fn add_leaf(&mut self, key: Nibbles, value: &[u8]) {
-        assert!(key > self.key, "add_leaf key {:?} self.key {:?}", key, self.key);
+        if let Err(error) = self.check_value(&key, value) {
+            self.reject(error);
+            return;
+        }
+        if self.key_order == KeyOrderPolicy::LastWriteWins {
+            self.add_unsorted_leaf(key, value);
+            return;
+        }
+        if let Err(error) = self.check_key(&key, false) {
+            self.reject(error);
+            return;
+        }
+        self.add_sorted_leaf(key, value);
+    }
+
+    fn add_sorted_leaf(&mut self, key: Nibbles, value: &[u8]) {
         if !self.key.is_empty() {
             self.update(&key);
         }
+        self.touch_leaf(&key);
+        self.report_progress(&key);
         self.set_key_value(key, HashBuilderValueRef::Bytes(value));
+        self.record_leaf();
     }
 
     /// Adds a new branch element and its hash to the trie hash builder.
+    ///
+    /// The key must follow the key of the previous element as in [`HashBuilder::add_leaf`]. Only
+    /// the first element may be a branch with an empty key, which is the root of the trie.
+    ///
+    /// # Panics
+    ///
+    /// If the key is invalid, with the [`KeyOrderPolicy::Panic`] or
+    /// [`KeyOrderPolicy::LastWriteWins`] policy.
     pub fn add_branch(&mut self, key: Nibbles, value: B256, stored_in_database: bool) {
-        assert!(
-            key > self.key || (self.key.is_empty() && key.is_empty()),
-            "add_branch key {:?} self.key {:?}",
-            key,
-            self.key
-        );
+        if let Err(error) = self.check_key(&key, true) {
+            self.reject(error);
+            return;
+        }
+        self.add_checked_branch(key, value, stored_in_database);
+    }
+
+    fn add_checked_branch(&mut self, key: Nibbles, value: B256, stored_in_database: bool) {
         if !self.key.is_empty() {
             self.update(&key);
         } else if key.is_empty() {
             self.stack.push(RlpNode::word_rlp(&value));
+            self.record_stack();
         }
         self.set_key_value(key, HashBuilderValueRef::Hash(&value));
         self.stored_in_database = stored_in_database;
     }
 
+    /// Checks that the key of a new leaf or branch can follow the key of the previous element.
+    fn check_key(&self, key: &Nibbles, is_branch: bool) -> Result<(), HashBuilderError> {
+        self.check_key_after(&self.key, key, is_branch)
+    }
+
+    /// Checks that the key of a new leaf or branch can follow the given key of the previous
+    /// element, which may not have been passed to the builder yet.
+    fn check_key_after(
+        &self,
+        previous: &Nibbles,
+        key: &Nibbles,
+        is_branch: bool,
+    ) -> Result<(), HashBuilderError> {
+        if key.is_empty() && !is_branch {
+            return Err(HashBuilderError::EmptyKey);
+        }
+        if !self.unsorted_leaves.is_empty() {
+            return Err(HashBuilderError::AfterUnsortedLeaves { key: key.clone() });
+        }
+        if previous.is_empty() && (self.stack.is_empty() || !key.is_empty()) {
+            return Ok(());
+        }
+        if key <= previous {
+            return Err(HashBuilderError::NonMonotonicKey {
+                key: key.clone(),
+                previous: previous.clone(),
+            });
+        }
+        if key.starts_with(previous) {
+            return Err(HashBuilderError::PrefixKey {
+                key: key.clone(),
+                previous: previous.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the value of a new leaf is not longer than the limit set with
+    /// [`HashBuilder::with_max_value_len`].
+    fn check_value(&self, key: &Nibbles, value: &[u8]) -> Result<(), HashBuilderError> {
+        match self.max_value_len {
+            Some(max) if value.len() > max => {
+                Err(HashBuilderError::ValueTooLong { key: key.clone(), len: value.len(), max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Handles an invalid element according to the [`KeyOrderPolicy`]: the first error is kept
+    /// with the [`KeyOrderPolicy::Error`] policy, and the builder panics otherwise.
+    fn reject(&mut self, error: HashBuilderError) {
+        if self.key_order != KeyOrderPolicy::Error {
+            panic!("{error}");
+        }
+        self.error.get_or_insert(error);
+    }
+
+    /// Buffers a leaf that can be added in any order.
+    ///
+    /// Buffered leaves are sorted by key and added to the trie when the root is computed by
+    /// [`HashBuilder::root`] or [`HashBuilder::finalize`]. If the same key is buffered multiple
+    /// times, the last value wins. Leaves added with [`HashBuilder::add_leaf`] and branches must
+    /// precede all buffered leaves, and are rejected otherwise.
+    pub fn add_unsorted_leaf(&mut self, key: Nibbles, value: &[u8]) {
+        let value = self.value_pool.copy(value);
+        self.unsorted_leaves.push((key, value));
+    }
+
+    /// Adds the leaves buffered with [`HashBuilder::add_unsorted_leaf`] in sorted order and
+    /// returns the root hash of the trie.
+    ///
+    /// # Panics
+    ///
+    /// If a buffered leaf is invalid. See [`HashBuilder::add_leaf`].
+    pub fn finalize(&mut self) -> B256 {
+        if let Err(error) = self.add_buffered_leaves() {
+            panic!("{error}");
+        }
+        self.root()
+    }
+
+    /// Adds the leaves buffered with [`HashBuilder::add_unsorted_leaf`] in sorted order, stopping
+    /// at the first invalid leaf.
+    fn add_buffered_leaves(&mut self) -> Result<(), HashBuilderError> {
+        let mut leaves = core::mem::take(&mut self.unsorted_leaves);
+        // The sort is stable, so the last value of duplicate keys is the last one buffered.
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+        leaves.dedup_by(|next, previous| {
+            let duplicate = next.0 == previous.0;
+            if duplicate {
+                core::mem::swap(&mut next.1, &mut previous.1);
+            }
+            duplicate
+        });
+        for (key, value) in leaves {
+            let result = self.check_key(&key, false);
+            if result.is_ok() {
+                self.add_sorted_leaf(key, &value);
+            }
+            self.value_pool.recycle(value);
+            result?;
+        }
+        Ok(())
+    }
+
     /// Returns the current root hash of the trie builder.
+    ///
+    /// The leaves buffered with [`HashBuilder::add_unsorted_leaf`] are added first.
+    ///
+    /// # Panics
+    ///
+    /// If an invalid element was added. See [`HashBuilder::try_root`].
     pub fn root(&mut self) -> B256 {
+        if let Some(error) = &self.error {
+            panic!("{error}");
+        }
+        if let Err(error) = self.add_buffered_leaves() {
+            panic!("{error}");
+        }
         // Clears the internal state
         if !self.key.is_empty() {
             self.update(&Nibbles::default());
@@ -144,14 +442,23 @@ impl HashBuilder {
             self.value.clear();
         }
         let root = self.current_root();
-        if root == EMPTY_ROOT_HASH {
+        if root == H::empty_root() {
             if let Some(proof_retainer) = self.proof_retainer.as_mut() {
-                proof_retainer.retain(&Nibbles::default(), &[EMPTY_STRING_CODE])
+                proof_retainer.retain(&Nibbles::default(), &[EMPTY_STRING_CODE]);
+                self.record_retained_proof_node();
             }
         }
         root
     }
 
+    /// Records the key of an added leaf, if enabled with [HashBuilder::with_touched_leaves].
+    #[inline]
+    fn touch_leaf(&mut self, key: &Nibbles) {
+        if let Some(touched_leaves) = self.touched_leaves.as_mut() {
+            touched_leaves.push(key.clone());
+        }
+    }
+
     #[inline]
     fn set_key_value(&mut self, key: Nibbles, value: HashBuilderValueRef<'_>) {
         self.log_key_value("old value");
@@ -168,15 +475,16 @@ impl HashBuilder {
         );
     }
 
-    fn current_root(&self) -> B256 {
+    fn current_root(&mut self) -> B256 {
+        self.resolve_stack(self.stack.len().saturating_sub(1));
         if let Some(node_ref) = self.stack.last() {
             if let Some(hash) = node_ref.as_hash() {
                 hash
             } else {
-                keccak256(node_ref)
+                H::hash(node_ref)
             }
         } else {
-            EMPTY_ROOT_HASH
+            H::empty_root()
         }
     }
 
@@ -185,9 +493,21 @@ impl HashBuilder {
     /// that the top of the stack always contains the merkle root corresponding to the trie
     /// built so far.
     fn update(&mut self, succeeding: &Nibbles) {
-        let mut build_extensions = false;
         // current / self.key is always the latest added element in the trie
-        let mut current = self.key.clone();
+        let value = self.value.take();
+        self.update_with(self.key.clone(), value.as_ref(), succeeding);
+        self.value = value;
+    }
+
+    /// Like [`HashBuilder::update`], but with the latest added element given explicitly instead
+    /// of being read from `self.key` and `self.value`.
+    fn update_with(
+        &mut self,
+        mut current: Nibbles,
+        value: HashBuilderValueRef<'_>,
+        succeeding: &Nibbles,
+    ) {
+        let mut build_extensions = false;
         debug_assert!(!current.is_empty());
 
         trace!(target: "trie::hash_builder", ?current, ?succeeding, "updating merkle tree");
@@ -243,23 +563,22 @@ impl HashBuilder {
 
             // Concatenate the 2 nodes together
             if !build_extensions {
-                match self.value.as_ref() {
+                match value {
                     HashBuilderValueRef::Bytes(leaf_value) => {
+                        let value_hash = H::hash_leaf_value(leaf_value);
+                        let leaf_value = value_hash.as_ref().map_or(leaf_value, |hash| &hash[..]);
                         let leaf_node = LeafNodeRef::new(&short_node_key, leaf_value);
                         self.rlp_buf.clear();
-                        let rlp = leaf_node.rlp(&mut self.rlp_buf);
-                        trace!(
-                            target: "trie::hash_builder",
-                            ?leaf_node,
-                            ?rlp,
-                            "pushing leaf node",
-                        );
-                        self.stack.push(rlp);
+                        leaf_node.encode(&mut self.rlp_buf);
+                        trace!(target: "trie::hash_builder", ?leaf_node, "pushing leaf node");
+                        self.push_node_from_buf(false);
+                        self.record_node(false);
                         self.retain_proof_from_buf(&current.slice(..len_from));
                     }
                     HashBuilderValueRef::Hash(hash) => {
                         trace!(target: "trie::hash_builder", ?hash, "pushing branch node hash");
                         self.stack.push(RlpNode::word_rlp(hash));
+                        self.record_stack();
 
                         if self.stored_in_database {
                             self.tree_masks[current.len() - 1] |=
@@ -275,18 +594,15 @@ impl HashBuilder {
 
             if build_extensions && !short_node_key.is_empty() {
                 self.update_masks(&current, len_from);
+                self.resolve_stack(self.stack.len().saturating_sub(1));
                 let stack_last = self.stack.pop().expect("there should be at least one stack item");
                 let extension_node = ExtensionNodeRef::new(&short_node_key, &stack_last);
 
                 self.rlp_buf.clear();
-                let rlp = extension_node.rlp(&mut self.rlp_buf);
-                trace!(
-                    target: "trie::hash_builder",
-                    ?extension_node,
-                    ?rlp,
-                    "pushing extension node",
-                );
-                self.stack.push(rlp);
+                extension_node.encode(&mut self.rlp_buf);
+                trace!(target: "trie::hash_builder", ?extension_node, "pushing extension node");
+                self.push_node_from_buf(false);
+                self.record_node(false);
                 self.retain_proof_from_buf(&current.slice(..len_from));
                 self.resize_masks(len_from);
             }
@@ -335,6 +651,8 @@ impl HashBuilder {
     fn push_branch_node(&mut self, current: &Nibbles, len: usize) -> Vec<B256> {
         let state_mask = self.groups[len];
         let hash_mask = self.hash_masks[len];
+        let first_child_idx = self.stack.len() - state_mask.count_ones() as usize;
+        self.resolve_stack(first_child_idx);
         let branch_node = BranchNodeRef::new(&self.stack, state_mask);
         // Avoid calculating this value if it's not needed.
         let children = if self.updated_branch_nodes.is_some() {
@@ -344,11 +662,10 @@ impl HashBuilder {
         };
 
         self.rlp_buf.clear();
-        let rlp = branch_node.rlp(&mut self.rlp_buf);
+        branch_node.encode(&mut self.rlp_buf);
         self.retain_proof_from_buf(&current.slice(..len));
 
         // Clears the stack from the branch node elements
-        let first_child_idx = self.stack.len() - state_mask.count_ones() as usize;
         trace!(
             target: "trie::hash_builder",
             new_len = first_child_idx,
@@ -357,8 +674,9 @@ impl HashBuilder {
         );
         self.stack.resize_with(first_child_idx, Default::default);
 
-        trace!(target: "trie::hash_builder", ?rlp, "pushing branch node with {state_mask:?} mask from stack");
-        self.stack.push(rlp);
+        trace!(target: "trie::hash_builder", "pushing branch node with {state_mask:?} mask from stack");
+        self.push_node_from_buf(true);
+        self.record_node(true);
         children
     }
 
@@ -389,13 +707,19 @@ impl HashBuilder {
                     (len == 0).then(|| self.current_root()),
                 );
                 trace!(target: "trie::hash_builder", ?node, "intermediate node");
-                self.updated_branch_nodes.as_mut().unwrap().insert(common_prefix, node);
+                if let Some(updated_branch_nodes) = self.updated_branch_nodes.as_mut() {
+                    updated_branch_nodes.insert(common_prefix, node);
+                }
             }
         }
     }
 
     fn retain_proof_from_buf(&mut self, prefix: &Nibbles) {
         if let Some(proof_retainer) = self.proof_retainer.as_mut() {
+            #[cfg(feature = "metrics")]
+            if prefix.is_empty() || proof_retainer.matches(prefix) {
+                self.metrics.proof_nodes_retained += 1;
+            }
             proof_retainer.retain(prefix, &self.rlp_buf)
         }
     }
@@ -428,9 +752,9 @@ impl HashBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{nodes::LeafNode, triehash_trie_root};
+    use crate::{nodes::LeafNode, triehash_trie_root, EMPTY_ROOT_HASH};
     use alloc::collections::BTreeMap;
-    use alloy_primitives::{b256, hex, U256};
+    use alloy_primitives::{b256, hex, keccak256, U256};
     use alloy_rlp::Encodable;
 
     // Hashes the keys, RLP encodes the values, compares the trie builder with the upstream root.
@@ -579,6 +903,57 @@ mod tests {
         assert_hashed_trie_root(data.iter());
     }
 
+    #[test]
+    fn test_root_unsorted_data() {
+        let data = (0..100u64)
+            .map(|i| (B256::from(U256::from(i * 7919 % 101)), U256::from(i)))
+            .collect::<Vec<_>>();
+
+        let mut hb = HashBuilder::default();
+        for (key, value) in &data {
+            hb.add_unsorted_leaf(Nibbles::unpack(key), &alloy_rlp::encode(value));
+        }
+        // Overwrite a value, the last write wins.
+        hb.add_unsorted_leaf(Nibbles::unpack(data[0].0), &alloy_rlp::encode(U256::MAX));
+
+        let mut expected = data.iter().copied().collect::<BTreeMap<_, _>>();
+        expected.insert(data[0].0, U256::MAX);
+        assert_eq!(
+            hb.finalize(),
+            triehash_trie_root(expected.iter().map(|(k, v)| (k, alloy_rlp::encode(v))))
+        );
+    }
+
+    #[test]
+    fn test_root_flushes_unsorted_leaves() {
+        let (first, second) = (B256::with_last_byte(1), B256::with_last_byte(2));
+        let expected = triehash_trie_root([(first, [1]), (second, [2])]);
+
+        let mut hb = HashBuilder::default();
+        hb.add_unsorted_leaf(Nibbles::unpack(second), &[2]);
+        hb.add_unsorted_leaf(Nibbles::unpack(first), &[1]);
+        assert_eq!(hb.root(), expected);
+
+        let mut hb = HashBuilder::default();
+        hb.add_leaf(Nibbles::unpack(first), &[1]);
+        hb.add_unsorted_leaf(Nibbles::unpack(second), &[2]);
+        assert_eq!(hb.try_root(), Ok(expected));
+
+        // Sorted leaves must precede the buffered ones.
+        let mut hb = HashBuilder::default();
+        hb.add_unsorted_leaf(Nibbles::unpack(first), &[1]);
+        assert_eq!(
+            hb.try_add_leaf(Nibbles::unpack(second), &[2]),
+            Err(HashBuilderError::AfterUnsortedLeaves { key: Nibbles::unpack(second) })
+        );
+    }
+
+    #[test]
+    fn hash_builder_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<HashBuilder>();
+    }
+
     #[test]
     fn test_root_known_hash() {
         let root_hash = b256!("45596e474b536a6b4d64764e4f75514d544577646c414e684271706871446456");
@@ -622,4 +997,32 @@ mod tests {
         assert_eq!(hb.root(), expected);
         assert_eq!(hb2.root(), expected);
     }
+
+    #[test]
+    fn removed_branch_nodes() {
+        // The root has 16 branch children, each with 16 leaves. Only the root is stored, as the
+        // children have neither stored nor hashed children.
+        let mut hb = HashBuilder::default().with_updates(true);
+        for key in 0..=0xffu8 {
+            hb.add_leaf(Nibbles::unpack([key, 0]), &[0xab; 32]);
+        }
+        hb.root();
+        // The root and one of its children were stored before.
+        let stored = [Nibbles::default(), Nibbles::from_nibbles([0x5])];
+        hb.add_removed_branch_nodes(stored.clone());
+
+        // The updated root is not removed, and the children that were never stored are not
+        // reported.
+        let (_, updates, removals) = hb.split_with_removals();
+        assert_eq!(updates.keys().collect::<Vec<_>>(), [&Nibbles::default()]);
+        assert_eq!(removals.into_iter().collect::<Vec<_>>(), [stored[1].clone()]);
+
+        // Removals are only tracked along with updates.
+        let mut hb = HashBuilder::default();
+        hb.add_leaf(Nibbles::unpack([0x00]), &[1]);
+        hb.add_leaf(Nibbles::unpack([0x01]), &[2]);
+        hb.root();
+        hb.add_removed_branch_nodes([Nibbles::default()]);
+        assert!(hb.split_with_removals().2.is_empty());
+    }
 }
@@ -0,0 +1,255 @@
+use super::{HashBuilder, HashBuilderError};
+use crate::{Nibbles, TrieHasher};
+use alloy_primitives::B256;
+
+/// How a [`HashBuilder`] handles an invalid leaf or branch passed to [`HashBuilder::add_leaf`] or
+/// [`HashBuilder::add_branch`], such as a key that does not strictly follow the key of the
+/// previous element. See [`HashBuilderError`] for the invalid elements.
+///
+/// The fallible [`HashBuilder::try_add_leaf`], [`HashBuilder::try_add_branch`] and
+/// [`HashBuilder::try_root`] return the error regardless of the policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyOrderPolicy {
+    /// Panic, as the root would be wrong otherwise.
+    #[default]
+    Panic,
+    /// Skip the element and keep the first error, which is returned by
+    /// [`HashBuilder::try_root`]. [`HashBuilder::root`] panics.
+    Error,
+    /// Buffer all leaves as with [`HashBuilder::add_unsorted_leaf`], so that they can be added in
+    /// any order, and the last value of a duplicate key wins. The buffered leaves are added when
+    /// the root is computed. Invalid branches panic.
+    LastWriteWins,
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Sets how leaves with duplicate or out-of-order keys are handled. See [`KeyOrderPolicy`].
+    pub const fn with_key_order(mut self, policy: KeyOrderPolicy) -> Self {
+        self.key_order = policy;
+        self
+    }
+
+    /// Sets the maximum length of leaf values. Longer values are invalid leaves, rejected with
+    /// [`HashBuilderError::ValueTooLong`] and handled according to the [`KeyOrderPolicy`].
+    pub const fn with_max_value_len(mut self, max_value_len: usize) -> Self {
+        self.max_value_len = Some(max_value_len);
+        self
+    }
+
+    /// Adds a new leaf element and its value to the trie hash builder, unless the computation was
+    /// cancelled or the leaf is invalid, leaving the builder unchanged. See
+    /// [`HashBuilder::add_leaf`].
+    ///
+    /// With the [`KeyOrderPolicy::LastWriteWins`] policy, the order of the buffered leaves is
+    /// checked by [`HashBuilder::try_root`].
+    pub fn try_add_leaf(&mut self, key: Nibbles, value: &[u8]) -> Result<(), HashBuilderError> {
+        if self.is_cancelled() {
+            return Err(HashBuilderError::Cancelled);
+        }
+        self.check_value(&key, value)?;
+        if self.key_order == KeyOrderPolicy::LastWriteWins {
+            if key.is_empty() {
+                return Err(HashBuilderError::EmptyKey);
+            }
+            self.add_unsorted_leaf(key, value);
+            return Ok(());
+        }
+        self.check_key(&key, false)?;
+        self.add_sorted_leaf(key, value);
+        Ok(())
+    }
+
+    /// Adds a new branch element and its hash to the trie hash builder, unless the computation was
+    /// cancelled or the branch is invalid, leaving the builder unchanged. See
+    /// [`HashBuilder::add_branch`].
+    pub fn try_add_branch(
+        &mut self,
+        key: Nibbles,
+        value: B256,
+        stored_in_database: bool,
+    ) -> Result<(), HashBuilderError> {
+        if self.is_cancelled() {
+            return Err(HashBuilderError::Cancelled);
+        }
+        self.check_key(&key, true)?;
+        self.add_checked_branch(key, value, stored_in_database);
+        Ok(())
+    }
+
+    /// Returns the root hash of the trie, unless the computation was cancelled or an invalid
+    /// element was added. See [`HashBuilder::root`].
+    ///
+    /// The error is either the first one kept with the [`KeyOrderPolicy::Error`] policy, or the
+    /// one of the first invalid buffered leaf, see [`HashBuilder::add_unsorted_leaf`].
+    /// In the latter case, the leaves preceding the invalid one have been added.
+    pub fn try_root(&mut self) -> Result<B256, HashBuilderError> {
+        if self.is_cancelled() {
+            return Err(HashBuilderError::Cancelled);
+        }
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        self.add_buffered_leaves()?;
+        Ok(self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triehash_trie_root;
+    use alloy_primitives::keccak256;
+
+    fn key(i: u8) -> Nibbles {
+        Nibbles::unpack(keccak256([i]))
+    }
+
+    #[test]
+    fn error_policy() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::Error);
+        let (first, second) = if key(1) < key(2) { (key(1), key(2)) } else { (key(2), key(1)) };
+        hb.try_add_leaf(first.clone(), &[1; 32]).unwrap();
+        hb.try_add_leaf(second.clone(), &[2; 32]).unwrap();
+        assert_eq!(
+            hb.try_add_leaf(second.clone(), &[3; 32]),
+            Err(HashBuilderError::NonMonotonicKey {
+                key: second.clone(),
+                previous: second.clone()
+            })
+        );
+        assert_eq!(
+            hb.try_add_leaf(first.clone(), &[3; 32]),
+            Err(HashBuilderError::NonMonotonicKey { key: first.clone(), previous: second.clone() })
+        );
+        // The rejected leaves are not added.
+        assert_eq!(
+            hb.root(),
+            triehash_trie_root([(first.pack(), [1; 32]), (second.pack(), [2; 32])])
+        );
+    }
+
+    #[test]
+    #[should_panic = "does not follow the previous key"]
+    fn panic_policy() {
+        let mut hb = HashBuilder::default();
+        hb.add_leaf(key(1), &[1]);
+        assert!(hb.try_add_leaf(key(1), &[2]).is_err());
+        hb.add_leaf(key(1), &[2]);
+    }
+
+    #[test]
+    fn error_policy_add_leaf() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::Error);
+        hb.add_leaf(Nibbles::from_nibbles([1, 2]), &[1]);
+        hb.add_leaf(Nibbles::from_nibbles([1, 2, 3]), &[2]);
+        hb.add_leaf(Nibbles::from_nibbles([1, 1]), &[3]);
+        hb.add_leaf(Nibbles::from_nibbles([1, 3]), &[4]);
+        // The first error is kept.
+        assert_eq!(
+            hb.try_root(),
+            Err(HashBuilderError::PrefixKey {
+                key: Nibbles::from_nibbles([1, 2, 3]),
+                previous: Nibbles::from_nibbles([1, 2])
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let mut hb = HashBuilder::default();
+        assert_eq!(hb.try_add_leaf(Nibbles::default(), &[1]), Err(HashBuilderError::EmptyKey));
+        hb.try_add_leaf(Nibbles::from_nibbles([1, 2]), &[1]).unwrap();
+        assert_eq!(
+            hb.try_add_leaf(Nibbles::from_nibbles([1, 2, 3]), &[2]),
+            Err(HashBuilderError::PrefixKey {
+                key: Nibbles::from_nibbles([1, 2, 3]),
+                previous: Nibbles::from_nibbles([1, 2])
+            })
+        );
+        assert_eq!(
+            hb.try_add_branch(Nibbles::from_nibbles([1, 2, 3]), B256::ZERO, false),
+            Err(HashBuilderError::PrefixKey {
+                key: Nibbles::from_nibbles([1, 2, 3]),
+                previous: Nibbles::from_nibbles([1, 2])
+            })
+        );
+        assert_eq!(
+            hb.try_add_branch(Nibbles::default(), B256::ZERO, false),
+            Err(HashBuilderError::NonMonotonicKey {
+                key: Nibbles::default(),
+                previous: Nibbles::from_nibbles([1, 2])
+            })
+        );
+        hb.try_add_branch(Nibbles::from_nibbles([1, 3]), B256::ZERO, false).unwrap();
+        assert!(hb.try_root().is_ok());
+
+        // Only the first element may be the root branch.
+        let mut hb = HashBuilder::default();
+        hb.try_add_branch(Nibbles::default(), B256::ZERO, false).unwrap();
+        assert_eq!(
+            hb.try_add_branch(Nibbles::default(), B256::ZERO, false),
+            Err(HashBuilderError::NonMonotonicKey {
+                key: Nibbles::default(),
+                previous: Nibbles::default()
+            })
+        );
+        assert_eq!(hb.try_root(), Ok(B256::ZERO));
+    }
+
+    #[test]
+    fn invalid_buffered_leaves() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::LastWriteWins);
+        assert_eq!(hb.try_add_leaf(Nibbles::default(), &[1]), Err(HashBuilderError::EmptyKey));
+        hb.try_add_leaf(Nibbles::from_nibbles([1, 2, 3]), &[1]).unwrap();
+        hb.try_add_leaf(Nibbles::from_nibbles([1, 2]), &[2]).unwrap();
+        assert_eq!(
+            hb.try_root(),
+            Err(HashBuilderError::PrefixKey {
+                key: Nibbles::from_nibbles([1, 2, 3]),
+                previous: Nibbles::from_nibbles([1, 2])
+            })
+        );
+    }
+
+    #[test]
+    fn value_too_long() {
+        let mut hb = HashBuilder::default().with_max_value_len(32);
+        hb.try_add_leaf(key(1), &[1; 32]).unwrap();
+        assert_eq!(
+            hb.try_add_leaf(key(2), &[2; 33]),
+            Err(HashBuilderError::ValueTooLong { key: key(2), len: 33, max: 32 })
+        );
+        assert_eq!(hb.root(), triehash_trie_root([(key(1).pack(), [1; 32])]));
+
+        // With the error policy, the rejected leaf is skipped and its error kept.
+        let mut hb =
+            HashBuilder::default().with_key_order(KeyOrderPolicy::Error).with_max_value_len(32);
+        hb.add_leaf(key(2), &[2; 33]);
+        hb.add_leaf(key(1), &[1; 32]);
+        assert_eq!(
+            hb.try_root(),
+            Err(HashBuilderError::ValueTooLong { key: key(2), len: 33, max: 32 })
+        );
+    }
+
+    #[test]
+    #[should_panic = "is 33 bytes long, more than 32"]
+    fn value_too_long_panic_policy() {
+        let mut hb = HashBuilder::default().with_max_value_len(32);
+        hb.add_leaf(key(1), &[1; 33]);
+    }
+
+    #[test]
+    fn last_write_wins_policy() {
+        let mut hb = HashBuilder::default().with_key_order(KeyOrderPolicy::LastWriteWins);
+        for i in (0..16u8).rev() {
+            hb.add_leaf(key(i), &[i; 32]);
+        }
+        hb.try_add_leaf(key(3), &[0xff; 32]).unwrap();
+        let expected = triehash_trie_root(
+            (0..16u8).map(|i| (key(i).pack(), if i == 3 { [0xff; 32] } else { [i; 32] })),
+        );
+        assert_eq!(hb.root(), expected);
+    }
+}
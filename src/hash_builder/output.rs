@@ -0,0 +1,94 @@
+use super::HashBuilder;
+use crate::{proof::ProofNodes, BranchNodeCompact, HashMap, Nibbles, TrieHasher};
+use alloy_primitives::{map::HashSet, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The output of a [`HashBuilder`], returned by [`HashBuilder::root_with_proofs`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HashBuilderOutput {
+    /// The root hash of the trie.
+    pub root: B256,
+    /// The proof nodes retained by the [`ProofRetainer`](crate::proof::ProofRetainer), if any.
+    pub proof_nodes: ProofNodes,
+    /// The updated branch nodes, if enabled with [`HashBuilder::with_updates`].
+    pub updated_branch_nodes: HashMap<Nibbles, BranchNodeCompact>,
+    /// The paths of branch nodes to remove from the database, if enabled with
+    /// [`HashBuilder::with_updates`]. See [`HashBuilder::split_with_removals`].
+    pub removed_branch_nodes: HashSet<Nibbles>,
+    /// The keys of the added leaves in order, if enabled with
+    /// [`HashBuilder::with_touched_leaves`].
+    pub touched_leaves: Vec<Nibbles>,
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Computes the root hash of the trie, adding any buffered unsorted leaves as in
+    /// [`HashBuilder::finalize`], and returns it along with everything the builder retained.
+    pub fn root_with_proofs(mut self) -> HashBuilderOutput {
+        let root = self.finalize();
+        let proof_nodes = self.take_proof_nodes();
+        let touched_leaves = self.touched_leaves.take().unwrap_or_default();
+        let (_, updated_branch_nodes, removed_branch_nodes) = self.split_with_removals();
+        HashBuilderOutput {
+            root,
+            proof_nodes,
+            updated_branch_nodes,
+            removed_branch_nodes,
+            touched_leaves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::ProofRetainer;
+    use alloy_primitives::keccak256;
+
+    #[test]
+    fn root_with_proofs() {
+        let leaves = (0..50u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), vec![i as u8; 40]))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(7).cloned().collect::<Vec<_>>();
+        let new_builder = || {
+            HashBuilder::default()
+                .with_updates(true)
+                .with_proof_retainer(ProofRetainer::new(targets.clone()))
+        };
+
+        let mut expected = new_builder();
+        for (key, value) in &leaves {
+            expected.add_leaf(key.clone(), value);
+        }
+        let root = expected.root();
+        let proof_nodes = expected.take_proof_nodes();
+        let (_, updated_branch_nodes, removed_branch_nodes) = expected.split_with_removals();
+
+        let mut hash_builder = new_builder().with_touched_leaves(true);
+        let mut remaining = leaves.iter();
+        for (key, value) in remaining.by_ref().take(20) {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        {
+            let mut borrowed = hash_builder.borrowed_leaves();
+            for (key, value) in remaining.by_ref().take(20) {
+                borrowed.add_leaf_borrowed(key.clone(), value);
+            }
+        }
+        for (key, value) in remaining.rev() {
+            hash_builder.add_unsorted_leaf(key.clone(), value);
+        }
+        let output = hash_builder.root_with_proofs();
+        assert_eq!(output.root, root);
+        assert_eq!(output.proof_nodes, proof_nodes);
+        assert_eq!(output.updated_branch_nodes, updated_branch_nodes);
+        assert_eq!(output.removed_branch_nodes, removed_branch_nodes);
+        assert_eq!(output.touched_leaves, leaves.keys().cloned().collect::<Vec<_>>());
+
+        let output = HashBuilder::default().root_with_proofs();
+        assert_eq!(output.root, crate::EMPTY_ROOT_HASH);
+        assert!(output.touched_leaves.is_empty());
+    }
+}
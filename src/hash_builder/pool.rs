@@ -0,0 +1,166 @@
+use super::HashBuilder;
+use crate::{Nibbles, TrieHasher};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The largest RLP encoding of a branch node: the list header, 16 hashed children and the empty
+/// value.
+const MAX_BRANCH_NODE_LEN: usize = 3 + 16 * 33 + 1;
+
+/// The length of the paths in tries keyed by hashes.
+const HASHED_KEY_LEN: usize = 64;
+
+/// A pool of the byte buffers holding the values of the leaves buffered with
+/// [`HashBuilder::add_unsorted_leaf`].
+///
+/// [`HashBuilder::finalize`] returns the buffers of the added leaves to the pool, so that a builder
+/// that is [reset](HashBuilder::reset) and reused for the next root doesn't allocate a buffer per
+/// leaf again.
+#[derive(Clone, Debug, Default)]
+pub struct ValuePool {
+    buffers: Vec<Vec<u8>>,
+}
+
+impl ValuePool {
+    /// Creates an empty pool.
+    pub const fn new() -> Self {
+        Self { buffers: Vec::new() }
+    }
+
+    /// Returns the number of pooled buffers.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns `true` if there are no pooled buffers.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Releases the pooled buffers.
+    pub fn clear(&mut self) {
+        self.buffers = Vec::new();
+    }
+
+    /// Returns a copy of the value in a pooled buffer, or in a new one if the pool is empty.
+    pub(super) fn copy(&mut self, value: &[u8]) -> Vec<u8> {
+        match self.buffers.pop() {
+            Some(mut buf) => {
+                buf.clear();
+                buf.extend_from_slice(value);
+                buf
+            }
+            None => value.to_vec(),
+        }
+    }
+
+    /// Returns the buffer to the pool.
+    pub(super) fn recycle(&mut self, buf: Vec<u8>) {
+        self.buffers.push(buf);
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Creates a new hash builder with its buffers allocated for adding the given number of
+    /// leaves with hashed keys, so that they don't grow while the leaves are added.
+    pub fn with_capacity(leaves: usize) -> Self {
+        // Each level of branch nodes holds at most 16 nodes on the stack.
+        let depth = (usize::BITS - leaves.leading_zeros()).div_ceil(4) as usize + 1;
+        let mut builder = Self::new();
+        builder.stack.reserve(depth * 16);
+        builder.groups.reserve(HASHED_KEY_LEN);
+        builder.tree_masks.reserve(HASHED_KEY_LEN);
+        builder.hash_masks.reserve(HASHED_KEY_LEN);
+        builder.rlp_buf.reserve(MAX_BRANCH_NODE_LEN);
+        builder
+    }
+
+    /// Resets the builder for computing a new root, keeping its allocations and configuration.
+    ///
+    /// Updates and touched leaves are cleared but stay enabled, the buffers of the unsorted leaves
+    /// are returned to the [`ValuePool`], and the proof retainer is removed, as its targets are
+    /// specific to a trie. The hash cache, batch hasher, progress tracker, cancellation token and
+    /// metrics are kept.
+    pub fn reset(&mut self) {
+        self.key = Nibbles::default();
+        self.value.clear();
+        self.stack.clear();
+        self.groups.clear();
+        self.tree_masks.clear();
+        self.hash_masks.clear();
+        self.stored_in_database = false;
+        if let Some(updated_branch_nodes) = self.updated_branch_nodes.as_mut() {
+            updated_branch_nodes.clear();
+        }
+        if let Some(removed_branch_nodes) = self.removed_branch_nodes.as_mut() {
+            removed_branch_nodes.clear();
+        }
+        self.proof_retainer = None;
+        if let Some(batch_hasher) = self.batch_hasher.as_mut() {
+            batch_hasher.clear();
+        }
+        for (_, value) in self.unsorted_leaves.drain(..) {
+            self.value_pool.recycle(value);
+        }
+        if let Some(touched_leaves) = self.touched_leaves.as_mut() {
+            touched_leaves.clear();
+        }
+        self.rlp_buf.clear();
+        self.error = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{keccak256, B256};
+
+    fn leaves(seed: u64) -> Vec<(Nibbles, Vec<u8>)> {
+        (0..500u64)
+            .map(|i| {
+                let key = Nibbles::unpack(keccak256((seed * 1000 + i).to_be_bytes()));
+                (key, vec![i as u8; 40])
+            })
+            .collect()
+    }
+
+    fn root(leaves: &[(Nibbles, Vec<u8>)]) -> (B256, usize) {
+        let mut hb = HashBuilder::default().with_updates(true);
+        for (key, value) in leaves {
+            hb.add_unsorted_leaf(key.clone(), value);
+        }
+        let root = hb.finalize();
+        (root, hb.split().1.len())
+    }
+
+    #[test]
+    fn reset_and_reuse() {
+        let mut hb: HashBuilder = HashBuilder::with_capacity(500).with_updates(true);
+        let capacity = (hb.stack.capacity(), hb.groups.capacity(), hb.rlp_buf.capacity());
+
+        for seed in 0..3 {
+            let leaves = leaves(seed);
+            for (key, value) in &leaves {
+                hb.add_unsorted_leaf(key.clone(), value);
+            }
+            assert_eq!(hb.value_pool.len(), 0);
+            let root = hb.finalize();
+            assert_eq!(hb.value_pool.len(), leaves.len());
+            assert_eq!(
+                (root, hb.updated_branch_nodes.as_ref().unwrap().len()),
+                self::root(&leaves)
+            );
+            assert_eq!(
+                (hb.stack.capacity(), hb.groups.capacity(), hb.rlp_buf.capacity()),
+                capacity
+            );
+            hb.reset();
+        }
+
+        hb.add_unsorted_leaf(Nibbles::unpack(B256::ZERO), &[1]);
+        hb.reset();
+        assert_eq!(hb.value_pool.len(), 500);
+        assert_eq!(hb.root(), crate::EMPTY_ROOT_HASH);
+    }
+}
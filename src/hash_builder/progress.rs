@@ -0,0 +1,160 @@
+use super::HashBuilder;
+use crate::{Nibbles, TrieHasher};
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The progress of a [`HashBuilder`], reported to the hook set with
+/// [`HashBuilder::with_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashBuilderProgress<'a> {
+    /// The number of leaves added so far.
+    pub leaves_added: u64,
+    /// The key of the last added leaf. As leaves are added in order, this tells how far the
+    /// computation has advanced through the key space.
+    pub key: &'a Nibbles,
+}
+
+/// Reports the progress of a [`HashBuilder`] to a hook every given number of leaves.
+pub struct ProgressTracker {
+    interval: u64,
+    leaves_added: u64,
+    hook: Box<dyn FnMut(HashBuilderProgress<'_>) + Send + Sync>,
+}
+
+impl fmt::Debug for ProgressTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("interval", &self.interval)
+            .field("leaves_added", &self.leaves_added)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProgressTracker {
+    /// Creates a tracker calling the hook every `interval` leaves.
+    ///
+    /// # Panics
+    ///
+    /// If the interval is zero.
+    pub fn new(
+        interval: u64,
+        hook: impl FnMut(HashBuilderProgress<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        assert!(interval > 0, "progress interval must not be zero");
+        Self { interval, leaves_added: 0, hook: Box::new(hook) }
+    }
+
+    /// Returns the number of leaves added so far.
+    pub const fn leaves_added(&self) -> u64 {
+        self.leaves_added
+    }
+
+    fn record_leaf(&mut self, key: &Nibbles) {
+        self.leaves_added += 1;
+        if self.leaves_added % self.interval == 0 {
+            (self.hook)(HashBuilderProgress { leaves_added: self.leaves_added, key });
+        }
+    }
+}
+
+/// A flag for cooperatively cancelling a root computation, e.g. from another thread.
+///
+/// Clones share the same flag. The [`HashBuilder`] checks it in
+/// [`try_add_leaf`](HashBuilder::try_add_leaf), [`try_add_branch`](HashBuilder::try_add_branch)
+/// and [`try_root`](HashBuilder::try_root), which return
+/// [`HashBuilderError::Cancelled`](super::HashBuilderError::Cancelled). The infallible
+/// [`add_leaf`](HashBuilder::add_leaf), [`add_branch`](HashBuilder::add_branch) and
+/// [`root`](HashBuilder::root) ignore it, so long-running loops feeding a builder with them can
+/// check it with [`HashBuilder::is_cancelled`].
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the computations holding this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Calls the hook with the progress of the builder every `interval` added leaves.
+    ///
+    /// # Panics
+    ///
+    /// If the interval is zero.
+    pub fn with_progress(
+        mut self,
+        interval: u64,
+        hook: impl FnMut(HashBuilderProgress<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(ProgressTracker::new(interval, hook));
+        self
+    }
+
+    /// Sets the token with which the computation can be cancelled. Only the fallible methods,
+    /// such as [`HashBuilder::try_add_leaf`], check it. See [`CancellationToken`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Returns `true` if the computation was cancelled with the token set with
+    /// [`HashBuilder::with_cancellation`].
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Reports the added leaf to the progress tracker, if any.
+    #[inline]
+    pub(super) fn report_progress(&mut self, key: &Nibbles) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.record_leaf(key);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::hash_builder::HashBuilderError;
+    use std::sync::Mutex;
+
+    #[test]
+    fn progress_and_cancellation() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let token = CancellationToken::new();
+        let mut hb = HashBuilder::default().with_cancellation(token.clone()).with_progress(3, {
+            let reports = reports.clone();
+            move |progress| {
+                reports.lock().unwrap().push((progress.leaves_added, progress.key.clone()))
+            }
+        });
+
+        for i in 0..7u8 {
+            hb.try_add_leaf(Nibbles::unpack([i]), &[i + 1]).unwrap();
+        }
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [(3, Nibbles::unpack([2u8])), (6, Nibbles::unpack([5u8]))]
+        );
+        assert_eq!(hb.progress.as_ref().unwrap().leaves_added(), 7);
+
+        token.cancel();
+        assert!(hb.is_cancelled());
+        assert_eq!(hb.try_add_leaf(Nibbles::unpack([7u8]), &[8]), Err(HashBuilderError::Cancelled));
+        assert_eq!(hb.progress.as_ref().unwrap().leaves_added(), 7);
+    }
+}
@@ -0,0 +1,137 @@
+use super::{HashBuilder, HashBuilderValue};
+use crate::{nodes::RlpNode, Nibbles, TrieMask};
+
+/// A read-only view of the intermediate state of a [`HashBuilder`], returned by
+/// [`HashBuilder::stack_view`].
+///
+/// The builder keeps the last added element aside until the next one is added, as the shape of
+/// the trie around it depends on the next key. The nodes of the completed subtries are kept on a
+/// stack until the branch nodes above them are complete, which are described by the
+/// [`PendingBranch`]es along the path of the last added key.
+#[derive(Clone, Copy, Debug)]
+pub struct HashBuilderStack<'a> {
+    key: &'a Nibbles,
+    value: &'a HashBuilderValue,
+    stack: &'a [RlpNode],
+    groups: &'a [TrieMask],
+    tree_masks: &'a [TrieMask],
+    hash_masks: &'a [TrieMask],
+}
+
+impl<'a> HashBuilderStack<'a> {
+    /// Returns the key of the last added element, or an empty key if the builder is empty or the
+    /// root was computed.
+    pub const fn key(&self) -> &'a Nibbles {
+        self.key
+    }
+
+    /// Returns the value of the last added element.
+    pub const fn value(&self) -> &'a HashBuilderValue {
+        self.value
+    }
+
+    /// Returns the RLP pointers to the nodes of the completed subtries, in path order. Nodes
+    /// deferred by a [`BatchHasher`](super::BatchHasher) are empty until their hashes are
+    /// computed.
+    pub const fn nodes(&self) -> &'a [RlpNode] {
+        self.stack
+    }
+
+    /// Returns the children of the pending branch nodes, by the length of their paths. See
+    /// [`PendingBranch::state_mask`].
+    pub const fn groups(&self) -> &'a [TrieMask] {
+        self.groups
+    }
+
+    /// Returns the tree masks of the pending branch nodes, by the length of their paths.
+    pub const fn tree_masks(&self) -> &'a [TrieMask] {
+        self.tree_masks
+    }
+
+    /// Returns the hash masks of the pending branch nodes, by the length of their paths.
+    pub const fn hash_masks(&self) -> &'a [TrieMask] {
+        self.hash_masks
+    }
+
+    /// Returns an iterator over the pending branch nodes, from the root down to the last added
+    /// key.
+    pub fn branches(&self) -> impl Iterator<Item = PendingBranch> + 'a {
+        let Self { key, groups, tree_masks, hash_masks, .. } = *self;
+        groups.iter().enumerate().filter(|(_, group)| !group.is_empty()).map(move |(len, group)| {
+            PendingBranch {
+                path: key.slice(..len),
+                state_mask: *group,
+                tree_mask: tree_masks.get(len).copied().unwrap_or_default(),
+                hash_mask: hash_masks.get(len).copied().unwrap_or_default(),
+            }
+        })
+    }
+}
+
+/// A branch node of a [`HashBuilder`] that is still missing some of its children. See
+/// [`HashBuilderStack::branches`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PendingBranch {
+    /// The path of the branch node, which is a prefix of the last added key.
+    pub path: Nibbles,
+    /// The children of the branch node whose subtries are complete, with their nodes on the
+    /// stack. The child towards the last added key is not included.
+    pub state_mask: TrieMask,
+    /// The children of the branch node that are stored in the database, so far.
+    pub tree_mask: TrieMask,
+    /// The children of the branch node that are referenced by hash, so far.
+    pub hash_mask: TrieMask,
+}
+
+impl<H> HashBuilder<H> {
+    /// Returns a read-only view of the intermediate state of the builder, for debugging and for
+    /// integrations that need to inspect the builder, such as splitting a computation.
+    pub fn stack_view(&self) -> HashBuilderStack<'_> {
+        HashBuilderStack {
+            key: &self.key,
+            value: &self.value,
+            stack: &self.stack,
+            groups: &self.groups,
+            tree_masks: &self.tree_masks,
+            hash_masks: &self.hash_masks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn pending_branches() {
+        let mut hb = HashBuilder::default().with_updates(true);
+        let view = hb.stack_view();
+        assert!(view.key().is_empty());
+        assert_eq!(view.branches().count(), 0);
+
+        hb.add_leaf(Nibbles::from_nibbles([0, 1, 0, 0]), &[1; 32]);
+        hb.add_leaf(Nibbles::from_nibbles([0, 1, 0, 5]), &[2; 32]);
+        hb.add_leaf(Nibbles::from_nibbles([0, 2, 0, 0]), &[3; 32]);
+        let view = hb.stack_view();
+        assert_eq!(view.key(), &Nibbles::from_nibbles([0, 2, 0, 0]));
+        assert_eq!(view.value().as_slice(), &[3; 32]);
+        // The subtrie at `0x01` is complete, and the branch at `0x0` waits for `0x02`.
+        assert_eq!(view.nodes().len(), 1);
+        assert_eq!(
+            view.branches().collect::<Vec<_>>(),
+            [PendingBranch {
+                path: Nibbles::from_nibbles([0]),
+                state_mask: TrieMask::new(0b10),
+                tree_mask: TrieMask::default(),
+                hash_mask: TrieMask::default(),
+            }]
+        );
+
+        hb.root();
+        let view = hb.stack_view();
+        assert!(view.key().is_empty());
+        assert_eq!(view.branches().count(), 0);
+        assert_eq!(view.nodes().len(), 1);
+    }
+}
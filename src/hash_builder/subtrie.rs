@@ -0,0 +1,316 @@
+use super::{HashBuilder, HashBuilderOutput};
+use crate::{
+    nodes::{BranchNodeRef, ExtensionNode, LeafNode, RlpNode, TrieNode},
+    proof::{ProofNodes, ProofRetainer},
+    BranchNodeCompact, HashMap, KeccakHasher, Nibbles, TrieHasher, TrieMask,
+};
+use alloy_primitives::{map::HashSet, Bytes};
+use alloy_rlp::{Decodable, EMPTY_STRING_CODE};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// One of the 16 subtries below the root branch node of a trie, computed by a [`HashBuilder`] over
+/// the leaves whose keys start with the same nibble. Returned by [`HashBuilder::into_subtrie`].
+///
+/// The subtries of a trie can be computed independently, e.g. on different threads, and merged
+/// into the output of a single builder over all leaves with [`SubtrieOutput::merge`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SubtrieOutput {
+    /// The first nibble of the keys of the subtrie.
+    pub nibble: u8,
+    /// The RLP pointer to the root node of the subtrie, which is the child of the root branch
+    /// node at the nibble.
+    pub node: RlpNode,
+    /// The encoding of the root node of the subtrie if it's a leaf or an extension node, from
+    /// which the root of the trie is computed if there are no other subtries.
+    pub short_node: Option<Bytes>,
+    /// The bit of the nibble in the tree mask of the root branch node.
+    pub tree_mask: TrieMask,
+    /// The bit of the nibble in the hash mask of the root branch node.
+    pub hash_mask: TrieMask,
+    /// The proof nodes retained by the [`ProofRetainer`], if any.
+    pub proof_nodes: Option<ProofNodes>,
+    /// The updated branch nodes, if enabled with [`HashBuilder::with_updates`].
+    pub updated_branch_nodes: Option<HashMap<Nibbles, BranchNodeCompact>>,
+    /// The paths of branch nodes to remove from the database, if enabled with
+    /// [`HashBuilder::with_updates`].
+    pub removed_branch_nodes: Option<HashSet<Nibbles>>,
+    /// The keys of the added leaves in order, if enabled with
+    /// [`HashBuilder::with_touched_leaves`].
+    pub touched_leaves: Option<Vec<Nibbles>>,
+}
+
+impl SubtrieOutput {
+    /// Merges the subtries of a trie into the output of a [`HashBuilder`] over all of its leaves.
+    /// See [`Self::merge_with_hasher`].
+    pub fn merge(subtries: impl IntoIterator<Item = Self>) -> HashBuilderOutput {
+        Self::merge_with_hasher::<KeccakHasher>(subtries)
+    }
+
+    /// Merges the subtries of a trie into the output of a [`HashBuilder`] over all of its leaves,
+    /// computing the root node with the given [`TrieHasher`], which must be the hasher of the
+    /// builders of the subtries.
+    ///
+    /// The root node is retained as a proof node if any of the builders retained proofs, and
+    /// stored as an updated branch node if any of them retained updates, so all builders should
+    /// be configured alike. Without subtries, the trie is empty and nothing is retained.
+    ///
+    /// # Panics
+    ///
+    /// If two subtries have the same nibble.
+    pub fn merge_with_hasher<H: TrieHasher>(
+        subtries: impl IntoIterator<Item = Self>,
+    ) -> HashBuilderOutput {
+        let mut subtries = subtries.into_iter().collect::<Vec<_>>();
+        subtries.sort_unstable_by_key(|subtrie| subtrie.nibble);
+        assert!(
+            subtries.windows(2).all(|pair| pair[0].nibble != pair[1].nibble),
+            "duplicate subtrie nibble"
+        );
+
+        let mut proof_nodes = None::<ProofNodes>;
+        let mut updated_branch_nodes = None::<HashMap<_, _>>;
+        let mut removed_branch_nodes = None::<HashSet<_>>;
+        let mut touched_leaves = None::<Vec<_>>;
+        let (mut state_mask, mut tree_mask, mut hash_mask) =
+            <(TrieMask, TrieMask, TrieMask)>::default();
+        let mut children = Vec::with_capacity(subtries.len());
+        let mut last = (0, None);
+        for subtrie in subtries {
+            if let Some(nodes) = subtrie.proof_nodes {
+                proof_nodes.get_or_insert_with(Default::default).extend_from(nodes);
+            }
+            if let Some(updates) = subtrie.updated_branch_nodes {
+                updated_branch_nodes.get_or_insert_with(Default::default).extend(updates);
+            }
+            if let Some(removals) = subtrie.removed_branch_nodes {
+                removed_branch_nodes.get_or_insert_with(Default::default).extend(removals);
+            }
+            if let Some(leaves) = subtrie.touched_leaves {
+                touched_leaves.get_or_insert_with(Vec::new).extend(leaves);
+            }
+            state_mask |= TrieMask::from_nibble(subtrie.nibble);
+            tree_mask |= subtrie.tree_mask;
+            hash_mask |= subtrie.hash_mask;
+            children.push(subtrie.node);
+            last = (subtrie.nibble, subtrie.short_node);
+        }
+
+        let mut rlp_buf = Vec::new();
+        let root = match children.len() {
+            0 => {
+                rlp_buf.push(EMPTY_STRING_CODE);
+                H::empty_root()
+            }
+            // The root node is the only subtrie with the nibble prepended to its key.
+            1 => {
+                let (nibble, short_node) = last;
+                let prefix = Nibbles::from_nibbles_unchecked([nibble]);
+                let root = match short_node.map(|node| TrieNode::decode(&mut &node[..])) {
+                    Some(Ok(TrieNode::Leaf(leaf))) => {
+                        TrieNode::Leaf(LeafNode::new(prefix.join(&leaf.key), leaf.value))
+                    }
+                    Some(Ok(TrieNode::Extension(extension))) => TrieNode::Extension(
+                        ExtensionNode::new(prefix.join(&extension.key), extension.child),
+                    ),
+                    _ => {
+                        TrieNode::Extension(ExtensionNode::new(prefix.clone(), children[0].clone()))
+                    }
+                };
+                if !matches!(root, TrieNode::Extension(ref extension) if extension.key == prefix) {
+                    // The short node of the subtrie is replaced by the root node.
+                    if let Some(proof_nodes) = proof_nodes.as_mut() {
+                        proof_nodes.remove(&prefix);
+                    }
+                }
+                root.rlp_with_hasher::<H>(&mut rlp_buf);
+                H::hash(&rlp_buf)
+            }
+            _ => {
+                let branch = BranchNodeRef::new(&children, state_mask);
+                let node = branch.rlp_with_hasher::<H>(&mut rlp_buf);
+                let root = node.as_hash().unwrap_or_else(|| H::hash(&node));
+                if let Some(updates) = updated_branch_nodes.as_mut() {
+                    if !tree_mask.is_empty() || !hash_mask.is_empty() {
+                        let hashes = branch.child_hashes(hash_mask).collect();
+                        updates.insert(
+                            Nibbles::default(),
+                            BranchNodeCompact::new(
+                                state_mask,
+                                tree_mask,
+                                hash_mask,
+                                hashes,
+                                Some(root),
+                            ),
+                        );
+                    }
+                }
+                root
+            }
+        };
+        if let Some(proof_nodes) = proof_nodes.as_mut() {
+            proof_nodes.insert(Nibbles::default(), Bytes::from(rlp_buf));
+        }
+
+        let updated_branch_nodes = updated_branch_nodes.unwrap_or_default();
+        let mut removed_branch_nodes = removed_branch_nodes.unwrap_or_default();
+        removed_branch_nodes.retain(|path| !updated_branch_nodes.contains_key(path));
+        HashBuilderOutput {
+            root,
+            proof_nodes: proof_nodes.unwrap_or_default(),
+            updated_branch_nodes,
+            removed_branch_nodes,
+            touched_leaves: touched_leaves.unwrap_or_default(),
+        }
+    }
+}
+
+impl<H: TrieHasher> HashBuilder<H> {
+    /// Completes the subtrie of a builder whose keys all start with the same nibble, as a child of
+    /// the root branch node of a trie with other subtries, and returns it along with everything
+    /// the builder retained. Returns `None` if no leaves or branches were added.
+    ///
+    /// Leaves buffered with [`HashBuilder::add_unsorted_leaf`] are added first. The builder must
+    /// be configured with the same updates and proof retention as the builders of the other
+    /// subtries, see [`SubtrieOutput::merge_with_hasher`].
+    ///
+    /// # Panics
+    ///
+    /// If the keys don't all start with the same nibble, if the root was already computed, or if
+    /// an invalid element was added, as in [`HashBuilder::root`].
+    pub fn into_subtrie(mut self) -> Option<SubtrieOutput> {
+        if let Some(error) = &self.error {
+            panic!("{error}");
+        }
+        if let Err(error) = self.add_buffered_leaves() {
+            panic!("{error}");
+        }
+        let Some(nibble) = self.key.first() else {
+            assert!(self.stack.is_empty(), "the root of the subtrie was already computed");
+            return None;
+        };
+
+        // Completes the subtrie as if a key under another nibble followed.
+        self.update(&Nibbles::from_nibbles_unchecked([nibble ^ 1]));
+        self.resolve_stack(0);
+        assert!(
+            self.stack.len() == 1 && self.groups.first() == Some(&TrieMask::from_nibble(nibble)),
+            "the keys of the subtrie must start with the same nibble"
+        );
+        let node = self.stack.pop().expect("the subtrie has a root node");
+
+        // Unless the root node is a branch node given by its hash, it was encoded last.
+        let short_node = (RlpNode::from_rlp_with_hasher::<H>(&self.rlp_buf) == node
+            && matches!(
+                TrieNode::decode(&mut &self.rlp_buf[..]),
+                Ok(TrieNode::Leaf(_) | TrieNode::Extension(_))
+            ))
+        .then(|| Bytes::copy_from_slice(&self.rlp_buf));
+
+        Some(SubtrieOutput {
+            nibble,
+            node,
+            short_node,
+            tree_mask: self.tree_masks.first().copied().unwrap_or_default(),
+            hash_mask: self.hash_masks.first().copied().unwrap_or_default(),
+            proof_nodes: self.proof_retainer.take().map(ProofRetainer::into_proof_nodes),
+            updated_branch_nodes: self.updated_branch_nodes.take(),
+            removed_branch_nodes: self.removed_branch_nodes.take(),
+            touched_leaves: self.touched_leaves.take(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{keccak256, B256, U256};
+
+    fn new_builder(targets: &[Nibbles]) -> HashBuilder {
+        HashBuilder::default()
+            .with_updates(true)
+            .with_proof_retainer(ProofRetainer::new(targets.to_vec()))
+            .with_touched_leaves(true)
+    }
+
+    /// Compares the merged subtries with a single builder over all leaves.
+    fn assert_merged(leaves: &BTreeMap<Nibbles, Vec<u8>>) {
+        let targets = leaves
+            .keys()
+            .step_by(3)
+            .cloned()
+            .chain([Nibbles::unpack(B256::repeat_byte(0x33))])
+            .collect::<Vec<_>>();
+        let mut hash_builder = new_builder(&targets);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let expected = hash_builder.root_with_proofs();
+
+        let subtries = (0..16u8).filter_map(|nibble| {
+            let mut hash_builder = new_builder(&targets);
+            for (key, value) in leaves.iter().filter(|(key, _)| key[0] == nibble) {
+                hash_builder.add_leaf(key.clone(), value);
+            }
+            hash_builder.into_subtrie()
+        });
+        assert_eq!(SubtrieOutput::merge(subtries), expected, "{leaves:?}");
+    }
+
+    #[test]
+    fn merge_subtries() {
+        let leaves = (0..300u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        assert_merged(&leaves);
+        assert_eq!(SubtrieOutput::merge([]).root, crate::EMPTY_ROOT_HASH);
+
+        // A single subtrie whose root is a leaf, an extension or a branch node.
+        let single = leaves.iter().filter(|(key, _)| key[0] == 3);
+        for n in [1, 2, 20] {
+            assert_merged(&single.clone().take(n).map(|(k, v)| (k.clone(), v.clone())).collect());
+        }
+
+        // Short keys and values produce nodes embedded in their parents.
+        for keys in [&[[1, 2, 3], [1, 2, 4]][..], &[[1, 2, 3], [5, 2, 4]], &[[0, 0, 0]]] {
+            let leaves = keys
+                .iter()
+                .map(|key| (Nibbles::from_nibbles(key), vec![key[2]]))
+                .collect::<BTreeMap<_, _>>();
+            assert_merged(&leaves);
+        }
+    }
+
+    #[test]
+    fn branch_subtrie() {
+        // The subtrie is a stored branch node given by its hash.
+        let mut hash_builder = new_builder(&[]);
+        hash_builder.add_branch(Nibbles::from_nibbles([2]), B256::repeat_byte(2), true);
+        let subtrie = hash_builder.into_subtrie().unwrap();
+        assert_eq!(subtrie.short_node, None);
+        assert_eq!(subtrie.hash_mask, TrieMask::from_nibble(2));
+        assert_eq!(subtrie.tree_mask, TrieMask::from_nibble(2));
+
+        let mut hash_builder = new_builder(&[]);
+        hash_builder.add_branch(Nibbles::from_nibbles([2]), B256::repeat_byte(2), true);
+        hash_builder.add_leaf(Nibbles::unpack(B256::repeat_byte(0x55)), &[5; 32]);
+        let expected = hash_builder.root_with_proofs();
+        let mut hash_builder = new_builder(&[]);
+        hash_builder.add_leaf(Nibbles::unpack(B256::repeat_byte(0x55)), &[5; 32]);
+        let merged = SubtrieOutput::merge([subtrie, hash_builder.into_subtrie().unwrap()]);
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    #[should_panic = "must start with the same nibble"]
+    fn mixed_nibbles() {
+        let mut hash_builder = HashBuilder::default();
+        hash_builder.add_leaf(Nibbles::from_nibbles([1, 0]), &[1]);
+        hash_builder.add_leaf(Nibbles::from_nibbles([2, 0]), &[2]);
+        hash_builder.into_subtrie();
+    }
+}
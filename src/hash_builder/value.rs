@@ -9,7 +9,7 @@ use core::fmt;
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HashBuilderValue {
     /// Stores the bytes of either the leaf node value or the hash of adjacent nodes.
-    #[cfg_attr(feature = "serde", serde(with = "hex"))]
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::bytes"))]
     buf: Vec<u8>,
     /// The kind of value that is stored in `buf`.
     kind: HashBuilderValueKind,
@@ -97,6 +97,12 @@ impl HashBuilderValue {
         self.kind = value.kind();
     }
 
+    /// Takes the value out, leaving an empty value without allocating.
+    #[inline]
+    pub(crate) fn take(&mut self) -> Self {
+        Self { buf: core::mem::take(&mut self.buf), kind: core::mem::take(&mut self.kind) }
+    }
+
     /// Clears the value.
     #[inline]
     pub fn clear(&mut self) {
@@ -0,0 +1,202 @@
+//! Pending changes of the hashed state, keyed by hashed address and hashed slot.
+//!
+//! A [`HashedPostState`] holds the accounts and storage slots changed by blocks that are not
+//! persisted yet, and is the input of an incremental [`StateRoot`](crate::root::StateRoot)
+//! computation: it provides the [`TriePrefixSets`] of the changed keys, and once
+//! [sorted](HashedPostState::into_sorted), is layered over the persisted hashed state by the
+//! [overlay cursors](crate::cursor::OverlayHashedCursorFactory).
+
+use crate::{
+    prefix_set::{PrefixSetMut, TriePrefixSets},
+    HashMap, Nibbles, TrieAccount,
+};
+use alloy_primitives::{keccak256, Address, B256, U256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The changed accounts and storage slots of a range of blocks.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HashedPostState {
+    /// The changed accounts keyed by hashed address, or [`None`] if the account was destroyed.
+    pub accounts: HashMap<B256, Option<TrieAccount>>,
+    /// The changed storage slots, keyed by hashed address.
+    pub storages: HashMap<B256, HashedStorage>,
+}
+
+impl HashedPostState {
+    /// Creates the hashed state changes from the changed accounts keyed by plain address, with
+    /// their changed storage slots keyed by plain slot.
+    ///
+    /// A destroyed account, given as [`None`], has its storage wiped. The storage roots of the
+    /// accounts are ignored.
+    pub fn from_state<I, S>(state: I) -> Self
+    where
+        I: IntoIterator<Item = (Address, Option<TrieAccount>, S)>,
+        S: IntoIterator<Item = (B256, U256)>,
+    {
+        let mut this = Self::default();
+        for (address, account, storage) in state {
+            let hashed_address = keccak256(address);
+            let storage = HashedStorage {
+                wiped: account.is_none(),
+                storage: storage
+                    .into_iter()
+                    .map(|(slot, value)| (keccak256(slot), value))
+                    .collect(),
+            };
+            this.accounts.insert(hashed_address, account);
+            if storage.wiped || !storage.storage.is_empty() {
+                this.storages.insert(hashed_address, storage);
+            }
+        }
+        this
+    }
+
+    /// Returns `true` if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty() && self.storages.is_empty()
+    }
+
+    /// Applies the changes of a later block on top of these ones.
+    pub fn extend(&mut self, other: Self) {
+        self.accounts.extend(other.accounts);
+        for (hashed_address, storage) in other.storages {
+            self.storages.entry(hashed_address).or_default().extend(storage);
+        }
+    }
+
+    /// Returns these changes followed by the changes of a later block. See
+    /// [`HashedPostState::extend`].
+    pub fn merge(mut self, other: Self) -> Self {
+        self.extend(other);
+        self
+    }
+
+    /// Returns the changed keys of the account trie and the storage tries.
+    ///
+    /// Accounts with changed storage are included in the account prefix set, and accounts with
+    /// wiped storage are marked as destroyed.
+    pub fn construct_prefix_sets(&self) -> TriePrefixSets {
+        let mut account_prefix_set = PrefixSetMut::with_capacity(self.accounts.len());
+        account_prefix_set.extend(self.accounts.keys().map(Nibbles::unpack));
+
+        let mut prefix_sets = TriePrefixSets::default();
+        for (hashed_address, storage) in &self.storages {
+            account_prefix_set.insert(Nibbles::unpack(hashed_address));
+            if storage.wiped {
+                prefix_sets.destroyed_accounts.insert(*hashed_address);
+            }
+            let mut storage_prefix_set = PrefixSetMut::with_capacity(storage.storage.len());
+            storage_prefix_set.extend(storage.storage.keys().map(Nibbles::unpack));
+            prefix_sets.storage_prefix_sets.insert(*hashed_address, storage_prefix_set.freeze());
+        }
+        prefix_sets.account_prefix_set = account_prefix_set.freeze();
+        prefix_sets
+    }
+
+    /// Sorts the changes by key, to be read by cursors.
+    pub fn into_sorted(self) -> HashedPostStateSorted {
+        let mut accounts = self.accounts.into_iter().collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(|(hashed_address, _)| *hashed_address);
+        let storages = self
+            .storages
+            .into_iter()
+            .map(|(hashed_address, storage)| (hashed_address, storage.into_sorted()))
+            .collect();
+        HashedPostStateSorted { accounts, storages }
+    }
+}
+
+/// The changed storage slots of an account.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HashedStorage {
+    /// Whether all persisted slots were removed, e.g. because the account was destroyed. The
+    /// changed slots are applied after the removal.
+    pub wiped: bool,
+    /// The changed slots keyed by hashed slot. A zero value removes the slot.
+    pub storage: HashMap<B256, U256>,
+}
+
+impl HashedStorage {
+    /// Creates the changes of a storage whose persisted slots were all removed.
+    pub fn wiped() -> Self {
+        Self { wiped: true, ..Default::default() }
+    }
+
+    /// Applies the changes of a later block on top of these ones.
+    pub fn extend(&mut self, other: Self) {
+        if other.wiped {
+            self.wiped = true;
+            self.storage.clear();
+        }
+        self.storage.extend(other.storage);
+    }
+
+    /// Sorts the changed slots by hashed slot.
+    pub fn into_sorted(self) -> HashedStorageSorted {
+        let mut storage = self.storage.into_iter().collect::<Vec<_>>();
+        storage.sort_unstable_by_key(|(hashed_slot, _)| *hashed_slot);
+        HashedStorageSorted { wiped: self.wiped, storage }
+    }
+}
+
+/// A [`HashedPostState`] with its changes sorted by key.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HashedPostStateSorted {
+    /// The changed accounts sorted by hashed address, or [`None`] if the account was destroyed.
+    pub accounts: Vec<(B256, Option<TrieAccount>)>,
+    /// The changed storage slots, keyed by hashed address.
+    pub storages: HashMap<B256, HashedStorageSorted>,
+}
+
+/// A [`HashedStorage`] with its changed slots sorted by hashed slot.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HashedStorageSorted {
+    /// Whether all persisted slots were removed.
+    pub wiped: bool,
+    /// The changed slots sorted by hashed slot. A zero value removes the slot.
+    pub storage: Vec<(B256, U256)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_post_state() {
+        let (a, b, c) = (Address::with_last_byte(1), Address::with_last_byte(2), Address::ZERO);
+        let slot = B256::with_last_byte(1);
+        let state = HashedPostState::from_state([
+            (a, Some(TrieAccount::default()), vec![(slot, U256::from(1))]),
+            (b, Some(TrieAccount::default()), vec![]),
+            (c, None, vec![]),
+        ]);
+        assert_eq!(state.accounts.len(), 3);
+        assert_eq!(state.storages.len(), 2);
+        assert_eq!(state.storages[&keccak256(a)].storage[&keccak256(slot)], U256::from(1));
+        assert!(state.storages[&keccak256(c)].wiped);
+
+        let prefix_sets = state.construct_prefix_sets();
+        assert_eq!(prefix_sets.account_prefix_set.len(), 3);
+        assert_eq!(prefix_sets.storage_prefix_sets[&keccak256(a)].len(), 1);
+        assert!(prefix_sets.destroyed_accounts.contains(&keccak256(c)));
+
+        // The account is recreated with new storage in a later block.
+        let later = HashedPostState::from_state([
+            (c, Some(TrieAccount::default()), vec![(slot, U256::from(2))]),
+            (a, Some(TrieAccount::default()), vec![(slot, U256::ZERO)]),
+        ]);
+        let merged = state.merge(later);
+        let storage = &merged.storages[&keccak256(c)];
+        assert!(storage.wiped);
+        assert_eq!(storage.storage[&keccak256(slot)], U256::from(2));
+        assert!(merged.accounts[&keccak256(c)].is_some());
+        assert_eq!(merged.storages[&keccak256(a)].storage[&keccak256(slot)], U256::ZERO);
+
+        let sorted = merged.into_sorted();
+        assert_eq!(sorted.accounts.len(), 3);
+        assert!(sorted.accounts.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(sorted.storages[&keccak256(c)].storage, [(keccak256(slot), U256::from(2))]);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{Nibbles, EMPTY_ROOT_HASH};
+use alloy_primitives::{keccak256, B256};
+use alloy_rlp::EMPTY_STRING_CODE;
+use core::{fmt::Debug, marker::PhantomData};
+
+/// The hash function used to compute the hashes of trie nodes.
+///
+/// Ethereum tries are hashed with keccak256, which is provided by [`KeccakHasher`] and used by
+/// default. Other 32-byte hash functions can be plugged into the
+/// [`HashBuilder`](crate::HashBuilder), node hashing and proof verification by implementing this
+/// trait.
+pub trait TrieHasher: Clone + Copy + Debug + Default + Send + Sync + 'static {
+    /// Hashes the given data.
+    fn hash(data: &[u8]) -> B256;
+
+    /// Node encodings shorter than this many bytes are embedded in their parent node instead of
+    /// being referenced by their hash. The root node is always hashed.
+    ///
+    /// Ethereum tries embed encodings shorter than 32 bytes. A threshold of zero hashes every
+    /// node. The threshold must not exceed 33, the capacity of an
+    /// [`RlpNode`](crate::nodes::RlpNode).
+    const INLINE_THRESHOLD: usize = 32;
+
+    /// Leaf values longer than this many bytes are replaced by their hash in leaf nodes, as in
+    /// tries that commit to large values by hash instead of embedding them.
+    ///
+    /// Ethereum tries embed values of any length, which is the default of `usize::MAX`.
+    const VALUE_HASH_THRESHOLD: usize = usize::MAX;
+
+    /// Returns the hash of the leaf value if it's longer than the
+    /// [value hashing threshold](Self::VALUE_HASH_THRESHOLD), in which case leaf nodes store the
+    /// hash instead of the value.
+    #[inline]
+    fn hash_leaf_value(value: &[u8]) -> Option<B256> {
+        (value.len() > Self::VALUE_HASH_THRESHOLD).then(|| Self::hash(value))
+    }
+
+    /// Returns the root hash of an empty trie, which is the hash of an empty RLP string.
+    #[inline]
+    fn empty_root() -> B256 {
+        Self::hash(&[EMPTY_STRING_CODE])
+    }
+}
+
+/// The keccak256 [`TrieHasher`] used by Ethereum tries.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct KeccakHasher;
+
+impl TrieHasher for KeccakHasher {
+    #[inline]
+    fn hash(data: &[u8]) -> B256 {
+        #[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+        if let Some(keccak256) = custom_keccak256() {
+            return keccak256(data);
+        }
+        keccak256(data)
+    }
+
+    #[inline]
+    fn empty_root() -> B256 {
+        EMPTY_ROOT_HASH
+    }
+}
+
+/// The keccak256 implementation set with [`set_keccak256`].
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+static CUSTOM_KECCAK256: CustomKeccak256 = CustomKeccak256::new();
+
+/// An optional keccak256 function pointer that can be set at runtime.
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+struct CustomKeccak256(core::sync::atomic::AtomicPtr<fn(&[u8]) -> B256>);
+
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+impl CustomKeccak256 {
+    const fn new() -> Self {
+        Self(core::sync::atomic::AtomicPtr::new(core::ptr::null_mut()))
+    }
+
+    fn set(&self, keccak256: fn(&[u8]) -> B256) {
+        // The function pointer is leaked, so that it can be read at any time after.
+        let keccak256 = alloc::boxed::Box::leak(alloc::boxed::Box::new(keccak256));
+        self.0.store(keccak256, core::sync::atomic::Ordering::Release);
+    }
+
+    #[inline]
+    fn get(&self) -> Option<fn(&[u8]) -> B256> {
+        let keccak256 = self.0.load(core::sync::atomic::Ordering::Acquire);
+        // SAFETY: the pointer is either null or was leaked in `set`, so it is valid forever.
+        unsafe { keccak256.as_ref() }.copied()
+    }
+}
+
+/// Sets the keccak256 implementation used by [`KeccakHasher`], and thereby by the
+/// [`HashBuilder`](crate::HashBuilder), node hashing and proof verification by default.
+///
+/// This is meant for zkVMs and other environments where keccak256 is provided by a precompile or
+/// an oracle. The function must compute keccak256, and should be set once at startup, before any
+/// trie is hashed. Hashes of keys, such as the hashed addresses of [`root::state_root`], are not
+/// affected.
+///
+/// This is only available on targets with pointer-sized atomics. On other targets, implement
+/// [`TrieHasher`] with the precompile and use it in place of [`KeccakHasher`].
+///
+/// [`root::state_root`]: crate::root::state_root
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+pub fn set_keccak256(keccak256: fn(&[u8]) -> B256) {
+    CUSTOM_KECCAK256.set(keccak256);
+}
+
+/// Returns the keccak256 implementation set with [`set_keccak256`], if any.
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+#[inline]
+pub(crate) fn custom_keccak256() -> Option<fn(&[u8]) -> B256> {
+    CUSTOM_KECCAK256.get()
+}
+
+/// A [`TrieHasher`] that hashes nodes with `H`, but embeds node encodings shorter than
+/// `THRESHOLD` bytes in their parent instead of those shorter than 32 bytes.
+///
+/// This is meant for experimental chains and tests. For example, `InlineThreshold<0>` produces
+/// keccak256 tries where every child node is referenced by its hash.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct InlineThreshold<const THRESHOLD: usize, H = KeccakHasher>(PhantomData<H>);
+
+impl<const THRESHOLD: usize, H: TrieHasher> TrieHasher for InlineThreshold<THRESHOLD, H> {
+    const INLINE_THRESHOLD: usize = THRESHOLD;
+    const VALUE_HASH_THRESHOLD: usize = H::VALUE_HASH_THRESHOLD;
+
+    #[inline]
+    fn hash(data: &[u8]) -> B256 {
+        H::hash(data)
+    }
+
+    #[inline]
+    fn empty_root() -> B256 {
+        H::empty_root()
+    }
+}
+
+/// A [`TrieHasher`] that hashes nodes with `H`, but stores the hash of leaf values longer than
+/// `THRESHOLD` bytes in leaf nodes instead of the values themselves.
+///
+/// This expresses trie layouts that commit to large values by hash. For example,
+/// `HashedValues<32>` produces keccak256 tries whose leaf nodes store values of up to 32 bytes,
+/// and the keccak256 hash of longer values.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct HashedValues<const THRESHOLD: usize, H = KeccakHasher>(PhantomData<H>);
+
+impl<const THRESHOLD: usize, H: TrieHasher> TrieHasher for HashedValues<THRESHOLD, H> {
+    const INLINE_THRESHOLD: usize = H::INLINE_THRESHOLD;
+    const VALUE_HASH_THRESHOLD: usize = THRESHOLD;
+
+    #[inline]
+    fn hash(data: &[u8]) -> B256 {
+        H::hash(data)
+    }
+
+    #[inline]
+    fn empty_root() -> B256 {
+        H::empty_root()
+    }
+}
+
+/// The mapping from keys to trie paths.
+///
+/// The state and storage tries are "secure" tries, where keys are hashed with keccak256 before
+/// being inserted, which is provided by [`KeccakKeyHasher`]. The transactions and receipts tries
+/// use the keys as they are, which is provided by [`IdentityKeyHasher`].
+pub trait KeyHasher: Clone + Copy + Debug + Default + Send + Sync + 'static {
+    /// Returns the path of the given key in the trie.
+    fn hash_key<T: AsRef<[u8]>>(key: T) -> Nibbles;
+}
+
+/// The [`KeyHasher`] of secure tries, such as the state and storage tries, which are keyed by the
+/// keccak256 hash of the key.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct KeccakKeyHasher;
+
+impl KeyHasher for KeccakKeyHasher {
+    #[inline]
+    fn hash_key<T: AsRef<[u8]>>(key: T) -> Nibbles {
+        Nibbles::unpack(keccak256(key))
+    }
+}
+
+/// The [`KeyHasher`] of tries keyed by the raw key, such as the transactions and receipts tries.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct IdentityKeyHasher;
+
+impl KeyHasher for IdentityKeyHasher {
+    #[inline]
+    fn hash_key<T: AsRef<[u8]>>(key: T) -> Nibbles {
+        crate::nibbles::unpack(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, verify_proof_with_hasher, ProofRetainer},
+        HashBuilder, Nibbles,
+    };
+    use alloc::vec::Vec;
+    use alloy_primitives::U256;
+
+    /// Domain-separated keccak256, standing in for an alternative hash function.
+    #[derive(Clone, Copy, Default, Debug)]
+    struct PrefixedKeccak;
+
+    impl TrieHasher for PrefixedKeccak {
+        fn hash(data: &[u8]) -> B256 {
+            keccak256([b"prefix", data].concat())
+        }
+    }
+
+    #[test]
+    fn custom_hasher() {
+        let leaves = (0..64u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let (target, value) = leaves.iter().nth(7).unwrap();
+
+        let retainer = ProofRetainer::from_iter([target.clone()]);
+        let mut hash_builder = HashBuilder::<PrefixedKeccak>::new().with_proof_retainer(retainer);
+        let mut keccak_hash_builder = HashBuilder::default();
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+            keccak_hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        assert_ne!(root, keccak_hash_builder.root());
+
+        let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+        let proof = proof.iter().map(|(_, node)| node);
+        assert_eq!(
+            verify_proof_with_hasher::<PrefixedKeccak, _>(
+                root,
+                target.clone(),
+                Some(value.clone()),
+                proof.clone()
+            ),
+            Ok(())
+        );
+        assert!(verify_proof(root, target.clone(), Some(value.clone()), proof).is_err());
+
+        assert_eq!(HashBuilder::<PrefixedKeccak>::new().root(), PrefixedKeccak::empty_root());
+        assert_eq!(KeccakHasher::empty_root(), KeccakHasher::hash(&[EMPTY_STRING_CODE]));
+    }
+
+    #[test]
+    fn inline_threshold() {
+        // Short keys and values, so that most nodes are embedded in their parents by default.
+        let leaves = (0..16u8)
+            .flat_map(|i| (0..3u8).map(move |j| (Nibbles::from_nibbles([i, j, 0, 1]), vec![i, j])))
+            .collect::<Vec<_>>();
+        let (target, value) = leaves[7].clone();
+
+        fn root_and_proof<H: TrieHasher>(
+            leaves: &[(Nibbles, Vec<u8>)],
+            target: &Nibbles,
+        ) -> (B256, Vec<alloy_primitives::Bytes>) {
+            let retainer = ProofRetainer::from_iter([target.clone()]);
+            let mut hash_builder = HashBuilder::<H>::new().with_proof_retainer(retainer);
+            for (key, value) in leaves {
+                hash_builder.add_leaf(key.clone(), value);
+            }
+            let root = hash_builder.root();
+            let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+            (root, proof.into_iter().map(|(_, node)| node).collect())
+        }
+
+        let (root, proof) = root_and_proof::<KeccakHasher>(&leaves, &target);
+        assert_eq!(root_and_proof::<InlineThreshold<32>>(&leaves, &target), (root, proof.clone()));
+        assert_eq!(root, crate::triehash_trie_root(leaves.iter().map(|(k, v)| (k.pack(), v))));
+        assert!(proof.iter().any(|node| node.len() < 32));
+
+        let (hashed_root, hashed_proof) = root_and_proof::<InlineThreshold<0>>(&leaves, &target);
+        assert_ne!(hashed_root, root);
+        // The proofs contain the same nodes, but their children are referenced by hash.
+        assert_eq!(hashed_proof.len(), proof.len());
+        assert!(hashed_proof
+            .iter()
+            .zip(&proof)
+            .all(|(hashed, inlined)| hashed.len() >= inlined.len()));
+        assert_ne!(hashed_proof, proof);
+        assert_eq!(
+            verify_proof_with_hasher::<InlineThreshold<0>, _>(
+                hashed_root,
+                target.clone(),
+                Some(value.clone()),
+                &hashed_proof
+            ),
+            Ok(())
+        );
+        assert!(verify_proof(hashed_root, target, Some(value), &hashed_proof).is_err());
+        assert_eq!(HashBuilder::<InlineThreshold<0>>::new().root(), KeccakHasher::empty_root());
+    }
+
+    #[test]
+    fn hashed_values() {
+        let leaves = (0..32u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), vec![i as u8; 1 + 4 * i as usize])
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let (target, value) = leaves.iter().nth(20).unwrap();
+        assert!(value.len() > 32);
+
+        let retainer = ProofRetainer::from_iter([target.clone()]);
+        let mut hash_builder = HashBuilder::<HashedValues<32>>::new().with_proof_retainer(retainer);
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+
+        // The same as a regular trie of the values, with long values replaced by their hashes.
+        let expected = crate::triehash_trie_root(leaves.iter().map(|(key, value)| {
+            let value = if value.len() > 32 { keccak256(value).to_vec() } else { value.clone() };
+            (key.pack(), value)
+        }));
+        assert_eq!(root, expected);
+
+        let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+        let proof = proof.iter().map(|(_, node)| node);
+        assert_eq!(
+            verify_proof_with_hasher::<HashedValues<32>, _>(
+                root,
+                target.clone(),
+                Some(value.clone()),
+                proof.clone()
+            ),
+            Ok(())
+        );
+        assert!(verify_proof(root, target.clone(), Some(value.clone()), proof).is_err());
+
+        let leaf = crate::nodes::LeafNode::new(target.clone(), value.clone());
+        let hashed_leaf = crate::nodes::LeafNode::new(target.clone(), keccak256(value).to_vec());
+        assert_eq!(leaf.rehash_with_hasher::<HashedValues<32>>(), hashed_leaf.rehash());
+        assert_eq!(HashedValues::<32>::hash_leaf_value(&[0; 32]), None);
+        assert_eq!(KeccakHasher::hash_leaf_value(value), None);
+    }
+
+    #[test]
+    #[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+    fn custom_keccak() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn counting_keccak256(data: &[u8]) -> B256 {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            keccak256(data)
+        }
+
+        let key = Nibbles::unpack(keccak256([1]));
+        let value = vec![0xab; 64];
+        let expected = crate::triehash_trie_root([(key.pack(), &value)]);
+
+        // Other tests may hash concurrently, so only check that calls are routed to the function.
+        set_keccak256(counting_keccak256);
+        let calls = CALLS.load(Ordering::Relaxed);
+        let mut hash_builder = HashBuilder::default();
+        hash_builder.add_leaf(key.clone(), &value);
+        assert_eq!(hash_builder.root(), expected);
+        assert!(CALLS.load(Ordering::Relaxed) > calls);
+
+        let calls = CALLS.load(Ordering::Relaxed);
+        let leaf = crate::nodes::LeafNodeRef::new(&key, &value);
+        assert_eq!(leaf.rlp_unbuffered(), leaf.rlp(&mut Vec::new()));
+        assert!(CALLS.load(Ordering::Relaxed) >= calls + 2);
+    }
+}
@@ -0,0 +1,272 @@
+//! Planning of trie healing, as done by snap sync.
+//!
+//! After the leaves of a trie are downloaded in ranges, the local nodes are a patchwork of
+//! different versions of the trie. Healing fetches the nodes of the target trie that are missing
+//! locally, descending from its root. The [`HealPlanner`] walks the target trie breadth-first
+//! through the local [`NodeProvider`] and the nodes downloaded so far, and yields the missing
+//! nodes as batches of [`HealRequest`]s, e.g. for the `GetTrieNodes` message of the `snap`
+//! protocol. Subtries whose root is available locally are assumed to be complete only once they
+//! are walked, so the frontier advances with every batch of delivered nodes until the trie is
+//! complete.
+
+use crate::{
+    nodes::{RlpNode, TrieNode},
+    resolver::{NodeProvider, ResolveError},
+    HashMap, Nibbles, EMPTY_ROOT_HASH,
+};
+use alloc::collections::VecDeque;
+use alloy_primitives::{keccak256, map::HashSet, Bytes, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The default number of nodes requested per batch by the [`HealPlanner`].
+pub const DEFAULT_HEAL_BATCH_SIZE: usize = 384;
+
+/// A request of a missing trie node, identified by its path and its hash.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HealRequest {
+    /// The path of the node in the trie.
+    pub path: Nibbles,
+    /// The keccak256 hash of the node.
+    pub hash: B256,
+}
+
+/// Computes the frontier of the nodes missing from a trie. See the [module documentation](self).
+///
+/// Nodes are identified by hash, so a subtrie that occurs at multiple paths is requested and
+/// walked once.
+#[derive(Debug)]
+pub struct HealPlanner<P> {
+    provider: P,
+    root: B256,
+    batch_size: usize,
+    /// The nodes left to walk, along with their paths, in breadth-first order.
+    queue: VecDeque<(Nibbles, RlpNode)>,
+    /// The hashes of the nodes that were walked.
+    visited: HashSet<B256>,
+    /// The paths of the requested nodes that were not delivered yet, keyed by their hash.
+    pending: HashMap<B256, Nibbles>,
+    /// The delivered nodes, keyed by their hash.
+    downloaded: HashMap<B256, Bytes>,
+}
+
+impl<P: NodeProvider> HealPlanner<P> {
+    /// Creates a planner healing the trie with the given root from the nodes of the provider.
+    pub fn new(provider: P, root: B256) -> Self {
+        let mut queue = VecDeque::new();
+        if root != EMPTY_ROOT_HASH {
+            queue.push_back((Nibbles::default(), RlpNode::word_rlp(&root)));
+        }
+        Self {
+            provider,
+            root,
+            batch_size: DEFAULT_HEAL_BATCH_SIZE,
+            queue,
+            visited: HashSet::default(),
+            pending: HashMap::default(),
+            downloaded: HashMap::default(),
+        }
+    }
+
+    /// Sets the maximum number of nodes requested per batch, which is at least one.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Returns the root of the trie being healed.
+    pub const fn root(&self) -> B256 {
+        self.root
+    }
+
+    /// Returns `true` if all nodes of the trie are available, either from the provider or
+    /// downloaded.
+    pub fn is_complete(&self) -> bool {
+        self.queue.is_empty() && self.pending.is_empty()
+    }
+
+    /// Returns the requests that were not answered yet, e.g. to retry them with another peer.
+    pub fn pending(&self) -> impl Iterator<Item = HealRequest> + '_ {
+        self.pending.iter().map(|(hash, path)| HealRequest { path: path.clone(), hash: *hash })
+    }
+
+    /// Returns the delivered nodes, keyed by their hash.
+    pub const fn downloaded(&self) -> &HashMap<B256, Bytes> {
+        &self.downloaded
+    }
+
+    /// Takes the delivered nodes, e.g. to persist them. Nodes that are taken are no longer
+    /// available to the planner, so they must be added to the provider.
+    pub fn take_downloaded(&mut self) -> HashMap<B256, Bytes> {
+        core::mem::take(&mut self.downloaded)
+    }
+
+    /// Walks the trie breadth-first from the current frontier, and returns the next batch of
+    /// missing nodes, in breadth-first order.
+    ///
+    /// The walk stops once the batch is full. An empty batch means that all nodes are either
+    /// available or pending, see [`Self::is_complete`] and [`Self::pending`]. Returns an error if
+    /// an available node can't be decoded.
+    pub fn next_batch(&mut self) -> Result<Vec<HealRequest>, ResolveError> {
+        let mut batch = Vec::new();
+        while batch.len() < self.batch_size {
+            let Some((path, child)) = self.queue.pop_front() else { break };
+            let node = match child.as_hash() {
+                Some(hash) => {
+                    if self.visited.contains(&hash) || self.pending.contains_key(&hash) {
+                        continue;
+                    }
+                    let Some(node) =
+                        self.downloaded.get(&hash).cloned().or_else(|| self.provider.node(hash))
+                    else {
+                        self.pending.insert(hash, path.clone());
+                        batch.push(HealRequest { path, hash });
+                        continue;
+                    };
+                    self.visited.insert(hash);
+                    node
+                }
+                None => Bytes::copy_from_slice(&child),
+            };
+
+            match TrieNode::decode_raw(&node)
+                .map_err(|error| ResolveError::Decode { path: path.clone(), error })?
+            {
+                TrieNode::Branch(branch) => {
+                    for (nibble, child) in branch.children() {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble);
+                        self.queue.push_back((child_path, child.clone()));
+                    }
+                }
+                TrieNode::Extension(extension) => {
+                    self.queue.push_back((path.join(&extension.key), extension.child));
+                }
+                TrieNode::Leaf(_) | TrieNode::EmptyRoot => {}
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Adds the delivered nodes, which are matched to the pending requests by their hash, and
+    /// returns the number of nodes that were requested. Nodes that were not requested are
+    /// ignored.
+    ///
+    /// The subtries of the delivered nodes are walked by the next calls to [`Self::next_batch`].
+    pub fn insert_nodes(&mut self, nodes: impl IntoIterator<Item = Bytes>) -> usize {
+        let mut accepted = 0;
+        for node in nodes {
+            let hash = keccak256(&node);
+            if let Some(path) = self.pending.remove(&hash) {
+                self.downloaded.insert(hash, node);
+                self.queue.push_back((path, RlpNode::word_rlp(&hash)));
+                accepted += 1;
+            }
+        }
+        accepted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{ProofNodesByHash, ProofRetainer},
+        resolver::TrieResolver,
+        HashBuilder,
+    };
+    use alloy_primitives::U256;
+
+    /// Builds a trie, returning its root and all of its nodes by hash.
+    fn trie(n: u64) -> (B256, ProofNodesByHash) {
+        let mut leaves = (0..n)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<Vec<_>>();
+        leaves.sort_unstable();
+        let retainer = ProofRetainer::new(leaves.iter().map(|(key, _)| key.clone()).collect());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_proof_nodes().into())
+    }
+
+    /// Heals the trie from the local nodes, returning the sizes of the batches.
+    fn heal(
+        root: B256,
+        local: &HashMap<B256, Bytes>,
+        remote: &ProofNodesByHash,
+    ) -> (Vec<usize>, HashMap<B256, Bytes>) {
+        let mut planner = HealPlanner::new(local, root).with_batch_size(16);
+        let mut batches = Vec::new();
+        while !planner.is_complete() {
+            let batch = planner.next_batch().unwrap();
+            assert!(batch.len() <= 16);
+            batches.push(batch.len());
+            let nodes = batch.iter().map(|request| remote.node(request.hash).unwrap());
+            assert_eq!(planner.insert_nodes(nodes), batch.len());
+        }
+        (batches, planner.take_downloaded())
+    }
+
+    #[test]
+    fn heals_from_scratch() {
+        let (root, nodes) = trie(300);
+        let (batches, downloaded) = heal(root, &HashMap::default(), &nodes);
+        // The root is requested first, followed by its children.
+        assert_eq!(batches[..2], [1, 16]);
+        assert_eq!(downloaded.len(), nodes.len());
+        assert_eq!(TrieResolver::new(&downloaded, root).leaves().count(), 300);
+    }
+
+    #[test]
+    fn heals_missing_nodes() {
+        let (root, nodes) = trie(300);
+        let mut local =
+            nodes.iter().map(|(hash, node)| (*hash, node.clone())).collect::<HashMap<_, _>>();
+        // Stale nodes of another trie are ignored.
+        let (_, other) = trie(10);
+        local.extend(other.iter().map(|(hash, node)| (*hash, node.clone())));
+        let removed = nodes.keys().step_by(7).copied().collect::<Vec<_>>();
+        for hash in &removed {
+            local.remove(hash);
+        }
+
+        let (_, downloaded) = heal(root, &local, &nodes);
+        let mut downloaded_hashes = downloaded.keys().copied().collect::<Vec<_>>();
+        let mut removed = removed;
+        downloaded_hashes.sort_unstable();
+        removed.sort_unstable();
+        assert_eq!(downloaded_hashes, removed);
+
+        local.extend(downloaded);
+        let leaves = TrieResolver::new(&local, root).leaves().collect::<Result<Vec<_>, _>>();
+        assert_eq!(leaves.map(|leaves| leaves.len()), Ok(300));
+
+        let mut planner = HealPlanner::new(&local, EMPTY_ROOT_HASH);
+        assert!(planner.is_complete());
+        assert_eq!(planner.next_batch(), Ok(vec![]));
+    }
+
+    #[test]
+    fn pending_and_unrequested() {
+        let (root, nodes) = trie(50);
+        let mut planner = HealPlanner::new(HashMap::<B256, Bytes>::default(), root);
+        let batch = planner.next_batch().unwrap();
+        assert_eq!(batch, vec![HealRequest { path: Nibbles::default(), hash: root }]);
+        // The root is pending until it's delivered.
+        assert_eq!(planner.next_batch(), Ok(vec![]));
+        assert_eq!(planner.pending().collect::<Vec<_>>(), batch);
+        assert!(!planner.is_complete());
+
+        let unrequested = nodes.iter().find(|(hash, _)| **hash != root).unwrap().1.clone();
+        assert_eq!(planner.insert_nodes([unrequested]), 0);
+        assert_eq!(planner.insert_nodes([nodes.node(root).unwrap()]), 1);
+        let children = planner.next_batch().unwrap();
+        assert!(!children.is_empty());
+        assert!(children.iter().all(|request| request.path.len() == 1));
+    }
+}
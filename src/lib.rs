@@ -23,13 +23,86 @@ extern crate alloc;
 pub mod nodes;
 pub use nodes::BranchNodeCompact;
 
+pub mod nibbles;
+
+mod hasher;
+#[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+pub use hasher::set_keccak256;
+pub use hasher::{
+    HashedValues, IdentityKeyHasher, InlineThreshold, KeccakHasher, KeccakKeyHasher, KeyHasher,
+    TrieHasher,
+};
+
 pub mod hash_builder;
 pub use hash_builder::HashBuilder;
 
 pub mod proof;
 
+pub mod sparse;
+
+pub mod cursor;
+
+pub mod prefix_set;
+
+pub mod walker;
+
+pub mod witness;
+
+pub mod resolver;
+
+pub mod heal;
+
+pub mod diff;
+
+pub mod updates;
+pub use updates::{StorageTrieUpdates, TrieUpdates};
+
+pub mod hashed_state;
+pub use hashed_state::{
+    HashedPostState, HashedPostStateSorted, HashedStorage, HashedStorageSorted,
+};
+
+pub mod stats;
+
+pub mod commitment;
+pub use commitment::{EthereumStateCommitment, MerklePatriciaTrie, StateCommitment};
+
+pub mod viz;
+
+#[cfg(feature = "zktrie")]
+pub mod zktrie;
+
+#[cfg(feature = "binary-trie")]
+pub mod binary_trie;
+
+#[cfg(feature = "reference")]
+pub mod reference;
+
+#[cfg(feature = "arbitrary")]
+pub mod strategies;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
+mod account;
+pub use account::TrieAccount;
+
+mod value;
+pub use value::{TrieValue, TypedEnvelope};
+
 mod mask;
-pub use mask::TrieMask;
+#[cfg(feature = "serde")]
+pub use mask::trie_mask_indices;
+pub use mask::{TrieMask, TrieMaskIter};
+
+#[cfg(feature = "serde")]
+mod serde_helpers;
+
+#[cfg(feature = "ssz")]
+pub mod ssz;
+
+#[cfg(feature = "portal")]
+pub mod portal;
 
 #[allow(missing_docs)]
 pub mod root;
@@ -44,6 +117,13 @@ pub use nybbles::{self, Nibbles};
 pub const EMPTY_ROOT_HASH: alloy_primitives::B256 =
     alloy_primitives::b256!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
 
+/// RLP encoding of the root node of an empty trie, which is the empty string.
+pub const EMPTY_ROOT_RLP: [u8; 1] = [alloy_rlp::EMPTY_STRING_CODE];
+
+/// Keccak256 hash of empty input, which is the code hash of accounts without code.
+pub const KECCAK_EMPTY: alloy_primitives::B256 =
+    alloy_primitives::b256!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+
 #[cfg(test)]
 pub(crate) fn triehash_trie_root<I, K, V>(iter: I) -> alloy_primitives::B256
 where
@@ -1,5 +1,8 @@
-use core::fmt;
-use derive_more::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Deref, From, Not};
+use arrayvec::ArrayVec;
+use core::{fmt, iter::FusedIterator, ops};
+use derive_more::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Deref, From, Not,
+};
 
 /// A struct representing a mask of 16 bits, used for Ethereum trie operations.
 ///
@@ -7,6 +10,10 @@ use derive_more::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Deref, From, Not};
 /// absence of certain elements, such as child nodes, within a trie. Masks are usually implemented
 /// as bit vectors, where each bit represents the presence (1) or absence (0) of a corresponding
 /// element.
+///
+/// With the `serde` feature, masks are serialized as hex strings such as `"0x0a05"` in
+/// human-readable formats, and as `u16` otherwise. The `trie_mask_indices` module serializes them
+/// as arrays of set indices instead. Human-readable deserialization accepts all of these forms.
 #[derive(
     Default,
     Clone,
@@ -21,9 +28,10 @@ use derive_more::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Deref, From, Not};
     BitAndAssign,
     BitOr,
     BitOrAssign,
+    BitXor,
+    BitXorAssign,
     Not,
 )]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "arbitrary", derive(derive_arbitrary::Arbitrary, proptest_derive::Arbitrary))]
 pub struct TrieMask(u16);
 
@@ -76,9 +84,21 @@ impl TrieMask {
         self.0.count_ones() as u8
     }
 
+    /// Returns the number of bits set in the mask.
+    #[inline]
+    pub const fn count_ones(self) -> u32 {
+        self.0.count_ones()
+    }
+
     /// Returns the index of the first bit set in the mask, or `None` if the mask is empty.
     #[inline]
     pub const fn first_set_bit_index(self) -> Option<u8> {
+        self.first_set()
+    }
+
+    /// Returns the index of the lowest bit set in the mask, or `None` if the mask is empty.
+    #[inline]
+    pub const fn first_set(self) -> Option<u8> {
         if self.is_empty() {
             None
         } else {
@@ -86,6 +106,46 @@ impl TrieMask {
         }
     }
 
+    /// Returns the index of the highest bit set in the mask, or `None` if the mask is empty.
+    #[inline]
+    pub const fn last_set(self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(15 - self.0.leading_zeros() as u8)
+        }
+    }
+
+    /// Returns an iterator over the indices of the bits set in the mask, in ascending order.
+    #[inline]
+    pub const fn iter_set_bits(self) -> TrieMaskIter {
+        TrieMaskIter(self.0)
+    }
+
+    /// Returns the indices of the bits set in the mask, in ascending order.
+    #[inline]
+    pub fn indices(self) -> ArrayVec<u8, 16> {
+        self.iter_set_bits().collect()
+    }
+
+    /// Returns the bits set in either mask.
+    #[inline]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the bits set in both masks.
+    #[inline]
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the bits set in this mask, but not in `other`.
+    #[inline]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
     /// Set bit at a specified index.
     #[inline]
     pub fn set_bit(&mut self, index: u8) {
@@ -98,3 +158,241 @@ impl TrieMask {
         self.0 &= !(1u16 << index);
     }
 }
+
+impl ops::Sub for TrieMask {
+    type Output = Self;
+
+    /// Returns the difference of the masks, see [`TrieMask::difference`].
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.difference(rhs)
+    }
+}
+
+impl ops::SubAssign for TrieMask {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = self.difference(rhs);
+    }
+}
+
+impl IntoIterator for TrieMask {
+    type Item = u8;
+    type IntoIter = TrieMaskIter;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_set_bits()
+    }
+}
+
+impl FromIterator<u8> for TrieMask {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut mask = Self::default();
+        for index in iter {
+            mask.set_bit(index);
+        }
+        mask
+    }
+}
+
+/// An iterator over the indices of the bits set in a [`TrieMask`], see
+/// [`TrieMask::iter_set_bits`].
+#[derive(Clone, Copy, Debug)]
+pub struct TrieMaskIter(u16);
+
+impl Iterator for TrieMaskIter {
+    type Item = u8;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = TrieMask(self.0).first_set()?;
+        self.0 &= self.0 - 1;
+        Some(index)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for TrieMaskIter {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = TrieMask(self.0).last_set()?;
+        self.0 &= !(1 << index);
+        Some(index)
+    }
+}
+
+impl ExactSizeIterator for TrieMaskIter {
+    #[inline]
+    fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
+
+impl FusedIterator for TrieMaskIter {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TrieMask {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&format_args!("{:#06x}", self.0))
+        } else {
+            serializer.serialize_u16(self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TrieMask {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TrieMaskVisitor)
+        } else {
+            deserializer.deserialize_u16(TrieMaskVisitor)
+        }
+    }
+}
+
+/// Deserializes a [`TrieMask`] from a `u16`, a hex string or a sequence of set indices.
+#[cfg(feature = "serde")]
+struct TrieMaskVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for TrieMaskVisitor {
+    type Value = TrieMask;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a trie mask as a u16, a hex string or an array of indices")
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        u16::try_from(value)
+            .map(TrieMask)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let digits = value.strip_prefix("0x").unwrap_or(value);
+        if digits.is_empty() || digits.len() > 4 {
+            return Err(E::invalid_value(serde::de::Unexpected::Str(value), &self));
+        }
+        u16::from_str_radix(digits, 16)
+            .map(TrieMask)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut mask = TrieMask::default();
+        while let Some(index) = seq.next_element::<u8>()? {
+            if index >= 16 || mask.is_bit_set(index) {
+                return Err(serde::de::Error::invalid_value(
+                    serde::de::Unexpected::Unsigned(index.into()),
+                    &"a unique index below 16",
+                ));
+            }
+            mask.set_bit(index);
+        }
+        Ok(mask)
+    }
+}
+
+/// Serializes a [`TrieMask`] as an array of the indices of its set bits, in ascending order, e.g.
+/// `[0, 2, 9, 11]` for `0x0a05`.
+///
+/// Use with `#[serde(with = "alloy_trie::trie_mask_indices")]`.
+#[cfg(feature = "serde")]
+pub mod trie_mask_indices {
+    use super::{TrieMask, TrieMaskVisitor};
+    use serde::{Deserializer, Serializer};
+
+    /// Serializes the mask as an array of set indices.
+    pub fn serialize<S: Serializer>(mask: &TrieMask, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(mask.iter_set_bits())
+    }
+
+    /// Deserializes the mask from an array of set indices, or in human-readable formats from any
+    /// of the forms accepted by [`TrieMask`].
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TrieMask, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TrieMaskVisitor)
+        } else {
+            deserializer.deserialize_seq(TrieMaskVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn iter_set_bits() {
+        let mask = TrieMask::new(0b1000_0000_0010_0101);
+        assert_eq!(mask.iter_set_bits().collect::<Vec<_>>(), [0, 2, 5, 15]);
+        assert_eq!(mask.iter_set_bits().rev().collect::<Vec<_>>(), [15, 5, 2, 0]);
+        assert_eq!(mask.iter_set_bits().len(), 4);
+        assert_eq!(mask.count_ones(), 4);
+        assert_eq!(mask.indices().as_slice(), [0, 2, 5, 15]);
+        assert_eq!(mask.into_iter().collect::<TrieMask>(), mask);
+        assert_eq!((mask.first_set(), mask.last_set()), (Some(0), Some(15)));
+
+        let empty = TrieMask::default();
+        assert_eq!(empty.iter_set_bits().next(), None);
+        assert_eq!((empty.first_set(), empty.last_set()), (None, None));
+        assert_eq!(TrieMask::new(u16::MAX).indices().len(), 16);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = TrieMask::new(0b1100);
+        let b = TrieMask::new(0b1010);
+        assert_eq!(a.union(b), a | b);
+        assert_eq!(a.union(b), TrieMask::new(0b1110));
+        assert_eq!(a.intersection(b), a & b);
+        assert_eq!(a.intersection(b), TrieMask::new(0b1000));
+        assert_eq!(a.difference(b), a - b);
+        assert_eq!(a.difference(b), TrieMask::new(0b0100));
+        assert_eq!(a ^ b, TrieMask::new(0b0110));
+
+        let mut c = a;
+        c -= b;
+        assert_eq!(c, TrieMask::new(0b0100));
+        assert!(c.is_subset_of(a));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Masks {
+            hex: TrieMask,
+            #[serde(with = "crate::trie_mask_indices")]
+            indices: TrieMask,
+        }
+
+        let mask = TrieMask::new(0x0a05);
+        let masks = Masks { hex: mask, indices: mask };
+        let json = serde_json::to_string(&masks).unwrap();
+        assert_eq!(json, r#"{"hex":"0x0a05","indices":[0,2,9,11]}"#);
+        assert_eq!(serde_json::from_str::<Masks>(&json).unwrap(), masks);
+
+        // Human-readable formats accept all forms, including the raw `u16` of older versions.
+        for json in [r#""0x0a05""#, r#""a05""#, "[0, 2, 9, 11]", "2565"] {
+            assert_eq!(serde_json::from_str::<TrieMask>(json).unwrap(), mask);
+        }
+        for json in [r#""0x""#, r#""0x10000""#, r#""0xzz""#, "[16]", "[1, 1]", "65536"] {
+            assert!(serde_json::from_str::<TrieMask>(json).is_err(), "{json}");
+        }
+        assert_eq!(serde_json::to_string(&TrieMask::default()).unwrap(), r#""0x0000""#);
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&masks, &mut cbor).unwrap();
+        assert_eq!(ciborium::from_reader::<Masks, _>(cbor.as_slice()).unwrap(), masks);
+    }
+}
@@ -0,0 +1,806 @@
+//! Nibble utilities: accelerated packing and unpacking, key arithmetic, and grouping of sorted keys
+//! by prefix.
+//!
+//! [`unpack`] and [`pack`] produce the same results as [`Nibbles::unpack`] and [`Nibbles::pack`],
+//! but process 16 bytes at a time with SSE2 on x86 and NEON on AArch64 when the CPU supports it,
+//! falling back to the scalar implementation otherwise. This matters when hashing large numbers
+//! of keys, where converting between bytes and nibbles shows up in profiles.
+//!
+//! Packing benefits the most, as the scalar implementation is not vectorized by the compiler.
+//! Unpacking of inputs up to 32 bytes, such as hashes, is left to [`Nibbles::unpack`]. The crate
+//! uses them to pack the keys of the [`TrieWalker`](crate::walker::TrieWalker) and the binary
+//! trie, and to unpack the keys of the [`IdentityKeyHasher`](crate::IdentityKeyHasher).
+//!
+//! [`Nibbles`] already stores up to 64 nibbles inline, which covers every path in tries keyed by
+//! hashes, so handling paths in the [`HashBuilder`](crate::HashBuilder) and when walking proofs
+//! does not allocate. Only longer paths, such as unhashed keys of more than 32 bytes, spill to
+//! the heap.
+
+use crate::{
+    nodes::{decode_path, PathDecodeError},
+    Nibbles,
+};
+use core::{
+    fmt,
+    ops::{Bound, RangeBounds},
+};
+use smallvec::SmallVec;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Unpacks the bytes into nibbles. Equivalent to [`Nibbles::unpack`].
+pub fn unpack<T: AsRef<[u8]>>(data: T) -> Nibbles {
+    let data = data.as_ref();
+    // The compiler already vectorizes the fixed-size unpacking of hashes well, so only larger
+    // inputs take the SIMD path.
+    if data.len() <= 32 {
+        return Nibbles::unpack(data);
+    }
+
+    let len = data.len() * 2;
+    let mut nibbles = Vec::with_capacity(len);
+    // SAFETY: the capacity is twice the input length, and all nibbles are written before the
+    // length is set.
+    unsafe {
+        unpack_to_unchecked(data, nibbles.as_mut_ptr());
+        nibbles.set_len(len);
+    }
+    Nibbles::from_vec_unchecked(nibbles)
+}
+
+/// Unpacks the bytes into nibbles, writing them to `out`.
+///
+/// # Panics
+///
+/// If `out` is not exactly twice as long as `data`.
+pub fn unpack_to(data: &[u8], out: &mut [u8]) {
+    assert_eq!(out.len(), data.len() * 2, "output length must be twice the input length");
+    // SAFETY: checked length.
+    unsafe { unpack_to_unchecked(data, out.as_mut_ptr()) }
+}
+
+/// Unpacks the bytes into nibbles, writing them to `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for writes of twice the input length.
+unsafe fn unpack_to_unchecked(data: &[u8], ptr: *mut u8) {
+    let simd_len = if simd::is_available() {
+        let simd_len = data.len() / 16 * 16;
+        simd::unpack_chunks(&data[..simd_len], ptr);
+        simd_len
+    } else {
+        0
+    };
+    for (i, byte) in data.iter().enumerate().skip(simd_len) {
+        ptr.add(i * 2).write(byte >> 4);
+        ptr.add(i * 2 + 1).write(byte & 0x0f);
+    }
+}
+
+/// Packs the nibbles into bytes. Equivalent to [`Nibbles::pack`].
+///
+/// If the number of nibbles is odd, the last nibble is placed in the high half of the last byte.
+pub fn pack(nibbles: &Nibbles) -> SmallVec<[u8; 32]> {
+    let mut packed = SmallVec::from_elem(0, nibbles.len().div_ceil(2));
+    pack_to(nibbles, &mut packed);
+    packed
+}
+
+/// Packs the nibbles into bytes, writing them to `out`. See [`pack`].
+///
+/// # Panics
+///
+/// If `out` is shorter than half the number of nibbles, rounded up.
+pub fn pack_to(nibbles: &[u8], out: &mut [u8]) {
+    assert!(out.len() >= nibbles.len().div_ceil(2), "output too short");
+    let simd_len = if simd::is_available() {
+        let chunks = nibbles.len() / 32;
+        // SAFETY: SIMD support was detected above, and the output is at least half as long as the
+        // input.
+        unsafe { simd::pack_chunks(&nibbles[..chunks * 32], &mut out[..chunks * 16]) };
+        chunks * 32
+    } else {
+        0
+    };
+    let mut pairs = nibbles[simd_len..].chunks(2);
+    for (byte, pair) in out[simd_len / 2..].iter_mut().zip(&mut pairs) {
+        *byte = (pair[0] << 4) | pair.get(1).copied().unwrap_or_default();
+    }
+}
+
+/// The number of bytes of packed nibbles in each group of a
+/// [packed key](NibblesExt::to_packed_key).
+const PACKED_KEY_GROUP_LEN: usize = 8;
+
+/// The marker of a [packed key](NibblesExt::to_packed_key) group that is followed by more groups.
+const PACKED_KEY_CONTINUATION: u8 = PACKED_KEY_GROUP_LEN as u8 * 2 + 1;
+
+/// Error during decoding of a [packed key](NibblesExt::to_packed_key).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PackedKeyDecodeError {
+    /// The length of the key is not a multiple of the group length.
+    InvalidLength {
+        /// Length of the key.
+        len: usize,
+    },
+    /// The marker of a group is not the continuation marker in a group followed by more groups,
+    /// or not a number of nibbles in the last group.
+    InvalidMarker {
+        /// Byte offset of the marker in the key.
+        offset: usize,
+        /// The marker byte.
+        marker: u8,
+    },
+    /// The padding after the nibbles of the last group is not zero.
+    NonZeroPadding {
+        /// Byte offset of the group in the key.
+        offset: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for PackedKeyDecodeError {}
+
+impl fmt::Display for PackedKeyDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { len } => write!(f, "invalid packed key length {len}"),
+            Self::InvalidMarker { offset, marker } => {
+                write!(f, "invalid packed key group marker {marker} at offset {offset}")
+            }
+            Self::NonZeroPadding { offset } => {
+                write!(f, "non-zero packed key padding in group at offset {offset}")
+            }
+        }
+    }
+}
+
+/// Extension methods for [`Nibbles`].
+pub trait NibblesExt {
+    /// Decrements the nibble sequence by one, returning the lexicographically previous sequence
+    /// of the same length, or [`None`] if all nibbles are zero.
+    ///
+    /// This is the inverse of [`Nibbles::increment`].
+    fn decrement(&self) -> Option<Nibbles>;
+
+    /// Decodes a hex-prefix encoded path, returning its nibbles and whether it's the path of a
+    /// leaf node. This is the inverse of [`Nibbles::encode_path_leaf`].
+    ///
+    /// See [`decode_path`].
+    fn decode_path(encoded: &[u8]) -> Result<(Nibbles, bool), PathDecodeError>
+    where
+        Self: Sized;
+
+    /// Encodes the nibbles as a database key whose byte order is the order of the nibbles.
+    ///
+    /// Unlike [`Nibbles::pack`], the encoding distinguishes paths that differ only in trailing
+    /// zero nibbles, and a path sorts before all the paths it's a prefix of, so it can be used as
+    /// the key of sorted tables of trie nodes.
+    ///
+    /// The nibbles are packed in groups of 16, i.e. 8 bytes, and the last group is padded with
+    /// zeros. Each group is followed by a marker byte: 17 if more groups follow, otherwise the
+    /// number of nibbles in the group. The empty path is encoded as no bytes, and a path of 64
+    /// nibbles as 36 bytes.
+    fn to_packed_key(&self) -> Vec<u8>;
+
+    /// Decodes nibbles from a database key. This is the inverse of [`NibblesExt::to_packed_key`].
+    fn from_packed_key(key: &[u8]) -> Result<Nibbles, PackedKeyDecodeError>
+    where
+        Self: Sized;
+}
+
+impl NibblesExt for Nibbles {
+    fn decode_path(encoded: &[u8]) -> Result<(Nibbles, bool), PathDecodeError> {
+        decode_path(encoded)
+    }
+
+    fn to_packed_key(&self) -> Vec<u8> {
+        let groups = self.len().div_ceil(PACKED_KEY_GROUP_LEN * 2);
+        let mut key = Vec::with_capacity(groups * (PACKED_KEY_GROUP_LEN + 1));
+        for (i, nibbles) in self.chunks(PACKED_KEY_GROUP_LEN * 2).enumerate() {
+            let start = key.len();
+            key.resize(start + PACKED_KEY_GROUP_LEN, 0);
+            pack_to(nibbles, &mut key[start..]);
+            key.push(if i + 1 < groups { PACKED_KEY_CONTINUATION } else { nibbles.len() as u8 });
+        }
+        key
+    }
+
+    fn from_packed_key(key: &[u8]) -> Result<Nibbles, PackedKeyDecodeError> {
+        if key.len() % (PACKED_KEY_GROUP_LEN + 1) != 0 {
+            return Err(PackedKeyDecodeError::InvalidLength { len: key.len() });
+        }
+
+        let groups = key.len() / (PACKED_KEY_GROUP_LEN + 1);
+        let mut nibbles = Vec::with_capacity(groups * PACKED_KEY_GROUP_LEN * 2);
+        for (i, group) in key.chunks_exact(PACKED_KEY_GROUP_LEN + 1).enumerate() {
+            let offset = i * (PACKED_KEY_GROUP_LEN + 1);
+            let marker = group[PACKED_KEY_GROUP_LEN];
+            let len = match marker {
+                PACKED_KEY_CONTINUATION if i + 1 < groups => PACKED_KEY_GROUP_LEN * 2,
+                1..=16 if i + 1 == groups => marker as usize,
+                _ => {
+                    return Err(PackedKeyDecodeError::InvalidMarker {
+                        offset: offset + PACKED_KEY_GROUP_LEN,
+                        marker,
+                    })
+                }
+            };
+            let group = unpack(&group[..PACKED_KEY_GROUP_LEN]);
+            if group[len..].iter().any(|nibble| *nibble != 0) {
+                return Err(PackedKeyDecodeError::NonZeroPadding { offset });
+            }
+            nibbles.extend_from_slice(&group[..len]);
+        }
+        Ok(Nibbles::from_vec_unchecked(nibbles))
+    }
+
+    fn decrement(&self) -> Option<Nibbles> {
+        let mut decremented = self.clone();
+        for nibble in decremented.as_mut_slice_unchecked().iter_mut().rev() {
+            if *nibble > 0 {
+                *nibble -= 1;
+                return Some(decremented);
+            }
+            *nibble = 0xf;
+        }
+        None
+    }
+}
+
+/// A view of the nibbles of a packed byte slice, two nibbles per byte with the high nibble first,
+/// that reads them in place instead of unpacking them into a [`Nibbles`].
+///
+/// Views can be sliced at nibble granularity, e.g. to skip the flag nibbles of a hex-prefix
+/// encoded path.
+#[derive(Clone, Copy)]
+pub struct PackedNibbles<'a> {
+    data: &'a [u8],
+    /// The index of the first nibble of the view in `data`.
+    start: usize,
+    len: usize,
+}
+
+impl<'a> PackedNibbles<'a> {
+    /// Creates a view of all nibbles of the packed bytes.
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data, start: 0, len: data.len() * 2 }
+    }
+
+    /// Returns the number of nibbles.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the view has no nibbles.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the nibble at the given index.
+    ///
+    /// # Panics
+    ///
+    /// If the index is out of bounds.
+    #[inline]
+    pub const fn at(&self, index: usize) -> u8 {
+        assert!(index < self.len, "nibble index out of bounds");
+        let index = self.start + index;
+        let byte = self.data[index / 2];
+        if index % 2 == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0f
+        }
+    }
+
+    /// Returns the nibble at the given index, or [`None`] if it's out of bounds.
+    pub const fn get(&self, index: usize) -> Option<u8> {
+        if index < self.len {
+            Some(self.at(index))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a view of the nibbles in the given range.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+        assert!(start <= end && end <= self.len, "nibble range out of bounds");
+        Self { data: self.data, start: self.start + start, len: end - start }
+    }
+
+    /// Returns the number of leading nibbles shared with the other view.
+    pub fn common_prefix_length(&self, other: &PackedNibbles<'_>) -> usize {
+        self.iter().zip(other.iter()).take_while(|(a, b)| a == b).count()
+    }
+
+    /// Returns `true` if the view starts with the nibbles of the other view.
+    pub fn starts_with(&self, prefix: &PackedNibbles<'_>) -> bool {
+        prefix.len <= self.len && self.common_prefix_length(prefix) == prefix.len
+    }
+
+    /// Returns an iterator over the nibbles.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = u8> + '_ {
+        (0..self.len).map(|index| self.at(index))
+    }
+
+    /// Unpacks the nibbles into a [`Nibbles`].
+    pub fn to_nibbles(&self) -> Nibbles {
+        Nibbles::from_nibbles_unchecked(self.iter().collect::<SmallVec<[u8; 64]>>())
+    }
+}
+
+impl PartialEq for PackedNibbles<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.starts_with(other)
+    }
+}
+
+impl Eq for PackedNibbles<'_> {}
+
+impl PartialEq<Nibbles> for PackedNibbles<'_> {
+    fn eq(&self, other: &Nibbles) -> bool {
+        self.len == other.len() && self.iter().eq(other.iter().copied())
+    }
+}
+
+impl fmt::Debug for PackedNibbles<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PackedNibbles(0x")?;
+        for nibble in self.iter() {
+            write!(f, "{nibble:x}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Groups sorted leaves by the first `depth` nibbles of their keys. See [`PrefixGroups`].
+///
+/// With a depth of 1, the groups are the leaves of the subtries under the root branch node, which
+/// can be built independently, e.g. with
+/// [`HashBuilder::into_subtrie`](crate::HashBuilder::into_subtrie).
+pub fn group_by_prefix<I, V>(leaves: I, depth: usize) -> PrefixGroups<I::IntoIter>
+where
+    I: IntoIterator<Item = (Nibbles, V)>,
+{
+    PrefixGroups { leaves: leaves.into_iter().peekable(), depth, prefix: None }
+}
+
+/// An adapter over leaves sorted by key that groups them by the prefixes of their keys, returned
+/// by [`group_by_prefix`].
+///
+/// Groups are never empty: prefixes without leaves are skipped, and the prefix of a key shorter
+/// than the depth is the whole key. Each group is either streamed with [`Self::next_group`], or
+/// collected by the [`Iterator`] implementation, e.g. to be sent to another thread.
+pub struct PrefixGroups<I: Iterator> {
+    leaves: core::iter::Peekable<I>,
+    depth: usize,
+    /// The prefix of the last returned group.
+    prefix: Option<Nibbles>,
+}
+
+impl<I, V> PrefixGroups<I>
+where
+    I: Iterator<Item = (Nibbles, V)>,
+{
+    /// Returns an iterator over the leaves of the next group, or [`None`] if there are no more
+    /// leaves.
+    ///
+    /// The leaves of the previous group that were not consumed are skipped.
+    pub fn next_group(&mut self) -> Option<PrefixGroup<'_, I>> {
+        let depth = self.depth;
+        if let Some(prefix) = self.prefix.take() {
+            while self.leaves.next_if(|(key, _)| depth_prefix(key, depth) == &prefix[..]).is_some()
+            {
+            }
+        }
+        let (key, _) = self.leaves.peek()?;
+        let prefix = Nibbles::from_nibbles_unchecked(depth_prefix(key, depth));
+        self.prefix = Some(prefix.clone());
+        Some(PrefixGroup { groups: self, prefix })
+    }
+}
+
+impl<I: Iterator> fmt::Debug for PrefixGroups<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrefixGroups")
+            .field("depth", &self.depth)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I, V> Iterator for PrefixGroups<I>
+where
+    I: Iterator<Item = (Nibbles, V)>,
+{
+    type Item = (Nibbles, Vec<(Nibbles, V)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut group = self.next_group()?;
+        let prefix = group.prefix().clone();
+        Some((prefix, group.by_ref().collect()))
+    }
+}
+
+/// Returns the first `depth` nibbles of the key, or the whole key if it's shorter.
+fn depth_prefix(key: &Nibbles, depth: usize) -> &[u8] {
+    &key[..depth.min(key.len())]
+}
+
+/// An iterator over the leaves of a group of [`PrefixGroups`], whose keys start with
+/// [`Self::prefix`].
+#[derive(Debug)]
+pub struct PrefixGroup<'a, I: Iterator> {
+    groups: &'a mut PrefixGroups<I>,
+    prefix: Nibbles,
+}
+
+impl<I: Iterator> PrefixGroup<'_, I> {
+    /// Returns the prefix shared by the keys of the group.
+    pub const fn prefix(&self) -> &Nibbles {
+        &self.prefix
+    }
+}
+
+impl<I, V> Iterator for PrefixGroup<'_, I>
+where
+    I: Iterator<Item = (Nibbles, V)>,
+{
+    type Item = (Nibbles, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Self { groups, prefix } = self;
+        let depth = groups.depth;
+        groups.leaves.next_if(|(key, _)| depth_prefix(key, depth) == &prefix[..])
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod simd {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    /// Returns `true` if SSE2 is available.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(super) fn is_available() -> bool {
+        cfg!(target_feature = "sse2") || std::is_x86_feature_detected!("sse2")
+    }
+
+    /// Returns `true` if SSE2 is enabled at compile time, as runtime detection requires `std`.
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    pub(super) const fn is_available() -> bool {
+        cfg!(target_feature = "sse2")
+    }
+
+    /// Unpacks 16-byte chunks of `data` into `out`.
+    ///
+    /// # Safety
+    ///
+    /// SSE2 must be available, `data.len()` must be a multiple of 16 and `out` must be valid for
+    /// writes of twice the length of `data`.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn unpack_chunks(data: &[u8], out: *mut u8) {
+        let low_mask = _mm_set1_epi8(0x0f);
+        for (i, chunk) in data.chunks_exact(16).enumerate() {
+            let bytes = _mm_loadu_si128(chunk.as_ptr().cast());
+            let high = _mm_and_si128(_mm_srli_epi16(bytes, 4), low_mask);
+            let low = _mm_and_si128(bytes, low_mask);
+            let out = out.add(i * 32);
+            _mm_storeu_si128(out.cast(), _mm_unpacklo_epi8(high, low));
+            _mm_storeu_si128(out.add(16).cast(), _mm_unpackhi_epi8(high, low));
+        }
+    }
+
+    /// Packs 32-nibble chunks of `nibbles` into `out`.
+    ///
+    /// # Safety
+    ///
+    /// SSE2 must be available, `nibbles.len()` must be a multiple of 32 and `out` must be half as
+    /// long as `nibbles`.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn pack_chunks(nibbles: &[u8], out: &mut [u8]) {
+        let low_mask = _mm_set1_epi16(0x00ff);
+        for (chunk, out) in nibbles.chunks_exact(32).zip(out.chunks_exact_mut(16)) {
+            // Each 16-bit lane holds a pair of nibbles, the first one in the low byte.
+            let pack_pairs = |pairs: __m128i| {
+                let high = _mm_and_si128(_mm_slli_epi16(pairs, 4), low_mask);
+                _mm_or_si128(high, _mm_srli_epi16(pairs, 8))
+            };
+            let first = pack_pairs(_mm_loadu_si128(chunk.as_ptr().cast()));
+            let second = pack_pairs(_mm_loadu_si128(chunk.as_ptr().add(16).cast()));
+            _mm_storeu_si128(out.as_mut_ptr().cast(), _mm_packus_epi16(first, second));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod simd {
+    use core::arch::aarch64::*;
+
+    /// Returns `true` if NEON is available.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(super) fn is_available() -> bool {
+        cfg!(target_feature = "neon") || std::arch::is_aarch64_feature_detected!("neon")
+    }
+
+    /// Returns `true` if NEON is enabled at compile time, as runtime detection requires `std`.
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    pub(super) const fn is_available() -> bool {
+        cfg!(target_feature = "neon")
+    }
+
+    /// Unpacks 16-byte chunks of `data` into `out`.
+    ///
+    /// # Safety
+    ///
+    /// NEON must be available, `data.len()` must be a multiple of 16 and `out` must be valid for
+    /// writes of twice the length of `data`.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn unpack_chunks(data: &[u8], out: *mut u8) {
+        for (i, chunk) in data.chunks_exact(16).enumerate() {
+            let bytes = vld1q_u8(chunk.as_ptr());
+            let high = vshrq_n_u8::<4>(bytes);
+            let low = vandq_u8(bytes, vdupq_n_u8(0x0f));
+            let out = out.add(i * 32);
+            vst1q_u8(out, vzip1q_u8(high, low));
+            vst1q_u8(out.add(16), vzip2q_u8(high, low));
+        }
+    }
+
+    /// Packs 32-nibble chunks of `nibbles` into `out`.
+    ///
+    /// # Safety
+    ///
+    /// NEON must be available, `nibbles.len()` must be a multiple of 32 and `out` must be half as
+    /// long as `nibbles`.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn pack_chunks(nibbles: &[u8], out: &mut [u8]) {
+        for (chunk, out) in nibbles.chunks_exact(32).zip(out.chunks_exact_mut(16)) {
+            // Deinterleaves the high and low nibbles of each byte.
+            let pairs = vld2q_u8(chunk.as_ptr());
+            vst1q_u8(out.as_mut_ptr(), vorrq_u8(vshlq_n_u8::<4>(pairs.0), pairs.1));
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+mod simd {
+    #[inline]
+    pub(super) const fn is_available() -> bool {
+        false
+    }
+
+    pub(super) unsafe fn unpack_chunks(_data: &[u8], _out: *mut u8) {
+        unreachable!()
+    }
+
+    pub(super) unsafe fn pack_chunks(_nibbles: &[u8], _out: &mut [u8]) {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{hex, keccak256};
+
+    #[test]
+    fn matches_scalar() {
+        let data = (0..8u8).flat_map(|i| keccak256([i]).0).collect::<Vec<_>>();
+        for len in 0..data.len() {
+            let data = &data[..len];
+            let nibbles = unpack(data);
+            assert_eq!(nibbles, Nibbles::unpack(data), "{len}");
+            assert_eq!(pack(&nibbles), nibbles.pack(), "{len}");
+
+            // Odd number of nibbles.
+            let nibbles = nibbles.slice(..len.saturating_sub(1) * 2 + len.min(1));
+            assert_eq!(pack(&nibbles), nibbles.pack(), "{len}");
+        }
+    }
+
+    #[test]
+    fn decrement() {
+        let nibbles = |nibbles: &[u8]| Nibbles::from_nibbles(nibbles);
+        assert_eq!(nibbles(&[0x1, 0x0]).decrement(), Some(nibbles(&[0x0, 0xf])));
+        assert_eq!(nibbles(&[0xf, 0xf]).decrement(), Some(nibbles(&[0xf, 0xe])));
+        assert_eq!(nibbles(&[0x0, 0x0]).decrement(), None);
+        assert_eq!(Nibbles::default().decrement(), None);
+
+        let key = Nibbles::unpack(keccak256([1]));
+        assert_eq!(key.increment().unwrap().decrement(), Some(key.clone()));
+        assert_eq!(key.decrement().unwrap().increment(), Some(key));
+    }
+
+    #[test]
+    fn packed_key() {
+        let nibbles = |nibbles: &[u8]| Nibbles::from_nibbles(nibbles);
+        assert!(Nibbles::default().to_packed_key().is_empty());
+        assert_eq!(nibbles(&[0x1]).to_packed_key(), hex!("100000000000000001"));
+        assert_eq!(nibbles(&[0x1, 0x0]).to_packed_key(), hex!("100000000000000002"));
+        assert_eq!(Nibbles::unpack(keccak256([1])).to_packed_key().len(), 36);
+
+        // Paths of all lengths up to 40 nibbles with a few nibble values.
+        let mut paths = (0..40usize)
+            .flat_map(|len| {
+                [0x0, 0x1, 0xf]
+                    .into_iter()
+                    .map(move |nibble| Nibbles::from_nibbles(vec![nibble; len]))
+                    .chain((len > 0).then(|| {
+                        let mut path = Nibbles::from_nibbles(vec![0xf; len]);
+                        path.set_at(len - 1, 0);
+                        path
+                    }))
+            })
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths.dedup();
+        let keys = paths.iter().map(NibblesExt::to_packed_key).collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        for (path, key) in paths.iter().zip(&keys) {
+            assert_eq!(Nibbles::from_packed_key(key).as_ref(), Ok(path));
+        }
+    }
+
+    #[test]
+    fn packed_key_errors() {
+        let key = Nibbles::from_nibbles([0x1; 20]).to_packed_key();
+        assert_eq!(key.len(), 18);
+        assert_eq!(
+            Nibbles::from_packed_key(&key[..17]),
+            Err(PackedKeyDecodeError::InvalidLength { len: 17 })
+        );
+        assert_eq!(
+            Nibbles::from_packed_key(&key[..9]),
+            Err(PackedKeyDecodeError::InvalidMarker { offset: 8, marker: 17 })
+        );
+
+        let with = |at: usize, byte: u8| {
+            let mut key = key.clone();
+            key[at] = byte;
+            Nibbles::from_packed_key(&key)
+        };
+        assert_eq!(with(8, 16), Err(PackedKeyDecodeError::InvalidMarker { offset: 8, marker: 16 }));
+        assert_eq!(with(17, 0), Err(PackedKeyDecodeError::InvalidMarker { offset: 17, marker: 0 }));
+        assert_eq!(
+            with(17, 17),
+            Err(PackedKeyDecodeError::InvalidMarker { offset: 17, marker: 17 })
+        );
+        assert_eq!(with(11, 0x01), Err(PackedKeyDecodeError::NonZeroPadding { offset: 9 }));
+    }
+
+    #[test]
+    fn paths_are_stored_inline() {
+        let mut key = Nibbles::unpack(keccak256([1]));
+        assert!(!key.as_mut_vec_unchecked().spilled());
+        let mut path = key.slice(..10).join(&key.slice(10..));
+        assert!(!path.as_mut_vec_unchecked().spilled());
+
+        path.push(0x1);
+        assert!(path.as_mut_vec_unchecked().spilled());
+    }
+
+    #[test]
+    fn packed_nibbles() {
+        let data = [0x12, 0x34, 0x56];
+        let packed = PackedNibbles::new(&data);
+        assert_eq!(packed.len(), 6);
+        assert_eq!(packed, Nibbles::unpack(data));
+        assert_eq!(packed.at(1), 0x2);
+        assert_eq!(packed.get(6), None);
+
+        let odd = packed.slice(1..4);
+        assert_eq!(odd, Nibbles::from_nibbles([0x2, 0x3, 0x4]));
+        assert_eq!(odd.slice(1..), Nibbles::from_nibbles([0x3, 0x4]));
+        assert_eq!(odd.to_nibbles(), Nibbles::from_nibbles([0x2, 0x3, 0x4]));
+        assert_eq!(format!("{odd:?}"), "PackedNibbles(0x234)");
+
+        let other = [0x23, 0x5f];
+        assert_eq!(odd.common_prefix_length(&PackedNibbles::new(&other)), 2);
+        assert!(packed.slice(1..).starts_with(&PackedNibbles::new(&other).slice(..3).slice(..2)));
+        assert!(!odd.starts_with(&PackedNibbles::new(&other)));
+        assert_eq!(packed.slice(2..4), PackedNibbles::new(&data[1..2]));
+        assert!(packed.slice(3..3).is_empty());
+    }
+
+    #[test]
+    #[should_panic = "nibble range out of bounds"]
+    fn packed_nibbles_slice_out_of_bounds() {
+        PackedNibbles::new(&[0x12]).slice(1..3);
+    }
+
+    #[test]
+    fn hex_prefix_round_trip() {
+        for len in 0..8 {
+            let nibbles =
+                Nibbles::from_nibbles((0..len).map(|i| (i * 5 % 16) as u8).collect::<Vec<_>>());
+            for is_leaf in [false, true] {
+                let encoded = nibbles.encode_path_leaf(is_leaf);
+                assert_eq!(Nibbles::decode_path(&encoded), Ok((nibbles.clone(), is_leaf)));
+            }
+        }
+
+        assert_eq!(Nibbles::decode_path(&[]), Err(PathDecodeError::Empty));
+        assert_eq!(Nibbles::decode_path(&[0xa0]), Err(PathDecodeError::InvalidFlag { flag: 0xa0 }));
+        assert_eq!(
+            Nibbles::decode_path(&[0x21, 0x23]),
+            Err(PathDecodeError::InvalidPadding { flag: 0x21 })
+        );
+    }
+
+    #[test]
+    fn prefix_groups() {
+        let key = |nibbles: &[u8]| Nibbles::from_nibbles(nibbles);
+        let leaves = [
+            (key(&[1]), 0),
+            (key(&[1, 2, 3]), 1),
+            (key(&[1, 2, 4]), 2),
+            (key(&[1, 3, 0]), 3),
+            (key(&[5, 0, 0]), 4),
+            (key(&[5, 0, 1]), 5),
+            (key(&[0xf, 0xf, 0xf]), 6),
+        ];
+
+        let groups = group_by_prefix(leaves.clone(), 1).collect::<Vec<_>>();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0], (key(&[1]), leaves[..4].to_vec()));
+        assert_eq!(groups[1], (key(&[5]), leaves[4..6].to_vec()));
+        assert_eq!(groups[2], (key(&[0xf]), leaves[6..].to_vec()));
+
+        // The key shorter than the depth is in a group of its own.
+        let groups = group_by_prefix(leaves.clone(), 2)
+            .map(|(prefix, leaves)| (prefix, leaves.into_iter().map(|(_, i)| i).collect()))
+            .collect::<Vec<(_, Vec<_>)>>();
+        assert_eq!(
+            groups,
+            [
+                (key(&[1]), vec![0]),
+                (key(&[1, 2]), vec![1, 2]),
+                (key(&[1, 3]), vec![3]),
+                (key(&[5, 0]), vec![4, 5]),
+                (key(&[0xf, 0xf]), vec![6]),
+            ]
+        );
+
+        let all = group_by_prefix(leaves.clone(), 0).collect::<Vec<_>>();
+        assert_eq!(all, [(Nibbles::default(), leaves.to_vec())]);
+        assert_eq!(group_by_prefix(Vec::<(Nibbles, ())>::new(), 1).next(), None);
+
+        // Leaves of groups that are not consumed are skipped.
+        let mut groups = group_by_prefix(leaves.clone(), 1);
+        let mut group = groups.next_group().unwrap();
+        assert_eq!(group.prefix(), &key(&[1]));
+        assert_eq!(group.next(), Some(leaves[0].clone()));
+        let group = groups.next_group().unwrap();
+        assert_eq!(group.prefix(), &key(&[5]));
+        let mut group = groups.next_group().unwrap();
+        assert_eq!(group.next(), Some(leaves[6].clone()));
+        assert_eq!(group.next(), None);
+        assert!(groups.next_group().is_none());
+    }
+}
@@ -1,5 +1,9 @@
-use super::{super::TrieMask, RlpNode, CHILD_INDEX_RANGE};
-use alloy_primitives::{hex, B256};
+use super::{
+    super::TrieMask, BranchNodeCompactDecodeError, ReducedBranchNodeError, RlpNode,
+    CHILD_INDEX_RANGE,
+};
+use crate::{KeccakHasher, TrieHasher};
+use alloy_primitives::{hex, keccak256, B256};
 use alloy_rlp::{length_of_length, Buf, BufMut, Decodable, Encodable, Header, EMPTY_STRING_CODE};
 use core::{fmt, ops::Range, slice::Iter};
 
@@ -87,6 +91,154 @@ impl BranchNode {
     pub fn as_ref(&self) -> BranchNodeRef<'_> {
         BranchNodeRef::new(&self.stack, self.state_mask)
     }
+
+    /// Returns the child at the given nibble, if any. Returns `None` if the nibble is greater than
+    /// `0xf`.
+    pub fn child(&self, nibble: u8) -> Option<&RlpNode> {
+        self.has_child(nibble).then(|| &self.stack[self.child_position(nibble)])
+    }
+
+    /// Returns an iterator over the children along with their nibbles, in ascending order.
+    pub fn children(&self) -> impl Iterator<Item = (u8, &RlpNode)> + '_ {
+        self.state_mask.iter_set_bits().zip(&self.stack)
+    }
+
+    /// Sets the child at the given nibble, returning the previous one, if any.
+    ///
+    /// The child is inserted into the stack at the position given by the state mask, and the
+    /// state mask is updated accordingly.
+    ///
+    /// # Panics
+    ///
+    /// If the nibble is greater than `0xf`.
+    pub fn set_child(&mut self, nibble: u8, child: RlpNode) -> Option<RlpNode> {
+        assert!(nibble < 16, "invalid nibble {nibble}");
+        let position = self.child_position(nibble);
+        if self.state_mask.is_bit_set(nibble) {
+            Some(core::mem::replace(&mut self.stack[position], child))
+        } else {
+            self.stack.insert(position, child);
+            self.state_mask.set_bit(nibble);
+            None
+        }
+    }
+
+    /// Removes the child at the given nibble, returning it, if any.
+    ///
+    /// Returns `None` if the nibble is greater than `0xf`. Note that a branch node is only valid
+    /// with at least two children.
+    pub fn remove_child(&mut self, nibble: u8) -> Option<RlpNode> {
+        if !self.has_child(nibble) {
+            return None;
+        }
+        let child = self.stack.remove(self.child_position(nibble));
+        self.state_mask.unset_bit(nibble);
+        Some(child)
+    }
+
+    /// Returns whether there is a child at the given nibble, which must be at most `0xf`.
+    const fn has_child(&self, nibble: u8) -> bool {
+        nibble < 16 && self.state_mask.is_bit_set(nibble)
+    }
+
+    /// Returns the position in the stack of the child at the given nibble, or where it would be
+    /// inserted.
+    fn child_position(&self, nibble: u8) -> usize {
+        debug_assert_eq!(self.stack.len(), self.state_mask.count_ones() as usize);
+        self.state_mask.get().checked_shl(16 - nibble as u32).map_or(0, u16::count_ones) as usize
+    }
+
+    /// Converts the branch node into its compact representation, with the given tree mask of the
+    /// children that are stored in the database.
+    ///
+    /// Children referenced by hash are recorded in the hash mask along with their hashes, while
+    /// children that are encoded in place are only present in the state mask. The root hash is
+    /// left unset, as it's only stored for the root node of the trie.
+    ///
+    /// # Panics
+    ///
+    /// If the tree mask is not a subset of the state mask.
+    pub fn to_compact(&self, tree_mask: TrieMask) -> BranchNodeCompact {
+        let mut hash_mask = TrieMask::default();
+        let mut hashes = Vec::new();
+        for (nibble, child) in self.children() {
+            if let Some(hash) = child.as_hash() {
+                hash_mask.set_bit(nibble);
+                hashes.push(hash);
+            }
+        }
+        BranchNodeCompact::new(self.state_mask, tree_mask, hash_mask, hashes, None)
+    }
+
+    /// Reduces the branch node to the children needed by a witness, e.g. those on the paths to
+    /// the proven keys.
+    ///
+    /// The node is left unchanged, so that it keeps its hash. Only the children referenced by
+    /// hash can be blinded, i.e. have their subtries omitted from the witness. Children that are
+    /// encoded in place are part of the node and always revealed, and needed children that are
+    /// not present are ignored.
+    pub fn reduce(&self, needed: TrieMask) -> ReducedBranchNode {
+        let mut revealed = needed & self.state_mask;
+        for (nibble, child) in self.children() {
+            if child.is_inline() {
+                revealed.set_bit(nibble);
+            }
+        }
+        ReducedBranchNode { node: self.clone(), revealed }
+    }
+}
+
+/// A [`BranchNode`] along with the children whose subtries are included in a witness. See
+/// [`BranchNode::reduce`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ReducedBranchNode {
+    /// The branch node.
+    pub node: BranchNode,
+    /// The children whose subtries are included in the witness.
+    pub revealed: TrieMask,
+}
+
+impl ReducedBranchNode {
+    /// Returns the children whose subtries are omitted from the witness.
+    pub fn blinded(&self) -> TrieMask {
+        self.node.state_mask - self.revealed
+    }
+
+    /// Returns an iterator over the hashes of the blinded children along with their nibbles, in
+    /// ascending order.
+    ///
+    /// Children that are encoded in place are skipped, see [`Self::verify`].
+    pub fn blinded_hashes(&self) -> impl Iterator<Item = (u8, B256)> + '_ {
+        let blinded = self.blinded();
+        self.node
+            .children()
+            .filter(move |(nibble, _)| blinded.is_bit_set(*nibble))
+            .filter_map(|(nibble, child)| Some((nibble, child.as_hash()?)))
+    }
+
+    /// Verifies that the reduced node is consistent, and still hashes to the given hash of the
+    /// original node.
+    pub fn verify(&self, hash: B256) -> Result<(), ReducedBranchNodeError> {
+        if self.node.stack.len() != self.node.state_mask.count_ones() as usize {
+            return Err(ReducedBranchNodeError::InvalidStack);
+        }
+        if let Some(nibble) = (self.revealed - self.node.state_mask).first_set() {
+            return Err(ReducedBranchNodeError::MissingChild { nibble });
+        }
+        let blinded = self.blinded();
+        if let Some((nibble, _)) = self
+            .node
+            .children()
+            .find(|(nibble, child)| blinded.is_bit_set(*nibble) && child.is_inline())
+        {
+            return Err(ReducedBranchNodeError::InlineChild { nibble });
+        }
+        let got = keccak256(alloy_rlp::encode(&self.node));
+        if got != hash {
+            return Err(ReducedBranchNodeError::HashMismatch { got, expected: hash });
+        }
+        Ok(())
+    }
 }
 
 /// A reference to [BranchNode] and its state mask.
@@ -158,12 +310,23 @@ impl<'a> BranchNodeRef<'a> {
         self.stack.len().checked_sub(self.state_mask.count_ones() as usize).unwrap()
     }
 
-    /// Returns an iterator over children of the branch node.
+    /// Returns an iterator over all 16 child slots of the branch node in nibble order, yielding
+    /// the nibble of each slot along with its child, if any.
     #[inline]
-    pub fn children(&self) -> impl Iterator<Item = (u8, Option<&RlpNode>)> + '_ {
+    pub fn children(&self) -> BranchChildrenIter<'a> {
         BranchChildrenIter::new(self)
     }
 
+    /// Returns the child at the given nibble, if any.
+    #[inline]
+    pub fn child(&self, nibble: u8) -> Option<&'a RlpNode> {
+        let stack = self.stack;
+        self.state_mask.is_bit_set(nibble).then(|| {
+            let position = (self.state_mask.get() & ((1u16 << nibble) - 1)).count_ones() as usize;
+            &stack[self.first_child_index() + position]
+        })
+    }
+
     /// Given the hash mask of children, return an iterator over stack items
     /// that match the mask.
     #[inline]
@@ -177,8 +340,15 @@ impl<'a> BranchNodeRef<'a> {
     /// RLP-encodes the node and returns either `rlp(node)` or `rlp(keccak(rlp(node)))`.
     #[inline]
     pub fn rlp(&self, rlp: &mut Vec<u8>) -> RlpNode {
+        self.rlp_with_hasher::<KeccakHasher>(rlp)
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(hash(rlp(node)))`, using the
+    /// given [`TrieHasher`].
+    #[inline]
+    pub fn rlp_with_hasher<H: TrieHasher>(&self, rlp: &mut Vec<u8>) -> RlpNode {
         self.encode(rlp);
-        RlpNode::from_rlp(rlp)
+        RlpNode::from_rlp_with_hasher::<H>(rlp)
     }
 
     /// Returns the length of RLP encoded fields of branch node.
@@ -196,9 +366,9 @@ impl<'a> BranchNodeRef<'a> {
     }
 }
 
-/// Iterator over branch node children.
-#[derive(Debug)]
-struct BranchChildrenIter<'a> {
+/// Iterator over the child slots of a branch node. See [`BranchNodeRef::children`].
+#[derive(Clone, Debug)]
+pub struct BranchChildrenIter<'a> {
     range: Range<u8>,
     state_mask: TrieMask,
     stack_iter: Iter<'a, RlpNode>,
@@ -304,12 +474,98 @@ impl BranchNodeCompact {
         Self { state_mask, tree_mask, hash_mask, hashes, root_hash }
     }
 
+    /// The version of the [database encoding](Self::to_bytes).
+    pub const ENCODING_VERSION: u8 = 1;
+
+    /// The length of the [database encoding](Self::to_bytes) without the hashes.
+    const ENCODING_HEADER_LEN: usize = 8;
+
+    /// The flag set in the [database encoding](Self::to_bytes) if the root hash is present.
+    const ROOT_HASH_FLAG: u8 = 0b1;
+
+    /// Encodes the node for storage as a key-value store value.
+    ///
+    /// The encoding is, in order:
+    ///  * the version byte, [`BranchNodeCompact::ENCODING_VERSION`];
+    ///  * the flags byte, with the lowest bit set if the root hash is present;
+    ///  * the state, tree and hash masks, as big-endian `u16`s;
+    ///  * the root hash, if present;
+    ///  * the hashes of the children, in ascending order of their nibbles.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let hashes = self.root_hash.iter().chain(&self.hashes);
+        let mut out = Vec::with_capacity(Self::ENCODING_HEADER_LEN + hashes.clone().count() * 32);
+        out.push(Self::ENCODING_VERSION);
+        out.push(if self.root_hash.is_some() { Self::ROOT_HASH_FLAG } else { 0 });
+        for mask in [self.state_mask, self.tree_mask, self.hash_mask] {
+            out.extend_from_slice(&mask.get().to_be_bytes());
+        }
+        for hash in hashes {
+            out.extend_from_slice(hash.as_slice());
+        }
+        out
+    }
+
+    /// Decodes a node from its [database encoding](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BranchNodeCompactDecodeError> {
+        let Some((header, hashes)) = bytes.split_first_chunk::<{ Self::ENCODING_HEADER_LEN }>()
+        else {
+            return Err(BranchNodeCompactDecodeError::TooShort { got: bytes.len() });
+        };
+        let [version, flags, ..] = *header;
+        if version != Self::ENCODING_VERSION {
+            return Err(BranchNodeCompactDecodeError::UnsupportedVersion { version });
+        }
+        if flags & !Self::ROOT_HASH_FLAG != 0 {
+            return Err(BranchNodeCompactDecodeError::InvalidFlags { flags });
+        }
+        let mask = |at: usize| TrieMask::new(u16::from_be_bytes([header[at], header[at + 1]]));
+        let (state_mask, tree_mask, hash_mask) = (mask(2), mask(4), mask(6));
+        if !tree_mask.is_subset_of(state_mask) || !hash_mask.is_subset_of(state_mask) {
+            return Err(BranchNodeCompactDecodeError::InvalidMasks);
+        }
+
+        let has_root_hash = flags & Self::ROOT_HASH_FLAG != 0;
+        let count = hash_mask.count_ones() as usize + has_root_hash as usize;
+        let expected = Self::ENCODING_HEADER_LEN + count * 32;
+        if bytes.len() != expected {
+            return Err(BranchNodeCompactDecodeError::LengthMismatch {
+                got: bytes.len(),
+                expected,
+            });
+        }
+        let mut hashes = hashes.chunks_exact(32).map(B256::from_slice);
+        let root_hash = if has_root_hash { hashes.next() } else { None };
+        let hashes = hashes.collect();
+        Ok(Self { state_mask, tree_mask, hash_mask, hashes, root_hash })
+    }
+
     /// Returns the hash associated with the given nibble.
     pub fn hash_for_nibble(&self, nibble: u8) -> B256 {
         let mask = *TrieMask::from_nibble(nibble) - 1;
         let index = (*self.hash_mask & mask).count_ones();
         self.hashes[index as usize]
     }
+
+    /// Converts the compact branch node into a [BranchNode] with the RLP encoded children.
+    ///
+    /// Children in the hash mask are referenced by their stored hashes. The compact representation
+    /// doesn't store the children that are encoded in place, so they are obtained from the
+    /// resolver, which is called with the nibble of each such child in ascending order.
+    pub fn into_branch_node<F, E>(self, mut resolver: F) -> Result<BranchNode, E>
+    where
+        F: FnMut(u8) -> Result<RlpNode, E>,
+    {
+        let mut hashes = self.hashes.iter();
+        let mut stack = Vec::with_capacity(self.state_mask.count_ones() as usize);
+        for nibble in CHILD_INDEX_RANGE.filter(|nibble| self.state_mask.is_bit_set(*nibble)) {
+            if self.hash_mask.is_bit_set(nibble) {
+                stack.push(RlpNode::word_rlp(hashes.next().expect("hash for every hash mask bit")));
+            } else {
+                stack.push(resolver(nibble)?);
+            }
+        }
+        Ok(BranchNode::new(stack, self.state_mask))
+    }
 }
 
 #[cfg(test)]
@@ -355,4 +611,199 @@ mod tests {
         let encoded = alloy_rlp::encode(&full);
         assert_eq!(BranchNode::decode(&mut &encoded[..]).unwrap(), full);
     }
+
+    #[test]
+    fn compact_conversion_roundtrip() {
+        let leaf = LeafNode::new(Nibbles::from_nibbles(hex!("0203")), hex!("1234").to_vec());
+        let leaf_rlp = leaf.as_ref().rlp(&mut Vec::new());
+        assert_eq!(leaf_rlp.as_hash(), None);
+        let node = BranchNode::new(
+            vec![
+                RlpNode::word_rlp(&B256::repeat_byte(1)),
+                leaf_rlp.clone(),
+                RlpNode::word_rlp(&B256::repeat_byte(2)),
+            ],
+            TrieMask::new(0b1000_0010_0001),
+        );
+
+        let compact = node.to_compact(TrieMask::new(0b1));
+        assert_eq!(
+            compact,
+            BranchNodeCompact::new(
+                0b1000_0010_0001,
+                0b1,
+                0b1000_0000_0001,
+                vec![B256::repeat_byte(1), B256::repeat_byte(2)],
+                None
+            )
+        );
+
+        let mut resolved = Vec::new();
+        let branch = compact
+            .clone()
+            .into_branch_node(|nibble| {
+                resolved.push(nibble);
+                Ok::<_, ()>(leaf_rlp.clone())
+            })
+            .unwrap();
+        assert_eq!(branch, node);
+        assert_eq!(resolved, [5]);
+        assert_eq!(compact.into_branch_node(Err), Err(5));
+    }
+
+    #[test]
+    fn compact_bytes_roundtrip() {
+        let hashes = vec![B256::repeat_byte(1), B256::repeat_byte(2)];
+        let node = BranchNodeCompact::new(0b1011, 0b0001, 0b1010, hashes.clone(), None);
+        let bytes = node.to_bytes();
+        assert_eq!(bytes[..8], hex!("0100000b0001000a"));
+        assert_eq!(bytes.len(), 8 + 2 * 32);
+        assert_eq!(BranchNodeCompact::from_bytes(&bytes), Ok(node));
+
+        let node = BranchNodeCompact::new(0b1011, 0, 0b1010, hashes, Some(B256::repeat_byte(3)));
+        let bytes = node.to_bytes();
+        assert_eq!(bytes[..8], hex!("0101000b0000000a"));
+        assert_eq!(bytes[8..40], B256::repeat_byte(3));
+        assert_eq!(BranchNodeCompact::from_bytes(&bytes), Ok(node));
+
+        let empty = BranchNodeCompact::default();
+        assert_eq!(BranchNodeCompact::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+
+    #[test]
+    fn compact_bytes_errors() {
+        use BranchNodeCompactDecodeError::*;
+
+        let bytes = BranchNodeCompact::new(0b11, 0, 0b1, vec![B256::ZERO], None).to_bytes();
+        assert_eq!(BranchNodeCompact::from_bytes(&bytes[..7]), Err(TooShort { got: 7 }));
+        assert_eq!(
+            BranchNodeCompact::from_bytes(&bytes[..39]),
+            Err(LengthMismatch { got: 39, expected: 40 })
+        );
+
+        let with = |at: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[at] = byte;
+            BranchNodeCompact::from_bytes(&bytes)
+        };
+        assert_eq!(with(0, 2), Err(UnsupportedVersion { version: 2 }));
+        assert_eq!(with(1, 0b10), Err(InvalidFlags { flags: 0b10 }));
+        assert_eq!(with(1, 1), Err(LengthMismatch { got: 40, expected: 72 }));
+        assert_eq!(with(5, 0b100), Err(InvalidMasks));
+        assert_eq!(with(7, 0b111), Err(InvalidMasks));
+    }
+
+    #[test]
+    fn child_accessors() {
+        let child = |byte: u8| RlpNode::word_rlp(&B256::repeat_byte(byte));
+        let mut node = BranchNode::default();
+        assert_eq!(node.child(0), None);
+        assert_eq!(node.remove_child(3), None);
+
+        assert_eq!(node.set_child(7, child(7)), None);
+        assert_eq!(node.set_child(0xf, child(0xf)), None);
+        assert_eq!(node.set_child(0, child(0)), None);
+        assert_eq!(node.set_child(3, child(3)), None);
+        assert_eq!(node.state_mask, TrieMask::new(0b1000_0000_1000_1001));
+        assert_eq!(node.stack, [child(0), child(3), child(7), child(0xf)]);
+        assert_eq!(
+            node.children().collect::<Vec<_>>(),
+            [(0, &child(0)), (3, &child(3)), (7, &child(7)), (0xf, &child(0xf))]
+        );
+        for nibble in 0..16 {
+            assert_eq!(
+                node.child(nibble),
+                node.as_ref().children().nth(nibble as usize).unwrap().1
+            );
+        }
+
+        assert_eq!(node.set_child(7, child(0xaa)), Some(child(7)));
+        assert_eq!(node.child(7), Some(&child(0xaa)));
+        assert_eq!(node.remove_child(3), Some(child(3)));
+        assert_eq!(node.remove_child(3), None);
+        assert_eq!(
+            node,
+            BranchNode::new(
+                vec![child(0), child(0xaa), child(0xf)],
+                TrieMask::new(0b1000_0000_1000_0001)
+            )
+        );
+    }
+
+    #[test]
+    fn child_accessors_invalid_nibble() {
+        let child = |byte: u8| RlpNode::word_rlp(&B256::repeat_byte(byte));
+        let mut node = BranchNode::default();
+        node.set_child(0, child(0));
+        node.set_child(0xf, child(0xf));
+        for nibble in [16, 17, 0xff] {
+            assert_eq!(node.child(nibble), None);
+            assert_eq!(node.remove_child(nibble), None);
+        }
+        assert_eq!(node.stack, [child(0), child(0xf)]);
+    }
+
+    #[test]
+    #[should_panic = "invalid nibble 16"]
+    fn set_child_invalid_nibble() {
+        BranchNode::default().set_child(16, RlpNode::word_rlp(&B256::ZERO));
+    }
+
+    #[test]
+    fn ref_children_skip_unrelated_stack_items() {
+        let child = |byte: u8| RlpNode::word_rlp(&B256::repeat_byte(byte));
+        // The hash builder stack holds unrelated nodes below the children of the branch node.
+        let stack = [child(0xee), child(1), child(5)];
+        let node = BranchNodeRef::new(&stack, TrieMask::new(0b10_0010));
+
+        let children = node.children();
+        assert_eq!(children.len(), 16);
+        assert_eq!(
+            children.filter_map(|(nibble, child)| Some((nibble, child?))).collect::<Vec<_>>(),
+            [(1, &child(1)), (5, &child(5))]
+        );
+        assert_eq!(node.child(1), Some(&child(1)));
+        assert_eq!(node.child(5), Some(&child(5)));
+        assert_eq!(node.child(0), None);
+        assert_eq!(node.child(0xf), None);
+    }
+
+    #[test]
+    fn reduced_branch_node() {
+        let hashed = |byte: u8| RlpNode::word_rlp(&B256::repeat_byte(byte));
+        let inline = RlpNode::from_rlp(&alloy_rlp::encode(LeafNode::new(
+            Nibbles::from_nibbles([0xa]),
+            vec![0x01],
+        )));
+        assert!(inline.is_inline());
+        let node =
+            BranchNode::new(vec![hashed(1), inline.clone(), hashed(5)], TrieMask::new(0b10_0101));
+        let hash = keccak256(alloy_rlp::encode(&node));
+
+        let reduced = node.reduce(TrieMask::new(0b1_0000_0001));
+        assert_eq!(reduced.node, node);
+        // The inline child is always revealed, and the missing child `8` is ignored.
+        assert_eq!(reduced.revealed, TrieMask::new(0b101));
+        assert_eq!(reduced.blinded(), TrieMask::new(0b10_0000));
+        assert_eq!(reduced.blinded_hashes().collect::<Vec<_>>(), [(5, B256::repeat_byte(5))]);
+        assert_eq!(reduced.verify(hash), Ok(()));
+
+        let all = node.reduce(TrieMask::new(0xffff));
+        assert_eq!(all.revealed, node.state_mask);
+        assert_eq!(all.blinded_hashes().count(), 0);
+        assert_eq!(all.verify(hash), Ok(()));
+
+        let mut invalid = reduced.clone();
+        invalid.revealed = TrieMask::new(0b1);
+        assert_eq!(invalid.verify(hash), Err(ReducedBranchNodeError::InlineChild { nibble: 2 }));
+        invalid.revealed = TrieMask::new(0b1000_0101);
+        assert_eq!(invalid.verify(hash), Err(ReducedBranchNodeError::MissingChild { nibble: 7 }));
+        invalid.revealed = reduced.revealed;
+        invalid.node.stack.pop();
+        assert_eq!(invalid.verify(hash), Err(ReducedBranchNodeError::InvalidStack));
+        assert_eq!(
+            reduced.verify(B256::ZERO),
+            Err(ReducedBranchNodeError::HashMismatch { got: hash, expected: B256::ZERO })
+        );
+    }
 }
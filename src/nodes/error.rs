@@ -0,0 +1,230 @@
+use super::TrieNodeKind;
+use alloy_primitives::B256;
+use core::fmt;
+
+/// Error during decoding of a trie node from its RLP encoding.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrieNodeDecodeError {
+    /// The input is not a valid RLP encoding of a trie node.
+    Rlp {
+        /// Byte offset in the input at which the invalid item starts.
+        offset: usize,
+        /// The kind of the node, if it could be determined before the error.
+        kind: Option<TrieNodeKind>,
+        /// The RLP decoding error.
+        error: alloy_rlp::Error,
+    },
+    /// The node is encoded as a list with neither 17 items (branch node), nor 2 items (extension
+    /// or leaf node).
+    UnexpectedItemCount {
+        /// Number of items in the list.
+        got: usize,
+    },
+    /// The input continues after the end of the node.
+    TrailingBytes {
+        /// Byte offset in the input at which the node ends.
+        offset: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for TrieNodeDecodeError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Rlp { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrieNodeDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rlp { offset, kind: Some(kind), error } => {
+                write!(f, "invalid {kind} node at offset {offset}: {error}")
+            }
+            Self::Rlp { offset, kind: None, error } => {
+                write!(f, "invalid trie node at offset {offset}: {error}")
+            }
+            Self::UnexpectedItemCount { got } => {
+                write!(f, "unexpected number of trie node items. got: {got}. expected: 2 or 17")
+            }
+            Self::TrailingBytes { offset } => {
+                write!(f, "unexpected trailing bytes after trie node at offset {offset}")
+            }
+        }
+    }
+}
+
+impl From<TrieNodeDecodeError> for alloy_rlp::Error {
+    fn from(error: TrieNodeDecodeError) -> Self {
+        match error {
+            TrieNodeDecodeError::Rlp { error, .. } => error,
+            TrieNodeDecodeError::UnexpectedItemCount { .. } => {
+                Self::Custom("invalid number of items in the list")
+            }
+            TrieNodeDecodeError::TrailingBytes { .. } => Self::UnexpectedLength,
+        }
+    }
+}
+
+/// Error during decoding of a hex-prefix encoded path. See [`decode_path`](super::decode_path).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathDecodeError {
+    /// The encoded path is empty, and has no flag byte.
+    Empty,
+    /// The high nibble of the flag byte is not one of the leaf or extension flags.
+    InvalidFlag {
+        /// The flag byte.
+        flag: u8,
+    },
+    /// The low nibble of the flag byte of a path with an even number of nibbles is not zero.
+    InvalidPadding {
+        /// The flag byte.
+        flag: u8,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for PathDecodeError {}
+
+impl fmt::Display for PathDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty hex-prefix encoded path"),
+            Self::InvalidFlag { flag } => write!(f, "invalid hex-prefix flag {flag:#04x}"),
+            Self::InvalidPadding { flag } => {
+                write!(f, "invalid padding in hex-prefix flag {flag:#04x}")
+            }
+        }
+    }
+}
+
+/// Error during decoding of a [`BranchNodeCompact`](super::BranchNodeCompact) from its database
+/// encoding. See [`BranchNodeCompact::from_bytes`](super::BranchNodeCompact::from_bytes).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BranchNodeCompactDecodeError {
+    /// The encoding is shorter than the header.
+    TooShort {
+        /// Length of the encoding.
+        got: usize,
+    },
+    /// The version byte is not a supported version.
+    UnsupportedVersion {
+        /// The version byte.
+        version: u8,
+    },
+    /// The flags byte has unknown bits set.
+    InvalidFlags {
+        /// The flags byte.
+        flags: u8,
+    },
+    /// The tree or hash mask has bits that are not set in the state mask.
+    InvalidMasks,
+    /// The length of the encoding doesn't match the number of hashes given by the flags and the
+    /// hash mask.
+    LengthMismatch {
+        /// Length of the encoding.
+        got: usize,
+        /// Expected length of the encoding.
+        expected: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for BranchNodeCompactDecodeError {}
+
+impl fmt::Display for BranchNodeCompactDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort { got } => write!(f, "branch node encoding too short: {got} bytes"),
+            Self::UnsupportedVersion { version } => {
+                write!(f, "unsupported branch node encoding version {version}")
+            }
+            Self::InvalidFlags { flags } => write!(f, "invalid branch node flags {flags:#04x}"),
+            Self::InvalidMasks => {
+                f.write_str("branch node masks are not subsets of the state mask")
+            }
+            Self::LengthMismatch { got, expected } => {
+                write!(f, "branch node encoding length mismatch. got: {got}. expected: {expected}")
+            }
+        }
+    }
+}
+
+/// Error when creating an [`RlpNode`](super::RlpNode) from data that is longer than
+/// [`RlpNode::CAPACITY`](super::RlpNode::CAPACITY).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RlpNodeTooLongError {
+    /// Length of the data.
+    pub len: usize,
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for RlpNodeTooLongError {}
+
+impl fmt::Display for RlpNodeTooLongError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RLP node too large: {} bytes, at most {} allowed",
+            self.len,
+            super::RlpNode::CAPACITY
+        )
+    }
+}
+
+/// Error during verification of a [`ReducedBranchNode`](super::ReducedBranchNode). See
+/// [`ReducedBranchNode::verify`](super::ReducedBranchNode::verify).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReducedBranchNodeError {
+    /// The number of children on the stack doesn't match the state mask.
+    InvalidStack,
+    /// A revealed child is not present in the state mask.
+    MissingChild {
+        /// The nibble of the child.
+        nibble: u8,
+    },
+    /// A child that is not revealed is encoded in place, so that its subtrie can't be omitted.
+    InlineChild {
+        /// The nibble of the child.
+        nibble: u8,
+    },
+    /// The node doesn't hash to the expected hash.
+    HashMismatch {
+        /// Hash of the node.
+        got: B256,
+        /// Expected hash of the node.
+        expected: B256,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for ReducedBranchNodeError {}
+
+impl fmt::Display for ReducedBranchNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidStack => f.write_str("branch node stack doesn't match the state mask"),
+            Self::MissingChild { nibble } => {
+                write!(f, "revealed child {nibble:x} is missing from the branch node")
+            }
+            Self::InlineChild { nibble } => {
+                write!(f, "blinded child {nibble:x} is encoded in place")
+            }
+            Self::HashMismatch { got, expected } => {
+                write!(f, "branch node hash mismatch. got: {got}. expected: {expected}")
+            }
+        }
+    }
+}
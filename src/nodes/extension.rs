@@ -1,4 +1,5 @@
-use super::{super::Nibbles, unpack_path_to_nibbles, RlpNode};
+use super::{super::Nibbles, unpack_path_to_nibbles, LeafNode, RlpNode, TrieNode};
+use crate::{KeccakHasher, TrieHasher};
 use alloy_primitives::{hex, Bytes};
 use alloy_rlp::{length_of_length, BufMut, Decodable, Encodable, Header};
 use core::fmt;
@@ -81,6 +82,51 @@ impl ExtensionNode {
     pub fn as_ref(&self) -> ExtensionNodeRef<'_> {
         ExtensionNodeRef { key: &self.key, child: &self.child }
     }
+
+    /// Splits the extension node at the given depth into two extension nodes, where the first one
+    /// has the first `depth` nibbles of the key and points to the second one, which has the rest
+    /// of the key and points to the original child.
+    ///
+    /// # Panics
+    ///
+    /// If `depth` is zero or not less than the key length, as extension keys cannot be empty.
+    pub fn split_at(&self, depth: usize) -> (Self, Self) {
+        self.split_at_with_hasher::<KeccakHasher>(depth)
+    }
+
+    /// Splits the extension node at the given depth, using the given [`TrieHasher`] for the
+    /// pointer to the second node. See [`split_at`](Self::split_at).
+    pub fn split_at_with_hasher<H: TrieHasher>(&self, depth: usize) -> (Self, Self) {
+        assert!(
+            depth > 0 && depth < self.key.len(),
+            "invalid split depth {depth} for extension key of length {}",
+            self.key.len()
+        );
+        let lower = Self::new(self.key.slice(depth..), self.child.clone());
+        let upper = Self::new(
+            self.key.slice(..depth),
+            lower.as_ref().rlp_with_hasher::<H>(&mut Vec::new()),
+        );
+        (upper, lower)
+    }
+
+    /// Merges the extension node with its child, if the child is a leaf or an extension node.
+    ///
+    /// The result is a node of the same kind as the child, with the extension key prepended to its
+    /// key. Branch nodes and the empty root cannot be merged, in which case [`None`] is returned.
+    ///
+    /// Note that the child is not checked against the pointer of this node.
+    pub fn merge(&self, child: &TrieNode) -> Option<TrieNode> {
+        match child {
+            TrieNode::Extension(child) => {
+                Some(TrieNode::Extension(Self::new(self.key.join(&child.key), child.child.clone())))
+            }
+            TrieNode::Leaf(child) => {
+                Some(TrieNode::Leaf(LeafNode::new(self.key.join(&child.key), child.value.clone())))
+            }
+            TrieNode::Branch(_) | TrieNode::EmptyRoot => None,
+        }
+    }
 }
 
 /// Reference to the extension node. See [ExtensionNode] from more information.
@@ -126,8 +172,15 @@ impl<'a> ExtensionNodeRef<'a> {
     /// RLP-encodes the node and returns either `rlp(node)` or `rlp(keccak(rlp(node)))`.
     #[inline]
     pub fn rlp(&self, rlp: &mut Vec<u8>) -> RlpNode {
+        self.rlp_with_hasher::<KeccakHasher>(rlp)
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(hash(rlp(node)))`, using the
+    /// given [`TrieHasher`].
+    #[inline]
+    pub fn rlp_with_hasher<H: TrieHasher>(&self, rlp: &mut Vec<u8>) -> RlpNode {
         self.encode(rlp);
-        RlpNode::from_rlp(rlp)
+        RlpNode::from_rlp_with_hasher::<H>(rlp)
     }
 
     /// Returns the length of RLP encoded fields of extension node.
@@ -157,4 +210,47 @@ mod tests {
         assert_eq!(rlp.as_ref(), hex!("c98300646f8476657262"));
         assert_eq!(ExtensionNode::decode(&mut &rlp[..]).unwrap(), extension);
     }
+
+    #[test]
+    fn split_and_merge() {
+        let child = RlpNode::word_rlp(&alloy_primitives::B256::repeat_byte(0x11));
+        let extension = ExtensionNode::new(Nibbles::from_nibbles([0x1, 0x2, 0x3]), child.clone());
+
+        let (upper, lower) = extension.split_at(1);
+        assert_eq!(upper.key, Nibbles::from_nibbles([0x1]));
+        assert_eq!(lower, ExtensionNode::new(Nibbles::from_nibbles([0x2, 0x3]), child));
+        assert_eq!(upper.child, lower.as_ref().rlp(&mut Vec::new()));
+        assert_eq!(upper.merge(&TrieNode::Extension(lower)), Some(TrieNode::Extension(extension)));
+
+        let leaf = LeafNode::new(Nibbles::from_nibbles([0x4]), vec![0x2a]);
+        assert_eq!(
+            upper.merge(&TrieNode::Leaf(leaf)),
+            Some(TrieNode::Leaf(LeafNode::new(Nibbles::from_nibbles([0x1, 0x4]), vec![0x2a])))
+        );
+        assert_eq!(upper.merge(&TrieNode::EmptyRoot), None);
+        assert_eq!(upper.merge(&TrieNode::Branch(Default::default())), None);
+    }
+
+    #[test]
+    #[should_panic = "invalid split depth"]
+    fn split_at_end() {
+        let extension = ExtensionNode::new(Nibbles::from_nibbles([0x1, 0x2]), RlpNode::default());
+        extension.split_at(2);
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    #[cfg_attr(miri, ignore = "no proptest")]
+    fn split_merge_roundtrip() {
+        use proptest::{collection::vec, prelude::*};
+
+        proptest::proptest!(|(key in vec(0u8..16, 2..64), depth: prop::sample::Index, child: RlpNode)| {
+            let extension = ExtensionNode::new(Nibbles::from_nibbles(&key), child);
+            let depth = depth.index(key.len() - 1) + 1;
+            let (upper, lower) = extension.split_at(depth);
+            prop_assert_eq!(upper.key.len(), depth);
+            prop_assert_eq!(&upper.child, &lower.as_ref().rlp(&mut Vec::new()));
+            prop_assert_eq!(upper.merge(&TrieNode::Extension(lower)), Some(TrieNode::Extension(extension)));
+        });
+    }
 }
@@ -1,6 +1,7 @@
 use super::{super::Nibbles, unpack_path_to_nibbles, RlpNode};
-use alloy_primitives::{hex, Bytes};
-use alloy_rlp::{length_of_length, BufMut, Decodable, Encodable, Header};
+use crate::{KeccakHasher, TrieHasher};
+use alloy_primitives::{hex, Bytes, Keccak256};
+use alloy_rlp::{length_of_length, BufMut, Decodable, Encodable, Header, EMPTY_STRING_CODE};
 use core::fmt;
 
 #[allow(unused_imports)]
@@ -79,6 +80,34 @@ impl LeafNode {
     pub fn as_ref(&self) -> LeafNodeRef<'_> {
         LeafNodeRef { key: &self.key, value: &self.value }
     }
+
+    /// Sets the key of the leaf node, returning the previous one.
+    ///
+    /// The node does not cache its encoding, so [`rehash`](Self::rehash) always reflects the
+    /// updated key.
+    pub fn set_key(&mut self, key: Nibbles) -> Nibbles {
+        core::mem::replace(&mut self.key, key)
+    }
+
+    /// Sets the value of the leaf node, returning the previous one.
+    ///
+    /// The node does not cache its encoding, so [`rehash`](Self::rehash) always reflects the
+    /// updated value.
+    pub fn set_value(&mut self, value: Vec<u8>) -> Vec<u8> {
+        core::mem::replace(&mut self.value, value)
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(keccak(rlp(node)))`, which is
+    /// how the node is referenced by its parent.
+    pub fn rehash(&self) -> RlpNode {
+        self.rehash_with_hasher::<KeccakHasher>()
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(hash(rlp(node)))`, using the
+    /// given [`TrieHasher`]. See [`rehash`](Self::rehash).
+    pub fn rehash_with_hasher<H: TrieHasher>(&self) -> RlpNode {
+        self.as_ref().rlp_with_hasher::<H>(&mut Vec::with_capacity(self.length()))
+    }
 }
 
 /// Reference to the leaf node. See [LeafNode] from more information.
@@ -123,8 +152,67 @@ impl<'a> LeafNodeRef<'a> {
     /// RLP-encodes the node and returns either `rlp(node)` or `rlp(keccak(rlp(node)))`.
     #[inline]
     pub fn rlp(&self, rlp: &mut Vec<u8>) -> RlpNode {
-        self.encode(rlp);
-        RlpNode::from_rlp(rlp)
+        self.rlp_with_hasher::<KeccakHasher>(rlp)
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(hash(rlp(node)))`, using the
+    /// given [`TrieHasher`].
+    ///
+    /// Values longer than the [value hashing threshold](TrieHasher::VALUE_HASH_THRESHOLD) of the
+    /// hasher are encoded as their hash.
+    #[inline]
+    pub fn rlp_with_hasher<H: TrieHasher>(&self, rlp: &mut Vec<u8>) -> RlpNode {
+        if let Some(hash) = H::hash_leaf_value(self.value) {
+            LeafNodeRef::new(self.key, hash.as_slice()).encode(rlp);
+        } else {
+            self.encode(rlp);
+        }
+        RlpNode::from_rlp_with_hasher::<H>(rlp)
+    }
+
+    /// RLP-encodes everything but the value payload: the list header, the encoded key and the
+    /// header of the value string. The full encoding is this prefix followed by the value bytes.
+    ///
+    /// This allows large values to be written or hashed straight from where they are stored,
+    /// without first copying them into the encoding buffer.
+    pub fn encode_prefix(&self, out: &mut dyn BufMut) {
+        Header { list: true, payload_length: self.rlp_payload_length() }.encode(out);
+        self.key.encode_path_leaf(true).as_slice().encode(out);
+        // Single bytes below the string offset are their own encoding.
+        if !matches!(self.value, [byte] if *byte < EMPTY_STRING_CODE) {
+            Header { list: false, payload_length: self.value.len() }.encode(out);
+        }
+    }
+
+    /// Writes the RLP encoding of the node to the writer, writing the value directly from the
+    /// borrowed slice. See [`Self::encode_prefix`].
+    #[cfg(feature = "std")]
+    pub fn encode_to_writer<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut prefix = Vec::with_capacity(self.length() - self.value.len());
+        self.encode_prefix(&mut prefix);
+        writer.write_all(&prefix)?;
+        writer.write_all(self.value)
+    }
+
+    /// Returns either `rlp(node)` or `rlp(keccak(rlp(node)))`, like [`Self::rlp`], but without
+    /// buffering the encoding when the node is hashed. The value is fed to the hasher directly.
+    pub fn rlp_unbuffered(&self) -> RlpNode {
+        let mut prefix = Vec::with_capacity(self.length() - self.value.len());
+        self.encode_prefix(&mut prefix);
+        // A custom keccak256 implementation can only hash the whole encoding at once.
+        #[cfg(all(feature = "custom-keccak", target_has_atomic = "ptr"))]
+        let buffered = crate::hasher::custom_keccak256().is_some();
+        #[cfg(not(all(feature = "custom-keccak", target_has_atomic = "ptr")))]
+        let buffered = false;
+        if buffered || prefix.len() + self.value.len() < 32 {
+            prefix.extend_from_slice(self.value);
+            return RlpNode::from_rlp(&prefix);
+        }
+
+        let mut hasher = Keccak256::new();
+        hasher.update(&prefix);
+        hasher.update(self.value);
+        RlpNode::word_rlp(&hasher.finalize())
     }
 
     /// Returns the length of RLP encoded fields of leaf node.
@@ -160,4 +248,42 @@ mod tests {
         assert_eq!(rlp.as_ref(), hex!("c98320646f8476657262"));
         assert_eq!(LeafNode::decode(&mut &rlp[..]).unwrap(), leaf);
     }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn unbuffered_encoding() {
+        let key = Nibbles::from_nibbles_unchecked(hex!("0604060f"));
+        for value in [vec![0x01], vec![0x80], vec![0xab; 20], vec![0xab; 100], vec![0xcd; 100_000]]
+        {
+            let leaf = LeafNodeRef::new(&key, &value);
+            let encoded = alloy_rlp::encode(&leaf);
+
+            let mut prefix = Vec::new();
+            leaf.encode_prefix(&mut prefix);
+            assert_eq!([&prefix[..], &value[..]].concat(), encoded);
+
+            let mut written = Vec::new();
+            leaf.encode_to_writer(&mut written).unwrap();
+            assert_eq!(written, encoded);
+
+            assert_eq!(leaf.rlp_unbuffered(), leaf.rlp(&mut Vec::new()));
+        }
+    }
+
+    #[test]
+    fn update_in_place() {
+        let mut leaf = LeafNode::new(Nibbles::from_nibbles_unchecked(hex!("0604060f")), vec![0x01]);
+        assert_eq!(leaf.rehash().as_ref(), hex!("c58320646f01"));
+
+        assert_eq!(leaf.set_value(hex!("76657262").to_vec()), [0x01]);
+        assert_eq!(leaf.rehash().as_ref(), hex!("c98320646f8476657262"));
+
+        let key = Nibbles::from_nibbles_unchecked(hex!("060406"));
+        assert_eq!(leaf.set_key(key.clone()), Nibbles::from_nibbles_unchecked(hex!("0604060f")));
+        assert_eq!(leaf.rehash(), LeafNode::new(key, hex!("76657262").to_vec()).rehash());
+
+        leaf.set_value(vec![0xab; 100]);
+        assert!(leaf.rehash().as_hash().is_some());
+        assert_eq!(leaf.rehash(), leaf.as_ref().rlp(&mut Vec::new()));
+    }
 }
@@ -1,5 +1,6 @@
 //! Various branch nodes produced by the hash builder.
 
+use crate::{KeccakHasher, TrieHasher};
 use alloy_primitives::{Bytes, B256};
 use alloy_rlp::{Decodable, Encodable, Header, EMPTY_STRING_CODE};
 use core::ops::Range;
@@ -10,7 +11,9 @@ use smallvec::SmallVec;
 use alloc::vec::Vec;
 
 mod branch;
-pub use branch::{BranchNode, BranchNodeCompact, BranchNodeRef};
+pub use branch::{
+    BranchChildrenIter, BranchNode, BranchNodeCompact, BranchNodeRef, ReducedBranchNode,
+};
 
 mod extension;
 pub use extension::{ExtensionNode, ExtensionNodeRef};
@@ -21,6 +24,12 @@ pub use leaf::{LeafNode, LeafNodeRef};
 mod rlp;
 pub use rlp::RlpNode;
 
+mod error;
+pub use error::{
+    BranchNodeCompactDecodeError, PathDecodeError, ReducedBranchNodeError, RlpNodeTooLongError,
+    TrieNodeDecodeError,
+};
+
 /// The range of valid child indexes.
 pub const CHILD_INDEX_RANGE: Range<u8> = 0..16;
 
@@ -37,6 +46,30 @@ pub enum TrieNode {
     Leaf(LeafNode),
 }
 
+/// The kind of a [`TrieNode`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TrieNodeKind {
+    /// Empty root node.
+    EmptyRoot,
+    /// Branch node.
+    Branch,
+    /// Extension node.
+    Extension,
+    /// Leaf node.
+    Leaf,
+}
+
+impl core::fmt::Display for TrieNodeKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::EmptyRoot => "empty root",
+            Self::Branch => "branch",
+            Self::Extension => "extension",
+            Self::Leaf => "leaf",
+        })
+    }
+}
+
 impl Encodable for TrieNode {
     #[inline]
     fn encode(&self, out: &mut dyn alloy_rlp::BufMut) {
@@ -63,13 +96,50 @@ impl Encodable for TrieNode {
 
 impl Decodable for TrieNode {
     fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
-        let mut items = match Header::decode_raw(buf)? {
+        Self::decode_inner(buf).map_err(Into::into)
+    }
+}
+
+impl TrieNode {
+    /// Decodes a trie node from its RLP encoding, which must span the whole input.
+    ///
+    /// Unlike [`Decodable::decode`], the returned error reports the offset of the invalid item
+    /// and the kind of the node, if it could be determined from its item count and path flag.
+    pub fn decode_raw(rlp: &[u8]) -> Result<Self, TrieNodeDecodeError> {
+        let mut buf = rlp;
+        let node = Self::decode_inner(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(TrieNodeDecodeError::TrailingBytes { offset: rlp.len() - buf.len() });
+        }
+        Ok(node)
+    }
+
+    /// Returns the kind of the node.
+    pub const fn kind(&self) -> TrieNodeKind {
+        match self {
+            Self::EmptyRoot => TrieNodeKind::EmptyRoot,
+            Self::Branch(_) => TrieNodeKind::Branch,
+            Self::Extension(_) => TrieNodeKind::Extension,
+            Self::Leaf(_) => TrieNodeKind::Leaf,
+        }
+    }
+
+    fn decode_inner(buf: &mut &[u8]) -> Result<Self, TrieNodeDecodeError> {
+        let input = *buf;
+        let offset_of = |item: &[u8]| item.as_ptr() as usize - input.as_ptr() as usize;
+        let rlp_error = |item: &[u8], kind, error| TrieNodeDecodeError::Rlp {
+            offset: offset_of(item),
+            kind,
+            error,
+        };
+
+        let mut items = match Header::decode_raw(buf).map_err(|e| rlp_error(input, None, e))? {
             alloy_rlp::PayloadView::List(list) => list,
             alloy_rlp::PayloadView::String(val) => {
                 return if val.is_empty() {
                     Ok(Self::EmptyRoot)
                 } else {
-                    Err(alloy_rlp::Error::UnexpectedString)
+                    Err(rlp_error(input, None, alloy_rlp::Error::UnexpectedString))
                 }
             }
         };
@@ -78,16 +148,21 @@ impl Decodable for TrieNode {
         // or 2 (extension or leaf node).
         match items.len() {
             17 => {
+                let kind = Some(TrieNodeKind::Branch);
                 let mut branch = BranchNode::default();
                 for (idx, item) in items.into_iter().enumerate() {
                     if idx == 16 {
                         if item != [EMPTY_STRING_CODE] {
-                            return Err(alloy_rlp::Error::Custom(
-                                "branch node values are not supported",
+                            return Err(rlp_error(
+                                item,
+                                kind,
+                                alloy_rlp::Error::Custom("branch node values are not supported"),
                             ));
                         }
                     } else if item != [EMPTY_STRING_CODE] {
-                        branch.stack.push(RlpNode::from_raw_rlp(item)?);
+                        branch.stack.push(
+                            RlpNode::from_raw_rlp(item).map_err(|e| rlp_error(item, kind, e))?,
+                        );
                         branch.state_mask.set_bit(idx as u8);
                     }
                 }
@@ -95,10 +170,16 @@ impl Decodable for TrieNode {
             }
             2 => {
                 let mut key = items.remove(0);
+                let key_item = key;
 
-                let encoded_key = Header::decode_bytes(&mut key, false)?;
+                let encoded_key = Header::decode_bytes(&mut key, false)
+                    .map_err(|e| rlp_error(key_item, None, e))?;
                 if encoded_key.is_empty() {
-                    return Err(alloy_rlp::Error::Custom("trie node key empty"));
+                    return Err(rlp_error(
+                        key_item,
+                        None,
+                        alloy_rlp::Error::Custom("trie node key empty"),
+                    ));
                 }
 
                 // extract the high order part of the nibble to then pick the odd nibble out
@@ -107,33 +188,53 @@ impl Decodable for TrieNode {
                 let first = match key_flag {
                     ExtensionNode::ODD_FLAG | LeafNode::ODD_FLAG => Some(encoded_key[0] & 0x0f),
                     ExtensionNode::EVEN_FLAG | LeafNode::EVEN_FLAG => None,
-                    _ => return Err(alloy_rlp::Error::Custom("node is not extension or leaf")),
+                    _ => {
+                        return Err(rlp_error(
+                            key_item,
+                            None,
+                            alloy_rlp::Error::Custom("node is not extension or leaf"),
+                        ))
+                    }
                 };
 
                 let key = unpack_path_to_nibbles(first, &encoded_key[1..]);
+                let mut value = items.remove(0);
+                let value_item = value;
                 let node = if key_flag == LeafNode::EVEN_FLAG || key_flag == LeafNode::ODD_FLAG {
-                    let value = Bytes::decode(&mut items.remove(0))?.into();
-                    Self::Leaf(LeafNode::new(key, value))
+                    let value = Bytes::decode(&mut value)
+                        .map_err(|e| rlp_error(value_item, Some(TrieNodeKind::Leaf), e))?;
+                    Self::Leaf(LeafNode::new(key, value.into()))
                 } else {
+                    if key.is_empty() {
+                        return Err(rlp_error(
+                            key_item,
+                            Some(TrieNodeKind::Extension),
+                            alloy_rlp::Error::Custom("extension node key empty"),
+                        ));
+                    }
                     // We don't decode value because it is expected to be RLP encoded.
-                    Self::Extension(ExtensionNode::new(
-                        key,
-                        RlpNode::from_raw_rlp(items.remove(0))?,
-                    ))
+                    let child = RlpNode::from_raw_rlp(value)
+                        .map_err(|e| rlp_error(value_item, Some(TrieNodeKind::Extension), e))?;
+                    Self::Extension(ExtensionNode::new(key, child))
                 };
                 Ok(node)
             }
-            _ => Err(alloy_rlp::Error::Custom("invalid number of items in the list")),
+            got => Err(TrieNodeDecodeError::UnexpectedItemCount { got }),
         }
     }
-}
 
-impl TrieNode {
     /// RLP-encodes the node and returns either `rlp(node)` or `rlp(keccak(rlp(node)))`.
     #[inline]
     pub fn rlp(&self, rlp: &mut Vec<u8>) -> RlpNode {
+        self.rlp_with_hasher::<KeccakHasher>(rlp)
+    }
+
+    /// RLP-encodes the node and returns either `rlp(node)` or `rlp(hash(rlp(node)))`, using the
+    /// given [`TrieHasher`].
+    #[inline]
+    pub fn rlp_with_hasher<H: TrieHasher>(&self, rlp: &mut Vec<u8>) -> RlpNode {
         self.encode(rlp);
-        RlpNode::from_rlp(rlp)
+        RlpNode::from_rlp_with_hasher::<H>(rlp)
     }
 }
 
@@ -234,6 +335,36 @@ pub fn encode_path_leaf(nibbles: &Nibbles, is_leaf: bool) -> SmallVec<[u8; 36]>
     encoded
 }
 
+/// Decodes a hex-prefix encoded path, as produced by [`encode_path_leaf`], returning its nibbles
+/// and whether it's the path of a leaf node rather than of an extension node.
+///
+/// The high nibble of the first byte holds the flag, and the low nibble holds the first nibble of
+/// paths with an odd number of nibbles, or zero padding otherwise.
+///
+/// # Examples
+///
+/// ```
+/// # use alloy_trie::{nodes::{decode_path, PathDecodeError}, Nibbles};
+/// let nibbles = Nibbles::from_nibbles(&[0x0A, 0x0B, 0x0C]);
+/// assert_eq!(decode_path(&[0x3A, 0xBC]), Ok((nibbles.clone(), true)));
+/// assert_eq!(decode_path(&[0x1A, 0xBC]), Ok((nibbles, false)));
+/// assert_eq!(decode_path(&[0x40]), Err(PathDecodeError::InvalidFlag { flag: 0x40 }));
+/// ```
+pub fn decode_path(encoded: &[u8]) -> Result<(Nibbles, bool), PathDecodeError> {
+    let (&flag, rest) = encoded.split_first().ok_or(PathDecodeError::Empty)?;
+    let (is_leaf, is_odd) = match flag & 0xf0 {
+        ExtensionNode::EVEN_FLAG => (false, false),
+        ExtensionNode::ODD_FLAG => (false, true),
+        LeafNode::EVEN_FLAG => (true, false),
+        LeafNode::ODD_FLAG => (true, true),
+        _ => return Err(PathDecodeError::InvalidFlag { flag }),
+    };
+    if !is_odd && flag & 0x0f != 0 {
+        return Err(PathDecodeError::InvalidPadding { flag });
+    }
+    Ok((unpack_path_to_nibbles(is_odd.then_some(flag & 0x0f), rest), is_leaf))
+}
+
 /// # Safety
 ///
 /// `ptr` must be valid for at least `self.len() / 2 + 1` bytes.
@@ -315,6 +446,16 @@ mod tests {
         assert_eq!(TrieNode::decode(&mut &rlp[..]).unwrap(), branch);
     }
 
+    #[test]
+    fn rlp_empty_extension_key() {
+        // An extension with the even flag and no nibbles.
+        let rlp = hex!("c20080");
+        assert!(matches!(
+            TrieNode::decode_raw(&rlp),
+            Err(TrieNodeDecodeError::Rlp { kind: Some(TrieNodeKind::Extension), .. })
+        ));
+    }
+
     #[test]
     fn hashed_encode_path_regression() {
         let nibbles = Nibbles::from_nibbles(hex!("05010406040a040203030f010805020b050c04070003070e0909070f010b0a0805020301070c0a0902040b0f000f0006040a04050f020b090701000a0a040b"));
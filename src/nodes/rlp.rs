@@ -1,11 +1,16 @@
-use alloy_primitives::{hex, keccak256, B256};
-use alloy_rlp::EMPTY_STRING_CODE;
+use super::RlpNodeTooLongError;
+use crate::{KeccakHasher, TrieHasher};
+use alloy_primitives::{hex, B256};
+use alloy_rlp::{Header, EMPTY_STRING_CODE};
 use arrayvec::ArrayVec;
 use core::fmt;
 
 const MAX: usize = 33;
 
 /// An RLP-encoded node.
+///
+/// Nodes are stored inline, in a buffer of [`RlpNode::CAPACITY`] bytes, so creating and cloning
+/// them never allocates.
 #[derive(Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RlpNode(ArrayVec<u8, MAX>);
@@ -13,7 +18,7 @@ pub struct RlpNode(ArrayVec<u8, MAX>);
 impl alloy_rlp::Decodable for RlpNode {
     fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
         let bytes = alloy_rlp::Header::decode_bytes(buf, false)?;
-        Self::from_raw_rlp(bytes)
+        Self::from_raw(bytes).ok_or(alloy_rlp::Error::Custom("RLP node too large"))
     }
 }
 
@@ -40,6 +45,16 @@ impl AsRef<[u8]> for RlpNode {
     }
 }
 
+impl TryFrom<&[u8]> for RlpNode {
+    type Error = RlpNodeTooLongError;
+
+    /// Creates a new RLP-encoded node from the given data. See [`RlpNode::from_raw`].
+    #[inline]
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_raw(data).ok_or(RlpNodeTooLongError { len: data.len() })
+    }
+}
+
 impl fmt::Debug for RlpNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RlpNode({})", hex::encode_prefixed(&self.0))
@@ -47,6 +62,9 @@ impl fmt::Debug for RlpNode {
 }
 
 impl RlpNode {
+    /// The maximum length of an RLP-encoded node, which is the length of an RLP-encoded hash.
+    pub const CAPACITY: usize = MAX;
+
     /// Creates a new RLP-encoded node from the given data.
     ///
     /// Returns `None` if the data is too large (greater than 33 bytes).
@@ -57,21 +75,41 @@ impl RlpNode {
         Some(Self(arr))
     }
 
-    /// Creates a new RLP-encoded node from the given data.
+    /// Creates a new RLP-encoded node from the given data, which must be a single RLP item.
+    ///
+    /// Returns an error if the data is too large (greater than 33 bytes), is not valid RLP, or
+    /// does not span exactly one item.
     #[inline]
     pub fn from_raw_rlp(data: &[u8]) -> alloy_rlp::Result<Self> {
-        Self::from_raw(data).ok_or(alloy_rlp::Error::Custom("RLP node too large"))
+        let node = Self::from_raw(data).ok_or(alloy_rlp::Error::Custom("RLP node too large"))?;
+        let mut payload = data;
+        let header = Header::decode(&mut payload)?;
+        if payload.len() < header.payload_length {
+            return Err(alloy_rlp::Error::InputTooShort);
+        }
+        if payload.len() > header.payload_length {
+            return Err(alloy_rlp::Error::Custom("trailing bytes after RLP node"));
+        }
+        Ok(node)
     }
 
     /// Given an RLP-encoded node, returns it either as `rlp(node)` or `rlp(keccak(rlp(node)))`.
     #[doc(alias = "rlp_node")]
     #[inline]
     pub fn from_rlp(rlp: &[u8]) -> Self {
-        if rlp.len() < 32 {
-            // SAFETY: `rlp` is less than max capacity (33).
+        Self::from_rlp_with_hasher::<KeccakHasher>(rlp)
+    }
+
+    /// Given an RLP-encoded node, returns it either as `rlp(node)` or `rlp(hash(rlp(node)))`,
+    /// using the given [`TrieHasher`] and its [inlining threshold](TrieHasher::INLINE_THRESHOLD).
+    #[inline]
+    pub fn from_rlp_with_hasher<H: TrieHasher>(rlp: &[u8]) -> Self {
+        const { assert!(H::INLINE_THRESHOLD <= MAX, "inlining threshold exceeds RLP node capacity") };
+        if rlp.len() < H::INLINE_THRESHOLD {
+            // SAFETY: `rlp` is less than the threshold, which is at most the max capacity (33).
             unsafe { Self::from_raw(rlp).unwrap_unchecked() }
         } else {
-            Self::word_rlp(&keccak256(rlp))
+            Self::word_rlp(&H::hash(rlp))
         }
     }
 
@@ -84,7 +122,7 @@ impl RlpNode {
         Self(arr)
     }
 
-    /// Returns the RLP-encoded node as a slice.
+    /// Returns the RLP-encoded node as a slice, which is at most [`Self::CAPACITY`] bytes long.
     #[inline]
     pub fn as_slice(&self) -> &[u8] {
         &self.0
@@ -93,12 +131,26 @@ impl RlpNode {
     /// Returns hash if this is an RLP-encoded hash
     #[inline]
     pub fn as_hash(&self) -> Option<B256> {
-        if self.len() == B256::len_bytes() + 1 {
+        if self.is_hash() {
             Some(B256::from_slice(&self.0[1..]))
         } else {
             None
         }
     }
+
+    /// Returns `true` if this is an RLP-encoded hash, i.e. a reference to a node that is too large
+    /// to be inlined.
+    #[inline]
+    pub fn is_hash(&self) -> bool {
+        self.len() == B256::len_bytes() + 1 && self.0[0] == EMPTY_STRING_CODE + 32
+    }
+
+    /// Returns `true` if this is an inlined node, i.e. the RLP encoding of a node that is small
+    /// enough to be embedded in its parent instead of being referenced by its hash.
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        !self.is_empty() && !self.is_hash()
+    }
 }
 
 #[cfg(feature = "arbitrary")]
@@ -123,3 +175,71 @@ impl proptest::arbitrary::Arbitrary for RlpNode {
             .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_rlp() {
+        let hash = B256::repeat_byte(0xab);
+        let node = RlpNode::word_rlp(&hash);
+        assert_eq!(node[0], 0xa0);
+        assert_eq!(node[1..], hash[..]);
+        assert_eq!(node.as_hash(), Some(hash));
+        assert!(node.is_hash());
+        assert!(!node.is_inline());
+        assert_eq!(RlpNode::from_raw_rlp(&node), Ok(node));
+    }
+
+    #[test]
+    fn inline_node() {
+        let node = RlpNode::from_raw_rlp(&hex!("c98320646f8476657262")).unwrap();
+        assert!(node.is_inline());
+        assert!(!node.is_hash());
+        assert_eq!(node.as_hash(), None);
+        assert!(!RlpNode::default().is_inline());
+
+        // A 33-byte list is not a hash, even though it has the length of one.
+        let mut list = vec![0xe0];
+        list.extend([0x01; 32]);
+        let node = RlpNode::from_raw_rlp(&list).unwrap();
+        assert!(node.is_inline());
+        assert_eq!(node.as_hash(), None);
+    }
+
+    #[test]
+    fn from_raw_rlp_validation() {
+        assert_eq!(
+            RlpNode::from_raw_rlp(&[0xc0; 34]),
+            Err(alloy_rlp::Error::Custom("RLP node too large"))
+        );
+        assert_eq!(RlpNode::from_raw_rlp(&[]), Err(alloy_rlp::Error::InputTooShort));
+        assert_eq!(
+            RlpNode::from_raw_rlp(&hex!("c98320646f84766572")),
+            Err(alloy_rlp::Error::InputTooShort)
+        );
+        assert_eq!(
+            RlpNode::from_raw_rlp(&hex!("c2010203")),
+            Err(alloy_rlp::Error::Custom("trailing bytes after RLP node"))
+        );
+        assert!(RlpNode::from_raw_rlp(&hex!("c20102")).is_ok());
+    }
+
+    #[test]
+    fn try_from_slice() {
+        let data = [0xab; RlpNode::CAPACITY];
+        let node = RlpNode::try_from(&data[..]).unwrap();
+        assert_eq!(node.as_slice(), data);
+        assert_eq!(RlpNode::try_from(&[][..]), Ok(RlpNode::default()));
+        assert_eq!(
+            RlpNode::try_from(&[0xab; RlpNode::CAPACITY + 1][..]),
+            Err(RlpNodeTooLongError { len: 34 })
+        );
+
+        // Nodes are stored inline, next to their length.
+        assert!(
+            core::mem::size_of::<RlpNode>() <= RlpNode::CAPACITY + core::mem::size_of::<u32>() + 3
+        );
+    }
+}
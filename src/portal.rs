@@ -0,0 +1,674 @@
+//! Content keys and values of the Portal Network state network.
+//!
+//! The state network distributes the nodes of the account and storage tries, and contract code,
+//! keyed by [`StateContentKey`]. Content is offered along with the proof of its inclusion in the
+//! state at a block, see [`AccountTrieNodeWithProof`], [`ContractTrieNodeWithProof`] and
+//! [`ContractCodeWithProof`], which can be verified against the state root of the block. Content
+//! is retrieved without its proof, see [`TrieNodeRetrieval`] and [`ContractCodeRetrieval`].
+//!
+//! All types are SSZ encoded with [`SszCodec`]. Content IDs, which are the SHA-256 hashes of the
+//! encoded content keys, are left to the client.
+
+use crate::{
+    nodes::TrieNode,
+    ssz::{decode_fields, decode_list, encode_fields, encode_variable, Field, SszCodec, SszError},
+    Nibbles, TrieAccount,
+};
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::Decodable;
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Selector of [`StateContentKey::AccountTrieNode`].
+pub const ACCOUNT_TRIE_NODE_SELECTOR: u8 = 0x20;
+/// Selector of [`StateContentKey::ContractTrieNode`].
+pub const CONTRACT_TRIE_NODE_SELECTOR: u8 = 0x21;
+/// Selector of [`StateContentKey::ContractCode`].
+pub const CONTRACT_CODE_SELECTOR: u8 = 0x22;
+
+/// Maximum length of an encoded trie node.
+pub const MAX_TRIE_NODE_LEN: usize = 1024;
+/// Maximum number of nodes in a proof.
+pub const MAX_TRIE_PROOF_LEN: usize = 65;
+/// Maximum length of contract code.
+pub const MAX_CODE_LEN: usize = 32768;
+/// Maximum length of a packed path, in bytes.
+const MAX_PACKED_PATH_LEN: usize = 33;
+
+/// Error during verification of Portal state network content.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PortalError {
+    /// The proof is empty.
+    EmptyProof,
+    /// The hash of a proof node does not match the hash referenced by its parent, or the root.
+    NodeHashMismatch {
+        /// Index of the node in the proof.
+        index: usize,
+        /// Hash referenced by the parent.
+        expected: B256,
+        /// Hash of the proof node.
+        got: B256,
+    },
+    /// The proof does not follow the path of the content key, or ends before or after it.
+    PathMismatch {
+        /// Index of the node in the proof.
+        index: usize,
+    },
+    /// A proof node could not be decoded.
+    InvalidNode(crate::nodes::TrieNodeDecodeError),
+    /// The account leaf could not be decoded.
+    InvalidAccount(alloy_rlp::Error),
+    /// The contract code doesn't match the code hash of the content key or the account.
+    CodeHashMismatch {
+        /// Expected code hash.
+        expected: B256,
+        /// Hash of the code, or code hash of the account.
+        got: B256,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for PortalError {}
+
+impl fmt::Display for PortalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyProof => f.write_str("empty proof"),
+            Self::NodeHashMismatch { index, expected, got } => write!(
+                f,
+                "proof node hash mismatch at index {index}. got: {got}. expected: {expected}"
+            ),
+            Self::PathMismatch { index } => {
+                write!(f, "proof does not follow the content key path at index {index}")
+            }
+            Self::InvalidNode(error) => write!(f, "invalid proof node: {error}"),
+            Self::InvalidAccount(error) => write!(f, "invalid account leaf: {error}"),
+            Self::CodeHashMismatch { expected, got } => {
+                write!(f, "code hash mismatch. got: {got}. expected: {expected}")
+            }
+        }
+    }
+}
+
+/// The key of state network content.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StateContentKey {
+    /// A node of the account trie.
+    AccountTrieNode(AccountTrieNodeKey),
+    /// A node of a storage trie.
+    ContractTrieNode(ContractTrieNodeKey),
+    /// The code of a contract.
+    ContractCode(ContractCodeKey),
+}
+
+/// The key of a node of the account trie.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AccountTrieNodeKey {
+    /// The path of the node.
+    pub path: Nibbles,
+    /// The hash of the node.
+    pub node_hash: B256,
+}
+
+/// The key of a node of a storage trie.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContractTrieNodeKey {
+    /// The hashed address of the contract.
+    pub address_hash: B256,
+    /// The path of the node in the storage trie.
+    pub path: Nibbles,
+    /// The hash of the node.
+    pub node_hash: B256,
+}
+
+/// The key of the code of a contract.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ContractCodeKey {
+    /// The hashed address of the contract.
+    pub address_hash: B256,
+    /// The hash of the code.
+    pub code_hash: B256,
+}
+
+impl SszCodec for StateContentKey {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::AccountTrieNode(key) => {
+                out.push(ACCOUNT_TRIE_NODE_SELECTOR);
+                let path = encode_packed_path(&key.path);
+                encode_fields(
+                    out,
+                    &[Field::Variable(&path), Field::Fixed(key.node_hash.as_slice())],
+                );
+            }
+            Self::ContractTrieNode(key) => {
+                out.push(CONTRACT_TRIE_NODE_SELECTOR);
+                let path = encode_packed_path(&key.path);
+                encode_fields(
+                    out,
+                    &[
+                        Field::Fixed(key.address_hash.as_slice()),
+                        Field::Variable(&path),
+                        Field::Fixed(key.node_hash.as_slice()),
+                    ],
+                );
+            }
+            Self::ContractCode(key) => {
+                out.push(CONTRACT_CODE_SELECTOR);
+                out.extend_from_slice(key.address_hash.as_slice());
+                out.extend_from_slice(key.code_hash.as_slice());
+            }
+        }
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (&selector, buf) = buf.split_first().ok_or(SszError::UnexpectedEnd)?;
+        match selector {
+            ACCOUNT_TRIE_NODE_SELECTOR => {
+                let [path, node_hash] = decode_fields(buf, [None, Some(32)])?;
+                Ok(Self::AccountTrieNode(AccountTrieNodeKey {
+                    path: decode_packed_path(path)?,
+                    node_hash: B256::from_slice(node_hash),
+                }))
+            }
+            CONTRACT_TRIE_NODE_SELECTOR => {
+                let [address_hash, path, node_hash] =
+                    decode_fields(buf, [Some(32), None, Some(32)])?;
+                Ok(Self::ContractTrieNode(ContractTrieNodeKey {
+                    address_hash: B256::from_slice(address_hash),
+                    path: decode_packed_path(path)?,
+                    node_hash: B256::from_slice(node_hash),
+                }))
+            }
+            CONTRACT_CODE_SELECTOR => {
+                let [address_hash, code_hash] = decode_fields(buf, [Some(32), Some(32)])?;
+                Ok(Self::ContractCode(ContractCodeKey {
+                    address_hash: B256::from_slice(address_hash),
+                    code_hash: B256::from_slice(code_hash),
+                }))
+            }
+            _ => Err(SszError::UnknownSelector(selector)),
+        }
+    }
+}
+
+/// A node of the account trie, offered with the proof of its inclusion in the state at a block.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AccountTrieNodeWithProof {
+    /// The proof nodes, from the root of the account trie down to the offered node.
+    pub proof: Vec<Bytes>,
+    /// The hash of the block.
+    pub block_hash: B256,
+}
+
+impl AccountTrieNodeWithProof {
+    /// Returns the offered node, which is the last node of the proof.
+    pub fn node(&self) -> Option<&Bytes> {
+        self.proof.last()
+    }
+
+    /// Verifies that the offered node is the node of the content key in the account trie with the
+    /// given state root.
+    pub fn verify(&self, key: &AccountTrieNodeKey, state_root: B256) -> Result<(), PortalError> {
+        verify_node_proof(&self.proof, state_root, &key.path, key.node_hash)
+    }
+}
+
+impl SszCodec for AccountTrieNodeWithProof {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let proof = encode_proof(&self.proof);
+        encode_fields(out, &[Field::Variable(&proof), Field::Fixed(self.block_hash.as_slice())]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let [proof, block_hash] = decode_fields(buf, [None, Some(32)])?;
+        Ok(Self { proof: decode_proof(proof)?, block_hash: B256::from_slice(block_hash) })
+    }
+}
+
+/// A node of a storage trie, offered with the proofs of its inclusion in the state at a block.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContractTrieNodeWithProof {
+    /// The proof nodes, from the root of the storage trie down to the offered node.
+    pub storage_proof: Vec<Bytes>,
+    /// The proof of the account of the contract in the account trie.
+    pub account_proof: Vec<Bytes>,
+    /// The hash of the block.
+    pub block_hash: B256,
+}
+
+impl ContractTrieNodeWithProof {
+    /// Returns the offered node, which is the last node of the storage proof.
+    pub fn node(&self) -> Option<&Bytes> {
+        self.storage_proof.last()
+    }
+
+    /// Verifies that the offered node is the node of the content key in the storage trie of the
+    /// contract, whose account is in the account trie with the given state root.
+    pub fn verify(&self, key: &ContractTrieNodeKey, state_root: B256) -> Result<(), PortalError> {
+        let account = verify_account_proof(&self.account_proof, state_root, key.address_hash)?;
+        verify_node_proof(&self.storage_proof, account.storage_root, &key.path, key.node_hash)
+    }
+}
+
+impl SszCodec for ContractTrieNodeWithProof {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let storage_proof = encode_proof(&self.storage_proof);
+        let account_proof = encode_proof(&self.account_proof);
+        encode_fields(
+            out,
+            &[
+                Field::Variable(&storage_proof),
+                Field::Variable(&account_proof),
+                Field::Fixed(self.block_hash.as_slice()),
+            ],
+        );
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let [storage_proof, account_proof, block_hash] =
+            decode_fields(buf, [None, None, Some(32)])?;
+        Ok(Self {
+            storage_proof: decode_proof(storage_proof)?,
+            account_proof: decode_proof(account_proof)?,
+            block_hash: B256::from_slice(block_hash),
+        })
+    }
+}
+
+/// The code of a contract, offered with the proof of its account in the state at a block.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContractCodeWithProof {
+    /// The code.
+    pub code: Bytes,
+    /// The proof of the account of the contract in the account trie.
+    pub account_proof: Vec<Bytes>,
+    /// The hash of the block.
+    pub block_hash: B256,
+}
+
+impl ContractCodeWithProof {
+    /// Verifies that the offered code is the code of the content key, and the code of the
+    /// contract, whose account is in the account trie with the given state root.
+    pub fn verify(&self, key: &ContractCodeKey, state_root: B256) -> Result<(), PortalError> {
+        let code_hash = keccak256(&self.code);
+        if code_hash != key.code_hash {
+            return Err(PortalError::CodeHashMismatch { expected: key.code_hash, got: code_hash });
+        }
+        let account = verify_account_proof(&self.account_proof, state_root, key.address_hash)?;
+        if account.code_hash != code_hash {
+            return Err(PortalError::CodeHashMismatch {
+                expected: code_hash,
+                got: account.code_hash,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl SszCodec for ContractCodeWithProof {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let account_proof = encode_proof(&self.account_proof);
+        encode_fields(
+            out,
+            &[
+                Field::Variable(&self.code),
+                Field::Variable(&account_proof),
+                Field::Fixed(self.block_hash.as_slice()),
+            ],
+        );
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let [code, account_proof, block_hash] = decode_fields(buf, [None, None, Some(32)])?;
+        if code.len() > MAX_CODE_LEN {
+            return Err(SszError::TooLong { max: MAX_CODE_LEN, got: code.len() });
+        }
+        Ok(Self {
+            code: Bytes::copy_from_slice(code),
+            account_proof: decode_proof(account_proof)?,
+            block_hash: B256::from_slice(block_hash),
+        })
+    }
+}
+
+/// A node of the account trie or of a storage trie, as retrieved without its proof.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrieNodeRetrieval {
+    /// The node.
+    pub node: Bytes,
+}
+
+impl SszCodec for TrieNodeRetrieval {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        encode_fields(out, &[Field::Variable(&self.node)]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let [node] = decode_fields(buf, [None])?;
+        Ok(Self { node: decode_trie_node(node)? })
+    }
+}
+
+/// The code of a contract, as retrieved without its proof.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContractCodeRetrieval {
+    /// The code.
+    pub code: Bytes,
+}
+
+impl SszCodec for ContractCodeRetrieval {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        encode_fields(out, &[Field::Variable(&self.code)]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let [code] = decode_fields(buf, [None])?;
+        if code.len() > MAX_CODE_LEN {
+            return Err(SszError::TooLong { max: MAX_CODE_LEN, got: code.len() });
+        }
+        Ok(Self { code: Bytes::copy_from_slice(code) })
+    }
+}
+
+/// Encodes a path as a flag byte followed by its packed nibbles. The flag is `0x00` if the path
+/// has an even number of nibbles, and `0x1` followed by its first nibble otherwise.
+fn encode_packed_path(path: &Nibbles) -> Vec<u8> {
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(0x10 | path[0]);
+        &path[1..]
+    } else {
+        out.push(0x00);
+        &path[..]
+    };
+    out.extend(rest.chunks_exact(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+/// Decodes a path encoded with [`encode_packed_path`].
+fn decode_packed_path(buf: &[u8]) -> Result<Nibbles, SszError> {
+    if buf.len() > MAX_PACKED_PATH_LEN {
+        return Err(SszError::TooLong { max: MAX_PACKED_PATH_LEN, got: buf.len() });
+    }
+    let (&flag, packed) = buf.split_first().ok_or(SszError::UnexpectedEnd)?;
+    let mut nibbles = Vec::with_capacity(packed.len() * 2 + 1);
+    match flag >> 4 {
+        0 if flag == 0 => {}
+        1 => nibbles.push(flag & 0x0f),
+        _ => return Err(SszError::InvalidPathFlag(flag)),
+    }
+    nibbles.extend(packed.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]));
+    Ok(Nibbles::from_nibbles_unchecked(nibbles))
+}
+
+/// Encodes a proof as a list of byte lists.
+fn encode_proof(proof: &[Bytes]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_variable(&mut out, &[], proof);
+    out
+}
+
+/// Decodes a proof encoded with [`encode_proof`].
+fn decode_proof(buf: &[u8]) -> Result<Vec<Bytes>, SszError> {
+    decode_list(buf, MAX_TRIE_PROOF_LEN)?.into_iter().map(decode_trie_node).collect()
+}
+
+/// Decodes a trie node, which is a byte list of at most [`MAX_TRIE_NODE_LEN`] bytes.
+fn decode_trie_node(buf: &[u8]) -> Result<Bytes, SszError> {
+    if buf.len() > MAX_TRIE_NODE_LEN {
+        return Err(SszError::TooLong { max: MAX_TRIE_NODE_LEN, got: buf.len() });
+    }
+    Ok(Bytes::copy_from_slice(buf))
+}
+
+/// Walks the proof from the root along the path, checking that each node is referenced by hash
+/// by its parent. Returns the last node along with the number of nibbles of the path consumed by
+/// the nodes before it.
+fn walk_proof<'a>(
+    proof: &'a [Bytes],
+    root: B256,
+    path: &Nibbles,
+) -> Result<(&'a Bytes, usize), PortalError> {
+    let (last, parents) = proof.split_last().ok_or(PortalError::EmptyProof)?;
+    let mut expected = root;
+    let mut consumed = 0;
+    for (index, node) in parents.iter().chain([last]).enumerate() {
+        let got = keccak256(node);
+        if got != expected {
+            return Err(PortalError::NodeHashMismatch { index, expected, got });
+        }
+        if index == parents.len() {
+            break;
+        }
+
+        let child = match TrieNode::decode_raw(node).map_err(PortalError::InvalidNode)? {
+            TrieNode::Branch(branch) => {
+                let child = path.get(consumed).and_then(|&nibble| branch.child(nibble).cloned());
+                consumed += 1;
+                child
+            }
+            TrieNode::Extension(extension) if path[consumed..].starts_with(&extension.key) => {
+                consumed += extension.key.len();
+                Some(extension.child)
+            }
+            _ => None,
+        };
+        expected =
+            child.and_then(|child| child.as_hash()).ok_or(PortalError::PathMismatch { index })?;
+    }
+    Ok((last, consumed))
+}
+
+/// Verifies that the proof ends at the node with the given path and hash.
+fn verify_node_proof(
+    proof: &[Bytes],
+    root: B256,
+    path: &Nibbles,
+    node_hash: B256,
+) -> Result<(), PortalError> {
+    let (node, consumed) = walk_proof(proof, root, path)?;
+    if consumed != path.len() {
+        return Err(PortalError::PathMismatch { index: proof.len() - 1 });
+    }
+    let got = keccak256(node);
+    if got != node_hash {
+        return Err(PortalError::NodeHashMismatch {
+            index: proof.len() - 1,
+            expected: node_hash,
+            got,
+        });
+    }
+    Ok(())
+}
+
+/// Verifies that the proof ends at the leaf of the account with the given hashed address, and
+/// returns the account.
+fn verify_account_proof(
+    proof: &[Bytes],
+    root: B256,
+    address_hash: B256,
+) -> Result<TrieAccount, PortalError> {
+    let path = Nibbles::unpack(address_hash);
+    let (node, consumed) = walk_proof(proof, root, &path)?;
+    match TrieNode::decode_raw(node).map_err(PortalError::InvalidNode)? {
+        TrieNode::Leaf(leaf) if leaf.key[..] == path[consumed..] => {
+            TrieAccount::decode(&mut &leaf.value[..]).map_err(PortalError::InvalidAccount)
+        }
+        _ => Err(PortalError::PathMismatch { index: proof.len() - 1 }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder, KECCAK_EMPTY};
+    use alloy_primitives::{hex, U256};
+
+    fn path(nibbles: &[u8]) -> Nibbles {
+        Nibbles::from_nibbles(nibbles)
+    }
+
+    /// Builds a trie with the given leaves, returning its root and the proof of the target.
+    fn trie(leaves: &[(B256, Vec<u8>)], target: B256) -> (B256, Vec<Bytes>) {
+        let mut leaves = leaves.to_vec();
+        leaves.sort_by_key(|(key, _)| *key);
+        let retainer = ProofRetainer::from_iter([Nibbles::unpack(target)]);
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &leaves {
+            hb.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hb.root();
+        let mut nodes = hb.take_proof_nodes().into_nodes_sorted();
+        nodes.retain(|(path, _)| Nibbles::unpack(target).starts_with(path));
+        (root, nodes.into_iter().map(|(_, node)| node).collect())
+    }
+
+    #[test]
+    fn content_key_encoding() {
+        let key = StateContentKey::AccountTrieNode(AccountTrieNodeKey {
+            path: path(&[1, 2, 0, 1]),
+            node_hash: B256::repeat_byte(0xab),
+        });
+        let encoded = key.ssz_encoded();
+        assert_eq!(encoded[..5], hex!("20 24000000"));
+        assert_eq!(encoded[37..], hex!("00 1201"));
+        assert_eq!(StateContentKey::ssz_decode(&encoded), Ok(key));
+
+        let key = StateContentKey::ContractTrieNode(ContractTrieNodeKey {
+            address_hash: B256::repeat_byte(0x01),
+            path: path(&[1, 2, 0]),
+            node_hash: B256::repeat_byte(0xab),
+        });
+        let encoded = key.ssz_encoded();
+        assert_eq!(encoded[1 + 32..1 + 36], hex!("44000000"));
+        assert_eq!(encoded[69..], hex!("11 20"));
+        assert_eq!(StateContentKey::ssz_decode(&encoded), Ok(key));
+
+        let key = StateContentKey::ContractCode(ContractCodeKey {
+            address_hash: B256::repeat_byte(0x01),
+            code_hash: B256::repeat_byte(0x02),
+        });
+        assert_eq!(StateContentKey::ssz_decode(&key.ssz_encoded()), Ok(key));
+
+        assert_eq!(StateContentKey::ssz_decode(&[0x23]), Err(SszError::UnknownSelector(0x23)));
+        let mut invalid = StateContentKey::AccountTrieNode(AccountTrieNodeKey {
+            path: path(&[]),
+            node_hash: B256::ZERO,
+        })
+        .ssz_encoded();
+        invalid[37] = 0x20;
+        assert_eq!(StateContentKey::ssz_decode(&invalid), Err(SszError::InvalidPathFlag(0x20)));
+    }
+
+    #[test]
+    fn content_value_roundtrip() {
+        let proof = vec![Bytes::from_static(&[0xc0; 40]), Bytes::from_static(&[0xc1; 35])];
+        let value = AccountTrieNodeWithProof { proof: proof.clone(), block_hash: B256::ZERO };
+        assert_eq!(AccountTrieNodeWithProof::ssz_decode(&value.ssz_encoded()), Ok(value));
+
+        let value = ContractTrieNodeWithProof {
+            storage_proof: proof.clone(),
+            account_proof: proof[..1].to_vec(),
+            block_hash: B256::repeat_byte(1),
+        };
+        assert_eq!(ContractTrieNodeWithProof::ssz_decode(&value.ssz_encoded()), Ok(value));
+
+        let value = ContractCodeWithProof {
+            code: Bytes::from_static(&[0x60, 0x00]),
+            account_proof: proof,
+            block_hash: B256::repeat_byte(2),
+        };
+        assert_eq!(ContractCodeWithProof::ssz_decode(&value.ssz_encoded()), Ok(value));
+
+        let value = TrieNodeRetrieval { node: Bytes::from_static(&[0xc0; 40]) };
+        assert_eq!(value.ssz_encoded()[..4], hex!("04000000"));
+        assert_eq!(TrieNodeRetrieval::ssz_decode(&value.ssz_encoded()), Ok(value));
+        let value = ContractCodeRetrieval { code: Bytes::from_static(&[0x60, 0x00]) };
+        assert_eq!(ContractCodeRetrieval::ssz_decode(&value.ssz_encoded()), Ok(value));
+
+        let too_long = TrieNodeRetrieval { node: vec![0; MAX_TRIE_NODE_LEN + 1].into() };
+        assert!(matches!(
+            TrieNodeRetrieval::ssz_decode(&too_long.ssz_encoded()),
+            Err(SszError::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_content() {
+        let code = Bytes::from_static(&[0x60, 0x00, 0x60, 0x00]);
+        let address_hash = keccak256([0x42]);
+        let slot = keccak256([0x01]);
+        let storage = (0u8..16)
+            .map(|i| (keccak256([i]), alloy_rlp::encode(U256::from(i + 1))))
+            .collect::<Vec<_>>();
+        let (storage_root, storage_proof) = trie(&storage, slot);
+
+        let account = TrieAccount {
+            nonce: 1,
+            balance: U256::from(10),
+            storage_root,
+            code_hash: keccak256(&code),
+        };
+        let mut accounts = (0u8..32)
+            .map(|i| {
+                let other = TrieAccount { nonce: i as u64, code_hash: KECCAK_EMPTY, ..account };
+                (keccak256([i, i]), alloy_rlp::encode(other))
+            })
+            .collect::<Vec<_>>();
+        accounts.push((address_hash, alloy_rlp::encode(account)));
+        let (state_root, account_proof) = trie(&accounts, address_hash);
+
+        // The root node of the account trie is offered at the empty path.
+        let root_node = account_proof[0].clone();
+        let key = AccountTrieNodeKey { path: Nibbles::default(), node_hash: state_root };
+        let value = AccountTrieNodeWithProof { proof: vec![root_node], block_hash: B256::ZERO };
+        assert_eq!(value.verify(&key, state_root), Ok(()));
+
+        // The second node of the account proof is offered at the first nibble of the address hash.
+        let key = AccountTrieNodeKey {
+            path: Nibbles::unpack(address_hash).slice(..1),
+            node_hash: keccak256(&account_proof[1]),
+        };
+        let value =
+            AccountTrieNodeWithProof { proof: account_proof[..2].to_vec(), block_hash: B256::ZERO };
+        assert_eq!(value.verify(&key, state_root), Ok(()));
+        let wrong_path = AccountTrieNodeKey { path: path(&[0xf, 0xf]), ..key.clone() };
+        assert!(matches!(
+            value.verify(&wrong_path, state_root),
+            Err(PortalError::PathMismatch { .. })
+        ));
+        assert!(matches!(
+            value.verify(&key, B256::ZERO),
+            Err(PortalError::NodeHashMismatch { index: 0, .. })
+        ));
+
+        // The leaf of the slot in the storage trie.
+        let leaf = storage_proof.last().unwrap();
+        let key = ContractTrieNodeKey {
+            address_hash,
+            path: Nibbles::unpack(slot).slice(..storage_proof.len() - 1),
+            node_hash: keccak256(leaf),
+        };
+        let value = ContractTrieNodeWithProof {
+            storage_proof,
+            account_proof: account_proof.clone(),
+            block_hash: B256::ZERO,
+        };
+        assert_eq!(value.verify(&key, state_root), Ok(()));
+
+        // The code of the contract.
+        let key = ContractCodeKey { address_hash, code_hash: keccak256(&code) };
+        let value = ContractCodeWithProof { code, account_proof, block_hash: B256::ZERO };
+        assert_eq!(value.verify(&key, state_root), Ok(()));
+        let other = ContractCodeWithProof { code: Bytes::from_static(&[0x00]), ..value };
+        assert!(matches!(
+            other.verify(&key, state_root),
+            Err(PortalError::CodeHashMismatch { .. })
+        ));
+    }
+}
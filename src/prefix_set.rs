@@ -0,0 +1,219 @@
+//! Sets of changed key prefixes, used to find the parts of the trie that need to be recomputed.
+
+use crate::{HashMap, Nibbles};
+use alloc::{sync::Arc, vec::Vec};
+use alloy_primitives::{map::HashSet, B256};
+
+/// A mutable set of changed keys, frozen into a [`PrefixSet`] for lookups.
+///
+/// Keys can be inserted in any order and more than once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrefixSetMut {
+    /// Whether every key is considered changed.
+    all: bool,
+    /// The changed keys.
+    keys: Vec<Nibbles>,
+}
+
+impl<I> From<I> for PrefixSetMut
+where
+    I: IntoIterator<Item = Nibbles>,
+{
+    fn from(keys: I) -> Self {
+        Self { all: false, keys: keys.into_iter().collect() }
+    }
+}
+
+impl Extend<Nibbles> for PrefixSetMut {
+    fn extend<T: IntoIterator<Item = Nibbles>>(&mut self, keys: T) {
+        self.keys.extend(keys);
+    }
+}
+
+impl PrefixSetMut {
+    /// Creates a new, empty set with the given capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { all: false, keys: Vec::with_capacity(capacity) }
+    }
+
+    /// Creates a set that contains every prefix, meaning the whole trie is considered changed.
+    pub const fn all() -> Self {
+        Self { all: true, keys: Vec::new() }
+    }
+
+    /// Inserts a changed key.
+    pub fn insert(&mut self, key: Nibbles) {
+        self.keys.push(key);
+    }
+
+    /// Merges another set into this one.
+    pub fn extend_from(&mut self, other: Self) {
+        self.all |= other.all;
+        self.keys.extend(other.keys);
+    }
+
+    /// Returns the number of inserted keys, including duplicates.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if no keys were inserted.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Removes all keys.
+    pub fn clear(&mut self) {
+        self.all = false;
+        self.keys.clear();
+    }
+
+    /// Sorts and deduplicates the keys, returning the immutable set.
+    pub fn freeze(mut self) -> PrefixSet {
+        if self.all {
+            return PrefixSet { all: true, keys: Arc::default(), index: 0 };
+        }
+        self.keys.sort_unstable();
+        self.keys.dedup();
+        self.keys.shrink_to_fit();
+        PrefixSet { all: false, keys: Arc::new(self.keys), index: 0 }
+    }
+}
+
+/// A sorted, deduplicated set of changed keys, queried by prefix.
+///
+/// Lookups are expected to be performed in increasing key order, as done when walking the trie.
+/// The set keeps track of the position of the last lookup, so that a walk over the whole trie
+/// only traverses the keys once. The keys are shared between clones of the set, while each clone
+/// has its own position.
+#[derive(Clone, Debug, Default)]
+pub struct PrefixSet {
+    /// Whether every key is considered changed.
+    all: bool,
+    /// The sorted, deduplicated changed keys.
+    keys: Arc<Vec<Nibbles>>,
+    /// The position of the last lookup.
+    index: usize,
+}
+
+impl FromIterator<Nibbles> for PrefixSet {
+    fn from_iter<T: IntoIterator<Item = Nibbles>>(iter: T) -> Self {
+        PrefixSetMut::from(iter).freeze()
+    }
+}
+
+impl<'a> IntoIterator for &'a PrefixSet {
+    type Item = &'a Nibbles;
+    type IntoIter = core::slice::Iter<'a, Nibbles>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl PrefixSet {
+    /// Returns `true` if any of the keys in the set has the given prefix.
+    pub fn contains(&mut self, prefix: &[u8]) -> bool {
+        if self.all {
+            return true;
+        }
+
+        while self.index > 0 && self.keys[self.index].as_slice() > prefix {
+            self.index -= 1;
+        }
+
+        for (idx, key) in self.keys[self.index..].iter().enumerate() {
+            if key.has_prefix(prefix) {
+                self.index += idx;
+                return true;
+            }
+            if key.as_slice() > prefix {
+                self.index += idx;
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// Returns `true` if every key is considered changed.
+    pub const fn all(&self) -> bool {
+        self.all
+    }
+
+    /// Returns an iterator over the keys in ascending order.
+    pub fn iter(&self) -> core::slice::Iter<'_, Nibbles> {
+        self.keys.iter()
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the set has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// The changed keys of the account trie and of the storage tries, which drive an incremental
+/// state root computation. See [`StateRoot`](crate::root::StateRoot).
+#[derive(Clone, Debug, Default)]
+pub struct TriePrefixSets {
+    /// The hashed addresses of the changed accounts, including the accounts whose storage
+    /// changed.
+    pub account_prefix_set: PrefixSet,
+    /// The hashed slots of the changed storage slots, keyed by hashed address.
+    pub storage_prefix_sets: HashMap<B256, PrefixSet>,
+    /// The hashed addresses of the accounts whose storage was wiped, e.g. because the account
+    /// was destroyed.
+    pub destroyed_accounts: HashSet<B256>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_with_cursor() {
+        let mut set = PrefixSetMut::default();
+        for key in [[1, 2, 3], [1, 2, 4], [1, 2, 5], [1, 2, 3], [2, 3, 4]] {
+            set.insert(Nibbles::from_nibbles(key));
+        }
+        assert_eq!(set.len(), 5);
+        let mut set = set.freeze();
+        assert_eq!(set.len(), 4);
+
+        assert!(set.contains(&[1, 2]));
+        assert!(set.contains(&[1, 2, 5]));
+        assert!(!set.contains(&[1, 2, 6]));
+        // Lookups behind the cursor still work.
+        assert!(set.contains(&[1, 2, 3]));
+        assert!(!set.contains(&[1, 3]));
+        assert!(set.contains(&[2]));
+        assert!(!set.contains(&[3]));
+        assert!(set.contains(&[]));
+    }
+
+    #[test]
+    fn freeze() {
+        let keys = [[3, 1], [1, 2], [3, 1], [0, 4]].map(Nibbles::from_nibbles);
+        let set = keys.iter().cloned().collect::<PrefixSet>();
+        assert!(!set.all());
+        assert_eq!(
+            set.iter().map(|key| key.to_vec()).collect::<Vec<_>>(),
+            [vec![0, 4], vec![1, 2], vec![3, 1]]
+        );
+
+        let mut all = PrefixSetMut::from(keys.clone());
+        all.extend_from(PrefixSetMut::all());
+        let mut all = all.freeze();
+        assert!(all.all());
+        assert!(all.is_empty());
+        assert!(all.contains(&[0xf]));
+
+        let mut empty = PrefixSetMut::default().freeze();
+        assert!(!empty.contains(&[]));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{
+    nodes::TrieNode,
+    proof::{verify_proof, AccountProofError, MultiProof, ProofVerificationError},
+    Nibbles, TrieAccount, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_rlp::Decodable;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The proof of a storage slot, as returned by `eth_getProof`.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageProof {
+    /// The storage slot, which is hashed to obtain the key in the storage trie.
+    pub key: B256,
+    /// The value of the slot. Zero if the slot is empty.
+    pub value: U256,
+    /// The nodes on the path to the slot in the storage trie, starting at the root.
+    pub proof: Vec<Bytes>,
+}
+
+impl StorageProof {
+    /// Creates the proof of the given slot with the given value and proof nodes.
+    pub const fn new(key: B256, value: U256, proof: Vec<Bytes>) -> Self {
+        Self { key, value, proof }
+    }
+
+    /// Returns `true` if the slot is in the storage trie, i.e. has a non-zero value. Otherwise,
+    /// the proof is an exclusion proof.
+    pub fn exists(&self) -> bool {
+        !self.value.is_zero()
+    }
+
+    /// Verifies the proof against the storage root of the account, which is the empty root if the
+    /// account does not exist.
+    ///
+    /// A zero value is verified as absent from the storage trie.
+    pub fn verify(&self, storage_root: B256) -> Result<(), ProofVerificationError> {
+        let expected = self.exists().then(|| alloy_rlp::encode(self.value));
+        verify_proof(storage_root, Nibbles::unpack(keccak256(self.key)), expected, &self.proof)
+    }
+}
+
+/// The proof of an account and its storage slots in the shape of the `eth_getProof` response, as
+/// specified in [EIP-1186](https://eips.ethereum.org/EIPS/eip-1186).
+///
+/// With the `serde` feature, this serializes to the JSON-RPC response, with hex-encoded proof
+/// nodes and quantities.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct AccountProof {
+    /// The address of the account.
+    pub address: Address,
+    /// The nodes on the path to the account in the state trie, starting at the root.
+    pub account_proof: Vec<Bytes>,
+    /// Account balance.
+    pub balance: U256,
+    /// Hash of the account code.
+    pub code_hash: B256,
+    /// Account nonce.
+    #[cfg_attr(feature = "serde", serde(with = "quantity"))]
+    pub nonce: u64,
+    /// Root of the account storage trie.
+    pub storage_hash: B256,
+    /// The proofs of the requested storage slots.
+    pub storage_proof: Vec<StorageProof>,
+}
+
+impl AccountProof {
+    /// Assembles the proof of the account and the given storage slots from the multiproof, which
+    /// must have been retained for the hashed address of the account and the hashed slots.
+    ///
+    /// The fields of a missing account are set to the values of an empty account.
+    pub fn from_multiproof(
+        address: Address,
+        account: Option<TrieAccount>,
+        multiproof: &MultiProof,
+        slots: impl IntoIterator<Item = (B256, U256)>,
+    ) -> Self {
+        let hashed_address = keccak256(address);
+        let TrieAccount { nonce, balance, storage_root, code_hash } = account.unwrap_or_default();
+        let storage_proof = slots
+            .into_iter()
+            .map(|(key, value)| StorageProof {
+                key,
+                value,
+                proof: multiproof
+                    .storage_proof(&hashed_address, keccak256(key))
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Self {
+            address,
+            account_proof: multiproof.account_proof(hashed_address),
+            balance,
+            code_hash,
+            nonce,
+            storage_hash: storage_root,
+            storage_proof,
+        }
+    }
+
+    /// Verifies the proof against the state root. See [`verify_account_proof`].
+    ///
+    /// The proven account must match the account fields of the response, which are not part of
+    /// the proof itself.
+    pub fn verify(&self, state_root: B256) -> Result<VerifiedAccount, AccountProofError> {
+        let verified = verify_account_proof(
+            state_root,
+            self.address,
+            &self.account_proof,
+            &self.storage_proof,
+        )?;
+        let TrieAccount { nonce, balance, storage_root, code_hash } =
+            verified.account.unwrap_or_default();
+        if (nonce, balance, storage_root, code_hash)
+            != (self.nonce, self.balance, self.storage_hash, self.code_hash)
+        {
+            return Err(AccountProofError::AccountMismatch);
+        }
+        Ok(verified)
+    }
+}
+
+/// Serializes a `u64` as a hex quantity.
+#[cfg(feature = "serde")]
+mod quantity {
+    use alloy_primitives::U64;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        U64::from(*value).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        U64::deserialize(deserializer).map(|value| value.to())
+    }
+}
+
+/// An account and its storage slots, verified against a state root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VerifiedAccount {
+    /// The address of the account.
+    pub address: Address,
+    /// The account, or [`None`] if it does not exist.
+    pub account: Option<TrieAccount>,
+    /// The verified storage slots and their values.
+    pub storage: Vec<(B256, U256)>,
+}
+
+impl VerifiedAccount {
+    /// Returns the storage root of the account, which is the empty root if it does not exist.
+    pub fn storage_root(&self) -> B256 {
+        self.account.map_or(EMPTY_ROOT_HASH, |account| account.storage_root)
+    }
+}
+
+/// Verifies the proof of an account and its storage slots against the state root, as returned by
+/// `eth_getProof`.
+///
+/// The account is read from the leaf at the end of the account proof, and the storage proofs are
+/// verified against its storage root. The proof of a missing account is accepted, in which case
+/// the storage proofs must be exclusion proofs against the empty root.
+pub fn verify_account_proof(
+    state_root: B256,
+    address: Address,
+    account_proof: &[Bytes],
+    storage_proofs: &[StorageProof],
+) -> Result<VerifiedAccount, AccountProofError> {
+    let key = Nibbles::unpack(keccak256(address));
+    let value = leaf_value(&key, account_proof);
+    let account = value
+        .as_deref()
+        .map(|mut value| TrieAccount::decode(&mut value))
+        .transpose()
+        .map_err(AccountProofError::InvalidAccount)?;
+    verify_proof(state_root, key, value, account_proof).map_err(AccountProofError::Account)?;
+
+    let mut verified = VerifiedAccount { address, account, storage: Vec::new() };
+    let storage_root = verified.storage_root();
+    for proof in storage_proofs {
+        proof
+            .verify(storage_root)
+            .map_err(|error| AccountProofError::Storage { slot: proof.key, error })?;
+        verified.storage.push((proof.key, proof.value));
+    }
+    Ok(verified)
+}
+
+/// Returns the value of the leaf at the end of the proof, if its path matches the key.
+fn leaf_value(key: &Nibbles, proof: &[Bytes]) -> Option<Vec<u8>> {
+    let mut path = Nibbles::default();
+    for node in proof {
+        match TrieNode::decode_raw(node).ok()? {
+            TrieNode::Branch(_) => path.push(*key.get(path.len())?),
+            TrieNode::Extension(extension) => path.extend_from_slice(&extension.key),
+            TrieNode::Leaf(leaf) => {
+                path.extend_from_slice(&leaf.key);
+                return (path == *key).then_some(leaf.value);
+            }
+            TrieNode::EmptyRoot => return None,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{ProofRetainer, StorageMultiProof},
+        HashBuilder,
+    };
+    use alloc::collections::BTreeMap;
+
+    /// Builds the trie from the leaves, returning its root and the proof of the target.
+    fn build(leaves: &BTreeMap<Nibbles, Vec<u8>>, target: &Nibbles) -> (B256, Vec<Bytes>) {
+        let retainer = ProofRetainer::from_iter([target.clone()]);
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+        (root, proof.into_iter().map(|(_, node)| node).collect())
+    }
+
+    #[test]
+    fn account_with_storage() {
+        let slots = (0..20u64).map(|i| B256::from(U256::from(i))).collect::<Vec<_>>();
+        let storage = slots
+            .iter()
+            .map(|slot| (Nibbles::unpack(keccak256(slot)), alloy_rlp::encode(U256::from(7))))
+            .collect::<BTreeMap<_, _>>();
+        let storage_proof = |slot: B256, value| {
+            StorageProof::new(slot, value, build(&storage, &Nibbles::unpack(keccak256(slot))).1)
+        };
+        let (storage_root, _) = build(&storage, &Nibbles::default());
+
+        let present = storage_proof(slots[3], U256::from(7));
+        assert!(present.exists());
+        assert_eq!(present.verify(storage_root), Ok(()));
+        let absent = storage_proof(B256::repeat_byte(0xff), U256::ZERO);
+        assert!(!absent.exists());
+        assert_eq!(absent.verify(storage_root), Ok(()));
+        assert!(matches!(
+            StorageProof { value: U256::ZERO, ..present.clone() }.verify(storage_root),
+            Err(ProofVerificationError::ValueMismatch { .. })
+        ));
+        assert!(StorageProof::new(slots[3], U256::from(7), vec![]).verify(storage_root).is_err());
+
+        let address = Address::repeat_byte(0x42);
+        let account = TrieAccount { nonce: 1, storage_root, ..Default::default() };
+        let mut accounts = (0..20u8)
+            .map(|i| (Nibbles::unpack(keccak256([i])), alloy_rlp::encode(TrieAccount::default())))
+            .collect::<BTreeMap<_, _>>();
+        accounts.insert(Nibbles::unpack(keccak256(address)), alloy_rlp::encode(account));
+        let (state_root, account_proof) = build(&accounts, &Nibbles::unpack(keccak256(address)));
+        assert_eq!(
+            verify_account_proof(
+                state_root,
+                address,
+                &account_proof,
+                &[present.clone(), absent.clone()]
+            ),
+            Ok(VerifiedAccount {
+                address,
+                account: Some(account),
+                storage: vec![(present.key, present.value), (absent.key, absent.value)],
+            })
+        );
+
+        let wrong_value = StorageProof { value: U256::from(8), ..present };
+        assert!(matches!(
+            verify_account_proof(state_root, address, &account_proof, &[wrong_value]),
+            Err(AccountProofError::Storage { slot, .. }) if slot == slots[3]
+        ));
+        assert!(matches!(
+            verify_account_proof(B256::ZERO, address, &account_proof, &[]),
+            Err(AccountProofError::Account(_))
+        ));
+
+        // A missing account has an empty storage.
+        let missing = Address::repeat_byte(0x43);
+        let (_, missing_proof) = build(&accounts, &Nibbles::unpack(keccak256(missing)));
+        let empty_slot = StorageProof { key: slots[0], ..Default::default() };
+        assert_eq!(
+            verify_account_proof(state_root, missing, &missing_proof, &[empty_slot]),
+            Ok(VerifiedAccount {
+                address: missing,
+                account: None,
+                storage: vec![(slots[0], U256::ZERO)]
+            })
+        );
+    }
+
+    #[test]
+    fn eip1186_response() {
+        let address = Address::repeat_byte(0x42);
+        let hashed_address = keccak256(address);
+        let slot = B256::with_last_byte(1);
+        let hashed_slot = Nibbles::unpack(keccak256(slot));
+
+        let mut storage_builder = HashBuilder::default()
+            .with_proof_retainer(ProofRetainer::from_iter([hashed_slot.clone()]));
+        storage_builder.add_leaf(hashed_slot, &alloy_rlp::encode(U256::from(5)));
+        let storage_root = storage_builder.root();
+
+        let account = TrieAccount { nonce: 3, storage_root, ..Default::default() };
+        let mut account_builder = HashBuilder::default()
+            .with_proof_retainer(ProofRetainer::from_iter([Nibbles::unpack(hashed_address)]));
+        account_builder.add_leaf(Nibbles::unpack(hashed_address), &alloy_rlp::encode(account));
+        let state_root = account_builder.root();
+
+        let mut multiproof = MultiProof::new(account_builder.take_proof_nodes());
+        multiproof.insert_storage(
+            hashed_address,
+            StorageMultiProof::new(storage_root, storage_builder.take_proof_nodes()),
+        );
+        let response = AccountProof::from_multiproof(
+            address,
+            Some(account),
+            &multiproof,
+            [(slot, U256::from(5))],
+        );
+        assert_eq!(response.nonce, 3);
+        assert_eq!(response.storage_hash, storage_root);
+        assert_eq!(response.verify(state_root).map(|verified| verified.account), Ok(Some(account)));
+
+        let tampered = AccountProof { nonce: 4, ..response.clone() };
+        assert_eq!(tampered.verify(state_root), Err(AccountProofError::AccountMismatch));
+
+        #[cfg(feature = "serde")]
+        {
+            use alloc::string::ToString;
+
+            let json = serde_json::to_value(&response).unwrap();
+            assert_eq!(json["nonce"], "0x3");
+            assert_eq!(json["balance"], "0x0");
+            assert_eq!(json["storageHash"], storage_root.to_string());
+            assert_eq!(json["accountProof"][0], response.account_proof[0].to_string());
+            assert_eq!(json["storageProof"][0]["key"], slot.to_string());
+            assert_eq!(json["storageProof"][0]["value"], "0x5");
+            assert_eq!(serde_json::from_value::<AccountProof>(json).unwrap(), response);
+        }
+    }
+}
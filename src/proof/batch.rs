@@ -0,0 +1,361 @@
+use crate::{
+    nodes::{RlpNode, TrieNode},
+    proof::ProofVerificationError,
+    HashMap, KeccakHasher, TrieHasher,
+};
+use alloy_primitives::{Bytes, B256};
+use alloy_rlp::{Decodable, EMPTY_STRING_CODE};
+use nybbles::Nibbles;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Verify the proofs for the given key value pairs against the provided state root, returning
+/// the result for each target in order.
+///
+/// The proof is an unordered collection of the nodes on the paths to all targets, e.g. the union
+/// of the proofs of an `eth_getProof` response. Each node is decoded at most once, and targets
+/// are walked in key order, so that the path shared with the previous target is not walked
+/// again.
+///
+/// The results are the same as those of [`verify_proof`](crate::proof::verify_proof) for each
+/// target, except that nodes inlined in their parent are always read from the parent, and a
+/// missing node is reported as a [`ProofVerificationError::ValueMismatch`] at the path of the
+/// node, with the reference to it as the expected value.
+pub fn verify_proofs<'a, I>(
+    root: B256,
+    targets: &[(Nibbles, Option<Vec<u8>>)],
+    proof: I,
+) -> Vec<Result<(), ProofVerificationError>>
+where
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    verify_proofs_with_hasher::<KeccakHasher, _>(root, targets, proof)
+}
+
+/// Verify the proofs for the given key value pairs against the provided root of a trie hashed
+/// with the given [`TrieHasher`].
+///
+/// See [`verify_proofs`] for details.
+pub fn verify_proofs_with_hasher<'a, H, I>(
+    root: B256,
+    targets: &[(Nibbles, Option<Vec<u8>>)],
+    proof: I,
+) -> Vec<Result<(), ProofVerificationError>>
+where
+    H: TrieHasher,
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    let mut results = Vec::with_capacity(targets.len());
+    results.resize_with(targets.len(), || Ok(()));
+
+    if root == H::empty_root() {
+        for (result, (_, expected_value)) in results.iter_mut().zip(targets) {
+            if expected_value.is_some() {
+                *result = Err(ProofVerificationError::KeyAbsent {
+                    divergence_node: Bytes::from_static(&[EMPTY_STRING_CODE]),
+                    consumed_nibbles: Nibbles::default(),
+                });
+            }
+        }
+        return results;
+    }
+
+    let mut verifier = BatchVerifier::<H> {
+        nodes: proof.into_iter().map(|node| (H::hash(node), node)).collect(),
+        decoded: HashMap::default(),
+        _hasher: core::marker::PhantomData,
+    };
+
+    let mut order = (0..targets.len()).collect::<Vec<_>>();
+    order.sort_unstable_by(|a, b| targets[*a].0.cmp(&targets[*b].0));
+
+    // The nodes on the path to the previous target, which are shared with the next target up to
+    // their common prefix.
+    let mut path = Vec::new();
+    let mut previous_key: Option<&Nibbles> = None;
+    for index in order {
+        let (key, expected_value) = &targets[index];
+        let common = previous_key.map_or(0, |previous| previous.common_prefix_length(key));
+        while path.last().is_some_and(|entry: &PathEntry<'_>| entry.depth > common) {
+            path.pop();
+        }
+        results[index] = verifier.verify(root, key, expected_value.as_deref(), &mut path);
+        previous_key = Some(key);
+    }
+    results
+}
+
+/// A reference to a node on the path to a key.
+#[derive(Clone, Debug)]
+enum NodeRef {
+    /// The node is in the proof, referenced by its hash.
+    Hash(B256),
+    /// The node is inlined in its parent.
+    Inline(RlpNode),
+}
+
+/// A node on the path to a key.
+#[derive(Clone, Debug)]
+struct PathEntry<'a> {
+    /// The number of key nibbles walked before the node.
+    depth: usize,
+    /// The reference to the node.
+    node: NodeRef,
+    /// The proof node that contains the node, which is the node itself if it's not inlined.
+    proof_node: Option<&'a Bytes>,
+}
+
+/// The result of stepping through a node on the path to a key.
+enum Step {
+    /// The path continues at the given node, after the given number of key nibbles.
+    Next(usize, NodeRef),
+    /// The path diverges from the key after the given number of key nibbles.
+    Absent(usize),
+    /// The path ends at the key, with a value matching the expected one if `true`.
+    Found(bool, Bytes),
+}
+
+#[derive(Debug)]
+struct BatchVerifier<'a, H> {
+    /// Proof nodes by hash.
+    nodes: HashMap<B256, &'a Bytes>,
+    /// Decoded proof nodes by hash.
+    decoded: HashMap<B256, TrieNode>,
+    _hasher: core::marker::PhantomData<H>,
+}
+
+impl<'a, H: TrieHasher> BatchVerifier<'a, H> {
+    /// Verifies a single target, resuming from the last node of the given path, which must be a
+    /// prefix of the key.
+    fn verify(
+        &mut self,
+        root: B256,
+        key: &Nibbles,
+        expected_value: Option<&[u8]>,
+        path: &mut Vec<PathEntry<'a>>,
+    ) -> Result<(), ProofVerificationError> {
+        let mut entry = match path.last() {
+            Some(entry) => entry.clone(),
+            None => PathEntry { depth: 0, node: NodeRef::Hash(root), proof_node: None },
+        };
+        let mut resumed = !path.is_empty();
+        loop {
+            if !resumed {
+                let proof_node = match &entry.node {
+                    NodeRef::Hash(hash) => match self.nodes.get(hash) {
+                        Some(node) => Some(*node),
+                        None => {
+                            return Err(ProofVerificationError::ValueMismatch {
+                                path: key.slice(..entry.depth),
+                                got: None,
+                                expected: Some(Bytes::copy_from_slice(&RlpNode::word_rlp(hash))),
+                            })
+                        }
+                    },
+                    NodeRef::Inline(_) => entry.proof_node,
+                };
+                entry.proof_node = proof_node;
+                path.push(entry.clone());
+            }
+            resumed = false;
+
+            match self.step(&entry.node, entry.depth, key, expected_value)? {
+                Step::Next(depth, node) => {
+                    entry = PathEntry { depth, node, proof_node: entry.proof_node };
+                }
+                Step::Absent(consumed) => {
+                    return match expected_value {
+                        None => Ok(()),
+                        Some(_) => Err(ProofVerificationError::KeyAbsent {
+                            divergence_node: entry.proof_node.cloned().unwrap_or_default(),
+                            consumed_nibbles: key.slice(..consumed),
+                        }),
+                    };
+                }
+                Step::Found(true, _) => return Ok(()),
+                Step::Found(false, value) => {
+                    return Err(ProofVerificationError::ValueMismatch {
+                        path: key.clone(),
+                        got: Some(value),
+                        expected: expected_value.map(Bytes::copy_from_slice),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Decodes the node, if it wasn't decoded before, and steps through it along the key.
+    fn step(
+        &mut self,
+        node: &NodeRef,
+        depth: usize,
+        key: &Nibbles,
+        expected_value: Option<&[u8]>,
+    ) -> Result<Step, ProofVerificationError> {
+        let inline;
+        let node = match node {
+            NodeRef::Hash(hash) => {
+                if !self.decoded.contains_key(hash) {
+                    let decoded = TrieNode::decode(&mut &self.nodes[hash][..])?;
+                    self.decoded.insert(*hash, decoded);
+                }
+                &self.decoded[hash]
+            }
+            NodeRef::Inline(rlp) => {
+                inline = TrieNode::decode(&mut &rlp[..])?;
+                &inline
+            }
+        };
+
+        let rest = &key[depth..];
+        Ok(match node {
+            TrieNode::EmptyRoot => return Err(ProofVerificationError::UnexpectedEmptyRoot),
+            TrieNode::Branch(branch) => match rest.first().and_then(|nibble| branch.child(*nibble))
+            {
+                Some(child) => Step::Next(depth + 1, child_ref(child)),
+                None => Step::Absent(depth),
+            },
+            TrieNode::Extension(extension) => {
+                if rest.starts_with(&extension.key) {
+                    Step::Next(depth + extension.key.len(), child_ref(&extension.child))
+                } else {
+                    Step::Absent(depth + extension.key.common_prefix_length(rest))
+                }
+            }
+            TrieNode::Leaf(leaf) => {
+                if rest == &leaf.key[..] {
+                    let matches = expected_value == Some(&leaf.value[..]);
+                    Step::Found(
+                        matches,
+                        if matches { Bytes::new() } else { leaf.value.clone().into() },
+                    )
+                } else {
+                    Step::Absent(depth + leaf.key.common_prefix_length(rest))
+                }
+            }
+        })
+    }
+}
+
+/// Returns the reference to a child node.
+fn child_ref(child: &RlpNode) -> NodeRef {
+    match child.as_hash() {
+        Some(hash) => NodeRef::Hash(hash),
+        None => NodeRef::Inline(child.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder, EMPTY_ROOT_HASH,
+    };
+    use alloy_primitives::keccak256;
+
+    fn build(leaves: &[(Nibbles, Vec<u8>)], targets: &[Nibbles]) -> (B256, Vec<Bytes>) {
+        let retainer = ProofRetainer::from_iter(targets.iter().cloned());
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+        (root, proof.into_iter().map(|(_, node)| node).collect())
+    }
+
+    #[test]
+    fn matches_verify_proof() {
+        let mut leaves = (0..200u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), vec![i as u8 | 1; 40]))
+            .collect::<Vec<_>>();
+        leaves.sort();
+
+        let mut targets = leaves
+            .iter()
+            .step_by(3)
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .collect::<Vec<_>>();
+        // Absent keys, wrong values and keys expected to be absent.
+        targets.push((Nibbles::unpack(B256::repeat_byte(0x42)), None));
+        targets.push((Nibbles::unpack(B256::repeat_byte(0x43)), Some(vec![1])));
+        targets.push((leaves[1].0.clone(), Some(vec![0xff])));
+        targets.push((leaves[2].0.clone(), None));
+        targets.reverse();
+
+        let keys = targets.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        let (root, proof) = build(&leaves, &keys);
+        let results = verify_proofs(root, &targets, &proof);
+        assert_eq!(results.len(), targets.len());
+        for ((key, expected_value), result) in targets.iter().zip(&results) {
+            let (_, target_proof) = build(&leaves, core::slice::from_ref(key));
+            assert_eq!(
+                *result,
+                verify_proof(root, key.clone(), expected_value.clone(), &target_proof),
+                "{key:?}"
+            );
+        }
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 3);
+    }
+
+    #[test]
+    fn missing_node() {
+        let leaves = (0..20u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), vec![1; 40]))
+            .collect::<alloc::collections::BTreeMap<_, _>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let targets = leaves
+            .iter()
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .collect::<Vec<_>>();
+        let (root, mut proof) = build(&leaves, &[leaves[0].0.clone()]);
+        let leaf = proof.pop().unwrap();
+
+        let results = verify_proofs(root, &targets, &proof);
+        assert_eq!(
+            results[0],
+            Err(ProofVerificationError::ValueMismatch {
+                path: leaves[0].0.slice(..proof.len()),
+                got: None,
+                expected: Some(Bytes::copy_from_slice(&RlpNode::word_rlp(&keccak256(&leaf)))),
+            })
+        );
+        assert!(results.iter().all(Result::is_err));
+    }
+
+    #[test]
+    fn inline_nodes() {
+        let leaves = (0..16u8)
+            .flat_map(|i| (0..3u8).map(move |j| (Nibbles::from_nibbles([i, j, 0, 1]), vec![i, j])))
+            .collect::<Vec<_>>();
+        let targets = leaves
+            .iter()
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .collect::<Vec<_>>();
+        let keys = leaves.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        let (root, proof) = build(&leaves, &keys);
+        assert!(proof.iter().any(|node| node.len() < 32));
+        assert!(verify_proofs(root, &targets, &proof).into_iter().all(|result| result.is_ok()));
+    }
+
+    #[test]
+    fn empty_trie() {
+        let targets = [
+            (Nibbles::unpack(B256::repeat_byte(1)), None),
+            (Nibbles::unpack(B256::repeat_byte(2)), Some(vec![1])),
+        ];
+        assert_eq!(
+            verify_proofs(EMPTY_ROOT_HASH, &targets, []),
+            [
+                Ok(()),
+                Err(ProofVerificationError::KeyAbsent {
+                    divergence_node: Bytes::from_static(&[EMPTY_STRING_CODE]),
+                    consumed_nibbles: Nibbles::default(),
+                })
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    proof::{MultiProof, ProofTargets, StorageMultiProof},
+    HashBuilder, Nibbles, TrieAccount,
+};
+use alloy_primitives::{B256, U256};
+use alloy_rlp::Encodable;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Computes a state root along with the [`MultiProof`] of the given [`ProofTargets`].
+///
+/// The builder owns the state trie [`HashBuilder`] and its retainer, and hands out storage trie
+/// builders with the retainers of the target slots of each account. Storage tries are built
+/// first, in any order and possibly on other threads, and their proofs are routed under their
+/// account when they are finished. Accounts are then added to the state trie in order of hashed
+/// address.
+///
+/// ```
+/// use alloy_primitives::{keccak256, Address, B256, U256};
+/// use alloy_trie::{
+///     proof::{MultiProofBuilder, ProofTargets},
+///     TrieAccount,
+/// };
+///
+/// let address = Address::with_last_byte(1);
+/// let targets = ProofTargets::from_unhashed([(address, [B256::ZERO])]);
+/// let (hashed_address, hashed_slot) = (keccak256(address), keccak256(B256::ZERO));
+///
+/// let mut builder = MultiProofBuilder::new(targets);
+/// let storage = [(hashed_slot, U256::from(1))];
+/// builder.add_account_with_storage(hashed_address, TrieAccount::default(), storage);
+/// let (_state_root, multiproof) = builder.finish();
+/// assert_eq!(multiproof.account_proof(hashed_address).len(), 1);
+/// assert_eq!(multiproof.storage_proof(&hashed_address, hashed_slot).unwrap().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct MultiProofBuilder {
+    targets: ProofTargets,
+    account_trie: HashBuilder,
+    multiproof: MultiProof,
+    value_buffer: Vec<u8>,
+}
+
+impl MultiProofBuilder {
+    /// Creates a new builder retaining the proofs of the given targets.
+    pub fn new(targets: ProofTargets) -> Self {
+        let account_trie = HashBuilder::default().with_proof_retainer(targets.account_retainer());
+        Self { targets, account_trie, multiproof: MultiProof::default(), value_buffer: Vec::new() }
+    }
+
+    /// Returns the targets of the multiproof.
+    pub const fn targets(&self) -> &ProofTargets {
+        &self.targets
+    }
+
+    /// Returns a builder for the storage trie of the account with the given hashed address, which
+    /// retains the proofs of the target slots of the account, if any.
+    ///
+    /// Once all slots are added, the builder must be passed to [`Self::finish_storage`].
+    pub fn storage_hash_builder(&self, hashed_address: &B256) -> HashBuilder {
+        let hash_builder = HashBuilder::default();
+        match self.targets.storage_retainer(hashed_address) {
+            Some(retainer) => hash_builder.with_proof_retainer(retainer),
+            None => hash_builder,
+        }
+    }
+
+    /// Computes the root of the storage trie of the account with the given hashed address,
+    /// storing its proofs under the account. Returns the storage root.
+    pub fn finish_storage(&mut self, hashed_address: B256, mut hash_builder: HashBuilder) -> B256 {
+        let root = hash_builder.root();
+        if hash_builder.proof_retainer.is_some() {
+            let storage = StorageMultiProof::new(root, hash_builder.take_proof_nodes());
+            self.multiproof.insert_storage(hashed_address, storage);
+        }
+        root
+    }
+
+    /// Adds the account with the given hashed address to the state trie. The storage root of
+    /// the account must already be set, see [`Self::finish_storage`].
+    ///
+    /// # Panics
+    ///
+    /// If the accounts are not added in order of strictly increasing hashed address.
+    pub fn add_account(&mut self, hashed_address: B256, account: &TrieAccount) {
+        self.value_buffer.clear();
+        account.encode(&mut self.value_buffer);
+        self.account_trie.add_leaf(Nibbles::unpack(hashed_address), &self.value_buffer);
+    }
+
+    /// Computes the storage root of the account with the given hashed address from its storage
+    /// slots keyed by hashed slot, and adds the account to the state trie. Returns the storage
+    /// root.
+    ///
+    /// The slots must be sorted by strictly increasing hashed slot. Slots with zero values are
+    /// skipped, as they are not stored in the trie.
+    ///
+    /// # Panics
+    ///
+    /// If the slots or the accounts are not added in order of strictly increasing hashed key.
+    pub fn add_account_with_storage<S>(
+        &mut self,
+        hashed_address: B256,
+        account: TrieAccount,
+        storage: S,
+    ) -> B256
+    where
+        S: IntoIterator<Item = (B256, U256)>,
+    {
+        let mut hash_builder = self.storage_hash_builder(&hashed_address);
+        for (hashed_slot, value) in storage.into_iter().filter(|(_, value)| !value.is_zero()) {
+            self.value_buffer.clear();
+            value.encode(&mut self.value_buffer);
+            hash_builder.add_leaf(Nibbles::unpack(hashed_slot), &self.value_buffer);
+        }
+        let storage_root = self.finish_storage(hashed_address, hash_builder);
+        self.add_account(hashed_address, &TrieAccount { storage_root, ..account });
+        storage_root
+    }
+
+    /// Computes the state root, and returns it along with the multiproof of the targets.
+    pub fn finish(mut self) -> (B256, MultiProof) {
+        let root = self.account_trie.root();
+        self.multiproof.account_subtree = self.account_trie.take_proof_nodes();
+        (root, self.multiproof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::verify_proof, EMPTY_ROOT_HASH};
+    use alloy_primitives::{keccak256, Address};
+
+    #[test]
+    fn state_multiproof() {
+        let accounts = (0..32u8)
+            .map(|i| {
+                let account = TrieAccount { nonce: i as u64, ..Default::default() };
+                let storage = (0..i % 5)
+                    .map(|j| (B256::with_last_byte(j), U256::from(j)))
+                    .collect::<Vec<_>>();
+                (Address::with_last_byte(i), account, storage)
+            })
+            .collect::<Vec<_>>();
+        let (with_storage, without_storage) = (accounts[9].0, accounts[5].0);
+        let slots = [B256::with_last_byte(1), B256::with_last_byte(3), B256::with_last_byte(7)];
+        let targets = ProofTargets::from_unhashed([
+            (with_storage, slots.to_vec()),
+            (without_storage, slots.to_vec()),
+            (accounts[1].0, Vec::new()),
+        ]);
+
+        let mut hashed_accounts = accounts
+            .iter()
+            .map(|(address, account, storage)| {
+                let mut storage = storage
+                    .iter()
+                    .map(|(slot, value)| (keccak256(slot), *value))
+                    .collect::<Vec<_>>();
+                storage.sort_unstable();
+                (keccak256(address), *account, storage)
+            })
+            .collect::<Vec<_>>();
+        hashed_accounts.sort_unstable_by_key(|(hashed_address, ..)| *hashed_address);
+        let mut builder = MultiProofBuilder::new(targets.clone());
+        for (hashed_address, account, storage) in &hashed_accounts {
+            let storage_root =
+                builder.add_account_with_storage(*hashed_address, *account, storage.clone());
+            assert_eq!(storage_root, crate::root::storage_root(storage.clone()));
+        }
+        let (state_root, multiproof) = builder.finish();
+        assert_eq!(state_root, crate::root::state_root(accounts));
+
+        // Only the accounts with target slots have storage proofs.
+        assert_eq!(multiproof.storages.len(), 2);
+        for (hashed_address, hashed_slots) in targets.iter() {
+            let (_, account, storage) =
+                hashed_accounts.iter().find(|(address, ..)| address == hashed_address).unwrap();
+            let storage_root = crate::root::storage_root(storage.clone());
+            let account = TrieAccount { storage_root, ..*account };
+            let proof = multiproof.account_proof(*hashed_address);
+            let key = Nibbles::unpack(hashed_address);
+            let value = Some(alloy_rlp::encode(account));
+            assert_eq!(verify_proof(state_root, key, value, &proof), Ok(()));
+            for hashed_slot in hashed_slots {
+                let storage_proof = multiproof.storage(hashed_address).unwrap();
+                assert_eq!(storage_proof.root, storage_root);
+                let value = storage
+                    .iter()
+                    .find(|(slot, value)| slot == hashed_slot && !value.is_zero())
+                    .map(|(_, value)| alloy_rlp::encode(value));
+                let proof = storage_proof.proof(*hashed_slot);
+                let key = Nibbles::unpack(hashed_slot);
+                assert_eq!(verify_proof(storage_root, key, value, &proof), Ok(()));
+            }
+        }
+        assert_eq!(multiproof.storage(&keccak256(without_storage)).unwrap().root, EMPTY_ROOT_HASH);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{
+    proof::{CompressedProofError, MultiProof, ProofNodes, StorageMultiProof},
+    HashMap, Nibbles,
+};
+use alloy_primitives::{keccak256, Bytes, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The version of the compressed multiproof format.
+const COMPRESSED_VERSION: u8 = 1;
+
+/// The RLP header of a 32-byte string, which precedes the hashes referencing child nodes.
+const HASH_RLP_HEADER: u8 = 0xa0;
+
+impl MultiProof {
+    /// Encodes the multiproof in a compact wire format, which is decoded with
+    /// [`MultiProof::from_compressed`].
+    ///
+    /// The format stores each distinct node once, even if it occurs in several tries, and orders
+    /// the nodes so that children precede their parents. The 32-byte hash of a preceding node, as
+    /// found in the references to child nodes and in the storage roots of accounts, is replaced
+    /// with a back-reference to the node, which is usually a single byte. Integers are LEB128
+    /// varints, and the sorted paths of the nodes of each trie are encoded as the length of the
+    /// prefix shared with the previous path, followed by the remaining nibbles packed in bytes.
+    ///
+    /// ```text
+    /// proof   = version nodes trie varint(storages) (hashed_address root trie)*
+    /// nodes   = varint(count) (varint(parts) part*)*
+    /// part    = varint(len << 1) byte* | varint(index << 1 | 1)
+    /// trie    = varint(count) (varint(shared) varint(nibbles) packed varint(index))*
+    /// root    = varint(0) hash | varint(index + 1)
+    /// ```
+    ///
+    /// The encoding is deterministic.
+    pub fn to_compressed(&self) -> Vec<u8> {
+        let mut storages = self.storages.iter().collect::<Vec<_>>();
+        storages.sort_unstable_by_key(|(hashed_address, _)| **hashed_address);
+        let tries = core::iter::once(&self.account_subtree)
+            .chain(storages.iter().map(|(_, storage)| &storage.subtree));
+
+        // Deeper nodes come first, so that children precede their parents.
+        let mut depths = HashMap::<&Bytes, usize>::default();
+        for (path, node) in tries.clone().flat_map(|trie| trie.iter()) {
+            let depth = depths.entry(node).or_default();
+            *depth = (*depth).max(path.len());
+        }
+        let mut nodes = depths.into_iter().collect::<Vec<_>>();
+        nodes.sort_unstable_by(|(a, a_depth), (b, b_depth)| b_depth.cmp(a_depth).then(a.cmp(b)));
+        let hashes = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, (node, _))| (keccak256(node), index))
+            .collect::<HashMap<_, _>>();
+        let indices = nodes.iter().enumerate().map(|(index, (node, _))| (*node, index));
+        let indices = indices.collect::<HashMap<_, _>>();
+
+        let mut out = Vec::from([COMPRESSED_VERSION]);
+        write_varint(&mut out, nodes.len() as u64);
+        for (index, (node, _)) in nodes.iter().enumerate() {
+            write_node(&mut out, node, |hash| hashes.get(hash).copied().filter(|i| *i < index));
+        }
+        write_trie(&mut out, &self.account_subtree, &indices);
+        write_varint(&mut out, storages.len() as u64);
+        for (hashed_address, storage) in storages {
+            out.extend_from_slice(hashed_address.as_slice());
+            match hashes.get(&storage.root) {
+                Some(index) => write_varint(&mut out, *index as u64 + 1),
+                None => {
+                    write_varint(&mut out, 0);
+                    out.extend_from_slice(storage.root.as_slice());
+                }
+            }
+            write_trie(&mut out, &storage.subtree, &indices);
+        }
+        out
+    }
+
+    /// Decodes a multiproof encoded with [`MultiProof::to_compressed`].
+    pub fn from_compressed(bytes: &[u8]) -> Result<Self, CompressedProofError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        let version = reader.byte()?;
+        if version != COMPRESSED_VERSION {
+            return Err(CompressedProofError::UnsupportedVersion { version });
+        }
+
+        let count = reader.len()?;
+        let mut nodes = Vec::<Bytes>::new();
+        let mut hashes = Vec::<B256>::new();
+        for _ in 0..count {
+            let node = reader.node(&hashes)?;
+            hashes.push(keccak256(&node));
+            nodes.push(node);
+        }
+
+        let mut multiproof = Self::new(reader.trie(&nodes)?);
+        for _ in 0..reader.len()? {
+            let hashed_address = reader.hash()?;
+            let root = match reader.len()? {
+                0 => reader.hash()?,
+                index => *hashes.get(index - 1).ok_or(CompressedProofError::InvalidReference {
+                    offset: reader.offset,
+                    index: index - 1,
+                })?,
+            };
+            let subtree = reader.trie(&nodes)?;
+            multiproof.insert_storage(hashed_address, StorageMultiProof::new(root, subtree));
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(CompressedProofError::TrailingBytes { len: bytes.len() - reader.offset });
+        }
+        Ok(multiproof)
+    }
+}
+
+/// Writes the node, replacing the hashes of the nodes returned by `reference` with
+/// back-references.
+fn write_node(out: &mut Vec<u8>, node: &[u8], reference: impl Fn(&B256) -> Option<usize>) {
+    let mut parts = Vec::new();
+    let (mut literal_start, mut offset) = (0, 0);
+    while offset < node.len() {
+        let referenced = (node[offset] == HASH_RLP_HEADER)
+            .then(|| node.get(offset + 1..offset + 33))
+            .flatten()
+            .and_then(|hash| reference(&B256::from_slice(hash)));
+        match referenced {
+            Some(index) => {
+                if literal_start < offset {
+                    parts.push(Err(&node[literal_start..offset]));
+                }
+                parts.push(Ok(index));
+                offset += 33;
+                literal_start = offset;
+            }
+            None => offset += 1,
+        }
+    }
+    if literal_start < node.len() {
+        parts.push(Err(&node[literal_start..]));
+    }
+
+    write_varint(out, parts.len() as u64);
+    for part in parts {
+        match part {
+            Ok(index) => write_varint(out, (index as u64) << 1 | 1),
+            Err(literal) => {
+                write_varint(out, (literal.len() as u64) << 1);
+                out.extend_from_slice(literal);
+            }
+        }
+    }
+}
+
+/// Writes the paths of the nodes of the trie in order, along with the indices of the nodes.
+fn write_trie(out: &mut Vec<u8>, trie: &ProofNodes, indices: &HashMap<&Bytes, usize>) {
+    let nodes = trie.nodes_sorted();
+    write_varint(out, nodes.len() as u64);
+    let mut previous = Nibbles::default();
+    for (path, node) in &nodes {
+        let shared = previous.common_prefix_length(path);
+        write_varint(out, shared as u64);
+        write_varint(out, (path.len() - shared) as u64);
+        out.extend(path[shared..].chunks(2).map(|pair| pair[0] << 4 | pair.get(1).unwrap_or(&0)));
+        write_varint(out, indices[&node] as u64);
+        previous = path.clone();
+    }
+}
+
+/// Writes the value as an LEB128 varint.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads the compressed format, tracking the offset for errors.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], CompressedProofError> {
+        let taken = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(len))
+            .ok_or(CompressedProofError::UnexpectedEnd { offset: self.bytes.len() })?;
+        self.offset += len;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, CompressedProofError> {
+        self.take(1).map(|byte| byte[0])
+    }
+
+    fn hash(&mut self) -> Result<B256, CompressedProofError> {
+        self.take(32).map(B256::from_slice)
+    }
+
+    fn varint(&mut self) -> Result<u64, CompressedProofError> {
+        let offset = self.offset;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(CompressedProofError::InvalidVarint { offset })
+    }
+
+    /// Reads a varint that counts or indexes items, which must fit in the remaining input.
+    fn len(&mut self) -> Result<usize, CompressedProofError> {
+        let offset = self.offset;
+        let value = self.varint()?;
+        usize::try_from(value)
+            .ok()
+            .filter(|value| *value <= self.bytes.len())
+            .ok_or(CompressedProofError::InvalidVarint { offset })
+    }
+
+    fn node(&mut self, hashes: &[B256]) -> Result<Bytes, CompressedProofError> {
+        let mut node = Vec::new();
+        for _ in 0..self.len()? {
+            let offset = self.offset;
+            let tag = self.varint()?;
+            if tag & 1 == 1 {
+                let index = usize::try_from(tag >> 1).unwrap_or(usize::MAX);
+                let hash = hashes
+                    .get(index)
+                    .ok_or(CompressedProofError::InvalidReference { offset, index })?;
+                node.push(HASH_RLP_HEADER);
+                node.extend_from_slice(hash.as_slice());
+            } else {
+                let len = usize::try_from(tag >> 1)
+                    .map_err(|_| CompressedProofError::InvalidVarint { offset })?;
+                node.extend_from_slice(self.take(len)?);
+            }
+        }
+        Ok(node.into())
+    }
+
+    fn trie(&mut self, nodes: &[Bytes]) -> Result<ProofNodes, CompressedProofError> {
+        let mut trie = ProofNodes::default();
+        let mut previous = Nibbles::default();
+        for _ in 0..self.len()? {
+            let offset = self.offset;
+            let shared = self.len()?;
+            let len = self.len()?;
+            if shared > previous.len() {
+                return Err(CompressedProofError::InvalidPath { offset });
+            }
+            let packed = self.take(len.div_ceil(2))?;
+            if len % 2 == 1 && packed[packed.len() - 1] & 0x0f != 0 {
+                return Err(CompressedProofError::InvalidPath { offset });
+            }
+            let mut path = previous.slice(..shared);
+            path.extend_from_slice_unchecked(&Nibbles::unpack(packed)[..len]);
+
+            let index_offset = self.offset;
+            let index = self.len()?;
+            let node = nodes
+                .get(index)
+                .ok_or(CompressedProofError::InvalidReference { offset: index_offset, index })?;
+            trie.insert(path.clone(), node.clone());
+            previous = path;
+        }
+        Ok(trie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofTargets, HashBuilder};
+    use alloy_primitives::{Address, U256};
+
+    fn root_with_proofs(
+        hashed_keys: &[B256],
+        retainer: crate::proof::ProofRetainer,
+    ) -> (B256, ProofNodes) {
+        let mut leaves = hashed_keys.iter().map(Nibbles::unpack).collect::<Vec<_>>();
+        leaves.sort_unstable();
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for key in &leaves {
+            hb.add_leaf(key.clone(), &alloy_rlp::encode(U256::from(key.len())));
+        }
+        (hb.root(), hb.take_proof_nodes())
+    }
+
+    fn multiproof() -> MultiProof {
+        let slots = (0..200u64).map(|i| B256::from(U256::from(i))).collect::<Vec<_>>();
+        let accounts = (0..500u16).map(|i| Address::left_padding_from(&i.to_be_bytes()));
+        let targets = ProofTargets::from_unhashed(
+            accounts.clone().step_by(25).map(|address| (address, slots.iter().step_by(9).copied())),
+        );
+        let hashed_addresses = accounts.map(keccak256).collect::<Vec<_>>();
+        let hashed_slots = slots.iter().map(keccak256).collect::<Vec<_>>();
+
+        let (_, account_subtree) = root_with_proofs(&hashed_addresses, targets.account_retainer());
+        let mut multiproof = MultiProof::new(account_subtree);
+        for (hashed_address, _) in targets.iter() {
+            let retainer = targets.storage_retainer(hashed_address).unwrap();
+            let (root, subtree) = root_with_proofs(&hashed_slots, retainer);
+            multiproof.insert_storage(*hashed_address, StorageMultiProof::new(root, subtree));
+        }
+        multiproof
+            .insert_storage(B256::ZERO, StorageMultiProof::new(B256::ZERO, ProofNodes::default()));
+        multiproof
+    }
+
+    #[test]
+    fn roundtrip() {
+        let multiproof = multiproof();
+        let compressed = multiproof.to_compressed();
+        assert_eq!(MultiProof::from_compressed(&compressed), Ok(multiproof.clone()));
+        assert_eq!(multiproof.to_compressed(), compressed);
+
+        // The storage tries are identical, so their nodes are stored once.
+        let uncompressed = core::iter::once(&multiproof.account_subtree)
+            .chain(multiproof.storages.values().map(|storage| &storage.subtree))
+            .flat_map(|trie| trie.iter().map(|(path, node)| path.len() + node.len()))
+            .sum::<usize>();
+        assert!(compressed.len() * 4 < uncompressed, "{} {uncompressed}", compressed.len());
+
+        let empty = MultiProof::default();
+        assert_eq!(empty.to_compressed(), [COMPRESSED_VERSION, 0, 0, 0]);
+        assert_eq!(MultiProof::from_compressed(&empty.to_compressed()), Ok(empty));
+    }
+
+    #[test]
+    fn errors() {
+        let compressed = multiproof().to_compressed();
+        assert_eq!(
+            MultiProof::from_compressed(&[]),
+            Err(CompressedProofError::UnexpectedEnd { offset: 0 })
+        );
+        assert_eq!(
+            MultiProof::from_compressed(&[2]),
+            Err(CompressedProofError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            MultiProof::from_compressed(&compressed[..compressed.len() - 1]),
+            Err(CompressedProofError::UnexpectedEnd { offset: compressed.len() - 1 })
+        );
+        assert_eq!(
+            MultiProof::from_compressed(&[compressed.as_slice(), &[0]].concat()),
+            Err(CompressedProofError::TrailingBytes { len: 1 })
+        );
+        // A node referencing itself.
+        assert_eq!(
+            MultiProof::from_compressed(&[COMPRESSED_VERSION, 1, 1, 1, 0, 0]),
+            Err(CompressedProofError::InvalidReference { offset: 3, index: 0 })
+        );
+        // A path sharing a prefix with no previous path.
+        assert_eq!(
+            MultiProof::from_compressed(&[COMPRESSED_VERSION, 1, 1, 2, 0x80, 1, 1, 0, 0, 0]),
+            Err(CompressedProofError::InvalidPath { offset: 6 })
+        );
+        assert_eq!(
+            MultiProof::from_compressed(&[COMPRESSED_VERSION, 0xff, 0xff, 0x7f]),
+            Err(CompressedProofError::InvalidVarint { offset: 1 })
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    nodes::{TrieNode, TrieNodeDecodeError},
+    proof::ProofNodes,
+    HashMap, Nibbles, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::EMPTY_STRING_CODE;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Decodes an unordered set of witness nodes into the proof nodes of the trie with the given
+/// root.
+///
+/// The nodes are indexed by their keccak256 hash, which is how trie nodes reference their
+/// children, and the trie is then traversed from the root to assign each reachable node its path.
+/// Witnesses usually carry the nodes of multiple tries along with contract bytecode, so nodes that
+/// are not reachable from the root are ignored and never decoded. Children that are missing from
+/// the witness are left out, along with their subtries.
+///
+/// Returns an error if any of the reachable nodes cannot be decoded.
+pub fn decode_witness<'a, I>(root: B256, witness: I) -> Result<ProofNodes, TrieNodeDecodeError>
+where
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    let nodes_by_hash =
+        witness.into_iter().map(|node| (keccak256(node), node)).collect::<HashMap<_, _>>();
+    decode_indexed_witness(root, |hash| nodes_by_hash.get(hash).copied())
+}
+
+/// Decodes the proof nodes of the trie with the given root from the witness nodes, which are
+/// looked up by their hash. See [`decode_witness`].
+pub(crate) fn decode_indexed_witness<'a>(
+    root: B256,
+    node_by_hash: impl Fn(&B256) -> Option<&'a Bytes>,
+) -> Result<ProofNodes, TrieNodeDecodeError> {
+    let mut proof_nodes = ProofNodes::default();
+    if root == EMPTY_ROOT_HASH {
+        proof_nodes.insert(Nibbles::default(), Bytes::from_static(&[EMPTY_STRING_CODE]));
+        return Ok(proof_nodes);
+    }
+
+    let mut stack = Vec::from([(Nibbles::default(), root)]);
+    while let Some((path, hash)) = stack.pop() {
+        let Some(node) = node_by_hash(&hash) else { continue };
+
+        match TrieNode::decode_raw(node)? {
+            TrieNode::Branch(branch) => {
+                for (nibble, child) in branch.as_ref().children() {
+                    if let Some(hash) = child.and_then(|child| child.as_hash()) {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble);
+                        stack.push((child_path, hash));
+                    }
+                }
+            }
+            TrieNode::Extension(extension) => {
+                if let Some(hash) = extension.child.as_hash() {
+                    stack.push((path.join(&extension.key), hash));
+                }
+            }
+            // In-place encoded children cannot reference other nodes by hash.
+            TrieNode::Leaf(_) | TrieNode::EmptyRoot => {}
+        }
+        proof_nodes.insert(path, node.clone());
+    }
+    Ok(proof_nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nodes::TrieNodeKind,
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder,
+    };
+    use alloy_primitives::U256;
+
+    #[test]
+    fn decode_retained_proof() {
+        let leaves = (0..100u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(7).cloned().collect::<Vec<_>>();
+
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets.clone()));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+
+        // The witness is unordered and contains unrelated data.
+        let mut witness = proof_nodes.values().cloned().collect::<Vec<_>>();
+        witness.push(Bytes::from_static(b"bytecode"));
+        let decoded = decode_witness(root, &witness).unwrap();
+
+        for target in &targets {
+            let proof = decoded.matching_nodes_sorted(target);
+            assert_eq!(
+                verify_proof(
+                    root,
+                    target.clone(),
+                    Some(leaves[target].clone()),
+                    proof.iter().map(|(_, node)| node)
+                ),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let branch = Bytes::from([&[0xd1][..], &[EMPTY_STRING_CODE; 16], &[0x01]].concat());
+        assert_eq!(
+            decode_witness(keccak256(&branch), [&branch]),
+            Err(TrieNodeDecodeError::Rlp {
+                offset: 17,
+                kind: Some(TrieNodeKind::Branch),
+                error: alloy_rlp::Error::Custom("branch node values are not supported"),
+            })
+        );
+
+        let list = Bytes::from_static(&[0xc3, 0x80, 0x80, 0x80]);
+        assert_eq!(
+            TrieNode::decode_raw(&list),
+            Err(TrieNodeDecodeError::UnexpectedItemCount { got: 3 })
+        );
+        assert_eq!(
+            TrieNode::decode_raw(&[EMPTY_STRING_CODE, 0x00]),
+            Err(TrieNodeDecodeError::TrailingBytes { offset: 1 })
+        );
+        assert_eq!(TrieNode::decode_raw(&[EMPTY_STRING_CODE]), Ok(TrieNode::EmptyRoot));
+    }
+}
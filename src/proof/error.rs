@@ -1,3 +1,4 @@
+use crate::{nodes::TrieNodeDecodeError, sparse::SparseTrieError};
 use alloy_primitives::{Bytes, B256};
 use core::fmt;
 use nybbles::Nibbles;
@@ -21,6 +22,13 @@ pub enum ProofVerificationError {
         /// Expected value.
         expected: Option<Bytes>,
     },
+    /// The proof is a valid exclusion proof for the key, but a value was expected.
+    KeyAbsent {
+        /// The last node of the proof, at which the path to the key diverged.
+        divergence_node: Bytes,
+        /// The nibbles of the key matched by the proof before the divergence.
+        consumed_nibbles: Nibbles,
+    },
     /// Encountered unexpected empty root node.
     UnexpectedEmptyRoot,
     /// Error during RLP decoding of trie node.
@@ -51,6 +59,12 @@ impl fmt::Display for ProofVerificationError {
             ProofVerificationError::ValueMismatch { path, got, expected } => {
                 write!(f, "value mismatch at path {path:?}. got: {got:?}. expected: {expected:?}")
             }
+            ProofVerificationError::KeyAbsent { divergence_node, consumed_nibbles } => {
+                write!(
+                    f,
+                    "key absent, diverged after {consumed_nibbles:?} at node {divergence_node}"
+                )
+            }
             ProofVerificationError::UnexpectedEmptyRoot => {
                 write!(f, "unexpected empty root node")
             }
@@ -64,3 +78,229 @@ impl From<alloy_rlp::Error> for ProofVerificationError {
         ProofVerificationError::Rlp(source)
     }
 }
+
+/// Error during range proof verification.
+#[derive(PartialEq, Eq, Debug)]
+pub enum RangeProofError {
+    /// The number of keys does not match the number of values.
+    KeyValueCountMismatch {
+        /// Number of keys.
+        keys: usize,
+        /// Number of values.
+        values: usize,
+    },
+    /// The key at the given index is not greater than the previous key, or, for the first key,
+    /// is less than the start key.
+    KeysNotIncreasing {
+        /// Index of the key.
+        index: usize,
+    },
+    /// The value at the given index is empty.
+    EmptyValue {
+        /// Index of the value.
+        index: usize,
+    },
+    /// The root of the trie reconstructed from the edge proofs and the range does not match the
+    /// expected.
+    RootMismatch {
+        /// Computed root.
+        got: B256,
+        /// Root provided to verify function.
+        expected: B256,
+    },
+    /// The range is empty, but the trie has entries after the start key.
+    MoreEntriesAvailable,
+    /// Error while reconstructing the trie from the edge proofs.
+    SparseTrie(SparseTrieError),
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for RangeProofError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SparseTrie(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RangeProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyValueCountMismatch { keys, values } => {
+                write!(f, "key and value count mismatch. keys: {keys}. values: {values}")
+            }
+            Self::KeysNotIncreasing { index } => {
+                write!(f, "key at index {index} is out of order")
+            }
+            Self::EmptyValue { index } => write!(f, "value at index {index} is empty"),
+            Self::RootMismatch { got, expected } => {
+                write!(f, "root mismatch. got: {got}. expected: {expected}")
+            }
+            Self::MoreEntriesAvailable => write!(f, "more entries available"),
+            Self::SparseTrie(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl From<SparseTrieError> for RangeProofError {
+    fn from(source: SparseTrieError) -> Self {
+        Self::SparseTrie(source)
+    }
+}
+
+impl From<alloy_rlp::Error> for RangeProofError {
+    fn from(source: alloy_rlp::Error) -> Self {
+        Self::SparseTrie(SparseTrieError::Rlp(source))
+    }
+}
+
+/// Error during account proof verification.
+#[derive(PartialEq, Eq, Debug)]
+pub enum AccountProofError {
+    /// The account proof is invalid.
+    Account(ProofVerificationError),
+    /// The account leaf could not be decoded.
+    InvalidAccount(alloy_rlp::Error),
+    /// The proven account does not match the account fields of the proof response.
+    AccountMismatch,
+    /// The proof of a storage slot is invalid.
+    Storage {
+        /// The storage slot.
+        slot: B256,
+        /// The proof verification error.
+        error: ProofVerificationError,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for AccountProofError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Account(error) | Self::Storage { error, .. } => Some(error),
+            Self::InvalidAccount(error) => Some(error),
+            Self::AccountMismatch => None,
+        }
+    }
+}
+
+impl fmt::Display for AccountProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Account(error) => write!(f, "invalid account proof: {error}"),
+            Self::InvalidAccount(error) => write!(f, "invalid account: {error}"),
+            Self::AccountMismatch => write!(f, "proven account does not match the response"),
+            Self::Storage { slot, error } => {
+                write!(f, "invalid storage proof for slot {slot}: {error}")
+            }
+        }
+    }
+}
+
+/// Error while decoding a compressed [`MultiProof`](crate::proof::MultiProof).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompressedProofError {
+    /// The input ends before the proof is complete.
+    UnexpectedEnd {
+        /// The length of the input.
+        offset: usize,
+    },
+    /// The version of the format is not supported.
+    UnsupportedVersion {
+        /// The version byte.
+        version: u8,
+    },
+    /// A varint overflows, or a count or index exceeds the length of the input.
+    InvalidVarint {
+        /// The offset of the varint.
+        offset: usize,
+    },
+    /// A reference to a node that does not precede it.
+    InvalidReference {
+        /// The offset of the reference.
+        offset: usize,
+        /// The index of the referenced node.
+        index: usize,
+    },
+    /// A path shares more nibbles with the previous path than it has, or has non-zero padding.
+    InvalidPath {
+        /// The offset of the path.
+        offset: usize,
+    },
+    /// The input continues after the proof.
+    TrailingBytes {
+        /// The number of trailing bytes.
+        len: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for CompressedProofError {}
+
+impl fmt::Display for CompressedProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd { offset } => {
+                write!(f, "unexpected end of input at offset {offset}")
+            }
+            Self::UnsupportedVersion { version } => {
+                write!(f, "unsupported compressed proof version {version}")
+            }
+            Self::InvalidVarint { offset } => write!(f, "invalid varint at offset {offset}"),
+            Self::InvalidReference { offset, index } => {
+                write!(f, "invalid reference to node {index} at offset {offset}")
+            }
+            Self::InvalidPath { offset } => write!(f, "invalid path at offset {offset}"),
+            Self::TrailingBytes { len } => write!(f, "{len} trailing bytes"),
+        }
+    }
+}
+
+/// Error while converting the proof nodes of a JSON-RPC response, such as the `accountProof` of
+/// `eth_getProof`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RpcProofError {
+    /// The node is not a hex string.
+    InvalidHex {
+        /// The index of the node in the proof.
+        index: usize,
+    },
+    /// The node can't be decoded.
+    InvalidNode {
+        /// The index of the node in the proof.
+        index: usize,
+        /// The decoding error.
+        error: TrieNodeDecodeError,
+    },
+    /// The node is not the one referenced by the previous node on the path to the key, or by the
+    /// root, so the proof is not ordered from the root to the leaf.
+    UnexpectedNode {
+        /// The index of the node in the proof.
+        index: usize,
+        /// The path of the node in the trie.
+        path: Nibbles,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for RpcProofError {}
+
+impl fmt::Display for RpcProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHex { index } => write!(f, "proof node {index} is not a hex string"),
+            Self::InvalidNode { index, error } => write!(f, "invalid proof node {index}: {error}"),
+            Self::UnexpectedNode { index, path } => {
+                write!(f, "unexpected proof node {index} at path {path:?}")
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    nodes::{BranchNode, RlpNode, TrieNode, TrieNodeKind},
+    KeccakHasher, TrieHasher,
+};
+use alloy_primitives::{Bytes, B256};
+use alloy_rlp::{Decodable, EMPTY_STRING_CODE};
+use core::fmt;
+use nybbles::Nibbles;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A node visited while walking a proof, see [`explain_proof_failure`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofStep {
+    /// The index of the node in the proof, or [`None`] if the node is embedded in its parent.
+    pub proof_index: Option<usize>,
+    /// The path of the node.
+    pub path: Nibbles,
+    /// The reference to the node held by its parent, or the RLP-encoded root hash. This is either
+    /// the RLP-encoded hash of the node or, if embedded, the node itself.
+    pub expected: Bytes,
+    /// The reference computed from the node. Equal to [`Self::expected`] for embedded nodes.
+    pub computed: Bytes,
+    /// The kind of the node, if it was decoded.
+    pub kind: Option<TrieNodeKind>,
+    /// The nibble of the key looked up in a branch node.
+    pub child_index: Option<u8>,
+    /// The nibbles of the key consumed by the node.
+    pub consumed: Nibbles,
+}
+
+impl ProofStep {
+    /// Returns `true` if the node matches the reference held by its parent.
+    pub fn matches(&self) -> bool {
+        self.expected == self.computed
+    }
+}
+
+/// The conclusion of a proof walk, see [`explain_proof_failure`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofOutcome {
+    /// The proof shows that the key has the given value.
+    Value(Bytes),
+    /// The proof shows that the key is absent.
+    Absent,
+    /// The proof node at the given index does not match the reference held by its parent.
+    ReferenceMismatch {
+        /// The index of the node in the proof.
+        proof_index: usize,
+    },
+    /// The proof ends before the key is either found or proven absent.
+    Incomplete,
+    /// The key was found or proven absent, but the proof has more nodes, starting at the given
+    /// index.
+    TrailingNodes {
+        /// The index of the first unused node in the proof.
+        proof_index: usize,
+    },
+    /// A node could not be decoded.
+    InvalidNode {
+        /// The index of the node in the proof, or [`None`] if the node is embedded in its parent.
+        proof_index: Option<usize>,
+        /// The decoding error.
+        error: alloy_rlp::Error,
+    },
+    /// An empty root node was found below the root.
+    UnexpectedEmptyRoot {
+        /// The index of the node in the proof, or [`None`] if the node is embedded in its parent.
+        proof_index: Option<usize>,
+    },
+}
+
+/// A step-by-step account of the verification of a proof, see [`explain_proof_failure`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofExplanation {
+    /// The nodes visited, in order.
+    pub steps: Vec<ProofStep>,
+    /// The conclusion of the walk.
+    pub outcome: ProofOutcome,
+}
+
+impl fmt::Display for ProofExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            match step.proof_index {
+                Some(index) => write!(f, "#{index}")?,
+                None => f.write_str("embedded")?,
+            }
+            write!(f, " at {:?}: ", step.path)?;
+            match step.kind {
+                Some(kind) => write!(f, "{kind}")?,
+                None => f.write_str("undecoded node")?,
+            }
+            if !step.matches() {
+                write!(f, ", expected {} but computed {}", step.expected, step.computed)?;
+            }
+            if let Some(nibble) = step.child_index {
+                write!(f, ", looked up child {nibble:x}")?;
+            }
+            if !step.consumed.is_empty() {
+                write!(f, ", consumed {:?}", step.consumed)?;
+            }
+            writeln!(f)?;
+        }
+        match &self.outcome {
+            ProofOutcome::Value(value) => write!(f, "proves value {value}"),
+            ProofOutcome::Absent => f.write_str("proves absence"),
+            ProofOutcome::ReferenceMismatch { proof_index } => {
+                write!(f, "node #{proof_index} does not match its reference")
+            }
+            ProofOutcome::Incomplete => f.write_str("proof is incomplete"),
+            ProofOutcome::TrailingNodes { proof_index } => {
+                write!(f, "unused nodes starting at #{proof_index}")
+            }
+            ProofOutcome::InvalidNode { proof_index: Some(index), error } => {
+                write!(f, "node #{index} is invalid: {error}")
+            }
+            ProofOutcome::InvalidNode { proof_index: None, error } => {
+                write!(f, "embedded node is invalid: {error}")
+            }
+            ProofOutcome::UnexpectedEmptyRoot { .. } => f.write_str("unexpected empty root node"),
+        }
+    }
+}
+
+/// Walks the proof of the key against the root the same way as [`verify_proof`], and explains
+/// what it proves or why it is invalid.
+///
+/// The explanation lists every node visited, including nodes embedded in their parents, with the
+/// reference expected by the parent and the one computed from the node, the child looked up in
+/// branch nodes and the nibbles of the key consumed. The walk stops at the first node that does
+/// not match its reference.
+///
+/// [`verify_proof`]: crate::proof::verify_proof
+pub fn explain_proof_failure<'a, I>(root: B256, key: &Nibbles, proof: I) -> ProofExplanation
+where
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    explain_proof_failure_with_hasher::<KeccakHasher, _>(root, key, proof)
+}
+
+/// Explains the proof of the key against the root, hashing nodes with the given [`TrieHasher`].
+///
+/// See [`explain_proof_failure`] for details.
+pub fn explain_proof_failure_with_hasher<'a, H, I>(
+    root: B256,
+    key: &Nibbles,
+    proof: I,
+) -> ProofExplanation
+where
+    H: TrieHasher,
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    let mut steps = Vec::new();
+    let outcome = walk::<H>(root, key, &mut proof.into_iter().enumerate().peekable(), &mut steps);
+    ProofExplanation { steps, outcome }
+}
+
+/// What the walk expects to find next.
+enum Next {
+    /// A node with the given reference.
+    Node(RlpNode),
+    /// Nothing, as the walk ended with the given value.
+    Value(Vec<u8>),
+    /// Nothing, as the walk ended at a branch node without a child at the key.
+    Nothing,
+}
+
+fn walk<'a, H: TrieHasher>(
+    root: B256,
+    key: &Nibbles,
+    proof: &mut core::iter::Peekable<impl Iterator<Item = (usize, &'a Bytes)>>,
+    steps: &mut Vec<ProofStep>,
+) -> ProofOutcome {
+    let root_ref = RlpNode::word_rlp(&root);
+
+    // An empty proof, or one with only an empty node, proves an empty trie.
+    if proof.peek().map_or(true, |(_, node)| node.as_ref() == [EMPTY_STRING_CODE]) {
+        let empty_ref = RlpNode::word_rlp(&H::empty_root());
+        if let Some((index, _)) = proof.next() {
+            steps.push(ProofStep {
+                proof_index: Some(index),
+                path: Nibbles::default(),
+                expected: Bytes::copy_from_slice(&root_ref),
+                computed: Bytes::copy_from_slice(&empty_ref),
+                kind: Some(TrieNodeKind::EmptyRoot),
+                child_index: None,
+                consumed: Nibbles::default(),
+            });
+        } else if root != H::empty_root() {
+            return ProofOutcome::Incomplete;
+        }
+        return if root == H::empty_root() {
+            ProofOutcome::Absent
+        } else {
+            ProofOutcome::ReferenceMismatch { proof_index: 0 }
+        };
+    }
+
+    let mut path = Nibbles::default();
+    let mut next = Next::Node(root_ref);
+    for (index, node) in proof {
+        let expected = match next {
+            Next::Node(expected) => expected,
+            Next::Value(_) | Next::Nothing => {
+                return ProofOutcome::TrailingNodes { proof_index: index }
+            }
+        };
+        let computed = RlpNode::from_rlp_with_hasher::<H>(node);
+        let decoded = TrieNode::decode(&mut &node[..]);
+        let step = steps.len();
+        steps.push(ProofStep {
+            proof_index: Some(index),
+            path: path.clone(),
+            expected: Bytes::copy_from_slice(&expected),
+            computed: Bytes::copy_from_slice(&computed),
+            kind: decoded.as_ref().ok().map(TrieNode::kind),
+            child_index: None,
+            consumed: Nibbles::default(),
+        });
+        if computed != expected {
+            return ProofOutcome::ReferenceMismatch { proof_index: index };
+        }
+
+        next = match decoded {
+            Ok(TrieNode::Branch(branch)) => {
+                match walk_branch(branch, step, &mut path, key, steps) {
+                    Ok(next) => next,
+                    Err(outcome) => return outcome,
+                }
+            }
+            Ok(TrieNode::Extension(extension)) => {
+                path.extend_from_slice(&extension.key);
+                steps[step].consumed = extension.key;
+                Next::Node(extension.child)
+            }
+            Ok(TrieNode::Leaf(leaf)) => {
+                path.extend_from_slice(&leaf.key);
+                steps[step].consumed = leaf.key;
+                Next::Value(leaf.value)
+            }
+            Ok(TrieNode::EmptyRoot) => {
+                return ProofOutcome::UnexpectedEmptyRoot { proof_index: Some(index) }
+            }
+            Err(error) => return ProofOutcome::InvalidNode { proof_index: Some(index), error },
+        };
+    }
+
+    match next {
+        Next::Value(value) if path == *key => ProofOutcome::Value(value.into()),
+        Next::Node(_) if key.starts_with(&path) => ProofOutcome::Incomplete,
+        Next::Value(_) | Next::Node(_) | Next::Nothing => ProofOutcome::Absent,
+    }
+}
+
+/// Looks up the child at the next nibble of the key in the branch node recorded at `step`,
+/// walking through embedded children.
+fn walk_branch(
+    branch: BranchNode,
+    step: usize,
+    path: &mut Nibbles,
+    key: &Nibbles,
+    steps: &mut Vec<ProofStep>,
+) -> Result<Next, ProofOutcome> {
+    let Some(&nibble) = key.get(path.len()) else { return Ok(Next::Nothing) };
+    steps[step].child_index = Some(nibble);
+    let Some(child) = branch.child(nibble) else { return Ok(Next::Nothing) };
+    path.push(nibble);
+    steps[step].consumed = Nibbles::from_nibbles_unchecked([nibble]);
+    if child.as_hash().is_some() {
+        return Ok(Next::Node(child.clone()));
+    }
+
+    // The child is embedded in the branch node.
+    let mut child = child.clone();
+    loop {
+        let decoded = TrieNode::decode(&mut &child[..]);
+        let step = steps.len();
+        steps.push(ProofStep {
+            proof_index: None,
+            path: path.clone(),
+            expected: Bytes::copy_from_slice(&child),
+            computed: Bytes::copy_from_slice(&child),
+            kind: decoded.as_ref().ok().map(TrieNode::kind),
+            child_index: None,
+            consumed: Nibbles::default(),
+        });
+        match decoded {
+            Ok(TrieNode::Branch(branch)) => return walk_branch(branch, step, path, key, steps),
+            Ok(TrieNode::Extension(extension)) => {
+                // An embedded extension node can only have an embedded branch node child.
+                path.extend_from_slice(&extension.key);
+                steps[step].consumed = extension.key;
+                child = extension.child;
+            }
+            Ok(TrieNode::Leaf(leaf)) => {
+                path.extend_from_slice(&leaf.key);
+                steps[step].consumed = leaf.key;
+                return Ok(Next::Value(leaf.value));
+            }
+            Ok(TrieNode::EmptyRoot) => {
+                return Err(ProofOutcome::UnexpectedEmptyRoot { proof_index: None })
+            }
+            Err(error) => return Err(ProofOutcome::InvalidNode { proof_index: None, error }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder, EMPTY_ROOT_HASH,
+    };
+    use alloc::string::ToString;
+    use alloy_primitives::keccak256;
+
+    fn proof(target: &Nibbles) -> (B256, Vec<Bytes>) {
+        let leaves = (0..64u8)
+            .map(|i| (Nibbles::unpack(keccak256([i])), vec![i; 40]))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let mut hb =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::from_iter([target.clone()]));
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        let root = hb.root();
+        let proof = hb.take_proof_nodes().into_nodes_sorted();
+        (root, proof.into_iter().map(|(_, node)| node).collect())
+    }
+
+    #[test]
+    fn valid_proofs() {
+        let key = Nibbles::unpack(keccak256([7]));
+        let (root, proof) = proof(&key);
+        let explanation = explain_proof_failure(root, &key, &proof);
+        assert_eq!(explanation.outcome, ProofOutcome::Value(vec![7; 40].into()));
+        assert_eq!(explanation.steps.len(), proof.len());
+        assert!(explanation.steps.iter().all(ProofStep::matches));
+        assert_eq!(
+            explanation
+                .steps
+                .iter()
+                .flat_map(|step| step.consumed.iter().copied())
+                .collect::<Vec<_>>(),
+            key.to_vec()
+        );
+
+        let absent = Nibbles::unpack(keccak256([100]));
+        let (root, proof) = self::proof(&absent);
+        assert_eq!(explain_proof_failure(root, &absent, &proof).outcome, ProofOutcome::Absent);
+        assert_eq!(verify_proof(root, absent, None, &proof), Ok(()));
+
+        assert_eq!(explain_proof_failure(EMPTY_ROOT_HASH, &key, []).outcome, ProofOutcome::Absent);
+    }
+
+    #[test]
+    fn invalid_proofs() {
+        let key = Nibbles::unpack(keccak256([7]));
+        let (root, proof) = proof(&key);
+
+        let explanation = explain_proof_failure(root, &key, &proof[..proof.len() - 1]);
+        assert_eq!(explanation.outcome, ProofOutcome::Incomplete);
+
+        let mut tampered = proof.clone();
+        tampered[1] = proof[0].clone();
+        let explanation = explain_proof_failure(root, &key, &tampered);
+        assert_eq!(explanation.outcome, ProofOutcome::ReferenceMismatch { proof_index: 1 });
+        let step = explanation.steps.last().unwrap();
+        assert!(!step.matches());
+        assert_eq!(step.path.len(), 1);
+        assert_eq!(step.expected[..], *RlpNode::word_rlp(&keccak256(&proof[1])));
+        assert!(explanation.to_string().contains("node #1 does not match its reference"));
+
+        let mut trailing = proof.clone();
+        trailing.push(proof[0].clone());
+        let explanation = explain_proof_failure(root, &key, &trailing);
+        assert_eq!(explanation.outcome, ProofOutcome::TrailingNodes { proof_index: proof.len() });
+
+        let explanation = explain_proof_failure(B256::ZERO, &key, []);
+        assert_eq!(explanation.outcome, ProofOutcome::Incomplete);
+    }
+}
@@ -0,0 +1,446 @@
+//! Proof verification without heap allocation.
+//!
+//! Unlike the rest of the crate, this module does not use `alloc`: nodes are decoded in place from
+//! the proof, and the key is read nibble by nibble from its packed bytes.
+
+use crate::{nibbles::PackedNibbles, KeccakHasher, TrieHasher};
+use alloy_primitives::B256;
+use alloy_rlp::{Header, EMPTY_STRING_CODE};
+use core::fmt;
+
+/// Verify the proof for the given key against the provided root, without allocating.
+///
+/// The key is given as packed bytes, e.g. the hashed address or slot, and the proof as the RLP
+/// encoded nodes on the path to it, starting with the root node. The expected value can be
+/// either [Some] if it's expected to be present in the trie or [None] if this is an exclusion
+/// proof.
+///
+/// Nodes that are inlined in their parent are read from the parent. If the proof also contains
+/// such a node right after its parent, as retained by the [`HashBuilder`](crate::HashBuilder), it
+/// is skipped.
+pub fn verify_proof_in_place(
+    root: B256,
+    key: &[u8],
+    expected_value: Option<&[u8]>,
+    proof: &[&[u8]],
+) -> Result<(), InPlaceProofError> {
+    verify_proof_in_place_with_hasher::<KeccakHasher>(root, key, expected_value, proof)
+}
+
+/// Verify the proof for the given key against the provided root of a trie hashed with the given
+/// [`TrieHasher`], without allocating.
+///
+/// See [`verify_proof_in_place`] for details.
+pub fn verify_proof_in_place_with_hasher<H: TrieHasher>(
+    root: B256,
+    key: &[u8],
+    expected_value: Option<&[u8]>,
+    proof: &[&[u8]],
+) -> Result<(), InPlaceProofError> {
+    // If the proof is empty or contains only an empty node, the trie must be empty.
+    let value = if proof.first().map_or(true, |node| *node == [EMPTY_STRING_CODE]) {
+        if root != H::empty_root() {
+            return Err(if proof.is_empty() {
+                InPlaceProofError::IncompleteProof
+            } else {
+                InPlaceProofError::NodeMismatch { proof_index: 0 }
+            });
+        }
+        None
+    } else {
+        walk::<H>(root, key, proof)?
+    };
+
+    match (value, expected_value) {
+        (None, None) => Ok(()),
+        (None, Some(_)) => Err(InPlaceProofError::KeyAbsent),
+        (Some(value), Some(expected)) if value == expected => Ok(()),
+        (Some(_), _) => Err(InPlaceProofError::ValueMismatch),
+    }
+}
+
+/// Error during proof verification without allocation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InPlaceProofError {
+    /// The proof node at the given index does not match the reference in its parent, or the root
+    /// for the first node.
+    NodeMismatch {
+        /// Index of the node in the proof.
+        proof_index: usize,
+    },
+    /// The value in the proof does not match the expected value.
+    ValueMismatch,
+    /// The proof is a valid exclusion proof for the key, but a value was expected.
+    KeyAbsent,
+    /// The proof ends before the key is either found or proven absent.
+    IncompleteProof,
+    /// The proof continues after the key is either found or proven absent.
+    TrailingNodes {
+        /// Index of the first node after the end of the path.
+        proof_index: usize,
+    },
+    /// The proof node at the given index, or a node inlined in it, is not a valid trie node.
+    InvalidNode {
+        /// Index of the node in the proof.
+        proof_index: usize,
+        /// The RLP decoding error.
+        error: alloy_rlp::Error,
+    },
+    /// Encountered unexpected empty root node.
+    UnexpectedEmptyRoot {
+        /// Index of the node in the proof.
+        proof_index: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for InPlaceProofError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidNode { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for InPlaceProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeMismatch { proof_index } => {
+                write!(f, "proof node {proof_index} does not match its reference")
+            }
+            Self::ValueMismatch => write!(f, "value mismatch"),
+            Self::KeyAbsent => write!(f, "key absent"),
+            Self::IncompleteProof => write!(f, "proof ends before the key is reached"),
+            Self::TrailingNodes { proof_index } => {
+                write!(f, "unexpected proof nodes after the end of the path at {proof_index}")
+            }
+            Self::InvalidNode { proof_index, error } => {
+                write!(f, "invalid proof node {proof_index}: {error}")
+            }
+            Self::UnexpectedEmptyRoot { proof_index } => {
+                write!(f, "unexpected empty root node at {proof_index}")
+            }
+        }
+    }
+}
+
+/// The next node on the path to the key.
+#[derive(Clone, Copy)]
+enum NextNode<'a> {
+    /// The node is referenced by its hash, and is the next node in the proof.
+    Hash(B256),
+    /// The node is inlined in its parent.
+    Inline(&'a [u8]),
+}
+
+/// The result of stepping through a node on the path to the key.
+enum Step<'a> {
+    /// The path continues at the given node.
+    Next(NextNode<'a>),
+    /// The path ends at a leaf with the key and the given value.
+    Value(&'a [u8]),
+    /// The path diverges from the key.
+    Absent,
+}
+
+/// Walks the proof along the key, returning the value at the key, if any.
+fn walk<'a, H: TrieHasher>(
+    root: B256,
+    key: &[u8],
+    proof: &[&'a [u8]],
+) -> Result<Option<&'a [u8]>, InPlaceProofError> {
+    let key = PackedNibbles::new(key);
+    let mut nodes = proof.iter().copied().enumerate().peekable();
+    let mut next = NextNode::Hash(root);
+    let mut proof_index = 0;
+    // The number of key nibbles consumed so far.
+    let mut depth = 0;
+    let value = loop {
+        let node = match next {
+            NextNode::Hash(hash) => {
+                let Some((index, node)) = nodes.next() else {
+                    return Err(InPlaceProofError::IncompleteProof);
+                };
+                if node.len() < H::INLINE_THRESHOLD || H::hash(node) != hash {
+                    return Err(InPlaceProofError::NodeMismatch { proof_index: index });
+                }
+                proof_index = index;
+                node
+            }
+            NextNode::Inline(node) => {
+                if let Some((index, _)) = nodes.next_if(|(_, retained)| *retained == node) {
+                    proof_index = index;
+                }
+                node
+            }
+        };
+
+        match step(node, key, &mut depth) {
+            Ok(Step::Next(node)) => next = node,
+            Ok(Step::Value(value)) => break Some(value),
+            Ok(Step::Absent) => break None,
+            Err(StepError::EmptyRoot) => {
+                return Err(InPlaceProofError::UnexpectedEmptyRoot { proof_index })
+            }
+            Err(StepError::Rlp(error)) => {
+                return Err(InPlaceProofError::InvalidNode { proof_index, error })
+            }
+        }
+    };
+
+    match nodes.next() {
+        Some((proof_index, _)) => Err(InPlaceProofError::TrailingNodes { proof_index }),
+        None => Ok(value),
+    }
+}
+
+/// Error while stepping through a node.
+enum StepError {
+    EmptyRoot,
+    Rlp(alloy_rlp::Error),
+}
+
+impl From<alloy_rlp::Error> for StepError {
+    fn from(error: alloy_rlp::Error) -> Self {
+        Self::Rlp(error)
+    }
+}
+
+/// Decodes the node and steps through it along the key, advancing `depth` by the number of key
+/// nibbles consumed.
+fn step<'a>(
+    node: &'a [u8],
+    key: PackedNibbles<'_>,
+    depth: &mut usize,
+) -> Result<Step<'a>, StepError> {
+    let mut payload = node;
+    let header = Header::decode(&mut payload)?;
+    if payload.len() != header.payload_length {
+        return Err(alloy_rlp::Error::UnexpectedLength.into());
+    }
+    if !header.list {
+        return Err(if payload.is_empty() {
+            StepError::EmptyRoot
+        } else {
+            alloy_rlp::Error::UnexpectedString.into()
+        });
+    }
+
+    let mut items = [&[][..]; 17];
+    let mut count = 0;
+    while !payload.is_empty() {
+        let item = items.get_mut(count).ok_or(alloy_rlp::Error::Custom("too many node items"))?;
+        *item = next_item(&mut payload)?;
+        count += 1;
+    }
+
+    let key_len = key.len();
+    match count {
+        17 => {
+            if items[16] != [EMPTY_STRING_CODE] {
+                return Err(alloy_rlp::Error::Custom("branch node values are not supported").into());
+            }
+            if *depth == key_len {
+                return Ok(Step::Absent);
+            }
+            let child = items[key.at(*depth) as usize];
+            *depth += 1;
+            if child == [EMPTY_STRING_CODE] {
+                return Ok(Step::Absent);
+            }
+            Ok(Step::Next(child_node(child)?))
+        }
+        2 => {
+            let path = Header::decode_bytes(&mut &items[0][..], false)?;
+            let Some(&flag) = path.first() else {
+                return Err(alloy_rlp::Error::Custom("trie node key empty").into());
+            };
+            let (is_leaf, is_odd) = match flag & 0xf0 {
+                0x00 => (false, false),
+                0x10 => (false, true),
+                0x20 => (true, false),
+                0x30 => (true, true),
+                _ => return Err(alloy_rlp::Error::Custom("node is not extension or leaf").into()),
+            };
+
+            // The flag nibble is followed by a padding nibble for paths of even length.
+            let path = PackedNibbles::new(path).slice(if is_odd { 1 } else { 2 }..);
+            if !key.slice(*depth..).starts_with(&path) {
+                return Ok(Step::Absent);
+            }
+            *depth += path.len();
+
+            if is_leaf {
+                if *depth != key_len {
+                    return Ok(Step::Absent);
+                }
+                Ok(Step::Value(Header::decode_bytes(&mut &items[1][..], false)?))
+            } else {
+                Ok(Step::Next(child_node(items[1])?))
+            }
+        }
+        _ => Err(alloy_rlp::Error::Custom("unexpected number of trie node items").into()),
+    }
+}
+
+/// Returns the next RLP item in the buffer, including its header, and advances the buffer.
+fn next_item<'a>(buf: &mut &'a [u8]) -> alloy_rlp::Result<&'a [u8]> {
+    let start = *buf;
+    let header = Header::decode(buf)?;
+    if buf.len() < header.payload_length {
+        return Err(alloy_rlp::Error::InputTooShort);
+    }
+    let len = start.len() - buf.len() + header.payload_length;
+    *buf = &start[len..];
+    Ok(&start[..len])
+}
+
+/// Returns the node referenced by the RLP item of a child.
+fn child_node(child: &[u8]) -> alloy_rlp::Result<NextNode<'_>> {
+    match child.first() {
+        Some(&flag) if flag == EMPTY_STRING_CODE + 32 && child.len() == 33 => {
+            Ok(NextNode::Hash(B256::from_slice(&child[1..])))
+        }
+        Some(&flag) if flag >= alloy_rlp::EMPTY_LIST_CODE => Ok(NextNode::Inline(child)),
+        _ => Err(alloy_rlp::Error::Custom("invalid child node reference")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder, Nibbles, EMPTY_ROOT_HASH,
+    };
+    use alloc::{vec, vec::Vec};
+    use alloy_primitives::{keccak256, Bytes};
+
+    fn build(leaves: &[(B256, Vec<u8>)], target: B256) -> (B256, Vec<Bytes>) {
+        let retainer = ProofRetainer::from_iter([Nibbles::unpack(target)]);
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        let proof = hash_builder.take_proof_nodes().into_nodes_sorted();
+        (root, proof.into_iter().map(|(_, node)| node).collect())
+    }
+
+    fn leaves(count: u64, value_len: usize) -> Vec<(B256, Vec<u8>)> {
+        let mut leaves = (0..count)
+            .map(|i| (keccak256(i.to_be_bytes()), vec![i as u8 | 1; value_len]))
+            .collect::<Vec<_>>();
+        leaves.sort();
+        leaves
+    }
+
+    #[test]
+    fn matches_verify_proof() {
+        let leaves = leaves(100, 32);
+        for (key, value) in leaves.iter().step_by(7) {
+            let (root, proof) = build(&leaves, *key);
+            let nodes = proof.iter().map(|node| &node[..]).collect::<Vec<_>>();
+            assert_eq!(verify_proof_in_place(root, &key[..], Some(value), &nodes), Ok(()));
+            assert_eq!(
+                verify_proof(root, Nibbles::unpack(key), Some(value.clone()), &proof),
+                Ok(())
+            );
+            assert_eq!(
+                verify_proof_in_place(root, &key[..], Some(&[0xff]), &nodes),
+                Err(InPlaceProofError::ValueMismatch)
+            );
+            assert_eq!(
+                verify_proof_in_place(root, &key[..], None, &nodes),
+                Err(InPlaceProofError::ValueMismatch)
+            );
+            assert_eq!(
+                verify_proof_in_place(root, &key[..], Some(value), &nodes[..nodes.len() - 1]),
+                Err(InPlaceProofError::IncompleteProof)
+            );
+            assert_eq!(
+                verify_proof_in_place(B256::ZERO, &key[..], Some(value), &nodes),
+                Err(InPlaceProofError::NodeMismatch { proof_index: 0 })
+            );
+        }
+
+        let absent = B256::repeat_byte(0x42);
+        let (root, proof) = build(&leaves, absent);
+        let nodes = proof.iter().map(|node| &node[..]).collect::<Vec<_>>();
+        assert_eq!(verify_proof_in_place(root, &absent[..], None, &nodes), Ok(()));
+        assert_eq!(verify_proof(root, Nibbles::unpack(absent), None, &proof), Ok(()));
+        assert_eq!(
+            verify_proof_in_place(root, &absent[..], Some(&[1]), &nodes),
+            Err(InPlaceProofError::KeyAbsent)
+        );
+
+        let mut trailing = nodes.clone();
+        trailing.push(nodes[0]);
+        assert_eq!(
+            verify_proof_in_place(root, &absent[..], None, &trailing),
+            Err(InPlaceProofError::TrailingNodes { proof_index: nodes.len() })
+        );
+    }
+
+    #[test]
+    fn inline_nodes() {
+        // Short keys and values produce nodes that are inlined in their parents.
+        let leaves = [0x10u8, 0x11, 0x12, 0x20]
+            .map(|key| (B256::with_last_byte(key), vec![key]))
+            .into_iter()
+            .collect::<Vec<_>>();
+        for (key, value) in &leaves {
+            let (root, proof) = build(&leaves, *key);
+            let nodes = proof.iter().map(|node| &node[..]).collect::<Vec<_>>();
+            assert!(nodes.iter().any(|node| node.len() < 32));
+            assert_eq!(verify_proof_in_place(root, &key[..], Some(value), &nodes), Ok(()));
+
+            // Inline nodes may also be omitted from the proof.
+            let hashed = nodes.iter().copied().filter(|node| node.len() >= 32).collect::<Vec<_>>();
+            assert_eq!(verify_proof_in_place(root, &key[..], Some(value), &hashed), Ok(()));
+        }
+
+        let absent = B256::with_last_byte(0x13);
+        let (root, proof) = build(&leaves, absent);
+        let nodes = proof.iter().map(|node| &node[..]).collect::<Vec<_>>();
+        assert_eq!(verify_proof_in_place(root, &absent[..], None, &nodes), Ok(()));
+    }
+
+    #[test]
+    fn empty_trie() {
+        let key = B256::repeat_byte(42);
+        assert_eq!(verify_proof_in_place(EMPTY_ROOT_HASH, &key[..], None, &[]), Ok(()));
+        assert_eq!(
+            verify_proof_in_place(EMPTY_ROOT_HASH, &key[..], None, &[&[EMPTY_STRING_CODE]]),
+            Ok(())
+        );
+        assert_eq!(
+            verify_proof_in_place(EMPTY_ROOT_HASH, &key[..], Some(&[1]), &[]),
+            Err(InPlaceProofError::KeyAbsent)
+        );
+        assert_eq!(
+            verify_proof_in_place(B256::ZERO, &key[..], None, &[]),
+            Err(InPlaceProofError::IncompleteProof)
+        );
+    }
+
+    #[test]
+    fn invalid_node() {
+        let leaves = leaves(2, 32);
+        let key = leaves[0].0;
+        let (_, proof) = build(&leaves, key);
+        let mut node = proof[0].to_vec();
+        node.push(0);
+        let root = keccak256(&node);
+        assert_eq!(
+            verify_proof_in_place(root, &key[..], None, &[&node]),
+            Err(InPlaceProofError::InvalidNode {
+                proof_index: 0,
+                error: alloy_rlp::Error::UnexpectedLength
+            })
+        );
+    }
+}
@@ -4,13 +4,54 @@
 use alloc::vec::Vec;
 
 mod verify;
-pub use verify::verify_proof;
+pub use verify::{verify_proof, verify_proof_with_hasher, verify_value_proof};
+
+mod batch;
+pub use batch::{verify_proofs, verify_proofs_with_hasher};
+
+mod in_place;
+pub use in_place::{verify_proof_in_place, verify_proof_in_place_with_hasher, InPlaceProofError};
+
+mod explain;
+pub use explain::{
+    explain_proof_failure, explain_proof_failure_with_hasher, ProofExplanation, ProofOutcome,
+    ProofStep,
+};
+
+mod account;
+pub use account::{verify_account_proof, AccountProof, StorageProof, VerifiedAccount};
+
+mod decode;
+pub(crate) use decode::decode_indexed_witness;
+pub use decode::decode_witness;
+
+mod rpc;
+pub use rpc::{check_proof_order, decode_rpc_proof, encode_rpc_proof};
+
+mod range;
+pub use range::{verify_range_proof, RangeProof};
 
 mod error;
-pub use error::ProofVerificationError;
+pub use error::{
+    AccountProofError, CompressedProofError, ProofVerificationError, RangeProofError, RpcProofError,
+};
 
 mod proof_nodes;
-pub use proof_nodes::ProofNodes;
+pub use proof_nodes::{ProofNodes, ProofNodesByHash};
 
 mod retainer;
-pub use retainer::ProofRetainer;
+pub use retainer::{ProofRetainer, ProofRetention};
+
+mod multiproof;
+pub use multiproof::{MultiProof, StorageMultiProof};
+
+mod builder;
+pub use builder::MultiProofBuilder;
+
+mod compressed;
+
+mod targets;
+pub use targets::ProofTargets;
+
+mod sufficiency;
+pub use sufficiency::{missing_proof_nodes, missing_proof_nodes_with_hasher};
@@ -0,0 +1,271 @@
+use crate::{
+    proof::{MultiProofBuilder, ProofNodes, ProofTargets},
+    HashMap, Nibbles, TrieAccount, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{Bytes, B256, U256};
+use alloy_rlp::EMPTY_STRING_CODE;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The state multiproof of target accounts and multiproofs of their storage tries.
+///
+/// Proof nodes are shared between all targets of the same trie, so each node is stored only once.
+/// Use [`MultiProof::account_proof`] and [`MultiProof::storage_proof`] to extract the proof for a
+/// single target.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct MultiProof {
+    /// State trie multiproof for all target accounts.
+    pub account_subtree: ProofNodes,
+    /// Storage trie multiproofs keyed by hashed address.
+    pub storages: HashMap<B256, StorageMultiProof>,
+}
+
+impl MultiProof {
+    /// Creates a new multiproof from the proof nodes retained for the state trie.
+    pub fn new(account_subtree: ProofNodes) -> Self {
+        Self { account_subtree, storages: HashMap::default() }
+    }
+
+    /// Computes the state root along with the multiproof of the given targets, in a single pass
+    /// over the accounts keyed by hashed address, along with their storage slots keyed by hashed
+    /// slot.
+    ///
+    /// The accounts and the slots of each account must be sorted by strictly increasing hashed
+    /// key. The storage roots of the accounts are replaced with the roots computed from their
+    /// slots. See [`MultiProofBuilder`] to build the storage tries separately.
+    ///
+    /// # Panics
+    ///
+    /// If the accounts or slots are not sorted.
+    pub fn from_sorted_state<I, S>(targets: ProofTargets, accounts: I) -> (B256, Self)
+    where
+        I: IntoIterator<Item = (B256, TrieAccount, S)>,
+        S: IntoIterator<Item = (B256, U256)>,
+    {
+        let mut builder = MultiProofBuilder::new(targets);
+        for (hashed_address, account, storage) in accounts {
+            builder.add_account_with_storage(hashed_address, account, storage);
+        }
+        builder.finish()
+    }
+
+    /// Returns `true` if the multiproof contains no proof nodes.
+    pub fn is_empty(&self) -> bool {
+        self.account_subtree.is_empty() && self.storages.is_empty()
+    }
+
+    /// Inserts the storage multiproof for the account with the given hashed address, returning
+    /// the previous one, if any.
+    pub fn insert_storage(
+        &mut self,
+        hashed_address: B256,
+        storage: StorageMultiProof,
+    ) -> Option<StorageMultiProof> {
+        self.storages.insert(hashed_address, storage)
+    }
+
+    /// Returns the storage multiproof for the account with the given hashed address.
+    pub fn storage(&self, hashed_address: &B256) -> Option<&StorageMultiProof> {
+        self.storages.get(hashed_address)
+    }
+
+    /// Returns the state trie proof for the account with the given hashed address, ordered from
+    /// the root node to the leaf.
+    pub fn account_proof(&self, hashed_address: B256) -> Vec<Bytes> {
+        proof_for_key(&self.account_subtree, hashed_address)
+    }
+
+    /// Returns the storage trie proof for the given slot of the account with the given hashed
+    /// address, ordered from the root node to the leaf.
+    ///
+    /// Returns [`None`] if the multiproof does not contain the storage multiproof of the account.
+    pub fn storage_proof(&self, hashed_address: &B256, hashed_slot: B256) -> Option<Vec<Bytes>> {
+        self.storage(hashed_address).map(|storage| storage.proof(hashed_slot))
+    }
+
+    /// Extends the multiproof with another one, merging the storage multiproofs of the same
+    /// accounts.
+    ///
+    /// # Panics
+    ///
+    /// If the storage multiproofs of the same account have different roots.
+    pub fn extend(&mut self, other: Self) {
+        self.account_subtree.extend_from(other.account_subtree);
+        for (hashed_address, storage) in other.storages {
+            match self.storages.get_mut(&hashed_address) {
+                Some(existing) => {
+                    assert_eq!(
+                        existing.root, storage.root,
+                        "storage multiproofs of account {hashed_address} have different roots"
+                    );
+                    existing.subtree.extend_from(storage.subtree);
+                }
+                None => {
+                    self.storages.insert(hashed_address, storage);
+                }
+            }
+        }
+    }
+}
+
+/// The multiproof of target slots of a single storage trie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageMultiProof {
+    /// Storage trie root.
+    pub root: B256,
+    /// Storage multiproof for all target slots.
+    pub subtree: ProofNodes,
+}
+
+impl Default for StorageMultiProof {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl StorageMultiProof {
+    /// Creates a new storage multiproof from the storage root and the retained proof nodes.
+    pub const fn new(root: B256, subtree: ProofNodes) -> Self {
+        Self { root, subtree }
+    }
+
+    /// Creates the multiproof of an empty storage trie.
+    pub fn empty() -> Self {
+        Self {
+            root: EMPTY_ROOT_HASH,
+            subtree: ProofNodes::from_iter([(
+                Nibbles::default(),
+                Bytes::from_static(&[EMPTY_STRING_CODE]),
+            )]),
+        }
+    }
+
+    /// Returns the proof for the given hashed slot, ordered from the root node to the leaf.
+    pub fn proof(&self, hashed_slot: B256) -> Vec<Bytes> {
+        proof_for_key(&self.subtree, hashed_slot)
+    }
+}
+
+/// Returns the proof nodes on the path to the given key, ordered from the root node to the leaf.
+fn proof_for_key(nodes: &ProofNodes, key: B256) -> Vec<Bytes> {
+    nodes.matching_nodes_sorted(&Nibbles::unpack(key)).into_iter().map(|(_, node)| node).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder,
+    };
+    use alloy_primitives::{keccak256, U256};
+
+    fn storage_multiproof(slots: &[(B256, U256)], targets: &[B256]) -> StorageMultiProof {
+        let retainer = ProofRetainer::from_iter(targets.iter().map(Nibbles::unpack));
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (slot, value) in slots {
+            hb.add_leaf(Nibbles::unpack(slot), &alloy_rlp::encode(value));
+        }
+        StorageMultiProof::new(hb.root(), hb.take_proof_nodes())
+    }
+
+    #[test]
+    fn lookup_and_verify() {
+        let mut slots = (0..64u64)
+            .map(|i| (keccak256(B256::with_last_byte(i as u8)), U256::from(i + 1)))
+            .collect::<Vec<_>>();
+        slots.sort_unstable();
+        let targets = [slots[3].0, slots[40].0, keccak256([0xff; 32])];
+
+        let storage = storage_multiproof(&slots, &targets);
+        let hashed_address = keccak256([0x42; 20]);
+        let mut multiproof = MultiProof::default();
+        multiproof.insert_storage(hashed_address, storage.clone());
+
+        for (slot, value) in [slots[3], slots[40]] {
+            let proof = multiproof.storage_proof(&hashed_address, slot).unwrap();
+            assert_eq!(
+                verify_proof(
+                    storage.root,
+                    Nibbles::unpack(slot),
+                    Some(alloy_rlp::encode(value)),
+                    &proof
+                ),
+                Ok(())
+            );
+        }
+        let proof = multiproof.storage_proof(&hashed_address, targets[2]).unwrap();
+        assert_eq!(verify_proof(storage.root, Nibbles::unpack(targets[2]), None, &proof), Ok(()));
+        assert_eq!(multiproof.storage_proof(&B256::ZERO, targets[2]), None);
+    }
+
+    #[test]
+    fn extend() {
+        let slots =
+            [(B256::with_last_byte(1), U256::from(1)), (B256::repeat_byte(2), U256::from(2))];
+        let hashed_address = B256::repeat_byte(0xaa);
+
+        let mut first = MultiProof::default();
+        first.insert_storage(hashed_address, storage_multiproof(&slots, &[slots[0].0]));
+        let mut second = MultiProof::default();
+        second.insert_storage(hashed_address, storage_multiproof(&slots, &[slots[1].0]));
+        second.insert_storage(B256::ZERO, StorageMultiProof::empty());
+
+        first.extend(second);
+        assert_eq!(
+            first.storage(&hashed_address),
+            Some(&storage_multiproof(&slots, &[slots[0].0, slots[1].0]))
+        );
+        assert_eq!(first.storage(&B256::ZERO), Some(&StorageMultiProof::empty()));
+        assert_eq!(
+            StorageMultiProof::empty().proof(B256::ZERO),
+            vec![Bytes::from_static(&[EMPTY_STRING_CODE])]
+        );
+    }
+
+    #[test]
+    #[should_panic = "have different roots"]
+    fn extend_different_roots() {
+        let hashed_address = B256::repeat_byte(0xaa);
+        let mut first = MultiProof::default();
+        first.insert_storage(hashed_address, StorageMultiProof::empty());
+        let mut second = MultiProof::default();
+        let slots = [(B256::with_last_byte(1), U256::from(1))];
+        second.insert_storage(hashed_address, storage_multiproof(&slots, &[slots[0].0]));
+        first.extend(second);
+    }
+
+    #[test]
+    fn from_sorted_state() {
+        let mut accounts = (0..16u8)
+            .map(|i| {
+                let mut storage = (1..=i % 4)
+                    .map(|j| (keccak256(B256::with_last_byte(j)), U256::from(j)))
+                    .collect::<Vec<_>>();
+                storage.sort_unstable();
+                (keccak256([i; 20]), TrieAccount { nonce: i as u64, ..Default::default() }, storage)
+            })
+            .collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(|(hashed_address, ..)| *hashed_address);
+        let (hashed_address, _, storage) = accounts[3].clone();
+        let mut targets = ProofTargets::new();
+        targets.insert(hashed_address, storage.iter().map(|(slot, _)| *slot));
+
+        let (root, multiproof) = MultiProof::from_sorted_state(targets.clone(), accounts.clone());
+        let mut builder = MultiProofBuilder::new(targets);
+        for (hashed_address, account, storage) in accounts {
+            builder.add_account_with_storage(hashed_address, account, storage);
+        }
+        assert_eq!((root, multiproof.clone()), builder.finish());
+
+        let storage_root = crate::root::storage_root(storage.clone());
+        let storage_proof = multiproof.storage(&hashed_address).unwrap();
+        assert_eq!(storage_proof.root, storage_root);
+        for (slot, value) in storage {
+            let proof = storage_proof.proof(slot);
+            let value = Some(alloy_rlp::encode(value));
+            assert_eq!(verify_proof(storage_root, Nibbles::unpack(slot), value, &proof), Ok(()));
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use crate::{HashMap, Nibbles};
-use alloy_primitives::Bytes;
+use super::decode_indexed_witness;
+use crate::{nodes::TrieNodeDecodeError, resolver::LeafIter, HashMap, Nibbles};
+use alloy_primitives::{keccak256, Bytes, B256};
 use core::ops::Deref;
 
 #[allow(unused_imports)]
@@ -7,7 +8,11 @@ use alloc::vec::Vec;
 
 /// A wrapper struct for trie node key to RLP encoded trie node.
 #[derive(PartialEq, Eq, Clone, Default, Debug)]
-pub struct ProofNodes(HashMap<Nibbles, Bytes>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofNodes(
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::map_as_seq"))]
+    HashMap<Nibbles, Bytes>,
+);
 
 impl Deref for ProofNodes {
     type Target = HashMap<Nibbles, Bytes>;
@@ -55,6 +60,11 @@ impl ProofNodes {
         self.0.insert(key, node)
     }
 
+    /// Remove the RLP encoded trie node at key.
+    pub fn remove(&mut self, key: &Nibbles) -> Option<Bytes> {
+        self.0.remove(key)
+    }
+
     /// Return the sorted vec of all proof nodes.
     pub fn nodes_sorted(&self) -> Vec<(Nibbles, Bytes)> {
         let mut nodes = Vec::from_iter(self.0.iter().map(|(k, v)| (k.clone(), v.clone())));
@@ -69,6 +79,16 @@ impl ProofNodes {
         nodes
     }
 
+    /// Returns an iterator over the leaves contained in the proof nodes of the trie with the given
+    /// root, in path order.
+    ///
+    /// The iterator returns an error at the first child that is missing from the proof nodes,
+    /// unless [`LeafIter::skip_missing`] is set, in which case only the leaves of the subtries
+    /// revealed by the proof are yielded.
+    pub fn leaves(&self, root: B256) -> LeafIter<ProofNodesByHash> {
+        LeafIter::new(ProofNodesByHash::from(self), root)
+    }
+
     /// Convert wrapper struct into inner map.
     pub fn into_inner(self) -> HashMap<Nibbles, Bytes> {
         self.0
@@ -78,4 +98,201 @@ impl ProofNodes {
     pub fn extend_from(&mut self, other: Self) {
         self.extend(other.0);
     }
+
+    /// Returns the total size of the RLP encoded trie nodes, in bytes.
+    ///
+    /// Nodes that are retained at several paths are counted once for each path. See
+    /// [`Self::dedup_by_hash`].
+    pub fn total_size(&self) -> usize {
+        self.0.values().map(|node| node.len()).sum()
+    }
+
+    /// Removes nodes that are also retained at another path, keeping only the node at the
+    /// shortest, and then lowest, path for each hash. Returns the number of removed nodes.
+    ///
+    /// Identical subtries, e.g. of storage tries with the same slots, produce the same nodes at
+    /// different paths, which only need to be included in a witness once.
+    pub fn dedup_by_hash(&mut self) -> usize {
+        let mut kept = HashMap::<B256, Nibbles>::default();
+        let mut removed = Vec::new();
+        for (path, node) in &self.0 {
+            let kept_path = kept.entry(keccak256(node)).or_insert_with(|| path.clone());
+            if (path.len(), path) < (kept_path.len(), &*kept_path) {
+                removed.push(core::mem::replace(kept_path, path.clone()));
+            } else if path != kept_path {
+                removed.push(path.clone());
+            }
+        }
+        for path in &removed {
+            self.0.remove(path);
+        }
+        removed.len()
+    }
+
+    /// Removes the nodes at paths longer than `depth` nibbles, keeping the upper levels of the
+    /// trie. Returns the number of removed nodes.
+    pub fn prune_below(&mut self, depth: usize) -> usize {
+        let len = self.0.len();
+        self.0.retain(|path, _| path.len() <= depth);
+        len - self.0.len()
+    }
+}
+
+/// A wrapper struct for keccak256 hash of RLP encoded trie node to the node.
+///
+/// This is how witnesses and node requests address trie nodes, as opposed to [`ProofNodes`],
+/// which are keyed by path.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct ProofNodesByHash(HashMap<B256, Bytes>);
+
+impl Deref for ProofNodesByHash {
+    type Target = HashMap<B256, Bytes>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromIterator<Bytes> for ProofNodesByHash {
+    fn from_iter<T: IntoIterator<Item = Bytes>>(iter: T) -> Self {
+        Self(iter.into_iter().map(|node| (keccak256(&node), node)).collect())
+    }
+}
+
+impl Extend<Bytes> for ProofNodesByHash {
+    fn extend<T: IntoIterator<Item = Bytes>>(&mut self, iter: T) {
+        self.0.extend(iter.into_iter().map(|node| (keccak256(&node), node)));
+    }
+}
+
+impl ProofNodesByHash {
+    /// Insert the RLP encoded trie node, returning its hash.
+    pub fn insert(&mut self, node: Bytes) -> B256 {
+        let hash = keccak256(&node);
+        self.0.insert(hash, node);
+        hash
+    }
+
+    /// Convert wrapper struct into inner map.
+    pub fn into_inner(self) -> HashMap<B256, Bytes> {
+        self.0
+    }
+
+    /// Returns the total size of the RLP encoded trie nodes, in bytes.
+    pub fn total_size(&self) -> usize {
+        self.0.values().map(|node| node.len()).sum()
+    }
+
+    /// Converts into proof nodes keyed by path, by traversing the trie with the given root.
+    ///
+    /// Nodes that are not reachable from the root are left out. See
+    /// [`decode_witness`](super::decode_witness).
+    pub fn to_proof_nodes(&self, root: B256) -> Result<ProofNodes, TrieNodeDecodeError> {
+        decode_indexed_witness(root, |hash| self.0.get(hash))
+    }
+}
+
+impl From<&ProofNodes> for ProofNodesByHash {
+    fn from(proof_nodes: &ProofNodes) -> Self {
+        proof_nodes.values().cloned().collect()
+    }
+}
+
+impl From<ProofNodes> for ProofNodesByHash {
+    fn from(proof_nodes: ProofNodes) -> Self {
+        proof_nodes.0.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloy_primitives::U256;
+
+    #[test]
+    fn proof_nodes_by_hash() {
+        let leaves = (0..100u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(9).cloned().collect::<Vec<_>>();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+
+        let by_hash = ProofNodesByHash::from(&proof_nodes);
+        assert_eq!(by_hash.len(), proof_nodes.len());
+        for node in proof_nodes.values() {
+            assert_eq!(by_hash.get(&keccak256(node)), Some(node));
+        }
+        assert_eq!(by_hash.to_proof_nodes(root), Ok(proof_nodes.clone()));
+        assert_eq!(ProofNodesByHash::from(proof_nodes), by_hash);
+
+        let mut other = ProofNodesByHash::default();
+        let node = by_hash.values().next().unwrap().clone();
+        assert_eq!(other.insert(node.clone()), keccak256(&node));
+        other.extend(by_hash.values().cloned());
+        assert_eq!(other, by_hash);
+    }
+
+    #[test]
+    fn witness_leaves() {
+        let leaves = (0..100u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(9).cloned().collect::<Vec<_>>();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets.clone()));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+
+        // The proof doesn't contain the subtries of the other leaves.
+        assert!(proof_nodes.leaves(root).any(|result| result.is_err()));
+
+        let revealed =
+            proof_nodes.leaves(root).skip_missing().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(revealed.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(revealed.iter().all(|(key, value)| leaves.get(key) == Some(value)));
+        for target in &targets {
+            assert!(revealed.iter().any(|(key, _)| key == target));
+        }
+    }
+
+    #[test]
+    fn size_accounting() {
+        let node = |byte: u8, len: usize| Bytes::from(vec![byte; len]);
+        let mut proof_nodes = ProofNodes::from_iter([
+            (Nibbles::default(), node(0, 100)),
+            (Nibbles::from_nibbles([1]), node(1, 40)),
+            (Nibbles::from_nibbles([2]), node(2, 40)),
+            (Nibbles::from_nibbles([1, 3]), node(3, 33)),
+            (Nibbles::from_nibbles([2, 3]), node(3, 33)),
+            (Nibbles::from_nibbles([0, 0, 3]), node(3, 33)),
+        ]);
+        assert_eq!(proof_nodes.total_size(), 279);
+        assert_eq!(ProofNodesByHash::from(&proof_nodes).total_size(), 213);
+
+        let mut deduplicated = proof_nodes.clone();
+        assert_eq!(deduplicated.dedup_by_hash(), 2);
+        assert_eq!(deduplicated.total_size(), 213);
+        assert_eq!(deduplicated.get(&Nibbles::from_nibbles([1, 3])), Some(&node(3, 33)));
+        assert_eq!(deduplicated.len(), 4);
+        assert_eq!(deduplicated.dedup_by_hash(), 0);
+
+        assert_eq!(proof_nodes.prune_below(1), 3);
+        assert_eq!(proof_nodes.total_size(), 180);
+        assert_eq!(proof_nodes.prune_below(0), 2);
+        assert_eq!(proof_nodes.into_nodes_sorted(), [(Nibbles::default(), node(0, 100))]);
+    }
 }
@@ -0,0 +1,547 @@
+//! Range proof generation and verification logic.
+
+use crate::{
+    nodes::TrieNode,
+    proof::{ProofRetainer, RangeProofError},
+    sparse::{SparseNode, SparseTrie, SparseTrieError},
+    HashBuilder, HashMap, Nibbles,
+};
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::Decodable;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// A contiguous range of trie leaves along with the proofs of its edges.
+///
+/// See [`verify_range_proof`] for the verification rules.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct RangeProof {
+    /// The root of the trie.
+    pub root: B256,
+    /// The keys of the leaves in the range.
+    pub keys: Vec<Nibbles>,
+    /// The values of the leaves in the range.
+    pub values: Vec<Vec<u8>>,
+    /// The proof of the start key, ordered from the root node.
+    pub left_edge_proof: Vec<Bytes>,
+    /// The proof of the last key in the range, or of the start key if the range is empty,
+    /// ordered from the root node.
+    pub right_edge_proof: Vec<Bytes>,
+}
+
+impl RangeProof {
+    /// Generates the proof of the leaves with keys between the start and end keys, inclusive.
+    ///
+    /// The leaves must be sorted by key and cover the whole trie, since the root is computed
+    /// along with the proofs in a single [`HashBuilder`] pass. The path to the start key is
+    /// retained from the beginning, while the path to the last key in the range is retained as
+    /// soon as the first leaf after the range is reached.
+    pub fn generate<I, V>(leaves: I, start_key: &Nibbles, end_key: &Nibbles) -> Self
+    where
+        I: IntoIterator<Item = (Nibbles, V)>,
+        V: AsRef<[u8]>,
+    {
+        let retainer = ProofRetainer::from_iter([start_key.clone()]);
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        let mut proof = Self::default();
+        let mut range_closed = false;
+        for (key, value) in leaves {
+            if !range_closed && key > *end_key {
+                proof.retain_right_edge(&mut hash_builder, start_key);
+                range_closed = true;
+            }
+            if !range_closed && key >= *start_key {
+                proof.keys.push(key.clone());
+                proof.values.push(value.as_ref().to_vec());
+            }
+            hash_builder.add_leaf(key, value.as_ref());
+        }
+        if !range_closed {
+            proof.retain_right_edge(&mut hash_builder, start_key);
+        }
+
+        proof.root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+        let right_key = proof.keys.last().unwrap_or(start_key);
+        for (target, edge_proof) in
+            [(start_key, &mut proof.left_edge_proof), (right_key, &mut proof.right_edge_proof)]
+        {
+            *edge_proof = proof_nodes
+                .matching_nodes_sorted(target)
+                .into_iter()
+                .map(|(_, node)| node)
+                .collect();
+        }
+        proof
+    }
+
+    /// Verifies the range against the root of the trie, as if by [`verify_range_proof`].
+    ///
+    /// Returns `true` if the trie has more entries after the range.
+    pub fn verify(&self, start_key: &Nibbles) -> Result<bool, RangeProofError> {
+        verify_range_proof(
+            self.root,
+            start_key,
+            &self.keys,
+            &self.values,
+            &self.left_edge_proof,
+            &self.right_edge_proof,
+        )
+    }
+
+    /// Retains the path to the last key in the range.
+    fn retain_right_edge(&self, hash_builder: &mut HashBuilder, start_key: &Nibbles) {
+        if let Some(retainer) = hash_builder.proof_retainer.as_mut() {
+            retainer.add_target(self.keys.last().unwrap_or(start_key).clone());
+        }
+    }
+}
+
+/// Verify that the given keys and values form a contiguous range of the trie with the provided
+/// root, starting at the start key, as required by the `snap/1` protocol.
+///
+/// The left edge proof must prove the existence or absence of the start key, and the right edge
+/// proof must prove the existence of the last key of the range, or the absence of the start key
+/// if the range is empty. The same proof can be passed as both edge proofs. If both proofs are
+/// empty, the range must contain all entries of the trie.
+///
+/// The partial trie is reconstructed from the edge proofs, all nodes between the edges are
+/// removed, and the range is inserted in their place. The range is valid if the root of the
+/// resulting trie matches the provided root.
+///
+/// All keys, including the start key, must be of the same length. Returns `true` if the trie has
+/// more entries after the range.
+pub fn verify_range_proof<'a, V, L, R>(
+    root: B256,
+    start_key: &Nibbles,
+    keys: &[Nibbles],
+    values: &[V],
+    left_edge_proof: L,
+    right_edge_proof: R,
+) -> Result<bool, RangeProofError>
+where
+    V: AsRef<[u8]>,
+    L: IntoIterator<Item = &'a Bytes>,
+    R: IntoIterator<Item = &'a Bytes>,
+{
+    if keys.len() != values.len() {
+        return Err(RangeProofError::KeyValueCountMismatch {
+            keys: keys.len(),
+            values: values.len(),
+        });
+    }
+    let mut previous = None;
+    for (index, (key, value)) in keys.iter().zip(values).enumerate() {
+        let in_order = match previous {
+            Some(previous) => key > previous,
+            None => key >= start_key,
+        };
+        if !in_order {
+            return Err(RangeProofError::KeysNotIncreasing { index });
+        }
+        if value.as_ref().is_empty() {
+            return Err(RangeProofError::EmptyValue { index });
+        }
+        previous = Some(key);
+    }
+
+    let proof_nodes = left_edge_proof
+        .into_iter()
+        .chain(right_edge_proof)
+        .map(|node| (keccak256(node), node))
+        .collect::<HashMap<_, _>>();
+
+    // Without edge proofs, the range must cover the whole trie.
+    if proof_nodes.is_empty() {
+        let mut hash_builder = HashBuilder::default();
+        for (key, value) in keys.iter().zip(values) {
+            hash_builder.add_leaf(key.clone(), value.as_ref());
+        }
+        let got = hash_builder.root();
+        return if got == root {
+            Ok(false)
+        } else {
+            Err(RangeProofError::RootMismatch { got, expected: root })
+        };
+    }
+
+    let end_key = keys.last().unwrap_or(start_key);
+    let mut trie = SparseTrie::blind(root);
+    reveal_path(&mut trie, &proof_nodes, start_key)?;
+    reveal_path(&mut trie, &proof_nodes, end_key)?;
+
+    let has_more = remove_range(&mut trie, start_key, end_key)?;
+    for (key, value) in keys.iter().zip(values) {
+        trie.update_leaf(key.clone(), value.as_ref().to_vec())?;
+    }
+
+    let got = trie.root();
+    if got != root {
+        return Err(RangeProofError::RootMismatch { got, expected: root });
+    }
+    if keys.is_empty() && has_more {
+        return Err(RangeProofError::MoreEntriesAvailable);
+    }
+    Ok(has_more)
+}
+
+/// Reveals the nodes on the path to the given key that are present among the proof nodes.
+fn reveal_path(
+    trie: &mut SparseTrie,
+    proof_nodes: &HashMap<B256, &Bytes>,
+    key: &Nibbles,
+) -> Result<(), SparseTrieError> {
+    let mut path = Nibbles::default();
+    loop {
+        if let Some(SparseNode::Hash(hash)) = trie.node(&path) {
+            let Some(node) = proof_nodes.get(hash) else { return Ok(()) };
+            let node = TrieNode::decode(&mut &node[..])?;
+            trie.reveal_node(path.clone(), node)?;
+        }
+
+        match trie.node(&path) {
+            Some(SparseNode::Extension { key: extension_key, .. })
+                if key[path.len()..].starts_with(extension_key) =>
+            {
+                path.extend_from_slice(&extension_key.clone());
+            }
+            Some(SparseNode::Branch { state_mask, .. }) => match key.get(path.len()) {
+                Some(&nibble) if state_mask.is_bit_set(nibble) => path.push(nibble),
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// The edge of the range.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Edge {
+    Left,
+    Right,
+}
+
+/// Removes all nodes between the paths to the start and end keys, inclusive.
+///
+/// Returns `true` if the trie has entries after the end key.
+fn remove_range(
+    trie: &mut SparseTrie,
+    start_key: &Nibbles,
+    end_key: &Nibbles,
+) -> Result<bool, SparseTrieError> {
+    let mut path = Nibbles::default();
+    let mut has_more = false;
+    loop {
+        let node = trie.node(&path).cloned().expect("node on the path must exist");
+        match node {
+            SparseNode::Empty => return Ok(has_more),
+            SparseNode::Hash(hash) => return Err(SparseTrieError::BlindedNode { path, hash }),
+            SparseNode::Leaf { key, .. } => {
+                let full_key = path.join(&key);
+                if full_key > *end_key {
+                    return Ok(true);
+                }
+                if full_key >= *start_key {
+                    trie.remove_subtree(&path);
+                }
+                return Ok(has_more);
+            }
+            SparseNode::Extension { key, .. } => {
+                let child_path = path.join(&key);
+                match (cmp_prefix(&child_path, start_key), cmp_prefix(&child_path, end_key)) {
+                    (Ordering::Equal, Ordering::Equal) => path = child_path,
+                    (Ordering::Equal, _) => {
+                        remove_edge(trie, child_path, start_key, Edge::Left)?;
+                        return Ok(has_more);
+                    }
+                    (_, Ordering::Equal) => {
+                        return Ok(remove_edge(trie, child_path, end_key, Edge::Right)? || has_more)
+                    }
+                    (_, Ordering::Greater) => return Ok(true),
+                    (Ordering::Greater, Ordering::Less) => {
+                        trie.remove_subtree(&path);
+                        return Ok(has_more);
+                    }
+                    (Ordering::Less, Ordering::Less) => return Ok(has_more),
+                }
+            }
+            SparseNode::Branch { state_mask, .. } => {
+                let (left, right) = (next_nibble(start_key, &path), next_nibble(end_key, &path));
+                has_more |= children(state_mask).any(|nibble| nibble > right);
+                if left == right {
+                    if left < 0 || !state_mask.is_bit_set(left as u8) {
+                        return Ok(has_more);
+                    }
+                    path.push(left as u8);
+                    continue;
+                }
+
+                for nibble in children(state_mask).filter(|&nibble| nibble > left && nibble < right)
+                {
+                    trie.remove_subtree(&child(&path, nibble));
+                }
+                if left >= 0 && state_mask.is_bit_set(left as u8) {
+                    remove_edge(trie, child(&path, left), start_key, Edge::Left)?;
+                }
+                if right >= 0 && state_mask.is_bit_set(right as u8) {
+                    has_more |= remove_edge(trie, child(&path, right), end_key, Edge::Right)?;
+                }
+                return Ok(has_more);
+            }
+        }
+    }
+}
+
+/// Removes all nodes on the inner side of the path to the given edge key, including the key
+/// itself.
+///
+/// Returns `true` if the trie has entries after the key.
+fn remove_edge(
+    trie: &mut SparseTrie,
+    mut path: Nibbles,
+    key: &Nibbles,
+    edge: Edge,
+) -> Result<bool, SparseTrieError> {
+    let mut has_more = false;
+    loop {
+        let node = trie.node(&path).cloned().expect("node on the path must exist");
+        let ordering = match node {
+            SparseNode::Empty => return Ok(has_more),
+            SparseNode::Hash(hash) => return Err(SparseTrieError::BlindedNode { path, hash }),
+            SparseNode::Leaf { key: leaf_key, .. } => match path.join(&leaf_key).cmp(key) {
+                // The edge key itself is a part of the range.
+                Ordering::Equal if edge == Edge::Left => Ordering::Greater,
+                Ordering::Equal => Ordering::Less,
+                ordering => ordering,
+            },
+            SparseNode::Extension { key: extension_key, .. } => {
+                let child_path = path.join(&extension_key);
+                match cmp_prefix(&child_path, key) {
+                    Ordering::Equal => {
+                        path = child_path;
+                        continue;
+                    }
+                    ordering => ordering,
+                }
+            }
+            SparseNode::Branch { state_mask, .. } => {
+                let next = next_nibble(key, &path);
+                for nibble in children(state_mask) {
+                    let inner = match edge {
+                        Edge::Left => nibble > next,
+                        Edge::Right => nibble < next,
+                    };
+                    if inner {
+                        trie.remove_subtree(&child(&path, nibble));
+                    } else if nibble > next {
+                        has_more = true;
+                    }
+                }
+                if next < 0 || !state_mask.is_bit_set(next as u8) {
+                    return Ok(has_more);
+                }
+                path.push(next as u8);
+                continue;
+            }
+        };
+
+        // The leaf or extension node is entirely on one side of the edge key.
+        let inner = match edge {
+            Edge::Left => ordering == Ordering::Greater,
+            Edge::Right => ordering == Ordering::Less,
+        };
+        if inner {
+            trie.remove_subtree(&path);
+        } else if ordering == Ordering::Greater {
+            has_more = true;
+        }
+        return Ok(has_more);
+    }
+}
+
+/// Compares the path with the prefix of the key of the same length.
+fn cmp_prefix(path: &Nibbles, key: &Nibbles) -> Ordering {
+    path[..].cmp(&key[..path.len().min(key.len())])
+}
+
+/// Returns the nibble of the key following the given path, or `-1` if the key ends at the path.
+fn next_nibble(key: &Nibbles, path: &Nibbles) -> i8 {
+    key.get(path.len()).map_or(-1, |&nibble| nibble as i8)
+}
+
+/// Returns the nibbles of the children set in the state mask.
+fn children(state_mask: crate::TrieMask) -> impl Iterator<Item = i8> {
+    (0..16).filter(move |&nibble| state_mask.is_bit_set(nibble as u8))
+}
+
+/// Returns the path of the child at the given nibble.
+fn child(path: &Nibbles, nibble: i8) -> Nibbles {
+    let mut child = path.clone();
+    child.push(nibble as u8);
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, EMPTY_ROOT_HASH};
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::U256;
+
+    fn leaves(count: u64) -> BTreeMap<Nibbles, Vec<u8>> {
+        (0..count)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect()
+    }
+
+    /// Returns the root of the trie and the proofs of the given keys.
+    fn root_with_proofs(
+        leaves: &BTreeMap<Nibbles, Vec<u8>>,
+        targets: [&Nibbles; 2],
+    ) -> (B256, Vec<Bytes>, Vec<Bytes>) {
+        let retainer = ProofRetainer::from_iter(targets.iter().map(|&target| target.clone()));
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+        let [left, right] = targets.map(|target| {
+            proof_nodes.matching_nodes_sorted(target).into_iter().map(|(_, node)| node).collect()
+        });
+        (root, left, right)
+    }
+
+    fn verify(
+        leaves: &BTreeMap<Nibbles, Vec<u8>>,
+        start: &Nibbles,
+        range: &[(Nibbles, Vec<u8>)],
+    ) -> Result<bool, RangeProofError> {
+        let end = range.last().map_or(start, |(key, _)| key);
+        let (root, left, right) = root_with_proofs(leaves, [start, end]);
+        let (keys, values): (Vec<_>, Vec<_>) = range.iter().cloned().unzip();
+        verify_range_proof(root, start, &keys, &values, &left, &right)
+    }
+
+    #[test]
+    fn valid_ranges() {
+        let leaves = leaves(256);
+        let entries = leaves.clone().into_iter().collect::<Vec<_>>();
+
+        for (start, end) in [(0, 256), (0, 1), (10, 20), (100, 101), (42, 200), (255, 256)] {
+            let range = &entries[start..end];
+            assert_eq!(verify(&leaves, &range[0].0, range), Ok(end < 256), "{start}..{end}");
+        }
+
+        // The start key is absent.
+        let start = entries[17].0.increment().unwrap();
+        assert_eq!(verify(&leaves, &start, &entries[18..30]), Ok(true));
+        assert_eq!(verify(&leaves, &Nibbles::unpack(B256::ZERO), &entries[..30]), Ok(true));
+
+        // The whole trie without edge proofs.
+        let (keys, values): (Vec<_>, Vec<_>) = entries.iter().cloned().unzip();
+        let (root, _, _) = root_with_proofs(&leaves, [&keys[0], &keys[0]]);
+        let start = Nibbles::unpack(B256::ZERO);
+        assert_eq!(verify_range_proof(root, &start, &keys, &values, [], []), Ok(false));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        let leaves = leaves(256);
+        let entries = leaves.clone().into_iter().collect::<Vec<_>>();
+
+        // Missing entry in the middle of the range.
+        let mut range = entries[10..20].to_vec();
+        range.remove(5);
+        assert!(matches!(
+            verify(&leaves, &range[0].0, &range),
+            Err(RangeProofError::RootMismatch { .. })
+        ));
+
+        // Modified value.
+        let mut range = entries[10..20].to_vec();
+        range[3].1 = alloy_rlp::encode(U256::MAX);
+        assert!(matches!(
+            verify(&leaves, &range[0].0, &range),
+            Err(RangeProofError::RootMismatch { .. })
+        ));
+
+        // Missing first entry, while the start key is proven to exist.
+        let range = &entries[11..20];
+        assert!(matches!(
+            verify(&leaves, &entries[10].0, range),
+            Err(RangeProofError::RootMismatch { .. })
+        ));
+
+        // Unordered keys.
+        let range = [entries[11].clone(), entries[10].clone()];
+        assert_eq!(
+            verify(&leaves, &entries[10].0, &range),
+            Err(RangeProofError::KeysNotIncreasing { index: 1 })
+        );
+
+        // Empty range with more entries available.
+        let start = entries[17].0.increment().unwrap();
+        assert_eq!(verify(&leaves, &start, &[]), Err(RangeProofError::MoreEntriesAvailable));
+
+        // Incomplete whole trie.
+        let (keys, values): (Vec<_>, Vec<_>) = entries[1..].iter().cloned().unzip();
+        let (root, _, _) = root_with_proofs(&leaves, [&keys[0], &keys[0]]);
+        assert!(matches!(
+            verify_range_proof(root, &keys[0], &keys, &values, [], []),
+            Err(RangeProofError::RootMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn generate() {
+        let leaves = leaves(256);
+        let entries = leaves.clone().into_iter().collect::<Vec<_>>();
+        let zero = Nibbles::unpack(B256::ZERO);
+        let max = Nibbles::unpack(B256::repeat_byte(0xff));
+
+        for (start, end, has_more) in [
+            (&zero, &max, false),
+            (&entries[0].0, &entries[0].0, true),
+            (&entries[10].0, &entries[20].0, true),
+            (&entries[200].0, &max, false),
+            (&entries[255].0, &entries[255].0, false),
+        ] {
+            let proof = RangeProof::generate(leaves.clone(), start, end);
+            let expected =
+                entries.iter().filter(|(key, _)| key >= start && key <= end).collect::<Vec<_>>();
+            assert_eq!(proof.keys, expected.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>());
+            assert_eq!(proof.verify(start), Ok(has_more));
+        }
+
+        // Range between two adjacent keys.
+        let start = entries[41].0.increment().unwrap();
+        let proof = RangeProof::generate(leaves.clone(), &start, &start);
+        assert!(proof.keys.is_empty());
+        assert_eq!(proof.verify(&start), Err(RangeProofError::MoreEntriesAvailable));
+
+        // Tampered range.
+        let mut proof = RangeProof::generate(leaves, &entries[10].0, &entries[20].0);
+        proof.values[5] = alloy_rlp::encode(U256::MAX);
+        assert!(matches!(proof.verify(&entries[10].0), Err(RangeProofError::RootMismatch { .. })));
+    }
+
+    #[test]
+    fn empty_range() {
+        let leaves = leaves(16);
+        let last = leaves.keys().last().unwrap();
+        let start = last.increment().unwrap();
+        assert_eq!(verify(&leaves, &start, &[]), Ok(false));
+
+        let start = Nibbles::unpack(B256::ZERO);
+        let empty = [Bytes::from_static(&[alloy_rlp::EMPTY_STRING_CODE])];
+        assert_eq!(
+            verify_range_proof::<Vec<u8>, _, _>(EMPTY_ROOT_HASH, &start, &[], &[], &empty, &empty),
+            Ok(false)
+        );
+    }
+}
@@ -1,17 +1,76 @@
-use crate::{proof::ProofNodes, Nibbles};
+use crate::{proof::ProofNodes, KeyHasher, Nibbles};
+use alloc::sync::Arc;
 use alloy_primitives::Bytes;
+use core::{fmt, ops::Range};
 
 #[allow(unused_imports)]
 use alloc::vec::Vec;
 
+/// A policy deciding which nodes emitted by the [`HashBuilder`](crate::HashBuilder) are retained by
+/// a [`ProofRetainer`].
+///
+/// The [`ProofRetainer`] itself retains the nodes on the paths to its targets and ranges. Custom
+/// policies, e.g. retaining the upper levels of the trie or a sample of its nodes, are added with
+/// [`ProofRetainer::with_retention`]. Closures taking the path of a node implement this trait.
+pub trait ProofRetention: Send + Sync {
+    /// Returns `true` if the node at the given path should be retained. The root node is always
+    /// retained.
+    fn should_retain(&self, path: &Nibbles) -> bool;
+}
+
+impl<F: Fn(&Nibbles) -> bool + Send + Sync> ProofRetention for F {
+    fn should_retain(&self, path: &Nibbles) -> bool {
+        self(path)
+    }
+}
+
+/// A shared custom [`ProofRetention`] policy. Policies are compared by identity.
+#[derive(Clone)]
+struct RetentionPolicy(Arc<dyn ProofRetention>);
+
+impl fmt::Debug for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RetentionPolicy(..)")
+    }
+}
+
+impl PartialEq for RetentionPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for RetentionPolicy {}
+
 /// Proof retainer is used to store proofs during merkle trie construction.
 /// It is intended to be used within the [`HashBuilder`](crate::HashBuilder).
-#[derive(Default, Debug)]
+///
+/// Nodes are retained if they are on the path to one of the targets, intersect one of the ranges,
+/// or are selected by one of the custom [`ProofRetention`] policies. Custom policies are not
+/// serialized.
+///
+/// The retainer tracks the total size of the retained nodes, which is the size of the witness
+/// they form. Block builders can check it against a limit while leaves are added, see
+/// [`ProofRetainer::with_size_limit`].
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProofRetainer {
     /// The nibbles of the target trie keys to retain proofs for.
     targets: Vec<Nibbles>,
+    /// The ranges of trie keys to retain all intersecting nodes for.
+    #[cfg_attr(feature = "serde", serde(default))]
+    ranges: Vec<Range<Nibbles>>,
+    /// The custom policies selecting additional nodes to retain.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    policies: Vec<RetentionPolicy>,
     /// The map retained trie node keys to RLP serialized trie nodes.
     proof_nodes: ProofNodes,
+    /// The total length of the retained nodes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    size: usize,
+    /// The size above which the retained nodes exceed the limit.
+    #[cfg_attr(feature = "serde", serde(default))]
+    size_limit: Option<usize>,
 }
 
 impl FromIterator<Nibbles> for ProofRetainer {
@@ -23,12 +82,93 @@ impl FromIterator<Nibbles> for ProofRetainer {
 impl ProofRetainer {
     /// Create new retainer with target nibbles.
     pub fn new(targets: Vec<Nibbles>) -> Self {
-        Self { targets, proof_nodes: Default::default() }
+        Self {
+            targets,
+            ranges: Vec::new(),
+            policies: Vec::new(),
+            proof_nodes: Default::default(),
+            size: 0,
+            size_limit: None,
+        }
+    }
+
+    /// Create new retainer with the paths of the given keys as targets, as mapped by the given
+    /// [`KeyHasher`].
+    pub fn from_keys<K, I>(keys: I) -> Self
+    where
+        K: KeyHasher,
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        keys.into_iter().map(K::hash_key).collect()
+    }
+
+    /// Adds a new target to retain proofs for.
+    ///
+    /// Targets can be added while the trie is being built, as long as no leaf following the
+    /// target was added to the [`HashBuilder`](crate::HashBuilder) yet, since the nodes on the path
+    /// to the target are not finalized before that.
+    pub fn add_target(&mut self, target: Nibbles) {
+        self.targets.push(target);
+    }
+
+    /// Retains every node whose subtrie intersects the given range of keys, in addition to the
+    /// proofs of the targets.
+    ///
+    /// The retained nodes form the proof of all leaves in the range along with the proofs of its
+    /// bounds, as used by snap sync range proofs and subtrie witnesses.
+    pub fn with_range(mut self, range: Range<Nibbles>) -> Self {
+        self.add_range(range);
+        self
+    }
+
+    /// Adds a new range of keys to retain nodes for. See [`Self::with_range`].
+    ///
+    /// As with [`Self::add_target`], ranges can be added while the trie is being built, as long as
+    /// no leaf following the start of the range was added yet.
+    pub fn add_range(&mut self, range: Range<Nibbles>) {
+        self.ranges.push(range);
+    }
+
+    /// Additionally retains the nodes selected by the given policy.
+    pub fn with_retention(mut self, policy: impl ProofRetention + 'static) -> Self {
+        self.policies.push(RetentionPolicy(Arc::new(policy)));
+        self
+    }
+
+    /// Sets the limit of the total size of the retained nodes, in bytes, which is checked with
+    /// [`Self::exceeds_size_limit`].
+    ///
+    /// Nodes are only retained once they are final, so while the trie is built, the retained nodes
+    /// lack the nodes on the path to the last added leaf, which are retained when the next leaf
+    /// diverging from it is added or when the root is computed.
+    pub const fn with_size_limit(mut self, limit: usize) -> Self {
+        self.size_limit = Some(limit);
+        self
+    }
+
+    /// Returns the limit set with [`Self::with_size_limit`].
+    pub const fn size_limit(&self) -> Option<usize> {
+        self.size_limit
+    }
+
+    /// Returns the total size of the RLP encodings of the retained nodes, in bytes.
+    pub const fn size(&self) -> usize {
+        self.size
     }
 
-    /// Returns `true` if the given prefix matches the retainer target.
+    /// Returns `true` if the total size of the retained nodes exceeds the limit set with
+    /// [`Self::with_size_limit`].
+    pub fn exceeds_size_limit(&self) -> bool {
+        self.size_limit.is_some_and(|limit| self.size > limit)
+    }
+
+    /// Returns `true` if the given prefix matches the retainer target, if the subtrie at the
+    /// prefix intersects one of the ranges, or if one of the custom policies retains it.
     pub fn matches(&self, prefix: &Nibbles) -> bool {
         self.targets.iter().any(|target| target.starts_with(prefix))
+            || self.ranges.iter().any(|range| intersects(prefix, range))
+            || self.policies.iter().any(|policy| policy.0.should_retain(prefix))
     }
 
     /// Returns all collected proofs.
@@ -39,7 +179,144 @@ impl ProofRetainer {
     /// Retain the proof if the key matches any of the targets.
     pub fn retain(&mut self, prefix: &Nibbles, proof: &[u8]) {
         if prefix.is_empty() || self.matches(prefix) {
-            self.proof_nodes.insert(prefix.clone(), Bytes::from(proof.to_vec()));
+            self.size += proof.len();
+            if let Some(previous) =
+                self.proof_nodes.insert(prefix.clone(), Bytes::from(proof.to_vec()))
+            {
+                self.size -= previous.len();
+            }
+        }
+    }
+}
+
+impl ProofRetention for ProofRetainer {
+    fn should_retain(&self, path: &Nibbles) -> bool {
+        self.matches(path)
+    }
+}
+
+/// Returns `true` if any key in the subtrie at the given prefix lies in the range.
+fn intersects(prefix: &Nibbles, range: &Range<Nibbles>) -> bool {
+    if *prefix >= range.start {
+        // The prefix is the smallest key of its subtrie.
+        *prefix < range.end
+    } else {
+        // Keys of the subtrie that don't extend the start of the range are smaller than it.
+        range.start.starts_with(prefix) && range.start < range.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashBuilder, IdentityKeyHasher, KeccakKeyHasher};
+    use alloy_primitives::{keccak256, Address};
+
+    #[test]
+    fn from_keys() {
+        let addresses = [Address::with_last_byte(1), Address::with_last_byte(2)];
+        let retainer = ProofRetainer::from_keys::<KeccakKeyHasher, _>(addresses);
+        assert!(retainer.matches(&Nibbles::unpack(keccak256(addresses[1]))));
+        assert!(!retainer.matches(&Nibbles::unpack(addresses[1])));
+
+        let retainer = ProofRetainer::from_keys::<IdentityKeyHasher, _>([[0x80], [0x01]]);
+        assert!(retainer.matches(&Nibbles::from_nibbles([0x8, 0x0])));
+        assert!(retainer.matches(&Nibbles::from_nibbles([0x0])));
+        assert!(!retainer.matches(&Nibbles::from_nibbles([0x1])));
+    }
+
+    #[test]
+    fn ranges() {
+        let nibbles = |nibbles: &[u8]| Nibbles::from_nibbles(nibbles);
+        let retainer = ProofRetainer::default().with_range(nibbles(&[0x2, 0x4])..nibbles(&[0x5]));
+        assert!(retainer.matches(&nibbles(&[])));
+        assert!(retainer.matches(&nibbles(&[0x2])));
+        assert!(retainer.matches(&nibbles(&[0x2, 0x4])));
+        assert!(retainer.matches(&nibbles(&[0x2, 0x4, 0x0])));
+        assert!(retainer.matches(&nibbles(&[0x3, 0xf])));
+        assert!(retainer.matches(&nibbles(&[0x4])));
+        assert!(!retainer.matches(&nibbles(&[0x2, 0x3, 0xf])));
+        assert!(!retainer.matches(&nibbles(&[0x1])));
+        assert!(!retainer.matches(&nibbles(&[0x5])));
+        assert!(!retainer.matches(&nibbles(&[0x5, 0x0])));
+
+        let empty = ProofRetainer::default().with_range(nibbles(&[0x5])..nibbles(&[0x5]));
+        assert!(!empty.matches(&nibbles(&[])));
+        assert!(!empty.matches(&nibbles(&[0x5])));
+    }
+
+    #[test]
+    fn range_proof() {
+        let leaves = (0..200u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(i)))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let start = Nibbles::from_nibbles([0x4]);
+        let end = Nibbles::from_nibbles([0x6, 0x8]);
+        let mut hb = HashBuilder::default()
+            .with_proof_retainer(ProofRetainer::default().with_range(start.clone()..end.clone()));
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
         }
+        let root = hb.root();
+        let proof_nodes = hb.take_proof_nodes();
+
+        // All leaves in the range are revealed by the retained nodes.
+        let revealed =
+            proof_nodes.leaves(root).skip_missing().collect::<Result<Vec<_>, _>>().unwrap();
+        let range = start..end;
+        let expected = leaves.range(range.clone()).map(|(key, value)| (key.clone(), value.clone()));
+        let in_range = revealed.iter().filter(|(key, _)| range.contains(key));
+        assert_eq!(in_range.cloned().collect::<Vec<_>>(), expected.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn size_limit() {
+        let leaves = (0..500u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(i)))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let targets = leaves.keys().step_by(50).cloned();
+        let retainer = ProofRetainer::from_iter(targets).with_size_limit(2000);
+        assert_eq!(retainer.size_limit(), Some(2000));
+
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        let mut exceeded_at = None;
+        for (i, (key, value)) in leaves.iter().enumerate() {
+            hb.add_leaf(key.clone(), value);
+            let retainer = hb.proof_retainer.as_ref().unwrap();
+            if exceeded_at.is_none() && retainer.exceeds_size_limit() {
+                exceeded_at = Some(i);
+            }
+        }
+        assert!(exceeded_at.is_some_and(|i| i < leaves.len() - 1));
+
+        hb.root();
+        let size = hb.proof_retainer.as_ref().unwrap().size();
+        let proof_nodes = hb.take_proof_nodes();
+        assert_eq!(size, proof_nodes.values().map(|node| node.len()).sum::<usize>());
+        assert!(!ProofRetainer::default().exceeds_size_limit());
+    }
+
+    #[test]
+    fn custom_retention() {
+        let leaves = (0..100u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(i)))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        let target = leaves.keys().nth(40).unwrap().clone();
+        let retainer = ProofRetainer::from_iter([target.clone()])
+            .with_retention(|path: &Nibbles| path.len() <= 1);
+        assert!(retainer.should_retain(&target));
+        assert_eq!(retainer, retainer.clone());
+
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root();
+        let proof_nodes = hb.take_proof_nodes();
+
+        // The upper two levels are retained along with the proof of the target.
+        assert_eq!(proof_nodes.keys().filter(|path| path.len() == 1).count(), 16);
+        assert!(proof_nodes.keys().all(|path| path.len() <= 1 || target.starts_with(path)));
+        assert!(proof_nodes.keys().any(|path| path.len() > 1));
     }
 }
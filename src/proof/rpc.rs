@@ -0,0 +1,194 @@
+use crate::{
+    nodes::{RlpNode, TrieNode},
+    proof::{ProofNodes, RpcProofError},
+    Nibbles, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{hex, Bytes, B256};
+use alloy_rlp::EMPTY_STRING_CODE;
+
+#[allow(unused_imports)]
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Encodes the proof of the key as the hex strings of a JSON-RPC response, such as the
+/// `accountProof` of `eth_getProof`, ordered from the root node to the leaf.
+pub fn encode_rpc_proof(proof_nodes: &ProofNodes, key: &Nibbles) -> Vec<String> {
+    proof_nodes.matching_nodes_sorted(key).into_iter().map(|(_, node)| node.to_string()).collect()
+}
+
+/// Decodes the hex strings of a JSON-RPC proof of the key, as returned by `eth_getProof`, checking
+/// that the nodes are ordered from the root node to the leaf. See [`check_proof_order`].
+///
+/// The `0x` prefix of the strings is optional. The returned nodes can be passed to
+/// [`verify_proof`](crate::proof::verify_proof).
+pub fn decode_rpc_proof<I>(root: B256, key: &Nibbles, proof: I) -> Result<Vec<Bytes>, RpcProofError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let proof = proof
+        .into_iter()
+        .enumerate()
+        .map(|(index, node)| {
+            hex::decode(node.as_ref())
+                .map(Bytes::from)
+                .map_err(|_| RpcProofError::InvalidHex { index })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    check_proof_order(root, key, &proof)?;
+    Ok(proof)
+}
+
+/// Checks that the proof of the key is ordered from the root node to the leaf, returning the
+/// proof nodes keyed by their paths.
+///
+/// The first node must be the root, and each following node must be the child referenced by the
+/// previous node on the path to the key. Nodes that are embedded in their parent are expected in
+/// the proof as well. The proof may end before the key is reached, so the order of an incomplete
+/// proof is checked, but its validity is left to [`verify_proof`](crate::proof::verify_proof).
+pub fn check_proof_order(
+    root: B256,
+    key: &Nibbles,
+    proof: &[Bytes],
+) -> Result<ProofNodes, RpcProofError> {
+    let mut proof_nodes = ProofNodes::default();
+    if root == EMPTY_ROOT_HASH && proof.first().map_or(true, |node| node[..] == [EMPTY_STRING_CODE])
+    {
+        if proof.len() > 1 {
+            return Err(RpcProofError::UnexpectedNode { index: 1, path: Nibbles::default() });
+        }
+        proof_nodes.extend(proof.first().map(|node| (Nibbles::default(), node.clone())));
+        return Ok(proof_nodes);
+    }
+
+    let mut path = Nibbles::default();
+    let mut expected = Some(RlpNode::word_rlp(&root));
+    for (index, node) in proof.iter().enumerate() {
+        if expected.as_deref() != Some(RlpNode::from_rlp(node).as_slice()) {
+            return Err(RpcProofError::UnexpectedNode { index, path });
+        }
+        proof_nodes.insert(path.clone(), node.clone());
+
+        expected = match TrieNode::decode_raw(node)
+            .map_err(|error| RpcProofError::InvalidNode { index, error })?
+        {
+            TrieNode::Branch(branch) => key.get(path.len()).and_then(|&nibble| {
+                path.push(nibble);
+                branch.child(nibble).cloned()
+            }),
+            TrieNode::Extension(extension) => {
+                key[path.len()..].starts_with(&extension.key[..]).then(|| {
+                    path.extend_from_slice(&extension.key);
+                    extension.child
+                })
+            }
+            TrieNode::Leaf(leaf) => {
+                path.extend_from_slice(&leaf.key);
+                None
+            }
+            TrieNode::EmptyRoot => None,
+        };
+    }
+    Ok(proof_nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder,
+    };
+    use alloy_primitives::{keccak256, U256};
+
+    /// Builds a trie, returning its root and the proof nodes of the target.
+    fn trie(target: &Nibbles) -> (B256, ProofNodes) {
+        let mut leaves = (0..100u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<Vec<_>>();
+        leaves.sort_unstable();
+        let retainer = ProofRetainer::from_iter([target.clone()]);
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_proof_nodes())
+    }
+
+    #[test]
+    fn roundtrip() {
+        let key = Nibbles::unpack(keccak256(7u64.to_be_bytes()));
+        let (root, proof_nodes) = trie(&key);
+        let hex = encode_rpc_proof(&proof_nodes, &key);
+        assert!(hex.len() > 1);
+        assert!(hex.iter().all(|node| node.starts_with("0x")));
+
+        let proof = decode_rpc_proof(root, &key, &hex).unwrap();
+        assert_eq!(check_proof_order(root, &key, &proof), Ok(proof_nodes));
+        let value = alloy_rlp::encode(U256::from(7));
+        assert_eq!(verify_proof(root, key.clone(), Some(value), &proof), Ok(()));
+
+        // The prefix is optional.
+        let unprefixed = hex.iter().map(|node| node.trim_start_matches("0x")).collect::<Vec<_>>();
+        assert_eq!(decode_rpc_proof(root, &key, unprefixed), Ok(proof.clone()));
+
+        // An exclusion proof of a key next to the leaf.
+        let absent = Nibbles::unpack(B256::repeat_byte(0xab));
+        let (_, proof_nodes) = trie(&absent);
+        let hex = encode_rpc_proof(&proof_nodes, &absent);
+        let proof = decode_rpc_proof(root, &absent, hex).unwrap();
+        assert_eq!(verify_proof(root, absent, None, &proof), Ok(()));
+
+        assert_eq!(
+            decode_rpc_proof(EMPTY_ROOT_HASH, &key, ["0x80"]),
+            Ok(vec![Bytes::from([0x80])])
+        );
+        assert_eq!(check_proof_order(EMPTY_ROOT_HASH, &key, &[]), Ok(ProofNodes::default()));
+    }
+
+    #[test]
+    fn errors() {
+        let key = Nibbles::unpack(keccak256(7u64.to_be_bytes()));
+        let (root, proof_nodes) = trie(&key);
+        let proof = proof_nodes.into_nodes_sorted();
+        let nodes = proof.iter().map(|(_, node)| node.clone()).collect::<Vec<_>>();
+
+        let mut hex = encode_rpc_proof(&proof.iter().cloned().collect(), &key);
+        hex[1].push('z');
+        assert_eq!(decode_rpc_proof(root, &key, &hex), Err(RpcProofError::InvalidHex { index: 1 }));
+
+        // Reversed, as in a leaf-to-root proof.
+        let reversed = nodes.iter().rev().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            check_proof_order(root, &key, &reversed),
+            Err(RpcProofError::UnexpectedNode { index: 0, path: Nibbles::default() })
+        );
+        let mut swapped = nodes.clone();
+        swapped.swap(1, 2);
+        assert_eq!(
+            check_proof_order(root, &key, &swapped),
+            Err(RpcProofError::UnexpectedNode { index: 1, path: proof[1].0.clone() })
+        );
+        // A node after the leaf.
+        let mut trailing = nodes.clone();
+        trailing.push(nodes[0].clone());
+        assert_eq!(
+            check_proof_order(root, &key, &trailing),
+            Err(RpcProofError::UnexpectedNode { index: nodes.len(), path: key.clone() })
+        );
+        assert_eq!(
+            check_proof_order(EMPTY_ROOT_HASH, &key, &[Bytes::from([0x80]), nodes[0].clone()]),
+            Err(RpcProofError::UnexpectedNode { index: 1, path: Nibbles::default() })
+        );
+
+        let invalid = Bytes::from([&[0xe1, 0xa0][..], &[0; 32]].concat());
+        assert!(matches!(
+            check_proof_order(keccak256(&invalid), &key, &[invalid]),
+            Err(RpcProofError::InvalidNode { index: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    nodes::{RlpNode, TrieNode, TrieNodeDecodeError},
+    proof::{MultiProof, ProofNodes, StorageMultiProof},
+    KeccakHasher, Nibbles, TrieHasher,
+};
+use alloy_primitives::B256;
+use core::marker::PhantomData;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Returns the paths of the nodes missing from the proof that are needed to recompute the root of
+/// the trie after updating and removing the given keys.
+///
+/// Besides the nodes on the paths to the keys, removing leaves may collapse a branch node into its
+/// only remaining child, which then needs to be revealed as well. A node whose hash does not match
+/// the reference in its parent is reported as missing. The subtries below missing nodes are not
+/// checked, so the check must be repeated after fetching them. A key that is both updated and
+/// removed is treated as updated.
+///
+/// Returns an empty list if the proof is sufficient, e.g. to apply the changes to a
+/// [`SparseTrie`](crate::sparse::SparseTrie) revealed from it.
+pub fn missing_proof_nodes(
+    root: B256,
+    proof: &ProofNodes,
+    updated: impl IntoIterator<Item = Nibbles>,
+    removed: impl IntoIterator<Item = Nibbles>,
+) -> Result<Vec<Nibbles>, TrieNodeDecodeError> {
+    missing_proof_nodes_with_hasher::<KeccakHasher>(root, proof, updated, removed)
+}
+
+/// Returns the paths of the nodes missing from the proof that are needed to recompute the root,
+/// with the given hasher. See [`missing_proof_nodes`].
+pub fn missing_proof_nodes_with_hasher<H: TrieHasher>(
+    root: B256,
+    proof: &ProofNodes,
+    updated: impl IntoIterator<Item = Nibbles>,
+    removed: impl IntoIterator<Item = Nibbles>,
+) -> Result<Vec<Nibbles>, TrieNodeDecodeError> {
+    let mut targets = updated
+        .into_iter()
+        .map(|key| (key, false))
+        .chain(removed.into_iter().map(|key| (key, true)))
+        .collect::<Vec<_>>();
+    // Updates are ordered before removals of the same key, which are then dropped.
+    targets.sort_unstable();
+    targets.dedup_by(|(key, _), (previous, _)| key == previous);
+
+    let mut checker = SufficiencyChecker::<H> { proof, missing: Vec::new(), _hasher: PhantomData };
+    if root != H::empty_root() {
+        checker.visit(Nibbles::default(), &RlpNode::word_rlp(&root), &targets)?;
+    }
+    Ok(checker.missing)
+}
+
+impl MultiProof {
+    /// Returns the paths of the state trie nodes missing from the multiproof that are needed to
+    /// recompute the state root after updating and removing the accounts with the given hashed
+    /// addresses. See [`missing_proof_nodes`].
+    pub fn missing_account_nodes(
+        &self,
+        state_root: B256,
+        updated: impl IntoIterator<Item = B256>,
+        removed: impl IntoIterator<Item = B256>,
+    ) -> Result<Vec<Nibbles>, TrieNodeDecodeError> {
+        missing_proof_nodes(
+            state_root,
+            &self.account_subtree,
+            updated.into_iter().map(Nibbles::unpack),
+            removed.into_iter().map(Nibbles::unpack),
+        )
+    }
+}
+
+impl StorageMultiProof {
+    /// Returns the paths of the storage trie nodes missing from the multiproof that are needed to
+    /// recompute the storage root after updating and removing the slots with the given hashed
+    /// keys. See [`missing_proof_nodes`].
+    pub fn missing_nodes(
+        &self,
+        updated: impl IntoIterator<Item = B256>,
+        removed: impl IntoIterator<Item = B256>,
+    ) -> Result<Vec<Nibbles>, TrieNodeDecodeError> {
+        missing_proof_nodes(
+            self.root,
+            &self.subtree,
+            updated.into_iter().map(Nibbles::unpack),
+            removed.into_iter().map(Nibbles::unpack),
+        )
+    }
+}
+
+struct SufficiencyChecker<'a, H> {
+    proof: &'a ProofNodes,
+    missing: Vec<Nibbles>,
+    _hasher: PhantomData<H>,
+}
+
+impl<H: TrieHasher> SufficiencyChecker<'_, H> {
+    /// Returns the node referenced at the given path, or [`None`] if it's not in the proof.
+    fn resolve(
+        &self,
+        path: &Nibbles,
+        reference: &RlpNode,
+    ) -> Result<Option<TrieNode>, TrieNodeDecodeError> {
+        match reference.as_hash() {
+            Some(hash) => match self.proof.get(path) {
+                Some(node) if H::hash(node) == hash => TrieNode::decode_raw(node).map(Some),
+                _ => Ok(None),
+            },
+            None => TrieNode::decode_raw(reference).map(Some),
+        }
+    }
+
+    /// Checks the subtrie at the given path against the targets below it, which are sorted by
+    /// key. Returns whether the subtrie is non-empty after the changes.
+    fn visit(
+        &mut self,
+        path: Nibbles,
+        reference: &RlpNode,
+        targets: &[(Nibbles, bool)],
+    ) -> Result<bool, TrieNodeDecodeError> {
+        let has_updates = targets.iter().any(|(_, removed)| !removed);
+        let Some(node) = self.resolve(&path, reference)? else {
+            self.missing.push(path);
+            return Ok(true);
+        };
+
+        match node {
+            TrieNode::EmptyRoot => Ok(has_updates),
+            TrieNode::Leaf(leaf) => {
+                let key = path.join(&leaf.key);
+                Ok(has_updates || !targets.iter().any(|(target, _)| *target == key))
+            }
+            TrieNode::Extension(extension) => {
+                let child_path = path.join(&extension.key);
+                let (below, diverging): (Vec<_>, Vec<_>) =
+                    targets.iter().cloned().partition(|(key, _)| key.starts_with(&child_path));
+                let child_remains =
+                    below.is_empty() || self.visit(child_path, &extension.child, &below)?;
+                Ok(child_remains || diverging.iter().any(|(_, removed)| !removed))
+            }
+            TrieNode::Branch(branch) => {
+                let depth = path.len();
+                let mut remaining = 0;
+                // The only untouched child, which must be revealed if the branch collapses.
+                let mut untouched = None;
+                let mut targets = targets;
+                for nibble in 0..16 {
+                    let end = targets
+                        .iter()
+                        .position(|(key, _)| key.get(depth) != Some(&nibble))
+                        .unwrap_or(targets.len());
+                    let (group, rest) = targets.split_at(end);
+                    targets = rest;
+
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    match branch.child(nibble) {
+                        Some(child) if group.is_empty() => {
+                            remaining += 1;
+                            untouched = Some((child_path, child));
+                        }
+                        Some(child) => remaining += self.visit(child_path, child, group)? as usize,
+                        None => remaining += group.iter().any(|(_, removed)| !removed) as usize,
+                    }
+                }
+
+                if remaining == 1 {
+                    if let Some((child_path, child)) = untouched {
+                        if self.resolve(&child_path, child)?.is_none() {
+                            self.missing.push(child_path);
+                        }
+                    }
+                }
+                Ok(remaining > 0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloy_primitives::hex;
+
+    fn build(leaves: &[(Nibbles, [u8; 32])], targets: &[Nibbles]) -> (B256, ProofNodes) {
+        let mut hb = HashBuilder::default()
+            .with_proof_retainer(ProofRetainer::from_iter(targets.iter().cloned()));
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_proof_nodes())
+    }
+
+    fn key(byte: u8) -> Nibbles {
+        Nibbles::unpack([byte; 32])
+    }
+
+    #[test]
+    fn sufficient_for_proven_keys() {
+        let leaves = [(key(0x11), [1; 32]), (key(0x12), [2; 32]), (key(0x23), [3; 32])];
+        let (root, proof) = build(&leaves, &[key(0x11)]);
+        assert_eq!(missing_proof_nodes(root, &proof, [key(0x11)], []), Ok(vec![]));
+        // A new key diverging from the proven path needs no further nodes.
+        assert_eq!(missing_proof_nodes(root, &proof, [key(0x15)], []), Ok(vec![]));
+        // A key in another subtrie needs its node.
+        assert_eq!(
+            missing_proof_nodes(root, &proof, [key(0x23)], []),
+            Ok(vec![Nibbles::from_nibbles([0x2])])
+        );
+    }
+
+    #[test]
+    fn removal_requires_sibling() {
+        let leaves = [(key(0x11), [1; 32]), (key(0x12), [2; 32]), (key(0x23), [3; 32])];
+        let (root, proof) = build(&leaves, &[key(0x11)]);
+
+        // Removing one of the leaves under [0x1] collapses its branch into the other leaf.
+        assert_eq!(
+            missing_proof_nodes(root, &proof, [], [key(0x11)]),
+            Ok(vec![Nibbles::from_nibbles([0x1, 0x2])])
+        );
+        // Updating the sibling in the same batch reveals it.
+        let (root, proof) = build(&leaves, &[key(0x11), key(0x12)]);
+        assert_eq!(missing_proof_nodes(root, &proof, [key(0x12)], [key(0x11)]), Ok(vec![]));
+        // Removing both leaves collapses the root into the leaf at [0x2].
+        assert_eq!(
+            missing_proof_nodes(root, &proof, [], [key(0x11), key(0x12)]),
+            Ok(vec![Nibbles::from_nibbles([0x2])])
+        );
+        // A key that is both updated and removed is updated.
+        assert_eq!(missing_proof_nodes(root, &proof, [key(0x11)], [key(0x11)]), Ok(vec![]));
+    }
+
+    #[test]
+    fn mismatched_and_empty() {
+        let leaves = [(key(0x11), [1; 32]), (key(0x23), [3; 32])];
+        let (root, mut proof) = build(&leaves, &[key(0x11)]);
+        proof.insert(Nibbles::from_nibbles([0x1]), hex!("c0").into());
+        assert_eq!(
+            missing_proof_nodes(root, &proof, [key(0x11)], []),
+            Ok(vec![Nibbles::from_nibbles([0x1])])
+        );
+
+        let empty = StorageMultiProof::empty();
+        assert_eq!(empty.missing_nodes([B256::ZERO], [B256::ZERO]), Ok(vec![]));
+        assert_eq!(
+            MultiProof::default().missing_account_nodes(root, [], [B256::ZERO]),
+            Ok(vec![Nibbles::default()])
+        );
+    }
+}
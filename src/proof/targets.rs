@@ -0,0 +1,190 @@
+use crate::{proof::ProofRetainer, HashMap, Nibbles};
+use alloy_primitives::{keccak256, map::HashSet, Address, B256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The targets of a state multiproof: the hashed addresses of the target accounts, each with the
+/// hashed slots of its target storage slots.
+///
+/// An account without target slots only needs the state trie proof. The targets provide the
+/// [`ProofRetainer`]s for the state trie and for each storage trie, so that all proofs of a trie
+/// are retained while computing its root once, and collected into a
+/// [`MultiProof`](crate::proof::MultiProof).
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct ProofTargets(HashMap<B256, HashSet<B256>>);
+
+impl FromIterator<(B256, HashSet<B256>)> for ProofTargets {
+    fn from_iter<T: IntoIterator<Item = (B256, HashSet<B256>)>>(iter: T) -> Self {
+        let mut targets = Self::default();
+        for (hashed_address, hashed_slots) in iter {
+            targets.insert(hashed_address, hashed_slots);
+        }
+        targets
+    }
+}
+
+impl IntoIterator for ProofTargets {
+    type Item = (B256, HashSet<B256>);
+    type IntoIter = <HashMap<B256, HashSet<B256>> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl ProofTargets {
+    /// Creates empty proof targets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates proof targets from unhashed addresses and storage slots, hashing them with
+    /// keccak256.
+    pub fn from_unhashed<I, S>(targets: I) -> Self
+    where
+        I: IntoIterator<Item = (Address, S)>,
+        S: IntoIterator<Item = B256>,
+    {
+        targets
+            .into_iter()
+            .map(|(address, slots)| {
+                (keccak256(address), slots.into_iter().map(keccak256).collect::<HashSet<_>>())
+            })
+            .collect()
+    }
+
+    /// Returns `true` if there are no target accounts.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the number of target accounts.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns the number of target storage slots of all accounts.
+    pub fn slots_len(&self) -> usize {
+        self.0.values().map(HashSet::len).sum()
+    }
+
+    /// Adds the account with the given hashed address as target, along with the given hashed
+    /// slots of its storage.
+    pub fn insert(&mut self, hashed_address: B256, hashed_slots: impl IntoIterator<Item = B256>) {
+        self.0.entry(hashed_address).or_default().extend(hashed_slots);
+    }
+
+    /// Returns `true` if the account with the given hashed address is a target.
+    pub fn contains(&self, hashed_address: &B256) -> bool {
+        self.0.contains_key(hashed_address)
+    }
+
+    /// Returns the hashed target slots of the account with the given hashed address, or [`None`]
+    /// if the account is not a target.
+    pub fn slots(&self, hashed_address: &B256) -> Option<&HashSet<B256>> {
+        self.0.get(hashed_address)
+    }
+
+    /// Returns an iterator over the hashed addresses of the target accounts and their hashed
+    /// target slots.
+    pub fn iter(&self) -> impl Iterator<Item = (&B256, &HashSet<B256>)> {
+        self.0.iter()
+    }
+
+    /// Merges the other targets into these ones.
+    pub fn extend(&mut self, other: Self) {
+        for (hashed_address, hashed_slots) in other {
+            self.insert(hashed_address, hashed_slots);
+        }
+    }
+
+    /// Returns the retainer of the state trie proofs of all target accounts.
+    pub fn account_retainer(&self) -> ProofRetainer {
+        self.0.keys().map(Nibbles::unpack).collect()
+    }
+
+    /// Returns the retainer of the storage trie proofs of the target slots of the account with
+    /// the given hashed address, or [`None`] if the account has no target slots.
+    pub fn storage_retainer(&self, hashed_address: &B256) -> Option<ProofRetainer> {
+        self.slots(hashed_address)
+            .filter(|slots| !slots.is_empty())
+            .map(|slots| slots.iter().map(Nibbles::unpack).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, MultiProof, StorageMultiProof},
+        HashBuilder,
+    };
+    use alloy_primitives::{address, U256};
+
+    #[test]
+    fn build_and_merge() {
+        let (a, b) = (address!("00000000000000000000000000000000000000aa"), Address::ZERO);
+        let mut targets = ProofTargets::from_unhashed([(a, vec![B256::ZERO])]);
+        targets.extend(ProofTargets::from_unhashed([
+            (a, vec![B256::ZERO, B256::with_last_byte(1)]),
+            (b, vec![]),
+        ]));
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets.slots_len(), 2);
+        assert!(targets.contains(&keccak256(b)));
+        assert_eq!(
+            targets.slots(&keccak256(a)),
+            Some(&HashSet::from_iter([keccak256(B256::ZERO), keccak256(B256::with_last_byte(1))]))
+        );
+        assert!(targets.storage_retainer(&keccak256(a)).is_some());
+        assert!(targets.storage_retainer(&keccak256(b)).is_none());
+    }
+
+    #[test]
+    fn retains_account_and_storage_proofs() {
+        let slots = (0..16u64).map(|i| B256::from(U256::from(i))).collect::<Vec<_>>();
+        let accounts = (0..16u8).map(Address::with_last_byte).collect::<Vec<_>>();
+        let targets = ProofTargets::from_unhashed([
+            (accounts[3], vec![slots[1], slots[7]]),
+            (accounts[5], vec![]),
+        ]);
+
+        let root_with_proofs = |hashed_keys: &[B256], retainer: ProofRetainer| {
+            let mut leaves = hashed_keys.iter().map(Nibbles::unpack).collect::<Vec<_>>();
+            leaves.sort_unstable();
+            let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+            for key in &leaves {
+                hb.add_leaf(key.clone(), &alloy_rlp::encode(key.len() as u64));
+            }
+            (hb.root(), hb.take_proof_nodes())
+        };
+
+        let hashed_addresses = accounts.iter().map(keccak256).collect::<Vec<_>>();
+        let hashed_slots = slots.iter().map(keccak256).collect::<Vec<_>>();
+        let (state_root, account_subtree) =
+            root_with_proofs(&hashed_addresses, targets.account_retainer());
+        let mut multiproof = MultiProof::new(account_subtree);
+        for (hashed_address, _) in targets.iter() {
+            if let Some(retainer) = targets.storage_retainer(hashed_address) {
+                let (root, subtree) = root_with_proofs(&hashed_slots, retainer);
+                multiproof.insert_storage(*hashed_address, StorageMultiProof::new(root, subtree));
+            }
+        }
+
+        let value = alloy_rlp::encode(64u64);
+        for (hashed_address, hashed_slots) in targets.iter() {
+            let key = Nibbles::unpack(hashed_address);
+            let proof = multiproof.account_proof(*hashed_address);
+            assert_eq!(verify_proof(state_root, key, Some(value.clone()), &proof), Ok(()));
+            for hashed_slot in hashed_slots {
+                let storage = multiproof.storage(hashed_address).unwrap();
+                let proof = storage.proof(*hashed_slot);
+                let key = Nibbles::unpack(hashed_slot);
+                assert_eq!(verify_proof(storage.root, key, Some(value.clone()), &proof), Ok(()));
+            }
+        }
+        assert_eq!(multiproof.storages.len(), 1);
+    }
+}
@@ -5,7 +5,7 @@ use core::ops::Deref;
 use crate::{
     nodes::{BranchNode, RlpNode, TrieNode, CHILD_INDEX_RANGE},
     proof::ProofVerificationError,
-    EMPTY_ROOT_HASH,
+    KeccakHasher, TrieHasher, TrieValue,
 };
 use alloc::vec::Vec;
 use alloy_primitives::{Bytes, B256};
@@ -16,6 +16,13 @@ use nybbles::Nibbles;
 ///
 /// The expected node value can be either [Some] if it's expected to be present
 /// in the tree or [None] if this is an exclusion proof.
+///
+/// An exclusion proof is valid if the path to the key ends at a branch node without a child at
+/// the next nibble of the key, or at a leaf or extension node whose key diverges from it. If a
+/// value is expected, but the proof is a valid exclusion proof for the key,
+/// [`ProofVerificationError::KeyAbsent`] is returned, so that callers can distinguish a missing
+/// key from a malformed proof. A proof that ends before the key is either found or proven absent
+/// is rejected with [`ProofVerificationError::ValueMismatch`].
 pub fn verify_proof<'a, I>(
     root: B256,
     key: Nibbles,
@@ -24,32 +31,71 @@ pub fn verify_proof<'a, I>(
 ) -> Result<(), ProofVerificationError>
 where
     I: IntoIterator<Item = &'a Bytes>,
+{
+    verify_proof_with_hasher::<KeccakHasher, _>(root, key, expected_value, proof)
+}
+
+/// Verify the proof for the given key and typed value against the provided root, encoding the
+/// value with [`TrieValue`].
+///
+/// See [`verify_proof`] for details.
+pub fn verify_value_proof<'a, V, I>(
+    root: B256,
+    key: Nibbles,
+    expected_value: Option<&V>,
+    proof: I,
+) -> Result<(), ProofVerificationError>
+where
+    V: TrieValue,
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    verify_proof(root, key, expected_value.map(TrieValue::to_value), proof)
+}
+
+/// Verify the proof for given key value pair against the provided root of a trie hashed with the
+/// given [`TrieHasher`].
+///
+/// If the hasher has a [value hashing threshold](TrieHasher::VALUE_HASH_THRESHOLD), the expected
+/// value is the full value, and is hashed to compare it with the leaf node. See [`verify_proof`]
+/// for details.
+pub fn verify_proof_with_hasher<'a, H, I>(
+    root: B256,
+    key: Nibbles,
+    expected_value: Option<Vec<u8>>,
+    proof: I,
+) -> Result<(), ProofVerificationError>
+where
+    H: TrieHasher,
+    I: IntoIterator<Item = &'a Bytes>,
 {
     let mut proof = proof.into_iter().peekable();
 
     // If the proof is empty or contains only an empty node, the expected value must be None.
     if proof.peek().map_or(true, |node| node.as_ref() == [EMPTY_STRING_CODE]) {
-        return if root == EMPTY_ROOT_HASH {
+        return if root == H::empty_root() {
             if expected_value.is_none() {
                 Ok(())
             } else {
-                Err(ProofVerificationError::ValueMismatch {
-                    path: key,
-                    got: None,
-                    expected: expected_value.map(Bytes::from),
+                Err(ProofVerificationError::KeyAbsent {
+                    divergence_node: Bytes::from_static(&[EMPTY_STRING_CODE]),
+                    consumed_nibbles: Nibbles::default(),
                 })
             }
         } else {
-            Err(ProofVerificationError::RootMismatch { got: EMPTY_ROOT_HASH, expected: root })
+            Err(ProofVerificationError::RootMismatch { got: H::empty_root(), expected: root })
         };
     }
 
     let mut walked_path = Nibbles::with_capacity(key.len());
     let mut last_decoded_node = Some(NodeDecodingResult::Node(RlpNode::word_rlp(&root)));
+    let mut last_proof_node = None;
     for node in proof {
+        last_proof_node = Some(node);
+
         // Check if the node that we just decoded (or root node, if we just started) matches
         // the expected node from the proof.
-        if Some(RlpNode::from_rlp(node).as_slice()) != last_decoded_node.as_deref() {
+        if Some(RlpNode::from_rlp_with_hasher::<H>(node).as_slice()) != last_decoded_node.as_deref()
+        {
             let got = Some(Bytes::copy_from_slice(node));
             let expected = last_decoded_node.as_deref().map(Bytes::copy_from_slice);
             return Err(ProofVerificationError::ValueMismatch { path: walked_path, got, expected });
@@ -70,9 +116,31 @@ where
         };
     }
 
-    // Last decoded node should have the key that we are looking for.
+    // The key is absent if the path stopped at a branch node without a child at the next nibble
+    // of the key, or diverged from the key at a leaf or an extension node.
+    let absent = match &last_decoded_node {
+        None => true,
+        Some(NodeDecodingResult::Value(_)) => walked_path != key,
+        Some(NodeDecodingResult::Node(_)) => !key.starts_with(&walked_path),
+    };
+    if absent {
+        return match expected_value {
+            None => Ok(()),
+            Some(_) => Err(ProofVerificationError::KeyAbsent {
+                divergence_node: last_proof_node.cloned().unwrap_or_default(),
+                consumed_nibbles: key.slice(..walked_path.common_prefix_length(&key)),
+            }),
+        };
+    }
+
+    // Leaf nodes store the hash of values longer than the value hashing threshold.
+    let expected_value =
+        expected_value.map(|value| H::hash_leaf_value(&value).map_or(value, |hash| hash.to_vec()));
+
+    // Last decoded node should have the key that we are looking for. Otherwise, the proof is
+    // incomplete.
     last_decoded_node = last_decoded_node.filter(|_| walked_path == key);
-    if last_decoded_node.as_deref() == expected_value.as_deref() {
+    if last_decoded_node.is_some() && last_decoded_node.as_deref() == expected_value.as_deref() {
         Ok(())
     } else {
         Err(ProofVerificationError::ValueMismatch {
@@ -182,9 +250,9 @@ mod tests {
     use crate::{
         nodes::{BranchNode, ExtensionNode, LeafNode},
         proof::{ProofNodes, ProofRetainer},
-        triehash_trie_root, HashBuilder, TrieMask,
+        triehash_trie_root, HashBuilder, TrieMask, EMPTY_ROOT_HASH,
     };
-    use alloy_primitives::hex;
+    use alloy_primitives::{b256, hex};
     use alloy_rlp::{Encodable, EMPTY_STRING_CODE};
     use core::str::FromStr;
 
@@ -340,6 +408,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn exclusion_proof_with_expected_value() {
+        let value = B256::with_last_byte(1);
+        let existing_keys = [
+            B256::ZERO,
+            b256!("3a00000000000000000000000000000000000000000000000000000000000000"),
+            b256!("3c15000000000000000000000000000000000000000000000000000000000000"),
+        ];
+        let divergent = b256!("3c19000000000000000000000000000000000000000000000000000000000000");
+        let missing_child =
+            b256!("5000000000000000000000000000000000000000000000000000000000000000");
+
+        let retainer = ProofRetainer::from_iter([divergent, missing_child].map(Nibbles::unpack));
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for key in existing_keys {
+            hash_builder.add_leaf(Nibbles::unpack(key), &value[..]);
+        }
+        let root = hash_builder.root();
+        let proof = hash_builder.take_proof_nodes();
+
+        // The path diverges at the leaf node.
+        let key = Nibbles::unpack(divergent);
+        let nodes = proof.matching_nodes_sorted(&key);
+        assert_eq!(
+            verify_proof(root, key.clone(), Some(value.to_vec()), nodes.iter().map(|(_, n)| n)),
+            Err(ProofVerificationError::KeyAbsent {
+                divergence_node: nodes.last().unwrap().1.clone(),
+                consumed_nibbles: Nibbles::from_nibbles([0x3, 0xc, 0x1]),
+            })
+        );
+
+        // An incomplete proof is not a valid exclusion proof.
+        assert_eq!(
+            verify_proof(root, key.clone(), None, nodes.iter().take(2).map(|(_, n)| n)),
+            Err(ProofVerificationError::ValueMismatch { path: key, got: None, expected: None })
+        );
+
+        // The root branch node has no child at the first nibble.
+        let key = Nibbles::unpack(missing_child);
+        let nodes = proof.matching_nodes_sorted(&key);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            verify_proof(root, key, Some(value.to_vec()), nodes.iter().map(|(_, n)| n)),
+            Err(ProofVerificationError::KeyAbsent {
+                divergence_node: nodes[0].1.clone(),
+                consumed_nibbles: Nibbles::default(),
+            })
+        );
+
+        // Empty trie.
+        assert_eq!(
+            verify_proof(EMPTY_ROOT_HASH, Nibbles::unpack(divergent), Some(value.to_vec()), []),
+            Err(ProofVerificationError::KeyAbsent {
+                divergence_node: Bytes::from_static(&[EMPTY_STRING_CODE]),
+                consumed_nibbles: Nibbles::default(),
+            })
+        );
+    }
+
     #[test]
     fn extension_root_trie_proof_verification() {
         let range = 0..=0xff;
@@ -0,0 +1,254 @@
+//! A naive Merkle Patricia Trie, used as an oracle in differential tests.
+//!
+//! The [`ReferenceTrie`] only stores its leaves, and derives the structure of the trie from them
+//! from scratch each time its root or a proof is computed. This makes it slow but obviously
+//! correct, which is what's needed to test optimized implementations such as the
+//! [`HashBuilder`](crate::HashBuilder), the [`SparseTrie`](crate::sparse::SparseTrie) and proof
+//! verification against it.
+
+use crate::{
+    nodes::{BranchNode, ExtensionNode, LeafNode, RlpNode},
+    proof::ProofNodes,
+    Nibbles, TrieMask,
+};
+use alloc::collections::BTreeMap;
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::EMPTY_STRING_CODE;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A naive Merkle Patricia Trie. See the [module documentation](self).
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct ReferenceTrie {
+    leaves: BTreeMap<Nibbles, Vec<u8>>,
+}
+
+impl FromIterator<(Nibbles, Vec<u8>)> for ReferenceTrie {
+    fn from_iter<T: IntoIterator<Item = (Nibbles, Vec<u8>)>>(iter: T) -> Self {
+        let mut trie = Self::default();
+        for (key, value) in iter {
+            trie.insert(key, value);
+        }
+        trie
+    }
+}
+
+impl ReferenceTrie {
+    /// Returns the leaves of the trie, ordered by key.
+    pub const fn leaves(&self) -> &BTreeMap<Nibbles, Vec<u8>> {
+        &self.leaves
+    }
+
+    /// Returns `true` if the trie has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Returns the value of the leaf at the given key.
+    pub fn get(&self, key: &Nibbles) -> Option<&[u8]> {
+        self.leaves.get(key).map(Vec::as_slice)
+    }
+
+    /// Inserts or updates the leaf at the given key, returning its previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is empty, or if the key is a prefix of another key or the other way
+    /// around, as such tries can't be represented.
+    pub fn insert(&mut self, key: Nibbles, value: Vec<u8>) -> Option<Vec<u8>> {
+        assert!(!value.is_empty(), "leaf values must not be empty");
+        let conflict = self.leaves.keys().find(|existing| {
+            **existing != key && (existing.starts_with(&key) || key.starts_with(existing))
+        });
+        assert!(conflict.is_none(), "key {key:?} conflicts with {conflict:?}");
+        self.leaves.insert(key, value)
+    }
+
+    /// Removes the leaf at the given key, returning its value.
+    pub fn remove(&mut self, key: &Nibbles) -> Option<Vec<u8>> {
+        self.leaves.remove(key)
+    }
+
+    /// Returns the root of the trie.
+    pub fn root(&self) -> B256 {
+        keccak256(self.encode(&mut |_, _| {}))
+    }
+
+    /// Returns the nodes on the path to the given key, ordered from the root node. The last node
+    /// is the leaf for existing keys, or the node where the path diverges from the key otherwise.
+    pub fn proof(&self, key: &Nibbles) -> Vec<Bytes> {
+        self.proof_nodes(core::slice::from_ref(key))
+            .into_nodes_sorted()
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect()
+    }
+
+    /// Returns the nodes on the paths to all given keys, keyed by their path, like the nodes
+    /// retained by a [`ProofRetainer`](crate::proof::ProofRetainer).
+    pub fn proof_nodes(&self, targets: &[Nibbles]) -> ProofNodes {
+        let mut proof_nodes = ProofNodes::default();
+        self.encode(&mut |path, node| {
+            if targets.iter().any(|target| target.starts_with(path)) {
+                proof_nodes.insert(path.clone(), Bytes::copy_from_slice(node));
+            }
+        });
+        proof_nodes
+    }
+
+    /// Returns the RLP encoding of the root node, passing the path and encoding of every node of
+    /// the trie to the visitor.
+    fn encode(&self, visit: &mut impl FnMut(&Nibbles, &[u8])) -> Vec<u8> {
+        if self.leaves.is_empty() {
+            let node = [EMPTY_STRING_CODE];
+            visit(&Nibbles::default(), &node);
+            return node.to_vec();
+        }
+        let entries = self.leaves.iter().collect::<Vec<_>>();
+        encode_subtrie(&entries, 0, visit)
+    }
+}
+
+/// Returns the RLP encoding of the node at the given depth above the sorted entries, which share
+/// their first `depth` nibbles.
+fn encode_subtrie(
+    entries: &[(&Nibbles, &Vec<u8>)],
+    depth: usize,
+    visit: &mut impl FnMut(&Nibbles, &[u8]),
+) -> Vec<u8> {
+    let (first, value) = entries[0];
+    let path = first.slice(..depth);
+
+    let node = if entries.len() == 1 {
+        alloy_rlp::encode(LeafNode::new(first.slice(depth..), value.clone()))
+    } else {
+        let last = entries[entries.len() - 1].0;
+        let common = first[depth..].iter().zip(&last[depth..]).take_while(|(a, b)| a == b).count();
+        if common > 0 {
+            let child = RlpNode::from_rlp(&encode_subtrie(entries, depth + common, visit));
+            alloy_rlp::encode(ExtensionNode::new(first.slice(depth..depth + common), child))
+        } else {
+            let mut stack = Vec::new();
+            let mut state_mask = TrieMask::default();
+            for group in entries.chunk_by(|(a, _), (b, _)| a[depth] == b[depth]) {
+                state_mask.set_bit(group[0].0[depth]);
+                stack.push(RlpNode::from_rlp(&encode_subtrie(group, depth + 1, visit)));
+            }
+            alloy_rlp::encode(BranchNode::new(stack, state_mask))
+        }
+    };
+    visit(&path, &node);
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder, EMPTY_ROOT_HASH};
+    use alloy_primitives::hex;
+
+    fn hash_builder(trie: &ReferenceTrie, targets: &[Nibbles]) -> (B256, ProofNodes) {
+        let retainer = ProofRetainer::from_iter(targets.iter().cloned());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in trie.leaves() {
+            hb.add_leaf(key.clone(), value);
+        }
+        (hb.root(), hb.take_proof_nodes())
+    }
+
+    #[test]
+    fn empty() {
+        let trie = ReferenceTrie::default();
+        assert_eq!(trie.root(), EMPTY_ROOT_HASH);
+        assert_eq!(trie.proof(&Nibbles::unpack(B256::ZERO)), [Bytes::from_static(&[0x80])]);
+    }
+
+    #[test]
+    fn raw_keys() {
+        let leaves = [
+            (hex!("646f").as_slice(), hex!("76657262").as_slice()),
+            (&hex!("676f6f64"), &hex!("7075707079")),
+            (&hex!("676f6b32"), &hex!("7075707079")),
+            (&hex!("676f6b34"), &hex!("7075707079")),
+        ];
+        let mut trie = leaves
+            .iter()
+            .map(|(key, value)| (Nibbles::unpack(key), value.to_vec()))
+            .collect::<ReferenceTrie>();
+        assert_eq!(trie.root(), crate::triehash_trie_root(leaves));
+
+        let targets = trie.leaves().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(trie.proof_nodes(&targets), hash_builder(&trie, &targets).1);
+
+        for (key, _) in &leaves[1..] {
+            assert!(trie.remove(&Nibbles::unpack(key)).is_some());
+        }
+        assert_eq!(trie.root(), crate::triehash_trie_root(leaves[..1].iter().copied()));
+    }
+
+    #[test]
+    #[should_panic = "conflicts"]
+    fn prefix_keys() {
+        let mut trie = ReferenceTrie::default();
+        trie.insert(Nibbles::from_nibbles([0x1, 0x2]), vec![1]);
+        trie.insert(Nibbles::from_nibbles([0x1]), vec![1]);
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    #[cfg_attr(miri, ignore = "no proptest")]
+    fn matches_hash_builder_and_proof_verification() {
+        use crate::proof::verify_proof;
+        use proptest::prelude::*;
+
+        proptest!(|(trie in crate::strategies::trie(0..64), absent: B256)| {
+            let trie = trie.leaves.into_iter().collect::<ReferenceTrie>();
+            let mut targets = trie.leaves().keys().step_by(8).cloned().collect::<Vec<_>>();
+            targets.push(Nibbles::unpack(absent));
+
+            let (root, proof_nodes) = hash_builder(&trie, &targets);
+            prop_assert_eq!(trie.root(), root);
+            prop_assert_eq!(trie.proof_nodes(&targets), proof_nodes);
+            for key in &targets {
+                let value = trie.get(key).map(<[u8]>::to_vec);
+                prop_assert_eq!(verify_proof(root, key.clone(), value, &trie.proof(key)), Ok(()));
+            }
+        });
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    #[cfg_attr(miri, ignore = "no proptest")]
+    fn matches_sparse_trie_updates() {
+        use crate::sparse::SparseTrie;
+        use proptest::{collection::vec, prelude::*};
+
+        let ops = vec(
+            (any::<prop::sample::Index>(), proptest::option::of(vec(any::<u8>(), 1..40))),
+            0..64,
+        );
+        proptest!(|(trie in crate::strategies::clustered_trie(1..64), ops in ops)| {
+            let mut reference = trie.leaves.into_iter().collect::<ReferenceTrie>();
+            let keys = reference.leaves().keys().cloned().collect::<Vec<_>>();
+            let mut sparse = SparseTrie::default();
+            for (key, value) in reference.leaves() {
+                sparse.update_leaf(key.clone(), value.clone()).unwrap();
+            }
+
+            for (index, value) in ops {
+                let key = index.get(&keys).clone();
+                match value {
+                    Some(value) => {
+                        reference.insert(key.clone(), value.clone());
+                        sparse.update_leaf(key, value).unwrap();
+                    }
+                    None => {
+                        prop_assert_eq!(sparse.remove_leaf(&key).unwrap(), reference.remove(&key));
+                    }
+                }
+                prop_assert_eq!(sparse.root(), reference.root());
+            }
+        });
+    }
+}
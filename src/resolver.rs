@@ -0,0 +1,672 @@
+//! Resolution of tries from node stores keyed by hash.
+//!
+//! Node databases and the `eth/63` `GetNodeData` message address trie nodes by the keccak256 hash
+//! of their RLP encoding, which is how nodes reference their children. [`NodeProvider`] abstracts
+//! over such stores, and [`TrieResolver`] walks the trie with a given root through a provider to
+//! iterate over its leaves with [`TrieResolver::leaves`], look up values with
+//! [`TrieResolver::get`], collect proofs with [`TrieResolver::proof`], or find the nearest leaves
+//! to a key with [`TrieResolver::seek_leaf`]. [`extract_subtrie`] isolates the subtrie under a
+//! prefix as a standalone trie.
+
+use crate::{
+    nodes::{ExtensionNode, LeafNode, RlpNode, TrieNode, TrieNodeDecodeError},
+    proof::ProofNodesByHash,
+    HashMap, Nibbles, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{Bytes, B256};
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A store of RLP encoded trie nodes, keyed by their keccak256 hash.
+pub trait NodeProvider {
+    /// Returns the node with the given hash, if it's in the store.
+    fn node(&self, hash: B256) -> Option<Bytes>;
+}
+
+impl<P: NodeProvider + ?Sized> NodeProvider for &P {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        (**self).node(hash)
+    }
+}
+
+impl NodeProvider for HashMap<B256, Bytes> {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        self.get(&hash).cloned()
+    }
+}
+
+impl NodeProvider for ProofNodesByHash {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        self.get(&hash).cloned()
+    }
+}
+
+/// Error during trie resolution.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ResolveError {
+    /// A node referenced by hash is missing from the provider.
+    MissingNode {
+        /// The path of the node.
+        path: Nibbles,
+        /// The hash of the node.
+        hash: B256,
+    },
+    /// A node could not be decoded.
+    Decode {
+        /// The path of the node.
+        path: Nibbles,
+        /// The decoding error.
+        error: TrieNodeDecodeError,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for ResolveError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode { error, .. } => Some(error),
+            Self::MissingNode { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingNode { path, hash } => {
+                write!(f, "missing node {hash} at path {path:?}")
+            }
+            Self::Decode { path, error } => write!(f, "invalid node at path {path:?}: {error}"),
+        }
+    }
+}
+
+/// A leaf of a trie along with its proof, returned by [`TrieResolver::seek_leaf`] and related
+/// queries.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LeafWithProof {
+    /// The full path of the leaf.
+    pub path: Nibbles,
+    /// The value of the leaf.
+    pub value: Vec<u8>,
+    /// The nodes referenced by hash from the root down to the leaf.
+    pub proof: Vec<Bytes>,
+}
+
+/// Walks the trie with a given root through a [`NodeProvider`]. See the
+/// [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct TrieResolver<P> {
+    provider: P,
+    root: B256,
+}
+
+impl<P: NodeProvider> TrieResolver<P> {
+    /// Creates a resolver of the trie with the given root.
+    pub const fn new(provider: P, root: B256) -> Self {
+        Self { provider, root }
+    }
+
+    /// Returns the root of the trie.
+    pub const fn root(&self) -> B256 {
+        self.root
+    }
+
+    /// Returns an iterator over the leaves of the trie, see [`LeafIter`].
+    pub fn leaves(&self) -> LeafIter<&P> {
+        LeafIter::new(&self.provider, self.root)
+    }
+
+    /// Returns the value of the leaf at the given full path, if any.
+    pub fn get(&self, path: &Nibbles) -> Result<Option<Vec<u8>>, ResolveError> {
+        self.walk(path, |_| {})
+    }
+
+    /// Returns the proof of the given full path, which consists of the nodes referenced by hash
+    /// from the root down to the leaf at the path, or down to the node where the path diverges
+    /// from the trie. The proof can be verified with
+    /// [`verify_proof`](crate::proof::verify_proof).
+    pub fn proof(&self, path: &Nibbles) -> Result<Vec<Bytes>, ResolveError> {
+        let mut proof = Vec::new();
+        self.walk(path, |node| proof.push(node))?;
+        Ok(proof)
+    }
+
+    /// Returns an iterator over the proofs of the given full paths, see [`Self::proof`].
+    pub fn proofs<'a, I>(
+        &'a self,
+        paths: I,
+    ) -> impl Iterator<Item = Result<(Nibbles, Vec<Bytes>), ResolveError>> + 'a
+    where
+        I: IntoIterator<Item = Nibbles>,
+        I::IntoIter: 'a,
+    {
+        paths.into_iter().map(|path| self.proof(&path).map(|proof| (path, proof)))
+    }
+
+    /// Returns the first leaf of the trie, along with its proof. See [`Self::seek_leaf`].
+    pub fn first_leaf(&self) -> Result<Option<LeafWithProof>, ResolveError> {
+        self.seek_leaf(&Nibbles::default())
+    }
+
+    /// Returns the last leaf of the trie, along with its proof. See [`Self::seek_leaf`].
+    pub fn last_leaf(&self) -> Result<Option<LeafWithProof>, ResolveError> {
+        if self.root == EMPTY_ROOT_HASH {
+            return Ok(None);
+        }
+
+        let mut proof = Vec::new();
+        let mut path = Nibbles::default();
+        let mut child = RlpNode::word_rlp(&self.root);
+        loop {
+            match resolve(&self.provider, &path, &child, |node| proof.push(node))? {
+                TrieNode::Branch(branch) => {
+                    // Branch nodes have at least two children.
+                    let Some((nibble, last)) = branch.children().last() else { return Ok(None) };
+                    path.push(nibble);
+                    child = last.clone();
+                }
+                TrieNode::Extension(extension) => {
+                    path.extend_from_slice(&extension.key);
+                    child = extension.child;
+                }
+                TrieNode::Leaf(leaf) => {
+                    return Ok(Some(LeafWithProof {
+                        path: path.join(&leaf.key),
+                        value: leaf.value,
+                        proof,
+                    }))
+                }
+                TrieNode::EmptyRoot => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the first leaf with a full path greater than or equal to the given key, along with
+    /// its proof, which consists of the nodes referenced by hash from the root down to the leaf.
+    ///
+    /// Together with the proof of the key, the proof of the leaf shows that there are no leaves
+    /// between the key and the leaf. Returns an error if a node that has to be visited is missing
+    /// from the provider.
+    pub fn seek_leaf(&self, key: &Nibbles) -> Result<Option<LeafWithProof>, ResolveError> {
+        if self.root == EMPTY_ROOT_HASH {
+            return Ok(None);
+        }
+        let mut proof = Vec::new();
+        let leaf =
+            self.seek(Nibbles::default(), &RlpNode::word_rlp(&self.root), key, &mut proof)?;
+        Ok(leaf.map(|(path, value)| LeafWithProof { path, value, proof }))
+    }
+
+    /// Returns the first leaf with a full path strictly greater than the given key, along with its
+    /// proof. See [`Self::seek_leaf`].
+    pub fn next_leaf(&self, key: &Nibbles) -> Result<Option<LeafWithProof>, ResolveError> {
+        // No path lies between the key and the key followed by a zero nibble.
+        let mut successor = key.clone();
+        successor.push(0);
+        self.seek_leaf(&successor)
+    }
+
+    /// Returns the first leaf in the subtrie of the given child with a full path greater than or
+    /// equal to the key, pushing the nodes referenced by hash on the way to it to the proof.
+    fn seek(
+        &self,
+        path: Nibbles,
+        child: &RlpNode,
+        key: &Nibbles,
+        proof: &mut Vec<Bytes>,
+    ) -> Result<Option<(Nibbles, Vec<u8>)>, ResolveError> {
+        let common = path.common_prefix_length(key);
+        // Unless the path is a proper prefix of the key, either all leaves of the subtrie are
+        // greater than or equal to the key, or none are.
+        let all = if common == path.len() {
+            common == key.len()
+        } else {
+            common == key.len() || path[common] > key[common]
+        };
+        if !all && common < path.len() {
+            return Ok(None);
+        }
+        // With `all` set, the key is exhausted, and every leaf of the subtrie matches.
+        let key = if all { &Nibbles::default() } else { key };
+
+        let proof_len = proof.len();
+        let found = match resolve(&self.provider, &path, child, |node| proof.push(node))? {
+            TrieNode::Branch(branch) => {
+                let mut found = None;
+                for (nibble, child) in branch.children() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    found = self.seek(child_path, child, key, proof)?;
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                found
+            }
+            TrieNode::Extension(extension) => {
+                self.seek(path.join(&extension.key), &extension.child, key, proof)?
+            }
+            TrieNode::Leaf(leaf) => {
+                let full_path = path.join(&leaf.key);
+                (full_path >= *key).then_some((full_path, leaf.value))
+            }
+            TrieNode::EmptyRoot => None,
+        };
+        if found.is_none() {
+            proof.truncate(proof_len);
+        }
+        Ok(found)
+    }
+
+    /// Walks the trie along the path, passing the nodes referenced by hash to `on_node`, and
+    /// returns the value of the leaf at the path, if any.
+    fn walk(
+        &self,
+        path: &Nibbles,
+        mut on_node: impl FnMut(Bytes),
+    ) -> Result<Option<Vec<u8>>, ResolveError> {
+        if self.root == EMPTY_ROOT_HASH {
+            return Ok(None);
+        }
+
+        let mut current = Nibbles::default();
+        let mut child = RlpNode::word_rlp(&self.root);
+        loop {
+            let node = resolve(&self.provider, &current, &child, &mut on_node)?;
+            let remaining = &path[current.len()..];
+            match node {
+                TrieNode::Branch(branch) => {
+                    let Some(&nibble) = remaining.first() else { return Ok(None) };
+                    let Some(next) = branch.child(nibble) else { return Ok(None) };
+                    child = next.clone();
+                    current.push(nibble);
+                }
+                TrieNode::Extension(extension) => {
+                    if !remaining.starts_with(&extension.key) {
+                        return Ok(None);
+                    }
+                    current.extend_from_slice(&extension.key);
+                    child = extension.child;
+                }
+                TrieNode::Leaf(leaf) => {
+                    return Ok((remaining == &leaf.key[..]).then_some(leaf.value));
+                }
+                TrieNode::EmptyRoot => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Iterator over the leaves of a trie, as pairs of full path and value, in path order. Returned by
+/// [`TrieResolver::leaves`] and [`ProofNodes::leaves`](crate::proof::ProofNodes::leaves).
+///
+/// Nodes are fetched from the provider as the iterator advances. By default, the iterator ends
+/// after yielding the first error, such as a node missing from the provider. Use
+/// [`Self::skip_missing`] to iterate over the leaves of a partial trie, such as a witness.
+#[derive(Debug)]
+pub struct LeafIter<P> {
+    provider: P,
+    /// The nodes left to visit, along with their paths. The top of the stack is visited first.
+    stack: Vec<(Nibbles, RlpNode)>,
+    skip_missing: bool,
+}
+
+impl<P: NodeProvider> LeafIter<P> {
+    /// Creates an iterator over the leaves of the trie with the given root.
+    pub fn new(provider: P, root: B256) -> Self {
+        let stack = if root == EMPTY_ROOT_HASH {
+            Vec::new()
+        } else {
+            Vec::from([(Nibbles::default(), RlpNode::word_rlp(&root))])
+        };
+        Self { provider, stack, skip_missing: false }
+    }
+
+    /// Skips the subtries of nodes that are missing from the provider instead of returning an
+    /// error, so that only the leaves contained in the available nodes are yielded.
+    pub const fn skip_missing(mut self) -> Self {
+        self.skip_missing = true;
+        self
+    }
+}
+
+impl<P: NodeProvider> Iterator for LeafIter<P> {
+    type Item = Result<(Nibbles, Vec<u8>), ResolveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, child)) = self.stack.pop() {
+            let node = match resolve(&self.provider, &path, &child, |_| {}) {
+                Ok(node) => node,
+                Err(ResolveError::MissingNode { .. }) if self.skip_missing => continue,
+                Err(error) => {
+                    self.stack.clear();
+                    return Some(Err(error));
+                }
+            };
+            match node {
+                TrieNode::Branch(branch) => {
+                    // Children are pushed in reverse, so that they are visited in nibble order.
+                    let children = branch.children().collect::<Vec<_>>();
+                    for (nibble, child) in children.into_iter().rev() {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble);
+                        self.stack.push((child_path, child.clone()));
+                    }
+                }
+                TrieNode::Extension(extension) => {
+                    self.stack.push((path.join(&extension.key), extension.child));
+                }
+                TrieNode::Leaf(leaf) => return Some(Ok((path.join(&leaf.key), leaf.value))),
+                TrieNode::EmptyRoot => {}
+            }
+        }
+        None
+    }
+}
+
+/// Extracts the subtrie under the given prefix of the trie with the given root as a standalone
+/// trie, returning its root and all of its nodes referenced by hash, including the root node.
+///
+/// The keys of the subtrie are the keys of the trie under the prefix, with the prefix removed. If
+/// the prefix ends within the key of an extension or leaf node, the subtrie is rooted at the node
+/// shortened to the rest of its key. The subtrie of a prefix without leaves is empty. The nodes of
+/// the subtrie are fetched from the provider, along with those on the path to the prefix.
+///
+/// This is meant for processing a trie in shards, or for extracting the witness of a subtrie, e.g.
+/// of a storage trie, whose nodes can be walked with a [`TrieResolver`].
+pub fn extract_subtrie<P: NodeProvider>(
+    root: B256,
+    prefix: &Nibbles,
+    provider: P,
+) -> Result<(B256, ProofNodesByHash), ResolveError> {
+    let mut nodes = ProofNodesByHash::default();
+    if root == EMPTY_ROOT_HASH {
+        return Ok((EMPTY_ROOT_HASH, nodes));
+    }
+
+    // Descends to the node at the prefix, or to the node whose key contains the end of the prefix.
+    let mut path = Nibbles::default();
+    let mut child = RlpNode::word_rlp(&root);
+    let subtrie_root = loop {
+        if path.len() == prefix.len() {
+            break fetch(&provider, &path, &child)?;
+        }
+        let remaining = &prefix[path.len()..];
+        match resolve(&provider, &path, &child, |_| {})? {
+            TrieNode::Branch(branch) => {
+                let Some(next) = branch.child(remaining[0]) else {
+                    return Ok((EMPTY_ROOT_HASH, nodes));
+                };
+                child = next.clone();
+                path.push(remaining[0]);
+            }
+            TrieNode::Extension(extension) if remaining.starts_with(&extension.key) => {
+                path.extend_from_slice(&extension.key);
+                child = extension.child;
+            }
+            TrieNode::Extension(extension) if extension.key.starts_with(remaining) => {
+                let key = extension.key.slice(remaining.len()..);
+                break alloy_rlp::encode(ExtensionNode::new(key, extension.child)).into();
+            }
+            TrieNode::Leaf(leaf) if leaf.key.starts_with(remaining) => {
+                let key = leaf.key.slice(remaining.len()..);
+                break alloy_rlp::encode(LeafNode::new(key, leaf.value)).into();
+            }
+            TrieNode::Extension(_) | TrieNode::Leaf(_) | TrieNode::EmptyRoot => {
+                return Ok((EMPTY_ROOT_HASH, nodes));
+            }
+        }
+    };
+
+    // Collects the nodes of the subtrie referenced by hash. The root is always hashed.
+    let subtrie_root_hash = nodes.insert(subtrie_root.clone());
+    let mut stack = Vec::from([(prefix.clone(), subtrie_root)]);
+    while let Some((path, node)) = stack.pop() {
+        let mut push_child = |path: Nibbles, child: &RlpNode| -> Result<(), ResolveError> {
+            let node = fetch(&provider, &path, child)?;
+            if child.is_hash() {
+                nodes.insert(node.clone());
+            }
+            stack.push((path, node));
+            Ok(())
+        };
+        match TrieNode::decode_raw(&node)
+            .map_err(|error| ResolveError::Decode { path: path.clone(), error })?
+        {
+            TrieNode::Branch(branch) => {
+                for (nibble, child) in branch.children() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    push_child(child_path, child)?;
+                }
+            }
+            TrieNode::Extension(extension) => {
+                push_child(path.join(&extension.key), &extension.child)?;
+            }
+            TrieNode::Leaf(_) | TrieNode::EmptyRoot => {}
+        }
+    }
+    Ok((subtrie_root_hash, nodes))
+}
+
+/// Returns the encoding of the node referenced by the given child reference at the given path,
+/// fetching it from the provider if it's referenced by hash.
+fn fetch<P: NodeProvider>(
+    provider: &P,
+    path: &Nibbles,
+    child: &RlpNode,
+) -> Result<Bytes, ResolveError> {
+    match child.as_hash() {
+        Some(hash) => provider
+            .node(hash)
+            .ok_or_else(|| ResolveError::MissingNode { path: path.clone(), hash }),
+        None => Ok(Bytes::copy_from_slice(child)),
+    }
+}
+
+/// Resolves the node referenced by the given child reference at the given path, fetching it from
+/// the provider if it's referenced by hash, in which case it's passed to `on_fetched`.
+pub(crate) fn resolve<P: NodeProvider>(
+    provider: &P,
+    path: &Nibbles,
+    child: &RlpNode,
+    mut on_fetched: impl FnMut(Bytes),
+) -> Result<TrieNode, ResolveError> {
+    let node = fetch(provider, path, child)?;
+    let decoded = TrieNode::decode_raw(&node)
+        .map_err(|error| ResolveError::Decode { path: path.clone(), error })?;
+    if child.is_hash() {
+        on_fetched(node);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proof::{verify_proof, ProofRetainer},
+        HashBuilder,
+    };
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{keccak256, U256};
+
+    /// Builds a trie with the given leaves, returning its root and all of its nodes by hash.
+    fn trie(leaves: &BTreeMap<Nibbles, Vec<u8>>) -> (B256, ProofNodesByHash) {
+        let retainer = ProofRetainer::new(leaves.keys().cloned().collect());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (path, value) in leaves {
+            hb.add_leaf(path.clone(), value);
+        }
+        let root = hb.root();
+        (root, hb.take_proof_nodes().into())
+    }
+
+    fn leaves(n: u64) -> BTreeMap<Nibbles, Vec<u8>> {
+        (0..n)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn iterate_leaves() {
+        let resolver = TrieResolver::new(ProofNodesByHash::default(), EMPTY_ROOT_HASH);
+        assert_eq!(resolver.leaves().count(), 0);
+
+        for n in [1, 2, 100] {
+            let leaves = leaves(n);
+            let (root, nodes) = trie(&leaves);
+            let resolver = TrieResolver::new(&nodes, root);
+            let resolved = resolver.leaves().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(resolved, leaves.clone().into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn inline_nodes() {
+        // Short paths and values produce nodes that are embedded in their parent.
+        let leaves =
+            (0u8..16).map(|i| (Nibbles::from_nibbles([i, 0]), vec![i])).collect::<BTreeMap<_, _>>();
+        let (root, nodes) = trie(&leaves);
+        let resolver = TrieResolver::new(nodes.into_inner(), root);
+        let resolved = resolver.leaves().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(resolved, leaves.clone().into_iter().collect::<Vec<_>>());
+        assert_eq!(resolver.get(&Nibbles::from_nibbles([3, 0])), Ok(Some(vec![3])));
+        assert_eq!(resolver.get(&Nibbles::from_nibbles([3, 1])), Ok(None));
+        assert_eq!(resolver.proof(&Nibbles::from_nibbles([3, 0])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn proofs() {
+        let leaves = leaves(100);
+        let (root, nodes) = trie(&leaves);
+        let resolver = TrieResolver::new(&nodes, root);
+
+        let absent = Nibbles::unpack(B256::repeat_byte(0x11));
+        let targets = leaves.keys().step_by(9).cloned().chain([absent.clone()]);
+        for result in resolver.proofs(targets) {
+            let (path, proof) = result.unwrap();
+            let value = leaves.get(&path).cloned();
+            assert_eq!(resolver.get(&path), Ok(value.clone()));
+            assert_eq!(verify_proof(root, path, value, &proof), Ok(()));
+        }
+        assert_eq!(resolver.get(&absent), Ok(None));
+    }
+
+    #[test]
+    fn seek_leaves() {
+        let resolver = TrieResolver::new(ProofNodesByHash::default(), EMPTY_ROOT_HASH);
+        assert_eq!(resolver.first_leaf(), Ok(None));
+        assert_eq!(resolver.last_leaf(), Ok(None));
+
+        let leaves = leaves(100);
+        let (root, nodes) = trie(&leaves);
+        let resolver = TrieResolver::new(&nodes, root);
+        let check = |leaf: Option<LeafWithProof>, expected: Option<(&Nibbles, &Vec<u8>)>| {
+            let leaf = leaf.map(|leaf| {
+                let value = Some(leaf.value.clone());
+                assert_eq!(verify_proof(root, leaf.path.clone(), value, &leaf.proof), Ok(()));
+                (leaf.path, leaf.value)
+            });
+            assert_eq!(leaf, expected.map(|(path, value)| (path.clone(), value.clone())));
+        };
+
+        check(resolver.first_leaf().unwrap(), leaves.iter().next());
+        check(resolver.last_leaf().unwrap(), leaves.iter().next_back());
+
+        let mut keys = leaves.keys().cloned().collect::<Vec<_>>();
+        keys.extend((0u8..50).map(|i| Nibbles::unpack(keccak256([i]))));
+        keys.extend([Nibbles::from_nibbles([0x7]), Nibbles::from_nibbles([0xf; 65])]);
+        for key in &keys {
+            check(resolver.seek_leaf(key).unwrap(), leaves.range(key.clone()..).next());
+            let next = leaves
+                .range((core::ops::Bound::Excluded(key.clone()), core::ops::Bound::Unbounded))
+                .next();
+            check(resolver.next_leaf(key).unwrap(), next);
+        }
+    }
+
+    #[test]
+    fn subtries() {
+        let clustered = (0..100u64)
+            .map(|i| {
+                let mut key = B256::repeat_byte(0xab);
+                key[31] = i as u8;
+                key[0] = (i % 3) as u8;
+                (Nibbles::unpack(key), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        for leaves in [leaves(200), clustered] {
+            let (root, nodes) = trie(&leaves);
+            let (first, _) = leaves.iter().next().unwrap();
+            let mut prefixes = (0..=8).map(|len| first.slice(..len)).collect::<Vec<_>>();
+            prefixes.extend([first.clone(), Nibbles::from_nibbles([0xf, 0xf, 0xf, 0xf])]);
+            for prefix in prefixes {
+                let expected = leaves
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(&prefix))
+                    .map(|(key, value)| (key.slice(prefix.len()..), value.clone()))
+                    .collect::<BTreeMap<_, _>>();
+                let (subtrie_root, subtrie_nodes) = extract_subtrie(root, &prefix, &nodes).unwrap();
+                let expected_root = match expected.get(&Nibbles::default()) {
+                    // The hash builder doesn't take empty keys.
+                    Some(value) => keccak256(alloy_rlp::encode(LeafNode::new(
+                        Nibbles::default(),
+                        value.clone(),
+                    ))),
+                    None => trie(&expected).0,
+                };
+                assert_eq!(subtrie_root, expected_root, "{prefix:?}");
+
+                let resolver = TrieResolver::new(&subtrie_nodes, subtrie_root);
+                let resolved = resolver.leaves().collect::<Result<BTreeMap<_, _>, _>>();
+                assert_eq!(resolved, Ok(expected), "{prefix:?}");
+                assert!(subtrie_nodes
+                    .keys()
+                    .all(|hash| *hash == subtrie_root || nodes.contains_key(hash)));
+            }
+        }
+
+        let (root, nodes) = trie(&leaves(10));
+        assert_eq!(extract_subtrie(root, &Nibbles::default(), &nodes).unwrap().1, nodes);
+        assert_eq!(
+            extract_subtrie(root, &Nibbles::default(), ProofNodesByHash::default()),
+            Err(ResolveError::MissingNode { path: Nibbles::default(), hash: root })
+        );
+    }
+
+    #[test]
+    fn missing_node() {
+        let leaves = leaves(100);
+        let (root, nodes) = trie(&leaves);
+        let (path, _) = leaves.iter().nth(50).unwrap();
+        let proof = TrieResolver::new(&nodes, root).proof(path).unwrap();
+        let removed = keccak256(proof.last().unwrap());
+        let mut nodes = nodes.into_inner();
+        nodes.remove(&removed);
+
+        let resolver = TrieResolver::new(&nodes, root);
+        assert!(matches!(
+            resolver.get(path),
+            Err(ResolveError::MissingNode { hash, .. }) if hash == removed
+        ));
+        let mut iter = resolver.leaves();
+        assert!(iter.by_ref().any(|result| result.is_err()));
+        assert!(iter.next().is_none());
+
+        // Skipping the missing node yields all other leaves.
+        let resolved = resolver.leaves().skip_missing().collect::<Result<Vec<_>, _>>().unwrap();
+        let expected = leaves
+            .iter()
+            .filter(|(key, _)| *key != path)
+            .map(|(key, value)| (key.clone(), value.clone()));
+        assert_eq!(resolved, expected.collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,310 @@
+//! Root computations that can be evaluated at compile time.
+
+use crate::{EMPTY_ROOT_HASH, EMPTY_ROOT_RLP, KECCAK_EMPTY};
+use alloy_primitives::B256;
+
+/// The number of bytes absorbed per permutation by keccak256.
+const RATE: usize = 136;
+
+/// The round constants of keccak-f\[1600\].
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+/// The rotation offsets of the lanes visited by the combined rho and pi steps.
+const ROTATIONS: [u32; 24] =
+    [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+
+/// The order in which the combined rho and pi steps visit the lanes.
+const PI_LANES: [usize; 24] =
+    [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+
+/// The largest key accepted by [`const_leaf_root`].
+const MAX_KEY_LEN: usize = 64;
+
+/// The largest value accepted by [`const_leaf_root`].
+const MAX_VALUE_LEN: usize = 1024;
+
+// The constants are consistent with the hash function.
+const _: () = {
+    assert!(bytes_eq(&const_keccak256(&EMPTY_ROOT_RLP).0, &EMPTY_ROOT_HASH.0));
+    assert!(bytes_eq(&const_keccak256(&[]).0, &KECCAK_EMPTY.0));
+};
+
+/// Computes the keccak256 hash of the input in a `const` context.
+///
+/// This is much slower than [`keccak256`](alloy_primitives::keccak256), and is meant for
+/// computing constants at compile time.
+pub const fn const_keccak256(data: &[u8]) -> B256 {
+    let mut state = [0u64; 25];
+    let mut offset = 0;
+    while data.len() - offset >= RATE {
+        let mut block = [0u8; RATE];
+        let mut i = 0;
+        while i < RATE {
+            block[i] = data[offset + i];
+            i += 1;
+        }
+        state = keccak_f(absorb(state, block));
+        offset += RATE;
+    }
+
+    // The last block is padded with `0x01 0x00.. 0x80`, which is `0x81` if it's a single byte.
+    let mut block = [0u8; RATE];
+    let mut i = 0;
+    while offset + i < data.len() {
+        block[i] = data[offset + i];
+        i += 1;
+    }
+    block[i] ^= 0x01;
+    block[RATE - 1] ^= 0x80;
+    state = keccak_f(absorb(state, block));
+
+    let mut out = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        out[i] = (state[i / 8] >> (8 * (i % 8))) as u8;
+        i += 1;
+    }
+    B256::new(out)
+}
+
+/// Computes the root of the trie with a single leaf in a `const` context, e.g. for static genesis
+/// definitions with a single account or storage slot.
+///
+/// The path of the leaf is the nibbles of `key`, which must already be hashed for secure tries,
+/// e.g. with [`const_keccak256`]. The value is stored as is, so it must already be RLP-encoded.
+///
+/// # Panics
+///
+/// If the key is longer than 64 bytes or the value is longer than 1024 bytes, which fails the
+/// compilation if evaluated at compile time.
+pub const fn const_leaf_root(key: &[u8], value: &[u8]) -> B256 {
+    assert!(key.len() <= MAX_KEY_LEN, "key too long");
+    assert!(value.len() <= MAX_VALUE_LEN, "value too long");
+
+    // The leaf node is the list of its hex-prefix encoded path, whose flag byte is `0x20` for
+    // leaves with an even number of nibbles, and its value.
+    let path_len = 1 + key.len();
+    let path_header_len = if path_len == 1 { 0 } else { string_header_len(path_len) };
+    let value_header_len =
+        if value.len() == 1 && value[0] < 0x80 { 0 } else { string_header_len(value.len()) };
+    let payload_len = path_header_len + path_len + value_header_len + value.len();
+
+    let buf = [0u8; 9 + MAX_KEY_LEN + MAX_VALUE_LEN + 10];
+    let (buf, len) = append_header(buf, 0, 0xc0, payload_len);
+    let (buf, len) =
+        if path_header_len > 0 { append_header(buf, len, 0x80, path_len) } else { (buf, len) };
+    let (buf, len) = append(buf, len, &[0x20]);
+    let (buf, len) = append(buf, len, key);
+    let (buf, len) =
+        if value_header_len > 0 { append_header(buf, len, 0x80, value.len()) } else { (buf, len) };
+    let (buf, len) = append(buf, len, value);
+
+    let (encoded, _) = buf.split_at(len);
+    const_keccak256(encoded)
+}
+
+/// Returns the length of the RLP header of a string or list with a payload of the given length.
+const fn string_header_len(payload_len: usize) -> usize {
+    if payload_len < 56 {
+        1
+    } else {
+        1 + be_len(payload_len)
+    }
+}
+
+/// Returns the number of bytes of the big-endian encoding of the value without leading zeros.
+const fn be_len(value: usize) -> usize {
+    (usize::BITS as usize - value.leading_zeros() as usize).div_ceil(8)
+}
+
+/// Appends the RLP header with the given short-form offset (`0x80` for strings, `0xc0` for
+/// lists) at `at`, returning the buffer and the position after the header.
+const fn append_header<const N: usize>(
+    buf: [u8; N],
+    at: usize,
+    offset: u8,
+    payload_len: usize,
+) -> ([u8; N], usize) {
+    if payload_len < 56 {
+        return append(buf, at, &[offset + payload_len as u8]);
+    }
+    let len_len = be_len(payload_len);
+    let mut header = [offset + 55 + len_len as u8, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut i = 0;
+    while i < len_len {
+        header[1 + i] = (payload_len >> (8 * (len_len - 1 - i))) as u8;
+        i += 1;
+    }
+    let (header, _) = header.split_at(1 + len_len);
+    append(buf, at, header)
+}
+
+/// Appends the bytes at `at`, returning the buffer and the position after them.
+const fn append<const N: usize>(mut buf: [u8; N], at: usize, bytes: &[u8]) -> ([u8; N], usize) {
+    let mut i = 0;
+    while i < bytes.len() {
+        buf[at + i] = bytes[i];
+        i += 1;
+    }
+    (buf, at + bytes.len())
+}
+
+/// XORs the block into the state, as little-endian lanes.
+const fn absorb(mut state: [u64; 25], block: [u8; RATE]) -> [u64; 25] {
+    let mut lane = 0;
+    while lane < RATE / 8 {
+        let mut bytes = [0u8; 8];
+        let mut i = 0;
+        while i < 8 {
+            bytes[i] = block[lane * 8 + i];
+            i += 1;
+        }
+        state[lane] ^= u64::from_le_bytes(bytes);
+        lane += 1;
+    }
+    state
+}
+
+/// The keccak-f\[1600\] permutation.
+const fn keccak_f(mut state: [u64; 25]) -> [u64; 25] {
+    let mut round = 0;
+    while round < 24 {
+        // Theta.
+        let mut columns = [0u64; 5];
+        let mut x = 0;
+        while x < 5 {
+            columns[x] = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+            x += 1;
+        }
+        x = 0;
+        while x < 5 {
+            let t = columns[(x + 4) % 5] ^ columns[(x + 1) % 5].rotate_left(1);
+            let mut y = 0;
+            while y < 25 {
+                state[y + x] ^= t;
+                y += 5;
+            }
+            x += 1;
+        }
+
+        // Rho and pi.
+        let mut last = state[1];
+        let mut i = 0;
+        while i < 24 {
+            let lane = PI_LANES[i];
+            let next = state[lane];
+            state[lane] = last.rotate_left(ROTATIONS[i]);
+            last = next;
+            i += 1;
+        }
+
+        // Chi.
+        let mut y = 0;
+        while y < 25 {
+            let row = [state[y], state[y + 1], state[y + 2], state[y + 3], state[y + 4]];
+            x = 0;
+            while x < 5 {
+                state[y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+                x += 1;
+            }
+            y += 5;
+        }
+
+        // Iota.
+        state[0] ^= ROUND_CONSTANTS[round];
+        round += 1;
+    }
+    state
+}
+
+/// Compares two byte slices in a `const` context.
+const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashBuilder, Nibbles, TrieAccount};
+    use alloy_primitives::{keccak256, U256};
+
+    #[allow(unused_imports)]
+    use alloc::vec::Vec;
+
+    #[test]
+    fn keccak() {
+        let data = (0..=255u8).cycle().take(3 * RATE + 7).collect::<Vec<_>>();
+        for len in (0..=data.len()).filter(|len| len % RATE < 3 || RATE - len % RATE < 3) {
+            assert_eq!(const_keccak256(&data[..len]), keccak256(&data[..len]), "{len}");
+        }
+        assert_eq!(const_keccak256(b"hello"), keccak256(b"hello"));
+    }
+
+    #[test]
+    fn leaf_root() {
+        let key = keccak256([1]);
+        for len in [0, 1, 2, 31, 32, 54, 55, 56, 255, 256, 1024] {
+            let value = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let mut hb = HashBuilder::default();
+            hb.add_leaf(Nibbles::unpack(key), &value);
+            assert_eq!(const_leaf_root(key.as_slice(), &value), hb.root(), "{len}");
+        }
+
+        // A single-byte value below 0x80 is its own encoding, and short keys produce nodes shorter
+        // than a hash, which are still hashed at the root.
+        let mut hb = HashBuilder::default();
+        hb.add_leaf(Nibbles::unpack([0x12]), &[0x7f]);
+        assert_eq!(const_leaf_root(&[0x12], &[0x7f]), hb.root());
+    }
+
+    #[test]
+    fn compile_time() {
+        const ADDRESS: [u8; 20] = [0xaa; 20];
+        const ACCOUNT_KEY: B256 = const_keccak256(&ADDRESS);
+        const STATE_ROOT: B256 = const_leaf_root(&ACCOUNT_KEY.0, &TrieAccount::EMPTY_RLP);
+
+        let account = TrieAccount::default();
+        assert_eq!(alloy_rlp::encode(account), TrieAccount::EMPTY_RLP);
+        assert_eq!(TrieAccount::EMPTY, account);
+        assert_eq!(STATE_ROOT, crate::root::state_root([(ADDRESS.into(), account, [])]));
+        assert_eq!(
+            const_leaf_root(&const_keccak256(&U256::from(1).to_be_bytes::<32>()).0, &[0x2a]),
+            crate::root::storage_root_unhashed([(B256::with_last_byte(1), U256::from(42))])
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use super::state_root_with_key_hasher;
+use crate::{KeccakKeyHasher, KeyHasher, TrieAccount, KECCAK_EMPTY};
+use alloc::collections::BTreeMap;
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+
+/// An account in a genesis allocation, as found in the `alloc` section of a geth genesis file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenesisAccount {
+    /// The nonce of the account, zero if not set.
+    pub nonce: Option<u64>,
+    /// The balance of the account.
+    pub balance: U256,
+    /// The code of the account, if any.
+    pub code: Option<Bytes>,
+    /// The storage of the account, keyed by slot.
+    pub storage: Option<BTreeMap<B256, B256>>,
+}
+
+impl GenesisAccount {
+    /// Returns the account as it is stored in the state trie, with the given storage root.
+    pub fn trie_account(&self, storage_root: B256) -> TrieAccount {
+        TrieAccount {
+            nonce: self.nonce.unwrap_or_default(),
+            balance: self.balance,
+            storage_root,
+            code_hash: self.code.as_ref().map_or(KECCAK_EMPTY, keccak256),
+        }
+    }
+}
+
+/// Computes the state root of a genesis allocation, matching geth.
+///
+/// Accounts without code have the code hash of empty code, and storage slots with zero values are
+/// not stored, as if they were never set.
+pub fn genesis_state_root<'a, I>(alloc: I) -> B256
+where
+    I: IntoIterator<Item = (&'a Address, &'a GenesisAccount)>,
+{
+    genesis_state_root_with_key_hasher::<KeccakKeyHasher, _>(alloc)
+}
+
+/// Computes the state root of a genesis allocation, mapping the addresses and slots to paths with
+/// the given [`KeyHasher`]. See [`genesis_state_root`].
+pub fn genesis_state_root_with_key_hasher<'a, KH, I>(alloc: I) -> B256
+where
+    KH: KeyHasher,
+    I: IntoIterator<Item = (&'a Address, &'a GenesisAccount)>,
+{
+    state_root_with_key_hasher::<KH, _, _, _>(alloc.into_iter().map(|(address, account)| {
+        let storage = account
+            .storage
+            .iter()
+            .flatten()
+            .map(|(slot, value)| (*slot, U256::from_be_bytes(value.0)));
+        (*address, account.trie_account(Default::default()), storage)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{triehash_trie_root, EMPTY_ROOT_HASH};
+
+    #[test]
+    fn genesis_root() {
+        assert_eq!(genesis_state_root(&BTreeMap::new()), EMPTY_ROOT_HASH);
+
+        let code = Bytes::from_static(&[0x60, 0x00, 0x60, 0x00, 0xf3]);
+        let storage = BTreeMap::from([
+            (B256::with_last_byte(1), B256::with_last_byte(0xaa)),
+            (B256::with_last_byte(2), B256::ZERO),
+        ]);
+        let alloc = BTreeMap::from([
+            (
+                Address::with_last_byte(1),
+                GenesisAccount { balance: U256::from(1), ..Default::default() },
+            ),
+            (
+                Address::with_last_byte(2),
+                GenesisAccount {
+                    nonce: Some(1),
+                    code: Some(code.clone()),
+                    storage: Some(storage),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let storage_root = triehash_trie_root([(
+            keccak256(B256::with_last_byte(1)),
+            alloy_rlp::encode(U256::from(0xaa)),
+        )]);
+        let expected = triehash_trie_root([
+            (
+                keccak256(Address::with_last_byte(1)),
+                alloy_rlp::encode(TrieAccount { balance: U256::from(1), ..Default::default() }),
+            ),
+            (
+                keccak256(Address::with_last_byte(2)),
+                alloy_rlp::encode(TrieAccount {
+                    nonce: 1,
+                    balance: U256::ZERO,
+                    storage_root,
+                    code_hash: keccak256(&code),
+                }),
+            ),
+        ]);
+        assert_eq!(genesis_state_root(&alloc), expected);
+
+        // Empty code and storage are the same as none.
+        let mut empty = alloc.clone();
+        let account = empty.get_mut(&Address::with_last_byte(1)).unwrap();
+        account.code = Some(Bytes::new());
+        account.storage = Some(BTreeMap::from([(B256::ZERO, B256::ZERO)]));
+        assert_eq!(account.trie_account(EMPTY_ROOT_HASH).code_hash, KECCAK_EMPTY);
+        assert_eq!(genesis_state_root(&empty), expected);
+    }
+}
@@ -0,0 +1,517 @@
+use alloc::vec::Vec;
+use alloy_primitives::{Address, B256, U256};
+use alloy_rlp::Encodable;
+use nybbles::Nibbles;
+
+use crate::{
+    hash_builder::HashBuilderError, HashBuilder, IdentityKeyHasher, KeccakKeyHasher, KeyHasher,
+    TrieAccount, TrieValue, EMPTY_ROOT_HASH,
+};
+
+mod consts;
+pub use consts::{const_keccak256, const_leaf_root};
+
+mod genesis;
+pub use genesis::{genesis_state_root, genesis_state_root_with_key_hasher, GenesisAccount};
+
+mod state;
+pub use state::{StateRoot, StateRootError, StorageRoot};
+
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
+/// Adjust the index of an item for rlp encoding.
+pub const fn adjust_index_for_rlp(i: usize, len: usize) -> usize {
+    if i > 0x7f {
+        i
+    } else if i == 0x7f || i + 1 == len {
+        0
+    } else {
+        i + 1
+    }
+}
+
+/// Returns the key of the item at the given index in an ordered trie, such as the transactions
+/// or receipts trie of a block, which is the RLP encoding of the index.
+///
+/// Note that index 0 is encoded as `0x80`, while indices 1 to 127 are encoded as single bytes, so
+/// the item at index 0 is not the first leaf of the trie.
+pub fn ordered_trie_key(index: usize) -> Nibbles {
+    IdentityKeyHasher::hash_key(alloy_rlp::encode_fixed_size(&index))
+}
+
+/// Computes the root of a trie from its entries, whose keys are mapped to paths with the given
+/// [`KeyHasher`].
+///
+/// The entries don't need to be sorted. Use [`KeccakKeyHasher`] for secure tries and
+/// [`IdentityKeyHasher`] for tries keyed by the raw key.
+pub fn trie_root<K, I, Key, V>(entries: I) -> B256
+where
+    K: KeyHasher,
+    I: IntoIterator<Item = (Key, V)>,
+    Key: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    let mut leaves =
+        entries.into_iter().map(|(key, value)| (K::hash_key(key), value)).collect::<Vec<_>>();
+    leaves.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut hash_builder = HashBuilder::default();
+    for (path, value) in leaves {
+        hash_builder.add_leaf(path, value.as_ref());
+    }
+    hash_builder.root()
+}
+
+/// Computes the root of a trie from its entries with typed values, whose keys are mapped to paths
+/// with the given [`KeyHasher`]. See [`trie_root`].
+pub fn trie_root_values<K, I, Key, V>(entries: I) -> B256
+where
+    K: KeyHasher,
+    I: IntoIterator<Item = (Key, V)>,
+    Key: AsRef<[u8]>,
+    V: TrieValue,
+{
+    trie_root::<K, _, _, _>(entries.into_iter().map(|(key, value)| (key, value.to_value())))
+}
+
+/// Computes the root of an ordered trie of typed values, such as the [`TypedEnvelope`]s of the
+/// transactions or receipts of a block. Items are keyed by their index, see [`ordered_trie_key`].
+///
+/// [`TypedEnvelope`]: crate::TypedEnvelope
+pub fn ordered_trie_root_values<I>(items: I) -> B256
+where
+    I: IntoIterator,
+    I::Item: TrieValue,
+{
+    ordered_trie_root_encoder(items, |item, buf| item.encode_value(buf))
+}
+
+/// Compute a trie root of the collection of rlp encodable items.
+pub fn ordered_trie_root<T: Encodable>(items: &[T]) -> B256 {
+    ordered_trie_root_with_encoder(items, |item, buf| item.encode(buf))
+}
+
+/// Compute a trie root of the collection of items with a custom encoder.
+pub fn ordered_trie_root_with_encoder<T, F>(items: &[T], mut encode: F) -> B256
+where
+    F: FnMut(&T, &mut Vec<u8>),
+{
+    ordered_trie_root_encoder(items, |item, buf| encode(item, buf))
+}
+
+/// Computes the root of an ordered trie, writing the value of each item into the buffer with the
+/// given encoder. Items are keyed by their index, see [`ordered_trie_key`].
+///
+/// The items are consumed in order, without being collected. Note that the value of an item is
+/// the raw output of the encoder: EIP-2718 typed transactions and receipts must be encoded as
+/// `type || rlp(payload)`, without an RLP string header, see [`ordered_trie_root_typed`].
+pub fn ordered_trie_root_encoder<I, F>(items: I, mut encode: F) -> B256
+where
+    I: IntoIterator,
+    F: FnMut(I::Item, &mut Vec<u8>),
+{
+    let mut items = items.into_iter();
+    let Some(first) = items.next() else { return EMPTY_ROOT_HASH };
+
+    // The key of the first item sorts after the keys of the items at indices 1 to 127, so its
+    // value is held back until then.
+    let mut first_value = Vec::new();
+    encode(first, &mut first_value);
+    let mut first_value = Some(first_value);
+
+    let mut hb = HashBuilder::default();
+    let mut value_buffer = Vec::new();
+    for (index, item) in (1..).zip(items) {
+        if index == 0x80 {
+            if let Some(first_value) = first_value.take() {
+                hb.add_leaf(ordered_trie_key(0), &first_value);
+            }
+        }
+
+        value_buffer.clear();
+        encode(item, &mut value_buffer);
+        hb.add_leaf(ordered_trie_key(index), &value_buffer);
+    }
+    if let Some(first_value) = first_value {
+        hb.add_leaf(ordered_trie_key(0), &first_value);
+    }
+
+    hb.root()
+}
+
+/// Computes the root of an ordered trie of EIP-2718 envelopes, such as the transactions or
+/// receipts of a block, given as pairs of transaction type and RLP encodable payload.
+///
+/// Legacy items, with type `0`, are encoded as `rlp(payload)`, while typed items are encoded as
+/// `type || rlp(payload)`.
+pub fn ordered_trie_root_typed<I, T>(items: I) -> B256
+where
+    I: IntoIterator<Item = (u8, T)>,
+    T: Encodable,
+{
+    ordered_trie_root_encoder(items, |(ty, payload), buf| {
+        if ty != 0 {
+            buf.push(ty);
+        }
+        payload.encode(buf);
+    })
+}
+
+/// Computes the root of a trie from leaves keyed by hashed key, writing the value of each item into
+/// a reused buffer with the given encoder.
+///
+/// The leaves are consumed in order, without being collected, and must be sorted by strictly
+/// increasing hashed key. Returns [`HashBuilderError::NonMonotonicKey`] otherwise.
+pub fn root_from_iter_with<I, T, F>(leaves: I, mut encode: F) -> Result<B256, HashBuilderError>
+where
+    I: IntoIterator<Item = (B256, T)>,
+    F: FnMut(T, &mut Vec<u8>),
+{
+    let mut hash_builder = HashBuilder::default();
+    let mut value_buffer = Vec::new();
+    for (hashed_key, item) in leaves {
+        value_buffer.clear();
+        encode(item, &mut value_buffer);
+        hash_builder.try_add_leaf(Nibbles::unpack(hashed_key), &value_buffer)?;
+    }
+    Ok(hash_builder.root())
+}
+
+/// Computes the root of a storage trie from storage slots keyed by hashed slot.
+///
+/// The slots don't need to be sorted. Slots with zero values are skipped, as they are not stored
+/// in the trie. Returns [`HashBuilderError::NonMonotonicKey`] if a slot is given more than once.
+pub fn try_storage_root<S>(storage: S) -> Result<B256, HashBuilderError>
+where
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    let mut slots = storage.into_iter().filter(|(_, value)| !value.is_zero()).collect::<Vec<_>>();
+    slots.sort_unstable_by_key(|(hashed_slot, _)| *hashed_slot);
+    root_from_iter_with(slots, |value, buf| value.encode(buf))
+}
+
+/// Computes the root of a storage trie from storage slots keyed by hashed slot. See
+/// [`try_storage_root`].
+///
+/// # Panics
+///
+/// If a slot is given more than once.
+pub fn storage_root<S>(storage: S) -> B256
+where
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    try_storage_root(storage).unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// Computes the root of a trie from leaves keyed by path, writing the value of each item into a
+/// reused buffer with the given encoder. The leaves don't need to be sorted.
+fn try_root_from_paths<T, F>(
+    mut leaves: Vec<(Nibbles, T)>,
+    mut encode: F,
+) -> Result<B256, HashBuilderError>
+where
+    F: FnMut(T, &mut Vec<u8>),
+{
+    leaves.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let mut hash_builder = HashBuilder::default();
+    let mut value_buffer = Vec::new();
+    for (path, item) in leaves {
+        value_buffer.clear();
+        encode(item, &mut value_buffer);
+        hash_builder.try_add_leaf(path, &value_buffer)?;
+    }
+    Ok(hash_builder.root())
+}
+
+fn try_storage_root_with_key_hasher<KH, S, K>(storage: S) -> Result<B256, HashBuilderError>
+where
+    KH: KeyHasher,
+    S: IntoIterator<Item = (K, U256)>,
+    K: Into<B256>,
+{
+    let slots = storage
+        .into_iter()
+        .filter(|(_, value)| !value.is_zero())
+        .map(|(slot, value)| (KH::hash_key(slot.into()), value))
+        .collect();
+    try_root_from_paths(slots, |value, buf| value.encode(buf))
+}
+
+/// Computes the root of a storage trie from storage slots keyed by slot, mapping the slots to
+/// paths with the given [`KeyHasher`]. See [`storage_root_unhashed`].
+///
+/// # Panics
+///
+/// If a slot is given more than once.
+pub fn storage_root_unhashed_with_key_hasher<KH, S, K>(storage: S) -> B256
+where
+    KH: KeyHasher,
+    S: IntoIterator<Item = (K, U256)>,
+    K: Into<B256>,
+{
+    try_storage_root_with_key_hasher::<KH, _, _>(storage).unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// Computes the root of a storage trie from storage slots keyed by slot, hashing the slots.
+///
+/// Slots can be given either as [`B256`] or as [`U256`], which is converted to its 32-byte
+/// big-endian representation before hashing. See [`storage_root`].
+///
+/// # Panics
+///
+/// If a slot is given more than once.
+pub fn storage_root_unhashed<S, K>(storage: S) -> B256
+where
+    S: IntoIterator<Item = (K, U256)>,
+    K: Into<B256>,
+{
+    storage_root_unhashed_with_key_hasher::<KeccakKeyHasher, _, _>(storage)
+}
+
+/// Computes the state root from accounts keyed by address, along with their storage slots keyed by
+/// slot, mapping the addresses and slots to paths with the given [`KeyHasher`]. See
+/// [`try_state_root`].
+pub fn try_state_root_with_key_hasher<KH, I, A, S>(accounts: I) -> Result<B256, HashBuilderError>
+where
+    KH: KeyHasher,
+    I: IntoIterator<Item = (Address, A, S)>,
+    A: Into<TrieAccount>,
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    let accounts = accounts
+        .into_iter()
+        .map(|(address, account, storage)| {
+            let storage_root = try_storage_root_with_key_hasher::<KH, _, _>(storage)?;
+            Ok((KH::hash_key(address), TrieAccount { storage_root, ..account.into() }))
+        })
+        .collect::<Result<Vec<_>, HashBuilderError>>()?;
+    try_root_from_paths(accounts, |account, buf| account.encode(buf))
+}
+
+/// Computes the state root from accounts keyed by address, along with their storage slots keyed by
+/// slot.
+///
+/// The addresses and slots are hashed, and the accounts don't need to be sorted. The storage roots
+/// of the accounts are replaced with the roots computed from their storage slots, as in
+/// [`storage_root_unhashed`]. Returns [`HashBuilderError::NonMonotonicKey`] if an account, or a
+/// slot of an account, is given more than once.
+pub fn try_state_root<I, A, S>(accounts: I) -> Result<B256, HashBuilderError>
+where
+    I: IntoIterator<Item = (Address, A, S)>,
+    A: Into<TrieAccount>,
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    try_state_root_with_key_hasher::<KeccakKeyHasher, _, _, _>(accounts)
+}
+
+/// Computes the state root from accounts keyed by address, along with their storage slots keyed by
+/// slot, mapping the addresses and slots to paths with the given [`KeyHasher`]. See
+/// [`try_state_root_with_key_hasher`].
+///
+/// # Panics
+///
+/// If an account, or a slot of an account, is given more than once.
+pub fn state_root_with_key_hasher<KH, I, A, S>(accounts: I) -> B256
+where
+    KH: KeyHasher,
+    I: IntoIterator<Item = (Address, A, S)>,
+    A: Into<TrieAccount>,
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    try_state_root_with_key_hasher::<KH, _, _, _>(accounts)
+        .unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// Computes the state root from accounts keyed by address, along with their storage slots keyed by
+/// slot. See [`try_state_root`].
+///
+/// # Panics
+///
+/// If an account, or a slot of an account, is given more than once.
+pub fn state_root<I, A, S>(accounts: I) -> B256
+where
+    I: IntoIterator<Item = (Address, A, S)>,
+    A: Into<TrieAccount>,
+    S: IntoIterator<Item = (B256, U256)>,
+{
+    state_root_with_key_hasher::<KeccakKeyHasher, _, _, _>(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triehash_trie_root;
+    use alloy_primitives::{keccak256, U256};
+
+    fn expected_root(values: &[Vec<u8>]) -> B256 {
+        triehash_trie_root(
+            values.iter().enumerate().map(|(i, value)| (alloy_rlp::encode(i), value)),
+        )
+    }
+
+    #[test]
+    fn ordered_root() {
+        assert_eq!(ordered_trie_root::<U256>(&[]), EMPTY_ROOT_HASH);
+        for len in [1, 2, 127, 128, 129, 300] {
+            let items = (0..len as u64).map(U256::from).collect::<Vec<_>>();
+            let values = items.iter().map(alloy_rlp::encode).collect::<Vec<_>>();
+            let root = expected_root(&values);
+            assert_eq!(ordered_trie_root(&items), root, "{len}");
+            assert_eq!(ordered_trie_root_encoder(items, |item, buf| item.encode(buf)), root);
+        }
+    }
+
+    #[test]
+    fn storage_root_from_unhashed_slots() {
+        assert_eq!(storage_root_unhashed::<_, U256>([]), EMPTY_ROOT_HASH);
+
+        let slots = (0..50u64)
+            .map(|i| (U256::from(i) << (i * 4), U256::from(i % 5) << (i * 3)))
+            .rev()
+            .collect::<Vec<_>>();
+        let expected = triehash_trie_root(
+            slots
+                .iter()
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(slot, value)| {
+                    let value = value.to_be_bytes_trimmed_vec();
+                    (keccak256(B256::from(*slot)), alloy_rlp::encode(&value[..]))
+                })
+                .collect::<alloc::collections::BTreeMap<_, _>>(),
+        );
+        assert_eq!(storage_root_unhashed(slots.clone()), expected);
+        assert_eq!(
+            storage_root_unhashed(slots.iter().map(|(slot, value)| (B256::from(*slot), *value))),
+            expected
+        );
+    }
+
+    #[test]
+    fn key_hashers() {
+        let entries = (0..100u64)
+            .map(|i| (i.to_be_bytes(), alloy_rlp::encode(U256::from(i))))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            trie_root::<IdentityKeyHasher, _, _, _>(entries.clone()),
+            triehash_trie_root(entries.clone())
+        );
+        assert_eq!(
+            trie_root::<crate::KeccakKeyHasher, _, _, _>(entries.clone()),
+            triehash_trie_root(entries.iter().map(|(key, value)| (keccak256(key), value)))
+        );
+        assert_eq!(trie_root::<IdentityKeyHasher, _, [u8; 0], [u8; 0]>([]), EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn state_root_unhashed() {
+        assert_eq!(state_root::<_, TrieAccount, Vec<_>>([]), EMPTY_ROOT_HASH);
+
+        let accounts = (0..50u8)
+            .map(|i| {
+                let account = TrieAccount { nonce: i as u64, ..Default::default() };
+                let storage = (0..i % 4)
+                    .map(|j| (B256::with_last_byte(j), U256::from(j)))
+                    .collect::<alloc::collections::BTreeMap<_, _>>();
+                (Address::with_last_byte(i), account, storage)
+            })
+            .collect::<Vec<_>>();
+
+        let expected = triehash_trie_root(accounts.iter().map(|(address, account, storage)| {
+            let storage_root = triehash_trie_root(
+                storage
+                    .iter()
+                    .filter(|(_, value)| !value.is_zero())
+                    .map(|(slot, value)| (keccak256(slot), alloy_rlp::encode(value))),
+            );
+            (keccak256(address), alloy_rlp::encode(TrieAccount { storage_root, ..*account }))
+        }));
+        assert_eq!(state_root(accounts), expected);
+    }
+
+    #[test]
+    fn state_root_key_hashers() {
+        let accounts = (0..20u8)
+            .map(|i| {
+                let account = TrieAccount { nonce: i as u64, ..Default::default() };
+                let storage = (1..i % 3 + 1)
+                    .map(|j| (B256::with_last_byte(j), U256::from(j)))
+                    .collect::<Vec<_>>();
+                (Address::with_last_byte(i), account, storage)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            state_root_with_key_hasher::<KeccakKeyHasher, _, _, _>(accounts.clone()),
+            state_root(accounts.clone())
+        );
+
+        let expected = triehash_trie_root(accounts.iter().map(|(address, account, storage)| {
+            let storage_root =
+                storage_root_unhashed_with_key_hasher::<IdentityKeyHasher, _, _>(storage.clone());
+            assert_eq!(
+                storage_root,
+                triehash_trie_root(
+                    storage.iter().map(|(slot, value)| (slot, alloy_rlp::encode(value)))
+                )
+            );
+            (address, alloy_rlp::encode(TrieAccount { storage_root, ..*account }))
+        }));
+        assert_eq!(state_root_with_key_hasher::<IdentityKeyHasher, _, _, _>(accounts), expected);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let slot = (B256::with_last_byte(1), U256::from(1));
+        assert!(matches!(
+            try_storage_root([slot, slot]),
+            Err(HashBuilderError::NonMonotonicKey { .. })
+        ));
+
+        let account = (Address::ZERO, TrieAccount::default(), vec![slot]);
+        assert!(try_state_root([account.clone()]).is_ok());
+        assert!(matches!(
+            try_state_root([account.clone(), account]),
+            Err(HashBuilderError::NonMonotonicKey { .. })
+        ));
+        assert!(matches!(
+            try_state_root([(Address::ZERO, TrieAccount::default(), vec![slot, slot])]),
+            Err(HashBuilderError::NonMonotonicKey { .. })
+        ));
+    }
+
+    #[test]
+    fn root_from_sorted_iter() {
+        let slots = (0..100u64)
+            .map(|i| (keccak256(B256::with_last_byte(i as u8)), U256::from(i + 1)))
+            .collect::<alloc::collections::BTreeMap<_, _>>();
+        assert_eq!(
+            root_from_iter_with(slots.clone(), |value, buf| value.encode(buf)),
+            Ok(storage_root(slots.clone()))
+        );
+        assert_eq!(root_from_iter_with::<_, U256, _>([], |_, _| {}), Ok(EMPTY_ROOT_HASH));
+
+        let unsorted = slots.into_iter().rev().take(2);
+        assert!(matches!(
+            root_from_iter_with(unsorted, |value, buf| value.encode(buf)),
+            Err(HashBuilderError::NonMonotonicKey { .. })
+        ));
+    }
+
+    #[test]
+    fn typed_envelopes() {
+        let items = (0..200u64).map(|i| ((i % 3) as u8, U256::from(i))).collect::<Vec<_>>();
+        let values = items
+            .iter()
+            .map(|(ty, payload)| {
+                let mut value = if *ty == 0 { vec![] } else { vec![*ty] };
+                payload.encode(&mut value);
+                value
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ordered_trie_root_typed(items.clone()), expected_root(&values));
+
+        let legacy = items.iter().map(|(_, payload)| (0, payload)).collect::<Vec<_>>();
+        let payloads = items.iter().map(|(_, payload)| *payload).collect::<Vec<_>>();
+        assert_eq!(ordered_trie_root_typed(legacy), ordered_trie_root(&payloads));
+    }
+}
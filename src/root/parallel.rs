@@ -0,0 +1,140 @@
+//! Parallel computation of storage roots and the state root.
+//!
+//! Storage tries are independent of each other, so their roots are computed concurrently on the
+//! [`rayon`] thread pool, and the state trie is then built from the accounts with their storage
+//! roots in a single [`HashBuilder`] pass. A single trie can be split into the 16 subtries under
+//! the root branch node, which are built concurrently and merged, see [`sorted_leaves_root`].
+
+use super::storage_root;
+use crate::{hash_builder::SubtrieOutput, HashBuilder, Nibbles, TrieAccount};
+use alloc::vec::Vec;
+use alloy_primitives::{B256, U256};
+use rayon::prelude::*;
+
+/// Computes the storage roots of the given storage tries in parallel.
+///
+/// Storage slots are keyed by hashed slot and don't need to be sorted, see [`storage_root`]. The
+/// roots are returned in the order of the input.
+pub fn storage_roots<S>(storages: Vec<(B256, S)>) -> Vec<(B256, B256)>
+where
+    S: IntoIterator<Item = (B256, U256)> + Send,
+{
+    storages
+        .into_par_iter()
+        .map(|(hashed_address, storage)| (hashed_address, storage_root(storage)))
+        .collect()
+}
+
+/// Computes the state root of the given accounts, computing their storage roots in parallel.
+///
+/// Accounts are keyed by hashed address and don't need to be sorted. The storage roots of the
+/// accounts are replaced with the roots computed from their storage slots, as in
+/// [`storage_roots`].
+pub fn state_root<S>(accounts: Vec<(B256, TrieAccount, S)>) -> B256
+where
+    S: IntoIterator<Item = (B256, U256)> + Send,
+{
+    let mut accounts = accounts
+        .into_par_iter()
+        .map(|(hashed_address, account, storage)| {
+            (hashed_address, TrieAccount { storage_root: storage_root(storage), ..account })
+        })
+        .collect::<Vec<_>>();
+    accounts.par_sort_unstable_by_key(|(hashed_address, _)| *hashed_address);
+
+    let mut hash_builder = HashBuilder::default();
+    let mut account_rlp = Vec::new();
+    for (hashed_address, account) in accounts {
+        account_rlp.clear();
+        alloy_rlp::Encodable::encode(&account, &mut account_rlp);
+        hash_builder.add_leaf(Nibbles::unpack(hashed_address), &account_rlp);
+    }
+    hash_builder.root()
+}
+
+/// Computes the root of a trie from its leaves sorted by key, building the subtries under each
+/// nibble in parallel and merging them with [`SubtrieOutput::merge`].
+///
+/// # Panics
+///
+/// If the leaves are not sorted by strictly increasing key.
+pub fn sorted_leaves_root<V>(leaves: &[(Nibbles, V)]) -> B256
+where
+    V: AsRef<[u8]> + Sync,
+{
+    let partitions = leaves.chunk_by(|a, b| a.0.first() == b.0.first()).collect::<Vec<_>>();
+    let subtries = partitions
+        .into_par_iter()
+        .filter_map(|leaves| {
+            let mut hash_builder = HashBuilder::default();
+            for (key, value) in leaves {
+                hash_builder.add_leaf(key.clone(), value.as_ref());
+            }
+            hash_builder.into_subtrie()
+        })
+        .collect::<Vec<_>>();
+    SubtrieOutput::merge(subtries).root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{triehash_trie_root, EMPTY_ROOT_HASH};
+    use alloy_primitives::keccak256;
+
+    fn storage(seed: u64) -> Vec<(B256, U256)> {
+        (0..seed % 10).map(|i| (keccak256([seed as u8, i as u8]), U256::from(i))).collect()
+    }
+
+    fn expected_storage_root(storage: &[(B256, U256)]) -> B256 {
+        triehash_trie_root(
+            storage
+                .iter()
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(slot, value)| (*slot, alloy_rlp::encode(value))),
+        )
+    }
+
+    #[test]
+    fn parallel_storage_roots() {
+        let storages =
+            (0..100u64).map(|i| (keccak256(i.to_be_bytes()), storage(i))).collect::<Vec<_>>();
+        let roots = storage_roots(storages.clone());
+        for ((hashed_address, storage), root) in storages.iter().zip(roots) {
+            assert_eq!(root, (*hashed_address, expected_storage_root(storage)));
+        }
+        assert_eq!(
+            storage_roots(vec![(B256::ZERO, Vec::new())]),
+            vec![(B256::ZERO, EMPTY_ROOT_HASH)]
+        );
+    }
+
+    #[test]
+    fn parallel_state_root() {
+        let accounts = (0..100u64)
+            .map(|i| {
+                let account = TrieAccount { nonce: i, ..Default::default() };
+                (keccak256(i.to_be_bytes()), account, storage(i))
+            })
+            .collect::<Vec<_>>();
+
+        let expected =
+            triehash_trie_root(accounts.iter().map(|(hashed_address, account, storage)| {
+                let account =
+                    TrieAccount { storage_root: expected_storage_root(storage), ..*account };
+                (*hashed_address, alloy_rlp::encode(account))
+            }));
+        assert_eq!(state_root(accounts), expected);
+    }
+
+    #[test]
+    fn parallel_sorted_leaves_root() {
+        let mut leaves = (0..500u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(i)))
+            .collect::<Vec<_>>();
+        leaves.sort_unstable();
+        let expected = triehash_trie_root(leaves.iter().map(|(key, value)| (key.pack(), value)));
+        assert_eq!(sorted_leaves_root(&leaves), expected);
+        assert_eq!(sorted_leaves_root::<Vec<u8>>(&[]), EMPTY_ROOT_HASH);
+    }
+}
@@ -0,0 +1,458 @@
+//! Incremental state root computation over stored trie nodes and hashed state.
+//!
+//! The [`StateRoot`] pipeline walks the stored branch nodes of the account trie with a
+//! [`TrieWalker`], reusing the hashes of the subtries without changes, and reads the accounts of
+//! the changed subtries from the hashed state. The storage root of every account read is
+//! recomputed in the same way by a [`StorageRoot`], and the changes of all branch nodes are
+//! collected in [`TrieUpdates`] to be applied to the stored nodes.
+
+use crate::{
+    cursor::{
+        HashedCursor, HashedCursorFactory, HashedStorageCursor, TrieCursor, TrieCursorFactory,
+    },
+    prefix_set::{PrefixSet, PrefixSetMut, TriePrefixSets},
+    updates::{StorageTrieUpdates, TrieUpdates},
+    walker::TrieWalker,
+    HashBuilder, Nibbles, TrieAccount, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::B256;
+use alloy_rlp::Encodable;
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Error returned by [`StateRoot`] and [`StorageRoot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateRootError<T, H> {
+    /// Reading the stored trie nodes failed.
+    TrieCursor(T),
+    /// Reading the hashed state failed.
+    HashedCursor(H),
+}
+
+impl<T: fmt::Display, H: fmt::Display> fmt::Display for StateRootError<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TrieCursor(err) => write!(f, "failed to read trie nodes: {err}"),
+            Self::HashedCursor(err) => write!(f, "failed to read hashed state: {err}"),
+        }
+    }
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl<T, H> std::error::Error for StateRootError<T, H>
+where
+    T: std::error::Error + 'static,
+    H: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::TrieCursor(err) => Some(err),
+            Self::HashedCursor(err) => Some(err),
+        }
+    }
+}
+
+/// The [`StateRootError`] of the given cursor factories.
+type FactoryError<T, H> =
+    StateRootError<<T as TrieCursorFactory>::Error, <H as HashedCursorFactory>::Error>;
+
+/// The [`StateRootError`] of the given cursors.
+type CursorError<C, H> = StateRootError<<C as TrieCursor>::Error, <H as HashedCursor>::Error>;
+
+/// An element of a trie in key order: either the hash of an unchanged subtrie, or a leaf.
+enum TrieElement<V> {
+    /// An unchanged subtrie, with its path, hash, and whether its root node is stored.
+    Branch(Nibbles, B256, bool),
+    /// A leaf, with its hashed key and value.
+    Leaf(B256, V),
+}
+
+/// Iterates over the elements of a trie, interleaving the unchanged subtries found by a
+/// [`TrieWalker`] with the leaves of the changed subtries read from a [`HashedCursor`].
+struct TrieNodeIter<C, H: HashedCursor> {
+    walker: TrieWalker<C>,
+    hashed_cursor: H,
+    current_hashed_entry: Option<(B256, H::Value)>,
+    current_walker_key_checked: bool,
+}
+
+impl<C: TrieCursor, H: HashedCursor> TrieNodeIter<C, H> {
+    const fn new(walker: TrieWalker<C>, hashed_cursor: H) -> Self {
+        Self {
+            walker,
+            hashed_cursor,
+            current_hashed_entry: None,
+            current_walker_key_checked: false,
+        }
+    }
+
+    fn try_next(&mut self) -> Result<Option<TrieElement<H::Value>>, CursorError<C, H>> {
+        loop {
+            // Emit the subtrie at the walker position once, if it is unchanged.
+            if let Some(key) = self.walker.key() {
+                if !self.current_walker_key_checked {
+                    self.current_walker_key_checked = true;
+                    if self.walker.can_skip_current_node {
+                        let hash = self.walker.hash().expect("skipped node must have a hash");
+                        let in_trie = self.walker.children_are_in_trie();
+                        return Ok(Some(TrieElement::Branch(key.clone(), hash, in_trie)));
+                    }
+                }
+            }
+
+            // Emit the leaves up to the walker position, which is only reached once they are all
+            // consumed.
+            if let Some((hashed_key, value)) = self.current_hashed_entry.take() {
+                if self.walker.key().is_some_and(|key| key < &Nibbles::unpack(hashed_key)) {
+                    self.current_walker_key_checked = false;
+                    continue;
+                }
+                self.current_hashed_entry =
+                    self.hashed_cursor.next().map_err(StateRootError::HashedCursor)?;
+                return Ok(Some(TrieElement::Leaf(hashed_key, value)));
+            }
+
+            let Some(seek_key) = self.walker.next_unprocessed_key() else { return Ok(None) };
+            self.current_hashed_entry =
+                self.hashed_cursor.seek(seek_key).map_err(StateRootError::HashedCursor)?;
+            self.walker.advance().map_err(StateRootError::TrieCursor)?;
+        }
+    }
+
+    fn into_removed_keys(self) -> impl IntoIterator<Item = Nibbles> {
+        self.walker.split().1
+    }
+}
+
+/// Computes the root of the storage trie of an account incrementally, from its stored branch
+/// nodes and its hashed storage slots.
+#[derive(Debug)]
+pub struct StorageRoot<T, H> {
+    trie_cursor_factory: T,
+    hashed_cursor_factory: H,
+    hashed_address: B256,
+    prefix_set: PrefixSet,
+}
+
+impl<T, H> StorageRoot<T, H> {
+    /// Creates a new storage root computation for the account with the given hashed address,
+    /// without changed slots.
+    pub fn new(trie_cursor_factory: T, hashed_cursor_factory: H, hashed_address: B256) -> Self {
+        Self {
+            trie_cursor_factory,
+            hashed_cursor_factory,
+            hashed_address,
+            prefix_set: PrefixSet::default(),
+        }
+    }
+
+    /// Sets the hashed slots that changed since the stored branch nodes were computed.
+    pub fn with_prefix_set(mut self, prefix_set: PrefixSet) -> Self {
+        self.prefix_set = prefix_set;
+        self
+    }
+}
+
+impl<T: TrieCursorFactory, H: HashedCursorFactory> StorageRoot<T, H> {
+    /// Computes the storage root.
+    pub fn root(self) -> Result<B256, FactoryError<T, H>> {
+        self.calculate(false).map(|(root, _)| root)
+    }
+
+    /// Computes the storage root, along with the changes of the stored branch nodes.
+    pub fn root_with_updates(self) -> Result<(B256, StorageTrieUpdates), FactoryError<T, H>> {
+        self.calculate(true)
+    }
+
+    fn calculate(
+        self,
+        retain_updates: bool,
+    ) -> Result<(B256, StorageTrieUpdates), FactoryError<T, H>> {
+        let mut hashed_cursor = self
+            .hashed_cursor_factory
+            .hashed_storage_cursor(self.hashed_address)
+            .map_err(StateRootError::HashedCursor)?;
+        if hashed_cursor.is_storage_empty().map_err(StateRootError::HashedCursor)? {
+            return Ok((EMPTY_ROOT_HASH, StorageTrieUpdates::deleted()));
+        }
+
+        let trie_cursor = self
+            .trie_cursor_factory
+            .storage_trie_cursor(self.hashed_address)
+            .map_err(StateRootError::TrieCursor)?;
+        let walker = TrieWalker::new(trie_cursor, self.prefix_set)
+            .map_err(StateRootError::TrieCursor)?
+            .with_deletions_retained(retain_updates);
+        let mut iter = TrieNodeIter::new(walker, hashed_cursor);
+
+        let mut hash_builder = HashBuilder::default().with_updates(retain_updates);
+        let mut value_buffer = Vec::new();
+        while let Some(element) = iter.try_next()? {
+            match element {
+                TrieElement::Branch(key, hash, in_trie) => {
+                    hash_builder.add_branch(key, hash, in_trie);
+                }
+                TrieElement::Leaf(hashed_slot, value) => {
+                    value_buffer.clear();
+                    value.encode(&mut value_buffer);
+                    hash_builder.add_leaf(Nibbles::unpack(hashed_slot), &value_buffer);
+                }
+            }
+        }
+
+        let root = hash_builder.root();
+        let updates = StorageTrieUpdates::from_hash_builder(hash_builder, iter.into_removed_keys());
+        Ok((root, updates))
+    }
+}
+
+/// Computes the state root incrementally, from the stored branch nodes of the account trie and
+/// the storage tries, and the hashed state.
+///
+/// Only the subtries containing the changed keys given by the [`TriePrefixSets`] are re-hashed.
+/// The account prefix set must include the accounts whose storage changed, as the storage root
+/// is part of the account leaf. Without prefix sets, the stored nodes are trusted entirely, so
+/// with no stored nodes the root is computed from scratch.
+///
+/// ```
+/// use alloy_primitives::{map::HashMap, B256};
+/// use alloy_trie::{
+///     cursor::{InMemoryHashedCursorFactory, InMemoryTrieCursorFactory},
+///     root::StateRoot,
+///     TrieAccount, EMPTY_ROOT_HASH,
+/// };
+/// use std::collections::BTreeMap;
+///
+/// let accounts = BTreeMap::from([(B256::with_last_byte(1), TrieAccount::default())]);
+/// let (storages, account_nodes, storage_nodes) =
+///     (HashMap::default(), BTreeMap::new(), HashMap::default());
+/// let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+/// let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+///
+/// let (root, updates) = StateRoot::new(trie, hashed).root_with_updates().unwrap();
+/// assert_ne!(root, EMPTY_ROOT_HASH);
+/// assert!(updates.account_nodes.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct StateRoot<T, H> {
+    trie_cursor_factory: T,
+    hashed_cursor_factory: H,
+    prefix_sets: TriePrefixSets,
+}
+
+impl<T, H> StateRoot<T, H> {
+    /// Creates a new state root computation without changed keys.
+    pub fn new(trie_cursor_factory: T, hashed_cursor_factory: H) -> Self {
+        Self { trie_cursor_factory, hashed_cursor_factory, prefix_sets: TriePrefixSets::default() }
+    }
+
+    /// Sets the keys that changed since the stored branch nodes were computed.
+    pub fn with_prefix_sets(mut self, prefix_sets: TriePrefixSets) -> Self {
+        self.prefix_sets = prefix_sets;
+        self
+    }
+}
+
+impl<T, H> StateRoot<T, H>
+where
+    T: TrieCursorFactory + Clone,
+    H: HashedCursorFactory + Clone,
+{
+    /// Computes the state root.
+    pub fn root(self) -> Result<B256, FactoryError<T, H>> {
+        self.calculate(false).map(|(root, _)| root)
+    }
+
+    /// Computes the state root, along with the changes of the stored branch nodes of the account
+    /// trie and of the storage tries.
+    pub fn root_with_updates(self) -> Result<(B256, TrieUpdates), FactoryError<T, H>> {
+        self.calculate(true)
+    }
+
+    fn calculate(self, retain_updates: bool) -> Result<(B256, TrieUpdates), FactoryError<T, H>> {
+        let TriePrefixSets { account_prefix_set, mut storage_prefix_sets, destroyed_accounts } =
+            self.prefix_sets;
+        let mut trie_updates = TrieUpdates::default();
+
+        let trie_cursor =
+            self.trie_cursor_factory.account_trie_cursor().map_err(StateRootError::TrieCursor)?;
+        let hashed_cursor = self
+            .hashed_cursor_factory
+            .hashed_account_cursor()
+            .map_err(StateRootError::HashedCursor)?;
+        let walker = TrieWalker::new(trie_cursor, account_prefix_set)
+            .map_err(StateRootError::TrieCursor)?
+            .with_deletions_retained(retain_updates);
+        let mut iter = TrieNodeIter::new(walker, hashed_cursor);
+
+        let mut hash_builder = HashBuilder::default().with_updates(retain_updates);
+        let mut value_buffer = Vec::new();
+        while let Some(element) = iter.try_next()? {
+            match element {
+                TrieElement::Branch(key, hash, in_trie) => {
+                    hash_builder.add_branch(key, hash, in_trie);
+                }
+                TrieElement::Leaf(hashed_address, account) => {
+                    // The stored nodes of a wiped storage trie are all invalid.
+                    let prefix_set = if destroyed_accounts.contains(&hashed_address) {
+                        if retain_updates {
+                            trie_updates.insert_storage_updates(
+                                hashed_address,
+                                StorageTrieUpdates::deleted(),
+                            );
+                        }
+                        PrefixSetMut::all().freeze()
+                    } else {
+                        storage_prefix_sets.remove(&hashed_address).unwrap_or_default()
+                    };
+                    let (storage_root, storage_updates) = StorageRoot::new(
+                        self.trie_cursor_factory.clone(),
+                        self.hashed_cursor_factory.clone(),
+                        hashed_address,
+                    )
+                    .with_prefix_set(prefix_set)
+                    .calculate(retain_updates)?;
+                    if retain_updates {
+                        trie_updates.insert_storage_updates(hashed_address, storage_updates);
+                    }
+
+                    value_buffer.clear();
+                    TrieAccount { storage_root, ..account }.encode(&mut value_buffer);
+                    hash_builder.add_leaf(Nibbles::unpack(hashed_address), &value_buffer);
+                }
+            }
+        }
+
+        let root = hash_builder.root();
+        if retain_updates {
+            // The storage tries of the destroyed accounts that were not recreated are removed.
+            for hashed_address in destroyed_accounts {
+                trie_updates
+                    .storage_tries
+                    .entry(hashed_address)
+                    .or_insert_with(StorageTrieUpdates::deleted);
+            }
+            trie_updates.finalize(hash_builder, iter.into_removed_keys());
+        }
+        Ok((root, trie_updates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursor::{InMemoryHashedCursorFactory, InMemoryTrieCursorFactory},
+        BranchNodeCompact, HashMap,
+    };
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{keccak256, U256};
+
+    type Nodes = BTreeMap<Nibbles, BranchNodeCompact>;
+
+    fn apply(
+        account_nodes: &mut Nodes,
+        storage_nodes: &mut HashMap<B256, Nodes>,
+        updates: TrieUpdates,
+    ) {
+        updates.removed_nodes.iter().for_each(|path| _ = account_nodes.remove(path));
+        account_nodes.extend(updates.account_nodes);
+        for (hashed_address, storage_updates) in updates.storage_tries {
+            let nodes = storage_nodes.entry(hashed_address).or_default();
+            if storage_updates.is_deleted {
+                nodes.clear();
+            }
+            storage_updates.removed_nodes.iter().for_each(|path| _ = nodes.remove(path));
+            nodes.extend(storage_updates.storage_nodes);
+        }
+    }
+
+    fn full_root(
+        accounts: &BTreeMap<B256, TrieAccount>,
+        storages: &HashMap<B256, BTreeMap<B256, U256>>,
+    ) -> B256 {
+        let mut hash_builder = HashBuilder::default();
+        for (hashed_address, account) in accounts {
+            let storage = storages.get(hashed_address).cloned().unwrap_or_default();
+            let storage_root = crate::root::storage_root(storage);
+            let account = TrieAccount { storage_root, ..*account };
+            hash_builder.add_leaf(Nibbles::unpack(hashed_address), &alloy_rlp::encode(account));
+        }
+        hash_builder.root()
+    }
+
+    #[test]
+    fn incremental_state_root() {
+        let mut accounts = (0..200u64)
+            .map(|i| (keccak256(i.to_be_bytes()), TrieAccount { nonce: i, ..Default::default() }))
+            .collect::<BTreeMap<_, _>>();
+        let mut storages = accounts
+            .keys()
+            .take(20)
+            .enumerate()
+            .map(|(i, hashed_address)| {
+                let storage = (0..i as u64 * 5)
+                    .map(|j| (keccak256(j.to_be_bytes()), U256::from(j + 1)))
+                    .collect::<BTreeMap<_, _>>();
+                (*hashed_address, storage)
+            })
+            .collect::<HashMap<_, _>>();
+        let mut account_nodes = Nodes::new();
+        let mut storage_nodes = HashMap::default();
+
+        // Without stored nodes, the root is computed from scratch.
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+        let (root, updates) = StateRoot::new(trie, hashed).root_with_updates().unwrap();
+        assert_eq!(root, full_root(&accounts, &storages));
+        assert!(!updates.account_nodes.is_empty());
+        apply(&mut account_nodes, &mut storage_nodes, updates);
+
+        // With stored nodes and no changes, the stored root is reused.
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+        assert_eq!(StateRoot::new(trie, hashed).root(), Ok(root));
+
+        // Change an account, a storage slot, remove an account, and destroy a storage.
+        let mut prefix_sets = TriePrefixSets::default();
+        let mut account_prefix_set = PrefixSetMut::default();
+        let keys = accounts.keys().copied().collect::<Vec<_>>();
+
+        accounts.get_mut(&keys[150]).unwrap().balance = U256::from(7);
+        account_prefix_set.insert(Nibbles::unpack(keys[150]));
+
+        accounts.remove(&keys[100]);
+        account_prefix_set.insert(Nibbles::unpack(keys[100]));
+
+        let slot = keccak256(3u64.to_be_bytes());
+        storages.get_mut(&keys[10]).unwrap().insert(slot, U256::from(42));
+        account_prefix_set.insert(Nibbles::unpack(keys[10]));
+        let mut storage_prefix_set = PrefixSetMut::default();
+        storage_prefix_set.insert(Nibbles::unpack(slot));
+        prefix_sets.storage_prefix_sets.insert(keys[10], storage_prefix_set.freeze());
+
+        storages.remove(&keys[15]);
+        account_prefix_set.insert(Nibbles::unpack(keys[15]));
+        prefix_sets.destroyed_accounts.insert(keys[15]);
+
+        prefix_sets.account_prefix_set = account_prefix_set.freeze();
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+        let (root, updates) =
+            StateRoot::new(trie, hashed).with_prefix_sets(prefix_sets).root_with_updates().unwrap();
+        assert_eq!(root, full_root(&accounts, &storages));
+        assert!(updates.storage_tries[&keys[15]].is_deleted);
+        apply(&mut account_nodes, &mut storage_nodes, updates);
+
+        // The updated nodes are consistent with the new state.
+        let trie = InMemoryTrieCursorFactory::new(&account_nodes, &storage_nodes);
+        let hashed = InMemoryHashedCursorFactory::new(&accounts, &storages);
+        assert_eq!(StateRoot::new(trie, hashed).root(), Ok(root));
+        let storage_root = StorageRoot::new(trie, hashed, keys[10]).root().unwrap();
+        assert_eq!(storage_root, crate::root::storage_root(storages[&keys[10]].clone()));
+    }
+}
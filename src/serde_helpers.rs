@@ -0,0 +1,173 @@
+//! Serde helpers for maps with keys that can't be serialized as strings, such as [`Nibbles`], and
+//! for byte buffers.
+//!
+//! The maps are serialized as sequences of key-value pairs, sorted by key so that the output is
+//! deterministic.
+//!
+//! None of the trie types rely on self-describing formats, so besides JSON they can be serialized
+//! with compact binary formats such as bincode or CBOR, e.g. to persist a
+//! [`HashBuilderCheckpoint`] or to send [`ProofNodes`] to another process. Like the
+//! `alloy-primitives` types, bytes are hex-encoded only in human-readable formats.
+//!
+//! [`Nibbles`]: crate::Nibbles
+//! [`HashBuilderCheckpoint`]: crate::hash_builder::HashBuilderCheckpoint
+//! [`ProofNodes`]: crate::proof::ProofNodes
+
+use crate::HashMap;
+use core::hash::Hash;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Serializes bytes as a hex string in human-readable formats, and as raw bytes otherwise.
+pub(crate) mod bytes {
+    use super::*;
+    use alloy_primitives::{hex, Bytes};
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            hex::serialize(bytes, serializer)
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            hex::deserialize(deserializer)
+        } else {
+            Bytes::deserialize(deserializer).map(Into::into)
+        }
+    }
+}
+
+/// Serializes a map as a sorted sequence of key-value pairs.
+pub(crate) mod map_as_seq {
+    use super::*;
+
+    pub(crate) fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Ord,
+        V: Serialize,
+        S: Serializer,
+    {
+        let mut entries = map.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        serializer.collect_seq(entries)
+    }
+
+    pub(crate) fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(HashMap::from_iter)
+    }
+}
+
+/// Serializes an optional map as an optional sorted sequence of key-value pairs.
+pub(crate) mod option_map_as_seq {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Ser<'a, K: Serialize + Ord, V: Serialize>(
+        #[serde(with = "map_as_seq")] &'a HashMap<K, V>,
+    );
+
+    #[derive(Deserialize)]
+    #[serde(bound = "K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>")]
+    struct De<K, V>(#[serde(with = "map_as_seq")] HashMap<K, V>);
+
+    pub(crate) fn serialize<K, V, S>(
+        map: &Option<HashMap<K, V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Ord,
+        V: Serialize,
+        S: Serializer,
+    {
+        map.as_ref().map(Ser).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, K, V, D>(
+        deserializer: D,
+    ) -> Result<Option<HashMap<K, V>>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Option::<De<K, V>>::deserialize(deserializer).map(|map| map.map(|De(map)| map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hash_builder::HashBuilderCheckpoint, proof::ProofRetainer, BranchNodeCompact, HashBuilder,
+        Nibbles, TrieMask,
+    };
+    use alloc::{vec, vec::Vec};
+    use alloy_primitives::{keccak256, B256};
+    use core::fmt::Debug;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    fn to_cbor<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(value, &mut buf).unwrap();
+        buf
+    }
+
+    fn roundtrip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+        assert_eq!(&ciborium::from_reader::<T, _>(to_cbor(value).as_slice()).unwrap(), value);
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn binary_formats() {
+        roundtrip(&Nibbles::from_nibbles([0x1, 0x2, 0xf]));
+        roundtrip(&TrieMask::new(0b1010));
+        roundtrip(&BranchNodeCompact::new(
+            0b1011,
+            0b0001,
+            0b1010,
+            vec![B256::repeat_byte(1), B256::repeat_byte(2)],
+            Some(B256::repeat_byte(3)),
+        ));
+
+        let mut leaves = (0..64u64)
+            .map(|i| (Nibbles::unpack(keccak256(i.to_be_bytes())), [i as u8; 40]))
+            .collect::<Vec<_>>();
+        leaves.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let retainer = ProofRetainer::new(vec![leaves[7].0.clone()]);
+        let mut hb = HashBuilder::default().with_updates(true).with_proof_retainer(retainer);
+        for (key, value) in &leaves[..32] {
+            hb.add_leaf(key.clone(), value);
+        }
+
+        let checkpoint = hb.checkpoint();
+        roundtrip(&checkpoint);
+        // Bytes are not hex-encoded in binary formats.
+        let cbor = to_cbor(&checkpoint);
+        assert!(cbor.len() < serde_json::to_vec(&checkpoint).unwrap().len());
+
+        let checkpoint = ciborium::from_reader::<HashBuilderCheckpoint, _>(cbor.as_slice());
+        let mut restored: HashBuilder = HashBuilder::restore(checkpoint.unwrap());
+        for (key, value) in &leaves[32..] {
+            hb.add_leaf(key.clone(), value);
+            restored.add_leaf(key.clone(), value);
+        }
+        assert_eq!(restored.root(), hb.root());
+
+        let proof_nodes = hb.take_proof_nodes();
+        assert_eq!(restored.take_proof_nodes(), proof_nodes);
+        assert!(!proof_nodes.is_empty());
+        roundtrip(&proof_nodes);
+    }
+}
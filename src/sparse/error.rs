@@ -0,0 +1,64 @@
+use alloy_primitives::B256;
+use core::fmt;
+use nybbles::Nibbles;
+
+/// Error during sparse trie operations.
+#[derive(PartialEq, Eq, Debug)]
+pub enum SparseTrieError {
+    /// Encountered a blinded node that needs to be revealed to complete the operation.
+    BlindedNode {
+        /// Path of the blinded node.
+        path: Nibbles,
+        /// Hash of the blinded node.
+        hash: B256,
+    },
+    /// The hash of the revealed node does not match the hash of the blinded node at its path.
+    NodeHashMismatch {
+        /// Path of the revealed node.
+        path: Nibbles,
+        /// Hash of the blinded node.
+        expected: B256,
+    },
+    /// Attempted to reveal a node that is not reachable from the revealed part of the trie.
+    UnreachableNode {
+        /// Path of the revealed node.
+        path: Nibbles,
+    },
+    /// Error during RLP decoding of trie node.
+    Rlp(alloy_rlp::Error),
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for SparseTrieError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Rlp(error) => std::error::Error::source(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SparseTrieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlindedNode { path, hash } => {
+                write!(f, "blinded node at path {path:?} with hash {hash}")
+            }
+            Self::NodeHashMismatch { path, expected } => {
+                write!(f, "node hash mismatch at path {path:?}. expected: {expected}")
+            }
+            Self::UnreachableNode { path } => {
+                write!(f, "node at path {path:?} is not reachable from the revealed trie")
+            }
+            Self::Rlp(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl From<alloy_rlp::Error> for SparseTrieError {
+    fn from(source: alloy_rlp::Error) -> Self {
+        Self::Rlp(source)
+    }
+}
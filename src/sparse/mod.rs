@@ -0,0 +1,17 @@
+//! Sparse trie implementation.
+//!
+//! A sparse trie is an in-memory partial trie, where only the parts of the trie required for
+//! reading and updating the targeted leaves are revealed from proof nodes, while the rest of the
+//! trie is represented by the hashes of its nodes.
+
+mod node;
+pub use node::SparseNode;
+
+mod error;
+pub use error::SparseTrieError;
+
+mod trie;
+pub use trie::SparseTrie;
+
+mod provider;
+pub use provider::BlindedProvider;
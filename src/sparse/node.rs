@@ -0,0 +1,87 @@
+use crate::{nodes::RlpNode, Nibbles, TrieMask};
+use alloy_primitives::B256;
+
+/// A node of the [`SparseTrie`](super::SparseTrie).
+///
+/// Revealed nodes cache their [`RlpNode`], which is cleared whenever the node or any node below it
+/// is modified.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SparseNode {
+    /// Empty trie node. Only possible at the root of the trie.
+    Empty,
+    /// Blinded node, known only by its hash.
+    Hash(B256),
+    /// Leaf node. The value is stored by the trie under the full path of the leaf.
+    Leaf {
+        /// The remainder of the key.
+        key: Nibbles,
+        /// Cached RLP pointer to the node.
+        rlp_node: Option<RlpNode>,
+    },
+    /// Extension node.
+    Extension {
+        /// The key shared by all the nodes below.
+        key: Nibbles,
+        /// Cached RLP pointer to the node.
+        rlp_node: Option<RlpNode>,
+    },
+    /// Branch node.
+    Branch {
+        /// The bitmask indicating the presence of children at the respective nibble positions.
+        state_mask: TrieMask,
+        /// Cached RLP pointer to the node.
+        rlp_node: Option<RlpNode>,
+    },
+}
+
+impl SparseNode {
+    /// Creates a new leaf node with the given key.
+    pub const fn new_leaf(key: Nibbles) -> Self {
+        Self::Leaf { key, rlp_node: None }
+    }
+
+    /// Creates a new extension node with the given key.
+    pub const fn new_extension(key: Nibbles) -> Self {
+        Self::Extension { key, rlp_node: None }
+    }
+
+    /// Creates a new branch node with the given state mask.
+    pub const fn new_branch(state_mask: TrieMask) -> Self {
+        Self::Branch { state_mask, rlp_node: None }
+    }
+
+    /// Returns `true` if the node is blinded.
+    pub const fn is_hash(&self) -> bool {
+        matches!(self, Self::Hash(_))
+    }
+
+    /// Returns the cached RLP pointer to the node, if any.
+    pub const fn rlp_node(&self) -> Option<&RlpNode> {
+        match self {
+            Self::Leaf { rlp_node, .. }
+            | Self::Extension { rlp_node, .. }
+            | Self::Branch { rlp_node, .. } => rlp_node.as_ref(),
+            Self::Empty | Self::Hash(_) => None,
+        }
+    }
+
+    /// Sets the cached RLP pointer to the node. Has no effect on empty and blinded nodes.
+    pub fn set_rlp_node(&mut self, node: RlpNode) {
+        match self {
+            Self::Leaf { rlp_node, .. }
+            | Self::Extension { rlp_node, .. }
+            | Self::Branch { rlp_node, .. } => *rlp_node = Some(node),
+            Self::Empty | Self::Hash(_) => {}
+        }
+    }
+
+    /// Clears the cached RLP pointer to the node.
+    pub fn invalidate(&mut self) {
+        match self {
+            Self::Leaf { rlp_node, .. }
+            | Self::Extension { rlp_node, .. }
+            | Self::Branch { rlp_node, .. } => *rlp_node = None,
+            Self::Empty | Self::Hash(_) => {}
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use super::{SparseTrie, SparseTrieError};
+use crate::{nodes::TrieNode, proof::ProofNodes, Nibbles};
+use alloy_primitives::{Bytes, B256};
+use alloy_rlp::Decodable;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Provides the blinded nodes of a [`SparseTrie`] on demand, e.g. from a database or the
+/// network, so that updates don't fail on nodes that weren't revealed beforehand.
+///
+/// Closures taking the path and the hash of the blinded node implement this trait.
+pub trait BlindedProvider {
+    /// Returns the RLP encoding of the blinded node with the given hash at the given path, or
+    /// [`None`] if it's not available.
+    fn blinded_node(&self, path: &Nibbles, hash: B256) -> Option<Bytes>;
+}
+
+impl<F: Fn(&Nibbles, B256) -> Option<Bytes>> BlindedProvider for F {
+    fn blinded_node(&self, path: &Nibbles, hash: B256) -> Option<Bytes> {
+        self(path, hash)
+    }
+}
+
+impl BlindedProvider for ProofNodes {
+    fn blinded_node(&self, path: &Nibbles, _hash: B256) -> Option<Bytes> {
+        self.get(path).cloned()
+    }
+}
+
+impl SparseTrie {
+    /// Inserts or updates the leaf at the given key, revealing the blinded nodes on the way with
+    /// the given provider. See [`SparseTrie::update_leaf`].
+    ///
+    /// Returns an error if the provider doesn't have a blinded node that must be revealed.
+    pub fn update_leaf_with_provider<P: BlindedProvider + ?Sized>(
+        &mut self,
+        key: Nibbles,
+        value: Vec<u8>,
+        provider: &P,
+    ) -> Result<(), SparseTrieError> {
+        loop {
+            match self.update_leaf(key.clone(), value.clone()) {
+                Err(error) => self.reveal_blinded(error, provider)?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Removes the leaf at the given key, revealing the blinded nodes on the way and the blinded
+    /// sibling that the branch node above the leaf collapses into with the given provider. See
+    /// [`SparseTrie::remove_leaf`].
+    ///
+    /// Returns an error if the provider doesn't have a blinded node that must be revealed.
+    pub fn remove_leaf_with_provider<P: BlindedProvider + ?Sized>(
+        &mut self,
+        key: &Nibbles,
+        provider: &P,
+    ) -> Result<Option<Vec<u8>>, SparseTrieError> {
+        loop {
+            match self.remove_leaf(key) {
+                Err(error) => self.reveal_blinded(error, provider)?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Reveals the blinded node of a [`SparseTrieError::BlindedNode`] error with the provider.
+    /// Other errors, and blinded nodes that the provider doesn't have, are returned as is.
+    fn reveal_blinded<P: BlindedProvider + ?Sized>(
+        &mut self,
+        error: SparseTrieError,
+        provider: &P,
+    ) -> Result<(), SparseTrieError> {
+        let SparseTrieError::BlindedNode { path, hash } = error else { return Err(error) };
+        let Some(node) = provider.blinded_node(&path, hash) else {
+            return Err(SparseTrieError::BlindedNode { path, hash });
+        };
+        let node = TrieNode::decode(&mut &node[..])?;
+        self.reveal_node(path, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder, EMPTY_ROOT_HASH};
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{keccak256, U256};
+    use core::cell::Cell;
+
+    #[test]
+    fn reveals_on_demand() {
+        let mut leaves = (0..128u64)
+            .map(|i| {
+                (Nibbles::unpack(keccak256(i.to_be_bytes())), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let root_with_nodes = |leaves: &BTreeMap<Nibbles, Vec<u8>>| {
+            let retainer = ProofRetainer::from_iter(leaves.keys().cloned());
+            let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+            for (key, value) in leaves {
+                hb.add_leaf(key.clone(), value);
+            }
+            (hb.root(), hb.take_proof_nodes())
+        };
+        let (root, nodes) = root_with_nodes(&leaves);
+
+        let fetched = Cell::new(0);
+        let provider = |path: &Nibbles, hash: B256| {
+            fetched.set(fetched.get() + 1);
+            let node = nodes.get(path).cloned();
+            assert_eq!(node.as_deref().map(keccak256), Some(hash));
+            node
+        };
+
+        let mut trie = SparseTrie::blind(root);
+        let keys = leaves.keys().cloned().collect::<Vec<_>>();
+        let value = alloy_rlp::encode(U256::MAX);
+        trie.update_leaf_with_provider(keys[5].clone(), value.clone(), &provider).unwrap();
+        leaves.insert(keys[5].clone(), value);
+        assert!(fetched.get() > 1);
+
+        for key in keys.iter().step_by(2) {
+            assert_eq!(trie.remove_leaf_with_provider(key, &provider).unwrap(), leaves.remove(key));
+        }
+        assert_eq!(trie.root(), root_with_nodes(&leaves).0);
+
+        // Nodes that are already revealed are not fetched again.
+        let fetched_before = fetched.get();
+        trie.update_leaf_with_provider(keys[5].clone(), vec![1], &provider).unwrap();
+        assert_eq!(fetched.get(), fetched_before);
+    }
+
+    #[test]
+    fn missing_node() {
+        let (key, value) = (Nibbles::unpack(B256::ZERO), B256::repeat_byte(1).to_vec());
+        let root = B256::repeat_byte(2);
+        let mut trie = SparseTrie::blind(root);
+        assert_eq!(
+            trie.update_leaf_with_provider(key.clone(), value, &ProofNodes::default()),
+            Err(SparseTrieError::BlindedNode { path: Nibbles::default(), hash: root })
+        );
+
+        let mut trie = SparseTrie::default();
+        assert_eq!(trie.remove_leaf_with_provider(&key, &|_: &Nibbles, _| None), Ok(None));
+        assert_eq!(trie.root(), EMPTY_ROOT_HASH);
+    }
+}
@@ -0,0 +1,832 @@
+use super::{SparseNode, SparseTrieError};
+use crate::{
+    nodes::{BranchNodeRef, ExtensionNodeRef, LeafNodeRef, RlpNode, TrieNode, CHILD_INDEX_RANGE},
+    proof::ProofNodes,
+    HashMap, KeccakHasher, Nibbles, TrieHasher, TrieMask, EMPTY_ROOT_HASH,
+};
+use alloc::vec::Vec;
+use alloy_primitives::{Bytes, B256};
+use alloy_rlp::{Decodable, Encodable, EMPTY_STRING_CODE};
+use tracing::trace;
+
+/// An in-memory partial Merkle Patricia Trie.
+///
+/// The trie starts out either empty or blinded, i.e. known only by its root hash. Nodes are
+/// revealed from proofs with [`SparseTrie::reveal_node`] or [`SparseTrie::reveal_proof_nodes`],
+/// after which the revealed leaves can be inserted, updated or removed, and the new root can be
+/// computed locally.
+///
+/// Nodes are stored by their path, and leaf values are stored by the full key of the leaf. All
+/// keys are expected to be of the same length, as values in branch nodes are not supported.
+#[derive(Clone, Debug)]
+pub struct SparseTrie {
+    /// The nodes of the trie keyed by their path.
+    nodes: HashMap<Nibbles, SparseNode>,
+    /// The values of the revealed leaves keyed by their full path.
+    values: HashMap<Nibbles, Vec<u8>>,
+    /// Reusable buffer for RLP encoding of nodes.
+    rlp_buf: Vec<u8>,
+}
+
+impl Default for SparseTrie {
+    fn default() -> Self {
+        Self {
+            nodes: HashMap::from_iter([(Nibbles::default(), SparseNode::Empty)]),
+            values: HashMap::default(),
+            rlp_buf: Vec::new(),
+        }
+    }
+}
+
+impl SparseTrie {
+    /// Creates a new blinded trie with the given root hash.
+    pub fn blind(root: B256) -> Self {
+        let mut trie = Self::default();
+        if root != EMPTY_ROOT_HASH {
+            trie.nodes.insert(Nibbles::default(), SparseNode::Hash(root));
+        }
+        trie
+    }
+
+    /// Returns `true` if the root node of the trie is not revealed.
+    pub fn is_blind(&self) -> bool {
+        self.nodes.get(&Nibbles::default()).is_some_and(SparseNode::is_hash)
+    }
+
+    /// Returns the node at the given path.
+    pub fn node(&self, path: &Nibbles) -> Option<&SparseNode> {
+        self.nodes.get(path)
+    }
+
+    /// Returns the nodes of the trie keyed by their path.
+    pub const fn nodes(&self) -> &HashMap<Nibbles, SparseNode> {
+        &self.nodes
+    }
+
+    /// Returns the value of the revealed leaf at the given key.
+    pub fn get_leaf_value(&self, key: &Nibbles) -> Option<&[u8]> {
+        self.values.get(key).map(Vec::as_slice)
+    }
+
+    /// Returns the values of the revealed leaves keyed by their full path.
+    pub const fn values(&self) -> &HashMap<Nibbles, Vec<u8>> {
+        &self.values
+    }
+
+    /// Reveals the proof nodes in the trie.
+    ///
+    /// Nodes are revealed in order of their paths, so that every node is revealed after its
+    /// parent. Nodes that were already revealed are skipped.
+    pub fn reveal_proof_nodes(&mut self, proof_nodes: &ProofNodes) -> Result<(), SparseTrieError> {
+        for (path, node) in proof_nodes.nodes_sorted() {
+            let node = TrieNode::decode(&mut &node[..])?;
+            self.reveal_node(path, node)?;
+        }
+        Ok(())
+    }
+
+    /// Reveals the node at the given path.
+    ///
+    /// The node must replace a blinded node with the same hash. Revealing a node that was already
+    /// revealed is a no-op.
+    pub fn reveal_node(&mut self, path: Nibbles, node: TrieNode) -> Result<(), SparseTrieError> {
+        let hash = match self.nodes.get(&path) {
+            Some(SparseNode::Hash(hash)) => *hash,
+            Some(_) => return Ok(()),
+            None => return Err(SparseTrieError::UnreachableNode { path }),
+        };
+
+        self.rlp_buf.clear();
+        node.encode(&mut self.rlp_buf);
+        if KeccakHasher::hash(&self.rlp_buf) != hash {
+            return Err(SparseTrieError::NodeHashMismatch { path, expected: hash });
+        }
+        let rlp_node = RlpNode::from_rlp(&self.rlp_buf);
+
+        trace!(target: "trie::sparse", ?path, ?node, "revealing node");
+        self.insert_revealed(path.clone(), node)?;
+        if let Some(node) = self.nodes.get_mut(&path) {
+            node.set_rlp_node(rlp_node);
+        }
+        Ok(())
+    }
+
+    /// Inserts the revealed node and its in-place encoded descendants at the given path.
+    fn insert_revealed(&mut self, path: Nibbles, node: TrieNode) -> Result<(), SparseTrieError> {
+        match node {
+            TrieNode::EmptyRoot => {
+                self.nodes.insert(path, SparseNode::Empty);
+            }
+            TrieNode::Leaf(leaf) => {
+                self.values.insert(path.join(&leaf.key), leaf.value);
+                self.nodes.insert(path, SparseNode::new_leaf(leaf.key));
+            }
+            TrieNode::Extension(extension) => {
+                self.reveal_child(path.join(&extension.key), &extension.child)?;
+                self.nodes.insert(path, SparseNode::new_extension(extension.key));
+            }
+            TrieNode::Branch(branch) => {
+                for (nibble, child) in branch.as_ref().children() {
+                    if let Some(child) = child {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble);
+                        self.reveal_child(child_path, child)?;
+                    }
+                }
+                self.nodes.insert(path, SparseNode::new_branch(branch.state_mask));
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts the child pointed to by the given RLP node. Hashed children are inserted as
+    /// blinded nodes, while in-place encoded children are revealed.
+    fn reveal_child(&mut self, path: Nibbles, child: &RlpNode) -> Result<(), SparseTrieError> {
+        if let Some(hash) = child.as_hash() {
+            self.nodes.entry(path).or_insert(SparseNode::Hash(hash));
+            Ok(())
+        } else {
+            let node = TrieNode::decode(&mut &child[..])?;
+            self.insert_revealed(path.clone(), node)?;
+            if let Some(node) = self.nodes.get_mut(&path) {
+                node.set_rlp_node(child.clone());
+            }
+            Ok(())
+        }
+    }
+
+    /// Inserts or updates the leaf at the given key.
+    ///
+    /// Returns an error if a blinded node is encountered on the path to the leaf.
+    pub fn update_leaf(&mut self, key: Nibbles, value: Vec<u8>) -> Result<(), SparseTrieError> {
+        let mut path = Nibbles::default();
+        loop {
+            let node = self.nodes.get_mut(&path).expect("node on the path must exist");
+            node.invalidate();
+            match node {
+                SparseNode::Empty => {
+                    *node = SparseNode::new_leaf(key.slice(path.len()..));
+                    break;
+                }
+                SparseNode::Hash(hash) => {
+                    return Err(SparseTrieError::BlindedNode { path, hash: *hash })
+                }
+                SparseNode::Leaf { key: leaf_key, .. } => {
+                    let remainder = &key[path.len()..];
+                    if leaf_key[..] == *remainder {
+                        break;
+                    }
+
+                    // Replace the leaf with a branch node, preceded by an extension node if the
+                    // keys share a prefix.
+                    let leaf_key = leaf_key.clone();
+                    let common = leaf_key.common_prefix_length(remainder);
+                    let branch_path = path.join(&leaf_key.slice(..common));
+                    let existing_nibble = leaf_key[common];
+                    let new_nibble = key[branch_path.len()];
+                    let state_mask =
+                        TrieMask::from_nibble(existing_nibble) | TrieMask::from_nibble(new_nibble);
+
+                    if common > 0 {
+                        *node = SparseNode::new_extension(leaf_key.slice(..common));
+                        self.nodes.insert(branch_path.clone(), SparseNode::new_branch(state_mask));
+                    } else {
+                        *node = SparseNode::new_branch(state_mask);
+                    }
+
+                    let mut existing_path = branch_path.clone();
+                    existing_path.push(existing_nibble);
+                    self.nodes
+                        .insert(existing_path, SparseNode::new_leaf(leaf_key.slice(common + 1..)));
+                    self.insert_new_leaf(&branch_path, new_nibble, &key);
+                    break;
+                }
+                SparseNode::Extension { key: extension_key, .. } => {
+                    let remainder = &key[path.len()..];
+                    if remainder.starts_with(extension_key) {
+                        path.extend_from_slice(&extension_key.clone());
+                        continue;
+                    }
+
+                    // Split the extension node at the first diverging nibble.
+                    let extension_key = extension_key.clone();
+                    let common = extension_key.common_prefix_length(remainder);
+                    let branch_path = path.join(&extension_key.slice(..common));
+                    let existing_nibble = extension_key[common];
+                    let new_nibble = key[branch_path.len()];
+                    let state_mask =
+                        TrieMask::from_nibble(existing_nibble) | TrieMask::from_nibble(new_nibble);
+
+                    if common > 0 {
+                        *node = SparseNode::new_extension(extension_key.slice(..common));
+                        self.nodes.insert(branch_path.clone(), SparseNode::new_branch(state_mask));
+                    } else {
+                        *node = SparseNode::new_branch(state_mask);
+                    }
+
+                    // The child of the original extension node stays at its path, so it's only
+                    // necessary to insert a shorter extension node if there are nibbles left.
+                    if extension_key.len() > common + 1 {
+                        let mut existing_path = branch_path.clone();
+                        existing_path.push(existing_nibble);
+                        self.nodes.insert(
+                            existing_path,
+                            SparseNode::new_extension(extension_key.slice(common + 1..)),
+                        );
+                    }
+                    self.insert_new_leaf(&branch_path, new_nibble, &key);
+                    break;
+                }
+                SparseNode::Branch { state_mask, .. } => {
+                    let nibble = key[path.len()];
+                    path.push(nibble);
+                    if !state_mask.is_bit_set(nibble) {
+                        state_mask.set_bit(nibble);
+                        self.nodes
+                            .insert(path.clone(), SparseNode::new_leaf(key.slice(path.len()..)));
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.values.insert(key, value);
+        Ok(())
+    }
+
+    /// Inserts a new leaf node for the given key as a child of the branch node at the given path.
+    fn insert_new_leaf(&mut self, branch_path: &Nibbles, nibble: u8, key: &Nibbles) {
+        let mut path = branch_path.clone();
+        path.push(nibble);
+        let leaf = SparseNode::new_leaf(key.slice(path.len()..));
+        self.nodes.insert(path, leaf);
+    }
+
+    /// Removes the leaf at the given key, collapsing the branch node above it if it's left with a
+    /// single child.
+    ///
+    /// Returns the value of the removed leaf, or [`None`] if the key is not present in the trie.
+    /// Returns an error if a blinded node is encountered on the path to the leaf, or if the only
+    /// remaining sibling of the leaf is blinded, since its type determines how the branch node
+    /// collapses. The nodes to reveal before removing a set of leaves can be determined with
+    /// [`missing_proof_nodes`](crate::proof::missing_proof_nodes).
+    pub fn remove_leaf(&mut self, key: &Nibbles) -> Result<Option<Vec<u8>>, SparseTrieError> {
+        // Find the leaf, its closest branch node ancestor, and the extension node directly above
+        // that branch node, if any.
+        let mut path = Nibbles::default();
+        let mut branch_path = None;
+        let mut branch_extension_path = None;
+        let mut extension_path = None;
+        loop {
+            match &self.nodes[&path] {
+                SparseNode::Empty => return Ok(None),
+                SparseNode::Hash(hash) => {
+                    return Err(SparseTrieError::BlindedNode { path, hash: *hash })
+                }
+                SparseNode::Leaf { key: leaf_key, .. } => {
+                    if key[path.len()..] != leaf_key[..] {
+                        return Ok(None);
+                    }
+                    break;
+                }
+                SparseNode::Extension { key: extension_key, .. } => {
+                    if !key[path.len()..].starts_with(extension_key) {
+                        return Ok(None);
+                    }
+                    extension_path = Some(path.clone());
+                    path.extend_from_slice(&extension_key.clone());
+                }
+                SparseNode::Branch { state_mask, .. } => {
+                    let nibble = key[path.len()];
+                    if !state_mask.is_bit_set(nibble) {
+                        return Ok(None);
+                    }
+                    branch_path = Some(path.clone());
+                    branch_extension_path = extension_path.take();
+                    path.push(nibble);
+                }
+            }
+        }
+
+        let Some(branch_path) = branch_path else {
+            // The leaf is the root node.
+            self.nodes.insert(Nibbles::default(), SparseNode::Empty);
+            return Ok(self.values.remove(key));
+        };
+
+        let SparseNode::Branch { state_mask, .. } = &self.nodes[&branch_path] else {
+            unreachable!("node at {branch_path:?} must be a branch node")
+        };
+        let mut state_mask = *state_mask;
+        state_mask.unset_bit(key[branch_path.len()]);
+
+        if state_mask.count_bits() > 1 {
+            self.nodes.insert(branch_path.clone(), SparseNode::new_branch(state_mask));
+        } else {
+            // The branch node is left with a single child, so it needs to be collapsed into it.
+            let sibling_nibble = state_mask.first_set_bit_index().expect("branch has a child");
+            let mut sibling_path = branch_path.clone();
+            sibling_path.push(sibling_nibble);
+
+            let sibling = &self.nodes[&sibling_path];
+            let prefix = Nibbles::from_nibbles_unchecked([sibling_nibble]);
+            let (mut collapsed, sibling_moved) = match sibling {
+                SparseNode::Hash(hash) => {
+                    return Err(SparseTrieError::BlindedNode { path: sibling_path, hash: *hash })
+                }
+                // The sibling branch node stays at its path, behind a new extension node.
+                SparseNode::Branch { .. } => (SparseNode::new_extension(prefix), false),
+                sibling => (with_key_prefix(&prefix, sibling), true),
+            };
+            if sibling_moved {
+                self.nodes.remove(&sibling_path);
+            }
+
+            // The collapsed node is either a leaf or an extension node, so it's merged into the
+            // extension node above the branch node, if there's one.
+            let collapsed_path = match branch_extension_path {
+                Some(extension_path) => {
+                    let SparseNode::Extension { key, .. } = &self.nodes[&extension_path] else {
+                        unreachable!("node at {extension_path:?} must be an extension node")
+                    };
+                    collapsed = with_key_prefix(key, &collapsed);
+                    self.nodes.remove(&branch_path);
+                    extension_path
+                }
+                None => branch_path.clone(),
+            };
+            trace!(target: "trie::sparse", ?collapsed_path, ?collapsed, "collapsed branch node");
+            self.nodes.insert(collapsed_path, collapsed);
+        }
+
+        self.nodes.remove(&path);
+        self.invalidate_path(&branch_path);
+        Ok(self.values.remove(key))
+    }
+
+    /// Removes the node at the given path along with all nodes and values below it, unsetting
+    /// the corresponding bit in the parent branch node. Removing the root node empties the trie.
+    ///
+    /// The node must be either the root node or a child of a branch node.
+    pub(crate) fn remove_subtree(&mut self, path: &Nibbles) {
+        self.nodes.retain(|node_path, _| !node_path.starts_with(path));
+        self.values.retain(|key, _| !key.starts_with(path));
+
+        let Some((&nibble, parent)) = path.split_last() else {
+            self.nodes.insert(Nibbles::default(), SparseNode::Empty);
+            return;
+        };
+        let parent = Nibbles::from_nibbles_unchecked(parent);
+        if let Some(SparseNode::Branch { state_mask, .. }) = self.nodes.get_mut(&parent) {
+            state_mask.unset_bit(nibble);
+        } else {
+            debug_assert!(false, "parent of {path:?} must be a branch node");
+        }
+        self.invalidate_path(&parent);
+    }
+
+    /// Clears the cached RLP pointers of all nodes on the given path.
+    fn invalidate_path(&mut self, path: &Nibbles) {
+        for len in 0..=path.len() {
+            if let Some(node) = self.nodes.get_mut(&path[..len]) {
+                node.invalidate();
+            }
+        }
+    }
+
+    /// Calculates the root of the trie.
+    ///
+    /// The RLP pointers of all nodes are cached, so that only the modified nodes are re-hashed on
+    /// subsequent calls.
+    pub fn root(&mut self) -> B256 {
+        let mut rlp_buf = core::mem::take(&mut self.rlp_buf);
+        let root = self.rlp_node(&Nibbles::default(), &mut rlp_buf);
+        self.rlp_buf = rlp_buf;
+        root.as_hash().unwrap_or_else(|| KeccakHasher::hash(&root))
+    }
+
+    /// Returns the proof of the given key, which consists of the nodes on the path from the root
+    /// down to the leaf at the key, or down to the node where the key diverges from the trie,
+    /// keyed by their paths.
+    ///
+    /// The proof is generated from the revealed nodes, including modified ones, and contains the
+    /// same nodes as retained by a [`HashBuilder`](crate::HashBuilder) for the key, so that many
+    /// proofs can be answered from a revealed witness without rebuilding the trie. Returns an
+    /// error if a blinded node is encountered on the path to the key.
+    pub fn proof(&mut self, key: &Nibbles) -> Result<ProofNodes, SparseTrieError> {
+        let mut rlp_buf = core::mem::take(&mut self.rlp_buf);
+        let mut proof = ProofNodes::default();
+        let mut path = Nibbles::default();
+        let result = loop {
+            let next = match &self.nodes[&path] {
+                SparseNode::Hash(hash) => {
+                    break Err(SparseTrieError::BlindedNode { path, hash: *hash })
+                }
+                SparseNode::Empty | SparseNode::Leaf { .. } => None,
+                SparseNode::Extension { key: extension_key, .. } => {
+                    key[path.len()..].starts_with(extension_key).then(|| path.join(extension_key))
+                }
+                SparseNode::Branch { state_mask, .. } => key
+                    .get(path.len())
+                    .filter(|nibble| state_mask.is_bit_set(**nibble))
+                    .map(|&nibble| {
+                        let mut child_path = path.clone();
+                        child_path.push(nibble);
+                        child_path
+                    }),
+            };
+            self.encode_node(&path, &mut rlp_buf);
+            proof.insert(path, Bytes::copy_from_slice(&rlp_buf));
+            match next {
+                Some(next) => path = next,
+                None => break Ok(proof),
+            }
+        };
+        self.rlp_buf = rlp_buf;
+        result
+    }
+
+    /// Returns the RLP pointer to the node at the given path, computing it if it's not cached.
+    fn rlp_node(&mut self, path: &Nibbles, rlp_buf: &mut Vec<u8>) -> RlpNode {
+        let node = &self.nodes[path];
+        if let Some(rlp_node) = node.rlp_node() {
+            return rlp_node.clone();
+        }
+
+        let rlp_node = match node {
+            SparseNode::Empty => RlpNode::from_rlp(&[EMPTY_STRING_CODE]),
+            SparseNode::Hash(hash) => RlpNode::word_rlp(hash),
+            SparseNode::Leaf { .. } | SparseNode::Extension { .. } | SparseNode::Branch { .. } => {
+                self.encode_node(path, rlp_buf);
+                RlpNode::from_rlp(rlp_buf)
+            }
+        };
+
+        if let Some(node) = self.nodes.get_mut(path) {
+            node.set_rlp_node(rlp_node.clone());
+        }
+        rlp_node
+    }
+
+    /// Encodes the revealed node at the given path into the buffer, computing the RLP pointers of
+    /// its children.
+    fn encode_node(&mut self, path: &Nibbles, rlp_buf: &mut Vec<u8>) {
+        match self.nodes[path].clone() {
+            SparseNode::Empty => {
+                rlp_buf.clear();
+                rlp_buf.push(EMPTY_STRING_CODE);
+            }
+            SparseNode::Hash(_) => unreachable!("blinded node at path {path:?}"),
+            SparseNode::Leaf { key, .. } => {
+                let value = &self.values[&path.join(&key)];
+                rlp_buf.clear();
+                LeafNodeRef::new(&key, value).encode(rlp_buf);
+            }
+            SparseNode::Extension { key, .. } => {
+                let child = self.rlp_node(&path.join(&key), rlp_buf);
+                rlp_buf.clear();
+                ExtensionNodeRef::new(&key, &child).encode(rlp_buf);
+            }
+            SparseNode::Branch { state_mask, .. } => {
+                let mut children = Vec::with_capacity(state_mask.count_bits() as usize);
+                let mut child_path = path.clone();
+                for nibble in CHILD_INDEX_RANGE.filter(|nibble| state_mask.is_bit_set(*nibble)) {
+                    child_path.push(nibble);
+                    children.push(self.rlp_node(&child_path, rlp_buf));
+                    child_path.pop();
+                }
+                rlp_buf.clear();
+                BranchNodeRef::new(&children, state_mask).encode(rlp_buf);
+            }
+        }
+    }
+}
+
+/// Returns a copy of the given leaf or extension node with the prefix prepended to its key.
+fn with_key_prefix(prefix: &Nibbles, node: &SparseNode) -> SparseNode {
+    match node {
+        SparseNode::Leaf { key, .. } => SparseNode::new_leaf(prefix.join(key)),
+        SparseNode::Extension { key, .. } => SparseNode::new_extension(prefix.join(key)),
+        _ => unreachable!("expected a leaf or an extension node, got {node:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::{hex, keccak256, U256};
+
+    fn hash_builder_root(leaves: &BTreeMap<Nibbles, Vec<u8>>) -> B256 {
+        let mut hb = HashBuilder::default();
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root()
+    }
+
+    fn hashed_leaves(range: core::ops::Range<u64>) -> BTreeMap<Nibbles, Vec<u8>> {
+        range
+            .map(|i| {
+                let key = keccak256(i.to_be_bytes());
+                (Nibbles::unpack(key), alloy_rlp::encode(U256::from(i)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty() {
+        let mut trie = SparseTrie::default();
+        assert_eq!(trie.root(), EMPTY_ROOT_HASH);
+        assert_eq!(SparseTrie::blind(EMPTY_ROOT_HASH).root(), EMPTY_ROOT_HASH);
+        assert!(!SparseTrie::blind(EMPTY_ROOT_HASH).is_blind());
+    }
+
+    #[test]
+    fn insert_and_remove_raw_keys() {
+        // Short keys and values produce nodes encoded in-place.
+        let leaves = BTreeMap::from([
+            (Nibbles::unpack(hex!("646f")), hex!("76657262").to_vec()),
+            (Nibbles::unpack(hex!("676f6f64")), hex!("7075707079").to_vec()),
+            (Nibbles::unpack(hex!("676f6b32")), hex!("7075707079").to_vec()),
+            (Nibbles::unpack(hex!("676f6b34")), hex!("7075707079").to_vec()),
+        ]);
+
+        let mut trie = SparseTrie::default();
+        let mut expected = BTreeMap::new();
+        for (key, value) in &leaves {
+            trie.update_leaf(key.clone(), value.clone()).unwrap();
+            expected.insert(key.clone(), value.clone());
+            assert_eq!(trie.root(), hash_builder_root(&expected));
+        }
+
+        for key in leaves.keys().rev() {
+            assert_eq!(trie.remove_leaf(key).unwrap(), expected.remove(key));
+            assert_eq!(trie.root(), hash_builder_root(&expected));
+        }
+        assert_eq!(trie.root(), EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn insert_update_remove_hashed_keys() {
+        let mut expected = hashed_leaves(0..300);
+        let mut trie = SparseTrie::default();
+        for (key, value) in &expected {
+            trie.update_leaf(key.clone(), value.clone()).unwrap();
+        }
+        assert_eq!(trie.root(), hash_builder_root(&expected));
+
+        let keys = expected.keys().cloned().collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate().step_by(7) {
+            let value = alloy_rlp::encode(U256::from(i) << 128);
+            trie.update_leaf(key.clone(), value.clone()).unwrap();
+            expected.insert(key.clone(), value);
+        }
+        assert_eq!(trie.root(), hash_builder_root(&expected));
+
+        for key in keys.iter().step_by(3) {
+            assert_eq!(trie.remove_leaf(key).unwrap(), expected.remove(key));
+        }
+        assert_eq!(trie.root(), hash_builder_root(&expected));
+        assert_eq!(trie.remove_leaf(&keys[0]).unwrap(), None);
+    }
+
+    #[test]
+    fn reveal_and_update() {
+        let mut expected = hashed_leaves(0..256);
+        let existing = expected.keys().nth(100).unwrap().clone();
+        let new = Nibbles::unpack(keccak256(b"new"));
+
+        let retainer = ProofRetainer::from_iter([existing.clone(), new.clone()]);
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in &expected {
+            hb.add_leaf(key.clone(), value);
+        }
+        let root = hb.root();
+
+        let mut trie = SparseTrie::blind(root);
+        assert!(trie.is_blind());
+        trie.reveal_proof_nodes(&hb.take_proof_nodes()).unwrap();
+        assert!(!trie.is_blind());
+        assert_eq!(trie.get_leaf_value(&existing), expected.get(&existing).map(Vec::as_slice));
+        assert_eq!(trie.root(), root);
+
+        let value = alloy_rlp::encode(U256::MAX);
+        trie.update_leaf(existing.clone(), value.clone()).unwrap();
+        expected.insert(existing, value.clone());
+        trie.update_leaf(new.clone(), value.clone()).unwrap();
+        expected.insert(new, value);
+        assert_eq!(trie.root(), hash_builder_root(&expected));
+    }
+
+    /// Returns the proof of the target retained by a hash builder over the leaves.
+    fn hash_builder_proof(leaves: &BTreeMap<Nibbles, Vec<u8>>, target: &Nibbles) -> ProofNodes {
+        let retainer = ProofRetainer::from_iter([target.clone()]);
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root();
+        hb.take_proof_nodes()
+    }
+
+    #[test]
+    fn proofs() {
+        let mut expected = hashed_leaves(0..256);
+        let targets = expected.keys().step_by(50).cloned().collect::<Vec<_>>();
+        let absent = Nibbles::unpack(keccak256(b"absent"));
+
+        let witness = targets
+            .iter()
+            .chain([&absent])
+            .map(|target| hash_builder_proof(&expected, target))
+            .fold(ProofNodes::default(), |mut witness, proof| {
+                witness.extend_from(proof);
+                witness
+            });
+        let mut trie = SparseTrie::blind(hash_builder_root(&expected));
+        trie.reveal_proof_nodes(&witness).unwrap();
+        for target in targets.iter().chain([&absent]) {
+            assert_eq!(trie.proof(target), Ok(hash_builder_proof(&expected, target)));
+        }
+
+        // Proofs reflect the updates.
+        let value = alloy_rlp::encode(U256::MAX);
+        trie.update_leaf(targets[1].clone(), value.clone()).unwrap();
+        expected.insert(targets[1].clone(), value);
+        trie.remove_leaf(&targets[2]).unwrap();
+        expected.remove(&targets[2]);
+        for target in &targets {
+            assert_eq!(trie.proof(target), Ok(hash_builder_proof(&expected, target)));
+        }
+
+        let blinded = expected.keys().nth(1).unwrap();
+        assert!(matches!(trie.proof(blinded), Err(SparseTrieError::BlindedNode { .. })));
+
+        // Nodes encoded in-place are part of the proof.
+        let leaves = collapse_leaves(1);
+        let mut trie = SparseTrie::default();
+        for (key, value) in &leaves {
+            trie.update_leaf(key.clone(), value.clone()).unwrap();
+        }
+        for target in leaves.keys().chain([&Nibbles::from_nibbles([0, 0, 2, 0])]) {
+            assert_eq!(trie.proof(target), Ok(hash_builder_proof(&leaves, target)));
+        }
+        assert_eq!(
+            SparseTrie::default().proof(&absent),
+            Ok(hash_builder_proof(&BTreeMap::new(), &absent))
+        );
+    }
+
+    #[test]
+    fn reveal_invalid_node() {
+        let mut trie = SparseTrie::blind(B256::repeat_byte(1));
+        let node = TrieNode::Leaf(crate::nodes::LeafNode::new(
+            Nibbles::unpack(B256::ZERO),
+            B256::ZERO.to_vec(),
+        ));
+        assert_eq!(
+            trie.reveal_node(Nibbles::default(), node.clone()),
+            Err(SparseTrieError::NodeHashMismatch {
+                path: Nibbles::default(),
+                expected: B256::repeat_byte(1)
+            })
+        );
+        assert_eq!(
+            trie.reveal_node(Nibbles::from_nibbles([0x1]), node),
+            Err(SparseTrieError::UnreachableNode { path: Nibbles::from_nibbles([0x1]) })
+        );
+    }
+
+    #[test]
+    fn remove_with_blinded_sibling() {
+        let leaves = BTreeMap::from([
+            (Nibbles::unpack(B256::with_last_byte(1)), B256::repeat_byte(1).to_vec()),
+            (Nibbles::unpack(B256::repeat_byte(0xff)), B256::repeat_byte(2).to_vec()),
+        ]);
+        let target = leaves.keys().next().unwrap().clone();
+
+        let mut hb =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(vec![target.clone()]));
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        let root = hb.root();
+
+        let mut trie = SparseTrie::blind(root);
+        trie.reveal_proof_nodes(&hb.take_proof_nodes()).unwrap();
+
+        // The sibling leaf must be revealed to collapse the root branch node.
+        let sibling_path = Nibbles::from_nibbles([0xf]);
+        assert!(matches!(
+            trie.remove_leaf(&target),
+            Err(SparseTrieError::BlindedNode { path, .. }) if path == sibling_path
+        ));
+        assert_eq!(trie.root(), root);
+    }
+
+    /// Two-byte keys sharing prefixes at different depths, so that removing them collapses branch
+    /// nodes into leaves, extensions and other branch nodes, with and without an extension node
+    /// above.
+    const COLLAPSE_KEYS: [[u8; 2]; 8] = [
+        [0x00, 0x00],
+        [0x00, 0x01],
+        [0x00, 0x10],
+        [0x01, 0x00],
+        [0x10, 0x00],
+        [0x10, 0x01],
+        [0x11, 0x11],
+        [0xff, 0xff],
+    ];
+
+    fn collapse_leaves(value_len: usize) -> BTreeMap<Nibbles, Vec<u8>> {
+        COLLAPSE_KEYS
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (Nibbles::unpack(key), vec![i as u8 + 1; value_len]))
+            .collect()
+    }
+
+    /// Returns the keys selected by the bits of the mask.
+    fn subset(leaves: &BTreeMap<Nibbles, Vec<u8>>, mask: u32) -> Vec<Nibbles> {
+        leaves
+            .keys()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    #[test]
+    fn remove_every_subset() {
+        // Short values produce nodes encoded in-place, long values hashed nodes.
+        for value_len in [1, 32] {
+            let leaves = collapse_leaves(value_len);
+            for mask in 0..1 << leaves.len() {
+                let removed = subset(&leaves, mask);
+                for order in [removed.clone(), removed.iter().rev().cloned().collect()] {
+                    let mut trie = SparseTrie::default();
+                    for (key, value) in &leaves {
+                        trie.update_leaf(key.clone(), value.clone()).unwrap();
+                    }
+
+                    let mut expected = leaves.clone();
+                    for key in &order {
+                        assert_eq!(trie.remove_leaf(key).unwrap(), expected.remove(key));
+                        let root = trie.root();
+                        assert_eq!(root, hash_builder_root(&expected), "removed {order:?}");
+                        assert_eq!(
+                            root,
+                            crate::triehash_trie_root(
+                                expected.iter().map(|(key, value)| (key.pack(), value))
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn remove_every_subset_from_revealed_proof() {
+        for value_len in [1, 32] {
+            let leaves = collapse_leaves(value_len);
+            let root = hash_builder_root(&leaves);
+            for mask in 1..1 << leaves.len() {
+                let removed = subset(&leaves, mask);
+
+                // Fetch the missing nodes until the proof is sufficient.
+                let mut targets = removed.clone();
+                let proof = loop {
+                    let retainer = ProofRetainer::from_iter(targets.iter().cloned());
+                    let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+                    for (key, value) in &leaves {
+                        hb.add_leaf(key.clone(), value);
+                    }
+                    hb.root();
+                    let proof = hb.take_proof_nodes();
+                    let missing = crate::proof::missing_proof_nodes(
+                        root,
+                        &proof,
+                        [],
+                        removed.iter().cloned(),
+                    )
+                    .unwrap();
+                    if missing.is_empty() {
+                        break proof;
+                    }
+                    targets.extend(missing);
+                };
+
+                let mut trie = SparseTrie::blind(root);
+                trie.reveal_proof_nodes(&proof).unwrap();
+                let mut expected = leaves.clone();
+                for key in &removed {
+                    assert_eq!(trie.remove_leaf(key).unwrap(), expected.remove(key));
+                }
+                assert_eq!(trie.root(), hash_builder_root(&expected), "removed {removed:?}");
+            }
+        }
+    }
+}
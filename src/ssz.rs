@@ -0,0 +1,531 @@
+//! SSZ encoding of trie nodes and proofs, for witness formats of consensus-layer clients and the
+//! Portal Network.
+//!
+//! The types implementing [`SszCodec`] are encoded with the following SSZ schemas, where paths
+//! and keys are lists of nibbles, one nibble per byte:
+//!
+//! ```text
+//! Nibbles           = List[uint8, 64]
+//! RlpNode           = ByteList[33]
+//! BranchNode        = Container { state_mask: uint16, stack: List[RlpNode, 16] }
+//! ExtensionNode     = Container { key: Nibbles, child: RlpNode }
+//! LeafNode          = Container { key: Nibbles, value: ByteList }
+//! TrieNode          = Union[None, BranchNode, ExtensionNode, LeafNode]
+//! ProofNodes        = List[Container { path: Nibbles, node: ByteList }]
+//! StorageMultiProof = Container { root: Bytes32, subtree: ProofNodes }
+//! MultiProof        = Container {
+//!     account_subtree: ProofNodes,
+//!     storages: List[Container { hashed_address: Bytes32, proof: StorageMultiProof }],
+//! }
+//! ```
+//!
+//! Proof nodes are sorted by path and storage multiproofs by hashed address, so that the encoding
+//! is deterministic. Proof nodes remain RLP encoded, as their hashes commit to the RLP encoding.
+
+use crate::{
+    nodes::{BranchNode, ExtensionNode, LeafNode, RlpNode, TrieNode},
+    proof::{MultiProof, ProofNodes, StorageMultiProof},
+    Nibbles, TrieMask,
+};
+use alloy_primitives::{Bytes, B256};
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Size of an SSZ offset.
+const OFFSET_SIZE: usize = 4;
+
+/// Maximum length of a path, in nibbles.
+const MAX_PATH_LEN: usize = 64;
+
+/// Union selectors of the [`TrieNode`] variants.
+const SELECTOR_EMPTY_ROOT: u8 = 0;
+const SELECTOR_BRANCH: u8 = 1;
+const SELECTOR_EXTENSION: u8 = 2;
+const SELECTOR_LEAF: u8 = 3;
+
+/// Error during SSZ decoding.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SszError {
+    /// The input is shorter than the type requires.
+    UnexpectedEnd,
+    /// The input continues after the end of a fixed-size value.
+    TrailingBytes,
+    /// An offset points before the end of the fixed-size part, before the previous offset or past
+    /// the end of the input.
+    InvalidOffset(u32),
+    /// A list is longer than its maximum length.
+    TooLong {
+        /// The maximum length.
+        max: usize,
+        /// The length of the list.
+        got: usize,
+    },
+    /// A path contains a byte that is not a nibble.
+    InvalidNibble(u8),
+    /// A packed path starts with an invalid flag byte.
+    InvalidPathFlag(u8),
+    /// A union has an unknown selector.
+    UnknownSelector(u8),
+    /// The number of children of a branch node does not match its state mask.
+    ChildCountMismatch {
+        /// The number of children in the state mask.
+        expected: usize,
+        /// The number of children in the stack.
+        got: usize,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for SszError {}
+
+impl fmt::Display for SszError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => f.write_str("unexpected end of ssz input"),
+            Self::TrailingBytes => f.write_str("unexpected trailing bytes after ssz value"),
+            Self::InvalidOffset(offset) => write!(f, "invalid ssz offset {offset}"),
+            Self::TooLong { max, got } => {
+                write!(f, "ssz list too long. got: {got}. max: {max}")
+            }
+            Self::InvalidNibble(byte) => write!(f, "invalid nibble {byte:#x}"),
+            Self::InvalidPathFlag(flag) => write!(f, "invalid packed path flag {flag:#x}"),
+            Self::UnknownSelector(selector) => write!(f, "unknown ssz union selector {selector}"),
+            Self::ChildCountMismatch { expected, got } => {
+                write!(f, "branch node child count mismatch. got: {got}. expected: {expected}")
+            }
+        }
+    }
+}
+
+/// SSZ encoding and decoding. See the [module documentation](self) for the schemas.
+pub trait SszCodec: Sized {
+    /// Encodes the value into the given buffer.
+    fn ssz_encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from its encoding, which must span the whole input.
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError>;
+
+    /// Returns the encoding of the value.
+    fn ssz_encoded(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.ssz_encode(&mut out);
+        out
+    }
+}
+
+impl SszCodec for BranchNode {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let stack = self.stack.iter().map(|child| child.to_vec()).collect::<Vec<_>>();
+        let mut list = Vec::new();
+        encode_variable(&mut list, &[], &stack);
+        encode_variable(out, &self.state_mask.get().to_le_bytes(), &[list]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (fixed, [stack]) = decode_container::<1>(buf, 2)?;
+        let state_mask = TrieMask::new(u16::from_le_bytes([fixed[0], fixed[1]]));
+        let stack = decode_list(stack, 16)?
+            .into_iter()
+            .map(decode_rlp_node)
+            .collect::<Result<Vec<_>, _>>()?;
+        let expected = state_mask.count_ones() as usize;
+        if stack.len() != expected {
+            return Err(SszError::ChildCountMismatch { expected, got: stack.len() });
+        }
+        Ok(Self::new(stack, state_mask))
+    }
+}
+
+impl SszCodec for ExtensionNode {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        encode_variable(out, &[], &[self.key.to_vec(), self.child.to_vec()]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (_, [key, child]) = decode_container::<2>(buf, 0)?;
+        Ok(Self::new(decode_nibbles(key)?, decode_rlp_node(child)?))
+    }
+}
+
+impl SszCodec for LeafNode {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        encode_variable(out, &[], &[self.key.to_vec(), self.value.clone()]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (_, [key, value]) = decode_container::<2>(buf, 0)?;
+        Ok(Self::new(decode_nibbles(key)?, value.to_vec()))
+    }
+}
+
+impl SszCodec for TrieNode {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::EmptyRoot => out.push(SELECTOR_EMPTY_ROOT),
+            Self::Branch(branch) => {
+                out.push(SELECTOR_BRANCH);
+                branch.ssz_encode(out);
+            }
+            Self::Extension(extension) => {
+                out.push(SELECTOR_EXTENSION);
+                extension.ssz_encode(out);
+            }
+            Self::Leaf(leaf) => {
+                out.push(SELECTOR_LEAF);
+                leaf.ssz_encode(out);
+            }
+        }
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (&selector, value) = buf.split_first().ok_or(SszError::UnexpectedEnd)?;
+        match selector {
+            SELECTOR_EMPTY_ROOT if value.is_empty() => Ok(Self::EmptyRoot),
+            SELECTOR_EMPTY_ROOT => Err(SszError::TrailingBytes),
+            SELECTOR_BRANCH => BranchNode::ssz_decode(value).map(Self::Branch),
+            SELECTOR_EXTENSION => ExtensionNode::ssz_decode(value).map(Self::Extension),
+            SELECTOR_LEAF => LeafNode::ssz_decode(value).map(Self::Leaf),
+            _ => Err(SszError::UnknownSelector(selector)),
+        }
+    }
+}
+
+impl SszCodec for ProofNodes {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let mut nodes = self.iter().collect::<Vec<_>>();
+        nodes.sort_unstable_by_key(|(path, _)| *path);
+        let nodes = nodes
+            .into_iter()
+            .map(|(path, node)| {
+                let mut item = Vec::new();
+                encode_variable(&mut item, &[], &[path.to_vec(), node.to_vec()]);
+                item
+            })
+            .collect::<Vec<_>>();
+        encode_variable(out, &[], &nodes);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        decode_list(buf, usize::MAX)?
+            .into_iter()
+            .map(|item| {
+                let (_, [path, node]) = decode_container::<2>(item, 0)?;
+                Ok((decode_nibbles(path)?, Bytes::copy_from_slice(node)))
+            })
+            .collect()
+    }
+}
+
+impl SszCodec for StorageMultiProof {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        encode_variable(out, self.root.as_slice(), &[self.subtree.ssz_encoded()]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (root, [subtree]) = decode_container::<1>(buf, 32)?;
+        Ok(Self { root: B256::from_slice(root), subtree: ProofNodes::ssz_decode(subtree)? })
+    }
+}
+
+impl SszCodec for MultiProof {
+    fn ssz_encode(&self, out: &mut Vec<u8>) {
+        let mut storages = self.storages.iter().collect::<Vec<_>>();
+        storages.sort_unstable_by_key(|(hashed_address, _)| *hashed_address);
+        let storages = storages
+            .into_iter()
+            .map(|(hashed_address, storage)| {
+                let mut item = Vec::new();
+                encode_variable(&mut item, hashed_address.as_slice(), &[storage.ssz_encoded()]);
+                item
+            })
+            .collect::<Vec<_>>();
+        let mut list = Vec::new();
+        encode_variable(&mut list, &[], &storages);
+        encode_variable(out, &[], &[self.account_subtree.ssz_encoded(), list]);
+    }
+
+    fn ssz_decode(buf: &[u8]) -> Result<Self, SszError> {
+        let (_, [account_subtree, storages]) = decode_container::<2>(buf, 0)?;
+        let storages = decode_list(storages, usize::MAX)?
+            .into_iter()
+            .map(|item| {
+                let (hashed_address, [storage]) = decode_container::<1>(item, 32)?;
+                Ok((B256::from_slice(hashed_address), StorageMultiProof::ssz_decode(storage)?))
+            })
+            .collect::<Result<_, SszError>>()?;
+        Ok(Self { account_subtree: ProofNodes::ssz_decode(account_subtree)?, storages })
+    }
+}
+
+/// Encodes the fixed-size part of a container followed by the offsets and encodings of its
+/// variable-size parts. A list of variable-size items is encoded without a fixed-size part.
+pub(crate) fn encode_variable<T: AsRef<[u8]>>(out: &mut Vec<u8>, fixed: &[u8], parts: &[T]) {
+    out.extend_from_slice(fixed);
+    let mut offset = fixed.len() + parts.len() * OFFSET_SIZE;
+    for part in parts {
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += part.as_ref().len();
+    }
+    for part in parts {
+        out.extend_from_slice(part.as_ref());
+    }
+}
+
+/// A field of a container, see [`encode_fields`].
+#[cfg(feature = "portal")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Field<'a> {
+    /// A fixed-size field, encoded in place.
+    Fixed(&'a [u8]),
+    /// A variable-size field, encoded as an offset in place and its encoding after the fixed-size
+    /// fields.
+    Variable(&'a [u8]),
+}
+
+/// Encodes a container with the given fields, in order.
+#[cfg(feature = "portal")]
+pub(crate) fn encode_fields(out: &mut Vec<u8>, fields: &[Field<'_>]) {
+    let mut offset = fields
+        .iter()
+        .map(|field| match field {
+            Field::Fixed(bytes) => bytes.len(),
+            Field::Variable(_) => OFFSET_SIZE,
+        })
+        .sum::<usize>();
+    for field in fields {
+        match field {
+            Field::Fixed(bytes) => out.extend_from_slice(bytes),
+            Field::Variable(bytes) => {
+                out.extend_from_slice(&(offset as u32).to_le_bytes());
+                offset += bytes.len();
+            }
+        }
+    }
+    for field in fields {
+        if let Field::Variable(bytes) = field {
+            out.extend_from_slice(bytes);
+        }
+    }
+}
+
+/// Decodes a container with `N` fields, given the sizes of its fixed-size fields and [`None`] for
+/// its variable-size fields. Returns the encodings of the fields, in order.
+#[cfg(feature = "portal")]
+pub(crate) fn decode_fields<const N: usize>(
+    buf: &[u8],
+    sizes: [Option<usize>; N],
+) -> Result<[&[u8]; N], SszError> {
+    let fixed_end = sizes.iter().map(|size| size.unwrap_or(OFFSET_SIZE)).sum::<usize>();
+    if buf.len() < fixed_end {
+        return Err(SszError::UnexpectedEnd);
+    }
+
+    let mut fields = [&buf[..0]; N];
+    // The field index and start of the previous variable-size field.
+    let mut variable: Option<(usize, usize)> = None;
+    let mut at = 0;
+    for (i, size) in sizes.into_iter().enumerate() {
+        match size {
+            Some(size) => fields[i] = &buf[at..at + size],
+            None => {
+                let offset = read_offset(buf, at)?;
+                let start = offset as usize;
+                let valid = match variable {
+                    Some((_, previous)) => start >= previous && start <= buf.len(),
+                    None => start == fixed_end,
+                };
+                if !valid {
+                    return Err(SszError::InvalidOffset(offset));
+                }
+                if let Some((index, previous)) = variable {
+                    fields[index] = &buf[previous..start];
+                }
+                variable = Some((i, start));
+            }
+        }
+        at += size.unwrap_or(OFFSET_SIZE);
+    }
+    match variable {
+        Some((index, start)) => fields[index] = &buf[start..],
+        None if buf.len() != fixed_end => return Err(SszError::TrailingBytes),
+        None => {}
+    }
+    Ok(fields)
+}
+
+/// Decodes a container with a fixed-size part of the given size followed by `N` variable-size
+/// parts.
+fn decode_container<const N: usize>(
+    buf: &[u8],
+    fixed_size: usize,
+) -> Result<(&[u8], [&[u8]; N]), SszError> {
+    let offsets_end = fixed_size + N * OFFSET_SIZE;
+    if buf.len() < offsets_end {
+        return Err(SszError::UnexpectedEnd);
+    }
+    let parts = decode_parts(buf, fixed_size, N)?;
+    Ok((&buf[..fixed_size], parts.try_into().unwrap()))
+}
+
+/// Decodes a list of variable-size items, which must not be longer than `max`.
+pub(crate) fn decode_list(buf: &[u8], max: usize) -> Result<Vec<&[u8]>, SszError> {
+    if buf.is_empty() {
+        return Ok(Vec::new());
+    }
+    let first = read_offset(buf, 0)?;
+    if first == 0 || first as usize % OFFSET_SIZE != 0 {
+        return Err(SszError::InvalidOffset(first));
+    }
+    let len = first as usize / OFFSET_SIZE;
+    if len > max {
+        return Err(SszError::TooLong { max, got: len });
+    }
+    decode_parts(buf, 0, len)
+}
+
+/// Splits the variable-size parts off the input, given the size of the fixed-size part and the
+/// number of parts.
+fn decode_parts(buf: &[u8], fixed_size: usize, count: usize) -> Result<Vec<&[u8]>, SszError> {
+    let offsets_end = fixed_size + count * OFFSET_SIZE;
+    let mut parts = Vec::with_capacity(count);
+    let mut start = offsets_end;
+    for i in 0..count {
+        let offset = read_offset(buf, fixed_size + i * OFFSET_SIZE)?;
+        if i == 0 && offset as usize != offsets_end {
+            return Err(SszError::InvalidOffset(offset));
+        }
+        if i > 0 {
+            let next = offset as usize;
+            if next < start || next > buf.len() {
+                return Err(SszError::InvalidOffset(offset));
+            }
+            parts.push(&buf[start..next]);
+            start = next;
+        }
+    }
+    if count > 0 {
+        if start > buf.len() {
+            return Err(SszError::UnexpectedEnd);
+        }
+        parts.push(&buf[start..]);
+    } else if buf.len() != fixed_size {
+        return Err(SszError::TrailingBytes);
+    }
+    Ok(parts)
+}
+
+/// Reads the offset at the given position.
+fn read_offset(buf: &[u8], at: usize) -> Result<u32, SszError> {
+    let bytes = buf.get(at..at + OFFSET_SIZE).ok_or(SszError::UnexpectedEnd)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Decodes a path of nibbles.
+fn decode_nibbles(buf: &[u8]) -> Result<Nibbles, SszError> {
+    if buf.len() > MAX_PATH_LEN {
+        return Err(SszError::TooLong { max: MAX_PATH_LEN, got: buf.len() });
+    }
+    if let Some(&byte) = buf.iter().find(|&&byte| byte > 0xf) {
+        return Err(SszError::InvalidNibble(byte));
+    }
+    Ok(Nibbles::from_nibbles_unchecked(buf))
+}
+
+/// Decodes a child reference of at most 33 bytes.
+fn decode_rlp_node(buf: &[u8]) -> Result<RlpNode, SszError> {
+    RlpNode::from_raw(buf).ok_or(SszError::TooLong { max: 33, got: buf.len() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloy_primitives::{hex, keccak256};
+
+    fn proof_nodes() -> ProofNodes {
+        let keys = (0u8..20).map(|i| Nibbles::unpack(keccak256([i]))).collect::<Vec<_>>();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        let retainer = ProofRetainer::from_iter(keys.iter().step_by(3).cloned());
+        let mut hb = HashBuilder::default().with_proof_retainer(retainer);
+        for key in &sorted {
+            hb.add_leaf(key.clone(), &[0xaa; 40]);
+        }
+        hb.root();
+        hb.take_proof_nodes()
+    }
+
+    #[test]
+    fn trie_node_roundtrip() {
+        let leaf = LeafNode::new(Nibbles::from_nibbles([0x1, 0x2, 0x3]), vec![0xde, 0xad]);
+        let extension = ExtensionNode::new(
+            Nibbles::from_nibbles([0xa, 0xb]),
+            RlpNode::word_rlp(&B256::repeat_byte(0x11)),
+        );
+        let branch = BranchNode::new(
+            vec![RlpNode::word_rlp(&B256::repeat_byte(0x22)), RlpNode::from_rlp(&[0x80; 3])],
+            TrieMask::new(0b1000_0000_0000_0010),
+        );
+        for node in [
+            TrieNode::EmptyRoot,
+            TrieNode::Leaf(leaf),
+            TrieNode::Extension(extension),
+            TrieNode::Branch(branch),
+        ] {
+            assert_eq!(TrieNode::ssz_decode(&node.ssz_encoded()), Ok(node));
+        }
+    }
+
+    #[test]
+    fn leaf_encoding() {
+        let leaf = LeafNode::new(Nibbles::from_nibbles([0x1, 0x2]), vec![0xff]);
+        assert_eq!(leaf.ssz_encoded(), hex!("08000000 0a000000 0102 ff"));
+    }
+
+    #[test]
+    fn proof_roundtrip() {
+        let nodes = proof_nodes();
+        assert!(!nodes.is_empty());
+        assert_eq!(ProofNodes::ssz_decode(&nodes.ssz_encoded()), Ok(nodes.clone()));
+        assert_eq!(ProofNodes::ssz_decode(&[]), Ok(ProofNodes::default()));
+
+        let mut multiproof = MultiProof::new(nodes.clone());
+        for i in 0..3 {
+            let storage = StorageMultiProof { root: B256::repeat_byte(i), subtree: nodes.clone() };
+            multiproof.insert_storage(B256::with_last_byte(i), storage);
+        }
+        multiproof.insert_storage(B256::with_last_byte(9), StorageMultiProof::empty());
+        let encoded = multiproof.ssz_encoded();
+        assert_eq!(MultiProof::ssz_decode(&encoded), Ok(multiproof.clone()));
+        // The encoding doesn't depend on the iteration order of the maps.
+        assert_eq!(MultiProof::ssz_decode(&encoded).unwrap().ssz_encoded(), encoded);
+    }
+
+    #[test]
+    fn invalid_encodings() {
+        assert_eq!(TrieNode::ssz_decode(&[]), Err(SszError::UnexpectedEnd));
+        assert_eq!(TrieNode::ssz_decode(&[4]), Err(SszError::UnknownSelector(4)));
+        assert_eq!(TrieNode::ssz_decode(&[0, 0]), Err(SszError::TrailingBytes));
+        assert_eq!(
+            LeafNode::ssz_decode(&hex!("08000000 0a000000 0110 ff")),
+            Err(SszError::InvalidNibble(0x10))
+        );
+        assert_eq!(
+            LeafNode::ssz_decode(&hex!("09000000 0a000000 0102 ff")),
+            Err(SszError::InvalidOffset(9))
+        );
+        assert_eq!(
+            LeafNode::ssz_decode(&hex!("08000000 0c000000 0102 ff")),
+            Err(SszError::InvalidOffset(12))
+        );
+        let branch = BranchNode::new(vec![RlpNode::word_rlp(&B256::ZERO)], TrieMask::new(0b11));
+        assert_eq!(
+            BranchNode::ssz_decode(&branch.ssz_encoded()),
+            Err(SszError::ChildCountMismatch { expected: 2, got: 1 })
+        );
+    }
+}
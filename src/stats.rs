@@ -0,0 +1,320 @@
+//! Statistics about the shape and size of a trie, for capacity planning and for validating state
+//! snapshots.
+//!
+//! [`TrieStats`] are collected from the nodes of a proof with [`TrieStats::from_proof_nodes`], from
+//! a [`SparseTrie`] with [`TrieStats::from_sparse_trie`], or from the branch nodes stored in a
+//! database with [`TrieStats::from_trie_cursor`]. Statistics of disjoint parts of a trie, such as
+//! its subtries, can be combined with [`TrieStats::merge`].
+
+use crate::{
+    cursor::{HashedCursor, TrieCursor},
+    nodes::{
+        BranchNodeRef, ExtensionNodeRef, LeafNodeRef, RlpNode, TrieNode, TrieNodeDecodeError,
+        CHILD_INDEX_RANGE,
+    },
+    proof::ProofNodes,
+    sparse::{SparseNode, SparseTrie},
+    Nibbles, TrieMask,
+};
+use alloy_primitives::B256;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Statistics about the nodes of a trie. See the [module documentation](self).
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrieStats {
+    /// The number of nodes by depth, i.e. by the length of their path in nibbles.
+    pub depth_histogram: Vec<u64>,
+    /// The number of branch nodes.
+    pub branch_nodes: u64,
+    /// The number of extension nodes.
+    pub extension_nodes: u64,
+    /// The number of leaf nodes.
+    pub leaf_nodes: u64,
+    /// The number of nodes known only by their hash, which are not counted in the histogram.
+    pub blinded_nodes: u64,
+    /// The total number of children of the branch nodes.
+    pub branch_children: u64,
+    /// The total length of the RLP encodings of the nodes referenced by hash, i.e. of the nodes
+    /// that are stored separately rather than embedded in their parent.
+    pub rlp_bytes: u64,
+}
+
+impl TrieStats {
+    /// Collects the statistics of the given proof nodes, including the nodes embedded in them that
+    /// are not part of the proof themselves.
+    pub fn from_proof_nodes(proof_nodes: &ProofNodes) -> Result<Self, TrieNodeDecodeError> {
+        let mut stats = Self::default();
+        for (path, node) in proof_nodes.iter() {
+            if path.is_empty() || node.len() >= 32 {
+                stats.rlp_bytes += node.len() as u64;
+            }
+            stats.record_decoded(proof_nodes, path.clone(), TrieNode::decode_raw(node)?)?;
+        }
+        Ok(stats)
+    }
+
+    /// Collects the statistics of the revealed nodes of the sparse trie. Its root is computed
+    /// first, so that the encodings of all nodes are known.
+    pub fn from_sparse_trie(trie: &mut SparseTrie) -> Self {
+        trie.root();
+
+        let mut stats = Self::default();
+        let mut rlp_buf = Vec::new();
+        for (path, node) in trie.nodes() {
+            rlp_buf.clear();
+            let rlp_node = match node {
+                SparseNode::Empty => continue,
+                SparseNode::Hash(_) => {
+                    stats.blinded_nodes += 1;
+                    continue;
+                }
+                SparseNode::Leaf { key, .. } => {
+                    let value = trie.get_leaf_value(&path.join(key)).unwrap_or_default();
+                    stats.record_leaf(path.len());
+                    LeafNodeRef::new(key, value).rlp(&mut rlp_buf)
+                }
+                SparseNode::Extension { key, .. } => {
+                    let child = cached_rlp_node(trie, &path.join(key));
+                    stats.record_extension(path.len());
+                    ExtensionNodeRef::new(key, &child).rlp(&mut rlp_buf)
+                }
+                SparseNode::Branch { state_mask, .. } => {
+                    let children = CHILD_INDEX_RANGE
+                        .filter(|nibble| state_mask.is_bit_set(*nibble))
+                        .map(|nibble| {
+                            let mut child_path = path.clone();
+                            child_path.push(nibble);
+                            cached_rlp_node(trie, &child_path)
+                        })
+                        .collect::<Vec<_>>();
+                    stats.record_branch(path.len(), *state_mask);
+                    BranchNodeRef::new(&children, *state_mask).rlp(&mut rlp_buf)
+                }
+            };
+            if path.is_empty() || rlp_node.is_hash() {
+                stats.rlp_bytes += rlp_buf.len() as u64;
+            }
+        }
+        stats
+    }
+
+    /// Collects the statistics of the branch nodes returned by the cursor.
+    ///
+    /// Only branch nodes are stored in the database, and their encodings are not, so the other
+    /// counts are left at zero. Leaves can be counted from the hashed entries with
+    /// [`TrieStats::count_hashed_leaves`].
+    pub fn from_trie_cursor<C: TrieCursor>(cursor: &mut C) -> Result<Self, C::Error> {
+        let mut stats = Self::default();
+        let mut entry = cursor.seek(Nibbles::default())?;
+        while let Some((path, node)) = entry {
+            stats.record_branch(path.len(), node.state_mask);
+            entry = cursor.next()?;
+        }
+        Ok(stats)
+    }
+
+    /// Counts the entries returned by the hashed cursor as leaves. As their depth in the trie is
+    /// not known, they are not counted in the histogram.
+    pub fn count_hashed_leaves<C: HashedCursor>(&mut self, cursor: &mut C) -> Result<(), C::Error> {
+        let mut entry = cursor.seek(B256::ZERO)?;
+        while entry.is_some() {
+            self.leaf_nodes += 1;
+            entry = cursor.next()?;
+        }
+        Ok(())
+    }
+
+    /// Adds the statistics of a disjoint part of the trie.
+    pub fn merge(&mut self, other: &Self) {
+        if self.depth_histogram.len() < other.depth_histogram.len() {
+            self.depth_histogram.resize(other.depth_histogram.len(), 0);
+        }
+        for (count, other) in self.depth_histogram.iter_mut().zip(&other.depth_histogram) {
+            *count += other;
+        }
+        self.branch_nodes += other.branch_nodes;
+        self.extension_nodes += other.extension_nodes;
+        self.leaf_nodes += other.leaf_nodes;
+        self.blinded_nodes += other.blinded_nodes;
+        self.branch_children += other.branch_children;
+        self.rlp_bytes += other.rlp_bytes;
+    }
+
+    /// Returns the number of revealed nodes.
+    pub const fn node_count(&self) -> u64 {
+        self.branch_nodes + self.extension_nodes + self.leaf_nodes
+    }
+
+    /// Returns the depth of the deepest node, or [`None`] if no node has been counted.
+    pub fn max_depth(&self) -> Option<usize> {
+        self.depth_histogram.iter().rposition(|count| *count > 0)
+    }
+
+    /// Returns the average number of children of the branch nodes, or [`None`] if there are no
+    /// branch nodes.
+    pub fn average_branch_fanout(&self) -> Option<f64> {
+        (self.branch_nodes > 0).then(|| self.branch_children as f64 / self.branch_nodes as f64)
+    }
+
+    /// Records the decoded node at the given path, along with the children embedded in it that are
+    /// missing from the proof.
+    fn record_decoded(
+        &mut self,
+        proof_nodes: &ProofNodes,
+        path: Nibbles,
+        node: TrieNode,
+    ) -> Result<(), TrieNodeDecodeError> {
+        match node {
+            TrieNode::EmptyRoot => {}
+            TrieNode::Leaf(_) => self.record_leaf(path.len()),
+            TrieNode::Extension(extension) => {
+                self.record_extension(path.len());
+                self.record_embedded(proof_nodes, path.join(&extension.key), &extension.child)?;
+            }
+            TrieNode::Branch(branch) => {
+                self.record_branch(path.len(), branch.state_mask);
+                for (nibble, child) in branch.children() {
+                    let mut child_path = path.clone();
+                    child_path.push(nibble);
+                    self.record_embedded(proof_nodes, child_path, child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the child at the given path if it's embedded in its parent and missing from the
+    /// proof. Other children are recorded from their own proof node, if present.
+    fn record_embedded(
+        &mut self,
+        proof_nodes: &ProofNodes,
+        path: Nibbles,
+        child: &RlpNode,
+    ) -> Result<(), TrieNodeDecodeError> {
+        if child.is_hash() || proof_nodes.contains_key(&path) {
+            return Ok(());
+        }
+        self.record_decoded(proof_nodes, path, TrieNode::decode_raw(child)?)
+    }
+
+    fn record_branch(&mut self, depth: usize, state_mask: TrieMask) {
+        self.record_depth(depth);
+        self.branch_nodes += 1;
+        self.branch_children += state_mask.count_ones() as u64;
+    }
+
+    fn record_extension(&mut self, depth: usize) {
+        self.record_depth(depth);
+        self.extension_nodes += 1;
+    }
+
+    fn record_leaf(&mut self, depth: usize) {
+        self.record_depth(depth);
+        self.leaf_nodes += 1;
+    }
+
+    fn record_depth(&mut self, depth: usize) {
+        if self.depth_histogram.len() <= depth {
+            self.depth_histogram.resize(depth + 1, 0);
+        }
+        self.depth_histogram[depth] += 1;
+    }
+}
+
+/// Returns the cached RLP pointer to the node at the given path of a trie whose root was computed.
+fn cached_rlp_node(trie: &SparseTrie, path: &Nibbles) -> RlpNode {
+    trie.node(path).and_then(SparseNode::rlp_node).cloned().expect("root was computed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursor::{InMemoryHashedCursor, InMemoryTrieCursor},
+        proof::ProofRetainer,
+        HashBuilder,
+    };
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn proof_nodes_and_sparse_trie() {
+        let leaves = BTreeMap::from([
+            (Nibbles::from_nibbles([0x1, 0x0, 0x0, 0x0]), vec![1; 32]),
+            (Nibbles::from_nibbles([0x2, 0x2, 0x2, 0x2]), vec![2; 32]),
+            (Nibbles::from_nibbles([0x2, 0x2, 0x3, 0x3]), vec![3]),
+        ]);
+        let targets = leaves.keys().cloned().collect::<Vec<_>>();
+        let mut hb = HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        let mut sparse = SparseTrie::default();
+        for (key, value) in &leaves {
+            hb.add_leaf(key.clone(), value);
+            sparse.update_leaf(key.clone(), value.clone()).unwrap();
+        }
+        hb.root();
+        let proof_nodes = hb.take_proof_nodes();
+
+        let stats = TrieStats::from_proof_nodes(&proof_nodes).unwrap();
+        assert_eq!(stats.depth_histogram, [1, 2, 1, 2]);
+        assert_eq!((stats.branch_nodes, stats.extension_nodes, stats.leaf_nodes), (2, 1, 3));
+        assert_eq!(stats.node_count(), 6);
+        assert_eq!(stats.max_depth(), Some(3));
+        assert_eq!(stats.average_branch_fanout(), Some(2.0));
+        let hashed = proof_nodes.iter().filter(|(path, node)| path.is_empty() || node.len() >= 32);
+        assert_eq!(stats.rlp_bytes, hashed.map(|(_, node)| node.len() as u64).sum::<u64>());
+
+        // Embedded nodes missing from the proof are decoded from their parent.
+        let embedded = Nibbles::from_nibbles([0x2, 0x2, 0x3]);
+        assert!(proof_nodes[&embedded].len() < 32);
+        let without_embedded = proof_nodes
+            .iter()
+            .filter(|(path, _)| **path != embedded)
+            .map(|(p, n)| (p.clone(), n.clone()));
+        assert_eq!(TrieStats::from_proof_nodes(&without_embedded.collect()).unwrap(), stats);
+
+        assert_eq!(TrieStats::from_sparse_trie(&mut sparse), stats);
+        assert_eq!(
+            TrieStats::from_sparse_trie(&mut SparseTrie::blind(sparse.root())),
+            TrieStats { blinded_nodes: 1, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn cursors_and_merge() {
+        let nodes = BTreeMap::from([
+            (Nibbles::default(), crate::BranchNodeCompact::new(0b0110, 0b0100, 0, vec![], None)),
+            (
+                Nibbles::from_nibbles([0x2]),
+                crate::BranchNodeCompact::new(0b1110, 0, 0, vec![], None),
+            ),
+        ]);
+        let mut stats = TrieStats::from_trie_cursor(&mut InMemoryTrieCursor::new(&nodes)).unwrap();
+        let hashed = BTreeMap::from([(B256::ZERO, ()), (B256::repeat_byte(1), ())]);
+        stats.count_hashed_leaves(&mut InMemoryHashedCursor::new(&hashed)).unwrap();
+        assert_eq!(
+            stats,
+            TrieStats {
+                depth_histogram: vec![1, 1],
+                branch_nodes: 2,
+                leaf_nodes: 2,
+                branch_children: 5,
+                ..Default::default()
+            }
+        );
+        assert_eq!(stats.average_branch_fanout(), Some(2.5));
+
+        let mut merged = TrieStats::default();
+        merged.merge(&stats);
+        merged.merge(&TrieStats {
+            depth_histogram: vec![0, 0, 3],
+            leaf_nodes: 3,
+            ..Default::default()
+        });
+        assert_eq!(merged.depth_histogram, [1, 1, 3]);
+        assert_eq!(merged.node_count(), 7);
+        assert_eq!(TrieStats::default().average_branch_fanout(), None);
+        assert_eq!(TrieStats::default().max_depth(), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! [`proptest`](mod@proptest) strategies generating whole tries, for fuzzing proof and update
+//! logic starting from valid states.
+//!
+//! Each generated [`ArbitraryTrie`] holds the leaves of the trie along with its root, which is
+//! computed by the [`ReferenceTrie`], independently from the [`HashBuilder`](crate::HashBuilder).
+
+use crate::{reference::ReferenceTrie, Nibbles};
+use alloc::collections::BTreeMap;
+use alloy_primitives::B256;
+use proptest::{
+    collection::{btree_map, vec, SizeRange},
+    prelude::*,
+};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A trie generated by the strategies of this module.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ArbitraryTrie {
+    /// The leaves of the trie, keyed by their full path of 64 nibbles.
+    pub leaves: BTreeMap<Nibbles, Vec<u8>>,
+    /// The root of the trie.
+    pub root: B256,
+}
+
+impl ArbitraryTrie {
+    /// Creates a trie from the given leaves, computing its root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value is empty, or if a key is a prefix of another key.
+    pub fn new(leaves: BTreeMap<Nibbles, Vec<u8>>) -> Self {
+        let root = leaves.clone().into_iter().collect::<ReferenceTrie>().root();
+        Self { leaves, root }
+    }
+}
+
+impl Arbitrary for ArbitraryTrie {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        prop_oneof![trie(0..256), clustered_trie(0..64)].boxed()
+    }
+}
+
+/// Returns a strategy generating tries with uniformly random 32-byte keys, as in tries keyed by
+/// hashes.
+pub fn trie(size: impl Into<SizeRange>) -> impl Strategy<Value = ArbitraryTrie> {
+    btree_map(any::<B256>().prop_map(Nibbles::unpack), value(), size).prop_map(ArbitraryTrie::new)
+}
+
+/// Returns a strategy generating tries whose 32-byte keys are made of the nibbles `0..4` only, so
+/// that keys share long prefixes and the tries contain deep extension and branch nodes.
+pub fn clustered_trie(size: impl Into<SizeRange>) -> impl Strategy<Value = ArbitraryTrie> {
+    let key = vec(0u8..4, 64).prop_map(Nibbles::from_nibbles_unchecked);
+    btree_map(key, value(), size).prop_map(ArbitraryTrie::new)
+}
+
+/// Returns a strategy generating leaf values, both shorter and longer than a hash, so that nodes
+/// are both encoded in place and referenced by hash.
+fn value() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 1..=40)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashBuilder, EMPTY_ROOT_HASH};
+
+    fn hash_builder_root(trie: &ArbitraryTrie) -> B256 {
+        let mut hb = HashBuilder::default();
+        for (key, value) in &trie.leaves {
+            hb.add_leaf(key.clone(), value);
+        }
+        hb.root()
+    }
+
+    #[test]
+    fn empty_trie() {
+        assert_eq!(ArbitraryTrie::new(BTreeMap::new()).root, EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no proptest")]
+    fn root_matches_hash_builder() {
+        proptest!(|(trie: ArbitraryTrie)| {
+            prop_assert_eq!(trie.root, hash_builder_root(&trie));
+            prop_assert_eq!(
+                trie.root,
+                crate::triehash_trie_root(trie.leaves.iter().map(|(key, value)| (key.pack(), value)))
+            );
+        });
+    }
+}
@@ -0,0 +1,276 @@
+//! Aggregated branch node changes of the account trie and the storage tries.
+//!
+//! [`TrieUpdates`] is the output of a state root computation that a persistence layer applies to
+//! its stored branch nodes. Updates of consecutive blocks can be combined with
+//! [`TrieUpdates::extend`], with the changes of the later block taking precedence.
+
+use crate::{hash_builder::HashBuilder, BranchNodeCompact, HashMap, Nibbles, TrieHasher};
+use alloy_primitives::{map::HashSet, B256};
+
+/// The branch node changes of the account trie and of the storage tries.
+///
+/// Every path is either updated or removed, never both.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrieUpdates {
+    /// Updated branch nodes of the account trie.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::map_as_seq"))]
+    pub account_nodes: HashMap<Nibbles, BranchNodeCompact>,
+    /// Paths of the removed branch nodes of the account trie.
+    pub removed_nodes: HashSet<Nibbles>,
+    /// The storage trie changes, keyed by hashed address.
+    pub storage_tries: HashMap<B256, StorageTrieUpdates>,
+}
+
+impl TrieUpdates {
+    /// Returns `true` if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.account_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.storage_tries.values().all(StorageTrieUpdates::is_empty)
+    }
+
+    /// Returns the total number of updated and removed branch nodes, including storage tries.
+    pub fn len(&self) -> usize {
+        self.account_nodes.len()
+            + self.removed_nodes.len()
+            + self.storage_tries.values().map(StorageTrieUpdates::len).sum::<usize>()
+    }
+
+    /// Records an updated branch node of the account trie.
+    pub fn insert_account_node(&mut self, path: Nibbles, node: BranchNodeCompact) {
+        self.removed_nodes.remove(&path);
+        self.account_nodes.insert(path, node);
+    }
+
+    /// Records a removed branch node of the account trie.
+    pub fn remove_account_node(&mut self, path: Nibbles) {
+        self.account_nodes.remove(&path);
+        self.removed_nodes.insert(path);
+    }
+
+    /// Records the changes of the storage trie of the given account, on top of the existing ones.
+    pub fn insert_storage_updates(&mut self, hashed_address: B256, updates: StorageTrieUpdates) {
+        self.storage_tries.entry(hashed_address).or_default().extend(updates);
+    }
+
+    /// Records the account trie changes of a finished [`HashBuilder`], and the paths of stored
+    /// branch nodes that are no longer valid, e.g. from
+    /// [`TrieWalker::split`](crate::walker::TrieWalker::split).
+    ///
+    /// The builder must have updates enabled with [`HashBuilder::with_updates`].
+    pub fn finalize<H: TrieHasher>(
+        &mut self,
+        hash_builder: HashBuilder<H>,
+        removed_keys: impl IntoIterator<Item = Nibbles>,
+    ) {
+        let (updates, removals) = split_hash_builder(hash_builder, removed_keys);
+        removals.into_iter().for_each(|path| self.remove_account_node(path));
+        updates.into_iter().for_each(|(path, node)| self.insert_account_node(path, node));
+    }
+
+    /// Applies the changes of a later computation on top of these ones.
+    pub fn extend(&mut self, other: Self) {
+        other.removed_nodes.into_iter().for_each(|path| self.remove_account_node(path));
+        for (path, node) in other.account_nodes {
+            self.insert_account_node(path, node);
+        }
+        for (hashed_address, updates) in other.storage_tries {
+            self.insert_storage_updates(hashed_address, updates);
+        }
+    }
+
+    /// Applies the changes of a later computation on top of these ones, cloning them.
+    pub fn extend_ref(&mut self, other: &Self) {
+        other.removed_nodes.iter().for_each(|path| self.remove_account_node(path.clone()));
+        for (path, node) in &other.account_nodes {
+            self.insert_account_node(path.clone(), node.clone());
+        }
+        for (hashed_address, updates) in &other.storage_tries {
+            self.storage_tries.entry(*hashed_address).or_default().extend_ref(updates);
+        }
+    }
+
+    /// Returns these changes followed by the changes of a later computation. See
+    /// [`TrieUpdates::extend`].
+    pub fn merge(mut self, other: Self) -> Self {
+        self.extend(other);
+        self
+    }
+}
+
+/// The branch node changes of a single storage trie.
+///
+/// Every path is either updated or removed, never both.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageTrieUpdates {
+    /// Whether all stored branch nodes of the trie were deleted, e.g. because the account was
+    /// destroyed. The updated nodes are written after the deletion.
+    pub is_deleted: bool,
+    /// Updated branch nodes of the storage trie.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::map_as_seq"))]
+    pub storage_nodes: HashMap<Nibbles, BranchNodeCompact>,
+    /// Paths of the removed branch nodes of the storage trie.
+    pub removed_nodes: HashSet<Nibbles>,
+}
+
+impl StorageTrieUpdates {
+    /// Creates the changes of a storage trie whose stored branch nodes were all deleted.
+    pub fn deleted() -> Self {
+        Self { is_deleted: true, ..Default::default() }
+    }
+
+    /// Creates the changes of a storage trie from a finished [`HashBuilder`], and the paths of
+    /// stored branch nodes that are no longer valid. See [`TrieUpdates::finalize`].
+    pub fn from_hash_builder<H: TrieHasher>(
+        hash_builder: HashBuilder<H>,
+        removed_keys: impl IntoIterator<Item = Nibbles>,
+    ) -> Self {
+        let (storage_nodes, removed_nodes) = split_hash_builder(hash_builder, removed_keys);
+        Self { is_deleted: false, storage_nodes, removed_nodes }
+    }
+
+    /// Returns `true` if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        !self.is_deleted && self.storage_nodes.is_empty() && self.removed_nodes.is_empty()
+    }
+
+    /// Returns the number of updated and removed branch nodes.
+    pub fn len(&self) -> usize {
+        self.storage_nodes.len() + self.removed_nodes.len()
+    }
+
+    /// Records an updated branch node.
+    pub fn insert_node(&mut self, path: Nibbles, node: BranchNodeCompact) {
+        self.removed_nodes.remove(&path);
+        self.storage_nodes.insert(path, node);
+    }
+
+    /// Records a removed branch node.
+    pub fn remove_node(&mut self, path: Nibbles) {
+        self.storage_nodes.remove(&path);
+        self.removed_nodes.insert(path);
+    }
+
+    /// Applies the changes of a later computation on top of these ones. A deletion discards all
+    /// previous changes.
+    pub fn extend(&mut self, other: Self) {
+        if other.is_deleted {
+            *self = other;
+            return;
+        }
+        other.removed_nodes.into_iter().for_each(|path| self.remove_node(path));
+        other.storage_nodes.into_iter().for_each(|(path, node)| self.insert_node(path, node));
+    }
+
+    /// Applies the changes of a later computation on top of these ones, cloning them.
+    pub fn extend_ref(&mut self, other: &Self) {
+        if other.is_deleted {
+            self.clone_from(other);
+            return;
+        }
+        other.removed_nodes.iter().for_each(|path| self.remove_node(path.clone()));
+        for (path, node) in &other.storage_nodes {
+            self.insert_node(path.clone(), node.clone());
+        }
+    }
+}
+
+fn split_hash_builder<H: TrieHasher>(
+    mut hash_builder: HashBuilder<H>,
+    removed_keys: impl IntoIterator<Item = Nibbles>,
+) -> (HashMap<Nibbles, BranchNodeCompact>, HashSet<Nibbles>) {
+    hash_builder.add_removed_branch_nodes(removed_keys);
+    let (_, updates, removals) = hash_builder.split_with_removals();
+    (updates, removals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrieMask;
+
+    fn node(hash_mask: u16) -> BranchNodeCompact {
+        BranchNodeCompact::new(
+            0b11,
+            0,
+            hash_mask,
+            vec![B256::ZERO; hash_mask.count_ones() as _],
+            None,
+        )
+    }
+
+    #[test]
+    fn extend_later_changes_take_precedence() {
+        let a = Nibbles::from_nibbles([0x1]);
+        let b = Nibbles::from_nibbles([0x2]);
+
+        let mut updates = TrieUpdates::default();
+        updates.insert_account_node(a.clone(), node(0));
+        updates.remove_account_node(b.clone());
+
+        let mut later = TrieUpdates::default();
+        later.remove_account_node(a.clone());
+        later.insert_account_node(b.clone(), node(0b1));
+
+        let merged = updates.clone().merge(later.clone());
+        assert_eq!(merged.removed_nodes, HashSet::from_iter([a]));
+        assert_eq!(merged.account_nodes, HashMap::from_iter([(b, node(0b1))]));
+
+        updates.extend_ref(&later);
+        assert_eq!(updates, merged);
+        assert_eq!(updates.len(), 2);
+    }
+
+    #[test]
+    fn storage_deletion_discards_previous_changes() {
+        let address = B256::with_last_byte(1);
+        let path = Nibbles::from_nibbles([0x3]);
+
+        let mut updates = TrieUpdates::default();
+        let mut storage = StorageTrieUpdates::default();
+        storage.insert_node(path.clone(), node(0));
+        updates.insert_storage_updates(address, storage);
+
+        updates.insert_storage_updates(address, StorageTrieUpdates::deleted());
+        assert_eq!(updates.storage_tries[&address], StorageTrieUpdates::deleted());
+        assert!(!updates.is_empty());
+
+        let mut storage = StorageTrieUpdates::default();
+        storage.remove_node(path.clone());
+        updates.insert_storage_updates(address, storage);
+        let storage = &updates.storage_tries[&address];
+        assert!(storage.is_deleted);
+        assert_eq!(storage.removed_nodes, HashSet::from_iter([path]));
+    }
+
+    #[test]
+    fn finalize_hash_builder() {
+        let mut hb = HashBuilder::default().with_updates(true);
+        for key in 0..=0xffu8 {
+            hb.add_leaf(Nibbles::unpack([key, 0]), &[0xab; 32]);
+        }
+        hb.root();
+
+        let stale = Nibbles::from_nibbles([0x5, 0x5]);
+        let mut updates = TrieUpdates::default();
+        updates.finalize(hb, [stale.clone()]);
+        assert_eq!(updates.account_nodes.len(), 1);
+        assert_eq!(updates.account_nodes[&Nibbles::default()].state_mask, TrieMask::new(0xffff));
+        assert_eq!(updates.removed_nodes.len(), 1);
+        assert!(updates.removed_nodes.contains(&stale));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut updates = TrieUpdates::default();
+        updates.insert_account_node(Nibbles::from_nibbles([0x1]), node(0b1));
+        updates.remove_account_node(Nibbles::from_nibbles([0x2]));
+        updates.insert_storage_updates(B256::with_last_byte(1), StorageTrieUpdates::deleted());
+
+        let json = serde_json::to_string(&updates).unwrap();
+        assert_eq!(serde_json::from_str::<TrieUpdates>(&json).unwrap(), updates);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::TrieAccount;
+use alloy_primitives::{Bytes, U256};
+use alloy_rlp::{Decodable, Encodable};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// A typed value stored in the leaves of a trie.
+///
+/// The trie stores leaf values as opaque bytes. This trait maps a typed value to the bytes of its
+/// leaf and back, so that the root and proof helpers, such as
+/// [`root::trie_root_values`](crate::root::trie_root_values) and
+/// [`verify_value_proof`](crate::proof::verify_value_proof), can be used with typed values:
+/// accounts in the state trie are [`TrieAccount`]s, storage values are [`U256`]s, and the
+/// transactions and receipts of a block are [`TypedEnvelope`]s.
+pub trait TrieValue: Sized {
+    /// Appends the leaf value of `self` to the buffer.
+    fn encode_value(&self, out: &mut Vec<u8>);
+
+    /// Decodes the value from the bytes of its leaf, which must be consumed entirely.
+    fn decode_value(value: &[u8]) -> alloy_rlp::Result<Self>;
+
+    /// Returns the leaf value of `self`.
+    fn to_value(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_value(&mut out);
+        out
+    }
+}
+
+impl TrieValue for TrieAccount {
+    fn encode_value(&self, out: &mut Vec<u8>) {
+        self.encode(out);
+    }
+
+    fn decode_value(value: &[u8]) -> alloy_rlp::Result<Self> {
+        decode_exact(value)
+    }
+}
+
+/// Storage values are stored as RLP-encoded integers. Note that storage tries don't contain zero
+/// values, which are encoded as the empty string.
+impl TrieValue for U256 {
+    fn encode_value(&self, out: &mut Vec<u8>) {
+        self.encode(out);
+    }
+
+    fn decode_value(value: &[u8]) -> alloy_rlp::Result<Self> {
+        decode_exact(value)
+    }
+}
+
+/// Raw leaf values, which are stored as they are.
+impl TrieValue for Bytes {
+    fn encode_value(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode_value(value: &[u8]) -> alloy_rlp::Result<Self> {
+        Ok(Self::copy_from_slice(value))
+    }
+}
+
+/// An [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) envelope of a transaction or receipt,
+/// as stored in the transactions and receipts tries of a block.
+///
+/// Legacy items, with type `0`, are stored as `rlp(payload)`, while typed items are stored as
+/// `type || rlp(payload)`. See also
+/// [`ordered_trie_root_typed`](crate::root::ordered_trie_root_typed).
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct TypedEnvelope<T> {
+    /// The transaction type, `0` for legacy items.
+    pub ty: u8,
+    /// The RLP encodable fields of the transaction or receipt.
+    pub payload: T,
+}
+
+impl<T> TypedEnvelope<T> {
+    /// Creates the envelope of the payload with the given type.
+    pub const fn new(ty: u8, payload: T) -> Self {
+        Self { ty, payload }
+    }
+
+    /// Returns `true` if this is a legacy item, without a type prefix.
+    pub const fn is_legacy(&self) -> bool {
+        self.ty == 0
+    }
+}
+
+impl<T> From<(u8, T)> for TypedEnvelope<T> {
+    fn from((ty, payload): (u8, T)) -> Self {
+        Self { ty, payload }
+    }
+}
+
+impl<T: Encodable + Decodable> TrieValue for TypedEnvelope<T> {
+    fn encode_value(&self, out: &mut Vec<u8>) {
+        if !self.is_legacy() {
+            out.push(self.ty);
+        }
+        self.payload.encode(out);
+    }
+
+    fn decode_value(value: &[u8]) -> alloy_rlp::Result<Self> {
+        match value.first() {
+            None => Err(alloy_rlp::Error::InputTooShort),
+            // Legacy payloads are RLP lists, while the types of typed items are below 0x80.
+            Some(&first) if first >= alloy_rlp::EMPTY_LIST_CODE => {
+                decode_exact(value).map(|payload| Self::new(0, payload))
+            }
+            Some(&ty @ 1..=0x7f) => decode_exact(&value[1..]).map(|payload| Self::new(ty, payload)),
+            Some(_) => Err(alloy_rlp::Error::Custom("invalid transaction type")),
+        }
+    }
+}
+
+/// Decodes the value, failing if the buffer is not consumed entirely.
+fn decode_exact<T: Decodable>(mut value: &[u8]) -> alloy_rlp::Result<T> {
+    let decoded = T::decode(&mut value)?;
+    if !value.is_empty() {
+        return Err(alloy_rlp::Error::UnexpectedLength);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    fn roundtrip<T: TrieValue + PartialEq + core::fmt::Debug>(value: T) -> Vec<u8> {
+        let encoded = value.to_value();
+        assert_eq!(T::decode_value(&encoded), Ok(value));
+        encoded
+    }
+
+    #[test]
+    fn roundtrips() {
+        assert_eq!(roundtrip(TrieAccount::EMPTY), TrieAccount::EMPTY_RLP);
+        assert_eq!(roundtrip(U256::from(0x1234)), [0x82, 0x12, 0x34]);
+        assert_eq!(roundtrip(Bytes::from_static(&[0xc0, 1])), [0xc0, 1]);
+
+        let payload = Payload(1, B256::with_last_byte(1));
+        assert_eq!(roundtrip(TypedEnvelope::new(0, payload))[0], 0xe2);
+        assert_eq!(roundtrip(TypedEnvelope::new(2, payload))[..2], [2, 0xe2]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(U256::decode_value(&[0x01, 0x02]), Err(alloy_rlp::Error::UnexpectedLength));
+        assert!(TrieAccount::decode_value(&[0xc0]).is_err());
+        assert_eq!(
+            TypedEnvelope::<Payload>::decode_value(&[]),
+            Err(alloy_rlp::Error::InputTooShort)
+        );
+        assert_eq!(
+            TypedEnvelope::<Payload>::decode_value(&[0x80]),
+            Err(alloy_rlp::Error::Custom("invalid transaction type"))
+        );
+    }
+
+    #[test]
+    fn typed_roots_and_proofs() {
+        use crate::{
+            proof::{verify_value_proof, ProofRetainer},
+            root::{
+                ordered_trie_root_typed, ordered_trie_root_values, storage_root, trie_root_values,
+            },
+            HashBuilder, KeccakKeyHasher, Nibbles,
+        };
+        use alloy_primitives::keccak256;
+
+        let slots =
+            (1..20u64).map(|i| (B256::with_last_byte(i as u8), U256::from(i))).collect::<Vec<_>>();
+        let root = trie_root_values::<KeccakKeyHasher, _, _, _>(slots.clone());
+        assert_eq!(root, storage_root(slots.iter().map(|(slot, value)| (keccak256(slot), *value))));
+
+        let items =
+            (0..200u64).map(|i| ((i % 3) as u8, Payload(i, B256::ZERO))).collect::<Vec<_>>();
+        assert_eq!(
+            ordered_trie_root_values(items.iter().copied().map(TypedEnvelope::from)),
+            ordered_trie_root_typed(items)
+        );
+
+        let (slot, value) = slots[3];
+        let key = Nibbles::unpack(keccak256(slot));
+        let mut leaves = slots
+            .iter()
+            .map(|(slot, value)| (Nibbles::unpack(keccak256(slot)), value.to_value()))
+            .collect::<Vec<_>>();
+        leaves.sort_unstable();
+        let mut hb =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::from_iter([key.clone()]));
+        leaves.iter().for_each(|(key, value)| hb.add_leaf(key.clone(), value));
+        assert_eq!(hb.root(), root);
+        let proof = hb.take_proof_nodes().into_nodes_sorted();
+        let proof = proof.iter().map(|(_, node)| node);
+        assert_eq!(verify_value_proof(root, key.clone(), Some(&value), proof.clone()), Ok(()));
+        assert!(verify_value_proof(root, key, Some(&U256::ZERO), proof).is_err());
+    }
+
+    #[derive(
+        Clone, Copy, PartialEq, Eq, Debug, alloy_rlp::RlpEncodable, alloy_rlp::RlpDecodable,
+    )]
+    struct Payload(u64, B256);
+}
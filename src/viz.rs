@@ -0,0 +1,303 @@
+//! Rendering of trie nodes as ASCII trees and Graphviz DOT graphs, for debugging.
+//!
+//! A [`TrieNodeTree`] is built from decoded nodes keyed by path, such as the nodes of a proof, and
+//! is rendered as an ASCII tree by its [`Display`](fmt::Display) implementation or as a Graphviz
+//! graph by [`TrieNodeTree::to_dot`]. Nodes are labeled with their path and contents, along with
+//! their hash if they are referenced by hash. Children embedded in their parent are rendered
+//! inline, while referenced nodes that are missing from the set, or whose hash does not match the
+//! reference, are marked as such.
+
+use crate::{
+    nodes::{RlpNode, TrieNode},
+    proof::ProofNodes,
+    Nibbles,
+};
+use alloc::{collections::BTreeMap, string::String};
+use alloy_primitives::{hex, keccak256, B256};
+use alloy_rlp::Decodable;
+use core::fmt::{self, Write};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The number of leading bytes of hashes and values that are rendered.
+const SHORT_LEN: usize = 4;
+
+/// A set of trie nodes keyed by path, rendered as a tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrieNodeTree {
+    nodes: BTreeMap<Nibbles, TrieNode>,
+}
+
+impl FromIterator<(Nibbles, TrieNode)> for TrieNodeTree {
+    fn from_iter<T: IntoIterator<Item = (Nibbles, TrieNode)>>(iter: T) -> Self {
+        Self { nodes: BTreeMap::from_iter(iter) }
+    }
+}
+
+impl TrieNodeTree {
+    /// Decodes the given proof nodes.
+    pub fn from_proof_nodes(proof_nodes: &ProofNodes) -> alloy_rlp::Result<Self> {
+        proof_nodes
+            .iter()
+            .map(|(path, node)| Ok((path.clone(), TrieNode::decode(&mut &node[..])?)))
+            .collect()
+    }
+
+    /// Renders the nodes as a Graphviz DOT graph.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph trie {\n    node [shape=box, fontname=monospace];\n");
+        let mut next_id = 0;
+        for entry in self.entries() {
+            entry.write_dot(&mut dot, &mut next_id);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the nodes to render from, which are those not referenced by any other node of the
+    /// set, resolved into entries.
+    fn entries(&self) -> Vec<Entry> {
+        let mut referenced = BTreeMap::new();
+        for (path, node) in &self.nodes {
+            for (_, child_path, _) in children(path, node) {
+                referenced.insert(child_path, ());
+            }
+        }
+        self.nodes
+            .iter()
+            .filter(|(path, _)| !referenced.contains_key(*path))
+            .map(|(path, node)| self.entry(path.clone(), node, Reference::Root))
+            .collect()
+    }
+
+    fn entry(&self, path: Nibbles, node: &TrieNode, reference: Reference) -> Entry {
+        let mut label = describe(node);
+        match reference {
+            Reference::Root => {
+                if let Some(hash) = hash(node) {
+                    let _ = write!(label, " hash {}", short_hex(hash.as_slice()));
+                }
+            }
+            Reference::Inline => label.push_str(" (inline)"),
+            Reference::Hash(expected) => {
+                let _ = write!(label, " hash {}", short_hex(expected.as_slice()));
+                if hash(node) != Some(expected) {
+                    label.push_str(" (hash mismatch)");
+                }
+            }
+        }
+
+        let children = children(&path, node)
+            .into_iter()
+            .map(|(edge, child_path, child)| {
+                let child = match child.as_hash() {
+                    Some(hash) => match self.nodes.get(&child_path) {
+                        Some(node) => self.entry(child_path, node, Reference::Hash(hash)),
+                        None => Entry {
+                            label: format!(
+                                "{}: missing node hash {}",
+                                path_hex(&child_path),
+                                short_hex(hash.as_slice())
+                            ),
+                            children: Vec::new(),
+                        },
+                    },
+                    None => match TrieNode::decode(&mut &child[..]) {
+                        Ok(node) => self.entry(child_path, &node, Reference::Inline),
+                        Err(err) => Entry {
+                            label: format!("{}: invalid node: {err}", path_hex(&child_path)),
+                            children: Vec::new(),
+                        },
+                    },
+                };
+                (edge, child)
+            })
+            .collect();
+
+        Entry { label: format!("{}: {label}", path_hex(&path)), children }
+    }
+}
+
+impl fmt::Display for TrieNodeTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in self.entries() {
+            writeln!(f, "{}", entry.label)?;
+            entry.write_children(f, "")?;
+        }
+        Ok(())
+    }
+}
+
+/// How a node is referenced by its parent.
+#[derive(Clone, Copy)]
+enum Reference {
+    /// The node has no parent in the set.
+    Root,
+    /// The node is embedded in its parent.
+    Inline,
+    /// The node is referenced by the given hash.
+    Hash(B256),
+}
+
+/// A resolved node to render.
+struct Entry {
+    label: String,
+    /// The children along with the label of the edge to them.
+    children: Vec<(String, Entry)>,
+}
+
+impl Entry {
+    fn write_children(&self, f: &mut fmt::Formatter<'_>, indent: &str) -> fmt::Result {
+        for (i, (edge, child)) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            let (branch, continuation) =
+                if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            writeln!(f, "{indent}{branch}[{edge}] {}", child.label)?;
+            child.write_children(f, &format!("{indent}{continuation}"))?;
+        }
+        Ok(())
+    }
+
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let _ = writeln!(dot, "    n{id} [label=\"{}\"];", self.label.replace('"', "\\\""));
+        for (edge, child) in &self.children {
+            let child_id = child.write_dot(dot, next_id);
+            let _ = writeln!(dot, "    n{id} -> n{child_id} [label=\"{edge}\"];");
+        }
+        id
+    }
+}
+
+/// Returns the children of the node, along with the label of the edge to them and their path.
+fn children<'a>(path: &Nibbles, node: &'a TrieNode) -> Vec<(String, Nibbles, &'a RlpNode)> {
+    match node {
+        TrieNode::Branch(branch) => branch
+            .children()
+            .map(|(nibble, child)| {
+                let mut child_path = path.clone();
+                child_path.push(nibble);
+                (format!("{nibble:x}"), child_path, child)
+            })
+            .collect(),
+        TrieNode::Extension(extension) => {
+            vec![(key_hex(&extension.key), path.join(&extension.key), &extension.child)]
+        }
+        TrieNode::Leaf(_) | TrieNode::EmptyRoot => Vec::new(),
+    }
+}
+
+/// Describes the node, without its children.
+fn describe(node: &TrieNode) -> String {
+    match node {
+        TrieNode::EmptyRoot => String::from("empty root"),
+        TrieNode::Branch(branch) => {
+            let nibbles = branch
+                .children()
+                .map(|(nibble, _)| char::from_digit(nibble as u32, 16).unwrap())
+                .collect::<String>();
+            format!("branch children {nibbles}")
+        }
+        TrieNode::Extension(extension) => format!("extension key {}", key_hex(&extension.key)),
+        TrieNode::Leaf(leaf) => format!(
+            "leaf key {} value {} ({} byte{})",
+            key_hex(&leaf.key),
+            short_hex(&leaf.value),
+            leaf.value.len(),
+            if leaf.value.len() == 1 { "" } else { "s" }
+        ),
+    }
+}
+
+/// Returns the hash of the node, or [`None`] if it is small enough to be embedded in its parent.
+fn hash(node: &TrieNode) -> Option<B256> {
+    let mut rlp = Vec::new();
+    node.rlp(&mut rlp);
+    (rlp.len() >= B256::len_bytes()).then(|| keccak256(&rlp))
+}
+
+/// Renders the path as hex digits, or `root` if it is empty.
+fn path_hex(path: &Nibbles) -> String {
+    if path.is_empty() {
+        return String::from("root");
+    }
+    key_hex(path)
+}
+
+/// Renders the key as hex digits, or `-` if it is empty.
+fn key_hex(key: &Nibbles) -> String {
+    if key.is_empty() {
+        return String::from("-");
+    }
+    key.iter().map(|nibble| char::from_digit(*nibble as u32, 16).unwrap()).collect()
+}
+
+/// Renders the first bytes of the data as hex.
+fn short_hex(data: &[u8]) -> String {
+    let mut short = hex::encode_prefixed(&data[..data.len().min(SHORT_LEN)]);
+    if data.len() > SHORT_LEN {
+        short.push('…');
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloc::string::ToString;
+
+    fn proof() -> ProofNodes {
+        let mut hb = HashBuilder::default().with_proof_retainer(ProofRetainer::from_iter([
+            Nibbles::from_nibbles([0x1, 0x2, 0x3, 0x4]),
+        ]));
+        hb.add_leaf(Nibbles::from_nibbles([0x1, 0x2, 0x3, 0x4]), &[0xaa; 40]);
+        hb.add_leaf(Nibbles::from_nibbles([0x1, 0x2, 0x5, 0x6]), &[0xbb; 40]);
+        hb.add_leaf(Nibbles::from_nibbles([0x1, 0x3, 0x0, 0x0]), &[0xcc; 1]);
+        hb.add_leaf(Nibbles::from_nibbles([0x1, 0x3, 0x0, 0x1]), &[0xdd; 1]);
+        hb.root();
+        hb.take_proof_nodes()
+    }
+
+    #[test]
+    fn ascii_tree() {
+        let tree = TrieNodeTree::from_proof_nodes(&proof()).unwrap();
+        let expected = "\
+root: extension key 1 hash 0x765f8d29…
+└── [1] 1: branch children 23 hash 0xa0ca10c6…
+    ├── [2] 12: branch children 35 hash 0xd6126222…
+    │   ├── [3] 123: leaf key 4 value 0xaaaaaaaa… (40 bytes) hash 0x9bfae636…
+    │   └── [5] 125: missing node hash 0xebb0584d…
+    └── [3] 13: extension key 0 (inline)
+        └── [0] 130: branch children 01 (inline)
+            ├── [0] 1300: leaf key - value 0xcc (1 byte) (inline)
+            └── [1] 1301: leaf key - value 0xdd (1 byte) (inline)
+";
+        assert_eq!(tree.to_string(), expected);
+    }
+
+    #[test]
+    fn dot() {
+        let tree = TrieNodeTree::from_proof_nodes(&proof()).unwrap();
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph trie {"));
+        assert!(dot.contains("n0 -> n1 [label=\"1\"];"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn hash_mismatch() {
+        let mut proof = proof();
+        let (path, _) = proof.iter().find(|(path, _)| path.len() == 3).unwrap();
+        let path = path.clone();
+        let leaf = TrieNode::Leaf(crate::nodes::LeafNode::new(
+            Nibbles::from_nibbles([0x4]),
+            vec![0xee; 40],
+        ));
+        proof.insert(path, alloy_rlp::encode(leaf).into());
+        let rendered = TrieNodeTree::from_proof_nodes(&proof).unwrap().to_string();
+        assert!(rendered.contains("(hash mismatch)"), "{rendered}");
+    }
+}
@@ -0,0 +1,154 @@
+use super::{check_stored_node, CursorSubNode, SiblingStep, TrieWalker};
+use crate::{cursor::AsyncTrieCursor, prefix_set::PrefixSet, BranchNodeCompact, Nibbles};
+use tracing::trace;
+
+impl<C: AsyncTrieCursor> TrieWalker<C> {
+    /// Creates a new walker positioned at the root of the trie, over an
+    /// [`AsyncTrieCursor`]. See [`TrieWalker::new`].
+    pub async fn new_async(cursor: C, changes: PrefixSet) -> Result<Self, C::Error> {
+        let mut this = Self {
+            cursor,
+            stack: vec![CursorSubNode::default()],
+            can_skip_current_node: false,
+            changes,
+            removed_keys: None,
+        };
+        if let Some((key, node)) = this.node_async(true).await? {
+            this.stack[0] = CursorSubNode::new(key, Some(node));
+        }
+        this.update_skip_node();
+        Ok(this)
+    }
+
+    /// Advances the walker to the next position, awaiting the cursor. See
+    /// [`TrieWalker::advance`].
+    pub async fn advance_async(&mut self) -> Result<Option<Nibbles>, C::Error> {
+        if let Some(last) = self.stack.last() {
+            if !self.can_skip_current_node && self.children_are_in_trie() {
+                // Descend into the current node, or into its first child.
+                match last.nibble() {
+                    -1 => self.move_to_next_sibling_async(true).await?,
+                    _ => self.consume_node_async().await?,
+                }
+            } else {
+                self.move_to_next_sibling_async(false).await?;
+            }
+            self.update_skip_node();
+        }
+        trace!(target: "trie::walker", key = ?self.key(), can_skip = self.can_skip_current_node, "advanced");
+        Ok(self.key().cloned())
+    }
+
+    /// Seeks the stored node at the current position.
+    async fn node_async(
+        &mut self,
+        exact: bool,
+    ) -> Result<Option<(Nibbles, BranchNodeCompact)>, C::Error> {
+        let key = self.key().expect("key must exist").clone();
+        let entry =
+            if exact { self.cursor.seek_exact(key).await? } else { self.cursor.seek(key).await? };
+        check_stored_node(&entry);
+        Ok(entry)
+    }
+
+    /// Pushes the next stored node onto the stack. Unlike the blocking version, the walker loops
+    /// instead of recursing, as recursive futures would need to be boxed.
+    async fn consume_node_async(&mut self) -> Result<(), C::Error> {
+        loop {
+            let Some((key, node)) = self.node_async(false).await? else {
+                self.stack.clear();
+                return Ok(());
+            };
+
+            match self.push_node(key, node) {
+                None => {
+                    if self.next_sibling(false) == SiblingStep::Found {
+                        return Ok(());
+                    }
+                }
+                Some(invalidated) => {
+                    if invalidated {
+                        let key = self.cursor.current().await?;
+                        self.record_removed_key(key);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Moves to the next child of the current node, popping the nodes whose children have been
+    /// exhausted.
+    async fn move_to_next_sibling_async(
+        &mut self,
+        allow_root_to_child_nibble: bool,
+    ) -> Result<(), C::Error> {
+        match self.next_sibling(allow_root_to_child_nibble) {
+            SiblingStep::Found => Ok(()),
+            SiblingStep::Consume => self.consume_node_async().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::InMemoryTrieCursor;
+    use alloc::collections::BTreeMap;
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    /// Polls a future that never waits to completion.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(core::ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+        // SAFETY: The vtable functions do nothing with the null data pointer.
+        let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+        let mut future = pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is pending"),
+        }
+    }
+
+    #[test]
+    fn async_walk_matches_blocking_walk() {
+        let nodes: BTreeMap<_, _> = [
+            (&[0x5][..], BranchNodeCompact::new(0b1_0000_0101, 0b1_0000_0100, 0, vec![], None)),
+            (&[0x5, 0x2, 0xc], BranchNodeCompact::new(0b1000_0111, 0, 0, vec![], None)),
+            (&[0x5, 0x8], BranchNodeCompact::new(0b0110, 0b0100, 0, vec![], None)),
+            // Not a child of the node at [0x5], which has a stale tree mask.
+            (&[0x5, 0x0, 0x1], BranchNodeCompact::new(0b0110, 0, 0, vec![], None)),
+        ]
+        .into_iter()
+        .map(|(path, node)| (Nibbles::from_nibbles(path), node))
+        .collect();
+        let changes: PrefixSet = [Nibbles::from_nibbles([0x5, 0x8, 0x1])].into_iter().collect();
+
+        let mut walker = TrieWalker::new(InMemoryTrieCursor::new(&nodes), changes.clone())
+            .unwrap()
+            .with_deletions_retained(true);
+        let mut expected = vec![walker.key().cloned()];
+        while let Some(key) = walker.advance().unwrap() {
+            expected.push(Some(key));
+        }
+        let expected_removed = walker.split().1;
+
+        let mut walker = block_on(TrieWalker::new_async(InMemoryTrieCursor::new(&nodes), changes))
+            .unwrap()
+            .with_deletions_retained(true);
+        let mut keys = vec![walker.key().cloned()];
+        while let Some(key) = block_on(walker.advance_async()).unwrap() {
+            keys.push(Some(key));
+        }
+        assert_eq!(keys, expected);
+        assert_eq!(walker.split().1, expected_removed);
+    }
+}
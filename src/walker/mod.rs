@@ -0,0 +1,352 @@
+//! Walker over the stored branch nodes of a trie, skipping the subtries that did not change.
+//!
+//! When recomputing the root of a trie after a set of changes, only the subtries containing
+//! changed keys need to be re-hashed. The [`TrieWalker`] traverses the branch nodes stored by a
+//! previous computation (see [`HashBuilder::with_updates`](crate::HashBuilder::with_updates)) in
+//! key order, descending only into the nodes whose path is a prefix of a changed key, as given by
+//! a [`PrefixSet`]. The hashes of all other subtries are taken from the stored nodes and can be
+//! fed into the [`HashBuilder`](crate::HashBuilder) with
+//! [`add_branch`](crate::HashBuilder::add_branch), while the leaves in between are read from the
+//! hashed state.
+//!
+//! The stored nodes on the changed paths are no longer valid. They are collected by the walker, if
+//! [retained](TrieWalker::with_deletions_retained), to be removed from the database, and replaced
+//! by the updated nodes produced by the [`HashBuilder`](crate::HashBuilder).
+
+use crate::{cursor::TrieCursor, prefix_set::PrefixSet, BranchNodeCompact, Nibbles};
+use alloy_primitives::{map::HashSet, B256};
+use tracing::trace;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+mod subnode;
+pub use subnode::CursorSubNode;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+/// Walks the stored branch nodes of a trie in key order, skipping the subtries without changes.
+#[derive(Debug)]
+pub struct TrieWalker<C> {
+    /// The cursor over the stored branch nodes.
+    pub cursor: C,
+    /// The stack of nodes from the root to the current position.
+    pub stack: Vec<CursorSubNode>,
+    /// Whether the subtrie at the current position is unchanged, and its hash can be reused.
+    pub can_skip_current_node: bool,
+    /// The changed keys.
+    pub changes: PrefixSet,
+    /// The paths of the stored nodes that are no longer valid, if retained.
+    removed_keys: Option<HashSet<Nibbles>>,
+}
+
+impl<C> TrieWalker<C> {
+    /// Creates a new walker resuming from the given stack.
+    pub fn from_stack(cursor: C, stack: Vec<CursorSubNode>, changes: PrefixSet) -> Self {
+        let mut this =
+            Self { cursor, stack, can_skip_current_node: false, changes, removed_keys: None };
+        this.update_skip_node();
+        this
+    }
+
+    /// Sets whether the paths of the stored nodes that are no longer valid are retained.
+    pub fn with_deletions_retained(mut self, retained: bool) -> Self {
+        if retained {
+            self.removed_keys = Some(HashSet::default());
+        }
+        self
+    }
+
+    /// Splits the walker into its stack and the paths of the stored nodes that are no longer
+    /// valid.
+    pub fn split(mut self) -> (Vec<CursorSubNode>, HashSet<Nibbles>) {
+        let keys = self.removed_keys.take();
+        (self.stack, keys.unwrap_or_default())
+    }
+
+    /// Returns the number of retained paths of stored nodes that are no longer valid.
+    pub fn removed_keys_len(&self) -> usize {
+        self.removed_keys.as_ref().map_or(0, |keys| keys.len())
+    }
+
+    /// Returns the current position, or [`None`] if the walk is finished.
+    pub fn key(&self) -> Option<&Nibbles> {
+        self.stack.last().map(|node| node.full_key())
+    }
+
+    /// Returns the hash of the subtrie at the current position, if known.
+    pub fn hash(&self) -> Option<B256> {
+        self.stack.last().and_then(|node| node.hash())
+    }
+
+    /// Returns `true` if the node at the current position is stored.
+    pub fn children_are_in_trie(&self) -> bool {
+        self.stack.last().is_some_and(|node| node.tree_flag())
+    }
+
+    /// Returns the first hashed key that is not covered by the subtries walked so far, or
+    /// [`None`] if the walk is finished.
+    ///
+    /// The leaves between the previous position and this key need to be read from the hashed
+    /// state.
+    pub fn next_unprocessed_key(&self) -> Option<B256> {
+        self.key()
+            .and_then(|key| {
+                if self.can_skip_current_node {
+                    key.increment().map(|key| crate::nibbles::pack(&key))
+                } else {
+                    Some(crate::nibbles::pack(key))
+                }
+            })
+            .map(|mut key| {
+                key.resize(32, 0);
+                B256::from_slice(&key)
+            })
+    }
+
+    fn update_skip_node(&mut self) {
+        self.can_skip_current_node = self
+            .stack
+            .last()
+            .is_some_and(|node| !self.changes.contains(node.full_key()) && node.hash_flag());
+    }
+}
+
+impl<C: TrieCursor> TrieWalker<C> {
+    /// Creates a new walker positioned at the root of the trie.
+    pub fn new(cursor: C, changes: PrefixSet) -> Result<Self, C::Error> {
+        let mut this = Self {
+            cursor,
+            stack: vec![CursorSubNode::default()],
+            can_skip_current_node: false,
+            changes,
+            removed_keys: None,
+        };
+        if let Some((key, node)) = this.node(true)? {
+            this.stack[0] = CursorSubNode::new(key, Some(node));
+        }
+        this.update_skip_node();
+        Ok(this)
+    }
+
+    /// Advances the walker to the next position, skipping the subtrie at the current position if
+    /// it is unchanged. Returns the new position, or [`None`] if the walk is finished.
+    pub fn advance(&mut self) -> Result<Option<Nibbles>, C::Error> {
+        if let Some(last) = self.stack.last() {
+            if !self.can_skip_current_node && self.children_are_in_trie() {
+                // Descend into the current node, or into its first child.
+                match last.nibble() {
+                    -1 => self.move_to_next_sibling(true)?,
+                    _ => self.consume_node()?,
+                }
+            } else {
+                self.move_to_next_sibling(false)?;
+            }
+            self.update_skip_node();
+        }
+        trace!(target: "trie::walker", key = ?self.key(), can_skip = self.can_skip_current_node, "advanced");
+        Ok(self.key().cloned())
+    }
+
+    /// Seeks the stored node at the current position.
+    fn node(&mut self, exact: bool) -> Result<Option<(Nibbles, BranchNodeCompact)>, C::Error> {
+        let key = self.key().expect("key must exist").clone();
+        let entry = if exact { self.cursor.seek_exact(key)? } else { self.cursor.seek(key)? };
+        check_stored_node(&entry);
+        Ok(entry)
+    }
+
+    /// Pushes the next stored node onto the stack.
+    fn consume_node(&mut self) -> Result<(), C::Error> {
+        let Some((key, node)) = self.node(false)? else {
+            self.stack.clear();
+            return Ok(());
+        };
+
+        match self.push_node(key, node) {
+            None => self.move_to_next_sibling(false),
+            Some(invalidated) => {
+                if invalidated {
+                    let key = self.cursor.current()?;
+                    self.record_removed_key(key);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Moves to the next child of the current node, popping the nodes whose children have been
+    /// exhausted.
+    fn move_to_next_sibling(&mut self, allow_root_to_child_nibble: bool) -> Result<(), C::Error> {
+        match self.next_sibling(allow_root_to_child_nibble) {
+            SiblingStep::Found => Ok(()),
+            SiblingStep::Consume => self.consume_node(),
+        }
+    }
+}
+
+/// The outcome of [`TrieWalker::next_sibling`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SiblingStep {
+    /// The walker is positioned at the next child, or the walk is finished.
+    Found,
+    /// The next child is not known, and the next stored node must be consumed.
+    Consume,
+}
+
+impl<C> TrieWalker<C> {
+    /// Pushes a stored node found by the cursor onto the stack.
+    ///
+    /// Returns [`None`] if the node is not a child of the current node, in which case the walker
+    /// must move to the next sibling. Otherwise returns whether the stored node was invalidated.
+    fn push_node(&mut self, key: Nibbles, node: BranchNodeCompact) -> Option<bool> {
+        // Keep the root node in sync with the position of the new node.
+        if !key.is_empty() && !self.stack.is_empty() {
+            self.stack[0].set_nibble(key[0] as i8);
+        }
+
+        // The tree mask of the parent may be stale, in which case the node found is not one of its
+        // children.
+        if let Some(subnode) = self.stack.last() {
+            if !key.starts_with(subnode.full_key()) {
+                return None;
+            }
+        }
+
+        let subnode = CursorSubNode::new(key, Some(node));
+        let nibble = subnode.nibble();
+        self.stack.push(subnode);
+        self.update_skip_node();
+
+        // The stored node is invalidated unless the hash of the whole node is reused.
+        Some(!self.can_skip_current_node || nibble != -1)
+    }
+
+    /// Records the path of an invalidated stored node, if deletions are retained.
+    fn record_removed_key(&mut self, key: Option<Nibbles>) {
+        if let Some((keys, key)) = self.removed_keys.as_mut().zip(key) {
+            keys.insert(key);
+        }
+    }
+
+    /// Moves to the next child of the current node without reading from the cursor, popping the
+    /// nodes whose children have been exhausted.
+    fn next_sibling(&mut self, mut allow_root_to_child_nibble: bool) -> SiblingStep {
+        loop {
+            let Some(subnode) = self.stack.last_mut() else { return SiblingStep::Found };
+
+            if subnode.nibble() >= 0xf || (subnode.nibble() < 0 && !allow_root_to_child_nibble) {
+                self.stack.pop();
+                allow_root_to_child_nibble = false;
+                continue;
+            }
+
+            subnode.inc_nibble();
+
+            if subnode.node.is_none() {
+                return SiblingStep::Consume;
+            }
+
+            // Find the next child that exists.
+            loop {
+                if subnode.state_flag() {
+                    return SiblingStep::Found;
+                }
+                if subnode.nibble() == 0xf {
+                    break;
+                }
+                subnode.inc_nibble();
+            }
+
+            self.stack.pop();
+            allow_root_to_child_nibble = false;
+        }
+    }
+}
+
+/// Asserts that a stored node found by the cursor has children.
+fn check_stored_node(entry: &Option<(Nibbles, BranchNodeCompact)>) {
+    if let Some((_, node)) = entry {
+        assert!(!node.state_mask.is_empty(), "stored branch nodes must have children");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::InMemoryTrieCursor;
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::b256;
+
+    fn nodes<const N: usize>(
+        nodes: [(&[u8], BranchNodeCompact); N],
+    ) -> BTreeMap<Nibbles, BranchNodeCompact> {
+        nodes.into_iter().map(|(path, node)| (Nibbles::from_nibbles(path), node)).collect()
+    }
+
+    #[test]
+    fn walk_nodes_with_common_prefix() {
+        let nodes = nodes([
+            (&[0x5], BranchNodeCompact::new(0b1_0000_0101, 0b1_0000_0100, 0, vec![], None)),
+            (&[0x5, 0x2, 0xc], BranchNodeCompact::new(0b1000_0111, 0, 0, vec![], None)),
+            (&[0x5, 0x8], BranchNodeCompact::new(0b0110, 0b0100, 0, vec![], None)),
+        ]);
+        let expected: [&[u8]; 9] = [
+            &[0x5, 0x0],
+            // The child at 0x2 of the first node is the node at [0x5, 0x2, 0xc], so it needs to be
+            // pushed before its children are walked.
+            &[0x5, 0x2],
+            &[0x5, 0x2, 0xc, 0x0],
+            &[0x5, 0x2, 0xc, 0x1],
+            &[0x5, 0x2, 0xc, 0x2],
+            &[0x5, 0x2, 0xc, 0x7],
+            &[0x5, 0x8],
+            &[0x5, 0x8, 0x1],
+            &[0x5, 0x8, 0x2],
+        ];
+
+        let mut walker =
+            TrieWalker::new(InMemoryTrieCursor::new(&nodes), PrefixSet::default()).unwrap();
+        assert_eq!(walker.key(), Some(&Nibbles::default()));
+        for expected in expected {
+            assert_eq!(walker.advance(), Ok(Some(Nibbles::from_nibbles(expected))));
+        }
+        assert_eq!(walker.advance(), Ok(None));
+    }
+
+    #[test]
+    fn skip_unchanged_subtries() {
+        let nodes = nodes([
+            (&[], BranchNodeCompact::new(0b10100, 0b00100, 0, vec![], Some(B256::repeat_byte(1)))),
+            (&[0x2], BranchNodeCompact::new(0b00010, 0, 0b00010, vec![B256::repeat_byte(2)], None)),
+        ]);
+        let mut cursor = InMemoryTrieCursor::new(&nodes);
+
+        // Without changes, the whole trie is skipped.
+        let mut walker = TrieWalker::new(&mut cursor, PrefixSet::default()).unwrap();
+        assert_eq!(walker.key(), Some(&Nibbles::default()));
+        assert!(walker.can_skip_current_node);
+        assert_eq!(walker.hash(), Some(B256::repeat_byte(1)));
+        assert_eq!(walker.advance(), Ok(None));
+
+        // A change outside of the stored subtries only invalidates the root.
+        let changes = [Nibbles::from_nibbles([0xf, 0x1])].into_iter().collect();
+        let mut walker =
+            TrieWalker::new(&mut cursor, changes).unwrap().with_deletions_retained(true);
+        assert!(!walker.can_skip_current_node);
+        assert_eq!(walker.advance(), Ok(Some(Nibbles::from_nibbles([0x2]))));
+        assert_eq!(walker.advance(), Ok(Some(Nibbles::from_nibbles([0x2, 0x1]))));
+        assert!(walker.can_skip_current_node);
+        assert_eq!(walker.hash(), Some(B256::repeat_byte(2)));
+        assert_eq!(
+            walker.next_unprocessed_key(),
+            Some(b256!("2200000000000000000000000000000000000000000000000000000000000000"))
+        );
+        assert_eq!(walker.advance(), Ok(Some(Nibbles::from_nibbles([0x4]))));
+        assert_eq!(walker.advance(), Ok(None));
+        let (stack, removed) = walker.split();
+        assert!(stack.is_empty());
+        assert_eq!(removed.len(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{nodes::CHILD_INDEX_RANGE, BranchNodeCompact, Nibbles};
+use alloy_primitives::B256;
+use core::fmt;
+
+/// A branch node on the stack of the [`TrieWalker`](super::TrieWalker), along with the child
+/// nibble the walker is currently positioned at.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CursorSubNode {
+    /// The path of the node.
+    pub key: Nibbles,
+    /// The current child nibble, or `-1` if the walker is positioned at the node itself.
+    nibble: i8,
+    /// The node, or [`None`] if it is not stored.
+    pub node: Option<BranchNodeCompact>,
+    /// The path of the node followed by the current child nibble.
+    full_key: Nibbles,
+}
+
+impl Default for CursorSubNode {
+    fn default() -> Self {
+        Self::new(Nibbles::default(), None)
+    }
+}
+
+impl fmt::Debug for CursorSubNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CursorSubNode")
+            .field("key", &self.key)
+            .field("nibble", &self.nibble)
+            .field("state_flag", &self.state_flag())
+            .field("tree_flag", &self.tree_flag())
+            .field("hash_flag", &self.hash_flag())
+            .field("hash", &self.hash())
+            .finish()
+    }
+}
+
+impl CursorSubNode {
+    /// Creates a new subnode positioned at the first child of the node, or at the node itself if
+    /// the hash of the whole node is known.
+    pub fn new(key: Nibbles, node: Option<BranchNodeCompact>) -> Self {
+        let nibble = node.as_ref().filter(|node| node.root_hash.is_none()).map_or(-1, |node| {
+            CHILD_INDEX_RANGE.clone().find(|i| node.state_mask.is_bit_set(*i)).unwrap() as i8
+        });
+        let mut full_key = key.clone();
+        if nibble >= 0 {
+            full_key.push(nibble as u8);
+        }
+        Self { key, nibble, node, full_key }
+    }
+
+    /// Returns the path of the node followed by the current child nibble.
+    pub const fn full_key(&self) -> &Nibbles {
+        &self.full_key
+    }
+
+    /// Returns the current child nibble, or `-1` if the walker is positioned at the node itself.
+    pub const fn nibble(&self) -> i8 {
+        self.nibble
+    }
+
+    /// Returns `true` if the current child exists in the trie.
+    pub fn state_flag(&self) -> bool {
+        self.node
+            .as_ref()
+            .map_or(true, |node| self.nibble < 0 || node.state_mask.is_bit_set(self.nibble as u8))
+    }
+
+    /// Returns `true` if the current child is a branch node stored in the database.
+    pub fn tree_flag(&self) -> bool {
+        self.node
+            .as_ref()
+            .map_or(true, |node| self.nibble < 0 || node.tree_mask.is_bit_set(self.nibble as u8))
+    }
+
+    /// Returns `true` if the hash of the current child, or of the node itself, is known.
+    pub fn hash_flag(&self) -> bool {
+        self.node.as_ref().is_some_and(|node| match self.nibble {
+            -1 => node.root_hash.is_some(),
+            nibble => node.hash_mask.is_bit_set(nibble as u8),
+        })
+    }
+
+    /// Returns the hash of the current child, or of the node itself, if known.
+    pub fn hash(&self) -> Option<B256> {
+        let node = self.node.as_ref()?;
+        match self.nibble {
+            -1 => node.root_hash,
+            nibble => {
+                node.hash_mask.is_bit_set(nibble as u8).then(|| node.hash_for_nibble(nibble as u8))
+            }
+        }
+    }
+
+    /// Moves to the next child nibble.
+    pub fn inc_nibble(&mut self) {
+        self.set_nibble(self.nibble + 1);
+    }
+
+    /// Moves to the given child nibble.
+    pub fn set_nibble(&mut self, nibble: i8) {
+        match (self.nibble >= 0, nibble >= 0) {
+            (true, true) => self.full_key.set_at(self.full_key.len() - 1, nibble as u8),
+            (false, true) => self.full_key.push(nibble as u8),
+            (true, false) => {
+                self.full_key.pop();
+            }
+            (false, false) => {}
+        }
+        self.nibble = nibble;
+    }
+}
@@ -0,0 +1,322 @@
+//! Stateless state root computation from execution witnesses.
+//!
+//! An execution witness is an unordered set of the trie nodes that a block touches, taken from the
+//! state before the block. It's enough to reveal the paths to all modified accounts and storage
+//! slots in a [`SparseTrie`], so that the post-state root can be recomputed without access to the
+//! full state.
+
+use crate::{
+    nodes::TrieNodeDecodeError,
+    proof::decode_indexed_witness,
+    sparse::{SparseTrie, SparseTrieError},
+    HashMap, KeccakHasher, Nibbles, TrieAccount, TrieHasher, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{Bytes, B256, U256};
+use alloy_rlp::Decodable;
+use core::fmt;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// The update of a single account in the state.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct AccountUpdate {
+    /// The new state of the account, or [`None`] if the account was destroyed. The storage root
+    /// of the account is ignored, as it's recomputed from the storage updates.
+    pub account: Option<TrieAccount>,
+    /// The updated storage slots keyed by hashed slot. Zero values remove the slots.
+    pub storage: HashMap<B256, U256>,
+    /// Whether all storage slots of the account were removed before the updated slots were
+    /// written, e.g. because the account was destroyed and recreated in the same block. The
+    /// removed slots don't need to be enumerated, and the witness doesn't need the nodes of the
+    /// previous storage trie.
+    pub storage_wiped: bool,
+}
+
+impl AccountUpdate {
+    /// Creates an update that sets the account to the given state.
+    pub fn new(account: TrieAccount) -> Self {
+        Self { account: Some(account), storage: HashMap::default(), storage_wiped: false }
+    }
+
+    /// Creates an update that destroys the account.
+    pub fn destroyed() -> Self {
+        Self::default()
+    }
+
+    /// Sets the updated storage slots.
+    pub fn with_storage(mut self, storage: HashMap<B256, U256>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Removes all existing storage slots before the updated slots are written.
+    pub const fn with_storage_wiped(mut self) -> Self {
+        self.storage_wiped = true;
+        self
+    }
+}
+
+/// Error during stateless state root computation.
+#[derive(PartialEq, Eq, Debug)]
+pub enum WitnessError {
+    /// A witness node could not be decoded.
+    Decode(TrieNodeDecodeError),
+    /// The witness does not contain a node required to apply the updates.
+    SparseTrie(SparseTrieError),
+    /// An account leaf could not be decoded.
+    InvalidAccount {
+        /// Hashed address of the account.
+        hashed_address: B256,
+        /// RLP decoding error.
+        error: alloy_rlp::Error,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for WitnessError {
+    fn source(&self) -> ::core::option::Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(error) => Some(error),
+            Self::SparseTrie(error) => Some(error),
+            Self::InvalidAccount { error, .. } => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for WitnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(error) => fmt::Display::fmt(error, f),
+            Self::SparseTrie(error) => fmt::Display::fmt(error, f),
+            Self::InvalidAccount { hashed_address, error } => {
+                write!(f, "invalid account {hashed_address}: {error}")
+            }
+        }
+    }
+}
+
+impl From<TrieNodeDecodeError> for WitnessError {
+    fn from(source: TrieNodeDecodeError) -> Self {
+        Self::Decode(source)
+    }
+}
+
+impl From<SparseTrieError> for WitnessError {
+    fn from(source: SparseTrieError) -> Self {
+        Self::SparseTrie(source)
+    }
+}
+
+/// Computes the state root after applying the updates to the state described by the witness.
+///
+/// The witness must contain the nodes of the state trie on the paths to all updated accounts,
+/// and the nodes of their storage tries on the paths to all updated slots. Since removing a leaf
+/// may collapse the branch node above it, the witness must also contain the remaining sibling of
+/// such a leaf. Accounts are keyed by hashed address. The storage trie of an account whose storage
+/// is wiped is rebuilt from its updated slots alone.
+pub fn post_state_root<'a, I>(
+    state_root: B256,
+    witness: I,
+    updates: &HashMap<B256, AccountUpdate>,
+) -> Result<B256, WitnessError>
+where
+    I: IntoIterator<Item = &'a Bytes>,
+{
+    let nodes_by_hash =
+        witness.into_iter().map(|node| (KeccakHasher::hash(node), node)).collect::<HashMap<_, _>>();
+    let reveal = |root: B256| -> Result<SparseTrie, WitnessError> {
+        let mut trie = SparseTrie::blind(root);
+        trie.reveal_proof_nodes(&decode_indexed_witness(root, |hash| {
+            nodes_by_hash.get(hash).copied()
+        })?)?;
+        Ok(trie)
+    };
+
+    let mut state_trie = reveal(state_root)?;
+    for (hashed_address, update) in updates {
+        let key = Nibbles::unpack(hashed_address);
+        let Some(mut account) = update.account else {
+            state_trie.remove_leaf(&key)?;
+            continue;
+        };
+
+        let storage_root = match state_trie.get_leaf_value(&key) {
+            _ if update.storage_wiped => EMPTY_ROOT_HASH,
+            Some(mut value) => {
+                TrieAccount::decode(&mut value)
+                    .map_err(|error| WitnessError::InvalidAccount {
+                        hashed_address: *hashed_address,
+                        error,
+                    })?
+                    .storage_root
+            }
+            None => EMPTY_ROOT_HASH,
+        };
+        account.storage_root = if update.storage.is_empty() {
+            storage_root
+        } else {
+            let mut storage_trie = reveal(storage_root)?;
+            for (hashed_slot, value) in &update.storage {
+                let key = Nibbles::unpack(hashed_slot);
+                if value.is_zero() {
+                    storage_trie.remove_leaf(&key)?;
+                } else {
+                    storage_trie.update_leaf(key, alloy_rlp::encode(value))?;
+                }
+            }
+            storage_trie.root()
+        };
+
+        state_trie.update_leaf(key, alloy_rlp::encode(account))?;
+    }
+    Ok(state_trie.root())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proof::ProofRetainer, HashBuilder};
+    use alloc::collections::BTreeMap;
+    use alloy_primitives::keccak256;
+
+    /// Builds the trie from the leaves, returning its root and the nodes on the paths to the
+    /// targets.
+    fn build(leaves: &BTreeMap<B256, Vec<u8>>, targets: &[B256]) -> (B256, Vec<Bytes>) {
+        let retainer = ProofRetainer::from_iter(targets.iter().map(Nibbles::unpack));
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in leaves {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        (root, hash_builder.take_proof_nodes().into_inner().into_values().collect())
+    }
+
+    #[derive(Default)]
+    struct State {
+        accounts: BTreeMap<B256, (TrieAccount, BTreeMap<B256, U256>)>,
+    }
+
+    impl State {
+        fn storage_leaves(storage: &BTreeMap<B256, U256>) -> BTreeMap<B256, Vec<u8>> {
+            storage.iter().map(|(slot, value)| (*slot, alloy_rlp::encode(value))).collect()
+        }
+
+        /// Returns the state root and the witness for the given updates.
+        fn root_with_witness(&self, updates: &HashMap<B256, AccountUpdate>) -> (B256, Vec<Bytes>) {
+            let mut witness = Vec::new();
+            let mut account_leaves = BTreeMap::new();
+            for (address, (account, storage)) in &self.accounts {
+                // The previous storage trie of an account whose storage is wiped isn't needed.
+                let slots = updates
+                    .get(address)
+                    .filter(|update| !update.storage_wiped)
+                    .map(|update| update.storage.keys().copied().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let (storage_root, nodes) = build(&Self::storage_leaves(storage), &slots);
+                witness.extend(nodes);
+                let account = TrieAccount { storage_root, ..*account };
+                account_leaves.insert(*address, alloy_rlp::encode(account));
+            }
+            let targets = updates.keys().copied().collect::<Vec<_>>();
+            let (root, nodes) = build(&account_leaves, &targets);
+            witness.extend(nodes);
+            (root, witness)
+        }
+
+        fn apply(&mut self, updates: &HashMap<B256, AccountUpdate>) {
+            for (address, update) in updates {
+                match update.account {
+                    Some(account) => {
+                        let (existing, storage) = self.accounts.entry(*address).or_default();
+                        *existing = account;
+                        if update.storage_wiped {
+                            storage.clear();
+                        }
+                        for (slot, value) in &update.storage {
+                            if value.is_zero() {
+                                storage.remove(slot);
+                            } else {
+                                storage.insert(*slot, *value);
+                            }
+                        }
+                    }
+                    None => {
+                        self.accounts.remove(address);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn post_state_root_matches_full_rebuild() {
+        let mut state = State::default();
+        for i in 0..64u64 {
+            let account = TrieAccount { nonce: i, balance: U256::from(i), ..Default::default() };
+            let storage = (0..i % 8)
+                .map(|j| (keccak256([i as u8, j as u8]), U256::from(j + 1)))
+                .collect::<BTreeMap<_, _>>();
+            state.accounts.insert(keccak256(i.to_be_bytes()), (account, storage));
+        }
+
+        let addresses = state.accounts.keys().copied().collect::<Vec<_>>();
+        let account = |nonce| TrieAccount { nonce, ..Default::default() };
+        let updates = HashMap::from_iter([
+            // Update the account and its storage.
+            (
+                addresses[7],
+                AccountUpdate::new(account(100)).with_storage(HashMap::from_iter([
+                    (keccak256([7u8, 0]), U256::ZERO),
+                    (keccak256([7u8, 1]), U256::from(42)),
+                    (B256::repeat_byte(0x77), U256::from(7)),
+                ])),
+            ),
+            // Update the account only.
+            (addresses[20], AccountUpdate::new(account(200))),
+            // Create a new account with storage.
+            (
+                B256::repeat_byte(0x11),
+                AccountUpdate::new(account(1))
+                    .with_storage(HashMap::from_iter([(B256::ZERO, U256::from(1))])),
+            ),
+            // Destroy an account.
+            (addresses[33], AccountUpdate::destroyed()),
+            // Destroy and recreate an account with storage.
+            (
+                addresses[15],
+                AccountUpdate::new(account(1))
+                    .with_storage(HashMap::from_iter([(B256::ZERO, U256::from(3))]))
+                    .with_storage_wiped(),
+            ),
+            // Wipe the storage of an account.
+            (addresses[23], AccountUpdate::new(account(2)).with_storage_wiped()),
+        ]);
+
+        let (state_root, witness) = state.root_with_witness(&updates);
+        state.apply(&updates);
+        let (expected, _) = state.root_with_witness(&HashMap::default());
+        assert_eq!(post_state_root(state_root, &witness, &updates), Ok(expected));
+    }
+
+    #[test]
+    fn incomplete_witness() {
+        let mut state = State::default();
+        for i in 0..16u64 {
+            state.accounts.insert(keccak256(i.to_be_bytes()), Default::default());
+        }
+        let address = *state.accounts.keys().next().unwrap();
+        let updates = HashMap::from_iter([(address, AccountUpdate::new(TrieAccount::default()))]);
+        let (state_root, _) = state.root_with_witness(&updates);
+
+        assert_eq!(
+            post_state_root(state_root, [], &updates),
+            Err(WitnessError::SparseTrie(SparseTrieError::BlindedNode {
+                path: Nibbles::default(),
+                hash: state_root
+            }))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use super::{
+    path_bit, ZkBranch, ZkHasher, ZkLeaf, ZkNode, ZkTrieError, MAX_LEVELS, ZK_PROOF_MAGIC,
+};
+use alloc::collections::BTreeMap;
+use alloy_primitives::{Bytes, U256};
+use core::marker::PhantomData;
+use tracing::trace;
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Computes the root of a zkTrie from its leaves and retains the proofs of target keys.
+///
+/// Unlike the [`HashBuilder`](crate::HashBuilder), leaves can be added in any order, since the
+/// path of a leaf is taken from the bits of its node key starting at the least significant bit.
+/// Adding a leaf with the node key of an existing leaf replaces it.
+#[derive(Clone, Debug)]
+pub struct ZkTrieBuilder<H> {
+    leaves: BTreeMap<U256, ZkLeaf>,
+    targets: Vec<U256>,
+    proofs: BTreeMap<U256, Vec<(usize, Bytes)>>,
+    _hasher: PhantomData<H>,
+}
+
+impl<H> Default for ZkTrieBuilder<H> {
+    fn default() -> Self {
+        Self {
+            leaves: BTreeMap::new(),
+            targets: Vec::new(),
+            proofs: BTreeMap::new(),
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H: ZkHasher> ZkTrieBuilder<H> {
+    /// Creates a new, empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retains the proofs of the given node keys while computing the root.
+    pub fn with_proof_targets(mut self, targets: impl IntoIterator<Item = U256>) -> Self {
+        self.targets = targets.into_iter().collect();
+        self
+    }
+
+    /// Adds a leaf to the trie.
+    pub fn add_leaf(&mut self, leaf: ZkLeaf) {
+        trace!(target: "trie::zktrie", node_key = ?leaf.node_key, "adding leaf");
+        self.leaves.insert(leaf.node_key, leaf);
+    }
+
+    /// Computes the root of the trie. The root of an empty trie is zero.
+    pub fn root(&mut self) -> Result<U256, ZkTrieError> {
+        // Sorting by the reversed bits groups the leaves and targets under each node together.
+        let mut leaves = self.leaves.values().collect::<Vec<_>>();
+        leaves.sort_unstable_by_key(|leaf| leaf.node_key.reverse_bits());
+        let mut targets = self.targets.clone();
+        targets.sort_unstable_by_key(|key| key.reverse_bits());
+        targets.dedup();
+
+        self.proofs.clear();
+        let (root, _) = Self::build(&leaves, &targets, 0, &mut self.proofs)?;
+        trace!(target: "trie::zktrie", ?root, "computed root");
+        Ok(root)
+    }
+
+    /// Takes the proofs of the target keys, retained during the last [`Self::root`] call.
+    ///
+    /// Each proof consists of the encoded nodes from the root down to the leaf or empty node at the
+    /// path of the target, followed by [`ZK_PROOF_MAGIC`].
+    pub fn take_proofs(&mut self) -> BTreeMap<U256, Vec<Bytes>> {
+        core::mem::take(&mut self.proofs)
+            .into_iter()
+            .map(|(key, mut nodes)| {
+                nodes.sort_unstable_by_key(|(depth, _)| *depth);
+                let mut proof = nodes.into_iter().map(|(_, node)| node).collect::<Vec<_>>();
+                proof.push(Bytes::from_static(ZK_PROOF_MAGIC));
+                (key, proof)
+            })
+            .collect()
+    }
+
+    /// Computes the hash of the subtrie with the given leaves at the given depth, returning it
+    /// along with whether the root of the subtrie is terminal.
+    fn build(
+        leaves: &[&ZkLeaf],
+        targets: &[U256],
+        depth: usize,
+        proofs: &mut BTreeMap<U256, Vec<(usize, Bytes)>>,
+    ) -> Result<(U256, bool), ZkTrieError> {
+        let node = match leaves {
+            [] => ZkNode::Empty,
+            [leaf] => ZkNode::Leaf((*leaf).clone()),
+            _ => {
+                if depth == MAX_LEVELS {
+                    return Err(ZkTrieError::MaxLevelReached { node_key: leaves[0].node_key });
+                }
+                let split = leaves.partition_point(|leaf| !path_bit(&leaf.node_key, depth));
+                let target_split = targets.partition_point(|key| !path_bit(key, depth));
+                let (left, left_terminal) =
+                    Self::build(&leaves[..split], &targets[..target_split], depth + 1, proofs)?;
+                let (right, right_terminal) =
+                    Self::build(&leaves[split..], &targets[target_split..], depth + 1, proofs)?;
+                ZkNode::Branch(ZkBranch { left, right, left_terminal, right_terminal })
+            }
+        };
+
+        if !targets.is_empty() {
+            let encoded = node.encoded();
+            for target in targets {
+                proofs.entry(*target).or_default().push((depth, encoded.clone()));
+            }
+        }
+        Ok((node.hash::<H>()?, node.is_terminal()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zktrie::{test_utils::TestHasher, verify_zk_proof};
+    use alloy_primitives::B256;
+
+    fn leaves(n: u64) -> Vec<ZkLeaf> {
+        (0..n)
+            .map(|i| {
+                let slot = B256::from(U256::from(i));
+                ZkLeaf::storage::<TestHasher>(slot, B256::from(U256::from(i + 1))).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(ZkTrieBuilder::<TestHasher>::new().root(), Ok(U256::ZERO));
+
+        let leaves = leaves(100);
+        let mut builder = ZkTrieBuilder::<TestHasher>::new();
+        builder.add_leaf(leaves[0].clone());
+        assert_eq!(builder.root(), leaves[0].hash::<TestHasher>());
+
+        let mut builder = ZkTrieBuilder::<TestHasher>::new();
+        let mut reversed = ZkTrieBuilder::<TestHasher>::new();
+        for leaf in &leaves {
+            builder.add_leaf(leaf.clone());
+        }
+        for leaf in leaves.iter().rev() {
+            reversed.add_leaf(leaf.clone());
+        }
+        let root = builder.root().unwrap();
+        assert_eq!(reversed.root(), Ok(root));
+
+        // Replacing a value changes the root.
+        builder.add_leaf(ZkLeaf { value_preimage: vec![B256::ZERO], ..leaves[3].clone() });
+        assert_ne!(builder.root(), Ok(root));
+    }
+
+    #[test]
+    fn two_leaves() {
+        let leaf = |key: u64| ZkLeaf {
+            node_key: U256::from(key),
+            compressed_flags: 0,
+            value_preimage: vec![B256::with_last_byte(1)],
+            key_preimage: None,
+        };
+        // The keys share the first two bits, so the leaves are placed at depth 3.
+        let (a, b) = (leaf(0b011), leaf(0b111));
+        let mut builder = ZkTrieBuilder::<TestHasher>::new();
+        builder.add_leaf(a.clone());
+        builder.add_leaf(b.clone());
+
+        let branch = |left, right, left_terminal, right_terminal| {
+            ZkBranch { left, right, left_terminal, right_terminal }.hash::<TestHasher>().unwrap()
+        };
+        let fork =
+            branch(a.hash::<TestHasher>().unwrap(), b.hash::<TestHasher>().unwrap(), true, true);
+        let expected = branch(U256::ZERO, branch(U256::ZERO, fork, true, false), true, false);
+        assert_eq!(builder.root(), Ok(expected));
+    }
+
+    #[test]
+    fn proofs() {
+        let leaves = leaves(50);
+        let absent = ZkLeaf::storage::<TestHasher>(B256::repeat_byte(0x11), B256::ZERO).unwrap();
+        let targets = leaves.iter().step_by(5).chain([&absent]).map(|leaf| leaf.node_key);
+
+        let mut builder = ZkTrieBuilder::<TestHasher>::new().with_proof_targets(targets);
+        for leaf in &leaves {
+            builder.add_leaf(leaf.clone());
+        }
+        let root = builder.root().unwrap();
+        let proofs = builder.take_proofs();
+        assert_eq!(proofs.len(), 11);
+
+        for leaf in leaves.iter().step_by(5) {
+            let proof = &proofs[&leaf.node_key];
+            assert_eq!(
+                verify_zk_proof::<TestHasher, _>(root, leaf.node_key, Some(leaf), proof),
+                Ok(())
+            );
+            assert!(verify_zk_proof::<TestHasher, _>(root, leaf.node_key, None, proof).is_err());
+        }
+        let proof = &proofs[&absent.node_key];
+        assert_eq!(verify_zk_proof::<TestHasher, _>(root, absent.node_key, None, proof), Ok(()));
+        assert!(
+            verify_zk_proof::<TestHasher, _>(root, absent.node_key, Some(&absent), proof).is_err()
+        );
+    }
+}
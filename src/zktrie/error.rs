@@ -0,0 +1,73 @@
+use alloy_primitives::U256;
+use core::fmt;
+
+/// Error during zkTrie hashing, node decoding or proof verification.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ZkTrieError {
+    /// A hash input is not smaller than the field modulus.
+    InvalidFieldElement,
+    /// A leaf has no value preimage.
+    EmptyValuePreimage,
+    /// Two node keys share the first [`MAX_LEVELS`](super::MAX_LEVELS) bits.
+    MaxLevelReached {
+        /// Node key of one of the colliding leaves.
+        node_key: U256,
+    },
+    /// A node has an unknown type.
+    UnknownNodeType(u8),
+    /// A node encoding is shorter than its type requires.
+    UnexpectedEnd,
+    /// A node encoding continues after the end of the node.
+    TrailingBytes,
+    /// The hash of a proof node does not match the hash referenced by its parent, or the root.
+    NodeHashMismatch {
+        /// Depth of the node in the trie.
+        depth: usize,
+        /// Hash referenced by the parent.
+        expected: U256,
+        /// Hash of the proof node.
+        got: U256,
+    },
+    /// The proof ends at a branch node.
+    IncompleteProof {
+        /// Depth at which the proof ends.
+        depth: usize,
+    },
+    /// The leaf hash at the node key does not match the expected value.
+    ValueMismatch {
+        /// Hash of the leaf in the trie, [`None`] if the key is absent.
+        got: Option<U256>,
+        /// Expected hash of the leaf, [`None`] if the key is expected to be absent.
+        expected: Option<U256>,
+    },
+}
+
+/// Enable Error trait implementation when core is stabilized.
+/// <https://github.com/rust-lang/rust/issues/103765>
+#[cfg(feature = "std")]
+impl std::error::Error for ZkTrieError {}
+
+impl fmt::Display for ZkTrieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFieldElement => f.write_str("hash input is not a valid field element"),
+            Self::EmptyValuePreimage => f.write_str("leaf has no value preimage"),
+            Self::MaxLevelReached { node_key } => {
+                write!(f, "reached the maximum trie depth inserting node key {node_key:#x}")
+            }
+            Self::UnknownNodeType(node_type) => write!(f, "unknown zktrie node type {node_type}"),
+            Self::UnexpectedEnd => f.write_str("unexpected end of zktrie node"),
+            Self::TrailingBytes => f.write_str("unexpected trailing bytes after zktrie node"),
+            Self::NodeHashMismatch { depth, expected, got } => write!(
+                f,
+                "node hash mismatch at depth {depth}. got: {got:#x}. expected: {expected:#x}"
+            ),
+            Self::IncompleteProof { depth } => {
+                write!(f, "proof ends at a branch node at depth {depth}")
+            }
+            Self::ValueMismatch { got, expected } => {
+                write!(f, "leaf hash mismatch. got: {got:?}. expected: {expected:?}")
+            }
+        }
+    }
+}
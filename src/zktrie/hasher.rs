@@ -0,0 +1,95 @@
+use super::ZkTrieError;
+use alloy_primitives::{B256, U256};
+use core::fmt::Debug;
+
+/// Base of the domain of [`ZkHasher::hash_elems`], multiplied by the number of elements past the
+/// first two.
+const HASH_DOMAIN_ELEMS_BASE: u64 = 256;
+
+/// Domain of [`ZkHasher::hash_elems`] for two elements.
+const HASH_DOMAIN_BYTE32: u64 = 2 * HASH_DOMAIN_ELEMS_BASE;
+
+/// The hash function of the zkTrie.
+///
+/// Scroll hashes zkTrie nodes with Poseidon over the BN254 scalar field, using the domain as the
+/// capacity element. Implementations must return [`None`] if either input is not smaller than
+/// [`FIELD_MODULUS`](super::FIELD_MODULUS).
+///
+/// The provided methods build the hashes of longer inputs from the two-to-one hash in the same way
+/// as Scroll's implementation.
+pub trait ZkHasher: Clone + Copy + Debug + Default + Send + Sync + 'static {
+    /// Hashes two field elements with the given domain.
+    fn hash(domain: u64, left: U256, right: U256) -> Option<U256>;
+
+    /// Hashes two or more field elements with the given domain.
+    ///
+    /// The elements past the first two are hashed pairwise, level by level, until a single element
+    /// is left, which is then hashed with the hash of the first two elements.
+    fn hash_elems_with_domain(
+        domain: u64,
+        first: U256,
+        second: U256,
+        rest: &[U256],
+    ) -> Result<U256, ZkTrieError> {
+        let base = Self::hash(domain, first, second).ok_or(ZkTrieError::InvalidFieldElement)?;
+        match rest {
+            [] => Ok(base),
+            [last] => Self::hash_elems_with_domain(domain, base, *last, &[]),
+            _ => {
+                let mut level = alloc::vec::Vec::with_capacity(rest.len().div_ceil(2));
+                for pair in rest.chunks(2) {
+                    level.push(match *pair {
+                        [left, right] => Self::hash(domain, left, right)
+                            .ok_or(ZkTrieError::InvalidFieldElement)?,
+                        [single] => single,
+                        _ => unreachable!(),
+                    });
+                }
+                Self::hash_elems_with_domain(domain, base, level[0], &level[1..])
+            }
+        }
+    }
+
+    /// Hashes two or more field elements, with the domain derived from the number of elements.
+    fn hash_elems(first: U256, second: U256, rest: &[U256]) -> Result<U256, ZkTrieError> {
+        let domain = rest.len() as u64 * HASH_DOMAIN_ELEMS_BASE + HASH_DOMAIN_BYTE32;
+        Self::hash_elems_with_domain(domain, first, second, rest)
+    }
+
+    /// Hashes 32 bytes, which may not be a valid field element, by splitting them into two 16-byte
+    /// big-endian halves.
+    fn hash_byte32(bytes: &B256) -> Result<U256, ZkTrieError> {
+        let high = U256::from_be_slice(&bytes[..16]);
+        let low = U256::from_be_slice(&bytes[16..]);
+        Self::hash_elems(high, low, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zktrie::{test_utils::TestHasher, FIELD_MODULUS};
+
+    #[test]
+    fn hash_elems() {
+        let [a, b, c, d, e] = [1, 2, 3, 4, 5].map(U256::from);
+        let hash = |domain, left, right| TestHasher::hash(domain, left, right).unwrap();
+
+        assert_eq!(TestHasher::hash_elems(a, b, &[]), Ok(hash(512, a, b)));
+        assert_eq!(TestHasher::hash_elems(a, b, &[c]), Ok(hash(768, hash(768, a, b), c)));
+        // The rest is reduced pairwise to `[hash(c, d), e]`, which is then folded into the hash of
+        // the first two elements.
+        let domain = 1280;
+        let base = hash(domain, hash(domain, a, b), hash(domain, c, d));
+        assert_eq!(TestHasher::hash_elems(a, b, &[c, d, e]), Ok(hash(domain, base, e)));
+
+        assert_eq!(
+            TestHasher::hash_elems(FIELD_MODULUS, a, &[]),
+            Err(ZkTrieError::InvalidFieldElement)
+        );
+        assert_eq!(
+            TestHasher::hash_byte32(&B256::repeat_byte(0xff)),
+            Ok(hash(512, U256::from(u128::MAX), U256::from(u128::MAX)))
+        );
+    }
+}
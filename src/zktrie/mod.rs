@@ -0,0 +1,67 @@
+//! Scroll's binary zkTrie.
+//!
+//! The zkTrie is a sparse binary Merkle trie over the BN254 scalar field that Scroll uses in place
+//! of the Merkle-Patricia trie. Leaves are placed at the shortest path that distinguishes their
+//! node key from all other keys, where the path is taken from the bits of the node key starting
+//! at the least significant bit. Nodes are hashed with a two-to-one Poseidon hash with a domain
+//! separator, which is abstracted by the [`ZkHasher`] trait: this crate does not bundle the
+//! Poseidon permutation or BN254 field arithmetic, so clients plug in their own implementation.
+//!
+//! The module mirrors the MPT API: [`ZkTrieBuilder`] computes the root of a set of leaves and
+//! retains proofs for target keys, and [`verify_zk_proof`] verifies them.
+
+mod error;
+pub use error::ZkTrieError;
+
+mod hasher;
+pub use hasher::ZkHasher;
+
+mod node;
+pub use node::{ZkAccount, ZkBranch, ZkLeaf, ZkNode};
+
+mod builder;
+pub use builder::ZkTrieBuilder;
+
+mod proof;
+pub use proof::{verify_zk_proof, ZK_PROOF_MAGIC};
+
+use alloy_primitives::{uint, U256};
+
+/// Modulus of the BN254 scalar field. Node keys, hashes and uncompressed value preimages must be
+/// smaller than the modulus.
+pub const FIELD_MODULUS: U256 =
+    uint!(0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001_U256);
+
+/// Maximum depth of the trie. Node keys have 248 usable bits.
+pub const MAX_LEVELS: usize = 248;
+
+/// Returns the direction of the node key at the given depth, `true` for the right child.
+#[inline]
+const fn path_bit(node_key: &U256, depth: usize) -> bool {
+    node_key.bit(depth)
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use alloy_primitives::keccak256;
+
+    /// Domain-separated keccak256 truncated to 253 bits, standing in for Poseidon.
+    #[derive(Clone, Copy, Default, Debug)]
+    pub(crate) struct TestHasher;
+
+    impl ZkHasher for TestHasher {
+        fn hash(domain: u64, left: U256, right: U256) -> Option<U256> {
+            if left >= FIELD_MODULUS || right >= FIELD_MODULUS {
+                return None;
+            }
+            let preimage = [
+                &domain.to_be_bytes()[..],
+                &left.to_be_bytes::<32>()[..],
+                &right.to_be_bytes::<32>()[..],
+            ]
+            .concat();
+            Some(U256::from_be_bytes(keccak256(preimage).0) >> 3)
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use super::{ZkHasher, ZkTrieError};
+use alloy_primitives::{Address, Bytes, B256, U256};
+
+#[allow(unused_imports)]
+use alloc::vec::Vec;
+
+/// Type of a branch node whose children are both terminal nodes.
+const NODE_TYPE_BRANCH_0: u8 = 6;
+/// Type of a branch node whose left child is terminal and right child is a branch.
+const NODE_TYPE_BRANCH_1: u8 = 7;
+/// Type of a branch node whose left child is a branch and right child is terminal.
+const NODE_TYPE_BRANCH_2: u8 = 8;
+/// Type of a branch node whose children are both branches.
+const NODE_TYPE_BRANCH_3: u8 = 9;
+/// Type of a leaf node.
+const NODE_TYPE_LEAF: u8 = 4;
+/// Type of an empty node.
+const NODE_TYPE_EMPTY: u8 = 5;
+
+/// A node of the zkTrie.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ZkNode {
+    /// An empty subtrie, whose hash is zero.
+    Empty,
+    /// A leaf node.
+    Leaf(ZkLeaf),
+    /// A branch node.
+    Branch(ZkBranch),
+}
+
+impl ZkNode {
+    /// Returns `true` if the node is a leaf or empty, i.e. it has no children.
+    pub const fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Branch(_))
+    }
+
+    /// Computes the hash of the node.
+    pub fn hash<H: ZkHasher>(&self) -> Result<U256, ZkTrieError> {
+        match self {
+            Self::Empty => Ok(U256::ZERO),
+            Self::Leaf(leaf) => leaf.hash::<H>(),
+            Self::Branch(branch) => branch.hash::<H>(),
+        }
+    }
+
+    /// Encodes the node into the given buffer.
+    ///
+    /// Branch nodes are encoded as their type followed by the hashes of their children, and leaf
+    /// nodes as their type, node key, the number of value preimage elements and the compression
+    /// flags, the value preimage and the optional key preimage. Field elements are encoded as
+    /// 32 little-endian bytes.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Empty => out.push(NODE_TYPE_EMPTY),
+            Self::Leaf(leaf) => {
+                out.push(NODE_TYPE_LEAF);
+                out.extend_from_slice(&leaf.node_key.to_le_bytes::<32>());
+                let flags = (leaf.compressed_flags << 8) | leaf.value_preimage.len() as u32;
+                out.extend_from_slice(&flags.to_le_bytes());
+                for element in &leaf.value_preimage {
+                    out.extend_from_slice(element.as_slice());
+                }
+                match &leaf.key_preimage {
+                    Some(key_preimage) => {
+                        out.push(32);
+                        out.extend_from_slice(key_preimage.as_slice());
+                    }
+                    None => out.push(0),
+                }
+            }
+            Self::Branch(branch) => {
+                out.push(branch.node_type());
+                out.extend_from_slice(&branch.left.to_le_bytes::<32>());
+                out.extend_from_slice(&branch.right.to_le_bytes::<32>());
+            }
+        }
+    }
+
+    /// Returns the encoding of the node.
+    pub fn encoded(&self) -> Bytes {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out.into()
+    }
+
+    /// Decodes a node from its encoding. See [`Self::encode`].
+    pub fn decode(buf: &[u8]) -> Result<Self, ZkTrieError> {
+        let (&node_type, mut buf) = buf.split_first().ok_or(ZkTrieError::UnexpectedEnd)?;
+        let node = match node_type {
+            NODE_TYPE_EMPTY => Self::Empty,
+            NODE_TYPE_LEAF => {
+                let node_key = U256::from_le_bytes(take::<32>(&mut buf)?);
+                let flags = u32::from_le_bytes(take::<4>(&mut buf)?);
+                let value_preimage = (0..flags & 0xff)
+                    .map(|_| take::<32>(&mut buf).map(B256::from))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let key_preimage = match take::<1>(&mut buf)? {
+                    [0] => None,
+                    [32] => Some(B256::from(take::<32>(&mut buf)?)),
+                    // Keys of other lengths are not used by Scroll.
+                    _ => return Err(ZkTrieError::UnexpectedEnd),
+                };
+                Self::Leaf(ZkLeaf {
+                    node_key,
+                    compressed_flags: flags >> 8,
+                    value_preimage,
+                    key_preimage,
+                })
+            }
+            NODE_TYPE_BRANCH_0..=NODE_TYPE_BRANCH_3 => {
+                let left = U256::from_le_bytes(take::<32>(&mut buf)?);
+                let right = U256::from_le_bytes(take::<32>(&mut buf)?);
+                let (left_terminal, right_terminal) = match node_type {
+                    NODE_TYPE_BRANCH_0 => (true, true),
+                    NODE_TYPE_BRANCH_1 => (true, false),
+                    NODE_TYPE_BRANCH_2 => (false, true),
+                    _ => (false, false),
+                };
+                Self::Branch(ZkBranch { left, right, left_terminal, right_terminal })
+            }
+            _ => return Err(ZkTrieError::UnknownNodeType(node_type)),
+        };
+        if !buf.is_empty() {
+            return Err(ZkTrieError::TrailingBytes);
+        }
+        Ok(node)
+    }
+}
+
+/// Splits the first `N` bytes off the buffer.
+fn take<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N], ZkTrieError> {
+    if buf.len() < N {
+        return Err(ZkTrieError::UnexpectedEnd);
+    }
+    let (head, tail) = buf.split_at(N);
+    *buf = tail;
+    Ok(head.try_into().unwrap())
+}
+
+/// A branch node of the zkTrie.
+///
+/// The node type, and thus the hash domain, depends on whether the children are terminal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ZkBranch {
+    /// Hash of the left child.
+    pub left: U256,
+    /// Hash of the right child.
+    pub right: U256,
+    /// Whether the left child is a leaf or empty.
+    pub left_terminal: bool,
+    /// Whether the right child is a leaf or empty.
+    pub right_terminal: bool,
+}
+
+impl ZkBranch {
+    /// Returns the node type of the branch.
+    pub const fn node_type(&self) -> u8 {
+        match (self.left_terminal, self.right_terminal) {
+            (true, true) => NODE_TYPE_BRANCH_0,
+            (true, false) => NODE_TYPE_BRANCH_1,
+            (false, true) => NODE_TYPE_BRANCH_2,
+            (false, false) => NODE_TYPE_BRANCH_3,
+        }
+    }
+
+    /// Computes the hash of the branch node.
+    pub fn hash<H: ZkHasher>(&self) -> Result<U256, ZkTrieError> {
+        H::hash(self.node_type() as u64, self.left, self.right)
+            .ok_or(ZkTrieError::InvalidFieldElement)
+    }
+}
+
+/// A leaf node of the zkTrie.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZkLeaf {
+    /// The node key, which is the hash of the key preimage and determines the path of the leaf.
+    pub node_key: U256,
+    /// Bit flags of the value preimage elements that are compressed with
+    /// [`ZkHasher::hash_byte32`] for hashing, rather than taken as field elements.
+    pub compressed_flags: u32,
+    /// The value preimage.
+    pub value_preimage: Vec<B256>,
+    /// The key preimage, if known.
+    pub key_preimage: Option<B256>,
+}
+
+impl ZkLeaf {
+    /// Creates a storage leaf for the given slot and value.
+    pub fn storage<H: ZkHasher>(slot: B256, value: B256) -> Result<Self, ZkTrieError> {
+        Ok(Self {
+            node_key: H::hash_byte32(&slot)?,
+            compressed_flags: 1,
+            value_preimage: vec![value],
+            key_preimage: Some(slot),
+        })
+    }
+
+    /// Creates an account leaf for the given address and account.
+    pub fn account<H: ZkHasher>(
+        address: Address,
+        account: &ZkAccount,
+    ) -> Result<Self, ZkTrieError> {
+        let mut key_preimage = B256::ZERO;
+        key_preimage[..Address::len_bytes()].copy_from_slice(address.as_slice());
+
+        let mut nonce_code_size = B256::ZERO;
+        nonce_code_size[16..24].copy_from_slice(&account.code_size.to_be_bytes());
+        nonce_code_size[24..].copy_from_slice(&account.nonce.to_be_bytes());
+
+        Ok(Self {
+            node_key: H::hash_byte32(&key_preimage)?,
+            // The keccak code hash is not a field element.
+            compressed_flags: 1 << 3,
+            value_preimage: vec![
+                nonce_code_size,
+                account.balance.into(),
+                account.storage_root,
+                account.keccak_code_hash,
+                account.poseidon_code_hash,
+            ],
+            key_preimage: Some(key_preimage),
+        })
+    }
+
+    /// Computes the hash of the value preimage.
+    pub fn value_hash<H: ZkHasher>(&self) -> Result<U256, ZkTrieError> {
+        let elements = self
+            .value_preimage
+            .iter()
+            .enumerate()
+            .map(|(i, element)| {
+                if i < 32 && self.compressed_flags & (1 << i) != 0 {
+                    H::hash_byte32(element)
+                } else {
+                    Ok(U256::from_be_bytes(element.0))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match elements.as_slice() {
+            [] => Err(ZkTrieError::EmptyValuePreimage),
+            [single] => Ok(*single),
+            [first, second, rest @ ..] => H::hash_elems(*first, *second, rest),
+        }
+    }
+
+    /// Computes the hash of the leaf node.
+    pub fn hash<H: ZkHasher>(&self) -> Result<U256, ZkTrieError> {
+        H::hash(NODE_TYPE_LEAF as u64, self.node_key, self.value_hash::<H>()?)
+            .ok_or(ZkTrieError::InvalidFieldElement)
+    }
+}
+
+/// An account in Scroll's state trie.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ZkAccount {
+    /// Account nonce.
+    pub nonce: u64,
+    /// Size of the account code.
+    pub code_size: u64,
+    /// Account balance.
+    pub balance: U256,
+    /// Root of the account's storage zkTrie.
+    pub storage_root: B256,
+    /// Keccak256 hash of the account code.
+    pub keccak_code_hash: B256,
+    /// Poseidon hash of the account code.
+    pub poseidon_code_hash: B256,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zktrie::test_utils::TestHasher;
+    use alloy_primitives::address;
+
+    #[test]
+    fn encode_decode() {
+        let account = ZkAccount {
+            nonce: 1,
+            code_size: 2,
+            balance: U256::from(3),
+            keccak_code_hash: B256::repeat_byte(0xff),
+            ..Default::default()
+        };
+        let nodes = [
+            ZkNode::Empty,
+            ZkNode::Leaf(
+                ZkLeaf::storage::<TestHasher>(B256::with_last_byte(1), B256::with_last_byte(2))
+                    .unwrap(),
+            ),
+            ZkNode::Leaf(ZkLeaf {
+                key_preimage: None,
+                ..ZkLeaf::account::<TestHasher>(
+                    address!("00000000000000000000000000000000000000aa"),
+                    &account,
+                )
+                .unwrap()
+            }),
+            ZkNode::Branch(ZkBranch {
+                left: U256::from(1),
+                right: U256::ZERO,
+                left_terminal: false,
+                right_terminal: true,
+            }),
+        ];
+        for node in nodes {
+            let encoded = node.encoded();
+            assert_eq!(ZkNode::decode(&encoded), Ok(node));
+            assert_eq!(
+                ZkNode::decode(&encoded[..encoded.len() - 1]),
+                Err(ZkTrieError::UnexpectedEnd)
+            );
+            assert_eq!(
+                ZkNode::decode(&[&encoded[..], &[0]].concat()),
+                Err(ZkTrieError::TrailingBytes)
+            );
+        }
+        assert_eq!(ZkNode::decode(&[1]), Err(ZkTrieError::UnknownNodeType(1)));
+    }
+
+    #[test]
+    fn leaf_hash() {
+        let leaf = ZkLeaf::storage::<TestHasher>(B256::ZERO, B256::repeat_byte(0xff)).unwrap();
+        let value_hash = TestHasher::hash_byte32(&B256::repeat_byte(0xff)).unwrap();
+        assert_eq!(leaf.value_hash::<TestHasher>(), Ok(value_hash));
+        assert_eq!(
+            ZkNode::Leaf(leaf.clone()).hash::<TestHasher>(),
+            Ok(TestHasher::hash(4, leaf.node_key, value_hash).unwrap())
+        );
+
+        // Uncompressed elements must be field elements.
+        let leaf = ZkLeaf { compressed_flags: 0, ..leaf };
+        assert_eq!(leaf.hash::<TestHasher>(), Err(ZkTrieError::InvalidFieldElement));
+        let leaf = ZkLeaf { value_preimage: Vec::new(), ..leaf };
+        assert_eq!(leaf.hash::<TestHasher>(), Err(ZkTrieError::EmptyValuePreimage));
+    }
+}
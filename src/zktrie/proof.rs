@@ -0,0 +1,117 @@
+use super::{path_bit, ZkHasher, ZkLeaf, ZkNode, ZkTrieError};
+use alloy_primitives::U256;
+
+/// Marker that Scroll appends to the nodes of a zkTrie proof.
+pub const ZK_PROOF_MAGIC: &[u8] = b"THIS IS SOME MAGIC BYTES FOR SMT m1rRXgP2xpDI";
+
+/// Verifies the proof of the node key against the zkTrie root.
+///
+/// If `expected` is [`None`], the proof must show that the node key is absent from the trie,
+/// either by ending at an empty node or by ending at a leaf with a different node key. Otherwise,
+/// the proof must end at a leaf with the same hash as the expected leaf. The proof nodes are
+/// ordered from the root, and the trailing [`ZK_PROOF_MAGIC`] is optional.
+pub fn verify_zk_proof<'a, H, I>(
+    root: U256,
+    node_key: U256,
+    expected: Option<&ZkLeaf>,
+    proof: I,
+) -> Result<(), ZkTrieError>
+where
+    H: ZkHasher,
+    I: IntoIterator<Item = &'a alloy_primitives::Bytes>,
+{
+    let expected = expected.map(ZkLeaf::hash::<H>).transpose()?;
+
+    let mut expected_hash = root;
+    let mut depth = 0;
+    let mut got = None;
+    let mut terminated = false;
+    for encoded in proof {
+        if encoded.as_ref() == ZK_PROOF_MAGIC {
+            break;
+        }
+        let node = ZkNode::decode(encoded)?;
+        let hash = node.hash::<H>()?;
+        if hash != expected_hash {
+            return Err(ZkTrieError::NodeHashMismatch {
+                depth,
+                expected: expected_hash,
+                got: hash,
+            });
+        }
+        match node {
+            ZkNode::Branch(branch) => {
+                expected_hash = if path_bit(&node_key, depth) { branch.right } else { branch.left };
+                depth += 1;
+            }
+            ZkNode::Leaf(leaf) => {
+                got = (leaf.node_key == node_key).then_some(hash);
+                terminated = true;
+                break;
+            }
+            ZkNode::Empty => {
+                terminated = true;
+                break;
+            }
+        }
+    }
+
+    // Empty subtries don't need to be included in the proof, as their hash is zero.
+    if !terminated && !expected_hash.is_zero() {
+        return Err(ZkTrieError::IncompleteProof { depth });
+    }
+    if got != expected {
+        return Err(ZkTrieError::ValueMismatch { got, expected });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zktrie::{test_utils::TestHasher, ZkTrieBuilder};
+    use alloc::vec::Vec;
+    use alloy_primitives::{Bytes, B256};
+
+    #[test]
+    fn empty_trie() {
+        let key = U256::from(1);
+        assert_eq!(verify_zk_proof::<TestHasher, _>(U256::ZERO, key, None, []), Ok(()));
+        let proof = [ZkNode::Empty.encoded(), Bytes::from_static(ZK_PROOF_MAGIC)];
+        assert_eq!(verify_zk_proof::<TestHasher, _>(U256::ZERO, key, None, &proof), Ok(()));
+    }
+
+    #[test]
+    fn invalid_proofs() {
+        let leaves = (0..8u8)
+            .map(|i| ZkLeaf::storage::<TestHasher>(B256::with_last_byte(i), B256::ZERO).unwrap())
+            .collect::<Vec<_>>();
+        let target = &leaves[0];
+        let mut builder = ZkTrieBuilder::<TestHasher>::new().with_proof_targets([target.node_key]);
+        for leaf in &leaves {
+            builder.add_leaf(leaf.clone());
+        }
+        let root = builder.root().unwrap();
+        let proof = builder.take_proofs().remove(&target.node_key).unwrap();
+        let verify = |proof: &[Bytes]| {
+            verify_zk_proof::<TestHasher, _>(root, target.node_key, Some(target), proof)
+        };
+        assert_eq!(verify(&proof), Ok(()));
+
+        // Truncated before the leaf.
+        assert!(matches!(
+            verify(&proof[..proof.len() - 2]),
+            Err(ZkTrieError::IncompleteProof { .. })
+        ));
+        // Leaf with a different value.
+        let mut tampered = proof.clone();
+        let leaf = ZkLeaf { value_preimage: vec![B256::with_last_byte(1)], ..target.clone() };
+        tampered[proof.len() - 2] = ZkNode::Leaf(leaf).encoded();
+        assert!(matches!(verify(&tampered), Err(ZkTrieError::NodeHashMismatch { .. })));
+        // Wrong root.
+        assert!(matches!(
+            verify_zk_proof::<TestHasher, _>(U256::from(1), target.node_key, Some(target), &proof),
+            Err(ZkTrieError::NodeHashMismatch { depth: 0, .. })
+        ));
+    }
+}